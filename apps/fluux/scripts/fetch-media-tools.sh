@@ -0,0 +1,66 @@
+#!/bin/bash
+# Fetch static ffmpeg/ffprobe builds for bundling with the app.
+#
+# src-tauri/tauri.media-tools.conf.json lists them as sidecars (Tauri
+# `bundle.externalBin`), which must exist as
+# src-tauri/binaries/<name>-<target triple>[.exe] before `tauri build`.
+# media_probe.rs finds them next to the app executable at runtime and falls
+# back to the system's ffmpeg, then to its native MP4/MOV parser.
+#
+# Usage:
+#   ./fetch-media-tools.sh <target triple>
+#   FFMPEG_TOOLS_DIR=/path/to/bin ./fetch-media-tools.sh <target triple>
+#
+# With FFMPEG_TOOLS_DIR set, the binaries are copied from that directory
+# instead of downloaded.
+
+set -e
+
+TARGET="$1"
+if [ -z "$TARGET" ]; then
+    echo "Usage: $0 <target triple>" >&2
+    exit 1
+fi
+
+SCRIPT_DIR="$(cd "$(dirname "$0")" && pwd)"
+BIN_DIR="$SCRIPT_DIR/../src-tauri/binaries"
+WORK_DIR="$(mktemp -d)"
+trap 'rm -rf "$WORK_DIR"' EXIT
+
+EXT=""
+case "$TARGET" in
+    *-windows-*) EXT=".exe" ;;
+esac
+
+if [ -z "$FFMPEG_TOOLS_DIR" ]; then
+    FFMPEG_TOOLS_DIR="$WORK_DIR"
+    case "$TARGET" in
+        aarch64-apple-darwin|x86_64-apple-darwin)
+            ARCH=arm64
+            [ "$TARGET" = x86_64-apple-darwin ] && ARCH=amd64
+            for tool in ffmpeg ffprobe; do
+                curl -fsSL -o "$WORK_DIR/$tool.zip" \
+                    "https://ffmpeg.martin-riedl.de/redirect/latest/macos/$ARCH/release/$tool.zip"
+                unzip -q -o "$WORK_DIR/$tool.zip" -d "$WORK_DIR"
+            done
+            ;;
+        x86_64-pc-windows-msvc|aarch64-pc-windows-msvc)
+            BUILD=ffmpeg-master-latest-win64-lgpl
+            [ "$TARGET" = aarch64-pc-windows-msvc ] && BUILD=ffmpeg-master-latest-winarm64-lgpl
+            curl -fsSL -o "$WORK_DIR/ffmpeg.zip" \
+                "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/$BUILD.zip"
+            unzip -q -o "$WORK_DIR/ffmpeg.zip" -d "$WORK_DIR"
+            FFMPEG_TOOLS_DIR="$WORK_DIR/$BUILD/bin"
+            ;;
+        *)
+            echo "No static ffmpeg build known for $TARGET; set FFMPEG_TOOLS_DIR." >&2
+            exit 1
+            ;;
+    esac
+fi
+
+mkdir -p "$BIN_DIR"
+for tool in ffmpeg ffprobe; do
+    install -m 755 "$FFMPEG_TOOLS_DIR/$tool$EXT" "$BIN_DIR/$tool-$TARGET$EXT"
+    echo "Bundling $BIN_DIR/$tool-$TARGET$EXT"
+done
@@ -0,0 +1,102 @@
+//! Framing-layer microbenchmarks. For the whole bridge on loopback, run the
+//! app with `--bench-bridge`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+#[allow(dead_code)]
+#[path = "../../src/xmpp_proxy/framing.rs"]
+mod framing;
+
+fn sm_acks(count: usize) -> Vec<u8> {
+    (0..count)
+        .map(|h| format!("<a xmlns='urn:xmpp:sm:3' h='{h}'/>"))
+        .collect::<String>()
+        .into_bytes()
+}
+
+fn mam_page(count: usize, body_len: usize) -> Vec<u8> {
+    let body = "x".repeat(body_len);
+    (0..count)
+        .map(|i| {
+            format!(
+                "<message to='juliet@example.com/desk'>\
+                 <result xmlns='urn:xmpp:mam:2' queryid='q' id='mam-{i}'>\
+                 <forwarded xmlns='urn:xmpp:forward:0'>\
+                 <delay xmlns='urn:xmpp:delay' stamp='2026-01-01T00:00:00Z'/>\
+                 <message xmlns='jabber:client' from='romeo@example.net/a' \
+                 to='juliet@example.com' type='chat' id='m{i}'><body>{body}</body>\
+                 </message></forwarded></result></message>"
+            )
+        })
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Extract every stanza from `buffer`, as the bridge does after each read.
+fn drain(buffer: &[u8]) -> usize {
+    let mut offset = 0;
+    let mut stanzas = 0;
+    while let Some((_, consumed)) = framing::extract_stanza(&buffer[offset..]) {
+        offset += consumed;
+        stanzas += 1;
+    }
+    stanzas
+}
+
+fn extract(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extract_stanza");
+    for (name, buffer) in [
+        ("sm_acks_1000", sm_acks(1000)),
+        ("mam_page_100x512", mam_page(100, 512)),
+        ("huge_stanza_512k", mam_page(1, 512 * 1024)),
+    ] {
+        group.throughput(Throughput::Bytes(buffer.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &buffer, |b, buffer| {
+            b.iter(|| drain(black_box(buffer)))
+        });
+    }
+    group.finish();
+}
+
+/// A huge stanza arriving in 8 KiB reads is re-scanned from its start on each
+/// read; this tracks that cost.
+fn extract_partial(c: &mut Criterion) {
+    let stanza = mam_page(1, 512 * 1024);
+    c.bench_function("extract_stanza/huge_stanza_8k_reads", |b| {
+        b.iter(|| {
+            let mut end = 0;
+            loop {
+                end = (end + 8192).min(stanza.len());
+                if framing::extract_stanza(black_box(&stanza[..end])).is_some() {
+                    break;
+                }
+            }
+        })
+    });
+}
+
+fn translate(c: &mut Criterion) {
+    let header = "<?xml version='1.0'?><stream:stream xmlns='jabber:client' \
+                  xmlns:stream='http://etherx.jabber.org/streams' from='example.com' \
+                  id='abc' version='1.0'>";
+    let features = "<stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+                    <mechanism>SCRAM-SHA-1</mechanism></mechanisms></stream:features>";
+    let open = "<open xmlns='urn:ietf:params:xml:ns:xmpp-framing' to='example.com' \
+                version='1.0'/>";
+    let message = String::from_utf8(mam_page(1, 512)).unwrap();
+    c.bench_function("translate_tcp_to_ws/stream_header", |b| {
+        b.iter(|| framing::translate_tcp_to_ws(black_box(header)).len())
+    });
+    c.bench_function("translate_tcp_to_ws/features", |b| {
+        b.iter(|| framing::translate_tcp_to_ws(black_box(features)).len())
+    });
+    c.bench_function("translate_tcp_to_ws/message", |b| {
+        b.iter(|| framing::translate_tcp_to_ws(black_box(&message)).len())
+    });
+    c.bench_function("translate_ws_to_tcp/open", |b| {
+        b.iter(|| framing::translate_ws_to_tcp(black_box(open)).len())
+    });
+}
+
+criterion_group!(benches, extract, extract_partial, translate);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+//! Stanza boundary detection on raw bytes from the server, as the bridge
+//! feeds it: repeatedly, advancing past each extracted stanza.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/xmpp_proxy/framing.rs"]
+mod framing;
+
+fuzz_target!(|data: &[u8]| {
+    let mut offset = 0;
+    while let Some((stanza, consumed)) = framing::extract_stanza(&data[offset..]) {
+        // Zero progress would spin the bridge's read loop forever.
+        assert!(consumed > 0);
+        offset += consumed;
+        assert!(offset <= data.len());
+        let _ = framing::translate_tcp_to_ws(&stanza);
+    }
+});
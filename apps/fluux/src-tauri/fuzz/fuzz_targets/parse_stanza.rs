@@ -0,0 +1,18 @@
+//! The element tree native features build from every relayed stanza. A
+//! parsed tree must serialize and parse back to itself.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/xmpp_proxy/stanza.rs"]
+mod stanza;
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    if let Some(element) = stanza::Element::parse(&text) {
+        let xml = element.to_xml();
+        assert!(stanza::Element::parse(&xml).is_some());
+    }
+});
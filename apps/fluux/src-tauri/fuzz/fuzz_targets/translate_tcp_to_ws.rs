@@ -0,0 +1,15 @@
+//! Server-to-client frame translation on arbitrary (lossily decoded) input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/xmpp_proxy/framing.rs"]
+mod framing;
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    let _ = framing::translate_tcp_to_ws(&text);
+    let _ = framing::extract_stream_error_condition(&text);
+});
@@ -0,0 +1,15 @@
+//! Client-to-server frame translation on arbitrary (lossily decoded) input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/xmpp_proxy/framing.rs"]
+mod framing;
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    let _ = framing::translate_ws_to_tcp(&text);
+    let _ = framing::extract_open_to(&text);
+});
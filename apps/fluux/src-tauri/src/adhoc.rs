@@ -0,0 +1,215 @@
+//! Ad-hoc commands (XEP-0050) on the server and components.
+//!
+//! Server administration in ejabberd is exposed as ad-hoc commands (add user,
+//! announce, statistics, …). [`list_adhoc_commands`] discovers what an entity
+//! offers and [`execute_adhoc`] drives one through its stages: the first call
+//! starts it, each further call carries the `sessionId` returned by the
+//! previous one together with the chosen action and the filled-in form.
+
+use crate::dataforms::DataForm;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::Element;
+use serde::Serialize;
+
+const COMMANDS_NS: &str = "http://jabber.org/protocol/commands";
+const DISCO_ITEMS_NS: &str = "http://jabber.org/protocol/disco#items";
+
+/// Actions a command execution may request (XEP-0050 §6).
+const ACTIONS: [&str; 5] = ["execute", "cancel", "prev", "next", "complete"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdhocCommand {
+    pub jid: String,
+    pub node: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdhocNote {
+    /// `info`, `warn` or `error`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: String,
+}
+
+/// One stage of a command execution.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdhocStage {
+    pub session_id: Option<String>,
+    /// `executing`, `completed` or `canceled`.
+    pub status: String,
+    /// Actions allowed for the next stage, besides `cancel`.
+    pub actions: Vec<String>,
+    pub default_action: Option<String>,
+    pub notes: Vec<AdhocNote>,
+    pub form: Option<DataForm>,
+}
+
+fn parse_commands(jid: &str, response: &Element) -> Vec<AdhocCommand> {
+    response
+        .child("query", Some(DISCO_ITEMS_NS))
+        .map(|query| {
+            query
+                .elements()
+                .filter(|e| e.local_name() == "item")
+                .filter_map(|item| {
+                    Some(AdhocCommand {
+                        jid: item.attr("jid").unwrap_or(jid).to_string(),
+                        node: item.attr("node")?.to_string(),
+                        name: item.attr("name").map(str::to_string),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn command_request(
+    jid: &str,
+    node: &str,
+    session_id: Option<&str>,
+    action: &str,
+    form: Option<&DataForm>,
+) -> Element {
+    let mut command = Element::new("command")
+        .with_attr("xmlns", COMMANDS_NS)
+        .with_attr("node", node)
+        .with_attr("action", action);
+    if let Some(session_id) = session_id {
+        command.set_attr("sessionid", session_id);
+    }
+    // A cancelled stage carries no payload.
+    if let Some(form) = form.filter(|_| action != "cancel") {
+        let mut form = form.clone();
+        form.kind = "submit".to_string();
+        command = command.with_child(form.to_submit_element());
+    }
+    Element::new("iq")
+        .with_attr("type", "set")
+        .with_attr("to", jid)
+        .with_child(command)
+}
+
+pub(crate) fn parse_stage(response: &Element) -> Result<AdhocStage, String> {
+    let command = response
+        .child("command", Some(COMMANDS_NS))
+        .ok_or("Malformed ad-hoc command response")?;
+    let actions = command.child("actions", None);
+    Ok(AdhocStage {
+        session_id: command.attr("sessionid").map(str::to_string),
+        status: command.attr("status").unwrap_or("completed").to_string(),
+        actions: actions
+            .map(|a| {
+                a.elements()
+                    .map(|e| e.local_name().to_string())
+                    .filter(|name| ACTIONS.contains(&name.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        default_action: actions
+            .and_then(|a| a.attr("execute"))
+            .map(str::to_string),
+        notes: command
+            .elements()
+            .filter(|e| e.local_name() == "note")
+            .map(|note| AdhocNote {
+                kind: note.attr("type").unwrap_or("info").to_string(),
+                text: note.text(),
+            })
+            .collect(),
+        form: DataForm::find_in(command),
+    })
+}
+
+/// Commands offered by `jid` (the server itself for ejabberd administration).
+#[tauri::command]
+pub async fn list_adhoc_commands(jid: String) -> Result<Vec<AdhocCommand>, String> {
+    let iq = Element::new("iq")
+        .with_attr("type", "get")
+        .with_attr("to", &jid)
+        .with_child(
+            Element::new("query")
+                .with_attr("xmlns", DISCO_ITEMS_NS)
+                .with_attr("node", COMMANDS_NS),
+        );
+    let response = session::request(iq).await?;
+    Ok(parse_commands(&jid, &response))
+}
+
+/// Run one stage of a command. Start with no `session_id`; continue with the
+/// one from the previous stage, an `action` from its allowed list (defaults
+/// to `execute`) and the completed `form`.
+#[tauri::command]
+pub async fn execute_adhoc(
+    jid: String,
+    node: String,
+    session_id: Option<String>,
+    action: Option<String>,
+    form: Option<DataForm>,
+) -> Result<AdhocStage, String> {
+    let action = action.unwrap_or_else(|| "execute".to_string());
+    if !ACTIONS.contains(&action.as_str()) {
+        return Err(format!("Unknown ad-hoc action: {action}"));
+    }
+    let iq = command_request(&jid, &node, session_id.as_deref(), &action, form.as_ref());
+    let response = session::request(iq).await?;
+    parse_stage(&response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_command_items() {
+        let response = Element::parse(
+            "<iq type='result'><query xmlns='http://jabber.org/protocol/disco#items' node='http://jabber.org/protocol/commands'>\
+             <item jid='example.com' node='http://jabber.org/protocol/admin#add-user' name='Add User'/>\
+             <item jid='example.com' name='no node'/></query></iq>",
+        )
+        .unwrap();
+        let commands = parse_commands("example.com", &response);
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].name.as_deref(), Some("Add User"));
+    }
+
+    #[test]
+    fn parses_executing_stage_with_form_and_actions() {
+        let response = Element::parse(
+            "<iq type='result'><command xmlns='http://jabber.org/protocol/commands' node='n' sessionid='s1' status='executing'>\
+             <actions execute='next'><next/><complete/></actions>\
+             <note type='warn'>careful</note>\
+             <x xmlns='jabber:x:data' type='form'><field var='accountjid' type='jid-single'/></x>\
+             </command></iq>",
+        )
+        .unwrap();
+        let stage = parse_stage(&response).unwrap();
+        assert_eq!(stage.session_id.as_deref(), Some("s1"));
+        assert_eq!(stage.status, "executing");
+        assert_eq!(stage.actions, vec!["next", "complete"]);
+        assert_eq!(stage.default_action.as_deref(), Some("next"));
+        assert_eq!(stage.notes[0].kind, "warn");
+        assert_eq!(stage.form.unwrap().fields.len(), 1);
+    }
+
+    #[test]
+    fn continuation_submits_form_and_cancel_drops_it() {
+        let form = DataForm {
+            kind: "form".into(),
+            ..Default::default()
+        };
+        let next = command_request("example.com", "n", Some("s1"), "complete", Some(&form));
+        let command = next.child("command", Some(COMMANDS_NS)).unwrap();
+        assert_eq!(command.attr("sessionid"), Some("s1"));
+        assert_eq!(
+            DataForm::find_in(command).map(|f| f.kind),
+            Some("submit".to_string())
+        );
+
+        let cancel = command_request("example.com", "n", Some("s1"), "cancel", Some(&form));
+        assert!(DataForm::find_in(cancel.child("command", None).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,225 @@
+//! ejabberd ReST admin API bridge.
+//!
+//! Optional integration for an in-app admin panel: calls ejabberd's
+//! `mod_http_api` with an OAuth bearer token (`ejabberd:admin` scope). The
+//! API base URL and token live in the OS keychain next to the XMPP
+//! credentials, and requests use the same TLS trust policy as the XMPP
+//! connection (see [`crate::xmpp_proxy::tls_client_config`]), including
+//! `--dangerous-insecure-tls` for self-hosted test servers.
+
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Keychain slot holding the admin API configuration.
+pub(crate) const ADMIN_KEYRING_USER: &str = "ejabberd-admin";
+const ADMIN_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AdminCredentials {
+    base_url: String,
+    token: String,
+}
+
+/// What the frontend may know about the stored configuration (never the
+/// token itself).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminConfig {
+    pub base_url: String,
+}
+
+/// Canonical API base URL: HTTPS (plain HTTP only on loopback), no trailing
+/// slash.
+fn normalize_base_url(raw: &str) -> Result<String, String> {
+    let url = raw.trim().trim_end_matches('/');
+    let host_part = url
+        .strip_prefix("https://")
+        .or_else(|| {
+            url.strip_prefix("http://").filter(|rest| {
+                rest.starts_with("localhost")
+                    || rest.starts_with("127.0.0.1")
+                    || rest.starts_with("[::1]")
+            })
+        })
+        .ok_or("Admin API URL must use https:// (http:// is allowed on localhost only)")?;
+    if host_part.is_empty() || host_part.starts_with('/') {
+        return Err("Admin API URL has no host".to_string());
+    }
+    Ok(url.to_string())
+}
+
+/// ejabberd command names are lowercase identifiers (`registered_users`,
+/// `stats`, …); anything else would let the caller escape the API path.
+fn validate_endpoint(endpoint: &str) -> Result<(), String> {
+    if endpoint.is_empty()
+        || !endpoint
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+    {
+        return Err(format!("Invalid admin endpoint: {endpoint}"));
+    }
+    Ok(())
+}
+
+/// ejabberd reports failures as `{"status":"error","code":N,"message":"…"}`.
+fn describe_failure(status: u16, body: &str) -> String {
+    let message = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("message").and_then(|m| m.as_str()).map(str::to_string))
+        .unwrap_or_else(|| body.chars().take(200).collect());
+    match status {
+        401 | 403 => format!("Admin API denied access ({status}): {message}"),
+        _ => format!("Admin API error ({status}): {message}"),
+    }
+}
+
+fn load_credentials() -> Result<Option<AdminCredentials>, String> {
+    let entry = Entry::new(crate::KEYRING_SERVICE, ADMIN_KEYRING_USER)
+        .map_err(|e| format!("Failed to create keyring entry for admin API: {e}"))?;
+    match crate::credential_log::read(&entry, ADMIN_KEYRING_USER, "admin API credentials") {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| format!("Stored admin API credentials are unreadable: {e}")),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(crate::classify_keyring_error(&e, "read admin API credentials")),
+    }
+}
+
+/// Store the admin API base URL and OAuth token in the keychain.
+#[tauri::command]
+pub async fn set_admin_credentials(base_url: String, token: String) -> Result<AdminConfig, String> {
+    let base_url = normalize_base_url(&base_url)?;
+    if token.trim().is_empty() {
+        return Err("Admin API token is empty".to_string());
+    }
+    tokio::task::spawn_blocking(move || {
+        let entry = Entry::new(crate::KEYRING_SERVICE, ADMIN_KEYRING_USER)
+            .map_err(|e| format!("Failed to create keyring entry for admin API: {e}"))?;
+        let json = serde_json::to_string(&AdminCredentials {
+            base_url: base_url.clone(),
+            token: token.trim().to_string(),
+        })
+        .map_err(|e| format!("Failed to serialize admin API credentials: {e}"))?;
+        entry.set_password(&json).map_err(|e| {
+            let desc = crate::classify_keyring_error(&e, "save admin API credentials");
+            tracing::error!("Keychain: {}", desc);
+            desc
+        })?;
+        Ok(AdminConfig { base_url })
+    })
+    .await
+    .map_err(|e| format!("Keychain task panicked: {e}"))?
+}
+
+/// The configured admin API, if any.
+#[tauri::command]
+pub async fn get_admin_config() -> Result<Option<AdminConfig>, String> {
+    tokio::task::spawn_blocking(|| {
+        Ok(load_credentials()?.map(|c| AdminConfig {
+            base_url: c.base_url,
+        }))
+    })
+    .await
+    .map_err(|e| format!("Keychain task panicked: {e}"))?
+}
+
+/// Whether the admin API is configured, for features that use it when it
+/// is.
+pub(crate) async fn is_configured() -> bool {
+    get_admin_config().await.is_ok_and(|config| config.is_some())
+}
+
+#[tauri::command]
+pub async fn clear_admin_credentials() -> Result<(), String> {
+    tokio::task::spawn_blocking(|| {
+        let entry = Entry::new(crate::KEYRING_SERVICE, ADMIN_KEYRING_USER)
+            .map_err(|e| format!("Failed to create keyring entry for admin API: {e}"))?;
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(crate::classify_keyring_error(&e, "delete admin API credentials")),
+        }
+    })
+    .await
+    .map_err(|e| format!("Keychain task panicked: {e}"))?
+}
+
+/// Call one ejabberd API command (`POST {base}/{endpoint}` with `params` as
+/// the JSON body) and return its JSON result.
+#[tauri::command]
+pub async fn admin_request(
+    endpoint: String,
+    params: Option<serde_json::Value>,
+) -> Result<serde_json::Value, String> {
+    validate_endpoint(&endpoint)?;
+    tokio::task::spawn_blocking(move || {
+        let credentials = load_credentials()?.ok_or("Admin API is not configured")?;
+        let client = reqwest::blocking::Client::builder()
+            .timeout(ADMIN_REQUEST_TIMEOUT)
+            .use_preconfigured_tls(crate::xmpp_proxy::tls_client_config()?)
+            .build()
+            .map_err(|e| format!("Failed to build admin API client: {e}"))?;
+        let url = format!("{}/{}", credentials.base_url, endpoint);
+        let body = serde_json::to_vec(&params.unwrap_or_else(|| serde_json::json!({})))
+            .map_err(|e| format!("Failed to encode admin API parameters: {e}"))?;
+        let response = client
+            .post(&url)
+            .bearer_auth(&credentials.token)
+            // Required by ejabberd for admin-scoped OAuth calls.
+            .header("X-Admin", "true")
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .map_err(|e| {
+                tracing::warn!(endpoint = %endpoint, "Admin API request failed: {}", e);
+                format!("Admin API request failed: {e}")
+            })?;
+        let status = response.status();
+        let body = response
+            .text()
+            .map_err(|e| format!("Failed to read admin API response: {e}"))?;
+        if !status.is_success() {
+            return Err(describe_failure(status.as_u16(), &body));
+        }
+        if body.trim().is_empty() {
+            return Ok(serde_json::Value::Null);
+        }
+        serde_json::from_str(&body).map_err(|e| format!("Admin API returned invalid JSON: {e}"))
+    })
+    .await
+    .map_err(|e| format!("Admin API task panicked: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_url_requires_https_except_loopback() {
+        assert_eq!(
+            normalize_base_url(" https://xmpp.example.com:5443/api/ ").unwrap(),
+            "https://xmpp.example.com:5443/api"
+        );
+        assert!(normalize_base_url("http://localhost:5280/api").is_ok());
+        assert!(normalize_base_url("http://xmpp.example.com/api").is_err());
+        assert!(normalize_base_url("https:///api").is_err());
+    }
+
+    #[test]
+    fn endpoint_must_be_a_command_name() {
+        assert!(validate_endpoint("registered_users").is_ok());
+        assert!(validate_endpoint("../oauth/token").is_err());
+        assert!(validate_endpoint("Stats").is_err());
+        assert!(validate_endpoint("").is_err());
+    }
+
+    #[test]
+    fn failure_uses_ejabberd_message() {
+        assert_eq!(
+            describe_failure(404, r#"{"status":"error","code":1,"message":"Unknown command"}"#),
+            "Admin API error (404): Unknown command"
+        );
+        assert!(describe_failure(401, "nope").starts_with("Admin API denied access (401)"));
+    }
+}
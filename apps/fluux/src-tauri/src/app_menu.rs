@@ -0,0 +1,249 @@
+//! The macOS menu bar.
+//!
+//! Besides the standard app/Edit/View/Window/Help menus, the bar carries a
+//! presence selector in the app menu and a "Conversations" menu listing the
+//! recent chats, the first nine on ⌘1–⌘9. Both depend on state only the
+//! frontend knows, so it pushes a [`MenuState`] through
+//! [`update_menu_state`] whenever the connection, presence or recent list
+//! changes, and the whole bar is rebuilt from it. Menu picks are sent back
+//! as events rather than handled here.
+
+use crate::shell_i18n::{self, ShellStrings};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::menu::{
+    CheckMenuItem, Menu, MenuBuilder, MenuItem, PredefinedMenuItem, SubmenuBuilder,
+};
+use tauri::{Emitter, Manager, Wry};
+
+/// Recent conversations listed in the menu; older ones are dropped.
+const MAX_CONVERSATIONS: usize = 20;
+
+/// Menu item id prefixes for the dynamic items.
+const CONVERSATION_PREFIX: &str = "conversation:";
+const PRESENCE_PREFIX: &str = "presence:";
+
+/// Presence values offered in the app menu, as used by the SDK.
+const PRESENCES: [&str; 3] = ["online", "away", "dnd"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MenuConversation {
+    /// Conversation id (bare JID) echoed back in `menu-open-conversation`.
+    pub id: String,
+    pub name: String,
+}
+
+/// What the frontend reports for the menu bar.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MenuState {
+    /// Items that need a live session are disabled while offline.
+    pub connected: bool,
+    /// Current presence (`online`, `away` or `dnd`), checked in the selector.
+    #[serde(default)]
+    pub presence: Option<String>,
+    /// Most recent first.
+    #[serde(default)]
+    pub conversations: Vec<MenuConversation>,
+}
+
+/// Last [`MenuState`] received, kept so a language change can rebuild the
+/// bar without waiting for the frontend.
+#[derive(Default)]
+pub struct AppMenu(Mutex<MenuState>);
+
+#[derive(Clone, Serialize)]
+struct OpenConversation<'a> {
+    id: &'a str,
+}
+
+#[derive(Clone, Serialize)]
+struct SetPresence<'a> {
+    presence: &'a str,
+}
+
+fn build(
+    app: &tauri::AppHandle,
+    s: &ShellStrings,
+    state: &MenuState,
+) -> tauri::Result<Menu<Wry>> {
+    let presence_items = PRESENCES
+        .iter()
+        .zip([s.available, s.away, s.dnd])
+        .map(|(presence, label)| {
+            CheckMenuItem::with_id(
+                app,
+                format!("{PRESENCE_PREFIX}{presence}"),
+                label,
+                state.connected,
+                state.presence.as_deref() == Some(*presence),
+                None::<&str>,
+            )
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let mut status_menu = SubmenuBuilder::new(app, s.status);
+    for item in &presence_items {
+        status_menu = status_menu.item(item);
+    }
+
+    // App menu (standard macOS app menu)
+    let app_menu = SubmenuBuilder::new(app, "Fluux Messenger")
+        .item(&PredefinedMenuItem::about(app, Some(s.about), None)?)
+        .separator()
+        .item(&status_menu.build()?)
+        .separator()
+        .item(&PredefinedMenuItem::services(app, Some(s.services))?)
+        .separator()
+        .item(&PredefinedMenuItem::hide(app, Some(s.hide))?)
+        .item(&PredefinedMenuItem::hide_others(app, Some(s.hide_others))?)
+        .item(&PredefinedMenuItem::show_all(app, Some(s.show_all))?)
+        .separator()
+        .item(&PredefinedMenuItem::quit(app, Some(s.quit_app))?)
+        .build()?;
+
+    // Edit menu (standard text editing)
+    let edit_menu = SubmenuBuilder::new(app, s.edit)
+        .item(&PredefinedMenuItem::undo(app, Some(s.undo))?)
+        .item(&PredefinedMenuItem::redo(app, Some(s.redo))?)
+        .separator()
+        .item(&PredefinedMenuItem::cut(app, Some(s.cut))?)
+        .item(&PredefinedMenuItem::copy(app, Some(s.copy))?)
+        .item(&PredefinedMenuItem::paste(app, Some(s.paste))?)
+        .separator()
+        .item(&PredefinedMenuItem::select_all(app, Some(s.select_all))?)
+        .build()?;
+
+    let view_menu = SubmenuBuilder::new(app, s.view)
+        .item(&PredefinedMenuItem::fullscreen(
+            app,
+            Some(s.toggle_full_screen),
+        )?)
+        .build()?;
+
+    // Conversations: new chat, then the recent list on ⌘1–⌘9. Recent chats
+    // stay reachable offline since their history is local.
+    let new_conversation = MenuItem::with_id(
+        app,
+        "new_conversation",
+        s.new_conversation,
+        state.connected,
+        Some("CmdOrCtrl+N"),
+    )?;
+    let conversation_items = state
+        .conversations
+        .iter()
+        .take(MAX_CONVERSATIONS)
+        .enumerate()
+        .map(|(index, conversation)| {
+            let accelerator = (index < 9).then(|| format!("CmdOrCtrl+{}", index + 1));
+            MenuItem::with_id(
+                app,
+                format!("{CONVERSATION_PREFIX}{}", conversation.id),
+                &conversation.name,
+                true,
+                accelerator.as_deref(),
+            )
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let mut conversations_menu = SubmenuBuilder::new(app, s.conversations)
+        .item(&new_conversation)
+        .separator();
+    if conversation_items.is_empty() {
+        conversations_menu = conversations_menu.item(&MenuItem::with_id(
+            app,
+            "no_conversations",
+            s.no_conversations,
+            false,
+            None::<&str>,
+        )?);
+    }
+    for item in &conversation_items {
+        conversations_menu = conversations_menu.item(item);
+    }
+
+    let window_menu = SubmenuBuilder::new(app, s.window)
+        .item(&PredefinedMenuItem::minimize(app, Some(s.minimize))?)
+        .item(&PredefinedMenuItem::maximize(app, Some(s.zoom))?)
+        .separator()
+        .item(&PredefinedMenuItem::close_window(
+            app,
+            Some(s.close_window),
+        )?)
+        .build()?;
+
+    // Help menu with GitHub link and log access
+    let github_item = MenuItem::with_id(app, "github", s.github, true, None::<&str>)?;
+    let report_issue_item =
+        MenuItem::with_id(app, "report_issue", s.report_issue, true, None::<&str>)?;
+    let show_logs_item = MenuItem::with_id(app, "show_logs", s.reveal_logs, true, None::<&str>)?;
+    let help_menu = SubmenuBuilder::new(app, s.help)
+        .item(&github_item)
+        .item(&report_issue_item)
+        .separator()
+        .item(&show_logs_item)
+        .build()?;
+
+    MenuBuilder::new(app)
+        .items(&[
+            &app_menu,
+            &edit_menu,
+            &view_menu,
+            &conversations_menu.build()?,
+            &window_menu,
+            &help_menu,
+        ])
+        .build()
+}
+
+/// Build the menu bar from the current language and menu state and install
+/// it.
+pub fn rebuild(app: &tauri::AppHandle) -> Result<(), String> {
+    let strings = shell_i18n::strings(app.state::<shell_i18n::UiLanguage>().get());
+    let state = app.state::<AppMenu>();
+    let state = state.0.lock().unwrap_or_else(|e| e.into_inner());
+    let menu = build(app, strings, &state).map_err(|e| e.to_string())?;
+    app.set_menu(menu).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Forward a pick from the dynamic menus to the frontend. Returns `false`
+/// for ids this module does not own.
+pub fn handle_event(app: &tauri::AppHandle, id: &str) -> bool {
+    if id == "new_conversation" {
+        show_main_window(app);
+        let _ = app.emit("menu-new-conversation", ());
+    } else if let Some(conversation) = id.strip_prefix(CONVERSATION_PREFIX) {
+        show_main_window(app);
+        let _ = app.emit(
+            "menu-open-conversation",
+            OpenConversation { id: conversation },
+        );
+    } else if let Some(presence) = id.strip_prefix(PRESENCE_PREFIX) {
+        // A check item toggles itself on click; rebuild so the mark follows
+        // the reported presence rather than the click.
+        let _ = rebuild(app);
+        let _ = app.emit("menu-set-presence", SetPresence { presence });
+    } else {
+        return false;
+    }
+    true
+}
+
+fn show_main_window(app: &tauri::AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Replace the menu state and rebuild the bar.
+#[tauri::command]
+pub fn update_menu_state(app: tauri::AppHandle, state: MenuState) -> Result<(), String> {
+    *app.state::<AppMenu>()
+        .0
+        .lock()
+        .unwrap_or_else(|e| e.into_inner()) = state;
+    rebuild(&app)
+}
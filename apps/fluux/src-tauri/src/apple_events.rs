@@ -0,0 +1,185 @@
+//! AppleScript and Shortcuts automation on macOS.
+//!
+//! `Fluux.sdef` (bundled, and named by `OSAScriptingDefinition` in
+//! `Info.plist`) declares three commands; their Apple events are handled
+//! here:
+//!
+//! ```applescript
+//! tell application "Fluux Messenger"
+//!     set presence "dnd" message "In a meeting"
+//!     send message "Running late" to "alice@example.org"
+//!     unread count
+//! end tell
+//! ```
+//!
+//! Shortcuts and Focus automations reach them through the "Run AppleScript"
+//! action. Apple events arrive on the main thread and each command only
+//! queues a stanza or reads a counter, so nothing here blocks.
+
+use crate::automation;
+use crate::unread::UnreadCounters;
+use objc2::rc::Retained;
+use objc2::runtime::{AnyObject, NSObject};
+use objc2::{class, define_class, msg_send, sel, AnyThread};
+use objc2_foundation::NSString;
+use std::sync::{Arc, OnceLock};
+use tauri::Manager;
+use tracing::{info, warn};
+
+const fn four_cc(code: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*code)
+}
+
+/// Event class of the suite in `Fluux.sdef`.
+const EVENT_CLASS: u32 = four_cc(b"Flux");
+const SET_PRESENCE: u32 = four_cc(b"sPrs");
+const SEND_MESSAGE: u32 = four_cc(b"sMsg");
+const UNREAD_COUNT: u32 = four_cc(b"gUnr");
+
+const KEY_DIRECT_OBJECT: u32 = four_cc(b"----");
+const KEY_STATUS: u32 = four_cc(b"stat");
+const KEY_TO: u32 = four_cc(b"to  ");
+const KEY_ERROR_NUMBER: u32 = four_cc(b"errn");
+const KEY_ERROR_STRING: u32 = four_cc(b"errs");
+/// `errAEEventFailed`.
+const EVENT_FAILED: i32 = -10000;
+
+static APP: OnceLock<tauri::AppHandle> = OnceLock::new();
+/// The event manager doesn't retain handlers.
+static HANDLER: OnceLock<Retained<ScriptCommandHandler>> = OnceLock::new();
+
+define_class!(
+    #[unsafe(super(NSObject))]
+    #[name = "FluuxScriptCommandHandler"]
+    struct ScriptCommandHandler;
+
+    impl ScriptCommandHandler {
+        // void handleEvent:(NSAppleEventDescriptor *) withReplyEvent:(NSAppleEventDescriptor *)
+        #[unsafe(method(handleEvent:withReplyEvent:))]
+        fn handle_event(&self, event: &AnyObject, reply: &AnyObject) {
+            // SAFETY: both arguments are NSAppleEventDescriptors, per the
+            // selector registered with the event manager.
+            unsafe {
+                let event_id: u32 = msg_send![event, eventID];
+                match run(event_id, event) {
+                    Ok(Some(result)) => set_param(reply, KEY_DIRECT_OBJECT, &result),
+                    Ok(None) => {}
+                    Err(e) => {
+                        warn!(error = %e, "apple events: command failed");
+                        set_param(reply, KEY_ERROR_NUMBER, &Reply::Integer(EVENT_FAILED));
+                        set_param(reply, KEY_ERROR_STRING, &Reply::Text(e));
+                    }
+                }
+            }
+        }
+    }
+);
+
+impl ScriptCommandHandler {
+    fn new() -> Retained<Self> {
+        // SAFETY: standard NSObject allocation/init for a defined subclass with
+        // no instance variables.
+        unsafe { msg_send![super(Self::alloc().set_ivars(())), init] }
+    }
+}
+
+enum Reply {
+    Text(String),
+    Integer(i32),
+}
+
+/// A text parameter of `event`.
+///
+/// # Safety
+/// `event` must be an `NSAppleEventDescriptor`.
+unsafe fn text_param(event: &AnyObject, keyword: u32) -> Option<String> {
+    let descriptor: Option<Retained<AnyObject>> =
+        msg_send![event, paramDescriptorForKeyword: keyword];
+    let descriptor = descriptor?;
+    let text: Option<Retained<NSString>> = msg_send![&*descriptor, stringValue];
+    text.map(|text| text.to_string())
+}
+
+/// # Safety
+/// `reply` must be an `NSAppleEventDescriptor`.
+unsafe fn set_param(reply: &AnyObject, keyword: u32, value: &Reply) {
+    let descriptor: Retained<AnyObject> = match value {
+        Reply::Text(text) => {
+            let text = NSString::from_str(text);
+            msg_send![class!(NSAppleEventDescriptor), descriptorWithString: &*text]
+        }
+        Reply::Integer(n) => {
+            msg_send![class!(NSAppleEventDescriptor), descriptorWithInt32: *n]
+        }
+    };
+    let _: () = msg_send![reply, setParamDescriptor: &*descriptor, forKeyword: keyword];
+}
+
+/// # Safety
+/// `event` must be an `NSAppleEventDescriptor`.
+unsafe fn run(event_id: u32, event: &AnyObject) -> Result<Option<Reply>, String> {
+    match event_id {
+        SET_PRESENCE => {
+            let show = text_param(event, KEY_DIRECT_OBJECT).unwrap_or_default();
+            // "available" reads better in a script than an empty string.
+            let show = if show == "available" { "" } else { &show };
+            let status = text_param(event, KEY_STATUS);
+            automation::send(automation::presence(Some(show), status.as_deref())?)?;
+            info!(show, "apple events: presence set");
+            Ok(None)
+        }
+        SEND_MESSAGE => {
+            let body = text_param(event, KEY_DIRECT_OBJECT).unwrap_or_default();
+            let to = text_param(event, KEY_TO).unwrap_or_default();
+            let (id, stanza) = automation::message(&to, "chat", &body)?;
+            automation::send(stanza)?;
+            info!(to, "apple events: message sent");
+            Ok(Some(Reply::Text(id)))
+        }
+        UNREAD_COUNT => {
+            let total = APP
+                .get()
+                .map(|app| app.state::<Arc<UnreadCounters>>().summary().total_unread)
+                .unwrap_or(0);
+            Ok(Some(Reply::Integer(total.min(i32::MAX as u32) as i32)))
+        }
+        _ => Err("Unknown command".to_string()),
+    }
+}
+
+/// Install the handlers. Called from the Tauri `setup` hook, on the main
+/// thread, after the unread counters are managed.
+pub fn register(app: tauri::AppHandle) {
+    let _ = APP.set(app);
+    let handler = HANDLER.get_or_init(ScriptCommandHandler::new);
+    // SAFETY: the handler lives in a static for the life of the process and
+    // implements the selector with the signature the manager calls.
+    unsafe {
+        let manager: Retained<AnyObject> =
+            msg_send![class!(NSAppleEventManager), sharedAppleEventManager];
+        for event_id in [SET_PRESENCE, SEND_MESSAGE, UNREAD_COUNT] {
+            let _: () = msg_send![
+                &*manager,
+                setEventHandler: &**handler,
+                andSelector: sel!(handleEvent:withReplyEvent:),
+                forEventClass: EVENT_CLASS,
+                andEventID: event_id
+            ];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn four_char_codes_match_the_scripting_definition() {
+        let sdef = include_str!("../Fluux.sdef");
+        for code in ["FluxsPrs", "FluxsMsg", "FluxgUnr"] {
+            assert!(sdef.contains(&format!("code=\"{code}\"")), "{code}");
+        }
+        assert_eq!(EVENT_CLASS, 0x466c_7578);
+        assert_eq!(KEY_TO, 0x746f_2020);
+    }
+}
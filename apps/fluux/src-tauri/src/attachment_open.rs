@@ -0,0 +1,209 @@
+//! Opening received attachments, and revealing them in the file manager.
+//!
+//! The frontend used to hand paths straight to the opener plugin, so a
+//! received `.exe` or `.command` ran on a single click. `open_attachment`
+//! asks first when the file can run code: by extension, or by content when
+//! the file starts like an executable whatever its name says.
+
+use std::io::Read;
+use std::path::Path;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tauri_plugin_opener::OpenerExt;
+use tracing::info;
+
+/// Extensions the desktop runs or installs rather than displays.
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    // Windows
+    "exe",
+    "com",
+    "scr",
+    "pif",
+    "msi",
+    "msix",
+    "appx",
+    "bat",
+    "cmd",
+    "ps1",
+    "psm1",
+    "vbs",
+    "vbe",
+    "js",
+    "jse",
+    "wsf",
+    "wsh",
+    "hta",
+    "cpl",
+    "lnk",
+    "url",
+    "reg",
+    "inf",
+    "dll",
+    "application",
+    "appref-ms",
+    "gadget",
+    "msc",
+    "jar",
+    // macOS
+    "app",
+    "command",
+    "tool",
+    "pkg",
+    "mpkg",
+    "dmg",
+    "workflow",
+    "scpt",
+    "terminal",
+    "webloc",
+    "inetloc",
+    // Linux and Unix
+    "sh",
+    "bash",
+    "zsh",
+    "csh",
+    "run",
+    "bin",
+    "appimage",
+    "desktop",
+    "deb",
+    "rpm",
+    "flatpakref",
+    "snap",
+    "py",
+    "pl",
+    "rb",
+];
+
+/// Why a file needs confirmation before it is opened.
+#[derive(Debug, PartialEq, Eq)]
+enum Risk {
+    Extension(String),
+    Content,
+}
+
+fn executable_header(head: &[u8]) -> bool {
+    head.starts_with(b"MZ")
+        || head.starts_with(b"\x7fELF")
+        || head.starts_with(b"#!")
+        // Mach-O, both byte orders, and universal binaries.
+        || [
+            [0xfe, 0xed, 0xfa, 0xce],
+            [0xfe, 0xed, 0xfa, 0xcf],
+            [0xce, 0xfa, 0xed, 0xfe],
+            [0xcf, 0xfa, 0xed, 0xfe],
+            [0xca, 0xfe, 0xba, 0xbe],
+        ]
+        .iter()
+        .any(|magic| head.starts_with(magic))
+}
+
+fn risk(path: &Path, head: &[u8]) -> Option<Risk> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if EXECUTABLE_EXTENSIONS.contains(&extension.as_str()) {
+        return Some(Risk::Extension(extension));
+    }
+    // Java class files share the universal-binary magic; they don't run on
+    // a double click and have their own extension.
+    if extension != "class" && executable_header(head) {
+        return Some(Risk::Content);
+    }
+    None
+}
+
+fn confirm(app: &tauri::AppHandle, path: &Path, risk: &Risk) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let why = match risk {
+        Risk::Extension(extension) => {
+            format!("“{name}” is a .{extension} file, which can run programs on this computer.")
+        }
+        Risk::Content => {
+            format!("“{name}” is a program, even though its name doesn't say so.")
+        }
+    };
+    app.dialog()
+        .message(format!(
+            "{why} Only open it if you trust the person who sent it and were expecting it."
+        ))
+        .title("Open this file?")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Open".to_string(),
+            "Cancel".to_string(),
+        ))
+        .blocking_show()
+}
+
+/// Open an attachment with its default application, asking first if it can
+/// run code. Returns whether it was opened.
+#[tauri::command]
+pub async fn open_attachment(app: tauri::AppHandle, path: String) -> Result<bool, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = Path::new(&path);
+        let mut file = std::fs::File::open(path)
+            .map_err(|e| format!("Cannot open {}: {e}", path.display()))?;
+        if !file.metadata().is_ok_and(|m| m.is_file()) {
+            return Err(format!("{} is not a file", path.display()));
+        }
+        let mut head = [0u8; 4];
+        let read = file.read(&mut head).unwrap_or(0);
+        drop(file);
+        if let Some(risk) = risk(path, &head[..read]) {
+            if !confirm(&app, path, &risk) {
+                info!(path = %path.display(), ?risk, "attachment: opening declined");
+                return Ok(false);
+            }
+            info!(path = %path.display(), ?risk, "attachment: opening after confirmation");
+        }
+        app.opener()
+            .open_path(path.to_string_lossy(), None::<&str>)
+            .map_err(|e| e.to_string())?;
+        Ok(true)
+    })
+    .await
+    .map_err(|e| format!("Open task failed: {e}"))?
+}
+
+/// Show an attachment selected in the system file manager.
+#[tauri::command]
+pub fn reveal_attachment(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    let path = Path::new(&path);
+    if !path.exists() {
+        return Err(format!("{} no longer exists", path.display()));
+    }
+    app.opener()
+        .reveal_item_in_dir(path)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_executables_by_extension_and_by_content() {
+        let pdf = b"%PDF";
+        assert_eq!(risk(Path::new("report.pdf"), pdf), None);
+        assert_eq!(
+            risk(Path::new("Setup.EXE"), b"MZ\x90\0"),
+            Some(Risk::Extension("exe".to_string()))
+        );
+        assert_eq!(
+            risk(Path::new("run.command"), b"echo"),
+            Some(Risk::Extension("command".to_string()))
+        );
+        assert_eq!(
+            risk(Path::new("invoice.pdf"), b"MZ\x90\0"),
+            Some(Risk::Content)
+        );
+        assert_eq!(risk(Path::new("notes"), b"\x7fELF"), Some(Risk::Content));
+        assert_eq!(
+            risk(Path::new("Main.class"), &[0xca, 0xfe, 0xba, 0xbe]),
+            None
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Automatic replies ("vacation messages").
+//!
+//! Each account can have an [`AutoReply`]: a message sent back to anyone
+//! who writes one-to-one while it is enabled and within its optional time
+//! window. It runs on the inbound traffic of the bridge and replies through
+//! the native session, so it keeps answering while the window is closed to
+//! the tray.
+//!
+//! A sender gets at most one reply per [`AutoReply::interval_hours`], which
+//! also stops two auto-responders from talking to each other forever. Rooms,
+//! private messages from room occupants, servers and our own carbons never
+//! get one. Settings and the last reply time per sender are persisted in
+//! `auto-reply.json`; changing the settings starts a fresh round of replies.
+
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::stanza::{bare_jid, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use crate::xmpp_proxy::{session, tap};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tauri::Emitter;
+use tracing::{info, warn};
+
+const SENT_EVENT: &str = "auto-reply-sent";
+const MUC_USER_NS: &str = "http://jabber.org/protocol/muc#user";
+
+/// Longest accepted reply, in characters.
+const MAX_MESSAGE_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoReply {
+    pub enabled: bool,
+    pub message: String,
+    /// Replies start at this time (Unix seconds); right away when unset.
+    pub starts_at: Option<u64>,
+    /// Replies stop at this time (Unix seconds); never when unset.
+    pub ends_at: Option<u64>,
+    /// Hours before the same sender is answered again.
+    pub interval_hours: u32,
+}
+
+impl Default for AutoReply {
+    fn default() -> Self {
+        AutoReply {
+            enabled: false,
+            message: String::new(),
+            starts_at: None,
+            ends_at: None,
+            interval_hours: 24,
+        }
+    }
+}
+
+impl AutoReply {
+    fn active(&self, now: u64) -> bool {
+        self.enabled
+            && !self.message.trim().is_empty()
+            && self.starts_at.is_none_or(|start| now >= start)
+            && self.ends_at.is_none_or(|end| now < end)
+    }
+
+    fn interval_secs(&self) -> u64 {
+        u64::from(self.interval_hours.max(1)) * 3600
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Stored {
+    /// By account bare JID.
+    accounts: BTreeMap<String, AutoReply>,
+    /// Last reply (Unix seconds) per account, then per sender bare JID.
+    replied: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SentEvent<'a> {
+    account: &'a str,
+    to: &'a str,
+    /// Also the stanza `id` and XEP-0359 origin id.
+    id: &'a str,
+    body: &'a str,
+    /// Unix seconds.
+    sent_at: u64,
+}
+
+static STORED: Mutex<Option<Stored>> = Mutex::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+/// Load the settings from `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("auto-reply.json")) else {
+        return;
+    };
+    let loaded: Stored = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *STORED.lock().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `STORED` lock held; see [`crate::json_file`].
+fn persist(snapshot: Stored) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+/// Claim the reply to `sender` on `account` at `now`. Returns the message
+/// to send, or `None` when the account is not answering or the sender was
+/// answered recently.
+fn claim(stored: &mut Stored, account: &str, sender: &str, now: u64) -> Option<String> {
+    let settings = stored.accounts.get(account).filter(|s| s.active(now))?;
+    let interval = settings.interval_secs();
+    let message = settings.message.clone();
+    let replied = stored.replied.entry(account.to_string()).or_default();
+    if replied
+        .get(sender)
+        .is_some_and(|at| now.saturating_sub(*at) < interval)
+    {
+        return None;
+    }
+    replied.retain(|_, at| now.saturating_sub(*at) < interval);
+    replied.insert(sender.to_string(), now);
+    Some(message)
+}
+
+/// The sender to answer, for an inbound one-to-one message from someone
+/// else.
+fn reply_target(stanza: &Element, own_bare: &str) -> Option<String> {
+    if stanza.local_name() != "message"
+        || !matches!(stanza.attr("type"), None | Some("chat" | "normal"))
+        || stanza
+            .child("body", None)
+            .is_none_or(|b| b.text().trim().is_empty())
+        || stanza.child("x", Some(MUC_USER_NS)).is_some()
+    {
+        return None;
+    }
+    let from = bare_jid(stanza.attr("from")?).to_lowercase();
+    if !from.contains('@') || from.eq_ignore_ascii_case(own_bare) {
+        return None;
+    }
+    Some(from)
+}
+
+fn build_stanza(to: &str, id: &str, body: &str) -> Element {
+    Element::new("message")
+        .with_attr("to", to)
+        .with_attr("type", "chat")
+        .with_attr("id", id)
+        .with_child(Element::new("body").with_text(body))
+        .with_child(
+            Element::new("origin-id")
+                .with_attr("xmlns", "urn:xmpp:sid:0")
+                .with_attr("id", id),
+        )
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Bridge observer answering one-to-one messages. Registered with the
+/// bridge tap.
+pub struct AutoResponder;
+
+impl StanzaObserver for AutoResponder {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction != Direction::Inbound || stanza.local_name() != "message" {
+            return Verdict::Forward;
+        }
+        let Some(account) = session::own_bare_jid().map(|j| j.to_lowercase()) else {
+            return Verdict::Forward;
+        };
+        let Some(sender) = reply_target(stanza, &account) else {
+            return Verdict::Forward;
+        };
+        let now = now_secs();
+        let message = {
+            let mut guard = STORED.lock().unwrap_or_else(|e| e.into_inner());
+            let stored = guard.get_or_insert_with(Stored::default);
+            let Some(message) = claim(stored, &account, &sender, now) else {
+                return Verdict::Forward;
+            };
+            persist(stored.clone());
+            message
+        };
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let reply = build_stanza(&sender, &id, &message);
+        match session::send(reply.clone()) {
+            Ok(()) => {
+                info!(to = %sender, "auto-reply: sent");
+                tap::observe_native(ctx.app, &reply);
+                if let Some(app) = ctx.app {
+                    let event = SentEvent {
+                        account: &account,
+                        to: &sender,
+                        id: &id,
+                        body: &message,
+                        sent_at: now,
+                    };
+                    let _ = app.emit(SENT_EVENT, event);
+                }
+            }
+            Err(e) => warn!(error = %e, "auto-reply: could not send"),
+        }
+        Verdict::Forward
+    }
+}
+
+fn account_or_own(account: Option<String>) -> Result<String, String> {
+    account
+        .or_else(session::own_bare_jid)
+        .map(|jid| bare_jid(&jid).to_lowercase())
+        .ok_or_else(|| "Not connected".to_string())
+}
+
+/// The auto-reply of `account`, or of the connected account.
+#[tauri::command]
+pub fn get_auto_reply(account: Option<String>) -> Result<AutoReply, String> {
+    let account = account_or_own(account)?;
+    let guard = STORED.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(guard
+        .as_ref()
+        .and_then(|stored| stored.accounts.get(&account).cloned())
+        .unwrap_or_default())
+}
+
+/// Replace the auto-reply of `account`, or of the connected account.
+/// Everyone may be answered again afterwards.
+#[tauri::command]
+pub fn set_auto_reply(account: Option<String>, settings: AutoReply) -> Result<(), String> {
+    let account = account_or_own(account)?;
+    if settings.enabled && settings.message.trim().is_empty() {
+        return Err("An enabled auto-reply needs a message".to_string());
+    }
+    if settings.message.chars().count() > MAX_MESSAGE_CHARS {
+        return Err(format!(
+            "The auto-reply is longer than {MAX_MESSAGE_CHARS} characters"
+        ));
+    }
+    if let (Some(start), Some(end)) = (settings.starts_at, settings.ends_at) {
+        if end <= start {
+            return Err("The auto-reply must end after it starts".to_string());
+        }
+    }
+    if settings.interval_hours == 0 {
+        return Err("The reply interval must be at least one hour".to_string());
+    }
+    let mut guard = STORED.lock().unwrap_or_else(|e| e.into_inner());
+    let stored = guard.get_or_insert_with(Stored::default);
+    stored.replied.remove(&account);
+    stored.accounts.insert(account, settings);
+    persist(stored.clone());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replies_once_per_interval_within_the_window() {
+        let mut stored = Stored::default();
+        stored.accounts.insert(
+            "me@example.com".to_string(),
+            AutoReply {
+                enabled: true,
+                message: "Away until Monday".to_string(),
+                starts_at: Some(1_000),
+                ends_at: Some(100_000),
+                interval_hours: 1,
+            },
+        );
+        let claim_at = |stored: &mut Stored, sender: &str, now: u64| {
+            claim(stored, "me@example.com", sender, now)
+        };
+        assert_eq!(claim_at(&mut stored, "bob@example.com", 500), None);
+        assert_eq!(
+            claim_at(&mut stored, "bob@example.com", 1_000).as_deref(),
+            Some("Away until Monday")
+        );
+        assert_eq!(claim_at(&mut stored, "bob@example.com", 4_000), None);
+        assert!(claim_at(&mut stored, "ann@example.com", 4_000).is_some());
+        assert!(claim_at(&mut stored, "bob@example.com", 4_600).is_some());
+        assert_eq!(claim_at(&mut stored, "eve@example.com", 100_000), None);
+        // Another account has no auto-reply.
+        assert_eq!(
+            claim(&mut stored, "work@example.org", "bob@example.com", 2_000),
+            None
+        );
+    }
+
+    #[test]
+    fn only_direct_messages_from_others_are_answered() {
+        let parse = |xml: &str| Element::parse(xml).unwrap();
+        let own = "me@example.com";
+        let chat =
+            parse("<message from='Bob@Example.com/pc' type='chat'><body>hi</body></message>");
+        assert_eq!(reply_target(&chat, own).as_deref(), Some("bob@example.com"));
+        for xml in [
+            "<message from='room@muc.example.com/bob' type='groupchat'><body>hi</body></message>",
+            "<message from='room@muc.example.com/bob' type='chat'><body>hi</body>\
+             <x xmlns='http://jabber.org/protocol/muc#user'/></message>",
+            "<message from='bob@example.com' type='chat'>\
+             <composing xmlns='http://jabber.org/protocol/chatstates'/></message>",
+            "<message from='example.com'><body>Maintenance tonight</body></message>",
+            "<message from='me@example.com/phone' type='chat'><body>hi</body></message>",
+            "<message from='bob@example.com' type='headline'><body>news</body></message>",
+        ] {
+            assert_eq!(reply_target(&parse(xml), own), None, "{xml}");
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Stanzas for the external automation surfaces: the headless control API,
+//! the Linux D-Bus service and macOS Apple Events. Each validates its own
+//! input format and sends through here, so they all produce the same
+//! messages and presence.
+
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::Element;
+
+/// `<show/>` values (RFC 6121 §4.7.2.1); no `show` means available.
+pub const SHOW_VALUES: [&str; 4] = ["away", "chat", "dnd", "xa"];
+
+/// A presence stanza; empty or missing `show` and `status` are left out.
+pub fn presence(show: Option<&str>, status: Option<&str>) -> Result<Element, String> {
+    let mut presence = Element::new("presence");
+    if let Some(show) = show.filter(|s| !s.is_empty()) {
+        if !SHOW_VALUES.contains(&show) {
+            return Err(format!("Unsupported show value: {show}"));
+        }
+        presence = presence.with_child(Element::new("show").with_text(show));
+    }
+    if let Some(status) = status.filter(|s| !s.is_empty()) {
+        presence = presence.with_child(Element::new("status").with_text(status));
+    }
+    Ok(presence)
+}
+
+/// A message with a fresh id (also used as its origin-id), and that id.
+pub fn message(to: &str, message_type: &str, body: &str) -> Result<(String, Element), String> {
+    if to.is_empty() || body.is_empty() {
+        return Err("Recipient and body are required".to_string());
+    }
+    if !matches!(message_type, "chat" | "groupchat" | "normal") {
+        return Err(format!("Unsupported message type: {message_type}"));
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    let stanza = Element::new("message")
+        .with_attr("to", to)
+        .with_attr("type", message_type)
+        .with_attr("id", &id)
+        .with_child(Element::new("body").with_text(body))
+        .with_child(
+            Element::new("origin-id")
+                .with_attr("xmlns", "urn:xmpp:sid:0")
+                .with_attr("id", &id),
+        );
+    Ok((id, stanza))
+}
+
+/// Send into the live session; fails when it isn't bound yet.
+pub fn send(stanza: Element) -> Result<(), String> {
+    if !session::is_ready() {
+        return Err("Not connected".to_string());
+    }
+    session::send(stanza)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_presence_and_messages_from_loose_input() {
+        assert_eq!(presence(Some(""), None).unwrap().to_xml(), "<presence/>");
+        let away = presence(Some("away"), Some("at lunch")).unwrap();
+        assert_eq!(
+            away.child("show", None).map(|e| e.text()),
+            Some("away".into())
+        );
+        assert_eq!(
+            away.child("status", None).map(|e| e.text()),
+            Some("at lunch".into())
+        );
+        assert!(presence(Some("busy"), None).is_err());
+
+        let (id, stanza) = message("alice@example.org", "chat", "hi").unwrap();
+        assert_eq!(stanza.attr("id"), Some(id.as_str()));
+        assert_eq!(
+            stanza.child("body", None).map(|e| e.text()),
+            Some("hi".into())
+        );
+        assert!(message("alice@example.org", "headline", "hi").is_err());
+        assert!(message("", "chat", "hi").is_err());
+    }
+}
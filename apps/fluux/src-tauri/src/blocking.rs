@@ -0,0 +1,401 @@
+//! XEP-0191 blocking command, enforced locally in the bridge.
+//!
+//! The server is the authority on the blocklist, but its block push can
+//! trail the user's action by a round trip (or by a whole reconnect). The
+//! bridge keeps its own copy — seeded from any blocklist result, updated from
+//! block/unblock pushes and from the client's own block requests — and drops
+//! inbound stanzas from blocked JIDs before the WebView ever sees them.
+//!
+//! Reporting (XEP-0377) rides on the same request: a block item carrying a
+//! `<report/>` tells the server why, so it can act on spam and abuse.
+
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use tauri::Emitter;
+use tracing::debug;
+
+const BLOCKING_NS: &str = "urn:xmpp:blocking";
+const REPORTING_NS: &str = "urn:xmpp:reporting:1";
+const BLOCKLIST_CHANGED_EVENT: &str = "blocklist-changed";
+
+/// Why a JID is reported (XEP-0377).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportReason {
+    Spam,
+    Abuse,
+}
+
+impl ReportReason {
+    fn urn(self) -> &'static str {
+        match self {
+            ReportReason::Spam => "urn:xmpp:reporting:spam",
+            ReportReason::Abuse => "urn:xmpp:reporting:abuse",
+        }
+    }
+}
+
+/// The `<report/>` attached to each block item.
+pub struct Report<'a> {
+    pub reason: ReportReason,
+    /// Free text for the server operator.
+    pub text: Option<&'a str>,
+}
+
+/// Local mirror of the server-side blocklist. Registered with the bridge tap
+/// and held in Tauri managed state as `Arc<Blocklist>`.
+#[derive(Default)]
+pub struct Blocklist {
+    jids: Mutex<BTreeSet<String>>,
+}
+
+impl Blocklist {
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.jids.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn jids(&self) -> Vec<String> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Whether stanzas from `jid` fall under any blocklist item.
+    pub fn is_blocked(&self, jid: &str) -> bool {
+        let jid = normalize(jid);
+        self.lock().iter().any(|item| item_matches(item, &jid))
+    }
+
+    /// Replace the whole list. Returns whether anything changed.
+    fn replace(&self, jids: impl IntoIterator<Item = String>) -> bool {
+        let next: BTreeSet<String> = jids.into_iter().map(|j| normalize(&j)).collect();
+        let mut current = self.lock();
+        if *current == next {
+            return false;
+        }
+        *current = next;
+        true
+    }
+
+    /// Add items, returning the ones that were not already blocked.
+    fn add(&self, jids: &[String]) -> Vec<String> {
+        let mut current = self.lock();
+        jids.iter()
+            .map(|j| normalize(j))
+            .filter(|j| current.insert(j.clone()))
+            .collect()
+    }
+
+    /// Remove items; an empty slice means "unblock all" as in the protocol.
+    fn remove(&self, jids: &[String]) -> bool {
+        let mut current = self.lock();
+        if jids.is_empty() {
+            let changed = !current.is_empty();
+            current.clear();
+            return changed;
+        }
+        jids.iter().fold(false, |changed, j| current.remove(&normalize(j)) | changed)
+    }
+
+    /// Apply one stanza; returns whether the list changed.
+    fn ingest(&self, direction: Direction, stanza: &Element) -> bool {
+        if stanza.local_name() != "iq" {
+            return false;
+        }
+        let kind = stanza.attr("type").unwrap_or("");
+        match (direction, kind) {
+            (Direction::Inbound, "result") => stanza
+                .child("blocklist", Some(BLOCKING_NS))
+                .is_some_and(|list| self.replace(items(list))),
+            // Server push: from our own account (or no `from` at all).
+            (Direction::Inbound, "set") if is_from_own_account(stanza) => {
+                if let Some(block) = stanza.child("block", Some(BLOCKING_NS)) {
+                    !self.add(&items(block)).is_empty()
+                } else if let Some(unblock) = stanza.child("unblock", Some(BLOCKING_NS)) {
+                    self.remove(&items(unblock))
+                } else {
+                    false
+                }
+            }
+            // The client's own block request: enforce right away rather than
+            // waiting for the server's push. Unblocking waits for the push so
+            // a rejected request never lets traffic through.
+            (Direction::Outbound, "set") => stanza
+                .child("block", Some(BLOCKING_NS))
+                .is_some_and(|block| !self.add(&items(block)).is_empty()),
+            _ => false,
+        }
+    }
+
+    /// Whether an inbound stanza must be dropped.
+    fn should_drop(&self, stanza: &Element) -> bool {
+        let Some(from) = stanza.attr("from") else {
+            return false;
+        };
+        let droppable = match stanza.local_name() {
+            "message" | "presence" => stanza.attr("type") != Some("error"),
+            // Responses are left alone: they answer something we asked.
+            "iq" => matches!(stanza.attr("type"), Some("get") | Some("set")),
+            _ => false,
+        };
+        droppable && !is_from_own_account(stanza) && self.is_blocked(from)
+    }
+}
+
+impl StanzaObserver for Blocklist {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if self.ingest(ctx.direction, stanza) {
+            if let Some(app) = ctx.app {
+                publish(app, self);
+            }
+        }
+        if ctx.direction == Direction::Inbound && self.should_drop(stanza) {
+            debug!(
+                from = ?stanza.attr("from"),
+                kind = stanza.local_name(),
+                "Dropped stanza from blocked JID"
+            );
+            return Verdict::Drop;
+        }
+        Verdict::Forward
+    }
+}
+
+fn items(parent: &Element) -> Vec<String> {
+    parent
+        .elements()
+        .filter(|e| e.local_name() == "item")
+        .filter_map(|e| e.attr("jid"))
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_from_own_account(stanza: &Element) -> bool {
+    match stanza.attr("from") {
+        None => true,
+        Some(from) => session::own_bare_jid()
+            .is_some_and(|own| own.eq_ignore_ascii_case(bare_jid(from))),
+    }
+}
+
+/// Localpart and domain are case-insensitive; the resource is kept as is.
+fn normalize(jid: &str) -> String {
+    let jid = jid.trim();
+    match jid.split_once('/') {
+        Some((bare, resource)) => format!("{}/{}", bare.to_lowercase(), resource),
+        None => jid.to_lowercase(),
+    }
+}
+
+/// Item matching per XEP-0191 §3.3 (the XEP-0016 JID rules): a full JID item
+/// matches exactly, a bare JID any of its resources, a `domain/resource` item
+/// that resource at the domain, and a bare domain everything at that domain.
+fn item_matches(item: &str, jid: &str) -> bool {
+    if item == jid {
+        return true;
+    }
+    // Full JID and `domain/resource` items only ever match exactly.
+    if item.contains('/') {
+        return false;
+    }
+    let bare = bare_jid(jid);
+    if item.contains('@') {
+        return item == bare;
+    }
+    item == bare.rsplit('@').next().unwrap_or(bare)
+}
+
+fn block_request(verb: &str, jids: &[String], report: Option<&Report<'_>>) -> Element {
+    jids.iter().fold(
+        Element::new(verb).with_attr("xmlns", BLOCKING_NS),
+        |parent, jid| {
+            let item = Element::new("item").with_attr("jid", jid);
+            parent.with_child(match report {
+                Some(report) => item.with_child(report_element(report)),
+                None => item,
+            })
+        },
+    )
+}
+
+fn report_element(report: &Report<'_>) -> Element {
+    let element = Element::new("report")
+        .with_attr("xmlns", REPORTING_NS)
+        .with_attr("reason", report.reason.urn());
+    match report.text.map(str::trim).filter(|t| !t.is_empty()) {
+        Some(text) => element.with_child(Element::new("text").with_text(text)),
+        None => element,
+    }
+}
+
+fn publish(app: &tauri::AppHandle, list: &Blocklist) {
+    let _ = app.emit(BLOCKLIST_CHANGED_EVENT, list.jids());
+}
+
+/// Fetch the blocklist from the server and refresh the local copy.
+#[tauri::command]
+pub async fn get_blocked_jids(
+    app: tauri::AppHandle,
+    blocklist: tauri::State<'_, Arc<Blocklist>>,
+) -> Result<Vec<String>, String> {
+    let iq = Element::new("iq")
+        .with_attr("type", "get")
+        .with_child(Element::new("blocklist").with_attr("xmlns", BLOCKING_NS));
+    let response = session::request(iq).await?;
+    let list = response
+        .child("blocklist", Some(BLOCKING_NS))
+        .ok_or("Malformed blocklist response")?;
+    if blocklist.replace(items(list)) {
+        publish(&app, &blocklist);
+    }
+    Ok(blocklist.jids())
+}
+
+/// Block JIDs, reporting them when `report` is given. Enforcement starts
+/// locally before the request is sent and is rolled back if the server
+/// rejects it.
+pub(crate) async fn block(
+    app: &tauri::AppHandle,
+    blocklist: &Blocklist,
+    jids: &[String],
+    report: Option<Report<'_>>,
+) -> Result<(), String> {
+    if jids.is_empty() {
+        return Err("No JIDs to block".to_string());
+    }
+    let added = blocklist.add(jids);
+    if !added.is_empty() {
+        publish(app, blocklist);
+    }
+    let iq = Element::new("iq")
+        .with_attr("type", "set")
+        .with_child(block_request("block", jids, report.as_ref()));
+    if let Err(e) = session::request(iq).await {
+        if !added.is_empty() && blocklist.remove(&added) {
+            publish(app, blocklist);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn block_jids(
+    app: tauri::AppHandle,
+    blocklist: tauri::State<'_, Arc<Blocklist>>,
+    jids: Vec<String>,
+) -> Result<(), String> {
+    block(&app, &blocklist, &jids, None).await
+}
+
+/// Report JIDs as spam or abuse. Reporting also blocks them: XEP-0377
+/// carries the report inside the block request.
+#[tauri::command]
+pub async fn report_jids(
+    app: tauri::AppHandle,
+    blocklist: tauri::State<'_, Arc<Blocklist>>,
+    jids: Vec<String>,
+    reason: ReportReason,
+    text: Option<String>,
+) -> Result<(), String> {
+    let report = Report {
+        reason,
+        text: text.as_deref(),
+    };
+    block(&app, &blocklist, &jids, Some(report)).await
+}
+
+/// Unblock JIDs. An explicit list is required; clearing the whole blocklist
+/// is deliberately not reachable through an empty argument.
+#[tauri::command]
+pub async fn unblock_jids(
+    app: tauri::AppHandle,
+    blocklist: tauri::State<'_, Arc<Blocklist>>,
+    jids: Vec<String>,
+) -> Result<(), String> {
+    if jids.is_empty() {
+        return Err("No JIDs to unblock".to_string());
+    }
+    let iq = Element::new("iq")
+        .with_attr("type", "set")
+        .with_child(block_request("unblock", &jids, None));
+    session::request(iq).await?;
+    if blocklist.remove(&jids) {
+        publish(&app, &blocklist);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(xml: &str) -> Element {
+        Element::parse(xml).unwrap()
+    }
+
+    #[test]
+    fn item_matching_follows_jid_rules() {
+        assert!(item_matches("spam@example.com", "spam@example.com/phone"));
+        assert!(item_matches("spam@example.com/phone", "spam@example.com/phone"));
+        assert!(!item_matches("spam@example.com/phone", "spam@example.com/desk"));
+        assert!(item_matches("example.com", "anyone@example.com/x"));
+        assert!(item_matches("example.com", "example.com"));
+        assert!(!item_matches("example.com", "anyone@example.org"));
+        assert!(item_matches("example.com/bot", "example.com/bot"));
+        assert!(!item_matches("example.com/bot", "user@example.com/bot"));
+    }
+
+    #[test]
+    fn drops_messages_and_presence_from_blocked_jids() {
+        let list = Blocklist::default();
+        list.replace(vec!["Spam@Example.com".to_string()]);
+        assert!(list.should_drop(&parse("<message from='spam@example.com/x'><body>buy</body></message>")));
+        assert!(list.should_drop(&parse("<presence from='spam@example.com/x'/>")));
+        assert!(!list.should_drop(&parse("<iq type='result' from='spam@example.com/x' id='1'/>")));
+        assert!(!list.should_drop(&parse("<message from='friend@example.com'><body>hi</body></message>")));
+    }
+
+    #[test]
+    fn reports_ride_on_block_items() {
+        let report = Report {
+            reason: ReportReason::Spam,
+            text: Some(" unsolicited ads "),
+        };
+        let request = block_request("block", &["spam@example.com".to_string()], Some(&report));
+        let report = request
+            .child("item", None)
+            .and_then(|item| item.child("report", Some(REPORTING_NS)))
+            .unwrap();
+        assert_eq!(report.attr("reason"), Some("urn:xmpp:reporting:spam"));
+        let text = report.child("text", None).map(Element::text);
+        assert_eq!(text.as_deref(), Some("unsolicited ads"));
+    }
+
+    #[test]
+    fn tracks_results_pushes_and_client_blocks() {
+        let list = Blocklist::default();
+        assert!(list.ingest(
+            Direction::Inbound,
+            &parse("<iq type='result' id='1'><blocklist xmlns='urn:xmpp:blocking'><item jid='a@x'/></blocklist></iq>"),
+        ));
+        assert!(list.ingest(
+            Direction::Outbound,
+            &parse("<iq type='set' id='2'><block xmlns='urn:xmpp:blocking'><item jid='b@x'/></block></iq>"),
+        ));
+        assert_eq!(list.jids(), vec!["a@x", "b@x"]);
+        assert!(list.ingest(
+            Direction::Inbound,
+            &parse("<iq type='set' id='3'><unblock xmlns='urn:xmpp:blocking'><item jid='a@x'/></unblock></iq>"),
+        ));
+        assert_eq!(list.jids(), vec!["b@x"]);
+        // An unblock without items clears everything.
+        assert!(list.ingest(
+            Direction::Inbound,
+            &parse("<iq type='set' id='4'><unblock xmlns='urn:xmpp:blocking'/></iq>"),
+        ));
+        assert!(list.jids().is_empty());
+    }
+}
@@ -0,0 +1,1014 @@
+//! File-transfer transports: SOCKS5 bytestreams (XEP-0065) and in-band
+//! bytestreams (XEP-0047).
+//!
+//! Older clients (Gajim, Pidgin, …) negotiate a transfer first, over Jingle
+//! or stream initiation, and then move the bytes over one of these two
+//! transports. The WebView can't open TCP sockets, so without native support
+//! their transfers stall once negotiation is done. This module is the
+//! transport half only; the WebView keeps the negotiation and tells it what
+//! to expect:
+//!
+//! - receiving: `expect_bytestream` registers a stream id, its peer and the
+//!   destination file. The bridge then answers that peer's IBB `<open/>`,
+//!   `<data/>` and `<close/>` natively, or connects to one of the
+//!   streamhosts it offers over SOCKS5. Bytestreams for stream ids that
+//!   were not registered go to the WebView untouched.
+//! - sending: `send_bytestream` offers the server's SOCKS5 proxies (found
+//!   through service discovery), connects through the one the peer picked
+//!   and activates it; when no proxy works it falls back to IBB.
+//!
+//! Progress is emitted as `fluux://bytestream-progress` events
+//! (`{sid, transferred, total}`) and the outcome as `fluux://bytestream-done`
+//! (`{sid, ok, bytes, error}`). Only mediated (proxy) streamhosts are offered
+//! when sending; any streamhost is tried when receiving.
+
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+const IBB_NS: &str = "http://jabber.org/protocol/ibb";
+const S5B_NS: &str = "http://jabber.org/protocol/bytestreams";
+const DISCO_INFO_NS: &str = "http://jabber.org/protocol/disco#info";
+const DISCO_ITEMS_NS: &str = "http://jabber.org/protocol/disco#items";
+const STANZAS_NS: &str = "urn:ietf:params:xml:ns:xmpp-stanzas";
+
+const PROGRESS_EVENT: &str = "fluux://bytestream-progress";
+const DONE_EVENT: &str = "fluux://bytestream-done";
+
+/// IBB block size we offer and the largest we accept.
+const IBB_BLOCK_SIZE: usize = 4096;
+const IBB_MAX_BLOCK_SIZE: usize = 65_535;
+/// Per-streamhost budget for the TCP connect and SOCKS5 handshake.
+const STREAMHOST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Disco items probed for a SOCKS5 proxy.
+const MAX_DISCO_ITEMS: usize = 20;
+const COPY_CHUNK_BYTES: usize = 64 * 1024;
+
+/// A SOCKS5 streamhost: a proxy, or a peer listening directly.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamHost {
+    pub jid: String,
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    /// SOCKS5 through a proxy, then IBB if that fails.
+    #[default]
+    Auto,
+    S5b,
+    Ibb,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Progress<'a> {
+    sid: &'a str,
+    transferred: u64,
+    total: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Done<'a> {
+    sid: &'a str,
+    ok: bool,
+    bytes: u64,
+    error: Option<String>,
+}
+
+enum Stage {
+    /// Registered, no transport opened yet.
+    Waiting,
+    /// IBB open; blocks go to the writer task.
+    Ibb {
+        next_seq: u16,
+        block_size: usize,
+        blocks: mpsc::UnboundedSender<Vec<u8>>,
+    },
+    /// A SOCKS5 receive task owns the transfer.
+    Socks,
+}
+
+struct Inbound {
+    /// Full JID of the sender.
+    peer: String,
+    path: PathBuf,
+    /// Bytes of `path` already present; the stream carries the rest.
+    offset: u64,
+    /// Bytes the stream carries, when known.
+    size: Option<u64>,
+    stage: Stage,
+    cancelled: Arc<AtomicBool>,
+    /// Taken by whichever transport opens.
+    reporter: Option<Reporter>,
+}
+
+/// Transport state, registered with the bridge tap and held in managed state
+/// as `Arc<Bytestreams>`.
+#[derive(Default)]
+pub struct Bytestreams {
+    /// Shared with the SOCKS5 receive tasks, which remove their transfer
+    /// when done.
+    inbound: Arc<Mutex<HashMap<String, Inbound>>>,
+    /// Cancellation flags of outgoing transfers.
+    outbound: Mutex<HashMap<String, Arc<AtomicBool>>>,
+    proxies: Mutex<Option<Vec<StreamHost>>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The SOCKS5 destination address for a stream: hex SHA-1 of the stream id
+/// and the requester's and target's full JIDs (XEP-0065 §5.3.2).
+pub(crate) fn dst_addr(sid: &str, requester: &str, target: &str) -> String {
+    let digest = Sha1::digest(format!("{sid}{requester}{target}").as_bytes());
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// SOCKS5 handshake (RFC 1928, no authentication) asking for a CONNECT to
+/// the domain `dst`, port 0, as XEP-0065 specifies.
+pub(crate) async fn socks5_connect<S>(stream: &mut S, dst: &str) -> Result<(), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let io = |e: std::io::Error| format!("SOCKS5: {e}");
+    stream.write_all(&[5, 1, 0]).await.map_err(io)?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await.map_err(io)?;
+    if choice != [5, 0] {
+        return Err("SOCKS5: no acceptable authentication method".to_string());
+    }
+    let mut request = vec![5, 1, 0, 3, dst.len() as u8];
+    request.extend_from_slice(dst.as_bytes());
+    request.extend_from_slice(&[0, 0]);
+    stream.write_all(&request).await.map_err(io)?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await.map_err(io)?;
+    if head[0] != 5 || head[1] != 0 {
+        return Err(format!("SOCKS5: connect refused (reply {})", head[1]));
+    }
+    // Skip the bound address the server echoes, then the port.
+    let address_len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await.map_err(io)?;
+            len[0] as usize
+        }
+        other => return Err(format!("SOCKS5: unknown address type {other}")),
+    };
+    let mut rest = vec![0u8; address_len + 2];
+    stream.read_exact(&mut rest).await.map_err(io)?;
+    Ok(())
+}
+
+/// Connect to the first streamhost that completes the SOCKS5 handshake.
+pub(crate) async fn connect_streamhost(
+    hosts: &[StreamHost],
+    dst: &str,
+) -> Result<(StreamHost, TcpStream), String> {
+    let mut last_error = "no streamhosts".to_string();
+    for host in hosts {
+        let attempt = async {
+            let mut stream = TcpStream::connect((host.host.as_str(), host.port))
+                .await
+                .map_err(|e| e.to_string())?;
+            socks5_connect(&mut stream, dst).await?;
+            Ok::<_, String>(stream)
+        };
+        match tokio::time::timeout(STREAMHOST_TIMEOUT, attempt).await {
+            Ok(Ok(stream)) => return Ok((host.clone(), stream)),
+            Ok(Err(e)) => last_error = format!("{}: {e}", host.jid),
+            Err(_) => last_error = format!("{}: timed out", host.jid),
+        }
+        debug!(host = %host.jid, error = %last_error, "bytestreams: streamhost failed");
+    }
+    Err(last_error)
+}
+
+fn streamhosts(query: &Element) -> Vec<StreamHost> {
+    query
+        .elements()
+        .filter(|e| e.local_name() == "streamhost")
+        .filter_map(|e| {
+            Some(StreamHost {
+                jid: e.attr("jid")?.to_string(),
+                host: e.attr("host")?.to_string(),
+                port: e.attr("port").map_or(Some(1080), |p| p.parse().ok())?,
+            })
+        })
+        .collect()
+}
+
+fn iq_result(request: &Element) -> Element {
+    let mut iq = Element::new("iq").with_attr("type", "result");
+    if let Some(id) = request.attr("id") {
+        iq.set_attr("id", id);
+    }
+    if let Some(from) = request.attr("from") {
+        iq.set_attr("to", from);
+    }
+    iq
+}
+
+fn iq_error(request: &Element, kind: &str, condition: &str) -> Element {
+    iq_result(request).with_attr("type", "error").with_child(
+        Element::new("error")
+            .with_attr("type", kind)
+            .with_child(Element::new(condition).with_attr("xmlns", STANZAS_NS)),
+    )
+}
+
+fn reply(stanza: Element) {
+    if let Err(e) = session::send(stanza) {
+        warn!(error = %e, "bytestreams: failed to answer");
+    }
+}
+
+/// Copy `reader` into `writer`, at most `limit` bytes, reporting the running
+/// total. Stops with an error once `cancelled` is set.
+pub(crate) async fn pump<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    limit: Option<u64>,
+    cancelled: &AtomicBool,
+    mut progress: impl FnMut(u64),
+) -> Result<u64, String>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; COPY_CHUNK_BYTES];
+    let mut total = 0u64;
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Err("Cancelled".to_string());
+        }
+        let want = limit.map_or(buf.len(), |limit| {
+            (limit - total).min(buf.len() as u64) as usize
+        });
+        if want == 0 {
+            break;
+        }
+        let read = reader
+            .read(&mut buf[..want])
+            .await
+            .map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        writer
+            .write_all(&buf[..read])
+            .await
+            .map_err(|e| e.to_string())?;
+        total += read as u64;
+        progress(total);
+    }
+    writer.flush().await.map_err(|e| e.to_string())?;
+    Ok(total)
+}
+
+/// Emits progress at most once per percent (or per MiB without a size), and
+/// the outcome.
+pub(crate) struct Reporter {
+    app: Option<tauri::AppHandle>,
+    /// Id the events carry: the stream id, or the id of the session that
+    /// owns the stream.
+    sid: String,
+    total: Option<u64>,
+    last_step: u64,
+    /// When set, the outcome goes here instead of a `bytestream-done` event,
+    /// for an owner that reports it itself.
+    notify: Option<oneshot::Sender<Result<u64, String>>>,
+}
+
+impl Reporter {
+    pub(crate) fn new(app: Option<tauri::AppHandle>, sid: &str, total: Option<u64>) -> Self {
+        Self {
+            app,
+            sid: sid.to_string(),
+            total,
+            last_step: 0,
+            notify: None,
+        }
+    }
+
+    pub(crate) fn notifying(mut self, notify: oneshot::Sender<Result<u64, String>>) -> Self {
+        self.notify = Some(notify);
+        self
+    }
+
+    pub(crate) fn update(&mut self, transferred: u64) {
+        let step = match self.total {
+            Some(total) if total > 0 => transferred * 100 / total,
+            _ => transferred >> 20,
+        };
+        if step == self.last_step {
+            return;
+        }
+        self.last_step = step;
+        if let Some(app) = &self.app {
+            let _ = app.emit(
+                PROGRESS_EVENT,
+                Progress {
+                    sid: &self.sid,
+                    transferred,
+                    total: self.total,
+                },
+            );
+        }
+    }
+
+    pub(crate) fn finish(&mut self, result: &Result<u64, String>) {
+        match result {
+            Ok(bytes) => info!(sid = %self.sid, bytes, "bytestreams: transfer complete"),
+            Err(e) => warn!(sid = %self.sid, error = %e, "bytestreams: transfer failed"),
+        }
+        if let Some(notify) = self.notify.take() {
+            let _ = notify.send(result.clone());
+        } else if let Some(app) = &self.app {
+            let _ = app.emit(
+                DONE_EVENT,
+                Done {
+                    sid: &self.sid,
+                    ok: result.is_ok(),
+                    bytes: *result.as_ref().unwrap_or(&0),
+                    error: result.as_ref().err().cloned(),
+                },
+            );
+        }
+    }
+}
+
+/// The transfer must have delivered exactly `size` bytes, when known.
+fn check_size(received: u64, size: Option<u64>) -> Result<u64, String> {
+    match size {
+        Some(size) if received != size => {
+            Err(format!("Transfer ended after {received} of {size} bytes"))
+        }
+        _ => Ok(received),
+    }
+}
+
+/// Open `path` for a stream that starts at `offset`: created afresh at 0,
+/// otherwise cut back to `offset` and appended to.
+async fn open_destination(path: &Path, offset: u64) -> Result<tokio::fs::File, String> {
+    let opened = if offset == 0 {
+        tokio::fs::File::create(path).await
+    } else {
+        tokio::fs::OpenOptions::new().write(true).open(path).await
+    };
+    let mut file = opened.map_err(|e| format!("Cannot write {}: {e}", path.display()))?;
+    if offset > 0 {
+        file.set_len(offset).await.map_err(|e| e.to_string())?;
+        file.seek(std::io::SeekFrom::End(0))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(file)
+}
+
+/// Remove a failed download; the WebView only sees complete files. A resumed
+/// one keeps the part it had before.
+async fn discard(path: &Path, offset: u64) {
+    if offset == 0 {
+        let _ = tokio::fs::remove_file(path).await;
+    } else if let Ok(file) = tokio::fs::OpenOptions::new().write(true).open(path).await {
+        let _ = file.set_len(offset).await;
+    }
+}
+
+impl Bytestreams {
+    fn take_inbound(&self, sid: &str) -> Option<Inbound> {
+        lock(&self.inbound).remove(sid)
+    }
+
+    /// Answer an IBB `<open/>`, `<data/>` or `<close/>` for a registered
+    /// stream. `false` leaves the stanza to the WebView.
+    fn handle_ibb(&self, stanza: &Element, app: Option<&tauri::AppHandle>) -> bool {
+        let Some(payload) = stanza.elements().find(|e| e.ns() == Some(IBB_NS)) else {
+            return false;
+        };
+        let Some(sid) = payload.attr("sid") else {
+            return false;
+        };
+        let from = stanza.attr("from").unwrap_or_default();
+        let is_iq = stanza.local_name() == "iq";
+        let mut inbound = lock(&self.inbound);
+        let Some(transfer) = inbound.get_mut(sid).filter(|t| t.peer == from) else {
+            return false;
+        };
+        match (payload.local_name(), &mut transfer.stage) {
+            ("open", Stage::Waiting) if is_iq => {
+                let block_size = payload
+                    .attr("block-size")
+                    .and_then(|b| b.parse::<usize>().ok())
+                    .filter(|b| (1..=IBB_MAX_BLOCK_SIZE).contains(b));
+                let Some(block_size) = block_size else {
+                    reply(iq_error(stanza, "modify", "resource-constraint"));
+                    return true;
+                };
+                let (blocks, rx) = mpsc::unbounded_channel();
+                transfer.stage = Stage::Ibb {
+                    next_seq: 0,
+                    block_size,
+                    blocks,
+                };
+                let destination = (transfer.path.clone(), transfer.offset);
+                let size = transfer.size;
+                let cancelled = transfer.cancelled.clone();
+                let reporter = transfer
+                    .reporter
+                    .take()
+                    .unwrap_or_else(|| Reporter::new(app.cloned(), sid, size));
+                tokio::spawn(write_blocks(destination, size, rx, cancelled, reporter));
+                info!(sid, peer = %from, block_size, "bytestreams: IBB opened");
+                reply(iq_result(stanza));
+            }
+            (
+                "data",
+                Stage::Ibb {
+                    next_seq,
+                    block_size,
+                    blocks,
+                },
+            ) => {
+                let seq = payload.attr("seq").and_then(|s| s.parse::<u16>().ok());
+                let block = BASE64
+                    .decode(payload.text().trim())
+                    .ok()
+                    .filter(|b| b.len() <= *block_size);
+                match (seq, block) {
+                    (Some(seq), Some(block)) if seq == *next_seq => {
+                        *next_seq = next_seq.wrapping_add(1);
+                        let _ = blocks.send(block);
+                        if is_iq {
+                            reply(iq_result(stanza));
+                        }
+                    }
+                    // Out of order or undecodable: the stream is broken.
+                    _ => {
+                        inbound.remove(sid);
+                        if is_iq {
+                            reply(iq_error(stanza, "cancel", "unexpected-request"));
+                        }
+                    }
+                }
+            }
+            ("close", Stage::Ibb { .. }) if is_iq => {
+                // Dropping the sender lets the writer finish.
+                inbound.remove(sid);
+                reply(iq_result(stanza));
+            }
+            _ if is_iq => reply(iq_error(stanza, "cancel", "unexpected-request")),
+            _ => {}
+        }
+        true
+    }
+
+    /// Try the streamhosts a registered peer offers.
+    fn handle_s5b(
+        &self,
+        stanza: &Element,
+        query: &Element,
+        app: Option<&tauri::AppHandle>,
+    ) -> bool {
+        let Some(sid) = query.attr("sid") else {
+            return false;
+        };
+        let from = stanza.attr("from").unwrap_or_default().to_string();
+        let mut inbound = lock(&self.inbound);
+        let Some(transfer) = inbound
+            .get_mut(sid)
+            .filter(|t| t.peer == from && matches!(t.stage, Stage::Waiting))
+        else {
+            return false;
+        };
+        let Some(own_jid) = session::own_jid() else {
+            return false;
+        };
+        transfer.stage = Stage::Socks;
+        let hosts = streamhosts(query);
+        let dst = dst_addr(sid, &from, &own_jid);
+        let request = stanza.clone();
+        let (path, offset, size) = (transfer.path.clone(), transfer.offset, transfer.size);
+        let cancelled = transfer.cancelled.clone();
+        let mut reporter = transfer
+            .reporter
+            .take()
+            .unwrap_or_else(|| Reporter::new(app.cloned(), sid, size));
+        let sid = sid.to_string();
+        let registry = self.inbound.clone();
+        tokio::spawn(async move {
+            let (host, mut stream) = match connect_streamhost(&hosts, &dst).await {
+                Ok(connected) => connected,
+                Err(e) => {
+                    lock(&registry).remove(&sid);
+                    reply(iq_error(&request, "cancel", "item-not-found"));
+                    reporter.finish(&Err(format!("No streamhost reachable ({e})")));
+                    return;
+                }
+            };
+            info!(sid = %sid, host = %host.jid, "bytestreams: SOCKS5 connected");
+            let query = Element::new("query")
+                .with_attr("xmlns", S5B_NS)
+                .with_attr("sid", &sid)
+                .with_child(Element::new("streamhost-used").with_attr("jid", &host.jid));
+            reply(iq_result(&request).with_child(query));
+            receive_into(&mut stream, &path, offset, size, &cancelled, reporter).await;
+            lock(&registry).remove(&sid);
+        });
+        true
+    }
+}
+
+/// Write a connected stream into `path` from `offset` until it ends or
+/// `size` bytes have arrived.
+pub(crate) async fn receive_into<S>(
+    stream: &mut S,
+    path: &Path,
+    offset: u64,
+    size: Option<u64>,
+    cancelled: &AtomicBool,
+    mut reporter: Reporter,
+) where
+    S: AsyncRead + Unpin,
+{
+    let result = async {
+        let mut file = open_destination(path, offset).await?;
+        let received = pump(stream, &mut file, size, cancelled, |n| reporter.update(n)).await?;
+        check_size(received, size)
+    }
+    .await;
+    if result.is_err() {
+        discard(path, offset).await;
+    }
+    reporter.finish(&result);
+}
+
+/// Append IBB blocks to `path` until the sender closes (the channel ends).
+async fn write_blocks(
+    (path, offset): (PathBuf, u64),
+    size: Option<u64>,
+    mut blocks: mpsc::UnboundedReceiver<Vec<u8>>,
+    cancelled: Arc<AtomicBool>,
+    mut reporter: Reporter,
+) {
+    let result = async {
+        let mut file = open_destination(&path, offset).await?;
+        let mut received = 0u64;
+        while let Some(block) = blocks.recv().await {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err("Cancelled".to_string());
+            }
+            received += block.len() as u64;
+            if size.is_some_and(|size| received > size) {
+                return Err("Peer sent more than the announced size".to_string());
+            }
+            file.write_all(&block).await.map_err(|e| e.to_string())?;
+            reporter.update(received);
+        }
+        file.flush().await.map_err(|e| e.to_string())?;
+        if cancelled.load(Ordering::Relaxed) {
+            return Err("Cancelled".to_string());
+        }
+        check_size(received, size)
+    }
+    .await;
+    if result.is_err() {
+        discard(&path, offset).await;
+    }
+    reporter.finish(&result);
+}
+
+impl StanzaObserver for Bytestreams {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction != Direction::Inbound {
+            return Verdict::Forward;
+        }
+        let is_set = stanza.local_name() == "iq" && stanza.attr("type") == Some("set");
+        let handled = if let Some(query) = stanza.child("query", Some(S5B_NS)).filter(|_| is_set) {
+            self.handle_s5b(stanza, query, ctx.app)
+        } else if is_set || stanza.local_name() == "message" {
+            self.handle_ibb(stanza, ctx.app)
+        } else {
+            false
+        };
+        if handled {
+            Verdict::Drop
+        } else {
+            Verdict::Forward
+        }
+    }
+}
+
+/// SOCKS5 proxies offered by our server, found through disco#items and
+/// disco#info.
+async fn discover_proxies() -> Result<Vec<StreamHost>, String> {
+    let jid = session::own_bare_jid().ok_or("Not connected")?;
+    let domain = jid.rsplit('@').next().unwrap_or(&jid).to_string();
+    let items = session::request(
+        Element::new("iq")
+            .with_attr("type", "get")
+            .with_attr("to", &domain)
+            .with_child(Element::new("query").with_attr("xmlns", DISCO_ITEMS_NS)),
+    )
+    .await?;
+    let candidates: Vec<String> = items
+        .child("query", Some(DISCO_ITEMS_NS))
+        .map(|query| {
+            query
+                .elements()
+                .filter_map(|item| item.attr("jid"))
+                .map(str::to_string)
+                .take(MAX_DISCO_ITEMS)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut proxies = Vec::new();
+    for candidate in candidates {
+        let info = session::request(
+            Element::new("iq")
+                .with_attr("type", "get")
+                .with_attr("to", &candidate)
+                .with_child(Element::new("query").with_attr("xmlns", DISCO_INFO_NS)),
+        )
+        .await;
+        let is_proxy = info.ok().is_some_and(|info| {
+            info.child("query", Some(DISCO_INFO_NS))
+                .is_some_and(|query| {
+                    query.elements().any(|e| {
+                        e.local_name() == "identity"
+                            && e.attr("category") == Some("proxy")
+                            && e.attr("type") == Some("bytestreams")
+                    })
+                })
+        });
+        if !is_proxy {
+            continue;
+        }
+        let address = session::request(
+            Element::new("iq")
+                .with_attr("type", "get")
+                .with_attr("to", &candidate)
+                .with_child(Element::new("query").with_attr("xmlns", S5B_NS)),
+        )
+        .await;
+        match address {
+            Ok(address) => {
+                if let Some(query) = address.child("query", Some(S5B_NS)) {
+                    proxies.extend(streamhosts(query));
+                }
+            }
+            Err(e) => debug!(proxy = %candidate, error = %e, "bytestreams: proxy has no address"),
+        }
+    }
+    info!(
+        count = proxies.len(),
+        "bytestreams: SOCKS5 proxies discovered"
+    );
+    Ok(proxies)
+}
+
+impl Bytestreams {
+    pub(crate) async fn proxies(&self, refresh: bool) -> Result<Vec<StreamHost>, String> {
+        if !refresh {
+            if let Some(cached) = lock(&self.proxies).clone() {
+                return Ok(cached);
+            }
+        }
+        let proxies = discover_proxies().await?;
+        *lock(&self.proxies) = Some(proxies.clone());
+        Ok(proxies)
+    }
+
+    /// Register an incoming stream `sid` from the full JID `peer`, to be
+    /// written to `path` from `offset` on. `size` is what the stream
+    /// carries, not the whole file.
+    pub(crate) fn expect(
+        &self,
+        sid: &str,
+        peer: &str,
+        (path, offset): (PathBuf, u64),
+        size: Option<u64>,
+        cancelled: Arc<AtomicBool>,
+        reporter: Reporter,
+    ) -> Result<(), String> {
+        let mut inbound = lock(&self.inbound);
+        if inbound.contains_key(sid) {
+            return Err(format!("Stream {sid} is already expected"));
+        }
+        info!(sid, peer, offset, "bytestreams: expecting stream");
+        inbound.insert(
+            sid.to_string(),
+            Inbound {
+                peer: peer.to_string(),
+                path,
+                offset,
+                size,
+                stage: Stage::Waiting,
+                cancelled,
+                reporter: Some(reporter),
+            },
+        );
+        Ok(())
+    }
+
+    /// Forget a registered stream that never opened.
+    pub(crate) fn unexpect(&self, sid: &str) {
+        if let Some(transfer) = self.take_inbound(sid) {
+            transfer.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Send over SOCKS5: offer our proxies, connect through the one the peer
+    /// used and activate it.
+    async fn send_s5b(
+        &self,
+        sid: &str,
+        to: &str,
+        path: &Path,
+        cancelled: &AtomicBool,
+        reporter: &mut Reporter,
+    ) -> Result<u64, String> {
+        let proxies = self.proxies(false).await?;
+        if proxies.is_empty() {
+            return Err("The server offers no SOCKS5 proxy".to_string());
+        }
+        let own_jid = session::own_jid().ok_or("Not connected")?;
+        let mut query = Element::new("query")
+            .with_attr("xmlns", S5B_NS)
+            .with_attr("sid", sid)
+            .with_attr("mode", "tcp");
+        for proxy in &proxies {
+            query = query.with_child(
+                Element::new("streamhost")
+                    .with_attr("jid", &proxy.jid)
+                    .with_attr("host", &proxy.host)
+                    .with_attr("port", &proxy.port.to_string()),
+            );
+        }
+        let answer = session::request(
+            Element::new("iq")
+                .with_attr("type", "set")
+                .with_attr("to", to)
+                .with_child(query),
+        )
+        .await?;
+        let used = answer
+            .child("query", Some(S5B_NS))
+            .and_then(|q| q.child("streamhost-used", None))
+            .and_then(|u| u.attr("jid"))
+            .ok_or("Peer did not pick a streamhost")?;
+        let proxy = proxies
+            .iter()
+            .find(|p| p.jid == used)
+            .cloned()
+            .ok_or_else(|| format!("Peer picked an unknown streamhost {used}"))?;
+        let (_, mut stream) =
+            connect_streamhost(std::slice::from_ref(&proxy), &dst_addr(sid, &own_jid, to)).await?;
+        activate(&proxy.jid, sid, to).await?;
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| format!("Cannot open {}: {e}", path.display()))?;
+        let sent = pump(&mut file, &mut stream, None, cancelled, |n| {
+            reporter.update(n)
+        })
+        .await?;
+        let _ = stream.shutdown().await;
+        Ok(sent)
+    }
+}
+
+/// Ask `proxy` to start relaying stream `sid` to the full JID `target`, once
+/// both ends are connected to it.
+pub(crate) async fn activate(proxy: &str, sid: &str, target: &str) -> Result<(), String> {
+    session::request(
+        Element::new("iq")
+            .with_attr("type", "set")
+            .with_attr("to", proxy)
+            .with_child(
+                Element::new("query")
+                    .with_attr("xmlns", S5B_NS)
+                    .with_attr("sid", sid)
+                    .with_child(Element::new("activate").with_text(target)),
+            ),
+    )
+    .await?;
+    info!(sid, proxy, "bytestreams: SOCKS5 stream activated");
+    Ok(())
+}
+
+/// Send `reader`, at most `limit` bytes of it, over IBB, one acknowledged IQ
+/// per block.
+pub(crate) async fn send_ibb<R>(
+    sid: &str,
+    to: &str,
+    reader: &mut R,
+    limit: Option<u64>,
+    cancelled: &AtomicBool,
+    reporter: &mut Reporter,
+) -> Result<u64, String>
+where
+    R: AsyncRead + Unpin,
+{
+    let iq = |payload: Element| {
+        Element::new("iq")
+            .with_attr("type", "set")
+            .with_attr("to", to)
+            .with_child(payload.with_attr("xmlns", IBB_NS).with_attr("sid", sid))
+    };
+    session::request(iq(Element::new("open")
+        .with_attr("block-size", &IBB_BLOCK_SIZE.to_string())
+        .with_attr("stanza", "iq")))
+    .await?;
+    let mut buf = vec![0u8; IBB_BLOCK_SIZE];
+    let mut seq: u16 = 0;
+    let mut sent = 0u64;
+    let result = async {
+        loop {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err("Cancelled".to_string());
+            }
+            let want = limit.map_or(buf.len(), |limit| {
+                (limit - sent).min(buf.len() as u64) as usize
+            });
+            if want == 0 {
+                return Ok(sent);
+            }
+            let read = reader
+                .read(&mut buf[..want])
+                .await
+                .map_err(|e| e.to_string())?;
+            if read == 0 {
+                return Ok(sent);
+            }
+            let data = Element::new("data")
+                .with_attr("seq", &seq.to_string())
+                .with_text(&BASE64.encode(&buf[..read]));
+            session::request(iq(data)).await?;
+            seq = seq.wrapping_add(1);
+            sent += read as u64;
+            reporter.update(sent);
+        }
+    }
+    .await;
+    // Close either way, so the peer doesn't wait for more.
+    let _ = session::request(iq(Element::new("close"))).await;
+    result
+}
+
+async fn send_ibb_file(
+    sid: &str,
+    to: &str,
+    path: &Path,
+    cancelled: &AtomicBool,
+    reporter: &mut Reporter,
+) -> Result<u64, String> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Cannot open {}: {e}", path.display()))?;
+    send_ibb(sid, to, &mut file, None, cancelled, reporter).await
+}
+
+#[tauri::command]
+pub fn expect_bytestream(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, Arc<Bytestreams>>,
+    sid: String,
+    from: String,
+    path: PathBuf,
+    size: Option<u64>,
+) -> Result<(), String> {
+    if sid.is_empty() || !from.contains('/') {
+        return Err("A stream id and the sender's full JID are required".to_string());
+    }
+    let reporter = Reporter::new(Some(app), &sid, size);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    state.expect(&sid, &from, (path, 0), size, cancelled, reporter)
+}
+
+/// Send `path` to the full JID `to` on stream `sid`. Resolves once the
+/// transfer has ended, with the number of bytes sent.
+#[tauri::command]
+pub async fn send_bytestream(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, Arc<Bytestreams>>,
+    sid: String,
+    to: String,
+    path: PathBuf,
+    method: Option<Method>,
+) -> Result<u64, String> {
+    let size = tokio::fs::metadata(&path).await.ok().map(|m| m.len());
+    let cancelled = Arc::new(AtomicBool::new(false));
+    lock(&state.outbound).insert(sid.clone(), cancelled.clone());
+    let mut reporter = Reporter::new(Some(app), &sid, size);
+    let result = match method.unwrap_or_default() {
+        Method::S5b => {
+            state
+                .send_s5b(&sid, &to, &path, &cancelled, &mut reporter)
+                .await
+        }
+        Method::Ibb => send_ibb_file(&sid, &to, &path, &cancelled, &mut reporter).await,
+        Method::Auto => match state
+            .send_s5b(&sid, &to, &path, &cancelled, &mut reporter)
+            .await
+        {
+            Err(e) if !cancelled.load(Ordering::Relaxed) => {
+                info!(sid = %sid, error = %e, "bytestreams: SOCKS5 failed, falling back to IBB");
+                send_ibb_file(&sid, &to, &path, &cancelled, &mut reporter).await
+            }
+            other => other,
+        },
+    };
+    lock(&state.outbound).remove(&sid);
+    reporter.finish(&result);
+    result
+}
+
+/// Abort a transfer in either direction. A partial download is deleted.
+#[tauri::command]
+pub fn cancel_bytestream(state: tauri::State<'_, Arc<Bytestreams>>, sid: String) {
+    if let Some(cancelled) = lock(&state.outbound).get(&sid) {
+        cancelled.store(true, Ordering::Relaxed);
+    }
+    if let Some(transfer) = state.take_inbound(&sid) {
+        transfer.cancelled.store(true, Ordering::Relaxed);
+        info!(sid = %sid, peer = %bare_jid(&transfer.peer), "bytestreams: cancelled");
+    }
+}
+
+#[tauri::command]
+pub async fn discover_bytestream_proxies(
+    state: tauri::State<'_, Arc<Bytestreams>>,
+    refresh: Option<bool>,
+) -> Result<Vec<StreamHost>, String> {
+    state.proxies(refresh.unwrap_or(false)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destination_address_matches_the_spec_example() {
+        // XEP-0065 Example 22's stream and JIDs.
+        assert_eq!(
+            dst_addr(
+                "vxf9n471bn46",
+                "requester@example.com/foo",
+                "target@example.org/bar"
+            ),
+            "98b8d688d0f5d895fd41c5e7309a2e9e33ba32ff"
+        );
+    }
+
+    #[tokio::test]
+    async fn socks5_handshake_requests_the_hashed_domain() {
+        let (mut client, mut proxy) = tokio::io::duplex(1024);
+        let dst = dst_addr("sid", "a@example.com/x", "b@example.com/y");
+        let expected = dst.clone();
+        let server = tokio::spawn(async move {
+            let mut greeting = [0u8; 3];
+            proxy.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            proxy.write_all(&[5, 0]).await.unwrap();
+            let mut head = [0u8; 5];
+            proxy.read_exact(&mut head).await.unwrap();
+            assert_eq!(head, [5, 1, 0, 3, 40]);
+            let mut rest = vec![0u8; 42];
+            proxy.read_exact(&mut rest).await.unwrap();
+            assert_eq!(&rest[..40], expected.as_bytes());
+            let mut answer = vec![5, 0, 0, 3, 40];
+            answer.extend_from_slice(expected.as_bytes());
+            answer.extend_from_slice(&[0, 0]);
+            proxy.write_all(&answer).await.unwrap();
+            proxy.write_all(b"payload").await.unwrap();
+        });
+        socks5_connect(&mut client, &dst).await.unwrap();
+        let mut payload = [0u8; 7];
+        client.read_exact(&mut payload).await.unwrap();
+        assert_eq!(&payload, b"payload");
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,291 @@
+//! Disk cache manager: one place for the app cache's categories, their
+//! quotas and eviction.
+//!
+//! Each category is a subdirectory of the app cache (avatars, video
+//! thumbnails, GIF previews, downloaded attachments), the same roots
+//! `media_server` serves. A category over its quota loses its least
+//! recently used files first; "used" is the modification time, which
+//! `touch` refreshes when `media_server` serves a file. Quotas have
+//! defaults and can be changed per category (persisted in
+//! `cache-quotas.json`). Categories are swept at startup, periodically, and
+//! right after an attachment is cached.
+
+use crate::json_file::JsonFile;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tauri::Manager;
+use tracing::info;
+
+/// Cache subdirectories, each with its own quota.
+pub const CATEGORIES: [&str; 4] = ["avatars", "thumbnails", "gifs", "attachments"];
+
+const MIB: u64 = 1024 * 1024;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// A served file's timestamp is refreshed at most this often.
+const TOUCH_INTERVAL: Duration = Duration::from_secs(3600);
+
+fn default_quota(category: &str) -> u64 {
+    match category {
+        "avatars" => 50 * MIB,
+        "thumbnails" => 200 * MIB,
+        "gifs" => 100 * MIB,
+        _ => 2048 * MIB,
+    }
+}
+
+static QUOTAS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+fn quotas() -> std::sync::MutexGuard<'static, BTreeMap<String, u64>> {
+    QUOTAS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn quota(category: &str) -> u64 {
+    quotas()
+        .get(category)
+        .copied()
+        .unwrap_or_else(|| default_quota(category))
+}
+
+fn check_category(category: &str) -> Result<(), String> {
+    if CATEGORIES.contains(&category) {
+        Ok(())
+    } else {
+        Err(format!("Unknown cache category: {category}"))
+    }
+}
+
+/// Cache directory for a category, e.g. `<app cache>/attachments`.
+pub fn dir(app: &tauri::AppHandle, category: &str) -> Result<PathBuf, String> {
+    check_category(category)?;
+    Ok(app
+        .path()
+        .app_cache_dir()
+        .map_err(|e| format!("No cache directory: {e}"))?
+        .join(category))
+}
+
+/// Load the quota overrides from `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("cache-quotas.json")) else {
+        return;
+    };
+    let loaded: BTreeMap<String, u64> = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *quotas() = loaded;
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `QUOTAS` lock held; see [`crate::json_file`].
+fn persist(snapshot: BTreeMap<String, u64>) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+/// A cached file, for eviction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    path: PathBuf,
+    len: u64,
+    used: SystemTime,
+}
+
+fn entries(dir: &Path) -> Vec<Entry> {
+    let Ok(read) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    read.flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then(|| Entry {
+                path: entry.path(),
+                len: metadata.len(),
+                used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            })
+        })
+        .collect()
+}
+
+/// The files to delete, least recently used first, to bring `entries`
+/// within `quota`.
+fn victims(mut entries: Vec<Entry>, quota: u64) -> Vec<Entry> {
+    let mut total: u64 = entries.iter().map(|e| e.len).sum();
+    entries.sort_by_key(|e| e.used);
+    entries
+        .into_iter()
+        .take_while(|entry| {
+            let over = total > quota;
+            total -= entry.len;
+            over
+        })
+        .collect()
+}
+
+/// Evict from `category` until it fits its quota. Blocking.
+pub fn enforce(app: &tauri::AppHandle, category: &str) {
+    let Ok(dir) = dir(app, category) else {
+        return;
+    };
+    let evicted = victims(entries(&dir), quota(category));
+    if evicted.is_empty() {
+        return;
+    }
+    let mut freed = 0;
+    for entry in &evicted {
+        if std::fs::remove_file(&entry.path).is_ok() {
+            freed += entry.len;
+        }
+    }
+    info!(
+        category,
+        files = evicted.len(),
+        freed,
+        "cache: evicted least recently used files"
+    );
+}
+
+/// Mark a cached file as used, so eviction keeps it longer.
+pub fn touch(path: &Path) {
+    let now = SystemTime::now();
+    let recent = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() < TOUCH_INTERVAL);
+    if recent {
+        return;
+    }
+    if let Ok(file) = std::fs::OpenOptions::new().write(true).open(path) {
+        let _ = file.set_modified(now);
+    }
+}
+
+/// Sweep every category now and then every `SWEEP_INTERVAL`.
+pub fn start(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let sweeping = app.clone();
+            let _ = tauri::async_runtime::spawn_blocking(move || {
+                for category in CATEGORIES {
+                    enforce(&sweeping, category);
+                }
+            })
+            .await;
+            tokio::time::sleep(SWEEP_INTERVAL).await;
+        }
+    });
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheUsage {
+    pub category: String,
+    pub bytes: u64,
+    pub files: usize,
+    pub quota: u64,
+}
+
+#[tauri::command]
+pub async fn get_cache_usage(app: tauri::AppHandle) -> Result<Vec<CacheUsage>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        CATEGORIES
+            .iter()
+            .map(|category| {
+                let entries = entries(&dir(&app, category)?);
+                Ok(CacheUsage {
+                    category: category.to_string(),
+                    bytes: entries.iter().map(|e| e.len).sum(),
+                    files: entries.len(),
+                    quota: quota(category),
+                })
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Cache usage task failed: {e}"))?
+}
+
+/// Delete every file in `category`, or in all categories when it is
+/// omitted. Returns the bytes freed.
+#[tauri::command]
+pub async fn clear_cache(app: tauri::AppHandle, category: Option<String>) -> Result<u64, String> {
+    let categories: Vec<String> = match category {
+        Some(category) => {
+            check_category(&category)?;
+            vec![category]
+        }
+        None => CATEGORIES.iter().map(|c| c.to_string()).collect(),
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut freed = 0;
+        for category in &categories {
+            for entry in entries(&dir(&app, category)?) {
+                if std::fs::remove_file(&entry.path).is_ok() {
+                    freed += entry.len;
+                }
+            }
+        }
+        info!(?categories, freed, "cache: cleared");
+        Ok(freed)
+    })
+    .await
+    .map_err(|e| format!("Cache clear task failed: {e}"))?
+}
+
+/// Set the quota of `category` in bytes; `None` restores the default. The
+/// category is trimmed to the new quota right away.
+#[tauri::command]
+pub async fn set_cache_quota(
+    app: tauri::AppHandle,
+    category: String,
+    bytes: Option<u64>,
+) -> Result<(), String> {
+    check_category(&category)?;
+    {
+        let mut all = quotas();
+        match bytes {
+            Some(bytes) => all.insert(category.clone(), bytes),
+            None => all.remove(&category),
+        };
+        persist(all.clone());
+    }
+    tauri::async_runtime::spawn_blocking(move || enforce(&app, &category))
+        .await
+        .map_err(|e| format!("Cache eviction task failed: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_until_under_quota() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let entry = |name: &str, len, used| Entry {
+            path: PathBuf::from(name),
+            len,
+            used: at(used),
+        };
+        let cached = vec![
+            entry("recent", 40, 300),
+            entry("oldest", 30, 100),
+            entry("middle", 50, 200),
+        ];
+        let names =
+            |victims: Vec<Entry>| -> Vec<PathBuf> { victims.into_iter().map(|e| e.path).collect() };
+        assert!(victims(cached.clone(), 120).is_empty());
+        assert_eq!(
+            names(victims(cached.clone(), 100)),
+            [PathBuf::from("oldest")]
+        );
+        assert_eq!(
+            names(victims(cached.clone(), 40)),
+            [PathBuf::from("oldest"), PathBuf::from("middle")]
+        );
+        assert_eq!(victims(cached, 0).len(), 3);
+    }
+}
@@ -0,0 +1,507 @@
+//! Entity capabilities (XEP-0115) cache and native disco#info service.
+//!
+//! Every presence carries a caps hash. Once the disco#info behind a hash has
+//! been fetched and verified it never changes, so it is cached on disk and
+//! shared by every contact advertising the same hash: [`get_peer_features`]
+//! answers from the cache whenever it can and only queries the peer on a
+//! miss. In the other direction, disco#info queries addressed to our own
+//! client are answered by the bridge from the (verified) answer the WebView
+//! last gave for the same hash, so a room join or a contact's client
+//! refreshing its caches doesn't wake the WebView.
+
+use crate::dataforms::DATA_FORMS_NS;
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, Element, Node};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+
+const CAPS_NS: &str = "http://jabber.org/protocol/caps";
+const DISCO_INFO_NS: &str = "http://jabber.org/protocol/disco#info";
+
+/// Verified hashes kept on disk. Generous: a hash is shared by every user of
+/// the same client build, so even large rosters produce few distinct ones.
+const MAX_CACHED_HASHES: usize = 2048;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Identity {
+    pub category: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub name: Option<String>,
+    pub lang: Option<String>,
+}
+
+/// Identities and features of an entity, as returned to the frontend.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoInfo {
+    pub identities: Vec<Identity>,
+    pub features: Vec<String>,
+}
+
+impl DiscoInfo {
+    fn from_query(query: &Element) -> Self {
+        DiscoInfo {
+            identities: query
+                .elements()
+                .filter(|e| e.local_name() == "identity")
+                .map(|e| Identity {
+                    category: e.attr("category").unwrap_or_default().to_string(),
+                    kind: e.attr("type").unwrap_or_default().to_string(),
+                    name: e.attr("name").map(str::to_string),
+                    lang: e.attr("xml:lang").map(str::to_string),
+                })
+                .collect(),
+            features: query
+                .elements()
+                .filter(|e| e.local_name() == "feature")
+                .filter_map(|e| e.attr("var"))
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    info: DiscoInfo,
+    /// Insertion order, used to evict the oldest hashes first.
+    seq: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedCache {
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// Caps advertised in a peer's latest presence.
+#[derive(Debug, Clone)]
+struct PeerCaps {
+    node: String,
+    ver: String,
+}
+
+#[derive(Default)]
+struct State {
+    /// Verified sha-1 `ver` → disco#info.
+    entries: HashMap<String, CacheEntry>,
+    next_seq: u64,
+    /// Full JID → caps from its latest available presence.
+    peers: HashMap<String, PeerCaps>,
+    /// `ver` our client currently advertises.
+    own_ver: Option<String>,
+    /// Verified disco#info answers our client gave, by `ver`.
+    own_info: HashMap<String, Element>,
+}
+
+impl State {
+    fn insert(&mut self, ver: String, info: DiscoInfo) {
+        self.next_seq += 1;
+        self.entries.insert(
+            ver,
+            CacheEntry {
+                info,
+                seq: self.next_seq,
+            },
+        );
+        if self.entries.len() > MAX_CACHED_HASHES {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.seq)
+                .map(|(ver, _)| ver.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Process-wide caps cache. Registered with the bridge tap and held in Tauri
+/// managed state as `Arc<CapsCache>`.
+pub struct CapsCache {
+    state: Mutex<State>,
+    /// Where verified hashes are persisted; `None` keeps the cache in memory.
+    file: Option<JsonFile>,
+}
+
+impl CapsCache {
+    /// Load the persisted cache. A missing or unreadable file starts empty.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let persisted: PersistedCache = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(cache) => Some(cache),
+                Err(e) => {
+                    warn!(error = %e, "caps: discarding unreadable cache file");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let next_seq = persisted.entries.values().map(|e| e.seq).max().unwrap_or(0);
+        CapsCache {
+            state: Mutex::new(State {
+                entries: persisted.entries,
+                next_seq,
+                ..State::default()
+            }),
+            file: path.map(JsonFile::new),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Write the cache in the background; observers must not block on disk.
+    /// Called with the state lock held; see [`crate::json_file`].
+    fn persist(&self, state: &State) {
+        if let Some(file) = &self.file {
+            file.save(PersistedCache {
+                entries: state.entries.clone(),
+            });
+        }
+    }
+
+    /// Cached info for a full JID whose caps hash is already verified.
+    fn cached_for(&self, jid: &str) -> Option<DiscoInfo> {
+        let state = self.lock();
+        let peer = state.peers.get(jid)?;
+        state.entries.get(&peer.ver).map(|e| e.info.clone())
+    }
+
+    fn peer_caps(&self, jid: &str) -> Option<PeerCaps> {
+        self.lock().peers.get(jid).cloned()
+    }
+
+    /// Verify a disco#info answer against `ver` and cache it on success.
+    fn learn(&self, ver: &str, query: &Element) -> bool {
+        if verification_string_hash(query) != ver {
+            debug!(ver, "caps: disco#info does not match advertised hash");
+            return false;
+        }
+        let mut state = self.lock();
+        if state.entries.contains_key(ver) {
+            return true;
+        }
+        state.insert(ver.to_string(), DiscoInfo::from_query(query));
+        self.persist(&state);
+        true
+    }
+
+    fn ingest_inbound(&self, stanza: &Element) {
+        match stanza.local_name() {
+            "presence" => {
+                let Some(from) = stanza.attr("from") else {
+                    return;
+                };
+                let caps = stanza
+                    .child("c", Some(CAPS_NS))
+                    .filter(|c| c.attr("hash") == Some("sha-1"))
+                    .and_then(|c| {
+                        Some(PeerCaps {
+                            node: c.attr("node")?.to_string(),
+                            ver: c.attr("ver")?.to_string(),
+                        })
+                    });
+                let mut state = self.lock();
+                match (stanza.attr("type"), caps) {
+                    (None, Some(caps)) => {
+                        state.peers.insert(from.to_string(), caps);
+                    }
+                    (Some("unavailable"), _) => {
+                        state.peers.remove(from);
+                    }
+                    _ => {}
+                }
+            }
+            "iq" if stanza.attr("type") == Some("result") => {
+                // Answers to the client's own caps lookups feed the cache too.
+                if let Some((query, ver)) = caps_query(stanza) {
+                    self.learn(ver, query);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ingest_outbound(&self, stanza: &Element) {
+        match stanza.local_name() {
+            // Only broadcast presence defines what we advertise.
+            "presence" if stanza.attr("to").is_none() && stanza.attr("type").is_none() => {
+                if let Some(ver) = stanza.child("c", Some(CAPS_NS)).and_then(|c| c.attr("ver")) {
+                    self.lock().own_ver = Some(ver.to_string());
+                }
+            }
+            "iq" if stanza.attr("type") == Some("result") => {
+                if let Some((query, ver)) = caps_query(stanza) {
+                    if verification_string_hash(query) == ver {
+                        self.lock().own_info.insert(ver.to_string(), query.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The answer to an inbound disco#info query for our own client, when the
+    /// client has already given a verified answer for the requested hash.
+    fn answer_for(&self, request: &Element) -> Option<Element> {
+        if request.attr("type") != Some("get") {
+            return None;
+        }
+        let query = request.child("query", Some(DISCO_INFO_NS))?;
+        let own_jid = session::own_jid()?;
+        if request.attr("to") != Some(own_jid.as_str()) {
+            return None;
+        }
+        let from = request.attr("from")?;
+        let id = request.attr("id")?;
+        let state = self.lock();
+        let ver = match query.attr("node") {
+            Some(node) => node.rsplit_once('#')?.1,
+            None => state.own_ver.as_deref()?,
+        };
+        let known = state.own_info.get(ver)?;
+        let mut answer = Element::new("query").with_attr("xmlns", DISCO_INFO_NS);
+        if let Some(node) = query.attr("node") {
+            answer.set_attr("node", node);
+        }
+        answer.children = known
+            .children
+            .iter()
+            .filter(|node| matches!(node, Node::Element(_)))
+            .cloned()
+            .collect();
+        Some(
+            Element::new("iq")
+                .with_attr("type", "result")
+                .with_attr("to", from)
+                .with_attr("id", id)
+                .with_child(answer),
+        )
+    }
+}
+
+impl StanzaObserver for CapsCache {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        match ctx.direction {
+            Direction::Inbound => {
+                if stanza.local_name() == "iq" {
+                    if let Some(answer) = self.answer_for(stanza) {
+                        if session::send(answer).is_ok() {
+                            return Verdict::Drop;
+                        }
+                    }
+                }
+                self.ingest_inbound(stanza);
+            }
+            Direction::Outbound => self.ingest_outbound(stanza),
+        }
+        Verdict::Forward
+    }
+}
+
+/// The disco#info payload of an IQ result and the caps `ver` it answers
+/// (from its `node#ver` node attribute).
+fn caps_query(iq: &Element) -> Option<(&Element, &str)> {
+    let query = iq.child("query", Some(DISCO_INFO_NS))?;
+    let ver = query.attr("node")?.rsplit_once('#')?.1;
+    Some((query, ver))
+}
+
+/// XEP-0115 §5.1 verification string, hashed with SHA-1 and base64-encoded.
+fn verification_string_hash(query: &Element) -> String {
+    let mut s = String::new();
+
+    let mut identities: Vec<(String, String, String, String)> = query
+        .elements()
+        .filter(|e| e.local_name() == "identity")
+        .map(|e| {
+            let attr = |name: &str| e.attr(name).unwrap_or_default().to_string();
+            (attr("category"), attr("type"), attr("xml:lang"), attr("name"))
+        })
+        .collect();
+    identities.sort();
+    for (category, kind, lang, name) in identities {
+        s.push_str(&format!("{category}/{kind}/{lang}/{name}<"));
+    }
+
+    let mut features: Vec<&str> = query
+        .elements()
+        .filter(|e| e.local_name() == "feature")
+        .filter_map(|e| e.attr("var"))
+        .collect();
+    features.sort_unstable();
+    for feature in features {
+        s.push_str(feature);
+        s.push('<');
+    }
+
+    let mut forms: Vec<(String, Vec<(String, Vec<String>)>)> = query
+        .elements()
+        .filter(|e| e.local_name() == "x" && e.ns() == Some(DATA_FORMS_NS))
+        .filter_map(|form| {
+            let mut form_type = None;
+            let mut fields = Vec::new();
+            for field in form.elements().filter(|e| e.local_name() == "field") {
+                let Some(var) = field.attr("var").map(str::to_string) else {
+                    continue;
+                };
+                let mut values: Vec<String> = field
+                    .elements()
+                    .filter(|e| e.local_name() == "value")
+                    .map(|e| e.text())
+                    .collect();
+                if var == "FORM_TYPE" {
+                    form_type = values.pop();
+                } else {
+                    values.sort();
+                    fields.push((var, values));
+                }
+            }
+            fields.sort();
+            // Forms without a FORM_TYPE are ignored by the algorithm.
+            Some((form_type?, fields))
+        })
+        .collect();
+    forms.sort();
+    for (form_type, fields) in forms {
+        s.push_str(&form_type);
+        s.push('<');
+        for (var, values) in fields {
+            s.push_str(&var);
+            s.push('<');
+            for value in values {
+                s.push_str(&value);
+                s.push('<');
+            }
+        }
+    }
+
+    BASE64.encode(Sha1::digest(s.as_bytes()))
+}
+
+/// Identities and features of `jid`, served from the caps cache when its
+/// advertised hash is already known and verified.
+#[tauri::command]
+pub async fn get_peer_features(
+    caps: tauri::State<'_, Arc<CapsCache>>,
+    jid: String,
+) -> Result<DiscoInfo, String> {
+    if let Some(info) = caps.cached_for(&jid) {
+        return Ok(info);
+    }
+    let peer = caps.peer_caps(&jid);
+    let mut query = Element::new("query").with_attr("xmlns", DISCO_INFO_NS);
+    if let Some(peer) = &peer {
+        query.set_attr("node", &format!("{}#{}", peer.node, peer.ver));
+    }
+    let iq = Element::new("iq")
+        .with_attr("type", "get")
+        .with_attr("to", &jid)
+        .with_child(query);
+    let response = session::request(iq).await?;
+    let query = response
+        .child("query", Some(DISCO_INFO_NS))
+        .ok_or("Malformed disco#info response")?;
+    if let Some(peer) = &peer {
+        caps.learn(&peer.ver, query);
+    } else {
+        debug!(jid = %bare_jid(&jid), "caps: uncached disco#info (no caps advertised)");
+    }
+    Ok(DiscoInfo::from_query(query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// XEP-0115 §5.3 "Complex Generation Example".
+    const COMPLEX_QUERY: &str = "<query xmlns='http://jabber.org/protocol/disco#info' node='http://psi-im.org#q07IKJEyjvHSyhy//CH0CxmKi8w='>\
+        <identity xml:lang='en' category='client' name='Psi 0.11' type='pc'/>\
+        <identity xml:lang='el' category='client' name='Ψ 0.11' type='pc'/>\
+        <feature var='http://jabber.org/protocol/caps'/>\
+        <feature var='http://jabber.org/protocol/disco#info'/>\
+        <feature var='http://jabber.org/protocol/disco#items'/>\
+        <feature var='http://jabber.org/protocol/muc'/>\
+        <x xmlns='jabber:x:data' type='result'>\
+          <field var='FORM_TYPE' type='hidden'><value>urn:xmpp:dataforms:softwareinfo</value></field>\
+          <field var='ip_version'><value>ipv4</value><value>ipv6</value></field>\
+          <field var='os'><value>Mac</value></field>\
+          <field var='os_version'><value>10.5.1</value></field>\
+          <field var='software'><value>Psi</value></field>\
+          <field var='software_version'><value>0.11</value></field>\
+        </x></query>";
+
+    #[test]
+    fn verification_matches_xep_examples() {
+        let simple = Element::parse(
+            "<query xmlns='http://jabber.org/protocol/disco#info'>\
+             <identity category='client' name='Exodus 0.9.1' type='pc'/>\
+             <feature var='http://jabber.org/protocol/caps'/>\
+             <feature var='http://jabber.org/protocol/disco#info'/>\
+             <feature var='http://jabber.org/protocol/disco#items'/>\
+             <feature var='http://jabber.org/protocol/muc'/></query>",
+        )
+        .unwrap();
+        assert_eq!(verification_string_hash(&simple), "QgayPKawpkPSDYmwT/WM94uAlu0=");
+        let complex = Element::parse(COMPLEX_QUERY).unwrap();
+        assert_eq!(verification_string_hash(&complex), "q07IKJEyjvHSyhy//CH0CxmKi8w=");
+    }
+
+    #[test]
+    fn presence_and_verified_result_serve_lookups() {
+        let caps = CapsCache::load(None);
+        caps.ingest_inbound(
+            &Element::parse(
+                "<presence from='juliet@capulet.lit/balcony'><c xmlns='http://jabber.org/protocol/caps' hash='sha-1' node='http://psi-im.org' ver='q07IKJEyjvHSyhy//CH0CxmKi8w='/></presence>",
+            )
+            .unwrap(),
+        );
+        assert!(caps.cached_for("juliet@capulet.lit/balcony").is_none());
+
+        let result = format!("<iq type='result' id='d1' from='juliet@capulet.lit/balcony'>{COMPLEX_QUERY}</iq>");
+        caps.ingest_inbound(&Element::parse(&result).unwrap());
+        let info = caps.cached_for("juliet@capulet.lit/balcony").expect("cached");
+        assert_eq!(info.identities.len(), 2);
+        assert!(info.features.iter().any(|f| f == "http://jabber.org/protocol/muc"));
+
+        caps.ingest_inbound(
+            &Element::parse("<presence from='juliet@capulet.lit/balcony' type='unavailable'/>").unwrap(),
+        );
+        assert!(caps.cached_for("juliet@capulet.lit/balcony").is_none());
+    }
+
+    #[test]
+    fn rejects_answers_that_do_not_match_the_hash() {
+        let caps = CapsCache::load(None);
+        let forged = Element::parse(
+            "<query xmlns='http://jabber.org/protocol/disco#info' node='n#QgayPKawpkPSDYmwT/WM94uAlu0='><feature var='urn:evil'/></query>",
+        )
+        .unwrap();
+        assert!(!caps.learn("QgayPKawpkPSDYmwT/WM94uAlu0=", &forged));
+        assert!(caps.lock().entries.is_empty());
+    }
+
+    #[test]
+    fn evicts_oldest_hash_beyond_limit() {
+        let mut state = State::default();
+        for i in 0..=MAX_CACHED_HASHES {
+            state.insert(format!("v{i}"), DiscoInfo::default());
+        }
+        assert_eq!(state.entries.len(), MAX_CACHED_HASHES);
+        assert!(!state.entries.contains_key("v0"));
+    }
+}
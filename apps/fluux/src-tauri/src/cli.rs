@@ -0,0 +1,164 @@
+//! Terminal-only subcommands, for diagnosis without the GUI.
+//!
+//! - `doctor <domain>`: probe the server the way the app connects and print
+//!   the report (see `xmpp_proxy::doctor`).
+//! - `logs [--tail [N]]`: list the log files, or print the last N lines of
+//!   the newest one.
+//! - `clear-credentials`: delete the saved login from the OS keychain.
+//!
+//! Each runs before the tracing subscriber and the Tauri app are set up and
+//! exits when done; anything else on the command line starts the app.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Tauri bundle identifier, the app data directory's name.
+const APP_IDENTIFIER: &str = "com.processone.fluux";
+const DEFAULT_TAIL_LINES: usize = 100;
+const TAIL_CHUNK: u64 = 64 * 1024;
+
+pub const USAGE: &str = "\
+Commands:
+  doctor <domain>       Test DNS, TCP and TLS to an XMPP server and print a report
+  logs                  List the log files
+  logs --tail [N]       Print the last N lines (default 100) of the newest log
+  clear-credentials     Delete the saved login from the system keychain";
+
+/// Run the subcommand in `args`, if there is one, and return the process
+/// exit code. `None` means start the app.
+pub fn run(args: &[String], log_dir: &Path) -> Option<i32> {
+    let command = args.get(1)?;
+    let rest = &args[2..];
+    let result = match command.as_str() {
+        "doctor" => doctor(rest),
+        "logs" => logs(rest, log_dir),
+        "clear-credentials" => clear_credentials(),
+        _ => return None,
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    })
+}
+
+fn doctor(args: &[String]) -> Result<(), String> {
+    let Some(server) = args.iter().find(|arg| !arg.starts_with('-')) else {
+        return Err(format!("Usage: fluux-messenger doctor <domain>\n\n{USAGE}"));
+    };
+    // Probe with the same network settings, fallback hosts and extra CAs
+    // the app would use.
+    let data_dir = dirs::data_dir().map(|d| d.join(APP_IDENTIFIER));
+    crate::xmpp_proxy::net_prefs::load(data_dir.clone());
+    crate::xmpp_proxy::host_overrides::load(data_dir.clone());
+    crate::xmpp_proxy::trust::load(data_dir);
+    println!(
+        "Fluux Messenger v{} connection check for {server}\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    match tauri::async_runtime::block_on(crate::xmpp_proxy::doctor::run(server)) {
+        Ok(report) => {
+            print!("{report}");
+            Ok(())
+        }
+        Err(report) => {
+            print!("{report}");
+            Err("The server could not be reached securely.".to_string())
+        }
+    }
+}
+
+/// Log files in `log_dir`, oldest first. Their names carry the date.
+fn log_files(log_dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(log_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| n.starts_with("fluux.") && n.ends_with(".log"))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+fn logs(args: &[String], log_dir: &Path) -> Result<(), String> {
+    let files = log_files(log_dir);
+    let Some(tail_at) = args.iter().position(|arg| arg == "--tail") else {
+        println!("{}", log_dir.display());
+        for file in &files {
+            let size = std::fs::metadata(file).map_or(0, |m| m.len());
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            println!("  {name}  {size} bytes");
+        }
+        return Ok(());
+    };
+    let lines = match args.get(tail_at + 1) {
+        Some(n) => n
+            .parse()
+            .map_err(|_| format!("--tail expects a line count, got {n:?}"))?,
+        None => DEFAULT_TAIL_LINES,
+    };
+    let newest = files
+        .last()
+        .ok_or_else(|| format!("No log files in {}", log_dir.display()))?;
+    let text = tail(newest, lines).map_err(|e| format!("{}: {e}", newest.display()))?;
+    println!("{text}");
+    Ok(())
+}
+
+/// The last `lines` lines of `path`, read backwards from the end so a large
+/// day's log isn't loaded whole.
+fn tail(path: &Path, lines: usize) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut pos = file.metadata()?.len();
+    let mut buf: Vec<u8> = Vec::new();
+    // One newline more than asked for: the line before the first one kept
+    // is then known to be complete.
+    while pos > 0 && buf.iter().filter(|&&b| b == b'\n').count() <= lines {
+        let step = pos.min(TAIL_CHUNK);
+        pos -= step;
+        file.seek(SeekFrom::Start(pos))?;
+        let mut chunk = vec![0u8; step as usize];
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+    }
+    let text = String::from_utf8_lossy(&buf);
+    let all: Vec<&str> = text.lines().collect();
+    Ok(all[all.len().saturating_sub(lines)..].join("\n"))
+}
+
+fn clear_credentials() -> Result<(), String> {
+    // From the backend the app uses, which may be the file fallback.
+    crate::keychain::load(dirs::data_dir().map(|d| d.join(APP_IDENTIFIER)));
+    crate::clear_stored_credentials()?;
+    println!("Saved credentials removed from the system keychain.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_returns_the_last_lines_across_chunks() {
+        let path = std::env::temp_dir().join(format!("fluux-cli-tail-{}", uuid::Uuid::new_v4()));
+        let text: String = (0..20_000).map(|i| format!("line {i}\n")).collect();
+        std::fs::write(&path, &text).unwrap();
+        assert_eq!(
+            tail(&path, 3).unwrap(),
+            "line 19997\nline 19998\nline 19999"
+        );
+        assert_eq!(tail(&path, 50_000).unwrap().lines().count(), 20_000);
+        std::fs::remove_file(&path).unwrap();
+        assert!(run(&["fluux-messenger".to_string()], Path::new(".")).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+//! Clipboard attachments: copied images and copied files.
+//!
+//! The WebView's clipboard API never exposes file paths and hands large
+//! bitmaps over as re-encoded blobs. Reading the native clipboard here gets
+//! the real file list, or the bitmap written once to a PNG temp file, and
+//! the frontend uploads from the resulting paths like any other attachment.
+
+use crate::file_info::{self, FileInfo};
+use serde::Serialize;
+use std::io::Cursor;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipboardAttachment {
+    /// `files` when the clipboard held a file list, `image` for a bitmap.
+    pub kind: String,
+    pub files: Vec<FileInfo>,
+}
+
+fn encode_png(width: usize, height: usize, rgba: Vec<u8>) -> Result<Vec<u8>, String> {
+    let image = image::RgbaImage::from_raw(width as u32, height as u32, rgba)
+        .ok_or("Clipboard image has an unexpected size")?;
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgba8(image)
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode clipboard image: {e}"))?;
+    Ok(png)
+}
+
+fn read_clipboard() -> Result<Option<ClipboardAttachment>, String> {
+    let mut clipboard =
+        arboard::Clipboard::new().map_err(|e| format!("Clipboard unavailable: {e}"))?;
+
+    // Copying a file in Finder/Explorer/Files often puts its icon or preview
+    // on the clipboard as well; the file itself is what the user meant.
+    if let Ok(paths) = clipboard.get().file_list() {
+        let files: Vec<FileInfo> = paths
+            .iter()
+            .filter_map(|p| file_info::describe(p).ok())
+            .collect();
+        if !files.is_empty() {
+            return Ok(Some(ClipboardAttachment {
+                kind: "files".to_string(),
+                files,
+            }));
+        }
+    }
+
+    let image = match clipboard.get_image() {
+        Ok(image) => image,
+        Err(arboard::Error::ContentNotAvailable) => return Ok(None),
+        Err(e) => return Err(format!("Failed to read clipboard image: {e}")),
+    };
+    let png = encode_png(image.width, image.height, image.bytes.into_owned())?;
+    // Deleted with the session's other temp files.
+    let path = crate::temp_files::path(
+        "clipboard",
+        &format!("pasted-image-{}.png", uuid::Uuid::new_v4()),
+    )?;
+    std::fs::write(&path, png).map_err(|e| format!("Failed to write pasted image: {e}"))?;
+    Ok(Some(ClipboardAttachment {
+        kind: "image".to_string(),
+        files: vec![file_info::describe(&path)?],
+    }))
+}
+
+/// Files or an image currently on the clipboard, as local paths ready for
+/// upload. `None` when the clipboard holds neither (e.g. plain text).
+#[tauri::command]
+pub async fn get_clipboard_attachment() -> Result<Option<ClipboardAttachment>, String> {
+    tauri::async_runtime::spawn_blocking(read_clipboard)
+        .await
+        .map_err(|e| format!("Clipboard task panicked: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_rgba_buffer_as_png() {
+        let png = encode_png(2, 1, vec![255, 0, 0, 255, 0, 255, 0, 255]).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        assert!(encode_png(2, 2, vec![0; 4]).is_err());
+    }
+}
@@ -0,0 +1,475 @@
+//! Compliance archive: a tamper-evident local record of every message.
+//!
+//! Some regulated deployments must retain their correspondence on the
+//! client, independently of the server's archive. With compliance mode on,
+//! every message carrying a body that passes through the bridge, sent or
+//! received, is appended to `compliance-archive.jsonl` in the app data
+//! directory. IQs, presence and body-less messages (chat states, receipts)
+//! are not recorded.
+//!
+//! Each line is one JSON [`Entry`] whose `hash` is the SHA-256 of the entry
+//! itself (without `hash`), and whose `prev` is the hash of the line before
+//! it. Editing, removing or reordering a line breaks the chain from that
+//! point on, which [`verify_compliance_archive`] reports. The archive is
+//! append-only; the app never rewrites or prunes it.
+//!
+//! The mode is a user setting, unless the managed policy enforces it (see
+//! [`crate::managed_policy`]).
+
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::Element;
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// `prev` of the first entry.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One archived message. Field order is part of the hash input: never
+/// reorder, only append optional fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub seq: u64,
+    /// Unix milliseconds when the bridge relayed the message.
+    pub ts: u64,
+    /// `in` (received) or `out` (sent).
+    pub direction: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub body: String,
+    /// The complete stanza as relayed.
+    pub xml: String,
+    pub prev: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Line {
+    #[serde(flatten)]
+    entry: Entry,
+    hash: String,
+}
+
+fn entry_hash(entry: &Entry) -> Result<String, String> {
+    let bytes = serde_json::to_vec(entry).map_err(|e| e.to_string())?;
+    Ok(Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// Outcome of walking the chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainReport {
+    /// Entries that verified, from the start of the file.
+    pub entries: u64,
+    /// Hash of the last verified entry.
+    pub head: String,
+    /// 1-based line where the chain breaks, if it does.
+    pub broken_at: Option<u64>,
+    pub error: Option<String>,
+}
+
+fn verify_chain(reader: impl BufRead) -> ChainReport {
+    let mut report = ChainReport {
+        entries: 0,
+        head: GENESIS.to_string(),
+        broken_at: None,
+        error: None,
+    };
+    for (index, line) in reader.lines().enumerate() {
+        let line_no = index as u64 + 1;
+        let result = line.map_err(|e| e.to_string()).and_then(|line| {
+            let line: Line = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+            if line.entry.prev != report.head {
+                return Err("does not follow the previous entry".to_string());
+            }
+            if line.entry.seq != report.entries {
+                return Err(format!("sequence {} out of order", line.entry.seq));
+            }
+            if entry_hash(&line.entry)? != line.hash {
+                return Err("content does not match its hash".to_string());
+            }
+            Ok(line.hash)
+        });
+        match result {
+            Ok(hash) => {
+                report.entries += 1;
+                report.head = hash;
+            }
+            Err(e) => {
+                report.broken_at = Some(line_no);
+                report.error = Some(format!("line {line_no}: {e}"));
+                break;
+            }
+        }
+    }
+    report
+}
+
+fn verify_file(path: &Path) -> Result<ChainReport, String> {
+    match std::fs::File::open(path) {
+        Ok(file) => Ok(verify_chain(BufReader::new(file))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(verify_chain(&b""[..])),
+        Err(e) => Err(format!("{}: {e}", path.display())),
+    }
+}
+
+/// Appends entries, continuing the chain found on disk.
+struct Writer {
+    path: PathBuf,
+    seq: u64,
+    head: String,
+}
+
+impl Writer {
+    fn open(path: PathBuf) -> Self {
+        let (seq, head) = match verify_file(&path) {
+            Ok(report) => {
+                if let Some(error) = &report.error {
+                    // Appending still works: the break stays visible to
+                    // verification, which is the point.
+                    warn!(%error, "compliance archive: chain is broken on disk");
+                }
+                (report.entries, report.head)
+            }
+            Err(e) => {
+                warn!(error = %e, "compliance archive: unreadable, starting a new chain");
+                (0, GENESIS.to_string())
+            }
+        };
+        Self { path, seq, head }
+    }
+
+    fn append(&mut self, mut entry: Entry) -> Result<(), String> {
+        entry.seq = self.seq;
+        entry.prev = self.head.clone();
+        let hash = entry_hash(&entry)?;
+        let mut line = serde_json::to_string(&Line {
+            entry,
+            hash: hash.clone(),
+        })
+        .map_err(|e| e.to_string())?;
+        line.push('\n');
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| e.to_string())?;
+        file.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+        // A compliance record is only as good as its durability.
+        file.sync_data().map_err(|e| e.to_string())?;
+        self.seq += 1;
+        self.head = hash;
+        Ok(())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Settings {
+    enabled: bool,
+}
+
+/// Archive state, registered with the bridge tap and held in managed state
+/// as `Arc<ComplianceArchive>`.
+pub struct ComplianceArchive {
+    enabled: AtomicBool,
+    /// Turned on by the managed policy; the user cannot switch it off.
+    enforced: bool,
+    /// Archive file; `None` without an app data directory.
+    path: Option<PathBuf>,
+    settings: Option<JsonFile>,
+    /// Held while the setting changes, so the last change is also the last
+    /// saved.
+    toggle: Mutex<()>,
+    /// Shared with the writer thread; held while reading for export so a
+    /// half-written line is never copied.
+    writer: Option<Arc<Mutex<Writer>>>,
+    /// Observers must not block the bridge, so appends go through a
+    /// dedicated thread.
+    queue: Option<Mutex<mpsc::Sender<Entry>>>,
+}
+
+impl ComplianceArchive {
+    /// Load the setting from `dir` and start the writer. A missing `dir`
+    /// disables archiving.
+    pub fn load(dir: Option<PathBuf>, enforced: bool) -> Self {
+        let settings_path = dir.as_ref().map(|d| d.join("compliance.json"));
+        let settings: Settings = settings_path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        let path = dir.map(|d| d.join("compliance-archive.jsonl"));
+
+        let (writer, queue) = match &path {
+            Some(path) => {
+                let writer = Arc::new(Mutex::new(Writer::open(path.clone())));
+                let (tx, rx) = mpsc::channel::<Entry>();
+                let thread_writer = writer.clone();
+                let spawned = std::thread::Builder::new()
+                    .name("compliance-archive".to_string())
+                    .spawn(move || {
+                        for entry in rx {
+                            let mut writer =
+                                thread_writer.lock().unwrap_or_else(|e| e.into_inner());
+                            if let Err(e) = writer.append(entry) {
+                                warn!(error = %e, "compliance archive: failed to append");
+                            }
+                        }
+                    });
+                match spawned {
+                    Ok(_) => (Some(writer), Some(Mutex::new(tx))),
+                    Err(e) => {
+                        warn!(error = %e, "compliance archive: cannot start writer");
+                        (None, None)
+                    }
+                }
+            }
+            None => (None, None),
+        };
+
+        let enabled = (enforced || settings.enabled) && queue.is_some();
+        if enabled {
+            info!(enforced, "Compliance archive active");
+        }
+        Self {
+            enabled: AtomicBool::new(enabled),
+            enforced,
+            path,
+            settings: settings_path.map(JsonFile::new),
+            toggle: Mutex::new(()),
+            writer,
+            queue,
+        }
+    }
+
+    /// Called with `toggle` held; see [`crate::json_file`].
+    fn persist_settings(&self, settings: Settings) {
+        if let Some(file) = &self.settings {
+            file.save(settings);
+        }
+    }
+
+    fn enqueue(&self, entry: Entry) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        if queue
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .send(entry)
+            .is_err()
+        {
+            warn!("compliance archive: writer is gone, message not archived");
+        }
+    }
+}
+
+/// Build the entry for a relayed message, or `None` when it is not one the
+/// archive keeps.
+fn entry_for(direction: Direction, stanza: &Element, own_jid: Option<String>) -> Option<Entry> {
+    if stanza.local_name() != "message" || stanza.attr("type") == Some("error") {
+        return None;
+    }
+    let body = stanza.child("body", None)?.text();
+    if body.trim().is_empty() {
+        return None;
+    }
+    let attr = |name: &str| stanza.attr(name).map(str::to_string);
+    let (direction, from) = match direction {
+        Direction::Inbound => ("in", attr("from")),
+        // The client leaves `from` to the server.
+        Direction::Outbound => ("out", attr("from").or(own_jid)),
+    };
+    Some(Entry {
+        seq: 0,
+        ts: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        direction: direction.to_string(),
+        from,
+        to: attr("to"),
+        id: attr("id"),
+        kind: attr("type"),
+        body,
+        xml: stanza.to_xml(),
+        prev: String::new(),
+    })
+}
+
+impl StanzaObserver for ComplianceArchive {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if self.enabled.load(Ordering::Relaxed) {
+            if let Some(entry) = entry_for(ctx.direction, stanza, session::own_jid()) {
+                self.enqueue(entry);
+            }
+        }
+        Verdict::Forward
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComplianceStatus {
+    pub enabled: bool,
+    pub enforced: bool,
+    pub available: bool,
+    pub path: Option<String>,
+}
+
+fn status(archive: &ComplianceArchive) -> ComplianceStatus {
+    ComplianceStatus {
+        enabled: archive.enabled.load(Ordering::Relaxed),
+        enforced: archive.enforced,
+        available: archive.queue.is_some(),
+        path: archive.path.as_ref().map(|p| p.display().to_string()),
+    }
+}
+
+#[tauri::command]
+pub fn get_compliance_status(
+    archive: tauri::State<'_, Arc<ComplianceArchive>>,
+) -> ComplianceStatus {
+    status(&archive)
+}
+
+#[tauri::command]
+pub fn set_compliance_mode(
+    archive: tauri::State<'_, Arc<ComplianceArchive>>,
+    enabled: bool,
+) -> Result<ComplianceStatus, String> {
+    if archive.enforced && !enabled {
+        return Err("Compliance archiving is required by your administrator".to_string());
+    }
+    if enabled && archive.queue.is_none() {
+        return Err("Compliance archiving is unavailable: no data directory".to_string());
+    }
+    let _toggle = archive.toggle.lock().unwrap_or_else(|e| e.into_inner());
+    archive.enabled.store(enabled, Ordering::Relaxed);
+    archive.persist_settings(Settings { enabled });
+    info!(enabled, "Compliance archive toggled");
+    Ok(status(&archive))
+}
+
+/// Walk the hash chain and report where, if anywhere, it breaks.
+#[tauri::command]
+pub async fn verify_compliance_archive(
+    archive: tauri::State<'_, Arc<ComplianceArchive>>,
+) -> Result<ChainReport, String> {
+    let (Some(path), Some(writer)) = (archive.path.clone(), archive.writer.clone()) else {
+        return Err("Compliance archive unavailable".to_string());
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        // A line being appended would read as a broken chain.
+        let _writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        verify_file(&path)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Copy the archive to `destination` after verifying it. The report is
+/// returned so the caller can record the head hash alongside the export.
+#[tauri::command]
+pub async fn export_compliance_archive(
+    archive: tauri::State<'_, Arc<ComplianceArchive>>,
+    destination: String,
+) -> Result<ChainReport, String> {
+    let (Some(path), Some(writer)) = (archive.path.clone(), archive.writer.clone()) else {
+        return Err("Compliance archive unavailable".to_string());
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let _writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        let report = verify_file(&path)?;
+        if path.exists() {
+            std::fs::copy(&path, &destination).map_err(|e| format!("{destination}: {e}"))?;
+        } else {
+            std::fs::write(&destination, b"").map_err(|e| format!("{destination}: {e}"))?;
+        }
+        info!(
+            entries = report.entries,
+            broken = report.broken_at.is_some(),
+            "Compliance archive exported"
+        );
+        Ok(report)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(xml: &str) -> Element {
+        Element::parse(xml).unwrap()
+    }
+
+    #[test]
+    fn keeps_only_messages_with_a_body() {
+        let chat =
+            message("<message to='a@example.com' id='1' type='chat'><body>hi</body></message>");
+        let entry = entry_for(Direction::Outbound, &chat, Some("me@example.com/x".into())).unwrap();
+        assert_eq!(entry.direction, "out");
+        assert_eq!(entry.from.as_deref(), Some("me@example.com/x"));
+        assert_eq!(entry.body, "hi");
+
+        let state = message(
+            "<message to='a@example.com'>\
+             <active xmlns='http://jabber.org/protocol/chatstates'/></message>",
+        );
+        assert!(entry_for(Direction::Inbound, &state, None).is_none());
+        assert!(entry_for(Direction::Inbound, &message("<iq type='get'/>"), None).is_none());
+    }
+
+    #[test]
+    fn chain_detects_tampering() {
+        let path =
+            std::env::temp_dir().join(format!("fluux-compliance-{}.jsonl", uuid::Uuid::new_v4()));
+        let mut writer = Writer::open(path.clone());
+        for body in ["one", "two", "three"] {
+            let stanza = message(&format!(
+                "<message from='a@example.com'><body>{body}</body></message>"
+            ));
+            writer
+                .append(entry_for(Direction::Inbound, &stanza, None).unwrap())
+                .unwrap();
+        }
+        let report = verify_file(&path).unwrap();
+        assert_eq!(report.entries, 3);
+        assert_eq!(report.broken_at, None);
+        assert_eq!(report.head, writer.head);
+
+        // Reopening continues the chain.
+        let mut reopened = Writer::open(path.clone());
+        assert_eq!(reopened.seq, 3);
+        let stanza = message("<message from='a@example.com'><body>four</body></message>");
+        reopened
+            .append(entry_for(Direction::Inbound, &stanza, None).unwrap())
+            .unwrap();
+        assert_eq!(verify_file(&path).unwrap().entries, 4);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, text.replacen("two", "TWO", 1)).unwrap();
+        let report = verify_file(&path).unwrap();
+        assert_eq!(report.entries, 1);
+        assert_eq!(report.broken_at, Some(2));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+//! Evolution Data Server address books over the session bus.
+//!
+//! EDS backs GNOME Contacts and Evolution. Every enabled address-book source
+//! is opened through the address-book factory and asked for all its
+//! contacts as vCards.
+
+use super::AddressBookContact;
+use zbus::blocking::{fdo::ObjectManagerProxy, Connection, Proxy};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+const SOURCES_BUS: &str = "org.gnome.evolution.dataserver.Sources5";
+const SOURCES_PATH: &str = "/org/gnome/evolution/dataserver/SourceManager";
+const SOURCE_IFACE: &str = "org.gnome.evolution.dataserver.Source";
+const FACTORY_BUS: &str = "org.gnome.evolution.dataserver.AddressBook10";
+const FACTORY_PATH: &str = "/org/gnome/evolution/dataserver/AddressBookFactory";
+const FACTORY_IFACE: &str = "org.gnome.evolution.dataserver.AddressBookFactory";
+const BOOK_IFACE: &str = "org.gnome.evolution.dataserver.AddressBook";
+
+/// An address-book source: its UID and display name, from the source's
+/// key-file `Data`.
+fn address_book_source(data: &str) -> Option<String> {
+    let mut in_book_section = false;
+    let mut enabled = true;
+    let mut display_name = None;
+    let mut is_book = false;
+    for line in data.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_book_section = line == "[Address Book]";
+            is_book |= in_book_section;
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("DisplayName=") {
+            display_name.get_or_insert_with(|| value.to_string());
+        }
+        if in_book_section {
+            continue;
+        }
+        if line == "Enabled=false" {
+            enabled = false;
+        }
+    }
+    (is_book && enabled).then(|| display_name.unwrap_or_else(|| "Address book".to_string()))
+}
+
+fn value_string(value: &OwnedValue) -> Option<String> {
+    String::try_from(value.try_clone().ok()?).ok()
+}
+
+pub fn read_contacts() -> Result<Vec<AddressBookContact>, String> {
+    let conn = Connection::session().map_err(|e| format!("No session bus: {e}"))?;
+    let manager = ObjectManagerProxy::builder(&conn)
+        .destination(SOURCES_BUS)
+        .and_then(|b| b.path(SOURCES_PATH))
+        .and_then(|b| b.build())
+        .map_err(|e| format!("Evolution Data Server is not available: {e}"))?;
+    let objects = manager
+        .get_managed_objects()
+        .map_err(|e| format!("Evolution Data Server is not available: {e}"))?;
+
+    let mut contacts = Vec::new();
+    for interfaces in objects.values() {
+        let Some(props) = interfaces
+            .iter()
+            .find(|(name, _)| name.as_str() == SOURCE_IFACE)
+            .map(|(_, props)| props)
+        else {
+            continue;
+        };
+        let (Some(uid), Some(data)) = (
+            props.get("UID").and_then(value_string),
+            props.get("Data").and_then(value_string),
+        ) else {
+            continue;
+        };
+        let Some(book_name) = address_book_source(&data) else {
+            continue;
+        };
+        match read_book(&conn, &uid) {
+            Ok(vcards) => {
+                for vcard in vcards {
+                    contacts.extend(super::vcard::parse_vcards(&vcard, &book_name));
+                }
+            }
+            // One broken source (e.g. an offline CardDAV book) must not hide
+            // the others.
+            Err(e) => tracing::warn!(source = %uid, "Contacts: skipping address book: {}", e),
+        }
+    }
+    Ok(contacts)
+}
+
+fn read_book(conn: &Connection, uid: &str) -> Result<Vec<String>, String> {
+    let factory = Proxy::new(conn, FACTORY_BUS, FACTORY_PATH, FACTORY_IFACE)
+        .map_err(|e| e.to_string())?;
+    let (path, bus): (OwnedObjectPath, String) = factory
+        .call("OpenAddressBook", &(uid,))
+        .map_err(|e| e.to_string())?;
+    let book = Proxy::new(conn, bus, path, BOOK_IFACE).map_err(|e| e.to_string())?;
+    let _: () = book.call("Open", &()).map_err(|e| e.to_string())?;
+    let vcards: Vec<String> = book
+        .call("GetContactList", &("",))
+        .map_err(|e| e.to_string())?;
+    let _: Result<(), _> = book.call("Close", &());
+    Ok(vcards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_enabled_address_book_sources() {
+        let book = "[Data Source]\nDisplayName=Personal\nEnabled=true\n[Address Book]\nBackendName=local\n";
+        assert_eq!(address_book_source(book).as_deref(), Some("Personal"));
+        let disabled = "[Data Source]\nDisplayName=Old\nEnabled=false\n[Address Book]\n";
+        assert_eq!(address_book_source(disabled), None);
+        let calendar = "[Data Source]\nDisplayName=Cal\n[Calendar]\n";
+        assert_eq!(address_book_source(calendar), None);
+    }
+}
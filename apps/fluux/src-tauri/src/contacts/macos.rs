@@ -0,0 +1,29 @@
+//! macOS Contacts, read through AppleScript.
+//!
+//! Asking Contacts.app for vCards keeps us off the CNContactStore bindings
+//! and reuses the system's own consent flow: the first run shows the
+//! Automation and Contacts permission prompts (usage strings in Info.plist).
+
+use super::AddressBookContact;
+use std::process::Command;
+
+const SCRIPT: &str = r#"tell application "Contacts" to get vcard of every person"#;
+
+pub fn read_contacts() -> Result<Vec<AddressBookContact>, String> {
+    let output = Command::new("/usr/bin/osascript")
+        .args(["-e", SCRIPT])
+        .output()
+        .map_err(|e| format!("Failed to run osascript: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // -1743: the user denied Automation access to Contacts.
+        if stderr.contains("-1743") {
+            return Err("Access to Contacts was denied in System Settings".to_string());
+        }
+        return Err(format!("Contacts query failed: {}", stderr.trim()));
+    }
+    Ok(super::vcard::parse_vcards(
+        &String::from_utf8_lossy(&output.stdout),
+        "Contacts",
+    ))
+}
@@ -0,0 +1,158 @@
+//! System address-book import.
+//!
+//! Reads the platform address book (macOS Contacts, Evolution Data Server on
+//! Linux, the Windows contact store) for entries carrying an XMPP address or
+//! an email, and returns them as roster-invitation candidates. Nothing is
+//! read before the user has agreed in a native consent prompt, and the
+//! result never leaves the machine: the frontend decides what to invite.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+mod vcard;
+#[cfg(target_os = "windows")]
+mod windows;
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+/// Consent given earlier in this run; the prompt is shown again after a
+/// restart.
+static CONSENT_GIVEN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressBookContact {
+    pub name: Option<String>,
+    /// Bare JIDs from the entry's IM fields.
+    pub jids: Vec<String>,
+    pub emails: Vec<String>,
+    /// Address book the entry came from (for display only).
+    pub source: String,
+}
+
+/// Collapse entries that several address books (or linked cards) share,
+/// keyed by their first JID, else first email.
+fn dedupe(contacts: Vec<AddressBookContact>) -> Vec<AddressBookContact> {
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+    let mut out: Vec<AddressBookContact> = Vec::new();
+    for contact in contacts {
+        let Some(key) = contact.jids.first().or(contact.emails.first()).cloned() else {
+            continue;
+        };
+        match by_key.get(&key) {
+            Some(&index) => {
+                let existing = &mut out[index];
+                for jid in contact.jids {
+                    if !existing.jids.contains(&jid) {
+                        existing.jids.push(jid);
+                    }
+                }
+                for email in contact.emails {
+                    if !existing.emails.contains(&email) {
+                        existing.emails.push(email);
+                    }
+                }
+                if existing.name.is_none() {
+                    existing.name = contact.name;
+                }
+            }
+            None => {
+                by_key.insert(key, out.len());
+                out.push(contact);
+            }
+        }
+    }
+    out.sort_by(|a, b| {
+        let name = |c: &AddressBookContact| c.name.clone().unwrap_or_default().to_lowercase();
+        name(a).cmp(&name(b))
+    });
+    out
+}
+
+fn ask_consent(app: &tauri::AppHandle) -> bool {
+    if CONSENT_GIVEN.load(Ordering::SeqCst) {
+        return true;
+    }
+    let allowed = app
+        .dialog()
+        .message(
+            "Fluux will read your address book to find contacts with a chat address or an \
+             email. Nothing is uploaded; you choose whom to invite.",
+        )
+        .title("Find contacts from your address book?")
+        .kind(MessageDialogKind::Info)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Allow".to_string(),
+            "Don't Allow".to_string(),
+        ))
+        .blocking_show();
+    CONSENT_GIVEN.store(allowed, Ordering::SeqCst);
+    allowed
+}
+
+fn read_platform_address_book() -> Result<Vec<AddressBookContact>, String> {
+    #[cfg(target_os = "macos")]
+    {
+        macos::read_contacts()
+    }
+    #[cfg(target_os = "linux")]
+    {
+        linux::read_contacts()
+    }
+    #[cfg(target_os = "windows")]
+    {
+        windows::read_contacts()
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        Err("Address book import is not supported on this platform".to_string())
+    }
+}
+
+/// Ask for consent, then return address-book entries that carry a JID or an
+/// email. Declining returns an error so the frontend can say so.
+#[tauri::command]
+pub async fn find_addressbook_contacts(
+    app: tauri::AppHandle,
+) -> Result<Vec<AddressBookContact>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        if !ask_consent(&app) {
+            return Err("Address book access was not allowed".to_string());
+        }
+        let contacts = dedupe(read_platform_address_book()?);
+        tracing::info!(count = contacts.len(), "Contacts: address book candidates found");
+        Ok(contacts)
+    })
+    .await
+    .map_err(|e| format!("Address book task panicked: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(name: &str, jids: &[&str], emails: &[&str]) -> AddressBookContact {
+        AddressBookContact {
+            name: Some(name.to_string()),
+            jids: jids.iter().map(|s| s.to_string()).collect(),
+            emails: emails.iter().map(|s| s.to_string()).collect(),
+            source: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn dedupe_merges_by_first_address_and_sorts_by_name() {
+        let merged = dedupe(vec![
+            contact("Zoe", &["zoe@x"], &[]),
+            contact("Adam", &[], &["adam@mail"]),
+            contact("Zoe Work", &["zoe@x"], &["zoe@work"]),
+        ]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].name.as_deref(), Some("Adam"));
+        assert_eq!(merged[1].emails, vec!["zoe@work"]);
+    }
+}
@@ -0,0 +1,164 @@
+//! Minimal vCard (2.1/3.0/4.0) reader for address-book exports.
+//!
+//! Only what contact import needs is extracted: the display name, email
+//! addresses and XMPP addresses (`IMPP:xmpp:…`, `X-JABBER`, `X-XMPP`).
+
+use super::AddressBookContact;
+
+/// Undo RFC 6350 §3.2 line folding.
+fn unfold(text: &str) -> String {
+    let normalized = text.replace("\r\n", "\n");
+    let mut out = String::with_capacity(normalized.len());
+    for line in normalized.split('\n') {
+        match line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')) {
+            Some(continuation) => out.push_str(continuation),
+            None => {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(line);
+            }
+        }
+    }
+    out
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+/// Split a content line into its upper-cased property name (group prefix
+/// such as `item1.` removed) and raw value.
+fn split_property(line: &str) -> Option<(String, &str)> {
+    let (head, value) = line.split_once(':')?;
+    let name = head.split(';').next()?;
+    let name = name.rsplit('.').next().unwrap_or(name);
+    Some((name.to_ascii_uppercase(), value))
+}
+
+/// A bare JID from an IM property value, when it is an XMPP address.
+fn xmpp_address(name: &str, value: &str) -> Option<String> {
+    let value = value.trim();
+    let address = match name {
+        "IMPP" => value
+            .strip_prefix("xmpp:")
+            .or_else(|| value.strip_prefix("XMPP:"))?,
+        "X-JABBER" | "X-XMPP" => value.strip_prefix("xmpp:").unwrap_or(value),
+        _ => return None,
+    };
+    // Drop any query part (`?message`) of an XMPP URI.
+    let address = address.split('?').next().unwrap_or(address).trim();
+    (address.contains('@') && !address.contains(char::is_whitespace))
+        .then(|| address.to_lowercase())
+}
+
+/// Parse every vCard in `text`. Entries without a JID or email are skipped.
+pub fn parse_vcards(text: &str, source: &str) -> Vec<AddressBookContact> {
+    let unfolded = unfold(text);
+    let mut contacts = Vec::new();
+    let mut current: Option<AddressBookContact> = None;
+    let mut structured_name: Option<String> = None;
+
+    for line in unfolded.lines() {
+        // `osascript` joins a list of vCards with ", ".
+        let line = line.trim_start_matches([',', ' ']).trim_end();
+        let Some((name, value)) = split_property(line) else {
+            continue;
+        };
+        match name.as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VCARD") => {
+                current = Some(AddressBookContact {
+                    source: source.to_string(),
+                    ..Default::default()
+                });
+                structured_name = None;
+            }
+            "END" if value.eq_ignore_ascii_case("VCARD") => {
+                if let Some(mut contact) = current.take() {
+                    if contact.name.is_none() {
+                        contact.name = structured_name.take();
+                    }
+                    if !contact.jids.is_empty() || !contact.emails.is_empty() {
+                        contacts.push(contact);
+                    }
+                }
+            }
+            _ => {
+                let Some(contact) = current.as_mut() else {
+                    continue;
+                };
+                match name.as_str() {
+                    "FN" => {
+                        let full_name = unescape(value).trim().to_string();
+                        if !full_name.is_empty() {
+                            contact.name = Some(full_name);
+                        }
+                    }
+                    // N is `family;given;additional;prefix;suffix`.
+                    "N" => {
+                        let parts: Vec<String> = value.split(';').map(unescape).collect();
+                        let given = parts.get(1).map(String::as_str).unwrap_or("");
+                        let family = parts.first().map(String::as_str).unwrap_or("");
+                        let joined = format!("{given} {family}").trim().to_string();
+                        if !joined.is_empty() {
+                            structured_name = Some(joined);
+                        }
+                    }
+                    "EMAIL" => {
+                        let email = unescape(value).trim().to_lowercase();
+                        if email.contains('@') && !contact.emails.contains(&email) {
+                            contact.emails.push(email);
+                        }
+                    }
+                    _ => {
+                        if let Some(jid) = xmpp_address(&name, &unescape(value)) {
+                            if !contact.jids.contains(&jid) {
+                                contact.jids.push(jid);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    contacts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_names_emails_and_xmpp_addresses() {
+        let text = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Capulet;Juliet;;;\r\nitem1.EMAIL;type=INTERNET:Juliet@Example.com\r\n\
+                    IMPP;X-SERVICE-TYPE=Jabber:xmpp:juliet@capulet.lit\r\nX-JABBER;type=HOME:juliet@capulet.lit\r\n\
+                    END:VCARD\r\n, BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Romeo Mon\r\n tague\r\nIMPP:xmpp:romeo@montague.lit?message\r\nEND:VCARD\r\n\
+                    BEGIN:VCARD\r\nFN:Nobody\r\nTEL:+1 555\r\nEND:VCARD\r\n";
+        let contacts = parse_vcards(text, "test");
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].name.as_deref(), Some("Juliet Capulet"));
+        assert_eq!(contacts[0].emails, vec!["juliet@example.com"]);
+        assert_eq!(contacts[0].jids, vec!["juliet@capulet.lit"]);
+        assert_eq!(contacts[1].name.as_deref(), Some("Romeo Montague"));
+        assert_eq!(contacts[1].jids, vec!["romeo@montague.lit"]);
+    }
+
+    #[test]
+    fn ignores_non_xmpp_impp() {
+        assert_eq!(xmpp_address("IMPP", "sip:alice@example.com"), None);
+        assert_eq!(xmpp_address("X-JABBER", "not a jid"), None);
+    }
+}
@@ -0,0 +1,50 @@
+//! Windows contact store (the People app's contacts) via WinRT.
+//!
+//! Unpackaged desktop apps are usually refused access to the contact store
+//! by the "Contacts" privacy setting; that surfaces as an access-denied
+//! error the frontend can explain, rather than an empty list.
+
+use super::AddressBookContact;
+use windows::ApplicationModel::Contacts::{ContactManager, ContactStoreAccessType};
+
+pub fn read_contacts() -> Result<Vec<AddressBookContact>, String> {
+    let store = ContactManager::RequestStoreAsyncWithAccessType(
+        ContactStoreAccessType::AllContactsReadOnly,
+    )
+    .and_then(|op| op.get())
+    .map_err(|e| format!("Access to Windows contacts was denied: {e}"))?;
+    let found = store
+        .FindContactsAsync()
+        .and_then(|op| op.get())
+        .map_err(|e| format!("Failed to read Windows contacts: {e}"))?;
+
+    let mut contacts = Vec::new();
+    for contact in found {
+        let emails: Vec<String> = contact
+            .Emails()
+            .map(|list| {
+                list.into_iter()
+                    .filter_map(|email| email.Address().ok())
+                    .map(|address| address.to_string().trim().to_lowercase())
+                    .filter(|address| address.contains('@'))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if emails.is_empty() {
+            continue;
+        }
+        let name = contact
+            .DisplayName()
+            .ok()
+            .map(|n| n.to_string())
+            .filter(|n| !n.is_empty());
+        contacts.push(AddressBookContact {
+            name,
+            // The contact store has no XMPP field; emails are the candidates.
+            jids: Vec::new(),
+            emails,
+            source: "Windows contacts".to_string(),
+        });
+    }
+    Ok(contacts)
+}
@@ -0,0 +1,213 @@
+//! Local log of every read of stored credentials.
+//!
+//! Users who care want to check that Fluux only touches their password when
+//! it connects (the saved login, at startup or from the login screen) and
+//! after something they did (starting the MCP server, opening the admin
+//! panel), never in the background. Keychain reads of credentials go
+//! through [`read`], which records an [`Access`]: when, which entry, what
+//! for, where in the code, and the outcome. Records are appended to
+//! `credential-access.jsonl` in the app data directory, which keeps the last
+//! [`MAX_ENTRIES`]; no secret is written and nothing leaves the machine.
+//! [`get_credential_access_log`] returns them, oldest first.
+
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Records kept. The file is compacted to these once it holds twice as many.
+const MAX_ENTRIES: usize = 1000;
+const LOG_FILE: &str = "credential-access.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Found,
+    Missing,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Access {
+    /// Unix milliseconds.
+    pub ts: u64,
+    /// Keychain account read: a JID, `mcp-token`, `push:<jid>`...
+    pub account: String,
+    /// What the credential was read for.
+    pub purpose: String,
+    /// Where in Fluux it was read, as `file:line`.
+    pub caller: String,
+    pub outcome: Outcome,
+}
+
+static LOG: Mutex<VecDeque<Access>> = Mutex::new(VecDeque::new());
+static LOG_PATH: OnceLock<PathBuf> = OnceLock::new();
+/// Lines in the file, compacted ones included.
+static FILE_LINES: AtomicUsize = AtomicUsize::new(0);
+
+/// Load the log from `dir`. Called from the Tauri `setup` hook, before
+/// anything reads the keychain.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join(LOG_FILE)) else {
+        return;
+    };
+    let (entries, lines) = read_file(&path);
+    FILE_LINES.store(lines, Ordering::Relaxed);
+    let mut log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+    // Reads before `load` go first.
+    let earlier = std::mem::replace(&mut *log, entries);
+    for access in earlier {
+        push(&mut log, access);
+    }
+    let _ = LOG_PATH.set(path);
+}
+
+/// Read the password of `entry` (keychain account `account`) and log it.
+#[track_caller]
+pub fn read(entry: &Entry, account: &str, purpose: &str) -> keyring::Result<String> {
+    let result = entry.get_password();
+    record(account, purpose, Location::caller(), outcome(&result));
+    result
+}
+
+/// Like [`read`], for the raw secret.
+#[track_caller]
+pub fn read_secret(entry: &Entry, account: &str, purpose: &str) -> keyring::Result<Vec<u8>> {
+    let result = entry.get_secret();
+    record(account, purpose, Location::caller(), outcome(&result));
+    result
+}
+
+fn outcome<T>(result: &keyring::Result<T>) -> Outcome {
+    match result {
+        Ok(_) => Outcome::Found,
+        Err(keyring::Error::NoEntry) => Outcome::Missing,
+        Err(_) => Outcome::Failed,
+    }
+}
+
+fn record(account: &str, purpose: &str, caller: &Location, outcome: Outcome) {
+    let access = Access {
+        ts: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64),
+        account: account.to_string(),
+        purpose: purpose.to_string(),
+        caller: format!("{}:{}", caller.file(), caller.line()),
+        outcome,
+    };
+    info!(account, purpose, caller = %access.caller, ?outcome, "Credential read");
+    let mut log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+    push(&mut log, access.clone());
+    let Some(path) = LOG_PATH.get() else {
+        return;
+    };
+    let result = if FILE_LINES.fetch_add(1, Ordering::Relaxed) + 1 > 2 * MAX_ENTRIES {
+        FILE_LINES.store(log.len(), Ordering::Relaxed);
+        rewrite(path, &log)
+    } else {
+        append(path, &access)
+    };
+    if let Err(e) = result {
+        warn!(error = %e, "Credential access log: failed to write");
+    }
+}
+
+fn push(log: &mut VecDeque<Access>, access: Access) {
+    log.push_back(access);
+    while log.len() > MAX_ENTRIES {
+        log.pop_front();
+    }
+}
+
+/// The last [`MAX_ENTRIES`] records of `path`, and how many lines it has.
+fn read_file(path: &Path) -> (VecDeque<Access>, usize) {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return (VecDeque::new(), 0);
+    };
+    let mut entries = VecDeque::new();
+    let mut lines = 0;
+    for line in text.lines() {
+        lines += 1;
+        if let Ok(access) = serde_json::from_str(line) {
+            push(&mut entries, access);
+        }
+    }
+    (entries, lines)
+}
+
+fn append(path: &Path, access: &Access) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut line = serde_json::to_vec(access)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+fn rewrite(path: &Path, log: &VecDeque<Access>) -> std::io::Result<()> {
+    let mut bytes = Vec::new();
+    for access in log {
+        serde_json::to_writer(&mut bytes, access)?;
+        bytes.push(b'\n');
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Reads of stored credentials, oldest first.
+#[tauri::command]
+pub fn get_credential_access_log() -> Vec<Access> {
+    LOG.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_survive_a_reload_and_are_capped() {
+        let path = std::env::temp_dir().join(format!("{LOG_FILE}-{}", uuid::Uuid::new_v4()));
+        let access = |account: &str| Access {
+            ts: 1,
+            account: account.to_string(),
+            purpose: "saved login".to_string(),
+            caller: "src/main.rs:1".to_string(),
+            outcome: Outcome::Found,
+        };
+        for n in 0..MAX_ENTRIES + 2 {
+            append(&path, &access(&format!("user{n}@example.org"))).unwrap();
+        }
+        let (entries, lines) = read_file(&path);
+        assert_eq!(lines, MAX_ENTRIES + 2);
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0], access("user2@example.org"));
+
+        rewrite(&path, &entries).unwrap();
+        let (compacted, lines) = read_file(&path);
+        assert_eq!(lines, MAX_ENTRIES);
+        assert_eq!(compacted, entries);
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(
+            outcome::<()>(&Err(keyring::Error::NoEntry)),
+            Outcome::Missing
+        );
+    }
+}
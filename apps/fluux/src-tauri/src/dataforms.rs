@@ -0,0 +1,214 @@
+//! XEP-0004 data forms, as exchanged with the frontend.
+//!
+//! Native protocol features that carry forms (ad-hoc commands, room
+//! configuration, …) convert between the wire `<x xmlns='jabber:x:data'/>`
+//! and this serde model so the frontend renders one shape regardless of
+//! where a form came from.
+
+use crate::xmpp_proxy::stanza::Element;
+use serde::{Deserialize, Serialize};
+
+pub const DATA_FORMS_NS: &str = "jabber:x:data";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormOption {
+    pub label: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormField {
+    pub var: Option<String>,
+    /// Field type (`text-single`, `list-multi`, …). `None` means
+    /// `text-single` per the spec.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub label: Option<String>,
+    pub desc: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub values: Vec<String>,
+    #[serde(default)]
+    pub options: Vec<FormOption>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataForm {
+    /// `form`, `submit`, `cancel` or `result`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub instructions: Vec<String>,
+    #[serde(default)]
+    pub fields: Vec<FormField>,
+    /// Column definitions of a multi-item `result` form.
+    #[serde(default)]
+    pub reported: Vec<FormField>,
+    /// Rows of a multi-item `result` form.
+    #[serde(default)]
+    pub items: Vec<Vec<FormField>>,
+}
+
+fn text_child(parent: &Element, name: &str) -> Option<String> {
+    parent.child(name, None).map(|e| e.text())
+}
+
+fn parse_field(field: &Element) -> FormField {
+    FormField {
+        var: field.attr("var").map(str::to_string),
+        kind: field.attr("type").map(str::to_string),
+        label: field.attr("label").map(str::to_string),
+        desc: text_child(field, "desc"),
+        required: field.child("required", None).is_some(),
+        values: field
+            .elements()
+            .filter(|e| e.local_name() == "value")
+            .map(|e| e.text())
+            .collect(),
+        options: field
+            .elements()
+            .filter(|e| e.local_name() == "option")
+            .map(|option| FormOption {
+                label: option.attr("label").map(str::to_string),
+                value: text_child(option, "value").unwrap_or_default(),
+            })
+            .collect(),
+    }
+}
+
+fn fields_of(parent: &Element) -> Vec<FormField> {
+    parent
+        .elements()
+        .filter(|e| e.local_name() == "field")
+        .map(parse_field)
+        .collect()
+}
+
+impl DataForm {
+    /// Parse an `<x xmlns='jabber:x:data'/>` element.
+    pub fn from_element(x: &Element) -> Option<Self> {
+        if x.local_name() != "x" || x.ns() != Some(DATA_FORMS_NS) {
+            return None;
+        }
+        Some(DataForm {
+            kind: x.attr("type").unwrap_or("form").to_string(),
+            title: text_child(x, "title"),
+            instructions: x
+                .elements()
+                .filter(|e| e.local_name() == "instructions")
+                .map(|e| e.text())
+                .collect(),
+            fields: fields_of(x),
+            reported: x.child("reported", None).map(fields_of).unwrap_or_default(),
+            items: x
+                .elements()
+                .filter(|e| e.local_name() == "item")
+                .map(fields_of)
+                .collect(),
+        })
+    }
+
+    /// First data form among an element's children.
+    pub fn find_in(parent: &Element) -> Option<Self> {
+        parent
+            .elements()
+            .find(|e| e.local_name() == "x" && e.ns() == Some(DATA_FORMS_NS))
+            .and_then(Self::from_element)
+    }
+
+    /// Serialize for submission. Only what a submitting entity may send is
+    /// written: field vars, types and values (labels, options and
+    /// descriptions belong to the form, not the answer).
+    pub fn to_submit_element(&self) -> Element {
+        let mut x = Element::new("x")
+            .with_attr("xmlns", DATA_FORMS_NS)
+            .with_attr("type", &self.kind);
+        for field in &self.fields {
+            let Some(var) = &field.var else {
+                continue;
+            };
+            if field.kind.as_deref() == Some("fixed") {
+                continue;
+            }
+            let mut element = Element::new("field").with_attr("var", var);
+            if let Some(kind) = &field.kind {
+                element.set_attr("type", kind);
+            }
+            for value in &field.values {
+                element = element.with_child(Element::new("value").with_text(value));
+            }
+            x = x.with_child(element);
+        }
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_form_fields_options_and_required() {
+        let x = Element::parse(
+            "<x xmlns='jabber:x:data' type='form'><title>Add User</title><instructions>Fill it</instructions>\
+             <field var='FORM_TYPE' type='hidden'><value>http://jabber.org/protocol/admin</value></field>\
+             <field var='accountjid' type='jid-single' label='Jabber ID'><required/></field>\
+             <field var='level' type='list-single'><option label='Admin'><value>admin</value></option><value>user</value></field>\
+             </x>",
+        )
+        .unwrap();
+        let form = DataForm::from_element(&x).unwrap();
+        assert_eq!(form.kind, "form");
+        assert_eq!(form.title.as_deref(), Some("Add User"));
+        assert_eq!(form.fields[0].values, vec!["http://jabber.org/protocol/admin"]);
+        assert!(form.fields[1].required);
+        assert_eq!(form.fields[2].options[0].value, "admin");
+        assert_eq!(form.fields[2].values, vec!["user"]);
+    }
+
+    #[test]
+    fn parses_multi_item_results() {
+        let x = Element::parse(
+            "<x xmlns='jabber:x:data' type='result'><reported><field var='jid'/></reported>\
+             <item><field var='jid'><value>a@x</value></field></item>\
+             <item><field var='jid'><value>b@x</value></field></item></x>",
+        )
+        .unwrap();
+        let form = DataForm::from_element(&x).unwrap();
+        assert_eq!(form.reported.len(), 1);
+        assert_eq!(form.items.len(), 2);
+        assert_eq!(form.items[1][0].values, vec!["b@x"]);
+    }
+
+    #[test]
+    fn submission_keeps_only_answers() {
+        let form = DataForm {
+            kind: "submit".into(),
+            fields: vec![
+                FormField {
+                    var: Some("accountjid".into()),
+                    kind: Some("jid-single".into()),
+                    label: Some("Jabber ID".into()),
+                    values: vec!["new@example.com".into()],
+                    ..Default::default()
+                },
+                FormField {
+                    kind: Some("fixed".into()),
+                    values: vec!["Section".into()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let xml = form.to_submit_element().to_xml();
+        assert_eq!(
+            xml,
+            "<x xmlns='jabber:x:data' type='submit'><field var='accountjid' type='jid-single'><value>new@example.com</value></field></x>"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Session-bus service for desktop integration on Linux.
+//!
+//! Owns `io.fluux.Messenger` and serves the `io.fluux.Messenger` interface at
+//! `/io/fluux/Messenger`, so shell extensions, panel widgets and scripts can
+//! drive the running app:
+//!
+//! - `ShowWindow()`: raise the main window.
+//! - `SetPresence(show, status)`: `show` is `""` (available), `away`,
+//!   `chat`, `dnd` or `xa`.
+//! - `SendMessage(to, body) -> id`: send a chat message.
+//! - Properties `UnreadCount`, `MentionCount` (with change signals, fed from
+//!   [`crate::unread`]), `Connected` and `Jid`.
+//!
+//! Notifications keep going through `org.freedesktop.Notifications` (see
+//! `notifications/linux.rs`); this service is the other direction.
+//!
+//! ```sh
+//! busctl --user call io.fluux.Messenger /io/fluux/Messenger \
+//!     io.fluux.Messenger SendMessage ss alice@example.org "hello"
+//! ```
+
+use crate::automation;
+use crate::xmpp_proxy::session;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, OnceLock};
+use tauri::Manager;
+use tracing::{info, warn};
+
+const BUS_NAME: &str = "io.fluux.Messenger";
+const OBJECT_PATH: &str = "/io/fluux/Messenger";
+
+/// Totals handed to the service thread, which emits the change signals off
+/// the bridge task that counted them.
+static UNREAD_UPDATES: OnceLock<Mutex<mpsc::Sender<(u32, u32)>>> = OnceLock::new();
+
+struct Messenger {
+    app: tauri::AppHandle,
+    /// Unread and mention totals.
+    totals: Arc<Mutex<(u32, u32)>>,
+}
+
+#[zbus::interface(name = "io.fluux.Messenger")]
+impl Messenger {
+    fn show_window(&self) {
+        if let Some(window) = self.app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.show();
+            crate::ensure_window_visible(&window);
+            let _ = window.set_focus();
+        }
+    }
+
+    fn set_presence(&self, show: &str, status: &str) -> zbus::fdo::Result<()> {
+        let stanza = automation::presence(Some(show), Some(status))
+            .map_err(zbus::fdo::Error::InvalidArgs)?;
+        automation::send(stanza).map_err(zbus::fdo::Error::Failed)
+    }
+
+    fn send_message(&self, to: &str, body: &str) -> zbus::fdo::Result<String> {
+        let (id, stanza) =
+            automation::message(to, "chat", body).map_err(zbus::fdo::Error::InvalidArgs)?;
+        automation::send(stanza).map_err(zbus::fdo::Error::Failed)?;
+        info!(to, "dbus: message sent");
+        Ok(id)
+    }
+
+    #[zbus(property)]
+    fn unread_count(&self) -> u32 {
+        self.totals.lock().unwrap_or_else(|e| e.into_inner()).0
+    }
+
+    #[zbus(property)]
+    fn mention_count(&self) -> u32 {
+        self.totals.lock().unwrap_or_else(|e| e.into_inner()).1
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn connected(&self) -> bool {
+        session::is_ready()
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn jid(&self) -> String {
+        session::own_jid().unwrap_or_default()
+    }
+}
+
+/// Record new unread totals. Called by [`crate::unread`] whenever they move.
+pub fn unread_changed(total_unread: u32, total_mentions: u32) {
+    if let Some(updates) = UNREAD_UPDATES.get() {
+        let _ = updates
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .send((total_unread, total_mentions));
+    }
+}
+
+/// Claim the bus name and serve the interface for the life of the app.
+/// Called from the Tauri `setup` hook; without a session bus (or with the
+/// name taken) the app just runs without it.
+pub fn start(app: tauri::AppHandle) {
+    let (tx, rx) = mpsc::channel();
+    let _ = UNREAD_UPDATES.set(Mutex::new(tx));
+    std::thread::spawn(move || {
+        let totals = Arc::new(Mutex::new((0, 0)));
+        let messenger = Messenger {
+            app,
+            totals: totals.clone(),
+        };
+        let connection = match zbus::blocking::connection::Builder::session()
+            .and_then(|b| b.name(BUS_NAME))
+            .and_then(|b| b.serve_at(OBJECT_PATH, messenger))
+            .and_then(|b| b.build())
+        {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!(error = %e, "dbus: service not available");
+                return;
+            }
+        };
+        info!(name = BUS_NAME, "dbus: service registered");
+        let Ok(iface) = connection
+            .object_server()
+            .interface::<_, Messenger>(OBJECT_PATH)
+        else {
+            return;
+        };
+        // The connection lives as long as this loop, i.e. the process.
+        while let Ok((unread, mentions)) = rx.recv() {
+            let previous = std::mem::replace(
+                &mut *totals.lock().unwrap_or_else(|e| e.into_inner()),
+                (unread, mentions),
+            );
+            let messenger = iface.get();
+            let emitter = iface.signal_emitter();
+            if previous.0 != unread {
+                let _ = zbus::block_on(messenger.unread_count_changed(emitter));
+            }
+            if previous.1 != mentions {
+                let _ = zbus::block_on(messenger.mention_count_changed(emitter));
+            }
+        }
+    });
+}
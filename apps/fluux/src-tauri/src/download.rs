@@ -16,6 +16,10 @@
 //! - `x-decrypt-key` / `x-decrypt-iv`: base64 AES-256-GCM key (32 bytes) and
 //!   IV (12 bytes); when present the response body is decrypted in Rust
 //!   before being returned (XEP-0454 aesgcm attachments)
+//! - `x-cache-as` (optional): file name to store the (decrypted) body under
+//!   in the media cache instead of returning it; the envelope then carries
+//!   a `mediaUrl` served by `media_server.rs`, and no file bytes
+//!   (the cached file is marked as downloaded, see `quarantine.rs`)
 //!
 //! Progress is emitted as `fluux://download-progress` events
 //! (`{id, received, total}`), at most once per integer percent while the
@@ -47,6 +51,8 @@ pub struct DownloadArgs {
     pub download_id: String,
     /// AES-256-GCM (key, IV) when the payload must be decrypted in Rust.
     pub decrypt: Option<([u8; 32], [u8; 12])>,
+    /// Media cache file name when the body goes to disk instead of IPC.
+    pub cache_as: Option<String>,
 }
 
 #[derive(Serialize, Clone)]
@@ -60,6 +66,8 @@ struct ProgressPayload {
 struct EnvelopeMeta<'a> {
     #[serde(rename = "contentType")]
     content_type: Option<&'a str>,
+    #[serde(rename = "mediaUrl", skip_serializing_if = "Option::is_none")]
+    media_url: Option<&'a str>,
 }
 
 fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, String> {
@@ -96,10 +104,19 @@ pub fn parse_download_args(headers: &HeaderMap) -> Result<DownloadArgs, String>
         }
     };
 
+    let cache_as = if headers.contains_key("x-cache-as") {
+        let name = header_str(headers, "x-cache-as")?;
+        crate::media_server::validate_name(name).map_err(|e| format!("download_file: {e}"))?;
+        Some(name.to_string())
+    } else {
+        None
+    };
+
     Ok(DownloadArgs {
         get_url: header_str(headers, "x-get-url")?.to_string(),
         download_id: header_str(headers, "x-download-id")?.to_string(),
         decrypt,
+        cache_as,
     })
 }
 
@@ -120,8 +137,22 @@ pub fn decrypt_for_download(
 /// Wrap the response body in the raw-IPC envelope:
 /// `[4-byte LE meta length][meta JSON][body bytes]`.
 pub fn build_response_envelope(content_type: Option<&str>, body: &[u8]) -> Vec<u8> {
-    let meta = serde_json::to_vec(&EnvelopeMeta { content_type })
-        .expect("EnvelopeMeta serialization cannot fail");
+    envelope(EnvelopeMeta { content_type, media_url: None }, body)
+}
+
+/// Envelope for a body written to the media cache: metadata only.
+pub fn build_cached_envelope(content_type: Option<&str>, media_url: &str) -> Vec<u8> {
+    envelope(
+        EnvelopeMeta {
+            content_type,
+            media_url: Some(media_url),
+        },
+        &[],
+    )
+}
+
+fn envelope(meta: EnvelopeMeta<'_>, body: &[u8]) -> Vec<u8> {
+    let meta = serde_json::to_vec(&meta).expect("EnvelopeMeta serialization cannot fail");
     let mut out = Vec::with_capacity(4 + meta.len() + body.len());
     out.extend_from_slice(&(meta.len() as u32).to_le_bytes());
     out.extend_from_slice(&meta);
@@ -205,6 +236,7 @@ pub async fn download_file(
     request: tauri::ipc::Request<'_>,
 ) -> Result<tauri::ipc::Response, String> {
     let args = parse_download_args(request.headers())?;
+    let app_for_cache = app.clone();
 
     tauri::async_runtime::spawn_blocking(move || {
         let (body, content_type) = get_blocking(app, &args)?;
@@ -212,6 +244,25 @@ pub async fn download_file(
             Some((key, iv)) => decrypt_for_download(&body, key, iv)?,
             None => body,
         };
+        if let Some(name) = &args.cache_as {
+            let dir = crate::cache::dir(&app_for_cache, "attachments")?;
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| format!("download_file: cannot create media cache: {e}"))?;
+            let tmp = dir.join(format!("{name}.part"));
+            std::fs::write(&tmp, &payload)
+                .and_then(|_| std::fs::rename(&tmp, dir.join(name)))
+                .map_err(|e| format!("download_file: cannot write media cache: {e}"))?;
+            crate::quarantine::mark_downloaded(&dir.join(name), &args.get_url)?;
+            crate::cache::enforce(&app_for_cache, "attachments");
+            if args.decrypt.is_some() {
+                // Decrypted media doesn't outlive the session.
+                crate::temp_files::track(&dir.join(name), true);
+            }
+            return Ok(tauri::ipc::Response::new(build_cached_envelope(
+                content_type.as_deref(),
+                &crate::media_server::media_url("attachments", name),
+            )));
+        }
         Ok(tauri::ipc::Response::new(build_response_envelope(
             content_type.as_deref(),
             &payload,
@@ -251,6 +302,7 @@ mod tests {
                 get_url: "https://dl.example.com/file/1".into(),
                 download_id: "dl-123".into(),
                 decrypt: None,
+                cache_as: None,
             }
         );
     }
@@ -348,6 +400,21 @@ mod tests {
         let meta: serde_json::Value =
             serde_json::from_slice(&envelope[4..4 + meta_len]).unwrap();
         assert!(meta["contentType"].is_null());
+        assert!(meta.get("mediaUrl").is_none());
+        assert_eq!(envelope.len(), 4 + meta_len);
+    }
+
+    #[test]
+    fn cache_as_must_be_a_plain_file_name() {
+        let base = [("x-get-url", "https://dl.example.com/f"), ("x-download-id", "d")];
+        let ok = parse_download_args(&headers(&[base[0], base[1], ("x-cache-as", "3f2a.mp4")])).unwrap();
+        assert_eq!(ok.cache_as.as_deref(), Some("3f2a.mp4"));
+        assert!(parse_download_args(&headers(&[base[0], base[1], ("x-cache-as", "../x")])).is_err());
+
+        let envelope = build_cached_envelope(Some("video/mp4"), "fluux-media://localhost/attachments/3f2a.mp4");
+        let meta_len = u32::from_le_bytes(envelope[0..4].try_into().unwrap()) as usize;
+        let meta: serde_json::Value = serde_json::from_slice(&envelope[4..4 + meta_len]).unwrap();
+        assert_eq!(meta["mediaUrl"], "fluux-media://localhost/attachments/3f2a.mp4");
         assert_eq!(envelope.len(), 4 + meta_len);
     }
 }
@@ -0,0 +1,330 @@
+//! Emoji search for the picker and `:shortcode` autocomplete.
+//!
+//! The emoji table (names, GitHub/Slack-style shortcodes, skin-tone variants)
+//! is compiled into the binary by the `emojis` crate, so the WebView no
+//! longer parses a multi-hundred-KB JSON index before first render. Picks are
+//! counted and persisted next to the other app data; frequently used emoji
+//! rank first, both for an empty query (the "frequent" row) and among
+//! equally good matches.
+
+use crate::json_file::JsonFile;
+use emojis::{Emoji, SkinTone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tracing::warn;
+
+const DEFAULT_LIMIT: usize = 24;
+/// Distinct emoji whose usage is remembered; the least used are forgotten.
+const MAX_TRACKED: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmojiMatch {
+    /// The emoji, in the requested skin tone when it has variants.
+    pub emoji: String,
+    pub name: String,
+    pub shortcode: Option<String>,
+    pub has_skin_tones: bool,
+}
+
+struct IndexEntry {
+    emoji: &'static Emoji,
+    shortcodes: Vec<&'static str>,
+    /// Lower-cased words of the CLDR name ("grinning face with big eyes").
+    keywords: Vec<String>,
+}
+
+/// Search table in the crate's order, which follows the Unicode emoji
+/// ordering and is the final tie-breaker.
+fn index() -> &'static [IndexEntry] {
+    static INDEX: OnceLock<Vec<IndexEntry>> = OnceLock::new();
+    INDEX.get_or_init(|| {
+        emojis::iter()
+            .map(|emoji| IndexEntry {
+                emoji,
+                shortcodes: emoji.shortcodes().collect(),
+                keywords: emoji
+                    .name()
+                    .to_lowercase()
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter(|w| !w.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            })
+            .collect()
+    })
+}
+
+fn parse_skin_tone(tone: &str) -> Option<SkinTone> {
+    match tone {
+        "light" => Some(SkinTone::Light),
+        "medium-light" => Some(SkinTone::MediumLight),
+        "medium" => Some(SkinTone::Medium),
+        "medium-dark" => Some(SkinTone::MediumDark),
+        "dark" => Some(SkinTone::Dark),
+        _ => None,
+    }
+}
+
+/// Usage counts are keyed by the default-tone emoji so switching skin tone
+/// doesn't split an emoji's history.
+fn base_emoji(emoji: &str) -> Option<&'static Emoji> {
+    let emoji = emojis::get(emoji)?;
+    Some(emoji.with_skin_tone(SkinTone::Default).unwrap_or(emoji))
+}
+
+/// `needle` appears in `haystack` in order, not necessarily contiguously.
+/// Returns how many characters were skipped, lower being a tighter match.
+fn subsequence_gaps(needle: &str, haystack: &str) -> Option<usize> {
+    let mut chars = haystack.chars();
+    let mut gaps = 0;
+    for wanted in needle.chars() {
+        loop {
+            match chars.next() {
+                Some(c) if c == wanted => break,
+                Some(_) => gaps += 1,
+                None => return None,
+            }
+        }
+    }
+    Some(gaps)
+}
+
+/// How well one query token matches an entry; `None` when it doesn't.
+fn token_score(token: &str, entry: &IndexEntry) -> Option<u32> {
+    let shortcode_score = entry
+        .shortcodes
+        .iter()
+        .filter_map(|shortcode| {
+            if *shortcode == token {
+                Some(1000)
+            } else if shortcode.starts_with(token) {
+                // Shorter completions first: "smi" → "smile" before "smiley_cat".
+                Some(800u32.saturating_sub((shortcode.len() - token.len()) as u32))
+            } else if shortcode.split('_').any(|part| part.starts_with(token)) {
+                Some(600)
+            } else {
+                None
+            }
+        })
+        .max();
+    let keyword_score = if entry.keywords.iter().any(|w| w == token) {
+        Some(550)
+    } else if entry.keywords.iter().any(|w| w.starts_with(token)) {
+        Some(500)
+    } else {
+        None
+    };
+    let best = shortcode_score.max(keyword_score);
+    if best.is_some() || token.len() < 3 {
+        return best;
+    }
+    entry
+        .shortcodes
+        .iter()
+        .filter_map(|s| subsequence_gaps(token, s))
+        .min()
+        .map(|gaps| 200u32.saturating_sub(gaps as u32 * 20).max(50))
+}
+
+/// Score of an entry for a whole query: every token must match, and the
+/// entry is only as good as its weakest token.
+fn query_score(tokens: &[&str], entry: &IndexEntry) -> Option<u32> {
+    tokens
+        .iter()
+        .map(|token| token_score(token, entry))
+        .try_fold(u32::MAX, |acc, score| score.map(|s| acc.min(s)))
+}
+
+/// Ranking bonus for past use: logarithmic, so a handful of uses lifts an
+/// emoji within its match tier without letting a favourite outrank an
+/// exact shortcode hit.
+fn usage_boost(count: u32) -> u32 {
+    ((count as f64).ln_1p() * 40.0).min(180.0) as u32
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PersistedUsage {
+    counts: HashMap<String, u32>,
+}
+
+/// Emoji pick counts, held as managed state `Arc<EmojiUsage>`.
+pub struct EmojiUsage {
+    counts: Mutex<HashMap<String, u32>>,
+    /// Where counts are persisted; `None` keeps them in memory.
+    file: Option<JsonFile>,
+}
+
+impl EmojiUsage {
+    /// Load persisted counts. A missing or unreadable file starts empty.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let persisted: PersistedUsage = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(usage) => Some(usage),
+                Err(e) => {
+                    warn!(error = %e, "emoji: discarding unreadable usage file");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        EmojiUsage {
+            counts: Mutex::new(persisted.counts),
+            file: path.map(JsonFile::new),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, u32>> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record(&self, emoji: &str) -> Result<(), String> {
+        let base = base_emoji(emoji).ok_or_else(|| format!("Unknown emoji: {emoji}"))?;
+        let mut counts = self.lock();
+        let key = base.as_str();
+        let count = counts.entry(key.to_string()).or_insert(0);
+        *count = count.saturating_add(1);
+        if counts.len() > MAX_TRACKED {
+            // Never evict the pick being recorded, even on its first use.
+            let mut by_use: Vec<_> = counts
+                .iter()
+                .filter(|(k, _)| k.as_str() != key)
+                .map(|(k, v)| (*v, k.clone()))
+                .collect();
+            by_use.sort();
+            for (_, emoji) in by_use.into_iter().take(counts.len() - MAX_TRACKED) {
+                counts.remove(&emoji);
+            }
+        }
+        self.persist(&counts);
+        Ok(())
+    }
+
+    /// Write the counts in the background; picking an emoji must not wait
+    /// on disk. Called with the counts lock held; see [`crate::json_file`].
+    fn persist(&self, counts: &HashMap<String, u32>) {
+        if let Some(file) = &self.file {
+            file.save(PersistedUsage {
+                counts: counts.clone(),
+            });
+        }
+    }
+
+    fn search(&self, query: &str, skin_tone: Option<SkinTone>, limit: usize) -> Vec<EmojiMatch> {
+        let counts = self.lock();
+        let count_of = |emoji: &Emoji| counts.get(emoji.as_str()).copied().unwrap_or(0);
+        let query = query.trim().trim_matches(':').to_lowercase();
+        let tokens: Vec<&str> = query
+            .split(|c: char| c.is_whitespace() || c == '_')
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        let mut ranked: Vec<(u32, usize, &IndexEntry)> = if tokens.is_empty() {
+            // No query: the most used emoji, most used first.
+            index()
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| count_of(entry.emoji) > 0)
+                .map(|(pos, entry)| (count_of(entry.emoji), pos, entry))
+                .collect()
+        } else {
+            index()
+                .iter()
+                .enumerate()
+                .filter_map(|(pos, entry)| {
+                    let score = query_score(&tokens, entry)?;
+                    Some((score + usage_boost(count_of(entry.emoji)), pos, entry))
+                })
+                .collect()
+        };
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(_, _, entry)| {
+                let toned = skin_tone
+                    .and_then(|tone| entry.emoji.with_skin_tone(tone))
+                    .unwrap_or(entry.emoji);
+                EmojiMatch {
+                    emoji: toned.as_str().to_string(),
+                    name: entry.emoji.name().to_string(),
+                    shortcode: entry.emoji.shortcode().map(str::to_string),
+                    has_skin_tones: entry.emoji.skin_tones().is_some(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Emoji matching `query` by shortcode, name words or a fuzzy shortcode
+/// match, best first. An empty query returns the most used emoji.
+/// `skin_tone` is `light`, `medium-light`, `medium`, `medium-dark` or `dark`.
+#[tauri::command]
+pub fn search_emoji(
+    usage: tauri::State<'_, std::sync::Arc<EmojiUsage>>,
+    query: String,
+    skin_tone: Option<String>,
+    limit: Option<usize>,
+) -> Vec<EmojiMatch> {
+    let tone = skin_tone.as_deref().and_then(parse_skin_tone);
+    usage.search(&query, tone, limit.unwrap_or(DEFAULT_LIMIT))
+}
+
+/// Count a pick towards frequency ranking.
+#[tauri::command]
+pub fn record_emoji_use(
+    usage: tauri::State<'_, std::sync::Arc<EmojiUsage>>,
+    emoji: String,
+) -> Result<(), String> {
+    usage.record(&emoji)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emojis_of(matches: &[EmojiMatch]) -> Vec<&str> {
+        matches.iter().map(|m| m.emoji.as_str()).collect()
+    }
+
+    #[test]
+    fn exact_shortcode_ranks_first() {
+        let usage = EmojiUsage::load(None);
+        let results = usage.search(":heart:", None, 5);
+        assert_eq!(results[0].emoji, "❤️");
+        assert_eq!(results[0].shortcode.as_deref(), Some("heart"));
+    }
+
+    #[test]
+    fn matches_name_words_and_fuzzy_shortcodes() {
+        let usage = EmojiUsage::load(None);
+        assert!(emojis_of(&usage.search("pizza", None, 5)).contains(&"🍕"));
+        assert!(emojis_of(&usage.search("thumbs up", None, 5)).contains(&"👍"));
+        assert!(emojis_of(&usage.search("thmbsup", None, 10)).contains(&"👍"));
+        assert!(usage.search("zzqqxx", None, 5).is_empty());
+    }
+
+    #[test]
+    fn applies_skin_tone_where_available() {
+        let usage = EmojiUsage::load(None);
+        let wave = usage.search("wave", Some(SkinTone::Dark), 1);
+        assert_eq!(wave[0].emoji, "👋🏿");
+        assert!(wave[0].has_skin_tones);
+        let heart = usage.search("heart", Some(SkinTone::Dark), 1);
+        assert_eq!(heart[0].emoji, "❤️");
+    }
+
+    #[test]
+    fn usage_feeds_ranking_and_ignores_tone() {
+        let usage = EmojiUsage::load(None);
+        usage.record("👋🏽").unwrap();
+        usage.record("👋").unwrap();
+        usage.record("🎉").unwrap();
+        assert!(usage.record("not an emoji").is_err());
+        assert_eq!(emojis_of(&usage.search("", None, 5)), vec!["👋", "🎉"]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Errors returned to the frontend by Tauri commands.
+//!
+//! Commands used to fail with a bare string, so the frontend could only show
+//! it verbatim (in English, with whatever technical detail it carried) and
+//! guess from its wording whether trying again made sense. A [`FluuxError`]
+//! reaches the frontend as `{ kind, retryable, message, detail }`: `kind` is
+//! a stable key to pick a localized message and retry policy from, `message`
+//! the English fallback for the user, and `detail` the technical text for
+//! logs and bug reports.
+//!
+//! The upstream connect pipeline keeps its string errors: they end up in
+//! WebSocket close reasons, where the SDK reads the `tls-error: <class>` and
+//! `stream-error: <condition>` markers. [`FluuxError::upstream`] classifies
+//! such a string when it reaches a command.
+
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FluuxError {
+    /// Input the user can correct: a malformed server, an empty nickname.
+    InvalidInput(String),
+    /// The server could not be reached: name resolution, TCP.
+    Network(String),
+    /// The server's certificate or the TLS handshake was rejected.
+    Tls(String),
+    /// The server refused the stream or the request.
+    Server(String),
+    /// The operation did not finish in time.
+    Timeout(String),
+    /// A local listener (the XMPP proxy, the MCP server) could not start,
+    /// or is not running.
+    LocalServer(String),
+    /// The OS keychain is locked, or access to it was denied.
+    KeychainLocked(String),
+    /// Any other keychain failure.
+    Keychain(String),
+    /// Forbidden by the administrator's policy.
+    Policy(String),
+    /// Anything else: a panicked task, an unreadable file.
+    Internal(String),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Serialized<'a> {
+    kind: &'static str,
+    retryable: bool,
+    message: &'static str,
+    detail: &'a str,
+}
+
+impl FluuxError {
+    /// Classify an error of the upstream connect pipeline by its markers.
+    pub fn upstream(detail: String) -> Self {
+        if detail.contains("tls-error") {
+            FluuxError::Tls(detail)
+        } else if detail.contains("stream-error") {
+            FluuxError::Server(detail)
+        } else if detail.contains("timed out") {
+            FluuxError::Timeout(detail)
+        } else {
+            FluuxError::Network(detail)
+        }
+    }
+
+    /// Stable key of the variant, for the frontend's messages and retries.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FluuxError::InvalidInput(_) => "invalid-input",
+            FluuxError::Network(_) => "network",
+            FluuxError::Tls(_) => "tls",
+            FluuxError::Server(_) => "server",
+            FluuxError::Timeout(_) => "timeout",
+            FluuxError::LocalServer(_) => "local-server",
+            FluuxError::KeychainLocked(_) => "keychain-locked",
+            FluuxError::Keychain(_) => "keychain",
+            FluuxError::Policy(_) => "policy",
+            FluuxError::Internal(_) => "internal",
+        }
+    }
+
+    /// Whether the same call may succeed later without the user changing
+    /// anything (a locked keychain once unlocked).
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            FluuxError::Network(_)
+                | FluuxError::Timeout(_)
+                | FluuxError::LocalServer(_)
+                | FluuxError::KeychainLocked(_)
+        )
+    }
+
+    /// What the user is told when the frontend has no translation for the
+    /// kind.
+    pub fn message(&self) -> &'static str {
+        match self {
+            FluuxError::InvalidInput(_) => "Please check what you entered.",
+            FluuxError::Network(_) => "The server could not be reached.",
+            FluuxError::Tls(_) => "The server's identity could not be verified.",
+            FluuxError::Server(_) => "The server refused the request.",
+            FluuxError::Timeout(_) => "The operation took too long.",
+            FluuxError::LocalServer(_) => "Fluux could not start a local service.",
+            FluuxError::KeychainLocked(_) => "The keychain is locked or access was denied.",
+            FluuxError::Keychain(_) => "The keychain could not be used.",
+            FluuxError::Policy(_) => "This is disabled by your administrator.",
+            FluuxError::Internal(_) => "Something went wrong.",
+        }
+    }
+
+    /// The technical text, for logs and bug reports.
+    pub fn detail(&self) -> &str {
+        match self {
+            FluuxError::InvalidInput(detail)
+            | FluuxError::Network(detail)
+            | FluuxError::Tls(detail)
+            | FluuxError::Server(detail)
+            | FluuxError::Timeout(detail)
+            | FluuxError::LocalServer(detail)
+            | FluuxError::KeychainLocked(detail)
+            | FluuxError::Keychain(detail)
+            | FluuxError::Policy(detail)
+            | FluuxError::Internal(detail) => detail,
+        }
+    }
+}
+
+impl fmt::Display for FluuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.detail())
+    }
+}
+
+impl std::error::Error for FluuxError {}
+
+impl Serialize for FluuxError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Serialized {
+            kind: self.kind(),
+            retryable: self.retryable(),
+            message: self.message(),
+            detail: self.detail(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// For the modules still reporting strings (`?` on a [`FluuxError`]).
+impl From<FluuxError> for String {
+    fn from(error: FluuxError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_kind_retry_and_both_texts() {
+        let error =
+            FluuxError::upstream("TLS handshake failed (tls-error: certificate-expired)".into());
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "kind": "tls",
+                "retryable": false,
+                "message": "The server's identity could not be verified.",
+                "detail": "TLS handshake failed (tls-error: certificate-expired)",
+            })
+        );
+        assert_eq!(
+            FluuxError::upstream("connect timed out".into()).kind(),
+            "timeout"
+        );
+        assert!(FluuxError::upstream("connection refused".into()).retryable());
+        assert_eq!(String::from(FluuxError::Policy("no".into())), "no");
+    }
+}
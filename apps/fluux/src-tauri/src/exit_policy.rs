@@ -0,0 +1,202 @@
+//! What happens between a quit request and the process exiting.
+//!
+//! Quitting used to emit `graceful-shutdown` and force-exit two seconds
+//! later, whatever the session was doing; a message sent just before
+//! quitting was often still unacknowledged and was lost with the socket.
+//! The flow is now:
+//!
+//! 1. If the frontend reported queued messages or an active call, and the
+//!    [`ExitPolicy`] asks for it, the user confirms first. Cancelling leaves
+//!    the app running.
+//! 2. With XEP-0198 active on the bridge, the server is asked for an ack and
+//!    the exit waits until everything sent has been acknowledged, up to
+//!    [`ExitPolicy::ack_timeout_ms`].
+//! 3. `graceful-shutdown` is emitted; the frontend disconnects and calls
+//!    `exit_app`, with a fallback exit if it does not.
+
+use crate::shell_i18n::{self, ShellStrings};
+use crate::xmpp_proxy::session;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+/// Upper bound accepted for the ack wait; longer would look like a hang.
+const MAX_ACK_TIMEOUT_MS: u64 = 30_000;
+
+/// How often the ack counters are checked while waiting.
+const ACK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Time the frontend gets to disconnect and call `exit_app` after
+/// `graceful-shutdown` before the process exits anyway.
+const FRONTEND_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExitPolicy {
+    /// Ask before quitting while messages are queued or a call is active.
+    pub confirm_pending: bool,
+    /// How long to wait for the server to acknowledge what was sent.
+    /// `0` skips the wait.
+    pub ack_timeout_ms: u64,
+}
+
+impl Default for ExitPolicy {
+    fn default() -> Self {
+        Self {
+            confirm_pending: true,
+            ack_timeout_ms: 5_000,
+        }
+    }
+}
+
+/// Exit policy plus what the frontend last reported as blocking a quiet
+/// exit.
+#[derive(Default)]
+pub struct ExitGuard {
+    policy: Mutex<ExitPolicy>,
+    outbox: AtomicU32,
+    call_active: AtomicBool,
+}
+
+impl ExitGuard {
+    fn policy(&self) -> ExitPolicy {
+        *self.policy.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Body of the confirmation dialog, or `None` when quitting needs no
+    /// confirmation.
+    fn confirmation(&self, s: &ShellStrings) -> Option<String> {
+        if !self.policy().confirm_pending {
+            return None;
+        }
+        let reasons: Vec<&str> = [
+            (self.outbox.load(Ordering::Relaxed) > 0, s.pending_messages),
+            (self.call_active.load(Ordering::Relaxed), s.active_call),
+        ]
+        .into_iter()
+        .filter_map(|(applies, reason)| applies.then_some(reason))
+        .collect();
+        (!reasons.is_empty()).then(|| reasons.join("\n"))
+    }
+}
+
+/// Run `proceed` once quitting is confirmed, or `cancel` if the user
+/// declines. Without anything pending, `proceed` runs immediately.
+pub fn confirm_quit(
+    app: &tauri::AppHandle,
+    proceed: impl FnOnce() + Send + 'static,
+    cancel: impl FnOnce() + Send + 'static,
+) {
+    let s = shell_i18n::strings(app.state::<shell_i18n::UiLanguage>().get());
+    let Some(body) = app.state::<ExitGuard>().confirmation(s) else {
+        proceed();
+        return;
+    };
+    // Bring the window forward so the sheet is not attached to a hidden one.
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+    app.dialog()
+        .message(body)
+        .title(s.quit_confirm)
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            s.tray_quit.to_string(),
+            s.cancel.to_string(),
+        ))
+        .show(move |confirmed| {
+            if confirmed {
+                proceed();
+            } else {
+                tracing::info!("Quit cancelled by the user");
+                cancel();
+            }
+        });
+}
+
+/// Wait for outstanding stream-management acks, then hand over to the
+/// frontend's graceful disconnect and exit if it does not finish in time.
+pub fn drain_then_exit(app: &tauri::AppHandle) {
+    let timeout = Duration::from_millis(app.state::<ExitGuard>().policy().ack_timeout_ms);
+    let handle = app.clone();
+    std::thread::spawn(move || {
+        wait_for_acks(timeout);
+        let _ = handle.emit("graceful-shutdown", ());
+        std::thread::sleep(FRONTEND_GRACE);
+        handle.exit(0);
+    });
+}
+
+fn wait_for_acks(timeout: Duration) {
+    if timeout.is_zero() || !matches!(session::unacked_outbound(), Some(n) if n > 0) {
+        return;
+    }
+    if let Err(e) = session::request_ack() {
+        tracing::debug!(error = %e, "Exit: cannot request an ack");
+        return;
+    }
+    let deadline = Instant::now() + timeout;
+    loop {
+        match session::unacked_outbound() {
+            Some(0) | None => return,
+            Some(unacked) if Instant::now() >= deadline => {
+                tracing::warn!(unacked, "Exit: gave up waiting for stanza acks");
+                return;
+            }
+            Some(_) => std::thread::sleep(ACK_POLL_INTERVAL),
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_exit_policy(guard: tauri::State<'_, ExitGuard>) -> ExitPolicy {
+    guard.policy()
+}
+
+#[tauri::command]
+pub fn set_exit_policy(guard: tauri::State<'_, ExitGuard>, policy: ExitPolicy) -> ExitPolicy {
+    let policy = ExitPolicy {
+        ack_timeout_ms: policy.ack_timeout_ms.min(MAX_ACK_TIMEOUT_MS),
+        ..policy
+    };
+    *guard.policy.lock().unwrap_or_else(|e| e.into_inner()) = policy;
+    policy
+}
+
+/// Report what a quit would interrupt: messages still in the outbox and
+/// whether a call is in progress.
+#[tauri::command]
+pub fn set_exit_blockers(guard: tauri::State<'_, ExitGuard>, outbox: u32, call_active: bool) {
+    guard.outbox.store(outbox, Ordering::Relaxed);
+    guard.call_active.store(call_active, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirms_only_when_something_is_pending() {
+        let s = shell_i18n::strings("en");
+        let guard = ExitGuard::default();
+        assert_eq!(guard.confirmation(s), None);
+
+        guard.outbox.store(2, Ordering::Relaxed);
+        assert_eq!(guard.confirmation(s).as_deref(), Some(s.pending_messages));
+
+        guard.call_active.store(true, Ordering::Relaxed);
+        let body = guard.confirmation(s).unwrap();
+        assert!(body.contains(s.pending_messages) && body.contains(s.active_call));
+
+        *guard.policy.lock().unwrap() = ExitPolicy {
+            confirm_pending: false,
+            ..ExitPolicy::default()
+        };
+        assert_eq!(guard.confirmation(s), None);
+    }
+}
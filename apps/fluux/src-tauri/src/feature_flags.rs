@@ -0,0 +1,210 @@
+//! Remote feature flags: a kill switch for features that misbehave in the
+//! field, without shipping an emergency release.
+//!
+//! At startup the flag set is fetched from [`ENDPOINT`] along with its
+//! minisign signature (`<ENDPOINT>.minisig`), made with the release key the
+//! updater trusts ([`PUBLIC_KEY`]). A correctly signed set replaces the
+//! current one unless its `serial` is older, so a replayed set can't turn a
+//! feature back on, and is cached as `feature-flags.json` with its signature.
+//! Offline, the cached set is used once its signature checks out again.
+//!
+//! Flags the set doesn't mention are on. Rust code asks [`enabled`]; the
+//! frontend gets the set from [`get_feature_flags`] and the
+//! `feature-flags-changed` event.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tauri::Emitter;
+use tracing::{info, warn};
+
+const ENDPOINT: &str = "https://fluux.io/flags/v1/flags.json";
+/// Release signing key (the updater's `pubkey` in `tauri.conf.json`).
+const PUBLIC_KEY: &str = "RWQaZps20iITrfpnJccAj2V2kK5OhDP2KEqkZapDcdP18wyVGPB1xXLJ";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const CACHE_FILE: &str = "feature-flags.json";
+
+/// Link previews (`fetch_url_metadata`).
+pub const LINK_PREVIEWS: &str = "linkPreviews";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FlagSet {
+    /// Increases with every published set.
+    pub serial: u64,
+    pub flags: BTreeMap<String, bool>,
+}
+
+/// A flag set as fetched or cached: the JSON and its `.minisig`.
+struct Signed {
+    json: Vec<u8>,
+    signature: String,
+}
+
+static FLAGS: Mutex<Option<FlagSet>> = Mutex::new(None);
+static CACHE_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+fn current() -> Option<FlagSet> {
+    FLAGS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Whether `flag` is on: true unless the flag set turns it off.
+pub fn enabled(flag: &str) -> bool {
+    current().is_none_or(|set| set.flags.get(flag).copied().unwrap_or(true))
+}
+
+/// Flags the current set names, for telemetry.
+pub fn flags() -> BTreeMap<String, bool> {
+    current().map(|set| set.flags).unwrap_or_default()
+}
+
+/// The flag set in `signed`, if its signature is valid.
+fn verify(signed: &Signed, public_key: &str) -> Result<FlagSet, String> {
+    let key = minisign_verify::PublicKey::from_base64(public_key)
+        .map_err(|e| format!("Invalid public key: {e}"))?;
+    let signature = minisign_verify::Signature::decode(&signed.signature)
+        .map_err(|e| format!("Invalid signature: {e}"))?;
+    key.verify(&signed.json, &signature, false)
+        .map_err(|e| format!("Bad signature: {e}"))?;
+    serde_json::from_slice(&signed.json).map_err(|e| format!("Invalid flag set: {e}"))
+}
+
+/// Whether `candidate` may replace `current`.
+fn newer(current: Option<&FlagSet>, candidate: &FlagSet) -> bool {
+    current.is_none_or(|set| candidate.serial >= set.serial)
+}
+
+fn signature_path(path: &std::path::Path) -> PathBuf {
+    path.with_extension("json.minisig")
+}
+
+fn read_cache() -> Option<Signed> {
+    let path = CACHE_PATH.get()?;
+    Some(Signed {
+        json: std::fs::read(path).ok()?,
+        signature: std::fs::read_to_string(signature_path(path)).ok()?,
+    })
+}
+
+fn write_cache(signed: &Signed) -> Result<(), String> {
+    let Some(path) = CACHE_PATH.get() else {
+        return Ok(());
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    // Signature first: a set whose signature is missing or stale is ignored,
+    // so an interrupted write loses the cache rather than trusting it.
+    std::fs::write(signature_path(path), &signed.signature).map_err(|e| e.to_string())?;
+    crate::json_file::write_atomic(path, &signed.json).map_err(|e| e.to_string())
+}
+
+/// Use the cached flag set in `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join(CACHE_FILE)) else {
+        return;
+    };
+    let _ = CACHE_PATH.set(path);
+    let Some(signed) = read_cache() else {
+        return;
+    };
+    match verify(&signed, PUBLIC_KEY) {
+        Ok(set) => *FLAGS.lock().unwrap_or_else(|e| e.into_inner()) = Some(set),
+        Err(e) => warn!(error = %e, "feature flags: ignoring the cached set"),
+    }
+}
+
+/// Blocking.
+fn fetch() -> Result<Signed, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .use_preconfigured_tls(crate::xmpp_proxy::tls_client_config()?)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))?;
+    let get = |url: &str| -> Result<Vec<u8>, String> {
+        let response = client
+            .get(url)
+            .send()
+            .map_err(|e| format!("Failed to fetch {url}: {e}"))?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to fetch {url}: HTTP {}", response.status()));
+        }
+        response
+            .bytes()
+            .map(|b| b.to_vec())
+            .map_err(|e| format!("Failed to read {url}: {e}"))
+    };
+    let json = get(ENDPOINT)?;
+    let signature = String::from_utf8(get(&format!("{ENDPOINT}.minisig"))?)
+        .map_err(|_| "Invalid signature: not UTF-8".to_string())?;
+    Ok(Signed { json, signature })
+}
+
+/// Fetch the current flag set once. Called from the Tauri `setup` hook.
+pub fn start(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let result = tauri::async_runtime::spawn_blocking(|| {
+            let signed = fetch()?;
+            let set = verify(&signed, PUBLIC_KEY)?;
+            if !newer(current().as_ref(), &set) {
+                return Err(format!("Refusing older flag set {}", set.serial));
+            }
+            if let Err(e) = write_cache(&signed) {
+                warn!(error = %e, "feature flags: failed to cache the set");
+            }
+            Ok(set)
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+        let set = match result {
+            Ok(set) => set,
+            Err(e) => {
+                warn!(error = %e, "feature flags: keeping the current set");
+                return;
+            }
+        };
+        let changed = {
+            let mut guard = FLAGS.lock().unwrap_or_else(|e| e.into_inner());
+            let changed = guard.as_ref() != Some(&set);
+            *guard = Some(set.clone());
+            changed
+        };
+        if changed {
+            info!(serial = set.serial, flags = ?set.flags, "feature flags: updated");
+            let _ = app.emit("feature-flags-changed", &set.flags);
+        }
+    });
+}
+
+/// Flags the current set names; the frontend treats the others as on.
+#[tauri::command]
+pub fn get_feature_flags() -> BTreeMap<String, bool> {
+    flags()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsigned_or_older_sets_are_refused() {
+        let signed = Signed {
+            json: br#"{"serial":3,"flags":{"linkPreviews":false}}"#.to_vec(),
+            signature: "untrusted comment: forged\nnot a signature\n".to_string(),
+        };
+        assert!(verify(&signed, PUBLIC_KEY).is_err());
+
+        let set: FlagSet = serde_json::from_slice(&signed.json).unwrap();
+        assert_eq!(set.flags.get(LINK_PREVIEWS), Some(&false));
+        let older = FlagSet {
+            serial: 2,
+            ..set.clone()
+        };
+        assert!(newer(None, &older));
+        assert!(newer(Some(&older), &set));
+        assert!(newer(Some(&set), &set));
+        assert!(!newer(Some(&set), &older));
+    }
+}
@@ -0,0 +1,202 @@
+//! Native handling of files dropped onto the main window.
+//!
+//! The WebView only sees dropped files as `File` blobs, so the upload
+//! pipeline would have to read every attachment into JS memory first. Tauri
+//! reports the real paths alongside; this module validates them, resolves
+//! what the platform needs resolved (macOS aliases and security scope) and
+//! emits `files-dropped` with size and MIME type for each file, so the
+//! frontend can attach by path.
+
+use crate::file_info::{self, FileInfo};
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::{DragDropEvent, Emitter, WebviewWindow, WindowEvent};
+
+const FILES_DROPPED_EVENT: &str = "files-dropped";
+
+/// A dropped path that cannot be attached, with the reason shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedPath {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FilesDroppedPayload {
+    files: Vec<FileInfo>,
+    rejected: Vec<RejectedPath>,
+    /// Drop location in logical pixels, for picking the target conversation.
+    x: f64,
+    y: f64,
+}
+
+/// Split dropped paths into attachable files and rejections. Symlinks are
+/// followed so the upload reads (and names) the actual file.
+fn validate(paths: Vec<PathBuf>) -> (Vec<FileInfo>, Vec<RejectedPath>) {
+    let mut files = Vec::new();
+    let mut rejected = Vec::new();
+    for path in paths {
+        let reject = |reason: &str| RejectedPath {
+            path: path.to_string_lossy().into_owned(),
+            reason: reason.to_string(),
+        };
+        let resolved = match std::fs::canonicalize(&path) {
+            Ok(resolved) => resolved,
+            Err(_) => {
+                rejected.push(reject("not found"));
+                continue;
+            }
+        };
+        if resolved.is_dir() {
+            rejected.push(reject("directories cannot be attached"));
+            continue;
+        }
+        if std::fs::File::open(&resolved).is_err() {
+            rejected.push(reject("not readable"));
+            continue;
+        }
+        match file_info::describe(&resolved) {
+            Ok(info) => files.push(info),
+            Err(e) => rejected.push(reject(&e)),
+        }
+    }
+    (files, rejected)
+}
+
+#[cfg(target_os = "macos")]
+mod scope {
+    //! Dropped URLs may be Finder aliases, and under the App Sandbox they
+    //! carry a security scope that must be entered before the file can be
+    //! opened and left once the upload is done. NSURL objects are not
+    //! `Send`; drop events and [`super::release_dropped_files`] both run on
+    //! the main thread, which owns the registry.
+
+    use objc2::rc::Retained;
+    use objc2_foundation::{NSString, NSURLBookmarkResolutionOptions, NSURL};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    thread_local! {
+        static ACCESSED: RefCell<HashMap<PathBuf, Retained<NSURL>>> =
+            RefCell::new(HashMap::new());
+    }
+
+    /// Resolve aliases and enter the security scope of a dropped path.
+    /// Falls back to the path as dropped when it is not an alias.
+    pub fn acquire(path: PathBuf) -> PathBuf {
+        let Some(raw) = path.to_str() else {
+            return path;
+        };
+        let url = NSURL::fileURLWithPath(&NSString::from_str(raw));
+        // SAFETY: `url` is a valid file URL for the duration of both calls;
+        // resolution options forbid UI, so this cannot block on a dialog.
+        let (url, scoped) = unsafe {
+            let url = NSURL::URLByResolvingAliasFileAtURL_options_error(
+                &url,
+                NSURLBookmarkResolutionOptions::WithoutUI,
+            )
+            .unwrap_or(url);
+            let scoped = url.startAccessingSecurityScopedResource();
+            (url, scoped)
+        };
+        let resolved = url
+            .path()
+            .map(|p| PathBuf::from(p.to_string()))
+            .unwrap_or(path);
+        if scoped {
+            ACCESSED.with(|accessed| {
+                if let Some(previous) = accessed.borrow_mut().insert(resolved.clone(), url) {
+                    // SAFETY: balances the start made when it was inserted.
+                    unsafe { previous.stopAccessingSecurityScopedResource() };
+                }
+            });
+        }
+        resolved
+    }
+
+    pub fn release(path: &std::path::Path) {
+        ACCESSED.with(|accessed| {
+            if let Some(url) = accessed.borrow_mut().remove(path) {
+                // SAFETY: balances the start made in `acquire`.
+                unsafe { url.stopAccessingSecurityScopedResource() };
+            }
+        });
+    }
+}
+
+fn handle_drop(window: &WebviewWindow, paths: Vec<PathBuf>, x: f64, y: f64) {
+    #[cfg(target_os = "macos")]
+    let paths: Vec<PathBuf> = paths.into_iter().map(scope::acquire).collect();
+
+    // Metadata and content sniffing touch the disk (possibly a network
+    // share); keep them off the event loop.
+    let window = window.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let (files, rejected) = validate(paths);
+        if !rejected.is_empty() {
+            tracing::info!(count = rejected.len(), "Rejected dropped paths");
+        }
+        let _ = window.emit(
+            FILES_DROPPED_EVENT,
+            FilesDroppedPayload {
+                files,
+                rejected,
+                x,
+                y,
+            },
+        );
+    });
+}
+
+/// Listen for file drops on `window`.
+pub fn attach(window: &WebviewWindow) {
+    let target = window.clone();
+    window.on_window_event(move |event| {
+        if let WindowEvent::DragDrop(DragDropEvent::Drop { paths, position }) = event {
+            let scale = target.scale_factor().unwrap_or(1.0);
+            let logical = position.to_logical::<f64>(scale);
+            handle_drop(&target, paths.clone(), logical.x, logical.y);
+        }
+    });
+}
+
+/// Give up access to dropped files once they are uploaded or discarded.
+/// Only meaningful on macOS, where drops may hold a security scope open.
+#[tauri::command]
+pub fn release_dropped_files(paths: Vec<String>) {
+    #[cfg(target_os = "macos")]
+    for path in paths {
+        scope::release(std::path::Path::new(&path));
+    }
+    #[cfg(not(target_os = "macos"))]
+    let _ = paths;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_files_from_directories_and_missing_paths() {
+        let dir = std::env::temp_dir().join(format!("fluux-file-drop-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, b"hello").unwrap();
+
+        let (files, rejected) = validate(vec![file.clone(), dir.clone(), dir.join("gone.png")]);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "notes.txt");
+        assert_eq!(files[0].mime_type, "text/plain");
+        assert_eq!(
+            rejected
+                .iter()
+                .map(|r| r.reason.as_str())
+                .collect::<Vec<_>>(),
+            vec!["directories cannot be attached", "not found"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,458 @@
+//! GIF search through Tenor or Giphy.
+//!
+//! The provider API key is stored in the OS keychain and only ever used
+//! here, so it is not shipped in (or extractable from) the WebView bundle.
+//! Search results are cached in memory for a few minutes, and preview
+//! thumbnails are downloaded into the app cache and served to the WebView
+//! as local files: typing a search no longer has the WebView hit the
+//! provider's CDN (and reveal the user's IP to it) for every keystroke's
+//! worth of previews. Only the GIF the user actually sends is fetched from
+//! the CDN, by its recipients.
+
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keychain slot holding the provider and API key.
+pub(crate) const GIF_KEYRING_USER: &str = "gif-provider";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const RESULT_TTL: Duration = Duration::from_secs(10 * 60);
+const MAX_CACHED_QUERIES: usize = 64;
+const DEFAULT_LIMIT: u32 = 24;
+const MAX_LIMIT: u32 = 50;
+/// Previews are small renditions; anything larger is not worth caching.
+const MAX_PREVIEW_BYTES: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GifProvider {
+    Tenor,
+    Giphy,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GifCredentials {
+    provider: GifProvider,
+    api_key: String,
+}
+
+/// What the frontend may know about the configuration (never the key).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GifConfig {
+    pub provider: GifProvider,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GifResult {
+    pub id: String,
+    pub title: String,
+    /// Full-size GIF, the URL to share.
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    /// Local copy of the preview rendition, for `convertFileSrc`.
+    pub preview_path: Option<String>,
+    pub preview_width: u32,
+    pub preview_height: u32,
+    #[serde(skip)]
+    preview_url: String,
+}
+
+fn dimension(value: &Value) -> u32 {
+    // Giphy sends dimensions as strings, Tenor as numbers.
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        .unwrap_or(0) as u32
+}
+
+fn parse_tenor(body: &Value) -> Vec<GifResult> {
+    let Some(results) = body.get("results").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    results
+        .iter()
+        .filter_map(|item| {
+            let formats = item.get("media_formats")?;
+            let full = formats.get("gif")?;
+            let preview = formats.get("tinygif").unwrap_or(full);
+            let dims =
+                |f: &Value, i: usize| f.get("dims").and_then(|d| d.get(i)).map_or(0, dimension);
+            Some(GifResult {
+                id: item.get("id")?.as_str()?.to_string(),
+                title: item
+                    .get("content_description")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                url: full.get("url")?.as_str()?.to_string(),
+                width: dims(full, 0),
+                height: dims(full, 1),
+                preview_path: None,
+                preview_width: dims(preview, 0),
+                preview_height: dims(preview, 1),
+                preview_url: preview.get("url")?.as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_giphy(body: &Value) -> Vec<GifResult> {
+    let Some(results) = body.get("data").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    results
+        .iter()
+        .filter_map(|item| {
+            let images = item.get("images")?;
+            let full = images.get("original")?;
+            let preview = images.get("fixed_width_small").unwrap_or(full);
+            let size = |f: &Value, key: &str| f.get(key).map_or(0, dimension);
+            Some(GifResult {
+                id: item.get("id")?.as_str()?.to_string(),
+                title: item
+                    .get("title")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                url: full.get("url")?.as_str()?.to_string(),
+                width: size(full, "width"),
+                height: size(full, "height"),
+                preview_path: None,
+                preview_width: size(preview, "width"),
+                preview_height: size(preview, "height"),
+                preview_url: preview.get("url")?.as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Search (or, for an empty query, trending) endpoint for a provider.
+fn search_url(
+    credentials: &GifCredentials,
+    query: &str,
+    limit: u32,
+) -> Result<reqwest::Url, String> {
+    let limit = limit.to_string();
+    let key = credentials.api_key.as_str();
+    let mut params: Vec<(&str, &str)> = Vec::new();
+    if !query.is_empty() {
+        params.push(("q", query));
+    }
+    let base = match credentials.provider {
+        GifProvider::Tenor => {
+            params.extend([
+                ("key", key),
+                ("client_key", "fluux"),
+                ("limit", &limit),
+                ("media_filter", "gif,tinygif"),
+                ("contentfilter", "medium"),
+            ]);
+            if query.is_empty() {
+                "https://tenor.googleapis.com/v2/featured"
+            } else {
+                "https://tenor.googleapis.com/v2/search"
+            }
+        }
+        GifProvider::Giphy => {
+            params.extend([("api_key", key), ("limit", &limit), ("rating", "pg-13")]);
+            if query.is_empty() {
+                "https://api.giphy.com/v1/gifs/trending"
+            } else {
+                "https://api.giphy.com/v1/gifs/search"
+            }
+        }
+    };
+    reqwest::Url::parse_with_params(base, &params)
+        .map_err(|e| format!("Invalid GIF search URL: {e}"))
+}
+
+/// Cache file for a preview URL: content-addressed by URL, so repeated
+/// searches reuse the same file.
+fn preview_file(dir: &Path, url: &str) -> PathBuf {
+    let digest = Sha1::digest(url.as_bytes());
+    let name: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    let ext = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|u| u.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .filter(|ext| matches!(ext.as_str(), "gif" | "webp" | "mp4"))
+        .unwrap_or_else(|| "gif".to_string());
+    dir.join(format!("{name}.{ext}"))
+}
+
+fn download_preview(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    path: &Path,
+) -> Result<(), String> {
+    let response = client
+        .get(url)
+        .send()
+        .map_err(|e| format!("Preview request failed: {e}"))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Preview request failed: {}",
+            response.status().as_u16()
+        ));
+    }
+    let mut bytes = Vec::new();
+    response
+        .take(MAX_PREVIEW_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to read preview: {e}"))?;
+    if bytes.len() as u64 > MAX_PREVIEW_BYTES {
+        return Err("Preview too large".to_string());
+    }
+    let tmp = path.with_extension("part");
+    std::fs::write(&tmp, bytes).map_err(|e| format!("Failed to write preview: {e}"))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to write preview: {e}"))
+}
+
+/// Fill in `preview_path` for every result, downloading missing previews in
+/// parallel. A failed preview leaves the path empty rather than failing the
+/// search.
+fn ensure_previews(client: &reqwest::blocking::Client, dir: &Path, results: &mut [GifResult]) {
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        tracing::warn!("GIF preview cache unavailable: {}", e);
+        return;
+    }
+    std::thread::scope(|scope| {
+        for result in results.iter_mut() {
+            scope.spawn(move || {
+                let path = preview_file(dir, &result.preview_url);
+                let ok = path.exists()
+                    || download_preview(client, &result.preview_url, &path)
+                        .map_err(|e| tracing::debug!(id = %result.id, "GIF preview: {}", e))
+                        .is_ok();
+                result.preview_path = ok.then(|| path.to_string_lossy().into_owned());
+            });
+        }
+    });
+}
+
+/// Recent searches, keyed by provider, query and limit.
+static RESULT_CACHE: Mutex<Option<HashMap<String, (Instant, Vec<GifResult>)>>> = Mutex::new(None);
+
+fn cached_results(key: &str) -> Option<Vec<GifResult>> {
+    let mut cache = RESULT_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = cache.get_or_insert_with(HashMap::new);
+    cache.retain(|_, (at, _)| at.elapsed() < RESULT_TTL);
+    cache.get(key).map(|(_, results)| results.clone())
+}
+
+fn store_results(key: String, results: Vec<GifResult>) {
+    let mut cache = RESULT_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = cache.get_or_insert_with(HashMap::new);
+    if cache.len() >= MAX_CACHED_QUERIES {
+        if let Some(oldest) = cache
+            .iter()
+            .min_by_key(|(_, (at, _))| *at)
+            .map(|(k, _)| k.clone())
+        {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(key, (Instant::now(), results));
+}
+
+fn clear_results() {
+    *RESULT_CACHE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+fn keyring_entry() -> Result<Entry, String> {
+    Entry::new(crate::KEYRING_SERVICE, GIF_KEYRING_USER)
+        .map_err(|e| format!("Failed to create keyring entry for GIF provider: {e}"))
+}
+
+fn load_credentials() -> Result<Option<GifCredentials>, String> {
+    let entry = keyring_entry()?;
+    match crate::credential_log::read(&entry, GIF_KEYRING_USER, "GIF provider API key") {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| format!("Stored GIF provider settings are unreadable: {e}")),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(crate::classify_keyring_error(
+            &e,
+            "read GIF provider settings",
+        )),
+    }
+}
+
+/// Store the GIF provider and its API key in the keychain.
+#[tauri::command]
+pub async fn set_gif_provider(provider: GifProvider, api_key: String) -> Result<GifConfig, String> {
+    let api_key = api_key.trim().to_string();
+    if api_key.is_empty() {
+        return Err("GIF provider API key is empty".to_string());
+    }
+    tokio::task::spawn_blocking(move || {
+        let json = serde_json::to_string(&GifCredentials { provider, api_key })
+            .map_err(|e| format!("Failed to serialize GIF provider settings: {e}"))?;
+        keyring_entry()?
+            .set_password(&json)
+            .map_err(|e| crate::classify_keyring_error(&e, "save GIF provider settings"))?;
+        clear_results();
+        Ok(GifConfig { provider })
+    })
+    .await
+    .map_err(|e| format!("Keychain task panicked: {e}"))?
+}
+
+/// The configured GIF provider, if any.
+#[tauri::command]
+pub async fn get_gif_config() -> Result<Option<GifConfig>, String> {
+    tokio::task::spawn_blocking(|| {
+        Ok(load_credentials()?.map(|c| GifConfig {
+            provider: c.provider,
+        }))
+    })
+    .await
+    .map_err(|e| format!("Keychain task panicked: {e}"))?
+}
+
+#[tauri::command]
+pub async fn clear_gif_provider() -> Result<(), String> {
+    tokio::task::spawn_blocking(|| {
+        clear_results();
+        match keyring_entry()?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(crate::classify_keyring_error(
+                &e,
+                "delete GIF provider settings",
+            )),
+        }
+    })
+    .await
+    .map_err(|e| format!("Keychain task panicked: {e}"))?
+}
+
+/// Search GIFs (trending for an empty query). Previews come back as local
+/// file paths in the app cache.
+#[tauri::command]
+pub async fn search_gifs(
+    app: tauri::AppHandle,
+    query: String,
+    limit: Option<u32>,
+) -> Result<Vec<GifResult>, String> {
+    let query = query.trim().to_string();
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let preview_dir = crate::cache::dir(&app, "gifs")?;
+    tokio::task::spawn_blocking(move || {
+        let credentials = load_credentials()?.ok_or("GIF search is not configured")?;
+        let client = reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {e}"))?;
+
+        let key = format!(
+            "{:?}\n{}\n{}",
+            credentials.provider,
+            query.to_lowercase(),
+            limit
+        );
+        let mut results = match cached_results(&key) {
+            Some(results) => results,
+            None => {
+                let url = search_url(&credentials, &query, limit)?;
+                let response = client
+                    .get(url)
+                    .send()
+                    .map_err(|e| format!("GIF search failed: {e}"))?;
+                let status = response.status();
+                if !status.is_success() {
+                    return Err(format!("GIF search failed: {}", status.as_u16()));
+                }
+                let body: Value = serde_json::from_str(
+                    &response
+                        .text()
+                        .map_err(|e| format!("Failed to read GIF search response: {e}"))?,
+                )
+                .map_err(|e| format!("GIF provider returned invalid JSON: {e}"))?;
+                let results = match credentials.provider {
+                    GifProvider::Tenor => parse_tenor(&body),
+                    GifProvider::Giphy => parse_giphy(&body),
+                };
+                store_results(key, results.clone());
+                results
+            }
+        };
+        // Also on cache hits: the preview files may have been evicted since.
+        ensure_previews(&client, &preview_dir, &mut results);
+        Ok(results)
+    })
+    .await
+    .map_err(|e| format!("GIF search task panicked: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tenor_results() {
+        let body: Value = serde_json::from_str(
+            r#"{"results":[{"id":"1","content_description":"cat typing",
+                "media_formats":{"gif":{"url":"https://media.tenor.com/a/full.gif","dims":[480,270]},
+                                 "tinygif":{"url":"https://media.tenor.com/a/tiny.gif","dims":[220,124]}}},
+               {"id":"2","media_formats":{}}]}"#,
+        )
+        .unwrap();
+        let results = parse_tenor(&body);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "cat typing");
+        assert_eq!((results[0].width, results[0].preview_height), (480, 124));
+        assert_eq!(results[0].preview_url, "https://media.tenor.com/a/tiny.gif");
+    }
+
+    #[test]
+    fn parses_giphy_string_dimensions() {
+        let body: Value = serde_json::from_str(
+            r#"{"data":[{"id":"x","title":"Dance",
+                "images":{"original":{"url":"https://media.giphy.com/o.gif","width":"500","height":"281"},
+                          "fixed_width_small":{"url":"https://media.giphy.com/s.gif","width":"100","height":"56"}}}]}"#,
+        )
+        .unwrap();
+        let results = parse_giphy(&body);
+        assert_eq!((results[0].width, results[0].preview_width), (500, 100));
+    }
+
+    #[test]
+    fn search_url_encodes_query_and_picks_trending() {
+        let credentials = GifCredentials {
+            provider: GifProvider::Giphy,
+            api_key: "k".into(),
+        };
+        let url = search_url(&credentials, "thumbs up & ok", 10).unwrap();
+        assert!(url
+            .as_str()
+            .starts_with("https://api.giphy.com/v1/gifs/search?q=thumbs+up+%26+ok&"));
+        let url = search_url(&credentials, "", 10).unwrap();
+        assert_eq!(url.path(), "/v1/gifs/trending");
+    }
+
+    #[test]
+    fn preview_file_is_stable_and_keeps_known_extensions() {
+        let dir = Path::new("/cache/gifs");
+        let a = preview_file(dir, "https://media.tenor.com/a/tiny.webp?x=1");
+        assert_eq!(
+            a,
+            preview_file(dir, "https://media.tenor.com/a/tiny.webp?x=1")
+        );
+        assert_eq!(a.extension().unwrap(), "webp");
+        assert_eq!(
+            preview_file(dir, "https://cdn/x.exe").extension().unwrap(),
+            "gif"
+        );
+    }
+}
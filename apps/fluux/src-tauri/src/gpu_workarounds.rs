@@ -0,0 +1,325 @@
+//! WebKitGTK rendering workarounds, applied only where the machine needs
+//! them.
+//!
+//! The DMA-BUF renderer crashes or paints nothing on some setups ("Error 71
+//! (Protocol error) dispatching to Wayland display", tauri#10702), mostly
+//! with NVIDIA's proprietary driver and in virtual machines. Turning it off
+//! everywhere costs Intel and AMD users their hardware acceleration, so the
+//! environment is probed at startup instead:
+//!
+//! - the DRM drivers behind `/sys/class/drm` and whether a render node
+//!   exists;
+//! - NVIDIA's proprietary driver (`/proc/driver/nvidia`);
+//! - the EGL vendor glvnd picks first;
+//! - Wayland vs X11;
+//! - a virtual machine, from the DMI vendor and product.
+//!
+//! The probe and the chosen workarounds are kept in
+//! `gpu-workarounds.json`. When the renderer crashes (see
+//! `webview_watchdog`), the next launch on the same environment uses every
+//! workaround; a changed environment is judged afresh.
+//!
+//! `FLUUX_DISABLE_GPU` still forces every workaround, and `WEBKIT_DISABLE_*`
+//! variables the user sets are left alone.
+
+use serde::{Deserialize, Serialize};
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::sync::OnceLock;
+
+#[cfg(target_os = "linux")]
+const APP_IDENTIFIER: &str = "com.processone.fluux";
+#[cfg(target_os = "linux")]
+const FILE_NAME: &str = "gpu-workarounds.json";
+/// Drivers whose DMA-BUF path works.
+const KNOWN_GOOD_DRIVERS: &[&str] = &["i915", "xe", "amdgpu", "radeon"];
+
+/// What the probe found.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Environment {
+    pub wayland: bool,
+    /// Kernel drivers of the DRM cards (`i915`, `amdgpu`, `nvidia`…).
+    pub drm_drivers: Vec<String>,
+    /// A `/dev/dri/renderD*` node exists.
+    pub render_node: bool,
+    pub nvidia_proprietary: bool,
+    /// glvnd vendor tried first (`nvidia`, `mesa`…).
+    pub egl_vendor: Option<String>,
+    pub virtual_machine: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Workarounds {
+    /// `WEBKIT_DISABLE_DMABUF_RENDERER`
+    pub disable_dmabuf: bool,
+    /// `WEBKIT_DISABLE_COMPOSITING_MODE`
+    pub disable_compositing: bool,
+}
+
+impl Workarounds {
+    const ALL: Workarounds = Workarounds {
+        disable_dmabuf: true,
+        disable_compositing: true,
+    };
+    const DMABUF: Workarounds = Workarounds {
+        disable_dmabuf: true,
+        disable_compositing: false,
+    };
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Saved {
+    environment: Environment,
+    workarounds: Workarounds,
+    /// The renderer crashed with `workarounds` on `environment`.
+    renderer_crashed: bool,
+}
+
+/// Applied workarounds and why, for the startup diagnostics.
+#[cfg(target_os = "linux")]
+static APPLIED: OnceLock<(Workarounds, &'static str)> = OnceLock::new();
+
+/// Workarounds for `environment`, and the reason.
+fn decide(environment: &Environment, renderer_crashed: bool) -> (Workarounds, &'static str) {
+    let nvidia = environment.nvidia_proprietary
+        || environment.egl_vendor.as_deref() == Some("nvidia")
+        || environment.drm_drivers.iter().any(|d| d == "nvidia");
+    let known_good = !environment.drm_drivers.is_empty()
+        && environment
+            .drm_drivers
+            .iter()
+            .all(|d| KNOWN_GOOD_DRIVERS.contains(&d.as_str()));
+    if renderer_crashed {
+        (Workarounds::ALL, "the renderer crashed on this setup")
+    } else if environment.virtual_machine {
+        (Workarounds::ALL, "virtual machine")
+    } else if nvidia {
+        (Workarounds::DMABUF, "NVIDIA proprietary driver")
+    } else if !environment.render_node {
+        (Workarounds::DMABUF, "no GPU render node")
+    } else if environment.wayland && !known_good {
+        (Workarounds::DMABUF, "Wayland with an untested GPU driver")
+    } else {
+        (Workarounds::default(), "none needed")
+    }
+}
+
+/// DMI vendor/product strings of hypervisors.
+fn is_virtual(vendor: &str, product: &str) -> bool {
+    const HYPERVISORS: &[&str] = &[
+        "qemu",
+        "kvm",
+        "vmware",
+        "virtualbox",
+        "innotek",
+        "parallels",
+        "xen",
+        "bochs",
+        "virtual machine",
+    ];
+    let text = format!("{vendor} {product}").to_ascii_lowercase();
+    HYPERVISORS.iter().any(|h| text.contains(h))
+}
+
+#[cfg(target_os = "linux")]
+fn saved_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join(APP_IDENTIFIER).join(FILE_NAME))
+}
+
+#[cfg(target_os = "linux")]
+fn load() -> Option<Saved> {
+    let bytes = std::fs::read(saved_path()?).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[cfg(target_os = "linux")]
+fn save(saved: &Saved) {
+    let Some(path) = saved_path() else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    if let Ok(bytes) = serde_json::to_vec_pretty(saved) {
+        let _ = std::fs::write(path, bytes);
+    }
+}
+
+/// Probe, decide and export the workarounds. Runs before `main` (WebKitGTK
+/// reads the variables when it initializes), so nothing is logged here.
+#[cfg(target_os = "linux")]
+pub fn apply() {
+    let environment = probe::environment();
+    let saved = load();
+    let crashed = saved
+        .as_ref()
+        .is_some_and(|s| s.renderer_crashed && s.environment == environment);
+    let (mut workarounds, mut reason) = decide(&environment, crashed);
+    if std::env::var_os("FLUUX_DISABLE_GPU").is_some() {
+        (workarounds, reason) = (Workarounds::ALL, "FLUUX_DISABLE_GPU set");
+    }
+    for (enabled, name) in [
+        (workarounds.disable_dmabuf, "WEBKIT_DISABLE_DMABUF_RENDERER"),
+        (
+            workarounds.disable_compositing,
+            "WEBKIT_DISABLE_COMPOSITING_MODE",
+        ),
+    ] {
+        if enabled && std::env::var_os(name).is_none() {
+            std::env::set_var(name, "1");
+        }
+    }
+    save(&Saved {
+        environment,
+        workarounds,
+        renderer_crashed: crashed,
+    });
+    let _ = APPLIED.set((workarounds, reason));
+}
+
+/// The renderer crashed: remember it, so the next launch turns every
+/// workaround on. Nothing to do when they already are.
+#[cfg(target_os = "linux")]
+pub fn renderer_crashed() {
+    let Some(mut saved) = load() else {
+        return;
+    };
+    if saved.workarounds == Workarounds::ALL || saved.renderer_crashed {
+        return;
+    }
+    saved.renderer_crashed = true;
+    save(&saved);
+}
+
+/// What [`apply`] did, for the startup diagnostics.
+#[cfg(target_os = "linux")]
+pub fn applied() -> Option<(Workarounds, &'static str)> {
+    APPLIED.get().copied()
+}
+
+#[cfg(target_os = "linux")]
+mod probe {
+    use super::{is_virtual, Environment};
+    use std::path::Path;
+
+    fn read(path: &str) -> String {
+        std::fs::read_to_string(path)
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Drivers of `/sys/class/drm/cardN` (connectors such as
+    /// `card0-HDMI-A-1` are skipped).
+    fn drm_drivers() -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir("/sys/class/drm") else {
+            return Vec::new();
+        };
+        let mut drivers: Vec<String> = entries
+            .flatten()
+            .filter(|e| {
+                let name = e.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with("card") && !name.contains('-')
+            })
+            .filter_map(|e| std::fs::read_link(e.path().join("device/driver")).ok())
+            .filter_map(|link| Some(link.file_name()?.to_string_lossy().into_owned()))
+            .collect();
+        drivers.sort();
+        drivers.dedup();
+        drivers
+    }
+
+    fn render_node() -> bool {
+        std::fs::read_dir("/dev/dri").is_ok_and(|entries| {
+            entries
+                .flatten()
+                .any(|e| e.file_name().to_string_lossy().starts_with("renderD"))
+        })
+    }
+
+    /// `10_nvidia.json` → `nvidia`.
+    fn vendor_name(path: &Path) -> Option<String> {
+        let stem = path.file_stem()?.to_string_lossy();
+        let name = stem.trim_start_matches(|c: char| c.is_ascii_digit() || c == '_');
+        (!name.is_empty()).then(|| name.to_ascii_lowercase())
+    }
+
+    /// The vendor glvnd tries first: the one forced through the
+    /// environment, or the lowest-numbered vendor file.
+    fn egl_vendor() -> Option<String> {
+        if let Some(files) = std::env::var_os("__EGL_VENDOR_LIBRARY_FILENAMES") {
+            let files = files.to_string_lossy().into_owned();
+            return files
+                .split(':')
+                .next()
+                .and_then(|f| vendor_name(Path::new(f)));
+        }
+        let mut files: Vec<_> = ["/etc/glvnd/egl_vendor.d", "/usr/share/glvnd/egl_vendor.d"]
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.flatten().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|x| x == "json"))
+            .collect();
+        files.sort_by_key(|p| p.file_name().map(|n| n.to_os_string()));
+        files.first().and_then(|f| vendor_name(f))
+    }
+
+    pub fn environment() -> Environment {
+        Environment {
+            wayland: std::env::var_os("WAYLAND_DISPLAY").is_some()
+                || std::env::var("XDG_SESSION_TYPE").is_ok_and(|t| t == "wayland"),
+            drm_drivers: drm_drivers(),
+            render_node: render_node(),
+            nvidia_proprietary: Path::new("/proc/driver/nvidia/version").exists(),
+            egl_vendor: egl_vendor(),
+            virtual_machine: is_virtual(
+                &read("/sys/class/dmi/id/sys_vendor"),
+                &read("/sys/class/dmi/id/product_name"),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_affected_setups_lose_acceleration() {
+        let intel_wayland = Environment {
+            wayland: true,
+            drm_drivers: vec!["i915".to_string()],
+            render_node: true,
+            egl_vendor: Some("mesa".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(decide(&intel_wayland, false).0, Workarounds::default());
+        assert_eq!(decide(&intel_wayland, true).0, Workarounds::ALL);
+
+        let nvidia = Environment {
+            egl_vendor: Some("nvidia".to_string()),
+            ..intel_wayland.clone()
+        };
+        assert_eq!(decide(&nvidia, false).0, Workarounds::DMABUF);
+
+        let nouveau_wayland = Environment {
+            drm_drivers: vec!["nouveau".to_string()],
+            ..intel_wayland.clone()
+        };
+        assert_eq!(decide(&nouveau_wayland, false).0, Workarounds::DMABUF);
+        let nouveau_x11 = Environment {
+            wayland: false,
+            ..nouveau_wayland
+        };
+        assert_eq!(decide(&nouveau_x11, false).0, Workarounds::default());
+
+        assert!(is_virtual("QEMU", "Standard PC (Q35 + ICH9, 2009)"));
+        assert!(is_virtual("Microsoft Corporation", "Virtual Machine"));
+        assert!(!is_virtual("LENOVO", "20XW0055GE"));
+    }
+}
@@ -0,0 +1,386 @@
+//! Headless mode (`--headless`): no visible window, and a local HTTP API to
+//! drive the session from scripts.
+//!
+//! The XMPP client (login, roster, stream management) lives in the webview,
+//! so the webview still runs; it is just never shown, and on macOS the app
+//! stays out of the Dock. It logs in with the credentials saved in the
+//! keychain, so log in once with the window before running headless. On a
+//! machine without a display, start it under a virtual one (`xvfb-run`).
+//!
+//! The API listens on loopback only, on `--api-port=PORT` (an OS-assigned
+//! port otherwise), and every request needs `Authorization: Bearer <token>`.
+//! The token comes from `FLUUX_API_TOKEN` or is generated and printed at
+//! startup with the port.
+//!
+//! - `GET /status`: `{ready, jid}`
+//! - `POST /message` `{to, body, type?}`: send a message, returns `{id}`
+//! - `POST /presence` `{show?, status?}`: send presence (the client may send
+//!   its own on reconnect)
+//! - `GET /events?after=SEQ&wait=SECS`: inbound messages and presence with a
+//!   sequence number above `after`, waiting up to `wait` seconds (at most
+//!   60) for the first one. The last `EVENT_BUFFER` events are kept.
+
+use crate::automation;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::Element;
+use crate::xmpp_proxy::tap::{self, Direction, StanzaObserver, TapContext, Verdict};
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::Manager;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+const EVENT_BUFFER: usize = 1000;
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Headless options from the command line.
+#[derive(Debug, Clone)]
+pub struct Options {
+    port: u16,
+    token: String,
+}
+
+/// `Some` when `--headless` was passed.
+pub fn options(args: &[String]) -> Option<Options> {
+    if !args.iter().any(|arg| arg == "--headless") {
+        return None;
+    }
+    let port = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--api-port="))
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(0);
+    let token = std::env::var("FLUUX_API_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    Some(Options { port, token })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum EventKind {
+    Message {
+        from: String,
+        #[serde(rename = "type")]
+        message_type: String,
+        id: Option<String>,
+        body: String,
+    },
+    Presence {
+        from: String,
+        #[serde(rename = "type")]
+        presence_type: Option<String>,
+        show: Option<String>,
+        status: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Event {
+    seq: u64,
+    #[serde(flatten)]
+    kind: EventKind,
+}
+
+/// Inbound messages and presence, for `GET /events`.
+struct EventLog {
+    events: Mutex<(u64, VecDeque<Event>)>,
+    arrived: Notify,
+}
+
+impl EventLog {
+    fn new() -> Self {
+        Self {
+            events: Mutex::new((0, VecDeque::new())),
+            arrived: Notify::new(),
+        }
+    }
+
+    fn push(&self, kind: EventKind) {
+        {
+            let mut guard = self.events.lock().unwrap_or_else(|e| e.into_inner());
+            let (last, events) = &mut *guard;
+            *last += 1;
+            events.push_back(Event { seq: *last, kind });
+            if events.len() > EVENT_BUFFER {
+                events.pop_front();
+            }
+        }
+        self.arrived.notify_waiters();
+    }
+
+    fn after(&self, seq: u64) -> Vec<Event> {
+        let guard = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        guard.1.iter().filter(|e| e.seq > seq).cloned().collect()
+    }
+
+    async fn wait_after(&self, seq: u64, wait: Duration) -> Vec<Event> {
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            // Created before the check, so a push in between still wakes it.
+            let arrived = self.arrived.notified();
+            let events = self.after(seq);
+            if !events.is_empty() || tokio::time::Instant::now() >= deadline {
+                return events;
+            }
+            let _ = tokio::time::timeout_at(deadline, arrived).await;
+        }
+    }
+}
+
+fn event_for(stanza: &Element) -> Option<EventKind> {
+    let from = stanza.attr("from")?.to_string();
+    match stanza.local_name() {
+        "message" => {
+            let body = stanza.child("body", None)?.text();
+            Some(EventKind::Message {
+                from,
+                message_type: stanza.attr("type").unwrap_or("normal").to_string(),
+                id: stanza.attr("id").map(str::to_string),
+                body,
+            })
+        }
+        "presence" => Some(EventKind::Presence {
+            from,
+            presence_type: stanza.attr("type").map(str::to_string),
+            show: stanza.child("show", None).map(|e| e.text()),
+            status: stanza.child("status", None).map(|e| e.text()),
+        }),
+        _ => None,
+    }
+}
+
+impl StanzaObserver for EventLog {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction == Direction::Inbound {
+            if let Some(kind) = event_for(stanza) {
+                self.push(kind);
+            }
+        }
+        Verdict::Forward
+    }
+}
+
+struct ApiState {
+    token: String,
+    events: Arc<EventLog>,
+}
+
+type ApiError = (StatusCode, String);
+
+/// The bearer check runs before the body is parsed, as for the MCP server.
+fn authorize(state: &ApiState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let expected = format!("Bearer {}", state.token);
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value == expected);
+    if authorized {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::UNAUTHORIZED,
+            "Missing or wrong token".to_string(),
+        ))
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: &Bytes) -> Result<T, ApiError> {
+    serde_json::from_slice(body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+fn send(stanza: Element) -> Result<(), ApiError> {
+    if !session::is_ready() {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "Not connected".to_string()));
+    }
+    automation::send(stanza).map_err(|e| (StatusCode::BAD_GATEWAY, e))
+}
+
+#[derive(Serialize)]
+struct Status {
+    ready: bool,
+    jid: Option<String>,
+}
+
+async fn status(
+    State(state): State<Arc<ApiState>>,
+    headers: HeaderMap,
+) -> Result<Json<Status>, ApiError> {
+    authorize(&state, &headers)?;
+    Ok(Json(Status {
+        ready: session::is_ready(),
+        jid: session::own_jid(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct SendMessage {
+    to: String,
+    body: String,
+    #[serde(rename = "type", default = "default_message_type")]
+    message_type: String,
+}
+
+fn default_message_type() -> String {
+    "chat".to_string()
+}
+
+#[derive(Serialize)]
+struct Sent {
+    id: String,
+}
+
+async fn message(
+    State(state): State<Arc<ApiState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Sent>, ApiError> {
+    authorize(&state, &headers)?;
+    let request: SendMessage = parse(&body)?;
+    let (id, stanza) = automation::message(&request.to, &request.message_type, &request.body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    send(stanza)?;
+    info!(to = %request.to, "headless: message sent");
+    Ok(Json(Sent { id }))
+}
+
+#[derive(Deserialize)]
+struct SetPresence {
+    show: Option<String>,
+    status: Option<String>,
+}
+
+async fn presence(
+    State(state): State<Arc<ApiState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, &headers)?;
+    let request: SetPresence = parse(&body)?;
+    let stanza = automation::presence(request.show.as_deref(), request.status.as_deref())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    send(stanza)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    #[serde(default)]
+    after: u64,
+    #[serde(default)]
+    wait: u64,
+}
+
+#[derive(Serialize)]
+struct Events {
+    events: Vec<Event>,
+    /// Pass as `after` on the next call.
+    next: u64,
+}
+
+async fn events(
+    State(state): State<Arc<ApiState>>,
+    headers: HeaderMap,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<Events>, ApiError> {
+    authorize(&state, &headers)?;
+    let wait = Duration::from_secs(query.wait).min(MAX_WAIT);
+    let events = state.events.wait_after(query.after, wait).await;
+    let next = events.last().map_or(query.after, |e| e.seq);
+    Ok(Json(Events { events, next }))
+}
+
+fn router(state: Arc<ApiState>) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/message", post(message))
+        .route("/presence", post(presence))
+        .route("/events", get(events))
+        .with_state(state)
+}
+
+/// Hide the window and start the control API. Called from the Tauri
+/// `setup` hook.
+pub fn start(app: &mut tauri::App, options: Options) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.hide();
+    }
+    #[cfg(target_os = "macos")]
+    app.set_activation_policy(tauri::ActivationPolicy::Accessory);
+
+    let events = Arc::new(EventLog::new());
+    tap::register(events.clone());
+    let state = Arc::new(ApiState {
+        token: options.token.clone(),
+        events,
+    });
+    tauri::async_runtime::spawn(async move {
+        let address = std::net::SocketAddr::from(([127, 0, 0, 1], options.port));
+        let listener = match tokio::net::TcpListener::bind(address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Headless: cannot listen on {address}: {e}");
+                warn!(error = %e, "headless: control API not started");
+                return;
+            }
+        };
+        let port = listener.local_addr().map_or(options.port, |a| a.port());
+        println!("Headless control API: http://127.0.0.1:{port}");
+        println!("Token: {}", options.token);
+        info!(port, "headless: control API listening");
+        let _ = axum::serve(listener, router(state)).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_inbound_messages_and_presence_in_order() {
+        let log = EventLog::new();
+        let chat = Element::parse(
+            "<message from='a@example.org/x' type='chat' id='m1'><body>hi</body></message>",
+        )
+        .unwrap();
+        let away = Element::parse(
+            "<presence from='b@example.org/y'><show>away</show><status>lunch</status></presence>",
+        )
+        .unwrap();
+        let typing = Element::parse(
+            "<message from='a@x.org'><gone xmlns='http://jabber.org/protocol/chatstates'/></message>",
+        )
+        .unwrap();
+        for stanza in [&chat, &typing, &away] {
+            if let Some(kind) = event_for(stanza) {
+                log.push(kind);
+            }
+        }
+        let events = log.after(0);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0].kind, EventKind::Message { body, .. } if body == "hi"));
+        assert_eq!(
+            events[1].kind,
+            EventKind::Presence {
+                from: "b@example.org/y".to_string(),
+                presence_type: None,
+                show: Some("away".to_string()),
+                status: Some("lunch".to_string()),
+            }
+        );
+        assert_eq!(log.after(events[0].seq).len(), 1);
+        for _ in 0..EVENT_BUFFER {
+            log.push(events[1].kind.clone());
+        }
+        assert_eq!(log.after(0).len(), EVENT_BUFFER);
+    }
+}
@@ -0,0 +1,341 @@
+//! Pre-authenticated invites (XEP-0401).
+//!
+//! [`create_invite`] asks our server for an invite link through its
+//! `urn:xmpp:invite#invite` ad-hoc command. The resulting URI lets the
+//! recipient add us as a contact and, when the server allows it, create an
+//! account with the embedded token.
+//!
+//! In the other direction, invite URIs (`xmpp:example.com?register;preauth=…`
+//! and `xmpp:romeo@example.com?roster;preauth=…`) opened through the `xmpp:`
+//! scheme are recognized natively and checked before anything is shown: the
+//! domain must look like a hostname and the token must be a plausible
+//! URI-safe token. A valid invite is handed to the frontend as an
+//! `invite-link` event, or through [`take_pending_invite`] when it arrived
+//! before the UI was listening, so it can prompt for account creation. The
+//! token itself can only be redeemed by the server during registration
+//! (XEP-0445), on a stream of its own.
+
+use crate::adhoc::{self, AdhocStage};
+use crate::dataforms::DataForm;
+use crate::xmpp_proxy::session;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::Emitter;
+
+const INVITE_NODE: &str = "urn:xmpp:invite#invite";
+const INVITE_EVENT: &str = "invite-link";
+
+/// Form fields servers use for the invite lifetime (seconds) and for
+/// invites that can be redeemed more than once.
+const TTL_FIELDS: [&str; 2] = ["ttl", "expires"];
+const REUSABLE_FIELDS: [&str; 2] = ["reusable", "multi-use"];
+
+/// Tokens are random strings of a few dozen characters. Bounds reject the
+/// obviously wrong without tying us to one server's format.
+const MIN_TOKEN_LEN: usize = 8;
+const MAX_TOKEN_LEN: usize = 256;
+
+/// Latest invite opened before the UI took it.
+static PENDING_INVITE: Mutex<Option<InviteLink>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invite {
+    pub uri: String,
+    pub landing_url: Option<String>,
+    /// As given by the server (XEP-0082 date-time).
+    pub expires: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InviteKind {
+    /// `?register`: create an account on `domain`.
+    Account,
+    /// `?roster`: add the inviter as a contact.
+    Contact,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteLink {
+    pub kind: InviteKind,
+    /// Server the account would be created on.
+    pub domain: String,
+    /// Username reserved by an account invite, or the inviter of a contact
+    /// invite (local part only; the domain is `domain`).
+    pub username: Option<String>,
+    pub token: String,
+    /// Whether the token may be used to register an account.
+    pub can_register: bool,
+    /// Display name of the inviter, when given.
+    pub name: Option<String>,
+}
+
+fn percent_decode(raw: &str) -> Option<String> {
+    let bytes = raw.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = raw.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn valid_domain(domain: &str) -> bool {
+    !domain.is_empty()
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+fn valid_token(token: &str) -> bool {
+    (MIN_TOKEN_LEN..=MAX_TOKEN_LEN).contains(&token.len())
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~'))
+}
+
+/// Parse an invite URI. `Ok(None)` for an `xmpp:` URI that is not an invite;
+/// `Err` for an invite that fails validation.
+pub fn parse_invite_uri(uri: &str) -> Result<Option<InviteLink>, String> {
+    let Some(rest) = uri.trim().strip_prefix("xmpp:") else {
+        return Ok(None);
+    };
+    let Some((path, query)) = rest.split_once('?') else {
+        return Ok(None);
+    };
+    let mut parts = query.split(';');
+    let kind = match parts.next() {
+        Some("register") => InviteKind::Account,
+        Some("roster") => InviteKind::Contact,
+        _ => return Ok(None),
+    };
+    let mut params = HashMap::new();
+    for part in parts {
+        let (key, value) = part.split_once('=').unwrap_or((part, ""));
+        let value = percent_decode(value).ok_or("Invite link is malformed")?;
+        params.insert(key.to_string(), value);
+    }
+    let Some(token) = params.remove("preauth") else {
+        return Ok(None);
+    };
+    if !valid_token(&token) {
+        return Err("Invite token is malformed".to_string());
+    }
+    let jid = percent_decode(path).ok_or("Invite link is malformed")?;
+    let (username, domain) = match jid.split_once('@') {
+        Some((local, domain)) => (Some(local.to_string()), domain),
+        None => (None, jid.as_str()),
+    };
+    let domain = domain.split('/').next().unwrap_or_default().to_lowercase();
+    if !valid_domain(&domain) || username.as_deref().is_some_and(str::is_empty) {
+        return Err(format!("Invite link names an invalid server: {domain}"));
+    }
+    if kind == InviteKind::Contact && username.is_none() {
+        return Err("Contact invite has no inviter".to_string());
+    }
+    Ok(Some(InviteLink {
+        kind,
+        domain,
+        username,
+        token,
+        can_register: kind == InviteKind::Account
+            || params.get("ibr").is_some_and(|ibr| ibr == "y"),
+        name: params.remove("name").filter(|name| !name.is_empty()),
+    }))
+}
+
+/// Handle URIs opened through the `xmpp:` scheme. Non-invite URIs are left
+/// to the frontend's deep-link handler. Called from the deep-link plugin.
+pub fn handle_opened_urls(app: &tauri::AppHandle, urls: &[String]) {
+    for url in urls {
+        match parse_invite_uri(url) {
+            Ok(Some(invite)) => {
+                tracing::info!(domain = %invite.domain, kind = ?invite.kind, "Invite link opened");
+                *PENDING_INVITE.lock().unwrap_or_else(|e| e.into_inner()) = Some(invite.clone());
+                let _ = app.emit(INVITE_EVENT, invite);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(error = %e, "Ignoring invalid invite link");
+                let _ = app.emit(INVITE_EVENT, serde_json::json!({ "error": e }));
+            }
+        }
+    }
+}
+
+/// Take the invite opened before the UI was ready, if any.
+#[tauri::command]
+pub fn take_pending_invite() -> Option<InviteLink> {
+    PENDING_INVITE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+}
+
+/// Validate an invite URI the user pasted.
+#[tauri::command]
+pub fn check_invite_uri(uri: String) -> Result<InviteLink, String> {
+    parse_invite_uri(&uri)?.ok_or_else(|| "Not an invite link".to_string())
+}
+
+/// Fill in the options the server's form offers. Returns whether reuse was
+/// offered.
+fn fill_options(form: &mut DataForm, ttl: Option<u64>, multi_use: bool) -> bool {
+    let mut reusable_offered = false;
+    for field in &mut form.fields {
+        let Some(var) = field.var.as_deref() else {
+            continue;
+        };
+        if let Some(ttl) = ttl.filter(|_| TTL_FIELDS.contains(&var)) {
+            field.values = vec![ttl.to_string()];
+        } else if REUSABLE_FIELDS.contains(&var) {
+            reusable_offered = true;
+            field.values = vec![if multi_use { "1" } else { "0" }.to_string()];
+        }
+    }
+    reusable_offered
+}
+
+fn invite_from(stage: &AdhocStage) -> Result<Invite, String> {
+    let form = stage.form.as_ref().ok_or("The server returned no invite")?;
+    let value = |var: &str| {
+        form.fields
+            .iter()
+            .find(|f| f.var.as_deref() == Some(var))
+            .and_then(|f| f.values.first().cloned())
+    };
+    Ok(Invite {
+        uri: value("uri").ok_or("The server returned no invite URI")?,
+        landing_url: value("landing-url"),
+        expires: value("expire"),
+    })
+}
+
+/// Create an invite on our server. `expires_in` is the lifetime in seconds
+/// (server default when `None`); `multi_use` asks for a token several people
+/// can redeem, which not every server offers.
+#[tauri::command]
+pub async fn create_invite(expires_in: Option<u64>, multi_use: bool) -> Result<Invite, String> {
+    let server = session::own_bare_jid()
+        .and_then(|jid| jid.split_once('@').map(|(_, domain)| domain.to_string()))
+        .ok_or("Not connected")?;
+    let start = adhoc::command_request(&server, INVITE_NODE, None, "execute", None);
+    let mut stage = adhoc::parse_stage(&session::request(start).await?)?;
+    if stage.status == "executing" {
+        let mut form = stage.form.clone().unwrap_or_default();
+        if !fill_options(&mut form, expires_in, multi_use) && multi_use {
+            let cancel = adhoc::command_request(
+                &server,
+                INVITE_NODE,
+                stage.session_id.as_deref(),
+                "cancel",
+                None,
+            );
+            let _ = session::request(cancel).await;
+            return Err("The server does not offer reusable invites".to_string());
+        }
+        let action = stage
+            .default_action
+            .clone()
+            .unwrap_or_else(|| "complete".to_string());
+        let submit = adhoc::command_request(
+            &server,
+            INVITE_NODE,
+            stage.session_id.as_deref(),
+            &action,
+            Some(&form),
+        );
+        stage = adhoc::parse_stage(&session::request(submit).await?)?;
+    } else if multi_use {
+        return Err("The server does not offer reusable invites".to_string());
+    }
+    if let Some(error) = stage.notes.iter().find(|n| n.kind == "error") {
+        return Err(error.text.clone());
+    }
+    invite_from(&stage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_account_and_contact_invites() {
+        let account = parse_invite_uri("xmpp:example.com?register;preauth=EmzPN2cO4kbk6bcE")
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.kind, InviteKind::Account);
+        assert_eq!(account.domain, "example.com");
+        assert_eq!(account.username, None);
+        assert!(account.can_register);
+
+        let contact = parse_invite_uri(
+            "xmpp:romeo@Example.com?roster;preauth=EmzPN2cO4kbk6bcE;ibr=y;name=Romeo%20M.",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(contact.kind, InviteKind::Contact);
+        assert_eq!(contact.domain, "example.com");
+        assert_eq!(contact.username.as_deref(), Some("romeo"));
+        assert_eq!(contact.name.as_deref(), Some("Romeo M."));
+        assert!(contact.can_register);
+
+        // Not invites: left to the regular deep-link handler.
+        assert_eq!(parse_invite_uri("xmpp:romeo@example.com?message"), Ok(None));
+        assert_eq!(parse_invite_uri("xmpp:romeo@example.com?roster"), Ok(None));
+
+        assert!(parse_invite_uri("xmpp:example.com?register;preauth=short").is_err());
+        assert!(parse_invite_uri("xmpp:exa mple.com?register;preauth=EmzPN2cO4kbk6bcE").is_err());
+        assert!(parse_invite_uri("xmpp:example.com?roster;preauth=EmzPN2cO4kbk6bcE").is_err());
+        assert!(parse_invite_uri("xmpp:example.com?register;preauth=%ZZ").is_err());
+    }
+
+    #[test]
+    fn fills_offered_options_and_reads_the_result() {
+        let mut form = DataForm::from_element(
+            &crate::xmpp_proxy::stanza::Element::parse(
+                "<x xmlns='jabber:x:data' type='form'>\
+                 <field var='ttl' type='text-single'/>\
+                 <field var='note' type='text-single'/></x>",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(!fill_options(&mut form, Some(3600), true));
+        assert_eq!(form.fields[0].values, ["3600"]);
+        assert!(form.fields[1].values.is_empty());
+
+        let result = adhoc::parse_stage(
+            &crate::xmpp_proxy::stanza::Element::parse(
+                "<iq type='result'><command xmlns='http://jabber.org/protocol/commands' \
+                 node='urn:xmpp:invite#invite' status='completed'>\
+                 <x xmlns='jabber:x:data' type='result'>\
+                 <field var='uri'>\
+                 <value>xmpp:juliet@example.com?roster;preauth=abcdefgh;ibr=y</value></field>\
+                 <field var='expire'><value>2026-10-22T12:00:00Z</value></field>\
+                 </x></command></iq>",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let invite = invite_from(&result).unwrap();
+        assert!(invite.uri.starts_with("xmpp:juliet@example.com?roster"));
+        assert_eq!(invite.expires.as_deref(), Some("2026-10-22T12:00:00Z"));
+        assert_eq!(invite.landing_url, None);
+    }
+}
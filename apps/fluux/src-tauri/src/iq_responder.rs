@@ -0,0 +1,196 @@
+//! Native answers to pings (XEP-0199) and software-version queries
+//! (XEP-0092).
+//!
+//! Servers that ping their clients drop a session whose pings go
+//! unanswered, and a WebView suspended in the background can't answer
+//! anything. The bridge answers both kinds of query itself, so the session
+//! survives however throttled the page is. Queries go to the WebView as
+//! before when the responder is switched off, or while no session is bound
+//! natively.
+//!
+//! With do-not-track mode on, version queries get `service-unavailable`,
+//! the same answer the client's own would be rewritten to.
+
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::stanza::Element;
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use crate::xmpp_proxy::{privacy, session};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info};
+
+const PING_NS: &str = "urn:xmpp:ping";
+const VERSION_NS: &str = "jabber:iq:version";
+const STANZAS_NS: &str = "urn:ietf:params:xml:ns:xmpp-stanzas";
+
+#[derive(Serialize, Deserialize)]
+struct Settings {
+    enabled: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Responder state, registered with the bridge tap and held in managed state
+/// as `Arc<IqResponder>`.
+pub struct IqResponder {
+    enabled: AtomicBool,
+    settings: Option<JsonFile>,
+    /// Held while the setting changes, so the last change is also the last
+    /// saved.
+    toggle: Mutex<()>,
+}
+
+impl IqResponder {
+    /// Load the setting from `dir`. Answering natively is on by default.
+    pub fn load(dir: Option<PathBuf>) -> Self {
+        let settings_path = dir.map(|d| d.join("iq-responder.json"));
+        let settings: Settings = settings_path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self {
+            enabled: AtomicBool::new(settings.enabled),
+            settings: settings_path.map(JsonFile::new),
+            toggle: Mutex::new(()),
+        }
+    }
+
+    /// Called with `toggle` held; see [`crate::json_file`].
+    fn persist_settings(&self, settings: Settings) {
+        if let Some(file) = &self.settings {
+            file.save(settings);
+        }
+    }
+}
+
+/// The answer to `request` when it is a ping or version query addressed to
+/// our own client.
+fn answer_for(request: &Element, own_jid: &str, hide_version: bool) -> Option<Element> {
+    if request.local_name() != "iq" || request.attr("type") != Some("get") {
+        return None;
+    }
+    // No `to` means the server itself is asking on behalf of our account.
+    if request.attr("to").is_some_and(|to| to != own_jid) {
+        return None;
+    }
+    let id = request.attr("id")?;
+    let mut answer = Element::new("iq").with_attr("id", id);
+    if let Some(from) = request.attr("from") {
+        answer.set_attr("to", from);
+    }
+    if request.child("ping", Some(PING_NS)).is_some() {
+        answer.set_attr("type", "result");
+    } else if request.child("query", Some(VERSION_NS)).is_some() {
+        if hide_version {
+            answer.set_attr("type", "error");
+            answer = answer.with_child(
+                Element::new("error")
+                    .with_attr("type", "cancel")
+                    .with_child(Element::new("service-unavailable").with_attr("xmlns", STANZAS_NS)),
+            );
+        } else {
+            answer.set_attr("type", "result");
+            answer = answer.with_child(
+                Element::new("query")
+                    .with_attr("xmlns", VERSION_NS)
+                    .with_child(Element::new("name").with_text("Fluux Messenger"))
+                    .with_child(Element::new("version").with_text(env!("CARGO_PKG_VERSION")))
+                    .with_child(Element::new("os").with_text(std::env::consts::OS)),
+            );
+        }
+    } else {
+        return None;
+    }
+    Some(answer)
+}
+
+impl StanzaObserver for IqResponder {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction != Direction::Inbound || !self.enabled.load(Ordering::Relaxed) {
+            return Verdict::Forward;
+        }
+        let Some(own_jid) = session::own_jid() else {
+            return Verdict::Forward;
+        };
+        let Some(answer) = answer_for(stanza, &own_jid, privacy::enabled()) else {
+            return Verdict::Forward;
+        };
+        match session::send(answer) {
+            Ok(()) => Verdict::Drop,
+            Err(e) => {
+                // Not bound natively yet: the client answers as it always did.
+                debug!(error = %e, "iq responder: leaving query to the client");
+                Verdict::Forward
+            }
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_native_iq_responder(responder: tauri::State<'_, Arc<IqResponder>>) -> bool {
+    responder.enabled.load(Ordering::Relaxed)
+}
+
+/// Choose whether the bridge answers pings and version queries itself.
+#[tauri::command]
+pub fn set_native_iq_responder(responder: tauri::State<'_, Arc<IqResponder>>, enabled: bool) {
+    let _toggle = responder.toggle.lock().unwrap_or_else(|e| e.into_inner());
+    responder.enabled.store(enabled, Ordering::Relaxed);
+    responder.persist_settings(Settings { enabled });
+    info!(enabled, "Native ping/version responder toggled");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWN: &str = "juliet@example.com/balcony";
+
+    fn answer(xml: &str, hide_version: bool) -> Option<Element> {
+        answer_for(&Element::parse(xml).unwrap(), OWN, hide_version)
+    }
+
+    #[test]
+    fn answers_pings_and_version_queries_for_our_client() {
+        let pong = answer(
+            "<iq type='get' from='example.com' to='juliet@example.com/balcony' id='p1'>\
+             <ping xmlns='urn:xmpp:ping'/></iq>",
+            false,
+        )
+        .unwrap();
+        assert_eq!(pong.attr("type"), Some("result"));
+        assert_eq!(pong.attr("to"), Some("example.com"));
+        assert_eq!(pong.attr("id"), Some("p1"));
+
+        let version_query = "<iq type='get' from='romeo@example.net/a' id='v1'>\
+                             <query xmlns='jabber:iq:version'/></iq>";
+        let version = answer(version_query, false).unwrap();
+        let query = version.child("query", Some(VERSION_NS)).unwrap();
+        assert_eq!(
+            query.child("version", None).unwrap().text(),
+            env!("CARGO_PKG_VERSION")
+        );
+        let hidden = answer(version_query, true).unwrap();
+        assert_eq!(hidden.attr("type"), Some("error"));
+
+        // Addressed to another resource, or not a query we answer.
+        assert!(answer(
+            "<iq type='get' to='juliet@example.com/other' id='p2'>\
+             <ping xmlns='urn:xmpp:ping'/></iq>",
+            false
+        )
+        .is_none());
+        assert!(answer(
+            "<iq type='get' id='t1'><time xmlns='urn:xmpp:time'/></iq>",
+            false
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,1190 @@
+//! Jingle file transfer (XEP-0234) sessions, run natively.
+//!
+//! `offer_file(jid, path)` offers a file to a full JID and drives the session
+//! to its end; an incoming offer is announced as a `fluux://jingle-file-offer`
+//! event and answered with `accept_file(session_id, dest)` or
+//! `decline_file(session_id)`. Every change of a session's state is emitted
+//! as `fluux://jingle-file-state` (`{sessionId, state, bytes, error}`), and
+//! transfer progress as the `fluux://bytestream-progress` events of
+//! `bytestreams`, keyed by the session id.
+//!
+//! Transports:
+//! - SOCKS5 bytestreams (XEP-0260) through the server's proxies, the
+//!   default when it has any. We offer only proxy candidates and connect to
+//!   any candidate the initiator offers.
+//! - In-band bytestreams (XEP-0261), offered when there is no proxy and
+//!   used as the fallback (`transport-replace`) when no candidate works.
+//! - A WebRTC data channel (XEP-0343) offer is answered with a
+//!   `transport-replace` to IBB: there is no native WebRTC stack.
+//!
+//! The file's hash (XEP-0300) is sent with the offer and checked on arrival;
+//! one announced later in a `checksum` session-info is checked too. When the
+//! destination already holds the start of the offered file, the responder
+//! asks for the rest only (`<range offset=…/>`), so an interrupted transfer
+//! resumes where it stopped.
+
+use crate::bytestreams::{self, Bytestreams, Reporter, StreamHost};
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::Element;
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+const JINGLE_NS: &str = "urn:xmpp:jingle:1";
+const FT_NS: &str = "urn:xmpp:jingle:apps:file-transfer:5";
+const S5B_NS: &str = "urn:xmpp:jingle:transports:s5b:1";
+const IBB_NS: &str = "urn:xmpp:jingle:transports:ibb:1";
+const DATACHANNEL_NS: &str = "urn:xmpp:jingle:transports:webrtc-datachannel:1";
+const HASHES_NS: &str = "urn:xmpp:hashes:2";
+
+const OFFER_EVENT: &str = "fluux://jingle-file-offer";
+const STATE_EVENT: &str = "fluux://jingle-file-state";
+
+/// How long the peer's user has to accept an offer.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(600);
+/// How long each negotiation step may take once both sides are engaged.
+const STEP_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time allowed for a `checksum` session-info after the bytes arrived.
+const CHECKSUM_GRACE: Duration = Duration::from_secs(10);
+const IBB_BLOCK_SIZE: &str = "4096";
+/// XEP-0260 type preference of proxy candidates, in the high bits of the
+/// priority.
+const PROXY_PRIORITY: u32 = 10 << 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileHash {
+    /// Hash function name as in XEP-0300 (`sha-256`, …).
+    pub algo: String,
+    /// Base64 digest.
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDescription {
+    pub name: String,
+    pub size: Option<u64>,
+    pub media_type: Option<String>,
+    pub hash: Option<FileHash>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct OfferEvent<'a> {
+    session_id: &'a str,
+    from: &'a str,
+    file: &'a FileDescription,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StateEvent<'a> {
+    session_id: &'a str,
+    /// `pending`, `transferring`, `done`, `declined`, `cancelled` or
+    /// `failed`.
+    state: &'a str,
+    bytes: Option<u64>,
+    error: Option<String>,
+}
+
+/// How a session ended early.
+#[derive(Debug)]
+enum End {
+    /// The peer terminated it, with this reason.
+    Peer(String),
+    /// We end it, telling the peer this reason condition.
+    Local(&'static str, String),
+}
+
+impl End {
+    fn local(condition: &'static str, message: impl Into<String>) -> Self {
+        End::Local(condition, message.into())
+    }
+}
+
+struct Session {
+    peer: String,
+    /// Jingle requests from the peer, already acknowledged.
+    inbox: mpsc::UnboundedSender<Element>,
+    /// Responder only: the user's answer (`None` declines).
+    answer: Option<oneshot::Sender<Option<PathBuf>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Session registry, registered with the bridge tap and held in managed
+/// state as `Arc<JingleFiles>`.
+pub struct JingleFiles {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    bytestreams: Arc<Bytestreams>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn emit_state(app: &tauri::AppHandle, sid: &str, result: Result<u64, &End>) {
+    let (state, bytes, error) = match result {
+        Ok(bytes) => ("done", Some(bytes), None),
+        Err(End::Peer(reason)) if reason == "decline" => ("declined", None, None),
+        Err(End::Peer(reason)) if reason == "cancel" => ("cancelled", None, None),
+        Err(End::Local("cancel", _)) => ("cancelled", None, None),
+        Err(End::Local("decline", _)) => ("declined", None, None),
+        Err(End::Peer(reason)) => ("failed", None, Some(format!("Peer ended: {reason}"))),
+        Err(End::Local(_, message)) => ("failed", None, Some(message.clone())),
+    };
+    let _ = app.emit(
+        STATE_EVENT,
+        StateEvent {
+            session_id: sid,
+            state,
+            bytes,
+            error,
+        },
+    );
+}
+
+fn emit_progress_state(app: &tauri::AppHandle, sid: &str, state: &str) {
+    let _ = app.emit(
+        STATE_EVENT,
+        StateEvent {
+            session_id: sid,
+            state,
+            bytes: None,
+            error: None,
+        },
+    );
+}
+
+fn jingle(action: &str, sid: &str) -> Element {
+    Element::new("jingle")
+        .with_attr("xmlns", JINGLE_NS)
+        .with_attr("action", action)
+        .with_attr("sid", sid)
+}
+
+fn content(name: &str) -> Element {
+    Element::new("content")
+        .with_attr("creator", "initiator")
+        .with_attr("name", name)
+}
+
+async fn send_jingle(to: &str, jingle: Element) -> Result<(), End> {
+    let iq = Element::new("iq")
+        .with_attr("type", "set")
+        .with_attr("to", to)
+        .with_child(jingle);
+    session::request(iq)
+        .await
+        .map(|_| ())
+        .map_err(|e| End::local("connectivity-error", e))
+}
+
+/// Tell the peer the session is over. Failures are only logged: the
+/// session ends either way.
+async fn terminate(to: &str, sid: &str, condition: &str) {
+    let reason = Element::new("reason").with_child(Element::new(condition));
+    if let Err(e) = send_jingle(to, jingle("session-terminate", sid).with_child(reason)).await {
+        debug!(sid, error = ?e, "jingle ft: terminate not acknowledged");
+    }
+}
+
+fn parse_hash(parent: &Element) -> Option<FileHash> {
+    parent
+        .elements()
+        .find(|e| e.local_name() == "hash" && e.ns() == Some(HASHES_NS))
+        .map(|hash| FileHash {
+            algo: hash.attr("algo").unwrap_or("sha-256").to_string(),
+            value: hash.text().trim().to_string(),
+        })
+}
+
+fn parse_file(file: &Element) -> FileDescription {
+    let text = |name: &str| {
+        file.child(name, None)
+            .map(|e| e.text().trim().to_string())
+            .filter(|t| !t.is_empty())
+    };
+    FileDescription {
+        name: text("name").unwrap_or_else(|| "file".to_string()),
+        size: text("size").and_then(|s| s.parse().ok()),
+        media_type: text("media-type"),
+        hash: parse_hash(file),
+    }
+}
+
+fn file_element(file: &FileDescription) -> Element {
+    let mut element = Element::new("file").with_child(Element::new("name").with_text(&file.name));
+    if let Some(size) = file.size {
+        element = element.with_child(Element::new("size").with_text(&size.to_string()));
+    }
+    if let Some(media_type) = &file.media_type {
+        element = element.with_child(Element::new("media-type").with_text(media_type));
+    }
+    if let Some(hash) = &file.hash {
+        element = element.with_child(
+            Element::new("hash")
+                .with_attr("xmlns", HASHES_NS)
+                .with_attr("algo", &hash.algo)
+                .with_text(&hash.value),
+        );
+    }
+    element
+}
+
+fn description(file: Element) -> Element {
+    Element::new("description")
+        .with_attr("xmlns", FT_NS)
+        .with_child(file)
+}
+
+fn ibb_transport(sid: &str) -> Element {
+    Element::new("transport")
+        .with_attr("xmlns", IBB_NS)
+        .with_attr("sid", sid)
+        .with_attr("block-size", IBB_BLOCK_SIZE)
+}
+
+fn s5b_transport(sid: &str, candidates: &[StreamHost]) -> Element {
+    let mut transport = Element::new("transport")
+        .with_attr("xmlns", S5B_NS)
+        .with_attr("sid", sid)
+        .with_attr("mode", "tcp");
+    for (i, host) in candidates.iter().enumerate() {
+        transport = transport.with_child(
+            Element::new("candidate")
+                .with_attr("cid", &format!("proxy-{i}"))
+                .with_attr("host", &host.host)
+                .with_attr("jid", &host.jid)
+                .with_attr("port", &host.port.to_string())
+                .with_attr("priority", &(PROXY_PRIORITY - i as u32).to_string())
+                .with_attr("type", "proxy"),
+        );
+    }
+    transport
+}
+
+/// A peer's S5B candidates, best first, with their cid and whether they
+/// are proxies.
+fn candidates(transport: &Element) -> Vec<(String, StreamHost, bool)> {
+    let mut found: Vec<(u32, String, StreamHost, bool)> = transport
+        .elements()
+        .filter(|e| e.local_name() == "candidate")
+        .filter_map(|c| {
+            let host = StreamHost {
+                jid: c.attr("jid")?.to_string(),
+                host: c.attr("host")?.to_string(),
+                port: c.attr("port").map_or(Some(1080), |p| p.parse().ok())?,
+            };
+            let priority = c.attr("priority").and_then(|p| p.parse().ok()).unwrap_or(0);
+            Some((
+                priority,
+                c.attr("cid")?.to_string(),
+                host,
+                c.attr("type") == Some("proxy"),
+            ))
+        })
+        .collect();
+    found.sort_by(|a, b| b.0.cmp(&a.0));
+    found
+        .into_iter()
+        .map(|(_, cid, host, proxy)| (cid, host, proxy))
+        .collect()
+}
+
+fn transport_info(sid: &str, content_name: &str, transport_sid: &str, child: Element) -> Element {
+    jingle("transport-info", sid).with_child(
+        content(content_name).with_child(
+            Element::new("transport")
+                .with_attr("xmlns", S5B_NS)
+                .with_attr("sid", transport_sid)
+                .with_child(child),
+        ),
+    )
+}
+
+/// Digest of `path` with the XEP-0300 function `algo`, base64. `None` for a
+/// function we don't implement.
+async fn file_hash(path: PathBuf, algo: String) -> Result<Option<String>, String> {
+    fn digest<D: Digest>(mut file: std::fs::File) -> std::io::Result<Vec<u8>> {
+        let mut hasher = D::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                return Ok(hasher.finalize().to_vec());
+            }
+            hasher.update(&buf[..read]);
+        }
+    }
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+        let bytes = match algo.as_str() {
+            "sha-256" => digest::<Sha256>(file),
+            "sha-512" => digest::<Sha512>(file),
+            _ => return Ok(None),
+        };
+        bytes
+            .map(|bytes| Some(BASE64.encode(bytes)))
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// The peer's side of the session, as it arrives.
+struct Inbox {
+    rx: mpsc::UnboundedReceiver<Element>,
+    /// Hash from a `checksum` session-info.
+    checksum: Option<FileHash>,
+}
+
+impl Inbox {
+    /// Wait for one of the `wanted` actions. A `session-terminate` (or a
+    /// local cancel) ends the session instead; anything else is skipped.
+    async fn next(&mut self, wanted: &[&str], timeout: Duration) -> Result<Element, End> {
+        self.wait(wanted, Some(tokio::time::Instant::now() + timeout))
+            .await
+    }
+
+    /// Wait, however long it takes, for the session to end.
+    async fn ended(&mut self) -> End {
+        match self.wait(&[], None).await {
+            Err(end) => end,
+            Ok(_) => End::local("general-error", "Session ended"),
+        }
+    }
+
+    async fn wait(
+        &mut self,
+        wanted: &[&str],
+        deadline: Option<tokio::time::Instant>,
+    ) -> Result<Element, End> {
+        loop {
+            let received = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, self.rx.recv())
+                    .await
+                    .ok()
+                    .flatten(),
+                None => self.rx.recv().await,
+            };
+            let Some(jingle) = received else {
+                return Err(End::local("timeout", "The peer stopped answering"));
+            };
+            if jingle.local_name() == "cancel" {
+                return Err(End::local("cancel", "Cancelled"));
+            }
+            let action = jingle.attr("action").unwrap_or_default();
+            if action == "session-terminate" {
+                let reason = jingle
+                    .child("reason", None)
+                    .and_then(|r| r.elements().find(|e| e.local_name() != "text"))
+                    .map_or("unknown", |r| r.local_name())
+                    .to_string();
+                return Err(End::Peer(reason));
+            }
+            if action == "session-info" {
+                if let Some(file) = jingle
+                    .child("checksum", Some(FT_NS))
+                    .and_then(|c| c.child("file", None))
+                {
+                    self.checksum = parse_hash(file);
+                }
+            }
+            if wanted.contains(&action) {
+                return Ok(jingle);
+            }
+            debug!(action, "jingle ft: skipping unexpected action");
+        }
+    }
+}
+
+impl JingleFiles {
+    pub fn new(bytestreams: Arc<Bytestreams>) -> Self {
+        Self {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            bytestreams,
+        }
+    }
+
+    fn register(&self, sid: &str, peer: &str) -> (Inbox, Arc<AtomicBool>) {
+        let (inbox, rx) = mpsc::unbounded_channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        lock(&self.sessions).insert(
+            sid.to_string(),
+            Session {
+                peer: peer.to_string(),
+                inbox,
+                answer: None,
+                cancelled: cancelled.clone(),
+            },
+        );
+        (Inbox { rx, checksum: None }, cancelled)
+    }
+
+    /// Route a Jingle request: a file offer opens a session, anything for a
+    /// known session goes to its task. `false` leaves it to the WebView
+    /// (calls, unknown sessions).
+    fn handle(&self, iq: &Element, jingle: &Element, app: Option<&tauri::AppHandle>) -> bool {
+        let (Some(sid), Some(from)) = (jingle.attr("sid"), iq.attr("from")) else {
+            return false;
+        };
+        let action = jingle.attr("action").unwrap_or_default();
+        if action == "session-initiate" {
+            return self.handle_offer(iq, jingle, sid, from, app);
+        }
+        let sessions = lock(&self.sessions);
+        let Some(session) = sessions.get(sid).filter(|s| s.peer == from) else {
+            return false;
+        };
+        let _ = session::send(ack(iq));
+        let _ = session.inbox.send(jingle.clone());
+        true
+    }
+
+    fn handle_offer(
+        &self,
+        iq: &Element,
+        jingle: &Element,
+        sid: &str,
+        from: &str,
+        app: Option<&tauri::AppHandle>,
+    ) -> bool {
+        let Some(content) = jingle.child("content", None) else {
+            return false;
+        };
+        // Offers only: a request (senders='responder') asks us for a file,
+        // which the WebView handles.
+        let Some(file) = content
+            .child("description", Some(FT_NS))
+            .filter(|_| content.attr("senders").unwrap_or("initiator") == "initiator")
+            .and_then(|d| d.child("file", None))
+        else {
+            return false;
+        };
+        let Some(app) = app.cloned() else {
+            return false;
+        };
+        if lock(&self.sessions).contains_key(sid) {
+            let _ = session::send(iq_error(iq, "cancel", "conflict"));
+            return true;
+        }
+        let file = parse_file(file);
+        let (inbox, cancelled) = self.register(sid, from);
+        let (answer, answer_rx) = oneshot::channel();
+        if let Some(session) = lock(&self.sessions).get_mut(sid) {
+            session.answer = Some(answer);
+        }
+        let _ = session::send(ack(iq));
+        info!(sid, from, name = %file.name, size = ?file.size, "jingle ft: file offered");
+        let _ = app.emit(
+            OFFER_EVENT,
+            OfferEvent {
+                session_id: sid,
+                from,
+                file: &file,
+            },
+        );
+
+        let responder = Responder {
+            sid: sid.to_string(),
+            peer: from.to_string(),
+            content: content.clone(),
+            file,
+            bytestreams: self.bytestreams.clone(),
+            cancelled,
+            app: app.clone(),
+        };
+        let sessions = self.sessions.clone();
+        let sid = sid.to_string();
+        tokio::spawn(async move {
+            let result = responder.run(answer_rx, inbox).await;
+            lock(&sessions).remove(&sid);
+            emit_state(&app, &sid, result.as_ref().copied());
+        });
+        true
+    }
+}
+
+fn ack(iq: &Element) -> Element {
+    let mut result = Element::new("iq").with_attr("type", "result");
+    if let Some(id) = iq.attr("id") {
+        result.set_attr("id", id);
+    }
+    if let Some(from) = iq.attr("from") {
+        result.set_attr("to", from);
+    }
+    result
+}
+
+fn iq_error(iq: &Element, kind: &str, condition: &str) -> Element {
+    ack(iq).with_attr("type", "error").with_child(
+        Element::new("error").with_attr("type", kind).with_child(
+            Element::new(condition).with_attr("xmlns", "urn:ietf:params:xml:ns:xmpp-stanzas"),
+        ),
+    )
+}
+
+/// Our side of a session someone offered us a file in.
+struct Responder {
+    sid: String,
+    peer: String,
+    /// The offer's `<content/>`.
+    content: Element,
+    file: FileDescription,
+    bytestreams: Arc<Bytestreams>,
+    cancelled: Arc<AtomicBool>,
+    app: tauri::AppHandle,
+}
+
+impl Responder {
+    async fn run(
+        &self,
+        answer: oneshot::Receiver<Option<PathBuf>>,
+        mut inbox: Inbox,
+    ) -> Result<u64, End> {
+        let result = self.answer(answer, &mut inbox).await;
+        match &result {
+            Ok(_) => terminate(&self.peer, &self.sid, "success").await,
+            Err(End::Local(condition, _)) => terminate(&self.peer, &self.sid, condition).await,
+            Err(End::Peer(_)) => {}
+        }
+        result
+    }
+
+    /// Wait for the user's answer, then receive the file if it is a yes.
+    async fn answer(
+        &self,
+        answer: oneshot::Receiver<Option<PathBuf>>,
+        inbox: &mut Inbox,
+    ) -> Result<u64, End> {
+        let answered = tokio::select! {
+            answered = answer => answered.ok().flatten(),
+            ended = inbox.next(&[], ANSWER_TIMEOUT) => {
+                return Err(ended.err().unwrap_or_else(|| End::local("timeout", "No answer")));
+            }
+        };
+        let Some(dest) = answered else {
+            return Err(End::local("decline", "Declined"));
+        };
+        self.receive(&dest, inbox).await
+    }
+
+    async fn receive(&self, dest: &Path, inbox: &mut Inbox) -> Result<u64, End> {
+        // Resume when the destination holds the start of this file.
+        let existing = tokio::fs::metadata(dest).await.map_or(0, |m| m.len());
+        let offset = match self.file.size {
+            Some(size) if existing > 0 && existing < size => existing,
+            _ => 0,
+        };
+        let remaining = self.file.size.map(|size| size - offset);
+        let name = self.content.attr("name").unwrap_or("file").to_string();
+        let mut accepted_file = file_element(&self.file);
+        if offset > 0 {
+            info!(sid = %self.sid, offset, "jingle ft: resuming");
+            accepted_file = accepted_file
+                .with_child(Element::new("range").with_attr("offset", &offset.to_string()));
+        }
+        let accept_content = |transport: Element| {
+            content(&name)
+                .with_child(description(accepted_file.clone()))
+                .with_child(transport)
+        };
+
+        let offered = self
+            .content
+            .child("transport", None)
+            .cloned()
+            .ok_or_else(|| End::local("failed-transport", "The offer has no transport"))?;
+        let received = match offered.ns() {
+            Some(S5B_NS) => {
+                let transport_sid = offered.attr("sid").unwrap_or(&self.sid).to_string();
+                let ours = s5b_transport(&transport_sid, &[]);
+                send_jingle(
+                    &self.peer,
+                    jingle("session-accept", &self.sid).with_child(accept_content(ours)),
+                )
+                .await?;
+                emit_progress_state(&self.app, &self.sid, "transferring");
+                self.receive_s5b(&offered, &name, dest, offset, remaining, inbox)
+                    .await?
+            }
+            Some(IBB_NS) => {
+                let transport_sid = offered.attr("sid").unwrap_or(&self.sid).to_string();
+                let done = self.expect_ibb(&transport_sid, dest, offset, remaining)?;
+                send_jingle(
+                    &self.peer,
+                    jingle("session-accept", &self.sid)
+                        .with_child(accept_content(ibb_transport(&transport_sid))),
+                )
+                .await?;
+                emit_progress_state(&self.app, &self.sid, "transferring");
+                self.await_ibb(&transport_sid, done, inbox).await?
+            }
+            // A data channel (or anything else): ask for IBB before accepting.
+            other => {
+                info!(sid = %self.sid, transport = ?other, "jingle ft: asking for IBB instead");
+                let transport_sid = format!("ibb-{}", uuid::Uuid::new_v4());
+                let done = self.expect_ibb(&transport_sid, dest, offset, remaining)?;
+                let replace = jingle("transport-replace", &self.sid)
+                    .with_child(content(&name).with_child(ibb_transport(&transport_sid)));
+                let replaced = async {
+                    send_jingle(&self.peer, replace).await?;
+                    inbox.next(&["transport-accept"], STEP_TIMEOUT).await?;
+                    send_jingle(
+                        &self.peer,
+                        jingle("session-accept", &self.sid)
+                            .with_child(accept_content(ibb_transport(&transport_sid))),
+                    )
+                    .await
+                }
+                .await;
+                if let Err(e) = replaced {
+                    self.bytestreams.unexpect(&transport_sid);
+                    return Err(e);
+                }
+                emit_progress_state(&self.app, &self.sid, "transferring");
+                self.await_ibb(&transport_sid, done, inbox).await?
+            }
+        };
+        self.verify(dest, inbox).await?;
+        let (path, source) = (dest.to_path_buf(), format!("xmpp:{}", self.peer));
+        tokio::task::spawn_blocking(move || crate::quarantine::mark_downloaded(&path, &source))
+            .await
+            .map_err(|e| End::local("media-error", e.to_string()))?
+            .map_err(|e| End::local("media-error", e))?;
+        Ok(received)
+    }
+
+    fn expect_ibb(
+        &self,
+        transport_sid: &str,
+        dest: &Path,
+        offset: u64,
+        remaining: Option<u64>,
+    ) -> Result<oneshot::Receiver<Result<u64, String>>, End> {
+        let (done, done_rx) = oneshot::channel();
+        let reporter = Reporter::new(Some(self.app.clone()), &self.sid, remaining).notifying(done);
+        self.bytestreams
+            .expect(
+                transport_sid,
+                &self.peer,
+                (dest.to_path_buf(), offset),
+                remaining,
+                self.cancelled.clone(),
+                reporter,
+            )
+            .map_err(|e| End::local("failed-transport", e))?;
+        Ok(done_rx)
+    }
+
+    async fn await_ibb(
+        &self,
+        transport_sid: &str,
+        done: oneshot::Receiver<Result<u64, String>>,
+        inbox: &mut Inbox,
+    ) -> Result<u64, End> {
+        // The transfer itself has no deadline; only the peer or a cancel
+        // can end the wait.
+        tokio::select! {
+            result = done => result
+                .map_err(|_| End::local("failed-transport", "Stream closed"))?
+                .map_err(|e| End::local("failed-transport", e)),
+            end = inbox.ended() => {
+                self.bytestreams.unexpect(transport_sid);
+                Err(end)
+            }
+        }
+    }
+
+    /// Connect to one of the initiator's candidates, then read the file.
+    /// Falls back to IBB when the initiator replaces the transport.
+    async fn receive_s5b(
+        &self,
+        offered: &Element,
+        content_name: &str,
+        dest: &Path,
+        offset: u64,
+        remaining: Option<u64>,
+        inbox: &mut Inbox,
+    ) -> Result<u64, End> {
+        let own_jid =
+            session::own_jid().ok_or_else(|| End::local("connectivity-error", "Not connected"))?;
+        let transport_sid = offered.attr("sid").unwrap_or(&self.sid).to_string();
+        let dst = bytestreams::dst_addr(&transport_sid, &self.peer, &own_jid);
+        let mut connected = None;
+        for (cid, host, proxy) in candidates(offered) {
+            match bytestreams::connect_streamhost(std::slice::from_ref(&host), &dst).await {
+                Ok((_, stream)) => {
+                    connected = Some((cid, stream, proxy));
+                    break;
+                }
+                Err(e) => {
+                    debug!(sid = %self.sid, cid = %cid, error = %e, "jingle ft: candidate failed")
+                }
+            }
+        }
+        let used = match &connected {
+            Some((cid, _, _)) => Element::new("candidate-used").with_attr("cid", cid),
+            None => Element::new("candidate-error"),
+        };
+        send_jingle(
+            &self.peer,
+            transport_info(&self.sid, content_name, &transport_sid, used),
+        )
+        .await?;
+
+        let Some((cid, mut stream, proxy)) = connected else {
+            // The initiator is expected to fall back to IBB.
+            let replace = inbox.next(&["transport-replace"], STEP_TIMEOUT).await?;
+            let ibb = replace
+                .child("content", None)
+                .and_then(|c| c.child("transport", Some(IBB_NS)))
+                .cloned()
+                .ok_or_else(|| End::local("failed-transport", "No usable transport"))?;
+            let ibb_sid = ibb.attr("sid").unwrap_or(&self.sid).to_string();
+            let done = self.expect_ibb(&ibb_sid, dest, offset, remaining)?;
+            send_jingle(
+                &self.peer,
+                jingle("transport-accept", &self.sid)
+                    .with_child(content(content_name).with_child(ibb_transport(&ibb_sid))),
+            )
+            .await?;
+            return self.await_ibb(&ibb_sid, done, inbox).await;
+        };
+        if proxy {
+            // Nothing flows through a proxy before the initiator activates it.
+            loop {
+                let info = inbox.next(&["transport-info"], STEP_TIMEOUT).await?;
+                let activated = info
+                    .child("content", None)
+                    .and_then(|c| c.child("transport", Some(S5B_NS)))
+                    .and_then(|t| t.child("activated", None))
+                    .is_some_and(|a| a.attr("cid") == Some(cid.as_str()));
+                if activated {
+                    break;
+                }
+            }
+        }
+        let (done, done_rx) = oneshot::channel();
+        let reporter = Reporter::new(Some(self.app.clone()), &self.sid, remaining).notifying(done);
+        bytestreams::receive_into(
+            &mut stream,
+            dest,
+            offset,
+            remaining,
+            &self.cancelled,
+            reporter,
+        )
+        .await;
+        done_rx
+            .await
+            .map_err(|_| End::local("failed-transport", "Stream closed"))?
+            .map_err(|e| End::local("failed-transport", e))
+    }
+
+    /// Check the received file against the offered hash, or one announced
+    /// shortly after the transfer.
+    async fn verify(&self, dest: &Path, inbox: &mut Inbox) -> Result<(), End> {
+        let mut expected = self.file.hash.clone().or_else(|| inbox.checksum.clone());
+        while expected.is_none() {
+            match inbox.next(&["session-info"], CHECKSUM_GRACE).await {
+                Ok(_) => expected = inbox.checksum.clone(),
+                Err(End::Local("timeout", _)) => break,
+                Err(end) => return Err(end),
+            }
+        }
+        let Some(expected) = expected else {
+            debug!(sid = %self.sid, "jingle ft: no hash to verify against");
+            return Ok(());
+        };
+        let actual = file_hash(dest.to_path_buf(), expected.algo.clone())
+            .await
+            .map_err(|e| End::local("media-error", e))?;
+        match actual {
+            Some(actual) if actual != expected.value => {
+                let _ = tokio::fs::remove_file(dest).await;
+                Err(End::local(
+                    "media-error",
+                    "The file does not match its hash",
+                ))
+            }
+            Some(_) => Ok(()),
+            None => {
+                warn!(sid = %self.sid, algo = %expected.algo, "jingle ft: unsupported hash");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Our side of a session we offered a file in.
+struct Initiator {
+    sid: String,
+    peer: String,
+    path: PathBuf,
+    file: FileDescription,
+    bytestreams: Arc<Bytestreams>,
+    cancelled: Arc<AtomicBool>,
+    app: tauri::AppHandle,
+}
+
+impl Initiator {
+    async fn run(&self, mut inbox: Inbox) -> Result<u64, End> {
+        let result = self.send(&mut inbox).await;
+        match &result {
+            // The responder ends a good transfer once it has checked it.
+            Ok(_) => {
+                if let Err(End::Local(..)) = inbox.next(&[], STEP_TIMEOUT).await {
+                    terminate(&self.peer, &self.sid, "success").await;
+                }
+            }
+            Err(End::Local(condition, _)) => terminate(&self.peer, &self.sid, condition).await,
+            Err(End::Peer(_)) => {}
+        }
+        result
+    }
+
+    async fn send(&self, inbox: &mut Inbox) -> Result<u64, End> {
+        let proxies = self.bytestreams.proxies(false).await.unwrap_or_default();
+        let transport_sid = uuid::Uuid::new_v4().to_string();
+        let transport = if proxies.is_empty() {
+            ibb_transport(&transport_sid)
+        } else {
+            s5b_transport(&transport_sid, &proxies)
+        };
+        let name = "file";
+        send_jingle(
+            &self.peer,
+            jingle("session-initiate", &self.sid)
+                .with_attr("initiator", &session::own_jid().unwrap_or_default())
+                .with_child(
+                    content(name)
+                        .with_attr("senders", "initiator")
+                        .with_child(description(file_element(&self.file)))
+                        .with_child(transport),
+                ),
+        )
+        .await?;
+        emit_progress_state(&self.app, &self.sid, "pending");
+
+        let accept = inbox.next(&["session-accept"], ANSWER_TIMEOUT).await?;
+        let accepted = accept.child("content", None);
+        let range = accepted
+            .and_then(|c| c.child("description", Some(FT_NS)))
+            .and_then(|d| d.child("file", None))
+            .and_then(|f| f.child("range", None));
+        let offset: u64 = range
+            .and_then(|r| r.attr("offset"))
+            .and_then(|o| o.parse().ok())
+            .unwrap_or(0);
+        let size = self.file.size.unwrap_or(0);
+        if offset > size {
+            return Err(End::local(
+                "failed-application",
+                "Range beyond the end of the file",
+            ));
+        }
+        let length = range
+            .and_then(|r| r.attr("length"))
+            .and_then(|l| l.parse::<u64>().ok())
+            .unwrap_or(size - offset)
+            .min(size - offset);
+        emit_progress_state(&self.app, &self.sid, "transferring");
+
+        let mut file = tokio::fs::File::open(&self.path)
+            .await
+            .map_err(|e| End::local("media-error", format!("Cannot open the file: {e}")))?;
+        file.seek(std::io::SeekFrom::Start(offset))
+            .await
+            .map_err(|e| End::local("media-error", e.to_string()))?;
+        let mut reporter = Reporter::new(Some(self.app.clone()), &self.sid, Some(length));
+
+        if proxies.is_empty() {
+            return self
+                .send_ibb(&transport_sid, &mut file, length, &mut reporter)
+                .await;
+        }
+        // We don't try the responder's candidates: only ours are proxies.
+        send_jingle(
+            &self.peer,
+            transport_info(
+                &self.sid,
+                name,
+                &transport_sid,
+                Element::new("candidate-error"),
+            ),
+        )
+        .await?;
+        let info = inbox.next(&["transport-info"], STEP_TIMEOUT).await?;
+        let used = info
+            .child("content", None)
+            .and_then(|c| c.child("transport", Some(S5B_NS)))
+            .and_then(|t| t.child("candidate-used", None))
+            .and_then(|u| u.attr("cid"))
+            .and_then(|cid| cid.strip_prefix("proxy-"))
+            .and_then(|i| i.parse::<usize>().ok())
+            .and_then(|i| proxies.get(i));
+        let Some(proxy) = used else {
+            info!(sid = %self.sid, "jingle ft: no candidate worked, replacing with IBB");
+            let ibb_sid = format!("ibb-{}", uuid::Uuid::new_v4());
+            send_jingle(
+                &self.peer,
+                jingle("transport-replace", &self.sid)
+                    .with_child(content(name).with_child(ibb_transport(&ibb_sid))),
+            )
+            .await?;
+            inbox.next(&["transport-accept"], STEP_TIMEOUT).await?;
+            return self
+                .send_ibb(&ibb_sid, &mut file, length, &mut reporter)
+                .await;
+        };
+
+        let own_jid =
+            session::own_jid().ok_or_else(|| End::local("connectivity-error", "Not connected"))?;
+        let dst = bytestreams::dst_addr(&transport_sid, &own_jid, &self.peer);
+        let (_, mut stream) = bytestreams::connect_streamhost(std::slice::from_ref(proxy), &dst)
+            .await
+            .map_err(|e| End::local("connectivity-error", e))?;
+        bytestreams::activate(&proxy.jid, &transport_sid, &self.peer)
+            .await
+            .map_err(|e| End::local("connectivity-error", e))?;
+        let cid = info
+            .child("content", None)
+            .and_then(|c| c.child("transport", Some(S5B_NS)))
+            .and_then(|t| t.child("candidate-used", None))
+            .and_then(|u| u.attr("cid"))
+            .unwrap_or_default()
+            .to_string();
+        send_jingle(
+            &self.peer,
+            transport_info(
+                &self.sid,
+                name,
+                &transport_sid,
+                Element::new("activated").with_attr("cid", &cid),
+            ),
+        )
+        .await?;
+        let sent = bytestreams::pump(&mut file, &mut stream, Some(length), &self.cancelled, |n| {
+            reporter.update(n)
+        })
+        .await
+        .map_err(|e| self.failure(e))?;
+        let _ = stream.shutdown().await;
+        Ok(sent)
+    }
+
+    async fn send_ibb(
+        &self,
+        transport_sid: &str,
+        file: &mut tokio::fs::File,
+        length: u64,
+        reporter: &mut Reporter,
+    ) -> Result<u64, End> {
+        bytestreams::send_ibb(
+            transport_sid,
+            &self.peer,
+            file,
+            Some(length),
+            &self.cancelled,
+            reporter,
+        )
+        .await
+        .map_err(|e| self.failure(e))
+    }
+
+    fn failure(&self, message: String) -> End {
+        if self.cancelled.load(Ordering::Relaxed) {
+            End::local("cancel", message)
+        } else {
+            End::local("failed-transport", message)
+        }
+    }
+}
+
+impl StanzaObserver for JingleFiles {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction != Direction::Inbound
+            || stanza.local_name() != "iq"
+            || stanza.attr("type") != Some("set")
+        {
+            return Verdict::Forward;
+        }
+        let Some(jingle) = stanza.child("jingle", Some(JINGLE_NS)) else {
+            return Verdict::Forward;
+        };
+        if self.handle(stanza, jingle, ctx.app) {
+            Verdict::Drop
+        } else {
+            Verdict::Forward
+        }
+    }
+}
+
+/// Offer `path` to the full JID `jid`. Returns the session id at once; the
+/// session's course is reported through events.
+#[tauri::command]
+pub async fn offer_file(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, Arc<JingleFiles>>,
+    jid: String,
+    path: PathBuf,
+) -> Result<String, String> {
+    if !jid.contains('/') {
+        return Err("Files are offered to a full JID".to_string());
+    }
+    let metadata = tokio::fs::metadata(&path)
+        .await
+        .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    let hash = file_hash(path.clone(), "sha-256".to_string()).await?;
+    let file = FileDescription {
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "file".to_string()),
+        size: Some(metadata.len()),
+        media_type: None,
+        hash: hash.map(|value| FileHash {
+            algo: "sha-256".to_string(),
+            value,
+        }),
+    };
+    let sid = uuid::Uuid::new_v4().to_string();
+    let (inbox, cancelled) = state.register(&sid, &jid);
+    info!(sid = %sid, to = %jid, name = %file.name, "jingle ft: offering file");
+    let initiator = Initiator {
+        sid: sid.clone(),
+        peer: jid,
+        path,
+        file,
+        bytestreams: state.bytestreams.clone(),
+        cancelled,
+        app: app.clone(),
+    };
+    let sessions = state.sessions.clone();
+    let task_sid = sid.clone();
+    tokio::spawn(async move {
+        let result = initiator.run(inbox).await;
+        lock(&sessions).remove(&task_sid);
+        emit_state(&app, &task_sid, result.as_ref().copied());
+    });
+    Ok(sid)
+}
+
+/// Accept an offered file into `dest`. A partial `dest` from an earlier
+/// attempt is resumed.
+#[tauri::command]
+pub fn accept_file(
+    state: tauri::State<'_, Arc<JingleFiles>>,
+    session_id: String,
+    dest: PathBuf,
+) -> Result<(), String> {
+    let answer = lock(&state.sessions)
+        .get_mut(&session_id)
+        .and_then(|s| s.answer.take())
+        .ok_or_else(|| format!("No pending offer {session_id}"))?;
+    answer
+        .send(Some(dest))
+        .map_err(|_| "The offer is gone".to_string())
+}
+
+#[tauri::command]
+pub fn decline_file(
+    state: tauri::State<'_, Arc<JingleFiles>>,
+    session_id: String,
+) -> Result<(), String> {
+    let answer = lock(&state.sessions)
+        .get_mut(&session_id)
+        .and_then(|s| s.answer.take())
+        .ok_or_else(|| format!("No pending offer {session_id}"))?;
+    let _ = answer.send(None);
+    Ok(())
+}
+
+/// Stop a session in any state; the peer is told.
+#[tauri::command]
+pub fn cancel_file_transfer(state: tauri::State<'_, Arc<JingleFiles>>, session_id: String) {
+    if let Some(session) = lock(&state.sessions).get(&session_id) {
+        session.cancelled.store(true, Ordering::Relaxed);
+        let _ = session.inbox.send(Element::new("cancel"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_offers_and_orders_candidates() {
+        let offer = Element::parse(
+            "<content xmlns='urn:xmpp:jingle:1' creator='initiator' name='a-file-offer'>\
+             <description xmlns='urn:xmpp:jingle:apps:file-transfer:5'><file>\
+             <media-type>text/plain</media-type><name>test.txt</name><size>6144</size>\
+             <hash xmlns='urn:xmpp:hashes:2' algo='sha-1'>w0mcJylzCn+AfvuGdqkty2+KP48=</hash>\
+             </file></description>\
+             <transport xmlns='urn:xmpp:jingle:transports:s5b:1' sid='vj3hs98y' mode='tcp'>\
+             <candidate cid='hft54dqy' host='192.168.4.1' jid='romeo@montague.example/dr4hc' \
+              port='5086' priority='8257636' type='direct'/>\
+             <candidate cid='ht567dq' host='proxy.example' jid='proxy.example' port='7625' \
+              priority='655360' type='proxy'/>\
+             <candidate cid='hr65dqyd' host='10.1.1.110' jid='romeo@montague.example/dr4hc' \
+              port='5087' priority='8258636' type='direct'/>\
+             </transport></content>",
+        )
+        .unwrap();
+        let file = parse_file(
+            offer
+                .child("description", Some(FT_NS))
+                .and_then(|d| d.child("file", None))
+                .unwrap(),
+        );
+        assert_eq!(file.name, "test.txt");
+        assert_eq!(file.size, Some(6144));
+        assert_eq!(file.media_type.as_deref(), Some("text/plain"));
+        assert_eq!(file.hash.as_ref().map(|h| h.algo.as_str()), Some("sha-1"));
+
+        let found = candidates(offer.child("transport", Some(S5B_NS)).unwrap());
+        let order: Vec<&str> = found.iter().map(|(cid, _, _)| cid.as_str()).collect();
+        assert_eq!(order, ["hr65dqyd", "hft54dqy", "ht567dq"]);
+        assert!(found[2].2);
+        assert_eq!(found[2].1.port, 7625);
+
+        // What we offer parses back the same way.
+        let described = parse_file(&file_element(&file));
+        assert_eq!(described, file);
+    }
+
+    #[tokio::test]
+    async fn hashes_files_with_the_announced_function() {
+        let path = std::env::temp_dir().join(format!("jingle-ft-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"abc").unwrap();
+        let sha256 = file_hash(path.clone(), "sha-256".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            sha256.as_deref(),
+            Some("ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=")
+        );
+        assert_eq!(
+            file_hash(path.clone(), "md5".to_string()).await.unwrap(),
+            None
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! The small JSON files in the app data directory: settings, queues, caches.
+//!
+//! A module takes a snapshot of its state under its own lock and hands it
+//! to its [`JsonFile`], which writes it to a temporary file renamed over the
+//! old one, so a crash never leaves a truncated file behind. Background
+//! writes may run in any order, so each snapshot gets a generation when it
+//! is saved, and one older than the last saved is dropped instead of being
+//! written: the file always ends up with the newest snapshot. That only
+//! holds if [`JsonFile::save`] is called before the lock the snapshot was
+//! taken under is released.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+#[derive(Clone)]
+pub(crate) struct JsonFile(Arc<Inner>);
+
+struct Inner {
+    path: PathBuf,
+    pretty: bool,
+    /// Generation of the last snapshot saved.
+    latest: AtomicU64,
+    /// Held while writing, so writes never interleave on the temporary file.
+    writing: Mutex<()>,
+}
+
+impl JsonFile {
+    /// A file written as compact JSON, for caches.
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self::with_layout(path, false)
+    }
+
+    /// A file written as indented JSON, for settings people may read.
+    pub(crate) fn pretty(path: PathBuf) -> Self {
+        Self::with_layout(path, true)
+    }
+
+    fn with_layout(path: PathBuf, pretty: bool) -> Self {
+        JsonFile(Arc::new(Inner {
+            path,
+            pretty,
+            latest: AtomicU64::new(0),
+            writing: Mutex::new(()),
+        }))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0.path
+    }
+
+    /// Write `snapshot` in the background; callers must not block on disk.
+    pub(crate) fn save<T: Serialize + Send + 'static>(&self, snapshot: T) {
+        let generation = self.next_generation();
+        let file = self.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            if let Err(e) = file.write(generation, &snapshot) {
+                warn!(path = %file.path().display(), error = %e, "Failed to save");
+            }
+        });
+    }
+
+    /// Write `snapshot` on this thread: from blocking tasks, and on the exit
+    /// path, which has no runtime left to run a task.
+    pub(crate) fn save_now<T: Serialize>(&self, snapshot: &T) -> std::io::Result<()> {
+        let generation = self.next_generation();
+        self.write(generation, snapshot)
+    }
+
+    fn next_generation(&self) -> u64 {
+        self.0.latest.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn write<T: Serialize>(&self, generation: u64, snapshot: &T) -> std::io::Result<()> {
+        let _guard = self.0.writing.lock().unwrap_or_else(|e| e.into_inner());
+        // A newer snapshot was saved since; its own write supersedes this one.
+        if generation < self.0.latest.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let bytes = if self.0.pretty {
+            serde_json::to_vec_pretty(snapshot)?
+        } else {
+            serde_json::to_vec(snapshot)?
+        };
+        write_atomic(&self.0.path, &bytes)
+    }
+}
+
+/// Write `bytes` to `path` through a temporary file renamed over it.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_late_write_of_an_older_snapshot_is_dropped() {
+        let path = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let file = JsonFile::new(path.clone());
+        let older = file.next_generation();
+        let newer = file.next_generation();
+
+        file.write(newer, &vec!["newer"]).unwrap();
+        file.write(older, &vec!["older"]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"["newer"]"#);
+
+        file.save_now(&vec!["latest"]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"["latest"]"#);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,479 @@
+//! Keychain health check, and moving stored secrets between backends.
+//!
+//! Credentials, tokens and passphrases go through `keyring::Entry`, backed
+//! by the platform's store: the macOS Keychain, the Windows Credential
+//! Manager (encrypted with DPAPI), or the Secret Service on Linux (GNOME
+//! Keyring, KWallet). When that store is locked or missing, every save and
+//! load fails with "Keychain locked or inaccessible", and nothing told the
+//! user what to do about it. [`check_keychain`] writes, reads back and
+//! deletes a probe entry in the active backend and reports which step
+//! failed, whether the store is merely locked (unlocking it is the fix), and
+//! where the entries could be moved instead.
+//!
+//! [`migrate_keychain`] moves Fluux's entries ([`known_accounts`]) to the
+//! other backend and makes it the active one: the platform store, or the
+//! file fallback, `credentials.json` in the app data directory (0600 on
+//! Unix). The file is not encrypted, which the health report says. As for
+//! OpenPGP passphrases (see `openpgp_storage`), it is not offered on macOS,
+//! where a keychain always exists. The choice is kept in `keychain.json`;
+//! while the file fallback is active it is keyring's default credential
+//! builder, so every `Entry` in the app uses it.
+
+use crate::error::FluuxError;
+use crate::json_file::JsonFile;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use keyring::credential::{Credential, CredentialApi, CredentialBuilderApi};
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tracing::{info, warn};
+
+/// Account of the entry [`check_keychain`] writes and deletes.
+const PROBE_USER: &str = "keychain-health-check";
+/// Whether the unencrypted file fallback may be used on this platform.
+const FILE_FALLBACK_ALLOWED: bool = !cfg!(target_os = "macos");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// The macOS Keychain.
+    Keychain,
+    /// The Windows Credential Manager, encrypted with DPAPI.
+    Dpapi,
+    /// The Secret Service on Linux (GNOME Keyring, KWallet).
+    SecretService,
+    /// `credentials.json` in the app data directory.
+    File,
+}
+
+impl Backend {
+    /// The platform's store, keyring's default.
+    fn native() -> Backend {
+        if cfg!(target_os = "macos") {
+            Backend::Keychain
+        } else if cfg!(target_os = "windows") {
+            Backend::Dpapi
+        } else {
+            Backend::SecretService
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Settings {
+    file_fallback: bool,
+}
+
+static FILE_STORE_PATH: OnceLock<PathBuf> = OnceLock::new();
+static SETTINGS_FILE: OnceLock<JsonFile> = OnceLock::new();
+static FILE_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Result of [`check_keychain`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeychainHealth {
+    pub backend: Backend,
+    /// Whether the backend encrypts what it stores (not the file fallback).
+    pub protected_at_rest: bool,
+    pub writable: bool,
+    pub readable: bool,
+    /// The store is locked or access was denied: unlocking it is the fix.
+    pub locked: bool,
+    /// What failed, if anything did.
+    pub error: Option<FluuxError>,
+    /// The backend [`migrate_keychain`] can move the entries to, if any.
+    pub migrate_to: Option<Backend>,
+}
+
+/// Load the backend choice from `dir`. Called from the Tauri `setup` hook,
+/// before anything reads the keychain.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(dir) = dir else {
+        return;
+    };
+    let _ = FILE_STORE_PATH.set(dir.join("credentials.json"));
+    let path = dir.join("keychain.json");
+    let settings: Settings = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    let _ = SETTINGS_FILE.set(JsonFile::new(path));
+    if settings.file_fallback && FILE_FALLBACK_ALLOWED {
+        info!("Keychain: using the file fallback");
+        activate(Backend::File);
+    }
+}
+
+fn active() -> Backend {
+    if FILE_ACTIVE.load(Ordering::Relaxed) {
+        Backend::File
+    } else {
+        Backend::native()
+    }
+}
+
+/// Make `backend` the one every `Entry::new` uses.
+fn activate(backend: Backend) {
+    match (backend, FILE_STORE_PATH.get()) {
+        (Backend::File, Some(path)) => {
+            keyring::set_default_credential_builder(Box::new(FileStore { path: path.clone() }));
+            FILE_ACTIVE.store(true, Ordering::Relaxed);
+        }
+        (Backend::File, None) => {}
+        _ => {
+            keyring::set_default_credential_builder(keyring::default::default_credential_builder());
+            FILE_ACTIVE.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+fn persist(backend: Backend) -> Result<(), FluuxError> {
+    let Some(file) = SETTINGS_FILE.get() else {
+        return Ok(());
+    };
+    let settings = Settings {
+        file_fallback: backend == Backend::File,
+    };
+    file.save_now(&settings)
+        .map_err(|e| FluuxError::Internal(format!("Failed to save the keychain choice: {e}")))
+}
+
+/// The entry for `user` in `backend`, whichever is active.
+fn entry(backend: Backend, user: &str) -> keyring::Result<Entry> {
+    let credential = match backend {
+        Backend::File => {
+            let path = FILE_STORE_PATH
+                .get()
+                .ok_or_else(|| keyring::Error::NoStorageAccess("No app data directory".into()))?;
+            FileStore { path: path.clone() }.build(None, crate::KEYRING_SERVICE, user)?
+        }
+        _ => keyring::default::default_credential_builder().build(
+            None,
+            crate::KEYRING_SERVICE,
+            user,
+        )?,
+    };
+    Ok(Entry::new_with_credential(credential))
+}
+
+/// The accounts Fluux stores entries under; those of `jid` (the last user)
+/// when known.
+fn known_accounts(jid: Option<&str>) -> Vec<String> {
+    let mut accounts: Vec<String> = [
+        "last_user",
+        crate::MCP_TOKEN_KEYRING_USER,
+        crate::admin::ADMIN_KEYRING_USER,
+        crate::gif::GIF_KEYRING_USER,
+    ]
+    .map(String::from)
+    .into();
+    if let Some(jid) = jid {
+        accounts.extend([
+            jid.to_string(),
+            crate::push::keyring_user(jid),
+            format!("settings-sync:{jid}"),
+            format!("{}{jid}", crate::openpgp_storage::KEYRING_ACCOUNT_PREFIX),
+        ]);
+    }
+    accounts
+}
+
+/// Write, read back and delete [`PROBE_USER`] in `backend`.
+fn probe(backend: Backend) -> KeychainHealth {
+    let mut health = KeychainHealth {
+        backend,
+        protected_at_rest: backend != Backend::File,
+        writable: false,
+        readable: false,
+        locked: false,
+        error: None,
+        migrate_to: None,
+    };
+    let value = uuid::Uuid::new_v4().to_string();
+    let result = entry(backend, PROBE_USER).and_then(|entry| {
+        match entry.set_password(&value) {
+            Ok(()) => health.writable = true,
+            // Whether it can be read at all.
+            Err(e) => {
+                health.readable = matches!(entry.get_password(), Err(keyring::Error::NoEntry));
+                return Err(e);
+            }
+        }
+        let read = entry.get_password();
+        let _ = entry.delete_credential();
+        health.readable = read.as_ref().is_ok_and(|read| *read == value);
+        read.map(drop)
+    });
+    if let Err(e) = result {
+        let error = crate::keyring_error(&e, "health check");
+        health.locked = matches!(error, FluuxError::KeychainLocked(_));
+        health.error = Some(error);
+    }
+    health
+}
+
+/// Check that the active backend can be written and read, and whether the
+/// entries could be moved elsewhere.
+#[tauri::command]
+pub async fn check_keychain() -> Result<KeychainHealth, FluuxError> {
+    tokio::task::spawn_blocking(|| {
+        let mut health = probe(active());
+        health.migrate_to = match health.backend {
+            // Back to the platform store once it works.
+            Backend::File => Some(Backend::native()).filter(|&native| {
+                let native = probe(native);
+                native.writable && native.readable
+            }),
+            _ if health.writable && health.readable => None,
+            _ => Some(Backend::File).filter(|_| FILE_FALLBACK_ALLOWED),
+        };
+        match &health.error {
+            Some(error) => {
+                warn!(backend = ?health.backend, error = %error, "Keychain check failed")
+            }
+            None => info!(backend = ?health.backend, "Keychain check passed"),
+        }
+        health
+    })
+    .await
+    .map_err(|e| FluuxError::Internal(format!("Keychain task panicked: {e}")))
+}
+
+/// Copy every known entry from `from` to `to`, switch to `to`, then delete
+/// the originals. Returns how many entries moved.
+fn migrate(from: Backend, to: Backend) -> Result<usize, FluuxError> {
+    let read = |user: &str| match entry(from, user)
+        .and_then(|entry| crate::credential_log::read_secret(&entry, user, "keychain migration"))
+    {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(crate::keyring_error(&e, &format!("read {user}"))),
+    };
+    let last_user = read("last_user")?.map(|jid| String::from_utf8_lossy(&jid).into_owned());
+    let mut moved = Vec::new();
+    for user in known_accounts(last_user.as_deref()) {
+        let Some(secret) = read(&user)? else {
+            continue;
+        };
+        let written = entry(to, &user).and_then(|target| {
+            target.set_secret(&secret)?;
+            target.get_secret()
+        });
+        match written {
+            Ok(copy) if copy == secret => moved.push(user),
+            Ok(_) => {
+                return Err(FluuxError::Keychain(format!(
+                    "{user} read back differently from {to:?}"
+                )))
+            }
+            Err(e) => return Err(crate::keyring_error(&e, &format!("write {user}"))),
+        }
+    }
+    activate(to);
+    persist(to)?;
+    for user in &moved {
+        // Best effort: the copies are the ones in use now.
+        if let Err(e) = entry(from, user).and_then(|entry| entry.delete_credential()) {
+            warn!(user = %user, error = %e, "Keychain: original entry left behind");
+        }
+    }
+    info!(from = ?from, to = ?to, moved = moved.len(), "Keychain entries migrated");
+    Ok(moved.len())
+}
+
+/// Move Fluux's entries to `to` and use it from now on. Returns how many
+/// entries moved.
+#[tauri::command]
+pub async fn migrate_keychain(to: Backend) -> Result<usize, FluuxError> {
+    let from = active();
+    if to == from {
+        return Ok(0);
+    }
+    if to == Backend::File && !FILE_FALLBACK_ALLOWED {
+        return Err(FluuxError::InvalidInput(
+            "The file fallback is not available on this platform".to_string(),
+        ));
+    }
+    if to != Backend::File && to != Backend::native() {
+        return Err(FluuxError::InvalidInput(format!(
+            "{to:?} is not available on this platform"
+        )));
+    }
+    tokio::task::spawn_blocking(move || migrate(from, to))
+        .await
+        .map_err(|e| FluuxError::Internal(format!("Keychain task panicked: {e}")))?
+}
+
+// ---------------------------------------------------------------------------
+// File fallback
+// ---------------------------------------------------------------------------
+
+/// Secrets by service, then user, base64-encoded.
+type Secrets = BTreeMap<String, BTreeMap<String, String>>;
+
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug)]
+struct FileStore {
+    path: PathBuf,
+}
+
+#[derive(Debug)]
+struct FileCredential {
+    path: PathBuf,
+    service: String,
+    user: String,
+}
+
+fn platform_failure(e: impl std::error::Error + Send + Sync + 'static) -> keyring::Error {
+    keyring::Error::PlatformFailure(Box::new(e))
+}
+
+impl FileCredential {
+    fn read(&self) -> keyring::Result<Secrets> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(platform_failure),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Secrets::new()),
+            Err(e) => Err(keyring::Error::NoStorageAccess(Box::new(e))),
+        }
+    }
+
+    fn update(
+        &self,
+        change: impl FnOnce(&mut Secrets) -> keyring::Result<()>,
+    ) -> keyring::Result<()> {
+        let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut secrets = self.read()?;
+        change(&mut secrets)?;
+        secrets.retain(|_, users| !users.is_empty());
+        let bytes = serde_json::to_vec(&secrets).map_err(platform_failure)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(platform_failure)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, bytes).map_err(platform_failure)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600));
+        }
+        std::fs::rename(&tmp, &self.path).map_err(platform_failure)
+    }
+}
+
+impl CredentialApi for FileCredential {
+    fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+        self.update(|secrets| {
+            secrets
+                .entry(self.service.clone())
+                .or_default()
+                .insert(self.user.clone(), BASE64.encode(secret));
+            Ok(())
+        })
+    }
+
+    fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+        let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let secrets = self.read()?;
+        let encoded = secrets
+            .get(&self.service)
+            .and_then(|users| users.get(&self.user))
+            .ok_or(keyring::Error::NoEntry)?;
+        BASE64.decode(encoded).map_err(platform_failure)
+    }
+
+    fn delete_credential(&self) -> keyring::Result<()> {
+        self.update(|secrets| {
+            secrets
+                .get_mut(&self.service)
+                .and_then(|users| users.remove(&self.user))
+                .map(drop)
+                .ok_or(keyring::Error::NoEntry)
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl CredentialBuilderApi for FileStore {
+    fn build(
+        &self,
+        _target: Option<&str>,
+        service: &str,
+        user: &str,
+    ) -> keyring::Result<Box<Credential>> {
+        Ok(Box::new(FileCredential {
+            path: self.path.clone(),
+            service: service.to_string(),
+            user: user.to_string(),
+        }))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_fallback_stores_and_deletes_entries() {
+        let path =
+            std::env::temp_dir().join(format!("fluux-credentials-{}.json", uuid::Uuid::new_v4()));
+        let store = FileStore { path: path.clone() };
+        let entry = |user: &str| {
+            Entry::new_with_credential(store.build(None, crate::KEYRING_SERVICE, user).unwrap())
+        };
+        assert!(matches!(
+            entry("last_user").get_password(),
+            Err(keyring::Error::NoEntry)
+        ));
+        entry("last_user")
+            .set_password("alice@example.org")
+            .unwrap();
+        entry("mcp-token").set_secret(&[0, 159, 255]).unwrap();
+        assert_eq!(
+            entry("last_user").get_password().unwrap(),
+            "alice@example.org"
+        );
+        assert_eq!(entry("mcp-token").get_secret().unwrap(), [0, 159, 255]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        entry("last_user").delete_credential().unwrap();
+        assert!(matches!(
+            entry("last_user").delete_credential(),
+            Err(keyring::Error::NoEntry)
+        ));
+        assert_eq!(entry("mcp-token").get_secret().unwrap(), [0, 159, 255]);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn accounts_of_the_last_user_are_migrated_too() {
+        assert!(!known_accounts(None).iter().any(|a| a.contains('@')));
+        let accounts = known_accounts(Some("alice@example.org"));
+        for account in [
+            "last_user",
+            "alice@example.org",
+            "push:alice@example.org",
+            "openpgp_passphrase:alice@example.org",
+        ] {
+            assert!(accounts.iter().any(|a| a == account), "{account}");
+        }
+    }
+}
@@ -0,0 +1,710 @@
+//! Locale service: the system's date and time conventions, and relative
+//! times ("5 minutes ago") rendered natively, so timestamps in
+//! notifications and exports match the OS without the frontend shipping
+//! `Intl` data for every locale.
+//!
+//! Conventions come from the locale tag (parsed with ICU4X's
+//! `icu_locale_core`, honouring the `-u-fw`, `-u-hc` and `-u-rg`
+//! extensions) and CLDR's region data, then from the OS settings that
+//! override them: the macOS global preferences, the Windows
+//! `Control Panel\International` key, and `LC_TIME` on Linux.
+//!
+//! Relative times are compiled in for the languages the shell is translated
+//! into (see [`crate::shell_i18n`]); any other language gets English.
+
+use icu_locale_core::extensions::unicode::{key, Key};
+use icu_locale_core::Locale;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocaleInfo {
+    /// BCP 47 tag of the system locale.
+    pub locale: String,
+    /// Region whose conventions apply, when known (`US`, `FR`…).
+    pub region: Option<String>,
+    /// 0 = Sunday … 6 = Saturday.
+    pub first_day_of_week: u8,
+    /// Times are shown on a 12-hour clock.
+    pub hour12: bool,
+}
+
+/// CLDR regions whose week starts on Sunday (`weekData`).
+const SUNDAY_FIRST: &[&str] = &[
+    "AG", "AS", "BD", "BR", "BS", "BT", "BW", "BZ", "CA", "CN", "CO", "DM", "DO", "ET", "GT", "GU",
+    "HK", "HN", "ID", "IL", "IN", "JM", "JP", "KE", "KH", "KR", "LA", "MH", "MM", "MO", "MT", "MX",
+    "MZ", "NI", "NP", "PA", "PE", "PH", "PK", "PR", "PT", "PY", "SA", "SG", "SV", "TH", "TT", "TW",
+    "UM", "US", "VE", "VI", "WS", "YE", "ZA", "ZW",
+];
+/// CLDR regions whose week starts on Saturday.
+const SATURDAY_FIRST: &[&str] = &[
+    "AE", "AF", "BH", "DJ", "DZ", "EG", "IQ", "IR", "JO", "KW", "LY", "OM", "QA", "SD", "SY",
+];
+/// Regions whose preferred clock is 12-hour (CLDR `timeData`).
+const HOUR12_REGIONS: &[&str] = &[
+    "AU", "BD", "CA", "CO", "EG", "HK", "IN", "KR", "MY", "NZ", "PH", "PK", "SA", "TW", "US",
+];
+
+/// Region assumed for a bare language tag (`en` → `US`).
+fn likely_region(language: &str) -> Option<&'static str> {
+    Some(match language {
+        "en" => "US",
+        "pt" => "BR",
+        "zh" => "CN",
+        "ja" => "JP",
+        "ko" => "KR",
+        "he" => "IL",
+        "hi" => "IN",
+        _ => return None,
+    })
+}
+
+/// Parse an OS locale string: a BCP 47 tag or a POSIX name such as
+/// `en_GB.UTF-8@euro`.
+fn parse_locale(tag: &str) -> Option<Locale> {
+    let tag = tag
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .replace('_', "-");
+    Locale::try_from_str(&tag).ok()
+}
+
+fn weekday_index(value: &str) -> Option<u8> {
+    ["sun", "mon", "tue", "wed", "thu", "fri", "sat"]
+        .iter()
+        .position(|day| *day == value)
+        .map(|i| i as u8)
+}
+
+/// The conventions of the locale `tag`, before OS overrides.
+fn conventions(tag: &str) -> LocaleInfo {
+    let Some(locale) = parse_locale(tag) else {
+        return LocaleInfo {
+            locale: "und".to_string(),
+            region: None,
+            first_day_of_week: 1,
+            hour12: false,
+        };
+    };
+    let language = locale.id.language.as_str().to_string();
+    let keyword = |name: Key| {
+        locale
+            .extensions
+            .unicode
+            .keywords
+            .get(&name)
+            .map(|value| value.to_string())
+    };
+    // `-u-rg-uszzzz`: formatting region different from the language's.
+    let region = keyword(key!("rg"))
+        .filter(|rg| rg.len() == 6)
+        .map(|rg| rg[..2].to_ascii_uppercase())
+        .or_else(|| locale.id.region.map(|r| r.as_str().to_string()))
+        .or_else(|| likely_region(&language).map(str::to_string));
+    let in_list = |list: &[&str]| region.as_deref().is_some_and(|r| list.contains(&r));
+
+    let first_day_of_week = keyword(key!("fw"))
+        .and_then(|fw| weekday_index(&fw))
+        .unwrap_or(if in_list(SUNDAY_FIRST) {
+            0
+        } else if in_list(SATURDAY_FIRST) {
+            6
+        } else if region.as_deref() == Some("MV") {
+            5
+        } else {
+            1
+        });
+    let hour12 = match keyword(key!("hc")).as_deref() {
+        Some("h11" | "h12") => true,
+        Some("h23" | "h24") => false,
+        // French Canada keeps the 24-hour clock.
+        _ => in_list(HOUR12_REGIONS) && !(language == "fr" && region.as_deref() == Some("CA")),
+    };
+    LocaleInfo {
+        locale: locale.to_string(),
+        region,
+        first_day_of_week,
+        hour12,
+    }
+}
+
+/// OS settings that override the locale's conventions.
+#[derive(Debug, Default)]
+struct Overrides {
+    locale: Option<String>,
+    first_day_of_week: Option<u8>,
+    hour12: Option<bool>,
+}
+
+#[cfg(target_os = "macos")]
+fn os_overrides() -> Overrides {
+    let Some(home) = std::env::var_os("HOME") else {
+        return Overrides::default();
+    };
+    let path = std::path::Path::new(&home).join("Library/Preferences/.GlobalPreferences.plist");
+    let Ok(plist::Value::Dictionary(prefs)) = plist::Value::from_file(path) else {
+        return Overrides::default();
+    };
+    let flag = |name: &str| prefs.get(name).and_then(plist::Value::as_boolean) == Some(true);
+    let hour12 = if flag("AppleICUForce24HourTime") {
+        Some(false)
+    } else if flag("AppleICUForce12HourTime") {
+        Some(true)
+    } else {
+        None
+    };
+    // {gregorian = 2}: 1 = Sunday.
+    let first_day_of_week = prefs
+        .get("AppleFirstWeekday")
+        .and_then(plist::Value::as_dictionary)
+        .and_then(|days| days.get("gregorian"))
+        .and_then(plist::Value::as_unsigned_integer)
+        .filter(|day| (1..=7).contains(day))
+        .map(|day| (day - 1) as u8);
+    Overrides {
+        locale: prefs
+            .get("AppleLocale")
+            .and_then(plist::Value::as_string)
+            .map(str::to_string),
+        first_day_of_week,
+        hour12,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn os_overrides() -> Overrides {
+    use winreg::enums::HKEY_CURRENT_USER;
+    use winreg::RegKey;
+
+    let Ok(key) = RegKey::predef(HKEY_CURRENT_USER).open_subkey(r"Control Panel\International")
+    else {
+        return Overrides::default();
+    };
+    let value = |name: &str| key.get_value::<String, _>(name).ok();
+    Overrides {
+        locale: value("LocaleName"),
+        // 0 = Monday … 6 = Sunday.
+        first_day_of_week: value("iFirstDayOfWeek")
+            .and_then(|day| day.trim().parse::<u8>().ok())
+            .filter(|day| *day <= 6)
+            .map(|day| (day + 1) % 7),
+        // `H` is the 24-hour field in Windows time patterns.
+        hour12: value("sShortTime").map(|pattern| !pattern.contains('H')),
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn os_overrides() -> Overrides {
+    // Time formatting follows LC_TIME, which may differ from the UI language.
+    let locale = ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty() && value != "C" && value != "POSIX");
+    Overrides {
+        locale,
+        ..Default::default()
+    }
+}
+
+/// The system's locale conventions.
+pub fn system() -> LocaleInfo {
+    let overrides = os_overrides();
+    let tag = overrides
+        .locale
+        .or_else(sys_locale::get_locale)
+        .unwrap_or_default();
+    let mut info = conventions(&tag);
+    if let Some(day) = overrides.first_day_of_week {
+        info.first_day_of_week = day;
+    }
+    if let Some(hour12) = overrides.hour12 {
+        info.hour12 = hour12;
+    }
+    info
+}
+
+/// CLDR plural category of the integer `n`: 0 = one, 1 = few, 2 = other
+/// (`many` in Polish and East Slavic, where it covers every other integer).
+fn plural(lang: &str, n: u64) -> usize {
+    let few = (2..=4).contains(&(n % 10)) && !(12..=14).contains(&(n % 100));
+    match lang {
+        "zh-CN" => 2,
+        "fr" | "pt" => usize::from(n > 1) * 2,
+        "cs" => match n {
+            1 => 0,
+            2..=4 => 1,
+            _ => 2,
+        },
+        "pl" if n == 1 => 0,
+        "ru" | "uk" if n % 10 == 1 && n % 100 != 11 => 0,
+        "pl" | "ru" | "uk" if few => 1,
+        "pl" | "ru" | "uk" => 2,
+        _ if n == 1 => 0,
+        _ => 2,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// One pattern per plural category ([`plural`]); `{0}` is the count.
+type Forms = [&'static str; 3];
+
+struct RelativeStrings {
+    now: &'static str,
+    /// By [`Unit`].
+    past: [Forms; 6],
+    future: [Forms; 6],
+}
+
+/// Two plural categories: `few` never occurs.
+const fn two(one: &'static str, other: &'static str) -> Forms {
+    [one, other, other]
+}
+
+const EN: RelativeStrings = RelativeStrings {
+    now: "now",
+    past: [
+        two("{0} minute ago", "{0} minutes ago"),
+        two("{0} hour ago", "{0} hours ago"),
+        two("{0} day ago", "{0} days ago"),
+        two("{0} week ago", "{0} weeks ago"),
+        two("{0} month ago", "{0} months ago"),
+        two("{0} year ago", "{0} years ago"),
+    ],
+    future: [
+        two("in {0} minute", "in {0} minutes"),
+        two("in {0} hour", "in {0} hours"),
+        two("in {0} day", "in {0} days"),
+        two("in {0} week", "in {0} weeks"),
+        two("in {0} month", "in {0} months"),
+        two("in {0} year", "in {0} years"),
+    ],
+};
+
+const CS: RelativeStrings = RelativeStrings {
+    now: "nyní",
+    past: [
+        ["před {0} minutou", "před {0} minutami", "před {0} minutami"],
+        ["před {0} hodinou", "před {0} hodinami", "před {0} hodinami"],
+        ["před {0} dnem", "před {0} dny", "před {0} dny"],
+        ["před {0} týdnem", "před {0} týdny", "před {0} týdny"],
+        ["před {0} měsícem", "před {0} měsíci", "před {0} měsíci"],
+        ["před {0} rokem", "před {0} lety", "před {0} lety"],
+    ],
+    future: [
+        ["za {0} minutu", "za {0} minuty", "za {0} minut"],
+        ["za {0} hodinu", "za {0} hodiny", "za {0} hodin"],
+        ["za {0} den", "za {0} dny", "za {0} dní"],
+        ["za {0} týden", "za {0} týdny", "za {0} týdnů"],
+        ["za {0} měsíc", "za {0} měsíce", "za {0} měsíců"],
+        ["za {0} rok", "za {0} roky", "za {0} let"],
+    ],
+};
+
+const DA: RelativeStrings = RelativeStrings {
+    now: "nu",
+    past: [
+        two("for {0} minut siden", "for {0} minutter siden"),
+        two("for {0} time siden", "for {0} timer siden"),
+        two("for {0} dag siden", "for {0} dage siden"),
+        two("for {0} uge siden", "for {0} uger siden"),
+        two("for {0} måned siden", "for {0} måneder siden"),
+        two("for {0} år siden", "for {0} år siden"),
+    ],
+    future: [
+        two("om {0} minut", "om {0} minutter"),
+        two("om {0} time", "om {0} timer"),
+        two("om {0} dag", "om {0} dage"),
+        two("om {0} uge", "om {0} uger"),
+        two("om {0} måned", "om {0} måneder"),
+        two("om {0} år", "om {0} år"),
+    ],
+};
+
+const DE: RelativeStrings = RelativeStrings {
+    now: "jetzt",
+    past: [
+        two("vor {0} Minute", "vor {0} Minuten"),
+        two("vor {0} Stunde", "vor {0} Stunden"),
+        two("vor {0} Tag", "vor {0} Tagen"),
+        two("vor {0} Woche", "vor {0} Wochen"),
+        two("vor {0} Monat", "vor {0} Monaten"),
+        two("vor {0} Jahr", "vor {0} Jahren"),
+    ],
+    future: [
+        two("in {0} Minute", "in {0} Minuten"),
+        two("in {0} Stunde", "in {0} Stunden"),
+        two("in {0} Tag", "in {0} Tagen"),
+        two("in {0} Woche", "in {0} Wochen"),
+        two("in {0} Monat", "in {0} Monaten"),
+        two("in {0} Jahr", "in {0} Jahren"),
+    ],
+};
+
+const ES: RelativeStrings = RelativeStrings {
+    now: "ahora",
+    past: [
+        two("hace {0} minuto", "hace {0} minutos"),
+        two("hace {0} hora", "hace {0} horas"),
+        two("hace {0} día", "hace {0} días"),
+        two("hace {0} semana", "hace {0} semanas"),
+        two("hace {0} mes", "hace {0} meses"),
+        two("hace {0} año", "hace {0} años"),
+    ],
+    future: [
+        two("dentro de {0} minuto", "dentro de {0} minutos"),
+        two("dentro de {0} hora", "dentro de {0} horas"),
+        two("dentro de {0} día", "dentro de {0} días"),
+        two("dentro de {0} semana", "dentro de {0} semanas"),
+        two("dentro de {0} mes", "dentro de {0} meses"),
+        two("dentro de {0} año", "dentro de {0} años"),
+    ],
+};
+
+const FI: RelativeStrings = RelativeStrings {
+    now: "nyt",
+    past: [
+        two("{0} minuutti sitten", "{0} minuuttia sitten"),
+        two("{0} tunti sitten", "{0} tuntia sitten"),
+        two("{0} päivä sitten", "{0} päivää sitten"),
+        two("{0} viikko sitten", "{0} viikkoa sitten"),
+        two("{0} kuukausi sitten", "{0} kuukautta sitten"),
+        two("{0} vuosi sitten", "{0} vuotta sitten"),
+    ],
+    future: [
+        two("{0} minuutin päästä", "{0} minuutin päästä"),
+        two("{0} tunnin päästä", "{0} tunnin päästä"),
+        two("{0} päivän päästä", "{0} päivän päästä"),
+        two("{0} viikon päästä", "{0} viikon päästä"),
+        two("{0} kuukauden päästä", "{0} kuukauden päästä"),
+        two("{0} vuoden päästä", "{0} vuoden päästä"),
+    ],
+};
+
+const FR: RelativeStrings = RelativeStrings {
+    now: "maintenant",
+    past: [
+        two("il y a {0} minute", "il y a {0} minutes"),
+        two("il y a {0} heure", "il y a {0} heures"),
+        two("il y a {0} jour", "il y a {0} jours"),
+        two("il y a {0} semaine", "il y a {0} semaines"),
+        two("il y a {0} mois", "il y a {0} mois"),
+        two("il y a {0} an", "il y a {0} ans"),
+    ],
+    future: [
+        two("dans {0} minute", "dans {0} minutes"),
+        two("dans {0} heure", "dans {0} heures"),
+        two("dans {0} jour", "dans {0} jours"),
+        two("dans {0} semaine", "dans {0} semaines"),
+        two("dans {0} mois", "dans {0} mois"),
+        two("dans {0} an", "dans {0} ans"),
+    ],
+};
+
+const IT: RelativeStrings = RelativeStrings {
+    now: "ora",
+    past: [
+        two("{0} minuto fa", "{0} minuti fa"),
+        two("{0} ora fa", "{0} ore fa"),
+        two("{0} giorno fa", "{0} giorni fa"),
+        two("{0} settimana fa", "{0} settimane fa"),
+        two("{0} mese fa", "{0} mesi fa"),
+        two("{0} anno fa", "{0} anni fa"),
+    ],
+    future: [
+        two("tra {0} minuto", "tra {0} minuti"),
+        two("tra {0} ora", "tra {0} ore"),
+        two("tra {0} giorno", "tra {0} giorni"),
+        two("tra {0} settimana", "tra {0} settimane"),
+        two("tra {0} mese", "tra {0} mesi"),
+        two("tra {0} anno", "tra {0} anni"),
+    ],
+};
+
+const NB: RelativeStrings = RelativeStrings {
+    now: "nå",
+    past: [
+        two("for {0} minutt siden", "for {0} minutter siden"),
+        two("for {0} time siden", "for {0} timer siden"),
+        two("for {0} dag siden", "for {0} dager siden"),
+        two("for {0} uke siden", "for {0} uker siden"),
+        two("for {0} måned siden", "for {0} måneder siden"),
+        two("for {0} år siden", "for {0} år siden"),
+    ],
+    future: [
+        two("om {0} minutt", "om {0} minutter"),
+        two("om {0} time", "om {0} timer"),
+        two("om {0} dag", "om {0} dager"),
+        two("om {0} uke", "om {0} uker"),
+        two("om {0} måned", "om {0} måneder"),
+        two("om {0} år", "om {0} år"),
+    ],
+};
+
+const NL: RelativeStrings = RelativeStrings {
+    now: "nu",
+    past: [
+        two("{0} minuut geleden", "{0} minuten geleden"),
+        two("{0} uur geleden", "{0} uur geleden"),
+        two("{0} dag geleden", "{0} dagen geleden"),
+        two("{0} week geleden", "{0} weken geleden"),
+        two("{0} maand geleden", "{0} maanden geleden"),
+        two("{0} jaar geleden", "{0} jaar geleden"),
+    ],
+    future: [
+        two("over {0} minuut", "over {0} minuten"),
+        two("over {0} uur", "over {0} uur"),
+        two("over {0} dag", "over {0} dagen"),
+        two("over {0} week", "over {0} weken"),
+        two("over {0} maand", "over {0} maanden"),
+        two("over {0} jaar", "over {0} jaar"),
+    ],
+};
+
+const PL: RelativeStrings = RelativeStrings {
+    now: "teraz",
+    past: [
+        ["{0} minutę temu", "{0} minuty temu", "{0} minut temu"],
+        ["{0} godzinę temu", "{0} godziny temu", "{0} godzin temu"],
+        ["{0} dzień temu", "{0} dni temu", "{0} dni temu"],
+        ["{0} tydzień temu", "{0} tygodnie temu", "{0} tygodni temu"],
+        ["{0} miesiąc temu", "{0} miesiące temu", "{0} miesięcy temu"],
+        ["{0} rok temu", "{0} lata temu", "{0} lat temu"],
+    ],
+    future: [
+        ["za {0} minutę", "za {0} minuty", "za {0} minut"],
+        ["za {0} godzinę", "za {0} godziny", "za {0} godzin"],
+        ["za {0} dzień", "za {0} dni", "za {0} dni"],
+        ["za {0} tydzień", "za {0} tygodnie", "za {0} tygodni"],
+        ["za {0} miesiąc", "za {0} miesiące", "za {0} miesięcy"],
+        ["za {0} rok", "za {0} lata", "za {0} lat"],
+    ],
+};
+
+const PT: RelativeStrings = RelativeStrings {
+    now: "agora",
+    past: [
+        two("há {0} minuto", "há {0} minutos"),
+        two("há {0} hora", "há {0} horas"),
+        two("há {0} dia", "há {0} dias"),
+        two("há {0} semana", "há {0} semanas"),
+        two("há {0} mês", "há {0} meses"),
+        two("há {0} ano", "há {0} anos"),
+    ],
+    future: [
+        two("em {0} minuto", "em {0} minutos"),
+        two("em {0} hora", "em {0} horas"),
+        two("em {0} dia", "em {0} dias"),
+        two("em {0} semana", "em {0} semanas"),
+        two("em {0} mês", "em {0} meses"),
+        two("em {0} ano", "em {0} anos"),
+    ],
+};
+
+const RU: RelativeStrings = RelativeStrings {
+    now: "сейчас",
+    past: [
+        ["{0} минуту назад", "{0} минуты назад", "{0} минут назад"],
+        ["{0} час назад", "{0} часа назад", "{0} часов назад"],
+        ["{0} день назад", "{0} дня назад", "{0} дней назад"],
+        ["{0} неделю назад", "{0} недели назад", "{0} недель назад"],
+        ["{0} месяц назад", "{0} месяца назад", "{0} месяцев назад"],
+        ["{0} год назад", "{0} года назад", "{0} лет назад"],
+    ],
+    future: [
+        ["через {0} минуту", "через {0} минуты", "через {0} минут"],
+        ["через {0} час", "через {0} часа", "через {0} часов"],
+        ["через {0} день", "через {0} дня", "через {0} дней"],
+        ["через {0} неделю", "через {0} недели", "через {0} недель"],
+        ["через {0} месяц", "через {0} месяца", "через {0} месяцев"],
+        ["через {0} год", "через {0} года", "через {0} лет"],
+    ],
+};
+
+const SV: RelativeStrings = RelativeStrings {
+    now: "nu",
+    past: [
+        two("för {0} minut sedan", "för {0} minuter sedan"),
+        two("för {0} timme sedan", "för {0} timmar sedan"),
+        two("för {0} dag sedan", "för {0} dagar sedan"),
+        two("för {0} vecka sedan", "för {0} veckor sedan"),
+        two("för {0} månad sedan", "för {0} månader sedan"),
+        two("för {0} år sedan", "för {0} år sedan"),
+    ],
+    future: [
+        two("om {0} minut", "om {0} minuter"),
+        two("om {0} timme", "om {0} timmar"),
+        two("om {0} dag", "om {0} dagar"),
+        two("om {0} vecka", "om {0} veckor"),
+        two("om {0} månad", "om {0} månader"),
+        two("om {0} år", "om {0} år"),
+    ],
+};
+
+const UK: RelativeStrings = RelativeStrings {
+    now: "зараз",
+    past: [
+        ["{0} хвилину тому", "{0} хвилини тому", "{0} хвилин тому"],
+        ["{0} годину тому", "{0} години тому", "{0} годин тому"],
+        ["{0} день тому", "{0} дні тому", "{0} днів тому"],
+        ["{0} тиждень тому", "{0} тижні тому", "{0} тижнів тому"],
+        ["{0} місяць тому", "{0} місяці тому", "{0} місяців тому"],
+        ["{0} рік тому", "{0} роки тому", "{0} років тому"],
+    ],
+    future: [
+        ["через {0} хвилину", "через {0} хвилини", "через {0} хвилин"],
+        ["через {0} годину", "через {0} години", "через {0} годин"],
+        ["через {0} день", "через {0} дні", "через {0} днів"],
+        ["через {0} тиждень", "через {0} тижні", "через {0} тижнів"],
+        ["через {0} місяць", "через {0} місяці", "через {0} місяців"],
+        ["через {0} рік", "через {0} роки", "через {0} років"],
+    ],
+};
+
+const ZH_CN: RelativeStrings = RelativeStrings {
+    now: "现在",
+    past: [
+        two("{0}分钟前", "{0}分钟前"),
+        two("{0}小时前", "{0}小时前"),
+        two("{0}天前", "{0}天前"),
+        two("{0}周前", "{0}周前"),
+        two("{0}个月前", "{0}个月前"),
+        two("{0}年前", "{0}年前"),
+    ],
+    future: [
+        two("{0}分钟后", "{0}分钟后"),
+        two("{0}小时后", "{0}小时后"),
+        two("{0}天后", "{0}天后"),
+        two("{0}周后", "{0}周后"),
+        two("{0}个月后", "{0}个月后"),
+        two("{0}年后", "{0}年后"),
+    ],
+};
+
+/// Same keys as the shell catalogs.
+const CATALOGS: [(&str, &RelativeStrings); 16] = [
+    ("en", &EN),
+    ("cs", &CS),
+    ("da", &DA),
+    ("de", &DE),
+    ("es", &ES),
+    ("fi", &FI),
+    ("fr", &FR),
+    ("it", &IT),
+    ("nb", &NB),
+    ("nl", &NL),
+    ("pl", &PL),
+    ("pt", &PT),
+    ("ru", &RU),
+    ("sv", &SV),
+    ("uk", &UK),
+    ("zh-CN", &ZH_CN),
+];
+
+/// The unit and count describing `seconds` (absolute), or `None` for "now".
+fn pick_unit(seconds: u64) -> Option<(Unit, u64)> {
+    const MINUTE: f64 = 60.0;
+    const HOUR: f64 = 60.0 * MINUTE;
+    const DAY: f64 = 24.0 * HOUR;
+    if seconds < 45 {
+        return None;
+    }
+    let s = seconds as f64;
+    let count = |unit: f64| ((s / unit).round() as u64).max(1);
+    Some(if s < 45.0 * MINUTE {
+        (Unit::Minute, count(MINUTE))
+    } else if s < 22.0 * HOUR {
+        (Unit::Hour, count(HOUR))
+    } else if s < 6.5 * DAY {
+        (Unit::Day, count(DAY))
+    } else if s < 26.0 * DAY {
+        (Unit::Week, count(7.0 * DAY))
+    } else if s < 345.0 * DAY {
+        (Unit::Month, count(30.44 * DAY).min(11))
+    } else {
+        (Unit::Year, count(365.25 * DAY))
+    })
+}
+
+/// `delta_secs` from now (negative: past) as relative time in `lang`.
+fn relative_time(lang: &str, delta_secs: i64) -> String {
+    let lang = crate::shell_i18n::normalize(lang);
+    let strings = CATALOGS
+        .iter()
+        .find(|(key, _)| *key == lang)
+        .map_or(&EN, |(_, strings)| strings);
+    let Some((unit, count)) = pick_unit(delta_secs.unsigned_abs()) else {
+        return strings.now.to_string();
+    };
+    let forms = if delta_secs < 0 {
+        &strings.past
+    } else {
+        &strings.future
+    };
+    forms[unit as usize][plural(lang, count)].replace("{0}", &count.to_string())
+}
+
+#[tauri::command]
+pub fn get_locale_info() -> LocaleInfo {
+    system()
+}
+
+/// `timestamp_ms` (Unix milliseconds) relative to now, or to `now_ms`:
+/// "5 minutes ago", "in 2 days". `lang` defaults to the system locale.
+#[tauri::command]
+pub fn format_relative_time(
+    timestamp_ms: i64,
+    now_ms: Option<i64>,
+    lang: Option<String>,
+) -> String {
+    let now_ms = now_ms.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let lang = lang.unwrap_or_else(|| system().locale);
+    relative_time(&lang, (timestamp_ms - now_ms) / 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conventions_follow_region_and_extensions() {
+        let us = conventions("en-US");
+        assert_eq!((us.first_day_of_week, us.hour12), (0, true));
+        let gb = conventions("en_GB.UTF-8");
+        assert_eq!(gb.region.as_deref(), Some("GB"));
+        assert_eq!((gb.first_day_of_week, gb.hour12), (1, false));
+        assert_eq!(conventions("en").region.as_deref(), Some("US"));
+        assert!(!conventions("fr-CA").hour12);
+        assert_eq!(conventions("ar-EG").first_day_of_week, 6);
+        let custom = conventions("en-US-u-fw-mon-hc-h23");
+        assert_eq!((custom.first_day_of_week, custom.hour12), (1, false));
+        let rg = conventions("en-GB-u-rg-uszzzz");
+        assert_eq!((rg.region.as_deref(), rg.hour12), (Some("US"), true));
+        assert_eq!(conventions("C").locale, "und");
+    }
+
+    #[test]
+    fn relative_times_pick_unit_and_plural() {
+        assert_eq!(relative_time("en", -30), "now");
+        assert_eq!(relative_time("en", -60), "1 minute ago");
+        assert_eq!(relative_time("en-GB", 3 * 3600), "in 3 hours");
+        assert_eq!(relative_time("de", -2 * 86_400), "vor 2 Tagen");
+        assert_eq!(relative_time("fr", -86_400), "il y a 1 jour");
+        assert_eq!(relative_time("ru", -5 * 60), "5 минут назад");
+        assert_eq!(relative_time("ru", -22 * 60), "22 минуты назад");
+        assert_eq!(relative_time("ru", -21 * 60), "21 минуту назад");
+        assert_eq!(relative_time("pl", -12 * 60), "12 minut temu");
+        assert_eq!(relative_time("cs", 3 * 365 * 86_400), "za 3 roky");
+        assert_eq!(relative_time("ja", -14 * 86_400), "2 weeks ago");
+        assert_eq!(relative_time("zh-Hans", -40 * 86_400), "1个月前");
+    }
+}
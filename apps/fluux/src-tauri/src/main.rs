@@ -5,22 +5,15 @@
 // This uses ctor to run a static constructor before any other code,
 // ensuring the env vars are set before WebKitGTK initializes.
 //
-// WEBKIT_DISABLE_DMABUF_RENDERER is always set to work around a Wayland crash:
-// "Error 71 (Protocol error) dispatching to Wayland display."
-// See: https://github.com/tauri-apps/tauri/issues/10702
-//
-// WEBKIT_DISABLE_COMPOSITING_MODE is additionally set when FLUUX_DISABLE_GPU
-// is defined, for NVIDIA EGL display issues (grey screen / EGL_BAD_PARAMETER).
+// WEBKIT_DISABLE_DMABUF_RENDERER (the Wayland "Error 71 (Protocol error)"
+// crash, https://github.com/tauri-apps/tauri/issues/10702) and
+// WEBKIT_DISABLE_COMPOSITING_MODE (NVIDIA EGL grey screen / EGL_BAD_PARAMETER)
+// are set only on setups that need them; see gpu_workarounds.rs.
+// FLUUX_DISABLE_GPU forces both.
 #[cfg(target_os = "linux")]
 #[ctor::ctor(unsafe)]
 fn set_linux_webkit_env() {
-    // Work around WebKitGTK dmabuf renderer crash on Wayland
-    std::env::set_var("WEBKIT_DISABLE_DMABUF_RENDERER", "1");
-
-    // Full GPU disable on request (for NVIDIA EGL issues)
-    if std::env::var("FLUUX_DISABLE_GPU").is_ok() {
-        std::env::set_var("WEBKIT_DISABLE_COMPOSITING_MODE", "1");
-    }
+    gpu_workarounds::apply();
 
     // Keep the loopback hop to the local XMPP bridge off any system-wide proxy.
     // Must run before WebKitGTK/libsoup initializes its proxy resolver.
@@ -177,33 +170,95 @@ use tauri::WindowEvent;
 use tauri::{Emitter, Manager, RunEvent};
 #[cfg(any(target_os = "macos", target_os = "windows"))]
 use tauri_plugin_window_state::{AppHandleExt, StateFlags};
-// Menu support
-#[cfg(target_os = "macos")]
-use tauri::menu::{MenuBuilder, PredefinedMenuItem, SubmenuBuilder};
 // System tray support for Linux and Windows
 use keyring::Entry;
 use scraper::{Html, Selector};
+use error::FluuxError;
 use serde::{Deserialize, Serialize};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 #[cfg(any(target_os = "linux", target_os = "windows"))]
-use tauri::{
-    menu::{Menu, MenuItem},
-    tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
-};
-#[cfg(any(target_os = "linux", all(debug_assertions, target_os = "windows")))]
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
 use tauri_plugin_deep_link::DeepLinkExt;
 #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
 use tauri_plugin_opener::OpenerExt;
 
+mod error;
+mod json_file;
 mod download;
 mod upload;
+mod upload_resume;
+mod cache;
+mod temp_files;
+mod quarantine;
+mod attachment_open;
+mod updates;
 mod xmpp_proxy;
 mod openpgp;
 mod openpgp_backup;
 mod openpgp_storage;
 mod notifications;
 mod mcp;
+mod unread;
+mod word_watch;
+mod blocking;
+mod screening;
+mod subscriptions;
+mod profile;
+mod caps;
+mod room_cache;
+mod dataforms;
+mod adhoc;
+mod admin;
+mod sessions;
+mod contacts;
+mod file_info;
+mod clipboard;
+mod file_drop;
+mod emoji;
+mod gif;
+mod iq_responder;
+mod voice;
+mod media_probe;
+mod media_server;
+mod shell_i18n;
+mod locale;
+mod exit_policy;
+mod window_prefs;
+mod window_profiles;
+mod managed_policy;
+mod compliance;
+mod scheduled;
+mod auto_reply;
+mod reminders;
+mod receipts;
+mod reactions;
+mod typing;
+mod push;
+mod invites;
+mod bytestreams;
+mod jingle_ft;
+mod cli;
+mod headless;
+mod automation;
+mod triggers;
+mod pep;
+mod settings_sync;
+mod recent_conversations;
+mod rich_presence;
+mod screen_lock;
+mod power;
+mod webview_watchdog;
+mod startup_timings;
+mod memory;
+mod telemetry;
+mod feature_flags;
+mod keychain;
+mod credential_log;
+#[cfg(target_os = "macos")]
+mod apple_events;
+#[cfg(target_os = "macos")]
+mod app_menu;
 
 // Runtime deep-link registration is only required for Linux development and
 // portable distributions; package-managed installs export a canonical desktop
@@ -211,11 +266,20 @@ mod mcp;
 #[cfg(any(target_os = "linux", test))]
 mod linux_deep_link;
 
+// WebKitGTK rendering workarounds chosen from a probe of the machine (pure
+// policy compiled in tests on every platform).
+#[cfg(any(target_os = "linux", test))]
+mod gpu_workarounds;
+
 // Linux tray-functionality detection (pure combiner compiled everywhere; the
 // DBus probe inside is Linux-only).
 mod linux_tray;
 mod window_behavior;
 
+// Session-bus service (`io.fluux.Messenger`) for desktop integration.
+#[cfg(target_os = "linux")]
+mod dbus_service;
+
 #[cfg(any(target_os = "linux", target_os = "windows"))]
 const MAIN_TRAY_ID: &str = "fluux-main-tray";
 
@@ -385,8 +449,8 @@ mod idle {
 
 /// Get the system idle time in seconds
 #[tauri::command]
-fn get_idle_time() -> Result<u64, String> {
-    idle::get_idle_seconds()
+fn get_idle_time() -> Result<u64, FluuxError> {
+    idle::get_idle_seconds().map_err(FluuxError::Internal)
 }
 
 // Keyring service name for storing credentials
@@ -405,32 +469,39 @@ pub struct StoredCredentials {
 /// framework message (e.g., "User canceled the operation", "The specified item
 /// already exists in the keychain"). This function checks the error string to
 /// provide a clearer diagnosis.
-fn classify_keyring_error(e: &keyring::Error, operation: &str) -> String {
+fn keyring_error(e: &keyring::Error, operation: &str) -> FluuxError {
     let msg = e.to_string();
     if msg.contains("User canceled") || msg.contains("user canceled") {
-        format!("Keychain access denied by user during {}", operation)
+        FluuxError::KeychainLocked(format!(
+            "Keychain access denied by user during {}",
+            operation
+        ))
     } else if msg.contains("already exists") {
         // This can also surface when access is denied on some macOS versions
-        format!(
+        FluuxError::Keychain(format!(
             "Keychain access conflict during {} (item may exist or access was denied)",
             operation
-        )
+        ))
     } else {
         match e {
-            keyring::Error::NoStorageAccess(_) => {
-                format!(
-                    "Keychain locked or inaccessible during {}: {}",
-                    operation, msg
-                )
-            }
-            keyring::Error::PlatformFailure(_) => {
-                format!("Keychain platform error during {}: {}", operation, msg)
-            }
-            _ => format!("Keychain error during {}: {}", operation, msg),
+            keyring::Error::NoStorageAccess(_) => FluuxError::KeychainLocked(format!(
+                "Keychain locked or inaccessible during {}: {}",
+                operation, msg
+            )),
+            keyring::Error::PlatformFailure(_) => FluuxError::Keychain(format!(
+                "Keychain platform error during {}: {}",
+                operation, msg
+            )),
+            _ => FluuxError::Keychain(format!("Keychain error during {}: {}", operation, msg)),
         }
     }
 }
 
+/// [`keyring_error`] as text, for the modules reporting string errors.
+fn classify_keyring_error(e: &keyring::Error, operation: &str) -> String {
+    keyring_error(e, operation).to_string()
+}
+
 /// Save credentials to OS keychain.
 /// Runs on a background thread to avoid blocking the main thread when
 /// macOS shows a keychain authorization dialog.
@@ -439,11 +510,11 @@ async fn save_credentials(
     jid: String,
     password: String,
     server: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), FluuxError> {
     tokio::task::spawn_blocking(move || {
         let entry = Entry::new(KEYRING_SERVICE, &jid).map_err(|e| {
             tracing::error!("Keychain: failed to create entry for {}: {}", jid, e);
-            format!("Failed to create keyring entry: {}", e)
+            FluuxError::Keychain(format!("Failed to create keyring entry: {}", e))
         })?;
 
         let credentials = StoredCredentials {
@@ -457,45 +528,45 @@ async fn save_credentials(
                 jid,
                 e
             );
-            format!("Failed to serialize credentials: {}", e)
+            FluuxError::Internal(format!("Failed to serialize credentials: {}", e))
         })?;
 
         entry.set_password(&json).map_err(|e| {
-            let desc = classify_keyring_error(&e, "save");
-            tracing::error!("Keychain: {} for {}", desc, jid);
-            desc
+            let error = keyring_error(&e, "save");
+            tracing::error!("Keychain: {} for {}", error, jid);
+            error
         })?;
 
         // Also store the JID as the "last user" so we know which account to load
         let last_user_entry = Entry::new(KEYRING_SERVICE, "last_user").map_err(|e| {
             tracing::error!("Keychain: failed to create last_user entry: {}", e);
-            format!("Failed to create last_user entry: {}", e)
+            FluuxError::Keychain(format!("Failed to create last_user entry: {}", e))
         })?;
         last_user_entry
             .set_password(&credentials.jid)
             .map_err(|e| {
-                let desc = classify_keyring_error(&e, "save last_user");
-                tracing::error!("Keychain: {} for {}", desc, jid);
-                desc
+                let error = keyring_error(&e, "save last_user");
+                tracing::error!("Keychain: {} for {}", error, jid);
+                error
             })?;
 
         tracing::info!("Keychain: saved credentials for {}", jid);
         Ok(())
     })
     .await
-    .map_err(|e| format!("Keychain task panicked: {}", e))?
+    .map_err(|e| FluuxError::Internal(format!("Keychain task panicked: {}", e)))?
 }
 
 /// Get credentials from OS keychain.
 /// Runs on a background thread to avoid blocking the main thread when
 /// macOS shows a keychain authorization dialog.
 #[tauri::command]
-async fn get_credentials() -> Result<Option<StoredCredentials>, String> {
+async fn get_credentials() -> Result<Option<StoredCredentials>, FluuxError> {
     tokio::task::spawn_blocking(move || {
         // First get the last used JID
         let last_user_entry = Entry::new(KEYRING_SERVICE, "last_user").map_err(|e| {
             tracing::error!("Keychain: failed to create last_user entry: {}", e);
-            format!("Failed to create last_user entry: {}", e)
+            FluuxError::Keychain(format!("Failed to create last_user entry: {}", e))
         })?;
 
         let jid = match last_user_entry.get_password() {
@@ -505,23 +576,23 @@ async fn get_credentials() -> Result<Option<StoredCredentials>, String> {
                 return Ok(None);
             }
             Err(e) => {
-                let desc = classify_keyring_error(&e, "read last_user");
-                tracing::error!("Keychain: {}", desc);
-                return Err(desc);
+                let error = keyring_error(&e, "read last_user");
+                tracing::error!("Keychain: {}", error);
+                return Err(error);
             }
         };
 
         // Now get the credentials for that JID
         let entry = Entry::new(KEYRING_SERVICE, &jid).map_err(|e| {
             tracing::error!("Keychain: failed to create entry for {}: {}", jid, e);
-            format!("Failed to create keyring entry: {}", e)
+            FluuxError::Keychain(format!("Failed to create keyring entry: {}", e))
         })?;
 
-        match entry.get_password() {
+        match credential_log::read(&entry, &jid, "saved login") {
             Ok(json) => {
                 let credentials: StoredCredentials = serde_json::from_str(&json).map_err(|e| {
                     tracing::error!("Keychain: failed to parse credentials for {}: {}", jid, e);
-                    format!("Failed to parse credentials: {}", e)
+                    FluuxError::Keychain(format!("Failed to parse credentials: {}", e))
                 })?;
                 tracing::info!("Keychain: loaded credentials for {}", jid);
                 Ok(Some(credentials))
@@ -531,65 +602,68 @@ async fn get_credentials() -> Result<Option<StoredCredentials>, String> {
                 Ok(None)
             }
             Err(e) => {
-                let desc = classify_keyring_error(&e, &format!("read credentials for {}", jid));
-                tracing::error!("Keychain: {}", desc);
-                Err(desc)
+                let error = keyring_error(&e, &format!("read credentials for {}", jid));
+                tracing::error!("Keychain: {}", error);
+                Err(error)
             }
         }
     })
     .await
-    .map_err(|e| format!("Keychain task panicked: {}", e))?
+    .map_err(|e| FluuxError::Internal(format!("Keychain task panicked: {}", e)))?
 }
 
-/// Delete credentials from OS keychain.
-/// Runs on a background thread to avoid blocking the main thread when
-/// macOS shows a keychain authorization dialog.
-#[tauri::command]
-async fn delete_credentials() -> Result<(), String> {
-    tokio::task::spawn_blocking(move || {
-        // Get the last used JID
-        let last_user_entry = Entry::new(KEYRING_SERVICE, "last_user").map_err(|e| {
-            tracing::error!("Keychain: failed to create last_user entry: {}", e);
-            format!("Failed to create last_user entry: {}", e)
-        })?;
-
-        if let Ok(jid) = last_user_entry.get_password() {
-            // Delete the credentials entry
-            let entry = Entry::new(KEYRING_SERVICE, &jid).map_err(|e| {
-                tracing::error!("Keychain: failed to create entry for {}: {}", jid, e);
-                format!("Failed to create keyring entry: {}", e)
-            })?;
-            match entry.delete_credential() {
-                Ok(()) => tracing::info!("Keychain: deleted credentials for {}", jid),
-                Err(keyring::Error::NoEntry) => {
-                    tracing::debug!("Keychain: no credentials to delete for {}", jid)
-                }
-                Err(e) => {
-                    let desc =
-                        classify_keyring_error(&e, &format!("delete credentials for {}", jid));
-                    tracing::warn!("Keychain: {}", desc);
-                }
-            }
-        } else {
-            tracing::debug!("Keychain: no last_user entry to look up for deletion");
-        }
+/// Delete the stored credentials and the last-user entry from the OS
+/// keychain. Blocking; shared with the `clear-credentials` subcommand.
+fn clear_stored_credentials() -> Result<(), FluuxError> {
+    // Get the last used JID
+    let last_user_entry = Entry::new(KEYRING_SERVICE, "last_user").map_err(|e| {
+        tracing::error!("Keychain: failed to create last_user entry: {}", e);
+        FluuxError::Keychain(format!("Failed to create last_user entry: {}", e))
+    })?;
 
-        // Delete the last_user entry
-        match last_user_entry.delete_credential() {
-            Ok(()) => tracing::debug!("Keychain: deleted last_user entry"),
+    if let Ok(jid) = last_user_entry.get_password() {
+        // Delete the credentials entry
+        let entry = Entry::new(KEYRING_SERVICE, &jid).map_err(|e| {
+            tracing::error!("Keychain: failed to create entry for {}: {}", jid, e);
+            FluuxError::Keychain(format!("Failed to create keyring entry: {}", e))
+        })?;
+        match entry.delete_credential() {
+            Ok(()) => tracing::info!("Keychain: deleted credentials for {}", jid),
             Err(keyring::Error::NoEntry) => {
-                tracing::debug!("Keychain: no last_user entry to delete")
+                tracing::debug!("Keychain: no credentials to delete for {}", jid)
             }
             Err(e) => {
-                let desc = classify_keyring_error(&e, "delete last_user");
+                let desc = classify_keyring_error(&e, &format!("delete credentials for {}", jid));
                 tracing::warn!("Keychain: {}", desc);
             }
         }
+    } else {
+        tracing::debug!("Keychain: no last_user entry to look up for deletion");
+    }
 
-        Ok(())
-    })
-    .await
-    .map_err(|e| format!("Keychain task panicked: {}", e))?
+    // Delete the last_user entry
+    match last_user_entry.delete_credential() {
+        Ok(()) => tracing::debug!("Keychain: deleted last_user entry"),
+        Err(keyring::Error::NoEntry) => {
+            tracing::debug!("Keychain: no last_user entry to delete")
+        }
+        Err(e) => {
+            let desc = classify_keyring_error(&e, "delete last_user");
+            tracing::warn!("Keychain: {}", desc);
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete credentials from OS keychain.
+/// Runs on a background thread to avoid blocking the main thread when
+/// macOS shows a keychain authorization dialog.
+#[tauri::command]
+async fn delete_credentials() -> Result<(), FluuxError> {
+    tokio::task::spawn_blocking(clear_stored_credentials)
+        .await
+        .map_err(|e| FluuxError::Internal(format!("Keychain task panicked: {}", e)))?
 }
 
 /// Exit the app (called by frontend after graceful disconnect)
@@ -611,8 +685,10 @@ const STOP_XMPP_PROXY_COMMAND_TIMEOUT: std::time::Duration = std::time::Duration
 async fn start_xmpp_proxy(
     app: tauri::AppHandle,
     server: String,
-) -> Result<xmpp_proxy::ProxyStartResult, String> {
-    tokio::time::timeout(
+) -> Result<xmpp_proxy::ProxyStartResult, FluuxError> {
+    managed_policy::check_server(&server).map_err(FluuxError::Policy)?;
+    startup_timings::begin(startup_timings::PROXY_START);
+    let result = tokio::time::timeout(
         START_XMPP_PROXY_COMMAND_TIMEOUT,
         xmpp_proxy::start_proxy(server, Some(app)),
     )
@@ -622,16 +698,21 @@ async fn start_xmpp_proxy(
             timeout_secs = START_XMPP_PROXY_COMMAND_TIMEOUT.as_secs(),
             "start_xmpp_proxy command timed out"
         );
-        format!(
+        FluuxError::Timeout(format!(
             "start_xmpp_proxy timed out after {}s",
             START_XMPP_PROXY_COMMAND_TIMEOUT.as_secs()
-        )
-    })?
+        ))
+    })?;
+    if result.is_ok() {
+        startup_timings::end(startup_timings::PROXY_START);
+        startup_timings::begin(startup_timings::FIRST_CONNECT);
+    }
+    result
 }
 
 /// Stop XMPP WebSocket-to-TCP proxy
 #[tauri::command]
-async fn stop_xmpp_proxy() -> Result<(), String> {
+async fn stop_xmpp_proxy() -> Result<(), FluuxError> {
     tokio::time::timeout(STOP_XMPP_PROXY_COMMAND_TIMEOUT, xmpp_proxy::stop_proxy())
         .await
         .map_err(|_| {
@@ -639,13 +720,37 @@ async fn stop_xmpp_proxy() -> Result<(), String> {
                 timeout_secs = STOP_XMPP_PROXY_COMMAND_TIMEOUT.as_secs(),
                 "stop_xmpp_proxy command timed out"
             );
-            format!(
+            FluuxError::Timeout(format!(
                 "stop_xmpp_proxy timed out after {}s",
                 STOP_XMPP_PROXY_COMMAND_TIMEOUT.as_secs()
-            )
+            ))
         })?
 }
 
+/// Proxy liveness and the client connections attached to it, so the
+/// frontend can check that the URL it holds is still served.
+#[tauri::command]
+async fn get_proxy_status() -> xmpp_proxy::ProxyStatus {
+    xmpp_proxy::proxy_status().await
+}
+
+/// The WebView failed to open the proxy URL it holds: diagnose the loopback
+/// hop and move the proxy if needed. Returns the URL to connect to now.
+#[tauri::command]
+async fn report_proxy_unreachable(url: String) -> Result<xmpp_proxy::ProxyStartResult, FluuxError> {
+    tokio::time::timeout(
+        START_XMPP_PROXY_COMMAND_TIMEOUT,
+        xmpp_proxy::report_unreachable(url),
+    )
+    .await
+    .map_err(|_| {
+        FluuxError::Timeout(format!(
+            "report_proxy_unreachable timed out after {}s",
+            START_XMPP_PROXY_COMMAND_TIMEOUT.as_secs()
+        ))
+    })?
+}
+
 /// Keychain slot for the MCP bearer token. Persisting it (instead of minting
 /// one per launch) keeps the user's MCP client config working across app
 /// restarts without ever writing a plaintext token file to disk.
@@ -654,26 +759,27 @@ const MCP_TOKEN_KEYRING_USER: &str = "mcp-token";
 /// Load the persisted MCP token, creating one on first use. `regenerate`
 /// discards any existing token (the Settings "reset token" action, revoking
 /// access for previously configured clients).
-async fn mcp_load_or_create_token(regenerate: bool) -> Result<String, String> {
+async fn mcp_load_or_create_token(regenerate: bool) -> Result<String, FluuxError> {
     tokio::task::spawn_blocking(move || {
-        let entry = Entry::new(KEYRING_SERVICE, MCP_TOKEN_KEYRING_USER)
-            .map_err(|e| format!("Failed to create keyring entry for MCP token: {e}"))?;
+        let entry = Entry::new(KEYRING_SERVICE, MCP_TOKEN_KEYRING_USER).map_err(|e| {
+            FluuxError::Keychain(format!("Failed to create keyring entry for MCP token: {e}"))
+        })?;
         if !regenerate {
-            match entry.get_password() {
+            match credential_log::read(&entry, MCP_TOKEN_KEYRING_USER, "MCP server token") {
                 Ok(token) if !token.is_empty() => return Ok(token),
                 _ => {}
             }
         }
         let token = uuid::Uuid::new_v4().to_string();
         entry.set_password(&token).map_err(|e| {
-            let desc = classify_keyring_error(&e, "save MCP token");
-            tracing::error!("Keychain: {}", desc);
-            desc
+            let error = keyring_error(&e, "save MCP token");
+            tracing::error!("Keychain: {}", error);
+            error
         })?;
         Ok(token)
     })
     .await
-    .map_err(|e| format!("Keychain task panicked: {e}"))?
+    .map_err(|e| FluuxError::Internal(format!("Keychain task panicked: {e}")))?
 }
 
 /// Start the local MCP server (Model Context Protocol) for Claude
@@ -685,10 +791,12 @@ async fn mcp_start_server(
     app: tauri::AppHandle,
     pending: tauri::State<'_, Arc<mcp::bridge::PendingRequests>>,
     preferred_port: Option<u16>,
-) -> Result<mcp::server::McpServerInfo, String> {
+) -> Result<mcp::server::McpServerInfo, FluuxError> {
     let token = mcp_load_or_create_token(false).await?;
     let executor = Arc::new(mcp::bridge::TauriBridgeExecutor::new(app, pending.inner().clone()));
-    mcp::server::start(executor, preferred_port, token).await
+    mcp::server::start(executor, preferred_port, token)
+        .await
+        .map_err(FluuxError::LocalServer)
 }
 
 /// Regenerate the MCP bearer token (revoking previously configured clients)
@@ -698,16 +806,18 @@ async fn mcp_reset_token(
     app: tauri::AppHandle,
     pending: tauri::State<'_, Arc<mcp::bridge::PendingRequests>>,
     preferred_port: Option<u16>,
-) -> Result<mcp::server::McpServerInfo, String> {
+) -> Result<mcp::server::McpServerInfo, FluuxError> {
     let token = mcp_load_or_create_token(true).await?;
     let executor = Arc::new(mcp::bridge::TauriBridgeExecutor::new(app, pending.inner().clone()));
-    mcp::server::start(executor, preferred_port, token).await
+    mcp::server::start(executor, preferred_port, token)
+        .await
+        .map_err(FluuxError::LocalServer)
 }
 
 /// Stop the local MCP server.
 #[tauri::command]
-async fn mcp_stop_server() -> Result<(), String> {
-    mcp::server::stop().await
+async fn mcp_stop_server() -> Result<(), FluuxError> {
+    mcp::server::stop().await.map_err(FluuxError::LocalServer)
 }
 
 /// Open Graph metadata extracted from a URL
@@ -729,19 +839,35 @@ pub struct UrlMetadata {
 /// the duration of the fetch — most visibly on Linux/WebKitGTK, where the
 /// webview renders on that same thread.
 #[tauri::command]
-async fn fetch_url_metadata(url: String) -> Result<UrlMetadata, String> {
+async fn fetch_url_metadata(url: String) -> Result<UrlMetadata, FluuxError> {
+    if managed_policy::current().disable_link_previews {
+        return Err(FluuxError::Policy(
+            "Link previews are disabled by your administrator".to_string(),
+        ));
+    }
+    if !feature_flags::enabled(feature_flags::LINK_PREVIEWS) {
+        return Err(FluuxError::Policy(
+            "Link previews are temporarily disabled".to_string(),
+        ));
+    }
     tauri::async_runtime::spawn_blocking(move || fetch_url_metadata_blocking(url))
         .await
-        .unwrap_or_else(|join_err| Err(format!("Link preview task panicked: {join_err}")))
+        .unwrap_or_else(|join_err| {
+            Err(FluuxError::Internal(format!(
+                "Link preview task panicked: {join_err}"
+            )))
+        })
 }
 
 /// Blocking implementation of [`fetch_url_metadata`]. Runs off the main thread.
 /// Uses `reqwest::blocking` and `scraper` (whose parsed document is `!Send`, so
 /// it must live entirely within this synchronous function).
-fn fetch_url_metadata_blocking(url: String) -> Result<UrlMetadata, String> {
+fn fetch_url_metadata_blocking(url: String) -> Result<UrlMetadata, FluuxError> {
     // Validate URL
     if !url.starts_with("http://") && !url.starts_with("https://") {
-        return Err("Invalid URL: must start with http:// or https://".to_string());
+        return Err(FluuxError::InvalidInput(
+            "Invalid URL: must start with http:// or https://".to_string(),
+        ));
     }
 
     // Create HTTP client with reasonable timeout and user agent
@@ -751,13 +877,18 @@ fn fetch_url_metadata_blocking(url: String) -> Result<UrlMetadata, String> {
         .build()
         .map_err(|e| {
             tracing::warn!(url = %url, "Link preview: failed to create HTTP client: {}", e);
-            format!("Failed to create HTTP client: {}", e)
+            FluuxError::Internal(format!("Failed to create HTTP client: {}", e))
         })?;
 
     // Fetch the URL
     let response = client.get(&url).send().map_err(|e| {
         tracing::warn!(url = %url, "Link preview: failed to fetch URL: {}", e);
-        format!("Failed to fetch URL: {}", e)
+        let detail = format!("Failed to fetch URL: {}", e);
+        if e.is_timeout() {
+            FluuxError::Timeout(detail)
+        } else {
+            FluuxError::Network(detail)
+        }
     })?;
 
     // Check content type - only process HTML
@@ -769,12 +900,14 @@ fn fetch_url_metadata_blocking(url: String) -> Result<UrlMetadata, String> {
 
     if !content_type.contains("text/html") {
         tracing::debug!(url = %url, content_type, "Link preview: non-HTML content type, skipping");
-        return Err("URL does not return HTML content".to_string());
+        return Err(FluuxError::Server(
+            "URL does not return HTML content".to_string(),
+        ));
     }
 
     let html = response.text().map_err(|e| {
         tracing::warn!(url = %url, "Link preview: failed to read response body: {}", e);
-        format!("Failed to read response: {}", e)
+        FluuxError::Network(format!("Failed to read response: {}", e))
     })?;
 
     let metadata = parse_og_metadata(&url, &html);
@@ -784,7 +917,9 @@ fn fetch_url_metadata_blocking(url: String) -> Result<UrlMetadata, String> {
         Ok(metadata)
     } else {
         tracing::debug!(url = %url, "Link preview: no title found in page metadata");
-        Err("Could not extract metadata from URL".to_string())
+        Err(FluuxError::Server(
+            "Could not extract metadata from URL".to_string(),
+        ))
     }
 }
 
@@ -1090,6 +1225,7 @@ mod macos {
                 &RcBlock::new(move |_notification: NonNull<NSNotification>| {
                     // Clear any pending wake (shouldn't happen, but be safe)
                     PENDING_WAKE_TIME.store(0, Ordering::SeqCst);
+                    crate::power::set_asleep(true);
                     // Emit event to frontend so it can set XA presence
                     let _ = sleep_handle.emit("system-will-sleep", ());
                 }),
@@ -1109,6 +1245,7 @@ mod macos {
                         .map(|d| d.as_secs())
                         .unwrap_or(0);
                     PENDING_WAKE_TIME.store(now, Ordering::SeqCst);
+                    crate::power::set_asleep(false);
 
                     // Probe the display state before emitting so the webview
                     // can distinguish DarkWake/PowerNap (display asleep, no
@@ -1150,10 +1287,14 @@ fn build_keepalive_payload(display_active: bool, slept_ms: u64) -> KeepalivePayl
     }
 }
 
-/// Native keepalive cadence. The thread emits an `xmpp-keepalive` event every
-/// `KEEPALIVE_INTERVAL`, regardless of display state.
+/// Native keepalive cadence. The task emits an `xmpp-keepalive` event every
+/// `KEEPALIVE_INTERVAL` without traffic, regardless of display state.
 const KEEPALIVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
 
+/// Longest time traffic can push the keepalive tick back, so the WebView
+/// still hears the display state regularly on a busy connection.
+const KEEPALIVE_MAX_DEFERRAL: std::time::Duration = std::time::Duration::from_secs(120);
+
 /// Floor above the interval beyond which an iteration's measured wall-clock
 /// elapsed is attributed to the machine having slept rather than to scheduler
 /// jitter. `30s + 90s = 120s`, well above any plausible jitter and aligned
@@ -1188,6 +1329,22 @@ fn next_wait(slept: Option<u64>) -> std::time::Duration {
     }
 }
 
+/// How much longer to wait once a keepalive tick is due. Traffic `quiet` ago,
+/// within the `interval`, shows the connection is alive, so the tick moves to
+/// one interval after it, unless ticks have already been held back for
+/// `since_tick` past `KEEPALIVE_MAX_DEFERRAL`. `None` fires now. Pure seam.
+fn keepalive_deferral(
+    quiet: std::time::Duration,
+    interval: std::time::Duration,
+    since_tick: std::time::Duration,
+) -> Option<std::time::Duration> {
+    if quiet >= interval || since_tick >= KEEPALIVE_MAX_DEFERRAL {
+        None
+    } else {
+        Some(interval - quiet)
+    }
+}
+
 /// One keepalive iteration's pure work: detect a sleep gap from the measured
 /// `elapsed`, probe the display state **fresh** (so a transient stuck reading
 /// can't poison later ticks), build the payload, and compute the next wait.
@@ -1206,7 +1363,7 @@ fn keepalive_step<F: Fn() -> bool>(
     (payload, next_wait(slept))
 }
 
-/// Crate-level display-active probe for the keepalive thread. Fails open:
+/// Crate-level display-active probe for the keepalive task. Fails open:
 /// returns `true` on platforms without a display-sleep probe, and the macOS
 /// `CGDisplayIsAsleep` path is documented to default active on any ambiguity.
 /// Failing open is mandatory — since `system-did-wake` is demoted to
@@ -1241,8 +1398,8 @@ fn log_to_terminal(level: String, message: String) {
 /// `notifications::settings_pane`. A failed launch is returned to the caller,
 /// which surfaces it in the UI.
 #[tauri::command]
-fn open_notification_settings() -> Result<(), String> {
-    notifications::settings_pane::open()
+fn open_notification_settings() -> Result<(), FluuxError> {
+    notifications::settings_pane::open().map_err(FluuxError::Internal)
 }
 
 #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
@@ -1250,10 +1407,10 @@ fn open_notification_settings() -> Result<(), String> {
 fn open_logs_folder(
     app: tauri::AppHandle,
     log_directory: tauri::State<'_, LogDirectory>,
-) -> Result<(), String> {
+) -> Result<(), FluuxError> {
     app.opener()
         .reveal_item_in_dir(&log_directory.0)
-        .map_err(|error| error.to_string())
+        .map_err(|error| FluuxError::Internal(error.to_string()))
 }
 
 #[cfg(any(target_os = "linux", target_os = "windows"))]
@@ -1287,7 +1444,7 @@ fn set_keep_in_tray(
     app: tauri::AppHandle,
     behavior: tauri::State<'_, window_behavior::WindowBehavior>,
     enabled: bool,
-) -> Result<TrayStatus, String> {
+) -> Result<TrayStatus, FluuxError> {
     let Some(tray) = app.tray_by_id(MAIN_TRAY_ID) else {
         behavior.set_keep_in_tray(false);
         return Ok(TrayStatus {
@@ -1300,7 +1457,7 @@ fn set_keep_in_tray(
         // Prefer native close/quit behavior over believing an icon is visible
         // and stranding the window.
         behavior.set_keep_in_tray(false);
-        return Err(error.to_string());
+        return Err(FluuxError::Internal(error.to_string()));
     }
 
     behavior.set_keep_in_tray(enabled);
@@ -1316,14 +1473,20 @@ fn begin_graceful_shutdown(
     keepalive_running: &Arc<AtomicBool>,
     graceful_shutdown_started: &Arc<AtomicBool>,
 ) {
-    keepalive_running.store(false, Ordering::Relaxed);
-    graceful_shutdown_started.store(true, Ordering::Relaxed);
-    let _ = app.emit("graceful-shutdown", ());
+    if graceful_shutdown_started.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let keepalive_running = keepalive_running.clone();
+    let graceful_shutdown_started = graceful_shutdown_started.clone();
     let handle = app.clone();
-    std::thread::spawn(move || {
-        std::thread::sleep(std::time::Duration::from_secs(2));
-        handle.exit(0);
-    });
+    exit_policy::confirm_quit(
+        app,
+        move || {
+            keepalive_running.store(false, Ordering::Relaxed);
+            exit_policy::drain_then_exit(&handle);
+        },
+        move || graceful_shutdown_started.store(false, Ordering::Relaxed),
+    );
 }
 
 /// Print startup diagnostics to stderr for debugging.
@@ -1341,19 +1504,20 @@ fn print_startup_diagnostics() {
 
     #[cfg(target_os = "linux")]
     {
-        let compositing_disabled = std::env::var("WEBKIT_DISABLE_COMPOSITING_MODE")
-            .map(|v| v == "1")
-            .unwrap_or(false);
+        let is_set = |name: &str| std::env::var(name).map(|v| v == "1").unwrap_or(false);
+        let on_off = |set: bool| if set { "enabled" } else { "disabled" };
 
         eprintln!("WebKitGTK GPU settings:");
-        eprintln!("  WEBKIT_DISABLE_DMABUF_RENDERER: always enabled (Wayland crash workaround)");
+        if let Some((_, reason)) = gpu_workarounds::applied() {
+            eprintln!("  Detected workarounds: {reason}");
+        }
         eprintln!(
-            "  WEBKIT_DISABLE_COMPOSITING_MODE: {}",
-            if compositing_disabled {
-                "enabled (FLUUX_DISABLE_GPU set)"
-            } else {
-                "disabled (set FLUUX_DISABLE_GPU to enable)"
-            }
+            "  WEBKIT_DISABLE_DMABUF_RENDERER: {}",
+            on_off(is_set("WEBKIT_DISABLE_DMABUF_RENDERER"))
+        );
+        eprintln!(
+            "  WEBKIT_DISABLE_COMPOSITING_MODE: {} (set FLUUX_DISABLE_GPU to force)",
+            on_off(is_set("WEBKIT_DISABLE_COMPOSITING_MODE"))
         );
     }
 
@@ -1366,6 +1530,9 @@ fn print_startup_diagnostics() {
 }
 
 fn main() {
+    startup_timings::init();
+    startup_timings::begin(startup_timings::ENV_SETUP);
+
     // Keep the loopback hop to the local XMPP bridge off any system-wide proxy.
     // On Linux this also runs from a pre-main ctor (before WebKitGTK init); on
     // macOS/Windows this is the earliest hook before the webview is created.
@@ -1378,6 +1545,17 @@ fn main() {
         .any(|arg| arg == "--clear-storage" || arg == "-c");
     let dangerous_insecure_tls = args.iter().any(|arg| arg == "--dangerous-insecure-tls");
 
+    // Managed deployments can lock TLS verification on; read the policy
+    // before the flag takes effect.
+    let policy = managed_policy::init();
+    let dangerous_insecure_tls = if dangerous_insecure_tls && policy.lock_tls {
+        eprintln!("WARNING: --dangerous-insecure-tls ignored: TLS is locked by managed policy");
+        false
+    } else {
+        dangerous_insecure_tls
+    };
+    xmpp_proxy::set_require_direct_tls(policy.require_direct_tls);
+
     // Set insecure TLS flag before any proxy can start
     xmpp_proxy::set_dangerous_insecure_tls(dangerous_insecure_tls);
     if dangerous_insecure_tls {
@@ -1385,6 +1563,15 @@ fn main() {
         eprintln!("         This is insecure and should only be used for development/testing.");
     }
 
+    let headless = headless::options(&args);
+    let metrics_port = xmpp_proxy::metrics::port(&args);
+
+    let mock_server = args.iter().any(|arg| arg == "--mock-server");
+    xmpp_proxy::mock::set_mock_server(mock_server);
+    if mock_server {
+        eprintln!("Mock server mode: connections are answered in-process (--mock-server)");
+    }
+
     // Parse verbose level: --verbose / -v (default, no XMPP packets) or --verbose=xmpp (with packets)
     let verbose_level = args.iter().find_map(|arg| {
         if arg == "--verbose" || arg == "-v" {
@@ -1406,6 +1593,9 @@ fn main() {
         eprintln!("Fluux Messenger v{}", env!("CARGO_PKG_VERSION"));
         eprintln!();
         eprintln!("Usage: fluux-messenger [OPTIONS]");
+        eprintln!("       fluux-messenger <COMMAND> [ARGS]");
+        eprintln!();
+        eprintln!("{}", cli::USAGE);
         eprintln!();
         eprintln!("Options:");
         eprintln!("  -v, --verbose         Enable verbose logging to stderr (no XMPP traffic)");
@@ -1416,6 +1606,11 @@ fn main() {
         eprintln!("  -c, --clear-storage   Clear local storage on startup");
         eprintln!("      --dangerous-insecure-tls");
         eprintln!("                        Disable TLS certificate verification (INSECURE!)");
+        eprintln!("      --mock-server     Answer connections with an in-process fake server");
+        eprintln!("      --headless        Run without a window, with a local control API");
+        eprintln!("      --api-port=PORT   Port of the headless control API (default: any free)");
+        eprintln!("      --metrics-port=PORT");
+        eprintln!("                        Serve Prometheus metrics on 127.0.0.1:PORT/metrics");
         eprintln!("  -h, --help            Show this help message");
         eprintln!();
         eprintln!("Logs are always written to a daily-rotating file in:");
@@ -1429,6 +1624,18 @@ fn main() {
         std::process::exit(0);
     }
 
+    // Hidden: measure the proxy bridge on loopback and exit.
+    if args.iter().any(|arg| arg == "--bench-bridge") {
+        match tauri::async_runtime::block_on(xmpp_proxy::bench::run()) {
+            Ok(report) => print!("{report}"),
+            Err(e) => {
+                eprintln!("Bridge benchmark failed: {e}");
+                std::process::exit(1);
+            }
+        }
+        std::process::exit(0);
+    }
+
     // Determine the log directory: --log-file=<path> overrides the default platform path
     let log_dir = if let Some(ref path) = log_file_path {
         std::path::PathBuf::from(path)
@@ -1451,10 +1658,19 @@ fn main() {
         dir
     };
 
+    // Terminal-only subcommands (doctor, logs, clear-credentials) exit here,
+    // before any logging or window setup.
+    if let Some(code) = cli::run(&args, &log_dir) {
+        std::process::exit(code);
+    }
+
+    startup_timings::end(startup_timings::ENV_SETUP);
+
     // Initialize tracing subscriber:
     // - Always write to a log file in the platform log directory (for bug reports)
     // - Optionally add stderr output when --verbose is passed
     {
+        let _tracing_init = startup_timings::span(startup_timings::TRACING_INIT);
         use tracing_subscriber::prelude::*;
         use tracing_subscriber::EnvFilter;
 
@@ -1535,8 +1751,11 @@ fn main() {
     if verbose || log_file_path.is_some() {
         print_startup_diagnostics();
     }
+    if policy.managed {
+        tracing::info!(?policy, "Managed policy in force");
+    }
 
-    // Shared flag to signal the keepalive thread to stop on app exit
+    // Shared flag to signal the keepalive task to stop on app exit
     let keepalive_running = Arc::new(AtomicBool::new(true));
     let keepalive_flag_for_setup = keepalive_running.clone();
     let keepalive_flag_for_run = keepalive_running.clone();
@@ -1548,11 +1767,12 @@ fn main() {
     // Tray "Quit" (and the Linux no-tray X-close) start the graceful shutdown
     // themselves, so they must claim the flag too. Otherwise the frontend's
     // follow-up `exit_app` looks like a *first* exit request to the run handler
-    // below, which prevents it — leaving the 2s fallback timer as the only way
+    // below, which prevents it — leaving the fallback timer as the only way
     // out, i.e. every tray quit force-killed instead of exiting cleanly.
     // Underscore-prefixed: only the linux/windows cfg blocks below consume it.
     let _graceful_shutdown_flag_for_setup = graceful_shutdown_started.clone();
 
+    let tauri_build = startup_timings::span(startup_timings::TAURI_BUILD);
     let app = tauri::Builder::default()
         // Single-instance guard MUST be the first plugin registered (Tauri
         // requirement). When a second copy of Fluux is launched, the OS lock is
@@ -1580,7 +1800,11 @@ fn main() {
         .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
+        .register_asynchronous_uri_scheme_protocol(media_server::SCHEME, |ctx, request, responder| {
+            media_server::handle(ctx.app_handle(), request, responder)
+        })
         .manage(window_behavior::WindowBehavior::default())
+        .manage(exit_policy::ExitGuard::default())
         .manage(LogDirectory(log_dir.clone()))
         // On macOS, decorum's on_window_ready hook repositions the traffic
         // lights at a fixed inset (dot centre ~20px from top) and keeps them
@@ -1590,15 +1814,49 @@ fn main() {
         .plugin(tauri_plugin_decorum::init())
         .invoke_handler(tauri::generate_handler![
             get_idle_time,
+            screen_lock::is_screen_locked,
+            power::get_power_state,
+            startup_timings::get_startup_timings,
+            startup_timings::export_startup_trace,
+            memory::get_memory_stats,
+            telemetry::get_telemetry_settings,
+            telemetry::set_telemetry_enabled,
+            telemetry::preview_telemetry,
+            feature_flags::get_feature_flags,
             save_credentials,
             get_credentials,
             delete_credentials,
+            keychain::check_keychain,
+            keychain::migrate_keychain,
+            credential_log::get_credential_access_log,
             exit_app,
             fetch_url_metadata,
             upload::upload_file,
+            upload_resume::upload_large_file,
+            upload_resume::resume_large_upload,
+            upload_resume::list_pending_uploads,
+            upload_resume::discard_pending_upload,
+            cache::get_cache_usage,
+            cache::clear_cache,
+            cache::set_cache_quota,
+            temp_files::clear_temp_files,
+            temp_files::secure_delete,
+            attachment_open::open_attachment,
+            attachment_open::reveal_attachment,
+            updates::get_update_settings,
+            updates::set_update_channel,
+            updates::set_update_check_interval,
+            updates::check_for_updates,
+            updates::download_update,
+            updates::get_staged_update,
+            updates::apply_update,
+            rich_presence::get_rich_presence_settings,
+            rich_presence::set_rich_presence_settings,
             download::download_file,
             start_xmpp_proxy,
             stop_xmpp_proxy,
+            get_proxy_status,
+            report_proxy_unreachable,
             mcp_start_server,
             mcp_stop_server,
             mcp_reset_token,
@@ -1631,12 +1889,137 @@ fn main() {
             notifications::request_notification_permission,
             notifications::take_pending_notification_target,
             notifications::set_notification_listener_ready,
-            notifications::dismiss_notifications
+            notifications::dismiss_notifications,
+            notifications::rules::get_notification_rules,
+            notifications::rules::set_conversation_rule,
+            notifications::rules::set_notification_keywords,
+            notifications::rules::set_quiet_hours,
+            word_watch::get_watch_terms,
+            word_watch::set_watch_terms,
+            word_watch::scan_decrypted_message,
+            unread::get_unread_summary,
+            unread::mark_conversation_read,
+            unread::set_active_conversation,
+            blocking::get_blocked_jids,
+            blocking::block_jids,
+            blocking::unblock_jids,
+            blocking::report_jids,
+            screening::get_screening_enabled,
+            screening::set_screening_enabled,
+            screening::list_quarantined_messages,
+            screening::approve_sender,
+            screening::reject_sender,
+            subscriptions::list_pending_subscriptions,
+            subscriptions::respond_subscription,
+            subscriptions::get_subscription_policy,
+            subscriptions::set_subscription_policy,
+            profile::get_own_profile,
+            profile::set_own_profile,
+            caps::get_peer_features,
+            room_cache::get_room_metadata,
+            room_cache::refresh_room_metadata,
+            adhoc::list_adhoc_commands,
+            adhoc::execute_adhoc,
+            admin::set_admin_credentials,
+            admin::get_admin_config,
+            admin::clear_admin_credentials,
+            admin::admin_request,
+            sessions::list_account_sessions,
+            sessions::end_account_session,
+            contacts::find_addressbook_contacts,
+            clipboard::get_clipboard_attachment,
+            file_drop::release_dropped_files,
+            emoji::search_emoji,
+            emoji::record_emoji_use,
+            gif::set_gif_provider,
+            gif::get_gif_config,
+            gif::clear_gif_provider,
+            gif::search_gifs,
+            voice::start_voice_recording,
+            voice::stop_voice_recording,
+            voice::cancel_voice_recording,
+            media_probe::probe_video,
+            shell_i18n::set_ui_language,
+            locale::get_locale_info,
+            locale::format_relative_time,
+            exit_policy::get_exit_policy,
+            exit_policy::set_exit_policy,
+            exit_policy::set_exit_blockers,
+            window_prefs::get_window_prefs,
+            window_prefs::set_always_on_top,
+            window_prefs::set_window_opacity,
+            window_prefs::set_compact_mode,
+            managed_policy::get_managed_policy,
+            compliance::get_compliance_status,
+            compliance::set_compliance_mode,
+            compliance::verify_compliance_archive,
+            compliance::export_compliance_archive,
+            scheduled::schedule_message,
+            scheduled::list_scheduled_messages,
+            scheduled::cancel_scheduled_message,
+            auto_reply::get_auto_reply,
+            auto_reply::set_auto_reply,
+            triggers::list_triggers,
+            triggers::save_trigger,
+            triggers::delete_trigger,
+            settings_sync::get_settings_sync,
+            settings_sync::enable_settings_sync,
+            settings_sync::disable_settings_sync,
+            settings_sync::publish_synced_settings,
+            settings_sync::pull_synced_settings,
+            reminders::remind_me,
+            reminders::list_reminders,
+            reminders::cancel_reminder,
+            xmpp_proxy::privacy::get_privacy_mode,
+            xmpp_proxy::privacy::set_privacy_mode,
+            xmpp_proxy::trust::add_trusted_ca,
+            xmpp_proxy::trust::remove_trusted_ca,
+            xmpp_proxy::trust::list_trusted_cas,
+            xmpp_proxy::trust::reload_trust_store,
+            xmpp_proxy::net_prefs::get_network_settings,
+            xmpp_proxy::net_prefs::set_network_settings,
+            xmpp_proxy::host_overrides::get_host_overrides,
+            xmpp_proxy::host_overrides::set_host_overrides,
+            xmpp_proxy::link_local::start_link_local,
+            xmpp_proxy::link_local::stop_link_local,
+            xmpp_proxy::link_local::link_local_status,
+            xmpp_proxy::link_local::list_link_local_peers,
+            xmpp_proxy::muc_join::join_room,
+            iq_responder::get_native_iq_responder,
+            iq_responder::set_native_iq_responder,
+            receipts::get_delivery_states,
+            reactions::get_message_reactions,
+            push::enable_push,
+            push::disable_push,
+            push::list_push_registrations,
+            invites::create_invite,
+            invites::check_invite_uri,
+            invites::take_pending_invite,
+            bytestreams::expect_bytestream,
+            bytestreams::send_bytestream,
+            bytestreams::cancel_bytestream,
+            bytestreams::discover_bytestream_proxies,
+            jingle_ft::offer_file,
+            jingle_ft::accept_file,
+            jingle_ft::decline_file,
+            jingle_ft::cancel_file_transfer,
+            xmpp_proxy::component::start_component_proxy,
+            xmpp_proxy::component::stop_component_proxy,
+            #[cfg(target_os = "macos")]
+            app_menu::update_menu_state
         ])
         .on_page_load(move |webview, payload| {
             // Always inject console-forwarding script so SDK diagnostic logs
             // (prefixed with [Fluux]) reach the Rust file log for troubleshooting.
             // When --verbose is not active, this is the only way JS logs reach the file.
+            match payload.event() {
+                tauri::webview::PageLoadEvent::Started => {
+                    startup_timings::begin(startup_timings::PAGE_LOAD)
+                }
+                tauri::webview::PageLoadEvent::Finished => {
+                    startup_timings::end(startup_timings::PAGE_LOAD)
+                }
+            }
             if matches!(payload.event(), tauri::webview::PageLoadEvent::Finished) {
                 let _ = webview.eval(r#"
                     (function() {
@@ -1720,9 +2103,16 @@ fn main() {
             }
         })
         .setup(move |app| {
+            let _setup_hook = startup_timings::span(startup_timings::SETUP_HOOK);
+
+            // Before anything reads the keychain: it may be the file fallback.
+            keychain::load(app.path().app_data_dir().ok());
+            credential_log::load(app.path().app_data_dir().ok());
+
             // Wire up native notification backends (macOS: request auth now;
             // the delegate / click routing lands in a later task).
             notifications::setup(app.handle());
+            notifications::rules::load(app.path().app_data_dir().ok());
 
             // OpenPGP key storage needs the per-user app data dir. Resolve
             // it here (inside setup, where `app.path()` is available) and
@@ -1749,6 +2139,164 @@ fn main() {
             app.manage(Arc::clone(&openpgp_state));
             app.manage(Arc::new(mcp::bridge::PendingRequests::new()));
 
+            // Blocklist enforcement goes first: observers registered after it
+            // never see a stanza it dropped.
+            let blocklist = Arc::new(blocking::Blocklist::default());
+            xmpp_proxy::tap::register(blocklist.clone());
+            app.manage(blocklist);
+            // First-contact screening holds messages back before anything
+            // that counts or notifies sees them.
+            screening::load(app.path().app_data_dir().ok());
+            xmpp_proxy::tap::register(Arc::new(screening::Screening::default()));
+
+            // Subscription requests are queued (and possibly answered) in
+            // the bridge; registered after the blocklist so blocked JIDs
+            // never reach the queue.
+            subscriptions::load(app.path().app_data_dir().ok());
+            xmpp_proxy::tap::register(Arc::new(subscriptions::SubscriptionRequests::default()));
+            xmpp_proxy::tap::register(Arc::new(xmpp_proxy::muc_join::MucJoins));
+
+            // Verified caps hashes survive restarts; a missing data dir just
+            // means starting from an empty, memory-only cache.
+            let caps_cache = Arc::new(caps::CapsCache::load(
+                app.path()
+                    .app_data_dir()
+                    .ok()
+                    .map(|dir| dir.join("caps-cache.json")),
+            ));
+            xmpp_proxy::tap::register(caps_cache.clone());
+            app.manage(caps_cache);
+
+            // Room metadata is served from disk first and refreshed lazily.
+            let room_cache = Arc::new(room_cache::RoomCache::load(
+                app.path()
+                    .app_data_dir()
+                    .ok()
+                    .map(|dir| dir.join("room-metadata.json")),
+            ));
+            xmpp_proxy::tap::register(room_cache.clone());
+            app.manage(room_cache);
+
+            // Unread counters follow the stanzas relayed by the proxy, so the
+            // same instance is both a bridge observer and command state.
+            let unread_counters = Arc::new(unread::UnreadCounters::default());
+            xmpp_proxy::tap::register(unread_counters.clone());
+            // Watch words need our room nicknames to skip our own messages.
+            word_watch::load(app.path().app_data_dir().ok());
+            xmpp_proxy::tap::register(Arc::new(word_watch::WordWatch::new(
+                unread_counters.clone(),
+            )));
+            let reaction_store = Arc::new(reactions::ReactionStore::new(unread_counters.clone()));
+            xmpp_proxy::tap::register(reaction_store.clone());
+            reactions::start(app.handle().clone(), reaction_store.clone());
+            app.manage(reaction_store);
+            let typing = Arc::new(typing::Typing::new(unread_counters.clone()));
+            xmpp_proxy::tap::register(typing.clone());
+            typing::start(app.handle().clone(), typing);
+            auto_reply::load(app.path().app_data_dir().ok());
+            xmpp_proxy::tap::register(Arc::new(auto_reply::AutoResponder));
+            triggers::load(app.path().app_data_dir().ok());
+            xmpp_proxy::tap::register(Arc::new(triggers::Triggers::new(
+                unread_counters.clone(),
+            )));
+            settings_sync::load(app.path().app_data_dir().ok());
+            xmpp_proxy::tap::register(Arc::new(pep::PrivatePushes));
+            let settings_sync = Arc::new(settings_sync::SettingsSync::default());
+            xmpp_proxy::tap::register(settings_sync.clone());
+            pep::on_push(&settings_sync::SCHEMA, settings_sync);
+            app.manage(unread_counters);
+            #[cfg(target_os = "linux")]
+            dbus_service::start(app.handle().clone());
+            #[cfg(target_os = "macos")]
+            apple_events::register(app.handle().clone());
+            #[cfg(target_os = "macos")]
+            recent_conversations::install_dock_menu(app.handle().clone());
+
+            let iq_responder = Arc::new(iq_responder::IqResponder::load(
+                app.path().app_data_dir().ok(),
+            ));
+            xmpp_proxy::tap::register(iq_responder.clone());
+            app.manage(iq_responder);
+
+            let receipt_tracker = Arc::new(receipts::ReceiptTracker::default());
+            xmpp_proxy::tap::register(receipt_tracker.clone());
+            receipts::start(app.handle().clone(), receipt_tracker.clone());
+            app.manage(receipt_tracker);
+
+            let bytestreams = Arc::new(bytestreams::Bytestreams::default());
+            xmpp_proxy::tap::register(bytestreams.clone());
+            let jingle_files = Arc::new(jingle_ft::JingleFiles::new(bytestreams.clone()));
+            xmpp_proxy::tap::register(jingle_files.clone());
+            app.manage(bytestreams);
+            app.manage(jingle_files);
+
+            xmpp_proxy::privacy::load(app.path().app_data_dir().ok());
+            upload_resume::load(app.path().app_data_dir().ok());
+            cache::load(app.path().app_data_dir().ok());
+            cache::start(app.handle().clone());
+            temp_files::load(app.path().app_data_dir().ok());
+            updates::load(app.path().app_data_dir().ok());
+            updates::start(app.handle().clone());
+            rich_presence::load(app.path().app_data_dir().ok());
+            rich_presence::start(app.handle().clone());
+            screen_lock::watch(app.handle().clone());
+            power::watch(app.handle().clone());
+            memory::start_sentinel(app.handle().clone());
+            telemetry::load(app.path().app_data_dir().ok());
+            telemetry::start();
+            feature_flags::load(app.path().app_data_dir().ok());
+            feature_flags::start(app.handle().clone());
+            xmpp_proxy::trust::load(app.path().app_data_dir().ok());
+            xmpp_proxy::trust::watch();
+            xmpp_proxy::net_prefs::load(app.path().app_data_dir().ok());
+            xmpp_proxy::host_overrides::load(app.path().app_data_dir().ok());
+
+            let compliance_archive = Arc::new(compliance::ComplianceArchive::load(
+                app.path().app_data_dir().ok(),
+                managed_policy::current().compliance_archive,
+            ));
+            xmpp_proxy::tap::register(compliance_archive.clone());
+            app.manage(compliance_archive);
+
+            let scheduler = Arc::new(scheduled::Scheduler::load(
+                app.path()
+                    .app_data_dir()
+                    .ok()
+                    .map(|dir| dir.join("scheduled-messages.json")),
+            ));
+            scheduled::start(app.handle().clone(), scheduler.clone());
+            app.manage(scheduler);
+
+            let reminders = Arc::new(reminders::Reminders::load(
+                app.path()
+                    .app_data_dir()
+                    .ok()
+                    .map(|dir| dir.join("reminders.json")),
+            ));
+            reminders::start(app.handle().clone(), reminders.clone());
+            app.manage(reminders);
+
+            let emoji_usage = Arc::new(emoji::EmojiUsage::load(
+                app.path()
+                    .app_data_dir()
+                    .ok()
+                    .map(|dir| dir.join("emoji-usage.json")),
+            ));
+            app.manage(emoji_usage);
+
+            // Native menu labels follow the system locale until the frontend
+            // reports its language through `set_ui_language`.
+            app.manage(shell_i18n::UiLanguage::from_system());
+
+            // Kept beside the window-state plugin's file, which restores the
+            // rest of the window's geometry.
+            app.manage(window_prefs::WindowPrefsStore::load(
+                app.path()
+                    .app_config_dir()
+                    .ok()
+                    .map(|dir| dir.join("window-prefs.json")),
+            ));
+
             // Boot-time prewarm: if `last_user` is stashed in the keychain
             // AND we have an encrypted TSK on disk for that JID, start the
             // unlock now so it overlaps with Tauri window creation, React
@@ -1823,67 +2371,26 @@ fn main() {
                 }
             }
 
+            // Invite links (XEP-0401) are validated natively before the UI
+            // is asked to prompt for an account; other URIs go to the
+            // frontend's deep-link handler as before.
+            let invite_handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                let urls: Vec<String> = event.urls().iter().map(|u| u.to_string()).collect();
+                invites::handle_opened_urls(&invite_handle, &urls);
+            });
+            if let Ok(Some(urls)) = app.deep_link().get_current() {
+                let urls: Vec<String> = urls.iter().map(|u| u.to_string()).collect();
+                invites::handle_opened_urls(app.handle(), &urls);
+            }
+
             // macOS: Create custom menu with Help submenu
             #[cfg(target_os = "macos")]
             {
-                use tauri::menu::MenuItem;
-
-                // App menu (standard macOS app menu)
-                let app_menu = SubmenuBuilder::new(app, "Fluux Messenger")
-                    .item(&PredefinedMenuItem::about(app, Some("About Fluux Messenger"), None)?)
-                    .separator()
-                    .item(&PredefinedMenuItem::services(app, None)?)
-                    .separator()
-                    .item(&PredefinedMenuItem::hide(app, Some("Hide Fluux Messenger"))?)
-                    .item(&PredefinedMenuItem::hide_others(app, None)?)
-                    .item(&PredefinedMenuItem::show_all(app, None)?)
-                    .separator()
-                    .item(&PredefinedMenuItem::quit(app, Some("Quit Fluux Messenger"))?)
-                    .build()?;
-
-                // Edit menu (standard text editing)
-                let edit_menu = SubmenuBuilder::new(app, "Edit")
-                    .item(&PredefinedMenuItem::undo(app, None)?)
-                    .item(&PredefinedMenuItem::redo(app, None)?)
-                    .separator()
-                    .item(&PredefinedMenuItem::cut(app, None)?)
-                    .item(&PredefinedMenuItem::copy(app, None)?)
-                    .item(&PredefinedMenuItem::paste(app, None)?)
-                    .separator()
-                    .item(&PredefinedMenuItem::select_all(app, None)?)
-                    .build()?;
-
-                // View menu
-                let view_menu = SubmenuBuilder::new(app, "View")
-                    .item(&PredefinedMenuItem::fullscreen(app, Some("Toggle Full Screen"))?)
-                    .build()?;
-
-                // Window menu
-                let window_menu = SubmenuBuilder::new(app, "Window")
-                    .item(&PredefinedMenuItem::minimize(app, None)?)
-                    .item(&PredefinedMenuItem::maximize(app, Some("Zoom"))?)
-                    .separator()
-                    .item(&PredefinedMenuItem::close_window(app, None)?)
-                    .build()?;
-
-                // Help menu with GitHub link and log access
-                let github_item = MenuItem::with_id(app, "github", "Fluux Messenger on GitHub", true, None::<&str>)?;
-                let report_issue_item = MenuItem::with_id(app, "report_issue", "Report an Issue...", true, None::<&str>)?;
-                let show_logs_item = MenuItem::with_id(app, "show_logs", "Reveal Logs in Finder", true, None::<&str>)?;
-
-                let help_menu = SubmenuBuilder::new(app, "Help")
-                    .item(&github_item)
-                    .item(&report_issue_item)
-                    .separator()
-                    .item(&show_logs_item)
-                    .build()?;
-
-                // Build and set the menu
-                let menu = MenuBuilder::new(app)
-                    .items(&[&app_menu, &edit_menu, &view_menu, &window_menu, &help_menu])
-                    .build()?;
-
-                app.set_menu(menu)?;
+                // The frontend fills in presence and recent conversations
+                // through `update_menu_state` once it has connected.
+                app.manage(app_menu::AppMenu::default());
+                app_menu::rebuild(app.handle())?;
 
                 // Handle menu events
                 let log_dir_for_menu = log_dir.clone();
@@ -1898,14 +2405,30 @@ fn main() {
                         "show_logs" => {
                             let _ = app_handle.opener().reveal_item_in_dir(&log_dir_for_menu);
                         }
-                        _ => {}
+                        id => {
+                            app_menu::handle_event(app_handle, id);
+                        }
                     }
                 });
             }
 
             // Check if window is off-screen (e.g., monitor was disconnected) and reset if needed
             if let Some(window) = app.get_webview_window("main") {
+                // A layout saved for this monitor setup wins over the single
+                // geometry the window-state plugin restored.
+                window_profiles::attach(
+                    &window,
+                    Arc::new(window_profiles::WindowProfiles::load(
+                        app.path()
+                            .app_config_dir()
+                            .ok()
+                            .map(|dir| dir.join("window-profiles.json")),
+                    )),
+                );
                 ensure_window_visible(&window);
+                window_prefs::apply(&window, &app.state::<window_prefs::WindowPrefsStore>());
+                file_drop::attach(&window);
+                webview_watchdog::watch(&window);
                 // Ensure window has keyboard focus on launch
                 let _ = window.set_focus();
                 // Linux/WebKitGTK: force the loopback hop to the XMPP bridge
@@ -1977,9 +2500,9 @@ fn main() {
             // close handler below quits gracefully instead. See linux_tray.rs.
             #[cfg(target_os = "linux")]
             {
-                let show_item = MenuItem::with_id(app, "show", "Show Fluux", true, None::<&str>)?;
-                let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-                let menu = Menu::with_items(app, &[&show_item, &quit_item])?;
+                let shell_strings =
+                    shell_i18n::strings(app.state::<shell_i18n::UiLanguage>().get());
+                let menu = shell_i18n::build_tray_menu(app, shell_strings)?;
                 // GNOME can restore hidden windows at (0,0). Keep the last placement
                 // and re-apply it when restoring from the tray menu.
                 let last_window_state =
@@ -2197,10 +2720,9 @@ fn main() {
             #[cfg(target_os = "windows")]
             {
                 // Create system tray menu
-                let show_item = MenuItem::with_id(app, "show", "Show Fluux", true, None::<&str>)?;
-                let show_logs_item = MenuItem::with_id(app, "show_logs", "Open Logs Folder", true, None::<&str>)?;
-                let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
-                let menu = Menu::with_items(app, &[&show_item, &show_logs_item, &quit_item])?;
+                let shell_strings =
+                    shell_i18n::strings(app.state::<shell_i18n::UiLanguage>().get());
+                let menu = shell_i18n::build_tray_menu(app, shell_strings)?;
                 let window_hidden_to_tray = Arc::new(AtomicBool::new(false));
 
                 // Build the system tray icon
@@ -2329,38 +2851,76 @@ fn main() {
             // Uses an AtomicBool flag to stop cleanly on app exit (prevents 100% CPU).
             if let Some(window) = app.get_webview_window("main") {
                 let running = keepalive_flag_for_setup.clone();
-                std::thread::spawn(move || {
+                tauri::async_runtime::spawn(async move {
                     // Measure real wall-clock elapsed per iteration so a sleep
-                    // the machine slept through (the `sleep()` call returns
-                    // late) is detected and the post-wake tick fires
-                    // immediately instead of waiting out another full interval.
-                    // The display state is probed FRESH every emit and the tick
-                    // keeps arriving every interval even when the display is
-                    // off, so the JS state machine can learn when it returns.
+                    // the machine slept through (the timer fires late) is
+                    // detected and the post-wake tick fires immediately
+                    // instead of waiting out another full interval. While the
+                    // system is asleep the tick waits for the wake. Traffic
+                    // over the bridge pushes the tick back (see
+                    // `keepalive_deferral`), so a connection that is evidently
+                    // alive isn't woken for nothing. The display state is
+                    // probed FRESH every emit and the tick keeps arriving even
+                    // when the display is off, so the JS state machine can
+                    // learn when it returns. On battery the interval is
+                    // stretched (`power`), and the gap check measures against
+                    // the wait actually used.
                     let mut wait = KEEPALIVE_INTERVAL;
+                    let mut last_tick = std::time::Instant::now();
                     while running.load(Ordering::Relaxed) {
                         let started = std::time::Instant::now();
-                        std::thread::sleep(wait);
+                        tokio::time::sleep(wait).await;
+                        power::until_awake().await;
                         if !running.load(Ordering::Relaxed) {
                             break;
                         }
                         let elapsed = started.elapsed();
+                        if detect_sleep_gap(elapsed, wait, SLEEP_GAP_MARGIN).is_none() {
+                            let now_ms = std::time::SystemTime::now()
+                                .duration_since(std::time::UNIX_EPOCH)
+                                .map_or(0, |d| d.as_millis() as u64);
+                            let quiet = std::time::Duration::from_millis(
+                                now_ms.saturating_sub(xmpp_proxy::last_traffic_ms()),
+                            );
+                            let interval = power::scale_interval(KEEPALIVE_INTERVAL);
+                            if let Some(more) =
+                                keepalive_deferral(quiet, interval, last_tick.elapsed())
+                            {
+                                wait = more;
+                                continue;
+                            }
+                        }
                         let (payload, next) = keepalive_step(
                             elapsed,
-                            KEEPALIVE_INTERVAL,
+                            wait,
                             SLEEP_GAP_MARGIN,
                             keepalive_display_active,
                         );
+                        // Slept through the timer: check the bridge's upstream
+                        // (the only wake signal off macOS).
+                        if payload.slept_ms > 0 {
+                            power::woke();
+                        }
                         let _ = window.emit("xmpp-keepalive", payload);
-                        wait = next;
+                        last_tick = std::time::Instant::now();
+                        wait = power::scale_interval(next);
                     }
                 });
             }
 
+            // --headless: hide the window and serve the local control API.
+            if let Some(options) = headless.clone() {
+                headless::start(app, options);
+            }
+            if let Some(port) = metrics_port {
+                xmpp_proxy::metrics::serve(port);
+            }
+
             Ok(())
         })
         .build(tauri::generate_context!())
         .expect("error while building tauri application");
+    drop(tauri_build);
 
     app.run(move |_app_handle, _event| {
         // Handle clicking dock icon to show window again (macOS only)
@@ -2371,6 +2931,10 @@ fn main() {
                 let _ = window.set_focus();
             }
         }
+        if let RunEvent::Exit = &_event {
+            temp_files::clear();
+            updates::install_staged(_app_handle);
+        }
         // Handle app termination: request graceful shutdown (all platforms)
         if let RunEvent::ExitRequested { api, .. } = &_event {
             // First exit request: trigger graceful shutdown and delay exit.
@@ -2379,8 +2943,6 @@ fn main() {
                 return;
             }
 
-            // Stop the keepalive thread to prevent 100% CPU on exit
-            keepalive_flag_for_run.store(false, Ordering::Relaxed);
             // Save window state including position (macOS and Windows only)
             #[cfg(any(target_os = "macos", target_os = "windows"))]
             {
@@ -2391,16 +2953,26 @@ fn main() {
                         | StateFlags::FULLSCREEN,
                 );
             }
-            // Emit event to frontend for graceful disconnect
-            let _ = _app_handle.emit("graceful-shutdown", ());
             // Prevent immediate exit - frontend will call exit_app after disconnect
             api.prevent_exit();
-            // Set a fallback timer to force exit after 2 seconds
+            // Confirm if a quit would drop queued messages or a call, then
+            // wait for stanza acks before the graceful disconnect (see
+            // exit_policy.rs). Cancelling re-arms this handler.
+            let keepalive_running = keepalive_flag_for_run.clone();
+            let graceful_shutdown_started = graceful_shutdown_flag_for_run.clone();
             let handle = _app_handle.clone();
-            std::thread::spawn(move || {
-                std::thread::sleep(std::time::Duration::from_secs(2));
-                handle.exit(0);
-            });
+            exit_policy::confirm_quit(
+                _app_handle,
+                move || {
+                    // Stop the keepalive task to prevent 100% CPU on exit
+                    keepalive_running.store(false, Ordering::Relaxed);
+                    exit_policy::drain_then_exit(&handle);
+                },
+                move || {
+                    graceful_shutdown_started.store(false, Ordering::Relaxed);
+                    updates::quit_cancelled();
+                },
+            );
         }
     });
 }
@@ -2550,6 +3122,25 @@ mod tests {
         assert_eq!(wait, KEEPALIVE_INTERVAL);
     }
 
+    #[test]
+    fn test_keepalive_deferral_follows_traffic_up_to_the_cap() {
+        let recent = Duration::from_secs(10);
+        assert_eq!(
+            keepalive_deferral(recent, KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL),
+            Some(Duration::from_secs(20))
+        );
+        // Quiet for a whole interval: the tick is due.
+        assert_eq!(
+            keepalive_deferral(KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL),
+            None
+        );
+        // Busy for long: fire anyway so the display state gets through.
+        assert_eq!(
+            keepalive_deferral(recent, KEEPALIVE_INTERVAL, KEEPALIVE_MAX_DEFERRAL),
+            None
+        );
+    }
+
     #[test]
     fn test_next_wait_no_gap_uses_interval() {
         assert_eq!(next_wait(None), KEEPALIVE_INTERVAL);
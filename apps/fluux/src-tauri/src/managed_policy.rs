@@ -0,0 +1,237 @@
+//! Administrator-provisioned policy for managed deployments.
+//!
+//! Read once at startup from the platform's managed-settings location:
+//!
+//! - Linux: `/etc/fluux/policy.toml`
+//! - macOS: the `com.processone.fluux` managed preferences written by a
+//!   configuration profile (`/Library/Managed Preferences/[<user>/]…plist`)
+//! - Windows: `HKLM\SOFTWARE\Policies\ProcessOne\Fluux`
+//!
+//! The same keys are used everywhere (see [`ManagedPolicy`]). Settings the
+//! UI presents (pre-set server, hidden server field) are handed to the
+//! frontend by [`get_managed_policy`]; the ones that matter for security are
+//! also enforced here, so a modified frontend cannot bypass them.
+
+use crate::xmpp_proxy::dns::{parse_server_input, ConnectionMode, ParsedServer};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all(serialize = "camelCase"))]
+pub struct ManagedPolicy {
+    /// A policy was found; the frontend shows a "managed by your
+    /// organization" note.
+    #[serde(skip_deserializing)]
+    pub managed: bool,
+    /// Server pre-filled on the login screen.
+    pub server: Option<String>,
+    /// Hide the server field and refuse to connect anywhere but `server`.
+    pub hide_server_field: bool,
+    /// Ignore `--dangerous-insecure-tls`: certificates are always verified.
+    pub lock_tls: bool,
+    /// Only connect over direct TLS (XEP-0368), never STARTTLS. Explicit
+    /// STARTTLS endpoints are refused up front; SRV results are filtered by
+    /// the proxy.
+    pub require_direct_tls: bool,
+    pub disable_link_previews: bool,
+    /// Keep the compliance archive on; the user cannot disable it.
+    pub compliance_archive: bool,
+}
+
+static POLICY: OnceLock<ManagedPolicy> = OnceLock::new();
+
+/// The policy in force; the default (unmanaged) one before [`init`].
+pub fn current() -> &'static ManagedPolicy {
+    static UNMANAGED: ManagedPolicy = ManagedPolicy {
+        managed: false,
+        server: None,
+        hide_server_field: false,
+        lock_tls: false,
+        require_direct_tls: false,
+        disable_link_previews: false,
+        compliance_archive: false,
+    };
+    POLICY.get().unwrap_or(&UNMANAGED)
+}
+
+/// Read the platform policy. Called once from `main` before logging is
+/// set up (the CLI flags depend on it), hence the `eprintln!`s.
+pub fn init() -> &'static ManagedPolicy {
+    POLICY.get_or_init(|| match read_platform_policy() {
+        Ok(Some(policy)) => sanitize(policy),
+        Ok(None) => ManagedPolicy::default(),
+        Err(e) => {
+            // Refusing to start would lock users out; run with the defaults
+            // and say so.
+            eprintln!("WARNING: managed policy unreadable, ignoring it: {e}");
+            ManagedPolicy::default()
+        }
+    })
+}
+
+fn sanitize(mut policy: ManagedPolicy) -> ManagedPolicy {
+    policy.managed = true;
+    policy.server = policy
+        .server
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    if policy.hide_server_field && policy.server.is_none() {
+        eprintln!("WARNING: managed policy hides the server field but sets no server");
+        policy.hide_server_field = false;
+    }
+    policy
+}
+
+fn parse_toml(text: &str) -> Result<ManagedPolicy, String> {
+    toml::from_str(text).map_err(|e| format!("Invalid policy file: {e}"))
+}
+
+#[cfg(target_os = "linux")]
+fn read_platform_policy() -> Result<Option<ManagedPolicy>, String> {
+    const PATH: &str = "/etc/fluux/policy.toml";
+    match std::fs::read_to_string(PATH) {
+        Ok(text) => parse_toml(&text).map(Some),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{PATH}: {e}")),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn read_platform_policy() -> Result<Option<ManagedPolicy>, String> {
+    const DIR: &str = "/Library/Managed Preferences";
+    const FILE: &str = "com.processone.fluux.plist";
+    // Per-user profiles take precedence over device-wide ones.
+    let user_path = std::env::var("USER")
+        .ok()
+        .map(|user| std::path::Path::new(DIR).join(user).join(FILE));
+    let device_path = std::path::Path::new(DIR).join(FILE);
+    for path in user_path.into_iter().chain(Some(device_path)) {
+        if path.exists() {
+            return plist::from_file(&path)
+                .map(Some)
+                .map_err(|e| format!("{}: {e}", path.display()));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(target_os = "windows")]
+fn read_platform_policy() -> Result<Option<ManagedPolicy>, String> {
+    use winreg::enums::HKEY_LOCAL_MACHINE;
+    use winreg::RegKey;
+
+    const KEY: &str = r"SOFTWARE\Policies\ProcessOne\Fluux";
+    let key = match RegKey::predef(HKEY_LOCAL_MACHINE).open_subkey(KEY) {
+        Ok(key) => key,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("HKLM\\{KEY}: {e}")),
+    };
+    let flag = |name: &str| {
+        key.get_value::<u32, _>(name)
+            .map(|v| v != 0)
+            .unwrap_or(false)
+    };
+    Ok(Some(ManagedPolicy {
+        managed: true,
+        server: key.get_value::<String, _>("server").ok(),
+        hide_server_field: flag("hide_server_field"),
+        lock_tls: flag("lock_tls"),
+        require_direct_tls: flag("require_direct_tls"),
+        disable_link_previews: flag("disable_link_previews"),
+        compliance_archive: flag("compliance_archive"),
+    }))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn read_platform_policy() -> Result<Option<ManagedPolicy>, String> {
+    Ok(None)
+}
+
+/// Host part of a proxy server input, for comparing against the policy.
+fn server_host(server: &str) -> String {
+    match parse_server_input(server) {
+        ParsedServer::Direct(host, ..) => host,
+        ParsedServer::Domain(domain) => domain,
+    }
+    .trim_end_matches('.')
+    .to_lowercase()
+}
+
+/// Refuse a proxy server input the policy does not allow.
+pub fn check_server(server: &str) -> Result<(), String> {
+    check_server_against(current(), server)
+}
+
+fn check_server_against(policy: &ManagedPolicy, server: &str) -> Result<(), String> {
+    if policy.hide_server_field {
+        if let Some(allowed) = policy.server.as_deref() {
+            if server_host(server) != server_host(allowed) {
+                return Err(format!(
+                    "Your administrator only allows connecting to {allowed}"
+                ));
+            }
+        }
+    }
+    if policy.require_direct_tls {
+        if let ParsedServer::Direct(host, port, ConnectionMode::Tcp, _) = parse_server_input(server)
+        {
+            return Err(format!(
+                "Your administrator requires direct TLS; {host}:{port} uses STARTTLS"
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_managed_policy() -> ManagedPolicy {
+    current().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_policy_files() {
+        let policy = sanitize(
+            parse_toml(
+                r#"
+                server = " chat.example.com "
+                hide_server_field = true
+                lock_tls = true
+                disable_link_previews = true
+                "#,
+            )
+            .unwrap(),
+        );
+        assert!(policy.managed);
+        assert_eq!(policy.server.as_deref(), Some("chat.example.com"));
+        assert!(policy.hide_server_field && policy.lock_tls && policy.disable_link_previews);
+        assert!(!policy.require_direct_tls);
+
+        let json = serde_json::to_string(&policy).unwrap();
+        assert!(json.contains("\"hideServerField\":true"));
+
+        assert!(parse_toml("server = 3").is_err());
+        let headless = sanitize(parse_toml("hide_server_field = true").unwrap());
+        assert!(!headless.hide_server_field);
+    }
+
+    #[test]
+    fn enforces_server_and_tls_mode() {
+        let policy = ManagedPolicy {
+            managed: true,
+            server: Some("chat.example.com".to_string()),
+            hide_server_field: true,
+            require_direct_tls: true,
+            ..ManagedPolicy::default()
+        };
+        assert!(check_server_against(&policy, "chat.example.com").is_ok());
+        assert!(check_server_against(&policy, "Chat.Example.com.").is_ok());
+        assert!(check_server_against(&policy, "tls://chat.example.com:5223").is_ok());
+        assert!(check_server_against(&policy, "tcp://chat.example.com:5222").is_err());
+        assert!(check_server_against(&policy, "evil.example.net").is_err());
+        assert!(check_server_against(&ManagedPolicy::default(), "tcp://any.example:5222").is_ok());
+    }
+}
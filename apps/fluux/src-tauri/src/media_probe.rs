@@ -0,0 +1,533 @@
+//! Video metadata and poster frames for attachments, computed before upload.
+//!
+//! Without this the WebView has to load the whole file into a `<video>`
+//! element just to learn its duration and size, and the bubble shows a
+//! placeholder until it has. MP4/MOV (what phones and screen recorders
+//! produce) are read natively from the container headers, which only
+//! touches the `moov` box however large the file is. Other containers, and
+//! poster frames, use `ffprobe`/`ffmpeg`.
+//!
+//! The macOS and Windows releases bundle both binaries next to the app
+//! executable (`bundle.externalBin` in `tauri.media-tools.conf.json`,
+//! fetched by `scripts/fetch-media-tools.sh`); the Linux packages recommend
+//! the distribution's `ffmpeg` instead. The bundled copy is preferred, then
+//! one on PATH or in the Homebrew/MacPorts prefixes. Without either, MP4/MOV
+//! metadata is still read natively, macOS renders the poster frame with
+//! Quick Look, and everything else stays `None`: the bubble then learns the
+//! duration and size from the WebView's `<video>` element as before.
+
+use serde::Serialize;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// `moov` boxes are a few KB to a few MB; anything beyond this is not a
+/// header worth parsing.
+const MAX_MOOV_BYTES: u64 = 64 * 1024 * 1024;
+const TOOL_TIMEOUT: Duration = Duration::from_secs(20);
+/// Poster frames are bubble-sized.
+const POSTER_MAX_WIDTH: u32 = 640;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoProbe {
+    pub duration_ms: Option<u64>,
+    /// Display size, i.e. after applying the track rotation.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    /// JPEG (or PNG from Quick Look) in the app cache.
+    pub poster_path: Option<String>,
+}
+
+/// Friendly codec name for an ISO-BMFF sample entry type.
+fn codec_name(fourcc: &[u8]) -> String {
+    match fourcc {
+        b"avc1" | b"avc3" => "h264",
+        b"hvc1" | b"hev1" => "hevc",
+        b"av01" => "av1",
+        b"vp08" => "vp8",
+        b"vp09" => "vp9",
+        b"mp4v" => "mpeg4",
+        b"mp4a" => "aac",
+        b"Opus" => "opus",
+        b"ac-3" => "ac3",
+        b"ec-3" => "eac3",
+        b"alac" => "alac",
+        b"fLaC" => "flac",
+        other => return String::from_utf8_lossy(other).trim().to_string(),
+    }
+    .to_string()
+}
+
+/// Iterate the boxes of an in-memory ISO-BMFF payload as (type, body).
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let size = u32::from_be_bytes(data[0..4].try_into().ok()?) as usize;
+        let kind = &data[4..8];
+        let (header, size) = match size {
+            0 => (8, data.len()),
+            1 if data.len() >= 16 => (
+                16,
+                usize::try_from(u64::from_be_bytes(data[8..16].try_into().ok()?)).ok()?,
+            ),
+            _ => (8, size),
+        };
+        if size < header || size > data.len() {
+            return None;
+        }
+        let body = &data[header..size];
+        data = &data[size..];
+        Some((kind, body))
+    })
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    boxes(data).find(|(k, _)| *k == kind).map(|(_, body)| body)
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Duration from `mvhd`, in milliseconds.
+fn movie_duration_ms(mvhd: &[u8]) -> Option<u64> {
+    let (timescale, duration) = match mvhd.first()? {
+        1 => (be_u32(mvhd, 20)?, be_u64(mvhd, 24)?),
+        _ => (be_u32(mvhd, 12)?, be_u32(mvhd, 16)? as u64),
+    };
+    if timescale == 0 || duration == u64::MAX || duration == u32::MAX as u64 {
+        return None;
+    }
+    Some(duration.saturating_mul(1000) / timescale as u64)
+}
+
+/// Display width and height from `tkhd`, swapped for 90°/270° rotations.
+fn track_size(tkhd: &[u8]) -> Option<(u32, u32)> {
+    // version/flags, then times and ids (wider in version 1), then
+    // reserved, layer, group, volume, reserved, matrix, width, height.
+    let matrix = match tkhd.first()? {
+        1 => 4 + 32 + 16,
+        _ => 4 + 20 + 16,
+    };
+    let a = be_u32(tkhd, matrix)? as i32;
+    let b = be_u32(tkhd, matrix + 4)? as i32;
+    let width = be_u32(tkhd, matrix + 36)? >> 16;
+    let height = be_u32(tkhd, matrix + 40)? >> 16;
+    let quarter_turn = a == 0 && b.unsigned_abs() == 0x10000;
+    Some(if quarter_turn {
+        (height, width)
+    } else {
+        (width, height)
+    })
+}
+
+/// Fill `probe` from a `moov` box.
+fn parse_moov(moov: &[u8], probe: &mut VideoProbe) {
+    probe.duration_ms = child(moov, b"mvhd").and_then(movie_duration_ms);
+    for (kind, trak) in boxes(moov) {
+        if kind != b"trak" {
+            continue;
+        }
+        let Some(mdia) = child(trak, b"mdia") else {
+            continue;
+        };
+        let handler = child(mdia, b"hdlr").and_then(|h| h.get(8..12));
+        let codec = child(mdia, b"minf")
+            .and_then(|minf| child(minf, b"stbl"))
+            .and_then(|stbl| child(stbl, b"stsd"))
+            .and_then(|stsd| stsd.get(12..16))
+            .map(codec_name);
+        match handler {
+            Some(b"vide") if probe.video_codec.is_none() => {
+                probe.video_codec = codec;
+                if let Some((w, h)) = child(trak, b"tkhd").and_then(track_size) {
+                    probe.width = Some(w);
+                    probe.height = Some(h);
+                }
+            }
+            Some(b"soun") if probe.audio_codec.is_none() => probe.audio_codec = codec,
+            _ => {}
+        }
+    }
+}
+
+/// Locate and parse the `moov` box of an MP4/MOV file, seeking over media
+/// data. `None` when the file isn't ISO-BMFF.
+fn probe_iso_bmff<R: Read + Seek>(file: &mut R) -> Option<VideoProbe> {
+    let mut header = [0u8; 16];
+    let mut offset = 0u64;
+    let mut first = true;
+    loop {
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut header[..8]).ok()?;
+        let kind: [u8; 4] = header[4..8].try_into().ok()?;
+        if first && &kind != b"ftyp" {
+            return None;
+        }
+        first = false;
+        let (header_len, size) = match u32::from_be_bytes(header[0..4].try_into().ok()?) {
+            1 => {
+                file.read_exact(&mut header[8..16]).ok()?;
+                (16u64, u64::from_be_bytes(header[8..16].try_into().ok()?))
+            }
+            0 => return None,
+            size => (8u64, size as u64),
+        };
+        if size < header_len {
+            return None;
+        }
+        if &kind == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_BYTES {
+                return None;
+            }
+            let mut moov = vec![0u8; body_len as usize];
+            file.read_exact(&mut moov).ok()?;
+            let mut probe = VideoProbe::default();
+            parse_moov(&moov, &mut probe);
+            return Some(probe);
+        }
+        offset = offset.checked_add(size)?;
+    }
+}
+
+/// Where to look for a tool: the executable's own directory, where Tauri
+/// installs bundled sidecars, then PATH, then the usual Homebrew/MacPorts
+/// prefixes that a GUI app launched from Finder doesn't have on its PATH.
+fn tool_dirs() -> Vec<PathBuf> {
+    let bundled = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let mut dirs: Vec<PathBuf> = bundled.into_iter().collect();
+    if let Some(path) = std::env::var_os("PATH") {
+        dirs.extend(std::env::split_paths(&path));
+    }
+    if cfg!(target_os = "macos") {
+        dirs.extend(["/opt/homebrew/bin", "/usr/local/bin", "/opt/local/bin"].map(PathBuf::from));
+    }
+    dirs
+}
+
+fn find_tool(name: &str) -> Option<PathBuf> {
+    let exe = if cfg!(windows) {
+        format!("{name}.exe")
+    } else {
+        name.to_string()
+    };
+    tool_dirs()
+        .into_iter()
+        .map(|d| d.join(&exe))
+        .find(|p| p.is_file())
+}
+
+/// Run a tool to completion, killing it after [`TOOL_TIMEOUT`].
+fn run_tool(command: &mut Command) -> Option<Vec<u8>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = stdout.read_to_end(&mut out);
+        out
+    });
+    let deadline = Instant::now() + TOOL_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(50)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                tracing::warn!("Media probe: {:?} timed out", command.get_program());
+                return None;
+            }
+        }
+    };
+    let out = reader.join().ok()?;
+    status.success().then_some(out)
+}
+
+/// Metadata from `ffprobe -show_streams -show_format` JSON.
+fn parse_ffprobe(json: &serde_json::Value) -> VideoProbe {
+    let mut probe = VideoProbe {
+        duration_ms: json
+            .pointer("/format/duration")
+            .and_then(|d| d.as_str())
+            .and_then(|d| d.parse::<f64>().ok())
+            .map(|secs| (secs * 1000.0) as u64),
+        ..VideoProbe::default()
+    };
+    let streams = json.get("streams").and_then(|s| s.as_array());
+    for stream in streams.into_iter().flatten() {
+        let codec = stream
+            .get("codec_name")
+            .and_then(|c| c.as_str())
+            .map(str::to_string);
+        match stream.get("codec_type").and_then(|t| t.as_str()) {
+            Some("video") if probe.video_codec.is_none() => {
+                // Attached cover art shows up as a video stream too.
+                if stream
+                    .pointer("/disposition/attached_pic")
+                    .and_then(|v| v.as_i64())
+                    == Some(1)
+                {
+                    continue;
+                }
+                probe.video_codec = codec;
+                let dim = |key: &str| stream.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+                let rotation = stream
+                    .get("side_data_list")
+                    .and_then(|l| l.as_array())
+                    .and_then(|l| l.iter().find_map(|d| d.get("rotation")?.as_i64()))
+                    .unwrap_or(0);
+                let (w, h) = (dim("width"), dim("height"));
+                (probe.width, probe.height) = if rotation.rem_euclid(180) == 90 {
+                    (h, w)
+                } else {
+                    (w, h)
+                };
+            }
+            Some("audio") if probe.audio_codec.is_none() => probe.audio_codec = codec,
+            _ => {}
+        }
+    }
+    probe
+}
+
+fn probe_with_ffprobe(path: &Path) -> Option<VideoProbe> {
+    let ffprobe = find_tool("ffprobe")?;
+    let out = run_tool(
+        Command::new(ffprobe)
+            .args([
+                "-v",
+                "error",
+                "-print_format",
+                "json",
+                "-show_format",
+                "-show_streams",
+            ])
+            .arg(path),
+    )?;
+    Some(parse_ffprobe(&serde_json::from_slice(&out).ok()?))
+}
+
+/// Render a poster frame into `dir`, named after `stem`.
+fn render_poster(path: &Path, duration_ms: Option<u64>, dir: &Path, stem: &str) -> Option<PathBuf> {
+    // One second in skips fade-ins and black first frames, unless the clip
+    // is shorter than that.
+    let at = match duration_ms {
+        Some(ms) if ms < 2000 => ms as f64 / 2000.0,
+        _ => 1.0,
+    };
+    if let Some(ffmpeg) = find_tool("ffmpeg") {
+        let target = dir.join(format!("{stem}.jpg"));
+        run_tool(
+            Command::new(ffmpeg)
+                .args(["-v", "error", "-ss", &format!("{at:.3}"), "-i"])
+                .arg(path)
+                .args([
+                    "-frames:v",
+                    "1",
+                    "-vf",
+                    &format!("scale='min({POSTER_MAX_WIDTH},iw)':-2"),
+                    "-y",
+                ])
+                .arg(&target),
+        )?;
+        return target.is_file().then_some(target);
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // Quick Look writes `<file name>.png` into the output directory;
+        // give it a private one so the name can't collide.
+        let scratch = dir.join(format!("{stem}.ql"));
+        std::fs::create_dir_all(&scratch).ok()?;
+        let rendered = run_tool(
+            Command::new("/usr/bin/qlmanage")
+                .args(["-t", "-s", &POSTER_MAX_WIDTH.to_string(), "-o"])
+                .arg(&scratch)
+                .arg(path),
+        )
+        .and_then(|_| std::fs::read_dir(&scratch).ok()?.flatten().next())
+        .map(|entry| entry.path());
+        let target = dir.join(format!("{stem}.png"));
+        let moved = rendered.and_then(|r| std::fs::rename(r, &target).ok());
+        let _ = std::fs::remove_dir_all(&scratch);
+        moved.map(|_| target)
+    }
+    #[cfg(not(target_os = "macos"))]
+    None
+}
+
+/// Cache name for a file's poster: changes whenever the file does.
+fn poster_stem(path: &Path, metadata: &std::fs::Metadata) -> String {
+    use sha1::{Digest, Sha1};
+    let mut hasher = Sha1::new();
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update(metadata.len().to_le_bytes());
+    if let Ok(modified) = metadata.modified() {
+        if let Ok(since) = modified.duration_since(std::time::UNIX_EPOCH) {
+            hasher.update(since.as_nanos().to_le_bytes());
+        }
+    }
+    let digest = hasher.finalize();
+    let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    format!("poster-{hex}")
+}
+
+fn probe(path: &Path, poster_dir: &Path) -> Result<VideoProbe, String> {
+    let metadata =
+        std::fs::metadata(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(format!("Not a regular file: {}", path.display()));
+    }
+    let mut file =
+        std::fs::File::open(path).map_err(|e| format!("Cannot open {}: {e}", path.display()))?;
+    let mut probe = probe_iso_bmff(&mut file)
+        .filter(|p| p.video_codec.is_some())
+        .or_else(|| probe_with_ffprobe(path))
+        .unwrap_or_default();
+
+    let stem = poster_stem(path, &metadata);
+    let cached = ["jpg", "png"]
+        .iter()
+        .map(|ext| poster_dir.join(format!("{stem}.{ext}")))
+        .find(|p| p.is_file());
+    let poster = cached.or_else(|| {
+        std::fs::create_dir_all(poster_dir).ok()?;
+        render_poster(path, probe.duration_ms, poster_dir, &stem)
+    });
+    probe.poster_path = poster.map(|p| p.to_string_lossy().into_owned());
+    Ok(probe)
+}
+
+/// Duration, display size, codecs and a poster frame for a local video.
+#[tauri::command]
+pub async fn probe_video(app: tauri::AppHandle, path: String) -> Result<VideoProbe, String> {
+    let poster_dir = crate::cache::dir(&app, "thumbnails")?;
+    tauri::async_runtime::spawn_blocking(move || probe(Path::new(&path), &poster_dir))
+        .await
+        .map_err(|e| format!("Media probe task panicked: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn tkhd(width: u32, height: u32, rotated: bool) -> Vec<u8> {
+        let mut body = vec![0u8; 4 + 20 + 16];
+        let (a, b) = if rotated {
+            (0i32, 0x10000i32)
+        } else {
+            (0x10000, 0)
+        };
+        let mut matrix = vec![0u8; 36];
+        matrix[0..4].copy_from_slice(&a.to_be_bytes());
+        matrix[4..8].copy_from_slice(&b.to_be_bytes());
+        body.extend_from_slice(&matrix);
+        body.extend_from_slice(&(width << 16).to_be_bytes());
+        body.extend_from_slice(&(height << 16).to_be_bytes());
+        mp4_box(b"tkhd", &body)
+    }
+
+    fn trak(handler: &[u8; 4], codec: &[u8; 4], tkhd: Vec<u8>) -> Vec<u8> {
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(handler);
+        hdlr.extend_from_slice(&[0u8; 12]);
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend_from_slice(&mp4_box(codec, &[0u8; 8]));
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+        let minf = mp4_box(b"minf", &stbl);
+        let mdia = mp4_box(b"mdia", &[mp4_box(b"hdlr", &hdlr), minf].concat());
+        mp4_box(b"trak", &[tkhd, mdia].concat())
+    }
+
+    fn movie(rotated: bool) -> Vec<u8> {
+        let mut mvhd = vec![0u8; 12];
+        mvhd.extend_from_slice(&600u32.to_be_bytes());
+        mvhd.extend_from_slice(&(600u32 * 12 + 300).to_be_bytes());
+        mvhd.extend_from_slice(&[0u8; 80]);
+        let moov = mp4_box(
+            b"moov",
+            &[
+                mp4_box(b"mvhd", &mvhd),
+                trak(b"soun", b"mp4a", mp4_box(b"tkhd", &[0u8; 84])),
+                trak(b"vide", b"hvc1", tkhd(1920, 1080, rotated)),
+            ]
+            .concat(),
+        );
+        // Media data first, as most cameras write it.
+        [
+            mp4_box(b"ftyp", b"qt  \0\0\0\0qt  "),
+            mp4_box(b"mdat", &[0u8; 4096]),
+            moov,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn reads_mp4_metadata_behind_media_data() {
+        let probe = probe_iso_bmff(&mut Cursor::new(movie(false))).unwrap();
+        assert_eq!(probe.duration_ms, Some(12_500));
+        assert_eq!((probe.width, probe.height), (Some(1920), Some(1080)));
+        assert_eq!(probe.video_codec.as_deref(), Some("hevc"));
+        assert_eq!(probe.audio_codec.as_deref(), Some("aac"));
+    }
+
+    #[test]
+    fn portrait_rotation_swaps_dimensions() {
+        let probe = probe_iso_bmff(&mut Cursor::new(movie(true))).unwrap();
+        assert_eq!((probe.width, probe.height), (Some(1080), Some(1920)));
+    }
+
+    #[test]
+    fn non_mp4_is_left_to_ffprobe() {
+        assert!(probe_iso_bmff(&mut Cursor::new(b"\x1a\x45\xdf\xa3webm....".to_vec())).is_none());
+    }
+
+    #[test]
+    fn parses_ffprobe_output() {
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"streams":[
+                {"codec_type":"video","codec_name":"mjpeg","disposition":{"attached_pic":1}},
+                {"codec_type":"video","codec_name":"vp9","width":1280,"height":720,
+                 "side_data_list":[{"rotation":-90}]},
+                {"codec_type":"audio","codec_name":"opus"}],
+               "format":{"duration":"3.250000"}}"#,
+        )
+        .unwrap();
+        let probe = parse_ffprobe(&json);
+        assert_eq!(probe.duration_ms, Some(3250));
+        assert_eq!(probe.video_codec.as_deref(), Some("vp9"));
+        assert_eq!((probe.width, probe.height), (Some(720), Some(1280)));
+        assert_eq!(probe.audio_codec.as_deref(), Some("opus"));
+    }
+
+    #[test]
+    fn prefers_tools_bundled_next_to_the_executable() {
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(tool_dirs().first().map(PathBuf::as_path), exe.parent());
+    }
+}
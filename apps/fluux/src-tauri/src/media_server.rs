@@ -0,0 +1,255 @@
+//! `fluux-media://` protocol: cached media served to the WebView by URL.
+//!
+//! Avatars, poster frames, GIF previews and downloaded attachments live in
+//! the app cache. Serving them through a URI scheme, rather than as bytes
+//! or base64 over IPC, lets `<img>` and `<video>` load them lazily, and
+//! byte-range support lets the video element seek without reading the
+//! whole file. URLs have the form `fluux-media://localhost/<root>/<name>`
+//! (`http://fluux-media.localhost/…` on Windows, see [`media_url`]); only
+//! the fixed cache roots below are reachable, and names cannot contain
+//! path separators.
+
+use crate::file_info;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use tauri::http::{header, Response, StatusCode};
+use tauri::Manager;
+
+pub const SCHEME: &str = "fluux-media";
+
+/// Cache subdirectories reachable through the scheme.
+const ROOTS: [&str; 4] = crate::cache::CATEGORIES;
+
+/// Largest body returned for an open-ended range (`bytes=N-`). Media
+/// elements ask for the rest of the file and read what they get, so a cap
+/// keeps seeking in a large video from loading it all into memory.
+const MAX_RANGE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// A file name usable in a media URL: a single path component, no hidden
+/// files, nothing that needs percent-encoding.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 200
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid media file name: {name}"))
+    }
+}
+
+/// URL under which the WebView can load `<root>/<name>`.
+pub fn media_url(root: &str, name: &str) -> String {
+    // WebView2 (and Android) only route custom schemes through the
+    // `http://<scheme>.localhost` form.
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{SCHEME}.localhost/{root}/{name}")
+    } else {
+        format!("{SCHEME}://localhost/{root}/{name}")
+    }
+}
+
+/// Parse a single `bytes=` range against a file of `len` bytes into an
+/// inclusive `(start, end)`. `Ok(None)` means "serve the whole file"
+/// (absent, malformed or multi-range headers); `Err` means unsatisfiable.
+fn parse_range(value: Option<&str>, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = value.and_then(|v| v.trim().strip_prefix("bytes=")) else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return Ok(None),
+        // Suffix range: the last N bytes.
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return Err(()),
+            Ok(n) => (len.saturating_sub(n), len.saturating_sub(1)),
+            Err(_) => return Ok(None),
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(s) => (
+                s,
+                len.saturating_sub(1)
+                    .min(s.saturating_add(MAX_RANGE_BYTES - 1)),
+            ),
+            Err(_) => return Ok(None),
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(s), Ok(e)) if s <= e => (s, e.min(len.saturating_sub(1))),
+            _ => return Ok(None),
+        },
+    };
+    if len == 0 || start >= len {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+fn error_response(status: StatusCode) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .body(Vec::new())
+        .expect("static response parts are valid")
+}
+
+/// Answer a request for `uri_path` (`/<root>/<name>`) from the cache
+/// directory `cache_dir`.
+fn serve(cache_dir: &Path, uri_path: &str, range: Option<&str>) -> Response<Vec<u8>> {
+    let mut parts = uri_path.trim_start_matches('/').splitn(2, '/');
+    let (Some(root), Some(name)) = (parts.next(), parts.next()) else {
+        return error_response(StatusCode::NOT_FOUND);
+    };
+    if !ROOTS.contains(&root) || validate_name(name).is_err() {
+        return error_response(StatusCode::NOT_FOUND);
+    }
+    let path = cache_dir.join(root).join(name);
+    let Ok(mut file) = File::open(&path) else {
+        return error_response(StatusCode::NOT_FOUND);
+    };
+    crate::cache::touch(&path);
+    let Ok(len) = file.metadata().map(|m| m.len()) else {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+
+    let mut head = [0u8; 16];
+    let sniffed = file.read(&mut head).unwrap_or(0);
+    let mime =
+        file_info::sniff(&head[..sniffed]).unwrap_or_else(|| file_info::mime_from_extension(&path));
+
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "private, max-age=3600")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    let (status, start, end, builder) = match parse_range(range, len) {
+        Ok(Some((start, end))) => (
+            StatusCode::PARTIAL_CONTENT,
+            start,
+            end,
+            builder.header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{len}")),
+        ),
+        Ok(None) if len == 0 => {
+            return builder
+                .status(StatusCode::OK)
+                .body(Vec::new())
+                .unwrap_or_else(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+        Ok(None) => (StatusCode::OK, 0, len - 1, builder),
+        Err(()) => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+                .body(Vec::new())
+                .unwrap_or_else(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+
+    let mut body = vec![0u8; (end - start + 1) as usize];
+    if file.seek(SeekFrom::Start(start)).is_err() || file.read_exact(&mut body).is_err() {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    builder
+        .status(status)
+        .header(header::CONTENT_LENGTH, body.len())
+        .body(body)
+        .unwrap_or_else(|_| error_response(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// Protocol handler registered for [`SCHEME`]. File reads happen on the
+/// blocking pool so a large range never stalls the WebView's IO thread.
+pub fn handle(
+    app: &tauri::AppHandle,
+    request: tauri::http::Request<Vec<u8>>,
+    responder: tauri::UriSchemeResponder,
+) {
+    let cache_dir = match app.path().app_cache_dir() {
+        Ok(dir) => dir,
+        Err(_) => {
+            responder.respond(error_response(StatusCode::INTERNAL_SERVER_ERROR));
+            return;
+        }
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let range = request
+            .headers()
+            .get(header::RANGE)
+            .and_then(|v| v.to_str().ok());
+        responder.respond(serve(&cache_dir, request.uri().path(), range));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn cache_with(name: &str, bytes: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fluux-media-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("attachments")).unwrap();
+        std::fs::write(dir.join("attachments").join(name), bytes).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(parse_range(Some("bytes=0-99"), 1000), Ok(Some((0, 99))));
+        assert_eq!(parse_range(Some("bytes=900-"), 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range(Some("bytes=-100"), 1000), Ok(Some((900, 999))));
+        assert_eq!(
+            parse_range(Some("bytes=990-2000"), 1000),
+            Ok(Some((990, 999)))
+        );
+        assert_eq!(parse_range(Some("bytes=1000-"), 1000), Err(()));
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 1000), Ok(None));
+        assert_eq!(parse_range(None, 1000), Ok(None));
+        assert_eq!(
+            parse_range(Some("bytes=0-"), 100 * 1024 * 1024),
+            Ok(Some((0, MAX_RANGE_BYTES - 1)))
+        );
+    }
+
+    #[test]
+    fn serves_whole_files_and_ranges() {
+        let dir = cache_with("clip.mp4", b"0123456789");
+        let full = serve(&dir, "/attachments/clip.mp4", None);
+        assert_eq!(full.status(), StatusCode::OK);
+        assert_eq!(full.headers()[header::CONTENT_TYPE], "video/mp4");
+        assert_eq!(full.body(), b"0123456789");
+
+        let part = serve(&dir, "/attachments/clip.mp4", Some("bytes=2-4"));
+        assert_eq!(part.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(part.headers()[header::CONTENT_RANGE], "bytes 2-4/10");
+        assert_eq!(part.body(), b"234");
+
+        let beyond = serve(&dir, "/attachments/clip.mp4", Some("bytes=10-"));
+        assert_eq!(beyond.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_cache_roots_and_plain_names_are_reachable() {
+        let dir = cache_with("a.png", b"x");
+        for path in [
+            "/attachments/../attachments/a.png",
+            "/elsewhere/a.png",
+            "/attachments/.hidden",
+            "/attachments",
+            "/attachments/missing.png",
+        ] {
+            assert_eq!(
+                serve(&dir, path, None).status(),
+                StatusCode::NOT_FOUND,
+                "{path}"
+            );
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
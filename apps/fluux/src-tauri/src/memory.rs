@@ -0,0 +1,170 @@
+//! Memory usage report and leak sentinel.
+//!
+//! [`get_memory_stats`] reports the app process's resident set size (the
+//! WebView's own processes are not included), the bytes the proxy bridges
+//! hold, the on-disk caches and the open client connections.
+//!
+//! The sentinel samples RSS every [`SAMPLE_INTERVAL`] and logs a warning
+//! with a full snapshot the first time it crosses each of
+//! [`THRESHOLDS_MB`], so a log from a machine that has been up for days
+//! shows when memory grew and what was holding it.
+
+use crate::cache::{self, CacheUsage};
+use crate::xmpp_proxy::{clients, gauges};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::warn;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+const THRESHOLDS_MB: &[u64] = &[512, 1024, 2048, 4096];
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryStats {
+    /// Resident set size of the app process; `None` when it can't be read.
+    pub rss_bytes: Option<u64>,
+    pub proxy: gauges::BufferUsage,
+    /// Client connections attached to the proxy, and how many of them are
+    /// bridged to a server.
+    pub connections: usize,
+    pub bridged_connections: usize,
+    pub caches: Vec<CacheUsage>,
+}
+
+/// `VmRSS` from `/proc/self/status` (in kB).
+#[cfg(any(target_os = "linux", test))]
+fn rss_from_status(status: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Resident set size of this process.
+fn rss() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        rss_from_status(&std::fs::read_to_string("/proc/self/status").ok()?)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // `ps` reports kB; like the idle probe, this avoids raw Mach calls.
+        let output = std::process::Command::new("ps")
+            .args(["-o", "rss=", "-p", &std::process::id().to_string()])
+            .output()
+            .ok()?;
+        let kb: u64 = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .ok()?;
+        Some(kb * 1024)
+    }
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::System::ProcessStatus::{
+            GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS,
+        };
+        use windows::Win32::System::Threading::GetCurrentProcess;
+        let mut counters = PROCESS_MEMORY_COUNTERS::default();
+        // SAFETY: the pseudo handle of our own process and a correctly sized
+        // out-parameter.
+        unsafe {
+            GetProcessMemoryInfo(
+                GetCurrentProcess(),
+                &mut counters,
+                std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+            )
+        }
+        .ok()?;
+        Some(counters.WorkingSetSize as u64)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        None
+    }
+}
+
+/// Highest threshold `rss` has reached, if any.
+fn threshold_reached(rss: u64) -> Option<u64> {
+    THRESHOLDS_MB
+        .iter()
+        .rev()
+        .copied()
+        .find(|&mb| rss >= mb * MB)
+}
+
+async fn snapshot(app: &tauri::AppHandle) -> MemoryStats {
+    let rss_bytes = tauri::async_runtime::spawn_blocking(rss)
+        .await
+        .ok()
+        .flatten();
+    let connections = clients::snapshot();
+    MemoryStats {
+        rss_bytes,
+        proxy: gauges::usage(),
+        connections: connections.len(),
+        bridged_connections: connections
+            .iter()
+            .filter(|c| c.phase == clients::ClientPhase::Bridged)
+            .count(),
+        caches: cache::get_cache_usage(app.clone())
+            .await
+            .unwrap_or_default(),
+    }
+}
+
+#[tauri::command]
+pub async fn get_memory_stats(app: tauri::AppHandle) -> MemoryStats {
+    snapshot(&app).await
+}
+
+/// Sample for the life of the app. Called from the Tauri `setup` hook.
+pub fn start_sentinel(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut reported: Option<u64> = None;
+        loop {
+            tokio::time::sleep(SAMPLE_INTERVAL).await;
+            let Some(resident) = tauri::async_runtime::spawn_blocking(rss)
+                .await
+                .ok()
+                .flatten()
+            else {
+                continue;
+            };
+            let reached = threshold_reached(resident);
+            if reached <= reported {
+                continue;
+            }
+            reported = reached;
+            let stats = snapshot(&app).await;
+            warn!(
+                rss_mb = resident / MB,
+                threshold_mb = reached.unwrap_or_default(),
+                stanza_buffer_bytes = stats.proxy.stanza_buffer_bytes,
+                ws_queue_bytes = stats.proxy.ws_queue_bytes,
+                connections = stats.connections,
+                bridged = stats.bridged_connections,
+                cache_bytes = stats.caches.iter().map(|c| c.bytes).sum::<u64>(),
+                "memory: resident size crossed a threshold"
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_rss_and_thresholds() {
+        let status = "Name:\tfluux\nVmPeak:\t  900000 kB\nVmRSS:\t  524288 kB\nThreads:\t12\n";
+        assert_eq!(rss_from_status(status), Some(512 * MB));
+        assert_eq!(rss_from_status("Name:\tfluux\n"), None);
+
+        assert_eq!(threshold_reached(100 * MB), None);
+        assert_eq!(threshold_reached(512 * MB), Some(512));
+        assert_eq!(threshold_reached(3000 * MB), Some(2048));
+        // Each threshold is reported once on the way up.
+        assert!(threshold_reached(600 * MB) <= Some(512));
+    }
+}
@@ -9,6 +9,7 @@ pub mod backend;
 mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+pub mod rules;
 pub mod settings_pane;
 #[cfg(target_os = "windows")]
 mod windows;
@@ -76,7 +77,18 @@ pub fn post_notification(
     message_id: Option<String>,
     account_id: Option<String>,
     avatar_path: Option<String>,
+    mentioned: Option<bool>,
 ) -> Result<(), String> {
+    let candidate = rules::Candidate {
+        jid: &nav_target,
+        direct: nav_type == "conversation",
+        mentioned: mentioned.unwrap_or(false),
+        body: &body,
+    };
+    if let Some(reason) = rules::check(&candidate) {
+        tracing::debug!(target = %nav_target, reason, "notification suppressed by rules");
+        return Ok(());
+    }
     let notification = NativeNotification {
         title,
         body,
@@ -88,7 +100,12 @@ pub fn post_notification(
         },
         avatar_path,
     };
+    show(notification)
+}
 
+/// Present a notification from the backend (reminders and other natively
+/// scheduled alerts). Clicks route through [`activate_target`] like any other.
+pub(crate) fn show(notification: NativeNotification) -> Result<(), String> {
     #[cfg(target_os = "macos")]
     return macos::post(notification);
     #[cfg(target_os = "linux")]
@@ -0,0 +1,300 @@
+//! Notification rules: per-conversation levels and mutes, keyword alerts and
+//! quiet hours, applied to every message notification the frontend posts
+//! (see `post_notification`).
+//!
+//! A notification is dropped when, in this order:
+//!
+//! 1. it falls within the quiet hours (local time);
+//! 2. its conversation is muted, indefinitely ([`Level::Never`]) or until
+//!    [`ConversationRule::muted_until`];
+//! 3. its conversation is set to [`Level::Mentions`] and the message neither
+//!    mentions the user nor contains one of the alert keywords. One-to-one
+//!    messages always count as mentions.
+//!
+//! Rules are persisted in `notification-rules.json`. Alerts the backend
+//! schedules itself (reminders) are not subject to them.
+
+use crate::json_file::JsonFile;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Level {
+    #[default]
+    All,
+    Mentions,
+    Never,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConversationRule {
+    pub level: Level,
+    /// Muted until this time (Unix seconds), whatever the level.
+    pub muted_until: Option<u64>,
+}
+
+/// Local time span, in minutes since midnight; wraps past midnight when
+/// `end` is before `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuietHours {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl QuietHours {
+    fn contains(self, minute: u16) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationRules {
+    /// By bare JID of the conversation or room.
+    pub conversations: BTreeMap<String, ConversationRule>,
+    /// Words that get a message through [`Level::Mentions`].
+    pub keywords: Vec<String>,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+/// A message notification about to be shown.
+pub(crate) struct Candidate<'a> {
+    pub jid: &'a str,
+    /// A one-to-one conversation.
+    pub direct: bool,
+    pub mentioned: bool,
+    pub body: &'a str,
+}
+
+static RULES: Mutex<Option<NotificationRules>> = Mutex::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+pub fn rules() -> NotificationRules {
+    RULES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Load the persisted rules from `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("notification-rules.json")) else {
+        return;
+    };
+    let loaded: NotificationRules = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *RULES.lock().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `RULES` lock held; see [`crate::json_file`].
+fn persist(snapshot: NotificationRules) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+fn update_rules(change: impl FnOnce(&mut NotificationRules)) -> NotificationRules {
+    let mut guard = RULES.lock().unwrap_or_else(|e| e.into_inner());
+    let rules = guard.get_or_insert_with(NotificationRules::default);
+    change(rules);
+    persist(rules.clone());
+    rules.clone()
+}
+
+/// `keyword` appears in `text` as a whole word, ignoring case.
+pub(crate) fn contains_word(text: &str, keyword: &str) -> bool {
+    let text = text.to_lowercase();
+    let keyword = keyword.trim().to_lowercase();
+    if keyword.is_empty() {
+        return false;
+    }
+    text.match_indices(&keyword).any(|(at, found)| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + found.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Why `candidate` must not be shown, if it mustn't.
+pub(crate) fn suppressed(
+    rules: &NotificationRules,
+    candidate: &Candidate,
+    now: u64,
+    minute_of_day: u16,
+) -> Option<&'static str> {
+    if rules.quiet_hours.is_some_and(|q| q.contains(minute_of_day)) {
+        return Some("quiet hours");
+    }
+    let rule = rules
+        .conversations
+        .get(candidate.jid)
+        .cloned()
+        .unwrap_or_default();
+    if rule.level == Level::Never || rule.muted_until.is_some_and(|until| until > now) {
+        return Some("muted");
+    }
+    if rule.level == Level::Mentions
+        && !candidate.direct
+        && !candidate.mentioned
+        && !rules
+            .keywords
+            .iter()
+            .any(|k| contains_word(candidate.body, k))
+    {
+        return Some("mentions only");
+    }
+    None
+}
+
+/// [`suppressed`] against the current rules and clock.
+pub(crate) fn check(candidate: &Candidate) -> Option<&'static str> {
+    use chrono::Timelike;
+    let local = chrono::Local::now();
+    let minute_of_day = (local.hour() * 60 + local.minute()) as u16;
+    let now = local.timestamp().max(0) as u64;
+    suppressed(&rules(), candidate, now, minute_of_day)
+}
+
+/// Replace every rule, e.g. with the ones synced from another device.
+pub(crate) fn replace(rules: NotificationRules) -> NotificationRules {
+    update_rules(|current| *current = rules)
+}
+
+#[tauri::command]
+pub fn get_notification_rules() -> NotificationRules {
+    rules()
+}
+
+/// Set the rule of the conversation or room `jid`; `None` restores the
+/// default (every message notifies).
+#[tauri::command]
+pub fn set_conversation_rule(jid: String, rule: Option<ConversationRule>) -> NotificationRules {
+    let jid = crate::xmpp_proxy::stanza::bare_jid(&jid).to_string();
+    update_rules(
+        |rules| match rule.filter(|r| *r != ConversationRule::default()) {
+            Some(rule) => {
+                rules.conversations.insert(jid, rule);
+            }
+            None => {
+                rules.conversations.remove(&jid);
+            }
+        },
+    )
+}
+
+#[tauri::command]
+pub fn set_notification_keywords(keywords: Vec<String>) -> NotificationRules {
+    let mut cleaned: Vec<String> = Vec::new();
+    for keyword in keywords.iter().map(|k| k.trim()).filter(|k| !k.is_empty()) {
+        if !cleaned.iter().any(|c| c.eq_ignore_ascii_case(keyword)) {
+            cleaned.push(keyword.to_string());
+        }
+    }
+    update_rules(|rules| rules.keywords = cleaned)
+}
+
+#[tauri::command]
+pub fn set_quiet_hours(quiet_hours: Option<QuietHours>) -> Result<NotificationRules, String> {
+    if let Some(q) = quiet_hours {
+        if q.start >= 24 * 60 || q.end >= 24 * 60 {
+            return Err("Quiet hours must be minutes since midnight (0–1439)".to_string());
+        }
+    }
+    Ok(update_rules(|rules| rules.quiet_hours = quiet_hours))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room_message(body: &str, mentioned: bool) -> Candidate<'_> {
+        Candidate {
+            jid: "ops@conference.example.com",
+            direct: false,
+            mentioned,
+            body,
+        }
+    }
+
+    #[test]
+    fn rules_apply_in_order() {
+        let mut rules = NotificationRules {
+            keywords: vec!["pager".to_string()],
+            ..Default::default()
+        };
+        rules.conversations.insert(
+            "ops@conference.example.com".to_string(),
+            ConversationRule {
+                level: Level::Mentions,
+                muted_until: None,
+            },
+        );
+        let now = 1_000_000;
+        let noon = 12 * 60;
+        assert_eq!(
+            suppressed(&rules, &room_message("lunch?", false), now, noon),
+            Some("mentions only")
+        );
+        assert_eq!(
+            suppressed(&rules, &room_message("lunch?", true), now, noon),
+            None
+        );
+        assert_eq!(
+            suppressed(&rules, &room_message("Pager: db01 down", false), now, noon),
+            None
+        );
+        assert_eq!(
+            suppressed(
+                &rules,
+                &room_message("pagerduty is quiet", false),
+                now,
+                noon
+            ),
+            Some("mentions only")
+        );
+
+        rules
+            .conversations
+            .get_mut("ops@conference.example.com")
+            .unwrap()
+            .muted_until = Some(now + 60);
+        assert_eq!(
+            suppressed(&rules, &room_message("hi", true), now, noon),
+            Some("muted")
+        );
+        assert_eq!(
+            suppressed(&rules, &room_message("hi", true), now + 60, noon),
+            None
+        );
+
+        rules.quiet_hours = Some(QuietHours {
+            start: 22 * 60,
+            end: 7 * 60,
+        });
+        assert_eq!(
+            suppressed(&rules, &room_message("hi", true), now + 60, 23 * 60),
+            Some("quiet hours")
+        );
+        assert_eq!(
+            suppressed(&rules, &room_message("hi", true), now + 60, 6 * 60 + 59),
+            Some("quiet hours")
+        );
+        assert_eq!(
+            suppressed(&rules, &room_message("hi", true), now + 60, 7 * 60),
+            None
+        );
+    }
+}
@@ -79,7 +79,7 @@ use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
 const KEYRING_SERVICE: &str = "com.processone.fluux";
 /// Keychain account prefix to namespace PGP passphrases away from XMPP
 /// credentials and the `last_user` marker.
-const KEYRING_ACCOUNT_PREFIX: &str = "openpgp_passphrase:";
+pub(crate) const KEYRING_ACCOUNT_PREFIX: &str = "openpgp_passphrase:";
 
 /// Which persistent store actually holds the passphrase right now. Used by
 /// the Tauri command shim to flag `keychainBacked: false` to the UI when
@@ -455,7 +455,11 @@ fn keyring_account(jid: &str) -> String {
 fn read_keychain_passphrase(jid: &str) -> Result<Option<Vec<u8>>> {
     let entry = Entry::new(KEYRING_SERVICE, &keyring_account(jid))
         .context("open keychain entry for passphrase")?;
-    let encoded = match entry.get_password() {
+    let encoded = match crate::credential_log::read(
+        &entry,
+        &keyring_account(jid),
+        "OpenPGP key passphrase",
+    ) {
         Ok(s) => s,
         // Genuinely absent — a legitimate "no passphrase stored" signal.
         Err(keyring::Error::NoEntry) => return Ok(None),
@@ -0,0 +1,322 @@
+//! Private PEP storage (XEP-0223): nodes on our own account that only our
+//! own account can read, for data the native side keeps in sync between
+//! devices.
+//!
+//! [`get_private`] and [`publish_private`] read and write items, checking
+//! their payloads against the node's [`Schema`] both ways so a malformed or
+//! oversized item from another client is never handed on. Publishing asks
+//! for `access_model=whitelist`; a node created earlier with another access
+//! model is reconfigured once and the publish retried. Pushes of a node
+//! registered with [`on_push`] are handed to its handler by the
+//! [`PrivatePushes`] observer and kept from the WebView, which has no use
+//! for them.
+//!
+//! Conflicts are resolved by revision: [`is_newer`] tells whether a remote
+//! revision should replace the local one (the last writer wins), and
+//! [`next_revision`] picks the revision of a local change so it sorts after
+//! everything already seen, even with a skewed clock.
+
+use crate::dataforms::{DataForm, FormField};
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use std::sync::{Arc, RwLock};
+use tracing::{debug, warn};
+
+const PUBSUB_NS: &str = "http://jabber.org/protocol/pubsub";
+const PUBSUB_OWNER_NS: &str = "http://jabber.org/protocol/pubsub#owner";
+const PUBSUB_EVENT_NS: &str = "http://jabber.org/protocol/pubsub#event";
+const PUBLISH_OPTIONS_FORM: &str = "http://jabber.org/protocol/pubsub#publish-options";
+const NODE_CONFIG_FORM: &str = "http://jabber.org/protocol/pubsub#node_config";
+
+/// What the items of a private node must look like.
+#[derive(Debug)]
+pub(crate) struct Schema {
+    pub node: &'static str,
+    /// Local name and namespace of the payload element.
+    pub root: &'static str,
+    pub ns: &'static str,
+    /// Attributes every payload carries.
+    pub required: &'static [&'static str],
+    /// Largest serialized payload accepted.
+    pub max_bytes: usize,
+}
+
+impl Schema {
+    pub(crate) fn validate(&self, payload: &Element) -> Result<(), String> {
+        if payload.local_name() != self.root || payload.ns() != Some(self.ns) {
+            return Err(format!(
+                "Unexpected <{}/> in {}",
+                payload.local_name(),
+                self.node
+            ));
+        }
+        if let Some(missing) = self.required.iter().find(|a| payload.attr(a).is_none()) {
+            return Err(format!("Item of {} lacks '{missing}'", self.node));
+        }
+        if payload.to_xml().len() > self.max_bytes {
+            return Err(format!("Item of {} is too large", self.node));
+        }
+        Ok(())
+    }
+}
+
+/// An item of a private node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Item {
+    pub id: Option<String>,
+    pub payload: Element,
+}
+
+/// Items of `items` (from a result or an event) whose payload matches
+/// `schema`; the others are skipped.
+fn valid_items(schema: &Schema, items: &Element) -> Vec<Item> {
+    items
+        .elements()
+        .filter(|item| item.local_name() == "item")
+        .filter_map(|item| {
+            let payload = item.elements().next()?;
+            if let Err(e) = schema.validate(payload) {
+                warn!(error = %e, "pep: skipping invalid item");
+                return None;
+            }
+            Some(Item {
+                id: item.attr("id").map(str::to_string),
+                payload: payload.clone(),
+            })
+        })
+        .collect()
+}
+
+fn form(form_type: &str, options: &[(&str, &str)]) -> Element {
+    let field = |var: &str, kind: Option<&str>, value: &str| FormField {
+        var: Some(var.to_string()),
+        kind: kind.map(str::to_string),
+        values: vec![value.to_string()],
+        ..Default::default()
+    };
+    let mut fields = vec![field("FORM_TYPE", Some("hidden"), form_type)];
+    fields.extend(options.iter().map(|(var, value)| field(var, None, value)));
+    DataForm {
+        kind: "submit".to_string(),
+        fields,
+        ..Default::default()
+    }
+    .to_submit_element()
+}
+
+/// Options that keep a node private and its items stored.
+const PRIVATE: [(&str, &str); 2] = [
+    ("pubsub#persist_items", "true"),
+    ("pubsub#access_model", "whitelist"),
+];
+
+fn items_request(node: &str) -> Element {
+    Element::new("iq").with_attr("type", "get").with_child(
+        Element::new("pubsub")
+            .with_attr("xmlns", PUBSUB_NS)
+            .with_child(Element::new("items").with_attr("node", node)),
+    )
+}
+
+fn publish_request(node: &str, item_id: &str, payload: Element) -> Element {
+    Element::new("iq").with_attr("type", "set").with_child(
+        Element::new("pubsub")
+            .with_attr("xmlns", PUBSUB_NS)
+            .with_child(
+                Element::new("publish").with_attr("node", node).with_child(
+                    Element::new("item")
+                        .with_attr("id", item_id)
+                        .with_child(payload),
+                ),
+            )
+            .with_child(
+                Element::new("publish-options").with_child(form(PUBLISH_OPTIONS_FORM, &PRIVATE)),
+            ),
+    )
+}
+
+fn configure_request(node: &str) -> Element {
+    Element::new("iq").with_attr("type", "set").with_child(
+        Element::new("pubsub")
+            .with_attr("xmlns", PUBSUB_OWNER_NS)
+            .with_child(
+                Element::new("configure")
+                    .with_attr("node", node)
+                    .with_child(form(NODE_CONFIG_FORM, &PRIVATE)),
+            ),
+    )
+}
+
+fn subscribe_request(node: &str, account: &str) -> Element {
+    Element::new("iq").with_attr("type", "set").with_child(
+        Element::new("pubsub")
+            .with_attr("xmlns", PUBSUB_NS)
+            .with_child(
+                Element::new("subscribe")
+                    .with_attr("node", node)
+                    .with_attr("jid", account),
+            ),
+    )
+}
+
+/// Valid items of a private node; empty when the node does not exist.
+pub(crate) async fn get_private(schema: &Schema) -> Result<Vec<Item>, String> {
+    let response = session::exchange(items_request(schema.node)).await?;
+    if response.attr("type") == Some("error") {
+        return match session::error_condition(&response).as_str() {
+            "item-not-found" => Ok(Vec::new()),
+            condition => Err(format!("Server error: {condition}")),
+        };
+    }
+    Ok(response
+        .child("pubsub", Some(PUBSUB_NS))
+        .and_then(|p| p.child("items", None))
+        .map(|items| valid_items(schema, items))
+        .unwrap_or_default())
+}
+
+/// Publish `payload` as item `item_id` of a private node, creating the node
+/// when needed.
+pub(crate) async fn publish_private(
+    schema: &Schema,
+    item_id: &str,
+    payload: Element,
+) -> Result<(), String> {
+    schema.validate(&payload)?;
+    let response =
+        session::exchange(publish_request(schema.node, item_id, payload.clone())).await?;
+    if response.attr("type") != Some("error") {
+        return Ok(());
+    }
+    match session::error_condition(&response).as_str() {
+        // The node exists with other options: make it private, then retry.
+        "precondition-not-met" | "conflict" => {
+            debug!(
+                node = schema.node,
+                "pep: reconfiguring node before publishing"
+            );
+            session::request(configure_request(schema.node)).await?;
+            session::request(publish_request(schema.node, item_id, payload))
+                .await
+                .map(drop)
+        }
+        condition => Err(format!("Server error: {condition}")),
+    }
+}
+
+/// Subscribe our bare JID to a private node, so the server pushes the other
+/// devices' publications even though the WebView does not advertise
+/// `+notify` for it.
+pub(crate) async fn subscribe_private(schema: &Schema) -> Result<(), String> {
+    let account = session::own_bare_jid().ok_or_else(|| "Not connected".to_string())?;
+    session::request(subscribe_request(schema.node, &account))
+        .await
+        .map(drop)
+}
+
+/// Is a remote `revision` newer than the `local` one?
+pub(crate) fn is_newer(local: Option<u64>, remote: u64) -> bool {
+    local.is_none_or(|local| remote > local)
+}
+
+/// Revision for a local change made at `now`: never behind `local`.
+pub(crate) fn next_revision(local: Option<u64>, now: u64) -> u64 {
+    local.map_or(now, |local| now.max(local + 1))
+}
+
+/// Receives the pushed items of a private node.
+pub(crate) trait PushHandler: Send + Sync {
+    fn pushed(&self, app: Option<&tauri::AppHandle>, items: Vec<Item>);
+}
+
+type Handlers = Vec<(&'static Schema, Arc<dyn PushHandler>)>;
+
+static HANDLERS: RwLock<Handlers> = RwLock::new(Vec::new());
+
+/// Hand the pushes of `schema`'s node to `handler`. Called from the Tauri
+/// `setup` hook.
+pub(crate) fn on_push(schema: &'static Schema, handler: Arc<dyn PushHandler>) {
+    HANDLERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((schema, handler));
+}
+
+/// Routes pushes of registered private nodes to their handlers.
+pub struct PrivatePushes;
+
+impl StanzaObserver for PrivatePushes {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction != Direction::Inbound || stanza.local_name() != "message" {
+            return Verdict::Forward;
+        }
+        let Some(items) = stanza
+            .child("event", Some(PUBSUB_EVENT_NS))
+            .and_then(|event| event.child("items", None))
+        else {
+            return Verdict::Forward;
+        };
+        // Only our own account publishes to our private nodes.
+        let own = session::own_bare_jid();
+        let from_us = match stanza.attr("from") {
+            Some(from) => own.as_deref() == Some(bare_jid(from)),
+            None => true,
+        };
+        if !from_us {
+            return Verdict::Forward;
+        }
+        let handlers = HANDLERS.read().unwrap_or_else(|e| e.into_inner());
+        let Some((schema, handler)) = handlers
+            .iter()
+            .find(|(schema, _)| items.attr("node") == Some(schema.node))
+        else {
+            return Verdict::Forward;
+        };
+        let pushed = valid_items(schema, items);
+        if !pushed.is_empty() {
+            handler.pushed(ctx.app, pushed);
+        }
+        Verdict::Drop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: Schema = Schema {
+        node: "urn:example:private:0",
+        root: "data",
+        ns: "urn:example:private:0",
+        required: &["revision"],
+        max_bytes: 64,
+    };
+
+    #[test]
+    fn keeps_only_items_matching_the_schema() {
+        let items = Element::parse(
+            "<items node='urn:example:private:0'>\
+             <item id='a'><data xmlns='urn:example:private:0' revision='1'/></item>\
+             <item id='b'><data xmlns='urn:example:private:0'/></item>\
+             <item id='c'><other xmlns='urn:example:private:0' revision='1'/></item>\
+             <item id='d'><data xmlns='urn:example:private:0' revision='1'>\
+             too large to be accepted by this schema</data></item>\
+             </items>",
+        )
+        .unwrap();
+        let valid = valid_items(&SCHEMA, &items);
+        assert_eq!(valid.len(), 1);
+        assert_eq!(valid[0].id.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn the_last_writer_wins() {
+        assert!(is_newer(None, 5));
+        assert!(is_newer(Some(4), 5));
+        assert!(!is_newer(Some(5), 5));
+        assert_eq!(next_revision(None, 10), 10);
+        assert_eq!(next_revision(Some(20), 10), 21);
+        assert_eq!(next_revision(Some(5), 10), 10);
+    }
+}
@@ -0,0 +1,290 @@
+//! Power source and battery level.
+//!
+//! Read natively on each platform and polled, since none of them offers one
+//! change notification that works everywhere:
+//!
+//! - Linux: `/sys/class/power_supply` (system batteries and mains/USB
+//!   supplies; peripheral batteries such as a mouse's are ignored).
+//! - macOS: `pmset -g batt`.
+//! - Windows: `GetSystemPowerStatus`.
+//!
+//! Changes are emitted as `power-source-changed` with the new
+//! [`PowerState`]. On battery the native keepalive tick is stretched (see
+//! [`scale_interval`]) and the frontend puts the SDK in power saving mode,
+//! which defers the post-connect MAM sync; both go back to normal on AC.
+//!
+//! Whether the system is asleep is tracked too ([`set_asleep`], from the
+//! macOS sleep and wake notifications): the bridge watchdog and the
+//! keepalive tick wait in [`until_awake`] rather than firing during a dark
+//! wake. Wakes are also counted ([`woke`], which the keepalive calls when it
+//! notices a sleep gap on the other platforms), so the bridge can check its
+//! upstream right away (see `xmpp_proxy::wake`).
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::sync::watch;
+use tracing::info;
+
+const POWER_EVENT: &str = "power-source-changed";
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How much longer periodic network work waits on battery.
+const BATTERY_STRETCH: u32 = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerState {
+    /// Running on battery. `false` on machines without one, or when the
+    /// source can't be read.
+    pub on_battery: bool,
+    /// Charge of the system battery, when there is one.
+    pub battery_percent: Option<u8>,
+}
+
+static ON_BATTERY: AtomicBool = AtomicBool::new(false);
+static LAST: Mutex<Option<PowerState>> = Mutex::new(None);
+static ASLEEP: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::Sender::new(false));
+static WAKES: LazyLock<watch::Sender<u64>> = LazyLock::new(|| watch::Sender::new(0));
+
+/// Record that the system is going to sleep (`true`) or woke up.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn set_asleep(asleep: bool) {
+    let was_asleep = ASLEEP.send_replace(asleep);
+    if was_asleep && !asleep {
+        woke();
+    }
+}
+
+/// Record a wake from sleep.
+pub fn woke() {
+    WAKES.send_modify(|wakes| *wakes += 1);
+}
+
+/// Changes with every wake from sleep.
+pub fn subscribe_wakes() -> watch::Receiver<u64> {
+    WAKES.subscribe()
+}
+
+/// Return once the system is awake; at once when it is.
+pub async fn until_awake() {
+    let mut asleep = ASLEEP.subscribe();
+    let _ = asleep.wait_for(|asleep| !asleep).await;
+}
+
+/// `interval` for the current power source: as is on AC, stretched on
+/// battery. `ZERO` stays `ZERO`.
+pub fn scale_interval(interval: Duration) -> Duration {
+    if ON_BATTERY.load(Ordering::Relaxed) {
+        interval * BATTERY_STRETCH
+    } else {
+        interval
+    }
+}
+
+fn read() -> PowerState {
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    {
+        platform::read()
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        PowerState::default()
+    }
+}
+
+/// Read the state and record it; `Some` when it differs from the last one.
+fn refresh() -> Option<PowerState> {
+    let state = read();
+    ON_BATTERY.store(state.on_battery, Ordering::Relaxed);
+    let mut last = LAST.lock().unwrap_or_else(|e| e.into_inner());
+    (last.replace(state) != Some(state)).then_some(state)
+}
+
+/// Poll for the life of the app. Called from the Tauri `setup` hook.
+pub fn watch(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        if let Some(state) = refresh() {
+            info!(
+                on_battery = state.on_battery,
+                percent = ?state.battery_percent,
+                "power source changed"
+            );
+            let _ = app.emit(POWER_EVENT, state);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[tauri::command]
+pub fn get_power_state() -> PowerState {
+    refresh();
+    LAST.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .unwrap_or_default()
+}
+
+/// One entry of `/sys/class/power_supply`.
+#[cfg(any(target_os = "linux", test))]
+#[derive(Debug)]
+struct Supply {
+    /// `type`: `Battery`, `Mains`, `USB`…
+    kind: String,
+    online: bool,
+    capacity: Option<u8>,
+    /// `scope` is `Device`: a peripheral's battery, not ours.
+    peripheral: bool,
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn from_supplies(supplies: &[Supply]) -> PowerState {
+    let battery = supplies
+        .iter()
+        .find(|s| s.kind == "Battery" && !s.peripheral);
+    let external = supplies
+        .iter()
+        .any(|s| s.kind != "Battery" && !s.peripheral && s.online);
+    PowerState {
+        on_battery: battery.is_some() && !external,
+        battery_percent: battery.and_then(|b| b.capacity),
+    }
+}
+
+/// `pmset -g batt` output: the source on the first line, then one line per
+/// battery with its charge (`85%;`).
+#[cfg(any(target_os = "macos", test))]
+fn from_pmset(output: &str) -> PowerState {
+    let battery_percent = output
+        .lines()
+        .skip(1)
+        .flat_map(|line| line.split_whitespace())
+        .find_map(|word| word.strip_suffix("%;")?.parse().ok());
+    PowerState {
+        on_battery: output.contains("'Battery Power'"),
+        battery_percent,
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{from_supplies, PowerState, Supply};
+    use std::path::Path;
+
+    fn field(dir: &Path, name: &str) -> Option<String> {
+        std::fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|s| s.trim().to_string())
+    }
+
+    pub fn read() -> PowerState {
+        let Ok(entries) = std::fs::read_dir("/sys/class/power_supply") else {
+            return PowerState::default();
+        };
+        let supplies: Vec<Supply> = entries
+            .flatten()
+            .map(|entry| {
+                let dir = entry.path();
+                Supply {
+                    kind: field(&dir, "type").unwrap_or_default(),
+                    online: field(&dir, "online").as_deref() == Some("1"),
+                    capacity: field(&dir, "capacity").and_then(|c| c.parse().ok()),
+                    peripheral: field(&dir, "scope").as_deref() == Some("Device"),
+                }
+            })
+            .collect();
+        from_supplies(&supplies)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{from_pmset, PowerState};
+
+    pub fn read() -> PowerState {
+        std::process::Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| from_pmset(&String::from_utf8_lossy(&output.stdout)))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::PowerState;
+    use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    /// `BatteryFlag` bit for "no system battery".
+    const NO_BATTERY: u8 = 128;
+
+    pub fn read() -> PowerState {
+        let mut status = SYSTEM_POWER_STATUS::default();
+        // SAFETY: plain out-parameter call.
+        if unsafe { GetSystemPowerStatus(&mut status) }.is_err() {
+            return PowerState::default();
+        }
+        let has_battery = status.BatteryFlag != 255 && status.BatteryFlag & NO_BATTERY == 0;
+        PowerState {
+            on_battery: has_battery && status.ACLineStatus == 0,
+            battery_percent: Some(status.BatteryLifePercent).filter(|&p| has_battery && p <= 100),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn supply(kind: &str, online: bool, capacity: Option<u8>, peripheral: bool) -> Supply {
+        Supply {
+            kind: kind.to_string(),
+            online,
+            capacity,
+            peripheral,
+        }
+    }
+
+    #[test]
+    fn reads_linux_supplies_and_pmset() {
+        let mouse = supply("Battery", false, Some(10), true);
+        let battery = supply("Battery", false, Some(64), false);
+        assert_eq!(
+            from_supplies(&[mouse, battery, supply("Mains", true, None, false)]),
+            PowerState {
+                on_battery: false,
+                battery_percent: Some(64)
+            }
+        );
+        let battery = supply("Battery", false, Some(64), false);
+        assert!(from_supplies(&[battery, supply("Mains", false, None, false)]).on_battery);
+        // A desktop: mains only.
+        assert!(!from_supplies(&[supply("Mains", false, None, false)]).on_battery);
+
+        let pmset = "Now drawing from 'Battery Power'\n \
+            -InternalBattery-0 (id=4653155)\t85%; discharging; 4:12 remaining present: true\n";
+        assert_eq!(
+            from_pmset(pmset),
+            PowerState {
+                on_battery: true,
+                battery_percent: Some(85)
+            }
+        );
+        assert!(!from_pmset("Now drawing from 'AC Power'\n").on_battery);
+    }
+
+    #[tokio::test]
+    async fn timers_wait_for_the_wake() {
+        until_awake().await;
+        set_asleep(true);
+        let waiting = tokio::spawn(until_awake());
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        let mut wakes = subscribe_wakes();
+        set_asleep(false);
+        waiting.await.unwrap();
+        assert!(wakes.has_changed().unwrap());
+    }
+}
@@ -0,0 +1,418 @@
+//! Own profile: vCard4 over PEP (XEP-0292) and user avatar (XEP-0084).
+//!
+//! Profile editing used to pull the avatar through the JS bridge as base64,
+//! scale it in a canvas and push it back the same way — megabytes per round
+//! trip. Here the WebView only hands over a file path: decoding, scaling,
+//! hashing and publication all happen natively over the bridged session (see
+//! [`crate::xmpp_proxy::session`]), and the published avatar is cached on
+//! disk so the UI can show it through the asset protocol.
+
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{Element, Node};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+const PUBSUB_NS: &str = "http://jabber.org/protocol/pubsub";
+const VCARD4_NODE: &str = "urn:xmpp:vcard4";
+const VCARD4_NS: &str = "urn:ietf:params:xml:ns:vcard-4.0";
+const AVATAR_DATA_NODE: &str = "urn:xmpp:avatar:data";
+const AVATAR_METADATA_NODE: &str = "urn:xmpp:avatar:metadata";
+
+/// Longest side of a published avatar, in pixels.
+const AVATAR_MAX_SIDE: u32 = 192;
+
+/// Largest source image accepted for an avatar.
+const AVATAR_MAX_SOURCE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Editable vCard properties.
+///
+/// In `set_own_profile`, `None` leaves a property as published, an empty
+/// string removes it, and anything else replaces it. Properties Fluux does not
+/// edit are carried over untouched.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileFields {
+    pub full_name: Option<String>,
+    pub nickname: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub url: Option<String>,
+    pub organization: Option<String>,
+    pub title: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvatarInfo {
+    /// XEP-0084 item id: hex SHA-1 of the image bytes.
+    pub id: String,
+    pub mime_type: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Cached copy on disk, when the data could be fetched.
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnProfile {
+    pub fields: ProfileFields,
+    pub avatar: Option<AvatarInfo>,
+}
+
+/// vCard4 property name and value element for each editable field.
+const FIELD_MAP: [(&str, &str); 8] = [
+    ("fn", "text"),
+    ("nickname", "text"),
+    ("email", "text"),
+    ("tel", "uri"),
+    ("url", "uri"),
+    ("org", "text"),
+    ("title", "text"),
+    ("note", "text"),
+];
+
+impl ProfileFields {
+    fn as_array(&self) -> [&Option<String>; 8] {
+        [
+            &self.full_name,
+            &self.nickname,
+            &self.email,
+            &self.phone,
+            &self.url,
+            &self.organization,
+            &self.title,
+            &self.note,
+        ]
+    }
+
+    fn from_vcard(vcard: &Element) -> Self {
+        let value = |index: usize| {
+            let (property, kind) = FIELD_MAP[index];
+            vcard
+                .child(property, None)
+                .and_then(|p| p.child(kind, None))
+                .map(|v| v.text())
+                .map(|v| match property {
+                    "tel" => v.strip_prefix("tel:").unwrap_or(&v).to_string(),
+                    _ => v,
+                })
+                .filter(|v| !v.is_empty())
+        };
+        ProfileFields {
+            full_name: value(0),
+            nickname: value(1),
+            email: value(2),
+            phone: value(3),
+            url: value(4),
+            organization: value(5),
+            title: value(6),
+            note: value(7),
+        }
+    }
+}
+
+/// Apply edited fields onto the published vCard, keeping every property that
+/// is not edited (addresses, birthday, extension properties, …).
+fn merge_vcard(existing: Option<&Element>, fields: &ProfileFields) -> Element {
+    let mut vcard = existing
+        .cloned()
+        .unwrap_or_else(|| Element::new("vcard").with_attr("xmlns", VCARD4_NS));
+    for ((property, kind), value) in FIELD_MAP.iter().zip(fields.as_array()) {
+        let Some(value) = value else {
+            continue;
+        };
+        let value = value.trim();
+        let position = vcard.children.iter().position(
+            |node| matches!(node, Node::Element(e) if e.local_name() == *property),
+        );
+        vcard.children.retain(
+            |node| !matches!(node, Node::Element(e) if e.local_name() == *property),
+        );
+        if value.is_empty() {
+            continue;
+        }
+        let value = match *property {
+            "tel" if !value.starts_with("tel:") => format!("tel:{value}"),
+            _ => value.to_string(),
+        };
+        let element = Node::Element(
+            Element::new(property).with_child(Element::new(kind).with_text(&value)),
+        );
+        match position {
+            Some(index) if index <= vcard.children.len() => vcard.children.insert(index, element),
+            _ => vcard.children.push(element),
+        }
+    }
+    vcard
+}
+
+struct PreparedAvatar {
+    png: Vec<u8>,
+    id: String,
+    width: u32,
+    height: u32,
+}
+
+/// Decode, scale down to [`AVATAR_MAX_SIDE`] and re-encode as PNG.
+fn prepare_avatar(source: &[u8]) -> Result<PreparedAvatar, String> {
+    let image =
+        image::load_from_memory(source).map_err(|e| format!("Unsupported avatar image: {e}"))?;
+    let image = if image.width() > AVATAR_MAX_SIDE || image.height() > AVATAR_MAX_SIDE {
+        image.resize(
+            AVATAR_MAX_SIDE,
+            AVATAR_MAX_SIDE,
+            image::imageops::FilterType::Lanczos3,
+        )
+    } else {
+        image
+    };
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode avatar: {e}"))?;
+    Ok(PreparedAvatar {
+        id: sha1_hex(&png),
+        width: image.width(),
+        height: image.height(),
+        png,
+    })
+}
+
+pub(crate) fn sha1_hex(bytes: &[u8]) -> String {
+    Sha1::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn items_request(node: &str, item_id: Option<&str>) -> Element {
+    let items = Element::new("items").with_attr("node", node);
+    let items = match item_id {
+        Some(id) => items.with_child(Element::new("item").with_attr("id", id)),
+        None => items.with_attr("max_items", "1"),
+    };
+    Element::new("iq").with_attr("type", "get").with_child(
+        Element::new("pubsub")
+            .with_attr("xmlns", PUBSUB_NS)
+            .with_child(items),
+    )
+}
+
+fn publish_request(node: &str, item_id: &str, payload: Element) -> Element {
+    Element::new("iq").with_attr("type", "set").with_child(
+        Element::new("pubsub").with_attr("xmlns", PUBSUB_NS).with_child(
+            Element::new("publish").with_attr("node", node).with_child(
+                Element::new("item")
+                    .with_attr("id", item_id)
+                    .with_child(payload),
+            ),
+        ),
+    )
+}
+
+/// Payload of the (first) item of a PEP node on our own account; `None` when
+/// the node or item does not exist yet.
+async fn fetch_item(node: &str, item_id: Option<&str>) -> Result<Option<Element>, String> {
+    let response = session::exchange(items_request(node, item_id)).await?;
+    if response.attr("type") == Some("error") {
+        return match session::error_condition(&response).as_str() {
+            "item-not-found" => Ok(None),
+            condition => Err(format!("Server error: {condition}")),
+        };
+    }
+    Ok(response
+        .child("pubsub", Some(PUBSUB_NS))
+        .and_then(|p| p.child("items", None))
+        .and_then(|items| items.child("item", None))
+        .and_then(|item| item.elements().next())
+        .cloned())
+}
+
+fn avatar_cache_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    crate::cache::dir(app, "avatars")
+}
+
+fn cached_avatar_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.png"))
+}
+
+/// Cache a fetched avatar after checking it matches its advertised hash.
+fn store_avatar(dir: &Path, id: &str, bytes: &[u8]) -> Result<PathBuf, String> {
+    if sha1_hex(bytes) != id.to_ascii_lowercase() {
+        return Err("Avatar data does not match its hash".to_string());
+    }
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create avatar cache: {e}"))?;
+    let path = cached_avatar_path(dir, id);
+    std::fs::write(&path, bytes).map_err(|e| format!("Failed to cache avatar: {e}"))?;
+    Ok(path)
+}
+
+async fn fetch_own_avatar(app: &tauri::AppHandle) -> Result<Option<AvatarInfo>, String> {
+    let Some(metadata) = fetch_item(AVATAR_METADATA_NODE, None).await? else {
+        return Ok(None);
+    };
+    let Some(info) = metadata.child("info", None) else {
+        // Empty metadata: the avatar was explicitly disabled.
+        return Ok(None);
+    };
+    let Some(id) = info.attr("id").map(str::to_string) else {
+        return Ok(None);
+    };
+    let dir = avatar_cache_dir(app)?;
+    let cached = cached_avatar_path(&dir, &id);
+    let path = if cached.exists() {
+        Some(cached)
+    } else if info.attr("url").is_some() {
+        // Avatar hosted out of band; the frontend fetches it from the URL.
+        None
+    } else {
+        match fetch_item(AVATAR_DATA_NODE, Some(&id)).await? {
+            Some(data) => {
+                let bytes = BASE64
+                    .decode(data.text().split_whitespace().collect::<String>())
+                    .map_err(|e| format!("Malformed avatar data: {e}"))?;
+                Some(store_avatar(&dir, &id, &bytes)?)
+            }
+            None => None,
+        }
+    };
+    Ok(Some(AvatarInfo {
+        id,
+        mime_type: info.attr("type").unwrap_or("image/png").to_string(),
+        width: info.attr("width").and_then(|w| w.parse().ok()),
+        height: info.attr("height").and_then(|h| h.parse().ok()),
+        path: path.map(|p| p.to_string_lossy().into_owned()),
+    }))
+}
+
+/// Fetch our own vCard4 and avatar from PEP.
+#[tauri::command]
+pub async fn get_own_profile(app: tauri::AppHandle) -> Result<OwnProfile, String> {
+    let fields = fetch_item(VCARD4_NODE, None)
+        .await?
+        .filter(|vcard| vcard.local_name() == "vcard")
+        .map(|vcard| ProfileFields::from_vcard(&vcard))
+        .unwrap_or_default();
+    let avatar = fetch_own_avatar(&app).await?;
+    Ok(OwnProfile { fields, avatar })
+}
+
+/// Publish profile edits and, when `avatar_path` is given, a new avatar read
+/// from that file. Returns the profile as now published.
+#[tauri::command]
+pub async fn set_own_profile(
+    app: tauri::AppHandle,
+    fields: ProfileFields,
+    avatar_path: Option<String>,
+) -> Result<OwnProfile, String> {
+    let existing = fetch_item(VCARD4_NODE, None).await?;
+    let vcard = merge_vcard(existing.as_ref(), &fields);
+    let published_fields = ProfileFields::from_vcard(&vcard);
+    session::request(publish_request(VCARD4_NODE, "current", vcard)).await?;
+
+    let avatar = match avatar_path {
+        Some(path) => Some(publish_avatar(&app, PathBuf::from(path)).await?),
+        None => fetch_own_avatar(&app).await?,
+    };
+    Ok(OwnProfile {
+        fields: published_fields,
+        avatar,
+    })
+}
+
+async fn publish_avatar(app: &tauri::AppHandle, source: PathBuf) -> Result<AvatarInfo, String> {
+    let avatar = tauri::async_runtime::spawn_blocking(move || {
+        let size = std::fs::metadata(&source)
+            .map_err(|e| format!("Cannot read avatar file: {e}"))?
+            .len();
+        if size > AVATAR_MAX_SOURCE_BYTES {
+            return Err("Avatar image is too large".to_string());
+        }
+        let bytes = std::fs::read(&source).map_err(|e| format!("Cannot read avatar file: {e}"))?;
+        prepare_avatar(&bytes)
+    })
+    .await
+    .map_err(|e| format!("Avatar task panicked: {e}"))??;
+
+    // XEP-0084 §4.1: data first, so metadata never points at a missing item.
+    let data = Element::new("data")
+        .with_attr("xmlns", AVATAR_DATA_NODE)
+        .with_text(&BASE64.encode(&avatar.png));
+    session::request(publish_request(AVATAR_DATA_NODE, &avatar.id, data)).await?;
+
+    let width = avatar.width.to_string();
+    let height = avatar.height.to_string();
+    let bytes = avatar.png.len().to_string();
+    let metadata = Element::new("metadata")
+        .with_attr("xmlns", AVATAR_METADATA_NODE)
+        .with_child(
+            Element::new("info")
+                .with_attr("bytes", &bytes)
+                .with_attr("id", &avatar.id)
+                .with_attr("type", "image/png")
+                .with_attr("width", &width)
+                .with_attr("height", &height),
+        );
+    session::request(publish_request(AVATAR_METADATA_NODE, &avatar.id, metadata)).await?;
+
+    let path = store_avatar(&avatar_cache_dir(app)?, &avatar.id, &avatar.png)?;
+    Ok(AvatarInfo {
+        id: avatar.id,
+        mime_type: "image/png".to_string(),
+        width: Some(avatar.width),
+        height: Some(avatar.height),
+        path: Some(path.to_string_lossy().into_owned()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_unedited_properties_and_order() {
+        let existing = Element::parse(
+            "<vcard xmlns='urn:ietf:params:xml:ns:vcard-4.0'><fn><text>Old</text></fn><bday><date>1990-01-01</date></bday><note><text>bye</text></note></vcard>",
+        )
+        .unwrap();
+        let fields = ProfileFields {
+            full_name: Some("New Name".into()),
+            phone: Some("+33 1 23".into()),
+            note: Some(String::new()),
+            ..Default::default()
+        };
+        let merged = merge_vcard(Some(&existing), &fields);
+        let names: Vec<&str> = merged.elements().map(|e| e.local_name()).collect();
+        assert_eq!(names, vec!["fn", "bday", "tel"]);
+        let read_back = ProfileFields::from_vcard(&merged);
+        assert_eq!(read_back.full_name.as_deref(), Some("New Name"));
+        assert_eq!(read_back.phone.as_deref(), Some("+33 1 23"));
+        assert_eq!(read_back.note, None);
+    }
+
+    #[test]
+    fn avatar_is_scaled_to_png_and_hashed() {
+        let source = image::RgbaImage::from_pixel(400, 200, image::Rgba([200, 10, 10, 255]));
+        let mut encoded = Vec::new();
+        image::DynamicImage::ImageRgba8(source)
+            .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::Png)
+            .unwrap();
+
+        let avatar = prepare_avatar(&encoded).unwrap();
+        assert_eq!((avatar.width, avatar.height), (192, 96));
+        assert_eq!(avatar.id, sha1_hex(&avatar.png));
+        assert_eq!(avatar.id.len(), 40);
+        assert!(prepare_avatar(b"not an image").is_err());
+    }
+
+    #[test]
+    fn sha1_hex_matches_known_vector() {
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+}
@@ -0,0 +1,254 @@
+//! XEP-0357 push registrations, managed from the desktop.
+//!
+//! A push app server (such as ProcessOne's) hands each device a node, and
+//! optionally a secret, when the device registers with it. Enabling push
+//! tells our server to notify that node while the device is offline. These
+//! commands let the desktop enable and disable push for any device the user
+//! has registered, including a companion phone.
+//!
+//! The server keeps no list we could query, so the registrations enabled
+//! from here are remembered in the OS keychain, one slot per account. The
+//! node and secret authorize publishing to the app server, so they are kept
+//! with the other credentials and never returned to the frontend whole.
+
+use crate::dataforms::{DataForm, FormField};
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::Element;
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+
+const PUSH_NS: &str = "urn:xmpp:push:0";
+const PUBLISH_OPTIONS_FORM: &str = "http://jabber.org/protocol/pubsub#publish-options";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredRegistration {
+    app_server: String,
+    node: String,
+    secret: Option<String>,
+}
+
+/// A registration as shown to the frontend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushRegistration {
+    pub app_server: String,
+    /// The node's first characters, enough to tell devices apart.
+    pub node_hint: String,
+    pub has_secret: bool,
+}
+
+impl From<&StoredRegistration> for PushRegistration {
+    fn from(stored: &StoredRegistration) -> Self {
+        PushRegistration {
+            app_server: stored.app_server.clone(),
+            node_hint: stored.node.chars().take(8).collect(),
+            has_secret: stored.secret.is_some(),
+        }
+    }
+}
+
+pub(crate) fn keyring_user(account: &str) -> String {
+    format!("push:{account}")
+}
+
+fn load(account: &str) -> Result<Vec<StoredRegistration>, String> {
+    let entry = Entry::new(crate::KEYRING_SERVICE, &keyring_user(account))
+        .map_err(|e| format!("Failed to create keyring entry for push: {e}"))?;
+    match crate::credential_log::read(&entry, &keyring_user(account), "push registrations") {
+        Ok(json) => serde_json::from_str(&json)
+            .map_err(|e| format!("Stored push registrations are unreadable: {e}")),
+        Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+        Err(e) => Err(crate::classify_keyring_error(&e, "read push registrations")),
+    }
+}
+
+fn save(account: &str, registrations: &[StoredRegistration]) -> Result<(), String> {
+    let entry = Entry::new(crate::KEYRING_SERVICE, &keyring_user(account))
+        .map_err(|e| format!("Failed to create keyring entry for push: {e}"))?;
+    if registrations.is_empty() {
+        return match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(crate::classify_keyring_error(
+                &e,
+                "delete push registrations",
+            )),
+        };
+    }
+    let json = serde_json::to_string(registrations)
+        .map_err(|e| format!("Failed to serialize push registrations: {e}"))?;
+    entry
+        .set_password(&json)
+        .map_err(|e| crate::classify_keyring_error(&e, "save push registrations"))
+}
+
+/// Run a keychain operation off the async runtime.
+async fn with_keychain<T: Send + 'static>(
+    op: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tokio::task::spawn_blocking(op)
+        .await
+        .map_err(|e| format!("Keychain task panicked: {e}"))?
+}
+
+fn current_account() -> Result<String, String> {
+    session::own_bare_jid().ok_or_else(|| "Not connected".to_string())
+}
+
+fn enable_request(app_server: &str, node: &str, secret: Option<&str>) -> Element {
+    let mut enable = Element::new("enable")
+        .with_attr("xmlns", PUSH_NS)
+        .with_attr("jid", app_server)
+        .with_attr("node", node);
+    if let Some(secret) = secret {
+        let field = |var: &str, kind: Option<&str>, value: &str| FormField {
+            var: Some(var.to_string()),
+            kind: kind.map(str::to_string),
+            values: vec![value.to_string()],
+            ..Default::default()
+        };
+        let form = DataForm {
+            kind: "submit".to_string(),
+            fields: vec![
+                field("FORM_TYPE", Some("hidden"), PUBLISH_OPTIONS_FORM),
+                field("secret", None, secret),
+            ],
+            ..Default::default()
+        };
+        enable = enable.with_child(form.to_submit_element());
+    }
+    Element::new("iq")
+        .with_attr("type", "set")
+        .with_child(enable)
+}
+
+fn disable_request(app_server: &str, node: Option<&str>) -> Element {
+    let mut disable = Element::new("disable")
+        .with_attr("xmlns", PUSH_NS)
+        .with_attr("jid", app_server);
+    if let Some(node) = node {
+        disable.set_attr("node", node);
+    }
+    Element::new("iq")
+        .with_attr("type", "set")
+        .with_child(disable)
+}
+
+fn validate_app_server(app_server: &str) -> Result<(), String> {
+    if app_server.is_empty() || app_server.contains(char::is_whitespace) {
+        return Err(format!("Invalid push app server: {app_server}"));
+    }
+    Ok(())
+}
+
+/// Enable push notifications to `node` on `app_server` for the connected
+/// account and remember the registration. Enabling the same node again
+/// replaces its secret.
+#[tauri::command]
+pub async fn enable_push(
+    app_server: String,
+    node: String,
+    secret: Option<String>,
+) -> Result<Vec<PushRegistration>, String> {
+    let app_server = app_server.trim().to_string();
+    validate_app_server(&app_server)?;
+    if node.trim().is_empty() {
+        return Err("Push node is empty".to_string());
+    }
+    let secret = secret.filter(|s| !s.is_empty());
+    let account = current_account()?;
+    session::request(enable_request(&app_server, &node, secret.as_deref())).await?;
+    tracing::info!(app_server = %app_server, "Push enabled");
+    with_keychain(move || {
+        let mut registrations = load(&account)?;
+        registrations.retain(|r| !(r.app_server == app_server && r.node == node));
+        registrations.push(StoredRegistration {
+            app_server,
+            node,
+            secret,
+        });
+        save(&account, &registrations)?;
+        Ok(registrations.iter().map(PushRegistration::from).collect())
+    })
+    .await
+}
+
+/// Disable push on `app_server`: for the registration whose node starts with
+/// `node_hint`, or for every node on that app server when no hint is given.
+#[tauri::command]
+pub async fn disable_push(
+    app_server: String,
+    node_hint: Option<String>,
+) -> Result<Vec<PushRegistration>, String> {
+    let app_server = app_server.trim().to_string();
+    validate_app_server(&app_server)?;
+    let account = current_account()?;
+    let lookup_account = account.clone();
+    let stored = with_keychain(move || load(&lookup_account)).await?;
+    let node = match &node_hint {
+        Some(hint) => {
+            let mut matching = stored
+                .iter()
+                .filter(|r| r.app_server == app_server && r.node.starts_with(hint.as_str()));
+            let found = matching
+                .next()
+                .ok_or_else(|| format!("No push registration matches {hint}"))?;
+            if matching.next().is_some() {
+                return Err(format!("Several push registrations match {hint}"));
+            }
+            Some(found.node.clone())
+        }
+        None => None,
+    };
+    session::request(disable_request(&app_server, node.as_deref())).await?;
+    tracing::info!(app_server = %app_server, "Push disabled");
+    with_keychain(move || {
+        let mut registrations = load(&account)?;
+        registrations.retain(|r| {
+            r.app_server != app_server || node.as_ref().is_some_and(|node| &r.node != node)
+        });
+        save(&account, &registrations)?;
+        Ok(registrations.iter().map(PushRegistration::from).collect())
+    })
+    .await
+}
+
+/// Registrations enabled from this desktop for the connected account.
+#[tauri::command]
+pub async fn list_push_registrations() -> Result<Vec<PushRegistration>, String> {
+    let account = current_account()?;
+    with_keychain(move || Ok(load(&account)?.iter().map(PushRegistration::from).collect())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_carry_node_and_secret_form() {
+        let enable = enable_request("push.example.com", "yxs32uqsflafdk3iuqo", Some("eruio"));
+        let xml = enable.to_xml();
+        let parsed = Element::parse(&xml).unwrap();
+        let enable = parsed.child("enable", Some(PUSH_NS)).unwrap();
+        assert_eq!(enable.attr("jid"), Some("push.example.com"));
+        assert_eq!(enable.attr("node"), Some("yxs32uqsflafdk3iuqo"));
+        let form = DataForm::find_in(enable).unwrap();
+        assert_eq!(form.fields[0].values, [PUBLISH_OPTIONS_FORM]);
+        assert_eq!(form.fields[1].var.as_deref(), Some("secret"));
+        assert_eq!(form.fields[1].values, ["eruio"]);
+
+        let bare = enable_request("push.example.com", "n", None);
+        assert!(bare.child("enable", None).unwrap().children.is_empty());
+
+        let disable = disable_request("push.example.com", None);
+        assert_eq!(disable.child("disable", None).unwrap().attr("node"), None);
+
+        let shown = PushRegistration::from(&StoredRegistration {
+            app_server: "push.example.com".to_string(),
+            node: "yxs32uqsflafdk3iuqo".to_string(),
+            secret: Some("eruio".to_string()),
+        });
+        assert_eq!(shown.node_hint, "yxs32uqs");
+        assert!(shown.has_secret);
+    }
+}
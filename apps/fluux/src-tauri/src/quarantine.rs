@@ -0,0 +1,158 @@
+//! Mark received files as downloaded from the internet, like a browser does.
+//!
+//! - Windows: the Attachment Execution Service (`IAttachmentExecute`) writes
+//!   the Mark of the Web (`Zone.Identifier` stream) and has the registered
+//!   antivirus scan the file. If the service can't be used, the stream is
+//!   written directly.
+//! - macOS: the `com.apple.quarantine` attribute, so Gatekeeper checks the
+//!   file when it is opened.
+//! - Linux: the freedesktop `user.xdg.origin.url` attribute, which file
+//!   managers show as the file's origin.
+//!
+//! Called by the native download pipeline on every file it writes:
+//! attachments cached by `download_file` and files received over Jingle.
+
+use std::path::Path;
+
+/// Mark `path` as downloaded from `source` (a URL, or `xmpp:` + sender).
+/// Blocking. `Err` only when the file was refused (an antivirus blocked or
+/// removed it); failing to add the mark is logged and otherwise ignored.
+pub fn mark_downloaded(path: &Path, source: &str) -> Result<(), String> {
+    platform::mark(path, source)
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::path::Path;
+    use tracing::{debug, warn};
+    use windows::core::{GUID, HSTRING};
+    use windows::Win32::Foundation::RPC_E_CHANGED_MODE;
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
+        COINIT_APARTMENTTHREADED,
+    };
+    use windows::Win32::UI::Shell::{AttachmentServices, IAttachmentExecute};
+
+    /// Identifies Fluux to the Attachment Execution Service, which keeps
+    /// per-client prompt settings.
+    const CLIENT_GUID: GUID = GUID::from_u128(0x5d8a_31c4_9b0e_4f6a_8c2d_71e4_a3b9_06f2);
+    /// URLZONE_INTERNET.
+    const INTERNET_ZONE: u32 = 3;
+
+    fn zone_identifier(source: &str) -> String {
+        let mut stream = format!("[ZoneTransfer]\r\nZoneId={INTERNET_ZONE}\r\n");
+        if source.starts_with("http://") || source.starts_with("https://") {
+            stream.push_str(&format!("HostUrl={source}\r\n"));
+        }
+        stream
+    }
+
+    fn write_stream(path: &Path, source: &str) {
+        let stream = format!("{}:Zone.Identifier", path.display());
+        if let Err(e) = std::fs::write(&stream, zone_identifier(source)) {
+            warn!(path = %path.display(), error = %e, "quarantine: cannot write Zone.Identifier");
+        }
+    }
+
+    pub(super) fn mark(path: &Path, source: &str) -> Result<(), String> {
+        // SAFETY: COM is initialised for this (blocking-pool) thread and
+        // released below; the interface does not outlive this function.
+        unsafe {
+            let init = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+            if init.is_err() && init != RPC_E_CHANGED_MODE {
+                write_stream(path, source);
+                return Ok(());
+            }
+            let result = (|| -> windows::core::Result<()> {
+                let execute: IAttachmentExecute =
+                    CoCreateInstance(&AttachmentServices, None, CLSCTX_INPROC_SERVER)?;
+                execute.SetClientGuid(&CLIENT_GUID)?;
+                execute.SetLocalPath(&HSTRING::from(path.as_os_str()))?;
+                execute.SetSource(&HSTRING::from(source))?;
+                // Applies the zone and runs the antivirus scan.
+                execute.Save()
+            })();
+            if init.is_ok() {
+                CoUninitialize();
+            }
+            match result {
+                Ok(()) => {
+                    debug!(path = %path.display(), "quarantine: marked and scanned");
+                    Ok(())
+                }
+                // A scanner that removed the file has made the decision.
+                Err(_) if !path.exists() => Err("The file was blocked by antivirus".to_string()),
+                Err(e) => {
+                    warn!(error = %e, "quarantine: attachment services failed");
+                    write_stream(path, source);
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::path::Path;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tracing::warn;
+
+    /// `kLSQuarantineTypeOtherDownload`-style flags: quarantined, not yet
+    /// approved by the user.
+    const FLAGS: &str = "0081";
+
+    pub(super) fn mark(path: &Path, _source: &str) -> Result<(), String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let value = format!(
+            "{FLAGS};{now:08x};Fluux;{}",
+            uuid::Uuid::new_v4().to_string().to_uppercase()
+        );
+        if let Err(e) = xattr::set(path, "com.apple.quarantine", value.as_bytes()) {
+            warn!(path = %path.display(), error = %e, "quarantine: cannot set attribute");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::path::Path;
+    use tracing::debug;
+
+    pub(super) fn mark(path: &Path, source: &str) -> Result<(), String> {
+        // Filesystems without user attributes (tmpfs on older kernels,
+        // some network mounts) just don't get the hint.
+        if let Err(e) = xattr::set(path, "user.xdg.origin.url", source.as_bytes()) {
+            debug!(path = %path.display(), error = %e, "quarantine: no origin attribute");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    use std::path::Path;
+
+    pub(super) fn mark(_path: &Path, _source: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marking_never_fails_on_linux() {
+        let path = std::env::temp_dir().join(format!("fluux-quarantine-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"x").unwrap();
+        mark_downloaded(&path, "https://upload.example.org/file.txt").unwrap();
+        if let Ok(Some(origin)) = xattr::get(&path, "user.xdg.origin.url") {
+            assert_eq!(origin, b"https://upload.example.org/file.txt");
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
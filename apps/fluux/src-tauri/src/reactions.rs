@@ -0,0 +1,483 @@
+//! Aggregated message reactions (XEP-0444).
+//!
+//! Every `<reactions/>` element passing through the bridge, live, carbon
+//! copied or replayed from the archive, replaces the previous set of that
+//! sender on that message. The store keeps the per-sender sets and hands the
+//! UI the aggregate only: per emoji, a count, the first few senders and
+//! whether we are among them.
+//!
+//! Changes are coalesced: the `reactions-changed` event goes out at most
+//! every [`FLUSH_DELAY`] and carries only the messages that changed since the
+//! last one, so a busy room costs one re-render per flush instead of one per
+//! stanza. A view that mounts later asks [`get_message_reactions`].
+//!
+//! Senders are the bare JID in chats and the occupant nick in rooms, as in
+//! the SDK. Tracking is in memory and bounded; the persisted state belongs in
+//! the native message store once it exists (see
+//! docs/2026-10-15-native-message-store.md).
+
+use crate::unread::UnreadCounters;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, resource, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::sync::Notify;
+
+const CHANGED_EVENT: &str = "reactions-changed";
+
+const REACTIONS_NS: &str = "urn:xmpp:reactions:0";
+const CARBONS_NS: &str = "urn:xmpp:carbons:2";
+const FORWARD_NS: &str = "urn:xmpp:forward:0";
+const MAM_NS: &str = "urn:xmpp:mam:2";
+const DELAY_NS: &str = "urn:xmpp:delay";
+
+/// Messages with reactions tracked at once. The oldest reacted-to are
+/// forgotten first.
+const MAX_MESSAGES: usize = 5000;
+/// Distinct emojis kept from one `<reactions/>` element.
+const MAX_EMOJIS_PER_SENDER: usize = 20;
+/// Senders listed per emoji in a delta; the count stays exact.
+const MAX_SENDERS_LISTED: usize = 10;
+/// How long changes are gathered before they are pushed.
+const FLUSH_DELAY: Duration = Duration::from_millis(150);
+
+/// One emoji on a message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: u32,
+    /// The first [`MAX_SENDERS_LISTED`] senders, in sender order.
+    pub senders: Vec<String>,
+    /// We reacted with this emoji.
+    pub mine: bool,
+}
+
+/// The reactions on one message. An empty list means the last one was
+/// withdrawn.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageReactions {
+    pub conversation_id: String,
+    pub message_id: String,
+    /// Most used first.
+    pub reactions: Vec<ReactionCount>,
+}
+
+/// (conversation bare JID, referenced message id)
+type Key = (String, String);
+
+struct SenderReactions {
+    emojis: Vec<String>,
+    /// Milliseconds since the epoch, from the delay stamp when there is one.
+    /// An older set never replaces a newer one, whatever order the archive
+    /// pages arrive in.
+    at: i64,
+    own: bool,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Senders per message. A withdrawn set stays as an empty one so an
+    /// older set replayed later cannot bring it back.
+    messages: HashMap<Key, BTreeMap<String, SenderReactions>>,
+    /// Keys in the order they were first reacted to.
+    order: VecDeque<Key>,
+    /// Keys changed since the last flush.
+    dirty: BTreeSet<Key>,
+}
+
+/// A parsed `<reactions/>` element.
+#[derive(Debug, PartialEq, Eq)]
+struct Reaction {
+    conversation_id: String,
+    message_id: String,
+    sender: String,
+    own: bool,
+    emojis: Vec<String>,
+    at: i64,
+}
+
+/// Reaction store, registered with the bridge tap and held in managed state
+/// as `Arc<ReactionStore>`.
+pub struct ReactionStore {
+    inner: Mutex<Inner>,
+    /// Our nick per room.
+    unread: Arc<UnreadCounters>,
+    changed: Notify,
+}
+
+impl ReactionStore {
+    pub fn new(unread: Arc<UnreadCounters>) -> Self {
+        ReactionStore {
+            inner: Mutex::new(Inner::default()),
+            unread,
+            changed: Notify::new(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record one sender's set. Returns whether the aggregate may have
+    /// changed.
+    fn apply(&self, reaction: Reaction) -> bool {
+        let mut inner = self.lock();
+        let key = (reaction.conversation_id, reaction.message_id);
+        if !inner.messages.contains_key(&key) {
+            inner.order.push_back(key.clone());
+        }
+        let senders = inner.messages.entry(key.clone()).or_default();
+        match senders.get_mut(&reaction.sender) {
+            Some(previous) if previous.at > reaction.at => return false,
+            Some(previous) if previous.emojis == reaction.emojis => {
+                previous.at = reaction.at;
+                return false;
+            }
+            _ => {}
+        }
+        senders.insert(
+            reaction.sender,
+            SenderReactions {
+                emojis: reaction.emojis,
+                at: reaction.at,
+                own: reaction.own,
+            },
+        );
+        inner.dirty.insert(key);
+        while inner.order.len() > MAX_MESSAGES {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.messages.remove(&oldest);
+                inner.dirty.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// The aggregate of every message changed since the last call.
+    fn take_changes(&self) -> Vec<MessageReactions> {
+        let mut inner = self.lock();
+        let dirty = std::mem::take(&mut inner.dirty);
+        dirty
+            .into_iter()
+            .filter_map(|key| {
+                let senders = inner.messages.get(&key)?;
+                Some(aggregate(key.0, key.1, senders))
+            })
+            .collect()
+    }
+
+    /// Current reactions on those of `message_ids` that have any.
+    pub fn reactions(
+        &self,
+        conversation_id: &str,
+        message_ids: &[String],
+    ) -> Vec<MessageReactions> {
+        let inner = self.lock();
+        message_ids
+            .iter()
+            .filter_map(|id| {
+                let key = (conversation_id.to_string(), id.clone());
+                let senders = inner.messages.get(&key)?;
+                let summary = aggregate(key.0, key.1, senders);
+                (!summary.reactions.is_empty()).then_some(summary)
+            })
+            .collect()
+    }
+}
+
+fn aggregate(
+    conversation_id: String,
+    message_id: String,
+    senders: &BTreeMap<String, SenderReactions>,
+) -> MessageReactions {
+    let mut by_emoji: BTreeMap<&str, ReactionCount> = BTreeMap::new();
+    for (sender, set) in senders {
+        for emoji in &set.emojis {
+            let entry = by_emoji.entry(emoji).or_insert_with(|| ReactionCount {
+                emoji: emoji.clone(),
+                count: 0,
+                senders: Vec::new(),
+                mine: false,
+            });
+            entry.count = entry.count.saturating_add(1);
+            entry.mine |= set.own;
+            if entry.senders.len() < MAX_SENDERS_LISTED {
+                entry.senders.push(sender.clone());
+            }
+        }
+    }
+    let mut reactions: Vec<ReactionCount> = by_emoji.into_values().collect();
+    reactions.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emoji.cmp(&b.emoji)));
+    MessageReactions {
+        conversation_id,
+        message_id,
+        reactions,
+    }
+}
+
+fn stamp_millis(element: &Element) -> Option<i64> {
+    let stamp = element.child("delay", Some(DELAY_NS))?.attr("stamp")?;
+    chrono::DateTime::parse_from_rfc3339(stamp)
+        .ok()
+        .map(|t| t.timestamp_millis())
+}
+
+/// Parse the reactions carried by `stanza`, unwrapping carbons and archive
+/// results. `own_nick` gives our nick in a room.
+fn parse(
+    direction: Direction,
+    stanza: &Element,
+    own_bare: Option<&str>,
+    own_nick: impl Fn(&str) -> Option<String>,
+    now: i64,
+) -> Option<Reaction> {
+    if stanza.local_name() != "message" {
+        return None;
+    }
+    let mut message = stanza;
+    let mut at = stamp_millis(stanza);
+    if direction == Direction::Outbound {
+        // The room reflects our reactions back; count them then.
+        if stanza.attr("type") == Some("groupchat") {
+            return None;
+        }
+    } else {
+        let outer_from = stanza.attr("from");
+        let from_own_account = outer_from.is_none_or(|from| Some(from) == own_bare);
+        let forwarded = from_own_account
+            .then(|| {
+                stanza
+                    .child("sent", Some(CARBONS_NS))
+                    .or_else(|| stanza.child("received", Some(CARBONS_NS)))
+            })
+            .flatten()
+            .and_then(|carbon| carbon.child("forwarded", Some(FORWARD_NS)))
+            .or_else(|| {
+                stanza
+                    .child("result", Some(MAM_NS))
+                    .and_then(|result| result.child("forwarded", Some(FORWARD_NS)))
+            });
+        if let Some(forwarded) = forwarded {
+            let inner = forwarded.child("message", None)?;
+            // A room archive only speaks for that room.
+            let room_archive =
+                !from_own_account && inner.attr("from").map(bare_jid) == outer_from.map(bare_jid);
+            if !from_own_account && !room_archive {
+                return None;
+            }
+            at = stamp_millis(forwarded)
+                .or_else(|| stamp_millis(inner))
+                .or(at);
+            message = inner;
+        }
+    }
+
+    let reactions = message.child("reactions", Some(REACTIONS_NS))?;
+    let message_id = reactions.attr("id").filter(|id| !id.is_empty())?;
+    let mut emojis: Vec<String> = Vec::new();
+    for reaction in reactions
+        .elements()
+        .filter(|e| e.local_name() == "reaction")
+    {
+        let emoji = reaction.text().trim().to_string();
+        if !emoji.is_empty() && !emojis.contains(&emoji) {
+            emojis.push(emoji);
+        }
+    }
+    emojis.truncate(MAX_EMOJIS_PER_SENDER);
+
+    let (conversation_id, sender, own) = match message.attr("type") {
+        Some("error") => return None,
+        Some("groupchat") => {
+            let from = message.attr("from")?;
+            let room = bare_jid(from);
+            let nick = resource(from)?;
+            let own = own_nick(room).as_deref() == Some(nick);
+            (room.to_string(), nick.to_string(), own)
+        }
+        _ => {
+            let from = message.attr("from").map(bare_jid).or(own_bare)?;
+            if Some(from) == own_bare {
+                let to = bare_jid(message.attr("to")?);
+                (to.to_string(), from.to_string(), true)
+            } else {
+                (from.to_string(), from.to_string(), false)
+            }
+        }
+    };
+    Some(Reaction {
+        conversation_id,
+        message_id: message_id.to_string(),
+        sender,
+        own,
+        emojis,
+        at: at.unwrap_or(now),
+    })
+}
+
+impl StanzaObserver for ReactionStore {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        let own = session::own_jid();
+        let own_bare = own.as_deref().map(bare_jid);
+        let now = chrono::Utc::now().timestamp_millis();
+        let reaction = parse(
+            ctx.direction,
+            stanza,
+            own_bare,
+            |room| self.unread.own_room_nick(room),
+            now,
+        );
+        if reaction.is_some_and(|r| self.apply(r)) {
+            self.changed.notify_one();
+        }
+        Verdict::Forward
+    }
+}
+
+/// Push the gathered changes for the lifetime of the app. Called from
+/// `setup`.
+pub fn start(app: tauri::AppHandle, store: Arc<ReactionStore>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            store.changed.notified().await;
+            tokio::time::sleep(FLUSH_DELAY).await;
+            let changes = store.take_changes();
+            if !changes.is_empty() {
+                let _ = app.emit(CHANGED_EVENT, changes);
+            }
+        }
+    });
+}
+
+/// Reactions on `message_ids` in one conversation, for a view that mounts
+/// after the events went out. Messages without reactions are left out.
+#[tauri::command]
+pub fn get_message_reactions(
+    store: tauri::State<'_, Arc<ReactionStore>>,
+    conversation_id: String,
+    message_ids: Vec<String>,
+) -> Vec<MessageReactions> {
+    store.reactions(&conversation_id, &message_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWN: Option<&str> = Some("me@example.com");
+
+    fn ingest(store: &ReactionStore, direction: Direction, xml: &str) -> bool {
+        let stanza = Element::parse(xml).unwrap();
+        let own_nick = |_: &str| Some("me".to_string());
+        parse(direction, &stanza, OWN, own_nick, 1_000).is_some_and(|r| store.apply(r))
+    }
+
+    fn emojis(summary: &MessageReactions) -> Vec<(&str, u32, bool)> {
+        summary
+            .reactions
+            .iter()
+            .map(|r| (r.emoji.as_str(), r.count, r.mine))
+            .collect()
+    }
+
+    #[test]
+    fn room_reactions_aggregate_per_sender() {
+        let store = ReactionStore::new(Arc::new(UnreadCounters::default()));
+        let react = |nick: &str, body: &str| {
+            format!(
+                "<message from='room@muc.example.com/{nick}' type='groupchat'>\
+                 <reactions xmlns='urn:xmpp:reactions:0' id='s1'>{body}</reactions></message>"
+            )
+        };
+        let thumbs = "<reaction>👍</reaction>";
+        assert!(ingest(&store, Direction::Inbound, &react("ann", thumbs)));
+        assert!(ingest(&store, Direction::Inbound, &react("bob", thumbs)));
+        let both = "<reaction>🎉</reaction><reaction>👍</reaction><reaction>👍</reaction>";
+        assert!(ingest(&store, Direction::Inbound, &react("me", both)));
+        // The same set again is not a change.
+        assert!(!ingest(&store, Direction::Inbound, &react("me", both)));
+        // Our own outbound copy waits for the reflection.
+        assert!(!ingest(
+            &store,
+            Direction::Outbound,
+            "<message to='room@muc.example.com' type='groupchat'>\
+             <reactions xmlns='urn:xmpp:reactions:0' id='s1'><reaction>🔥</reaction>\
+             </reactions></message>"
+        ));
+
+        let changes = store.take_changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(emojis(&changes[0]), vec![("👍", 3, true), ("🎉", 1, true)]);
+        assert_eq!(changes[0].reactions[0].senders, vec!["ann", "bob", "me"]);
+        assert!(store.take_changes().is_empty());
+
+        // Bob withdraws; the message stays listed until the last one goes.
+        assert!(ingest(&store, Direction::Inbound, &react("bob", "")));
+        let ids = vec!["s1".to_string()];
+        let current = store.reactions("room@muc.example.com", &ids);
+        assert_eq!(emojis(&current[0]), vec![("👍", 2, true), ("🎉", 1, true)]);
+    }
+
+    #[test]
+    fn carbons_and_archive_pages_resolve_to_the_latest_set() {
+        let store = ReactionStore::new(Arc::new(UnreadCounters::default()));
+        let archived = |stamp: &str, emoji: &str| {
+            format!(
+                "<message to='me@example.com/pc'>\
+                 <result xmlns='urn:xmpp:mam:2' queryid='q' id='a'>\
+                 <forwarded xmlns='urn:xmpp:forward:0'>\
+                 <delay xmlns='urn:xmpp:delay' stamp='{stamp}'/>\
+                 <message from='bob@example.com/phone' to='me@example.com' type='chat'>\
+                 <reactions xmlns='urn:xmpp:reactions:0' id='m1'>\
+                 <reaction>{emoji}</reaction></reactions></message>\
+                 </forwarded></result></message>"
+            )
+        };
+        // The newer page arrives first; the older one must not win.
+        assert!(ingest(
+            &store,
+            Direction::Inbound,
+            &archived("2026-01-02T00:00:00Z", "❤")
+        ));
+        assert!(!ingest(
+            &store,
+            Direction::Inbound,
+            &archived("2026-01-01T00:00:00Z", "👎")
+        ));
+
+        // Our reaction from another device, as a sent carbon.
+        assert!(ingest(
+            &store,
+            Direction::Inbound,
+            "<message from='me@example.com' to='me@example.com/pc'>\
+             <sent xmlns='urn:xmpp:carbons:2'><forwarded xmlns='urn:xmpp:forward:0'>\
+             <message from='me@example.com/phone' to='bob@example.com' type='chat'>\
+             <reactions xmlns='urn:xmpp:reactions:0' id='m1'><reaction>❤</reaction>\
+             </reactions></message></forwarded></sent></message>"
+        ));
+        // Nobody else can forge a carbon.
+        assert!(!ingest(
+            &store,
+            Direction::Inbound,
+            "<message from='eve@example.com'>\
+             <sent xmlns='urn:xmpp:carbons:2'><forwarded xmlns='urn:xmpp:forward:0'>\
+             <message from='me@example.com/phone' to='bob@example.com' type='chat'>\
+             <reactions xmlns='urn:xmpp:reactions:0' id='m1'><reaction>👎</reaction>\
+             </reactions></message></forwarded></sent></message>"
+        ));
+
+        let ids = vec!["m1".to_string()];
+        let current = store.reactions("bob@example.com", &ids);
+        assert_eq!(emojis(&current[0]), vec![("❤", 2, true)]);
+        assert_eq!(
+            current[0].reactions[0].senders,
+            vec!["bob@example.com", "me@example.com"]
+        );
+    }
+}
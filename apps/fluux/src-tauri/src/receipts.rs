@@ -0,0 +1,335 @@
+//! Delivery state of the messages we send.
+//!
+//! Every outbound message with a body is tracked as it passes through the
+//! bridge and moves forward through the states the protocol can tell apart:
+//! sent, acknowledged by the server (XEP-0198), delivered to a recipient
+//! device (XEP-0184 or a XEP-0333 `received` marker) and displayed (XEP-0333).
+//! Each change is pushed as a `message-delivery-state` event, so the
+//! checkmarks have one source of truth instead of being derived separately
+//! from acks, receipts and markers in JS.
+//!
+//! States only move forward, except to `failed` on a bounced message.
+//! Displayed markers are cumulative: one for a message also covers every
+//! earlier message in the same conversation. Tracking is in memory and
+//! bounded; the persisted state belongs in the native message store once it
+//! exists (see docs/2026-10-15-native-message-store.md).
+
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tauri::Emitter;
+
+const STATE_EVENT: &str = "message-delivery-state";
+
+const RECEIPTS_NS: &str = "urn:xmpp:receipts";
+const MARKERS_NS: &str = "urn:xmpp:chat-markers:0";
+const CARBONS_NS: &str = "urn:xmpp:carbons:2";
+const FORWARD_NS: &str = "urn:xmpp:forward:0";
+
+/// Messages tracked at once. The oldest are forgotten first; by then the UI
+/// has long shown their final state.
+const MAX_TRACKED: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    Sent,
+    ServerAcked,
+    Delivered,
+    Displayed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateChange {
+    pub id: String,
+    /// Bare JID of the conversation.
+    pub to: String,
+    pub state: DeliveryState,
+}
+
+struct Tracked {
+    to: String,
+    state: DeliveryState,
+    /// Stream-management sequence number, when the session has one.
+    seq: Option<u32>,
+}
+
+#[derive(Default)]
+struct Inner {
+    messages: HashMap<String, Tracked>,
+    /// Ids in the order they were sent.
+    order: VecDeque<String>,
+}
+
+/// Delivery tracker, registered with the bridge tap and held in managed
+/// state as `Arc<ReceiptTracker>`.
+#[derive(Default)]
+pub struct ReceiptTracker {
+    inner: Mutex<Inner>,
+}
+
+/// `seq` is covered by the acknowledged count `acked` (modulo 2^32).
+fn is_acked(seq: u32, acked: u32) -> bool {
+    acked.wrapping_sub(seq) < 1 << 31
+}
+
+impl ReceiptTracker {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn track(&self, id: &str, to: &str, seq: Option<u32>) -> StateChange {
+        let mut inner = self.lock();
+        // A retransmission after resumption keeps its place but gets the new
+        // sequence number.
+        if inner.messages.contains_key(id) {
+            inner.order.retain(|known| known != id);
+        }
+        inner.messages.insert(
+            id.to_string(),
+            Tracked {
+                to: to.to_string(),
+                state: DeliveryState::Sent,
+                seq,
+            },
+        );
+        inner.order.push_back(id.to_string());
+        while inner.order.len() > MAX_TRACKED {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.messages.remove(&oldest);
+            }
+        }
+        StateChange {
+            id: id.to_string(),
+            to: to.to_string(),
+            state: DeliveryState::Sent,
+        }
+    }
+
+    /// Move `id` forward to `state`. `from` is the bare JID the update came
+    /// from and must be the conversation the message was sent to.
+    fn advance(&self, id: &str, from: &str, state: DeliveryState) -> Vec<StateChange> {
+        let mut inner = self.lock();
+        let Some(target) = inner.messages.get(id) else {
+            return Vec::new();
+        };
+        if target.to != from {
+            return Vec::new();
+        }
+        // Displayed markers also cover earlier messages to the same peer.
+        let ids: Vec<String> = if state == DeliveryState::Displayed {
+            let position = inner.order.iter().position(|known| known == id);
+            inner
+                .order
+                .iter()
+                .take(position.map_or(0, |p| p + 1))
+                .filter(|known| inner.messages.get(*known).is_some_and(|t| t.to == from))
+                .cloned()
+                .collect()
+        } else {
+            vec![id.to_string()]
+        };
+        let mut changes = Vec::new();
+        for id in ids {
+            let Some(tracked) = inner.messages.get_mut(&id) else {
+                continue;
+            };
+            // `Failed` sorts last, so it is final and overrides the rest.
+            if tracked.state >= state {
+                continue;
+            }
+            tracked.state = state;
+            changes.push(StateChange {
+                id,
+                to: tracked.to.clone(),
+                state,
+            });
+        }
+        changes
+    }
+
+    /// Mark as server-acknowledged everything covered by `acked`.
+    fn acknowledge(&self, acked: u32) -> Vec<StateChange> {
+        let mut inner = self.lock();
+        let mut changes = Vec::new();
+        for (id, tracked) in inner.messages.iter_mut() {
+            if tracked.state == DeliveryState::Sent
+                && tracked.seq.is_some_and(|seq| is_acked(seq, acked))
+            {
+                tracked.state = DeliveryState::ServerAcked;
+                changes.push(StateChange {
+                    id: id.clone(),
+                    to: tracked.to.clone(),
+                    state: DeliveryState::ServerAcked,
+                });
+            }
+        }
+        changes
+    }
+
+    /// Current state of `id`, if it is still tracked.
+    pub fn state(&self, id: &str) -> Option<DeliveryState> {
+        self.lock().messages.get(id).map(|t| t.state)
+    }
+
+    fn ingest_outbound(&self, stanza: &Element) -> Vec<StateChange> {
+        if stanza.local_name() != "message" || stanza.child("body", None).is_none() {
+            return Vec::new();
+        }
+        let (Some(id), Some(to)) = (stanza.attr("id"), stanza.attr("to")) else {
+            return Vec::new();
+        };
+        vec![self.track(id, bare_jid(to), session::next_outbound_seq())]
+    }
+
+    fn ingest_inbound(&self, stanza: &Element) -> Vec<StateChange> {
+        if stanza.local_name() != "message" {
+            return Vec::new();
+        }
+        // A receipt or marker that reached another of our devices arrives as
+        // a carbon copy, which only our own account may send.
+        let own_carbon = match stanza.attr("from") {
+            None => true,
+            Some(from) => session::own_bare_jid().as_deref() == Some(from),
+        };
+        let message = own_carbon
+            .then(|| stanza.child("received", Some(CARBONS_NS)))
+            .flatten()
+            .and_then(|carbon| carbon.child("forwarded", Some(FORWARD_NS)))
+            .and_then(|forwarded| forwarded.child("message", None))
+            .unwrap_or(stanza);
+        let Some(from) = message.attr("from").map(bare_jid) else {
+            return Vec::new();
+        };
+        if message.attr("type") == Some("error") {
+            return match message.attr("id") {
+                Some(id) => self.advance(id, from, DeliveryState::Failed),
+                None => Vec::new(),
+            };
+        }
+        let update = message
+            .child("received", Some(RECEIPTS_NS))
+            .or_else(|| message.child("received", Some(MARKERS_NS)))
+            .map(|e| (e, DeliveryState::Delivered))
+            .or_else(|| {
+                message
+                    .child("displayed", Some(MARKERS_NS))
+                    .map(|e| (e, DeliveryState::Displayed))
+            });
+        match update {
+            Some((element, state)) => match element.attr("id") {
+                Some(id) => self.advance(id, from, state),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        }
+    }
+}
+
+fn publish(app: &tauri::AppHandle, changes: Vec<StateChange>) {
+    for change in changes {
+        let _ = app.emit(STATE_EVENT, change);
+    }
+}
+
+impl StanzaObserver for ReceiptTracker {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        let changes = match ctx.direction {
+            Direction::Outbound => self.ingest_outbound(stanza),
+            Direction::Inbound => self.ingest_inbound(stanza),
+        };
+        if let Some(app) = ctx.app {
+            publish(app, changes);
+        }
+        Verdict::Forward
+    }
+}
+
+/// Follow stream-management acks for the lifetime of the app. Called from
+/// `setup`.
+pub fn start(app: tauri::AppHandle, tracker: Arc<ReceiptTracker>) {
+    let mut acks = session::subscribe_acks();
+    tauri::async_runtime::spawn(async move {
+        while acks.changed().await.is_ok() {
+            let acked = *acks.borrow_and_update();
+            publish(&app, tracker.acknowledge(acked));
+        }
+    });
+}
+
+/// Current delivery state of each of `ids` still tracked, for a view that
+/// mounts after the events went out.
+#[tauri::command]
+pub fn get_delivery_states(
+    tracker: tauri::State<'_, Arc<ReceiptTracker>>,
+    ids: Vec<String>,
+) -> HashMap<String, DeliveryState> {
+    ids.into_iter()
+        .filter_map(|id| tracker.state(&id).map(|state| (id, state)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(xml: &str) -> Element {
+        Element::parse(xml).unwrap()
+    }
+
+    #[test]
+    fn states_move_forward_from_the_right_peer_only() {
+        let tracker = ReceiptTracker::default();
+        tracker.track("m1", "romeo@example.net", Some(1));
+        tracker.track("m2", "romeo@example.net", Some(2));
+        tracker.track("m3", "nurse@example.net", Some(3));
+
+        let acked = tracker.acknowledge(2);
+        assert_eq!(acked.len(), 2);
+        assert_eq!(tracker.state("m3"), Some(DeliveryState::Sent));
+
+        // Receipts from someone else are ignored.
+        let spoofed = parse(
+            "<message from='tybalt@example.net/x'>\
+             <received xmlns='urn:xmpp:receipts' id='m1'/></message>",
+        );
+        assert!(tracker.ingest_inbound(&spoofed).is_empty());
+
+        let receipt = parse(
+            "<message from='romeo@example.net/phone'>\
+             <received xmlns='urn:xmpp:receipts' id='m1'/></message>",
+        );
+        assert_eq!(tracker.ingest_inbound(&receipt).len(), 1);
+        assert_eq!(tracker.state("m1"), Some(DeliveryState::Delivered));
+
+        // Displaying m2 covers m1 too, but not the other conversation.
+        let displayed = parse(
+            "<message from='romeo@example.net/phone'>\
+             <displayed xmlns='urn:xmpp:chat-markers:0' id='m2'/></message>",
+        );
+        assert_eq!(tracker.ingest_inbound(&displayed).len(), 2);
+        assert_eq!(tracker.state("m1"), Some(DeliveryState::Displayed));
+        assert_eq!(tracker.state("m3"), Some(DeliveryState::Sent));
+
+        // A late receipt does not move a displayed message back.
+        assert!(tracker.ingest_inbound(&receipt).is_empty());
+        assert_eq!(tracker.state("m1"), Some(DeliveryState::Displayed));
+
+        let bounce = parse("<message type='error' from='nurse@example.net' id='m3'/>");
+        assert_eq!(tracker.ingest_inbound(&bounce).len(), 1);
+        assert_eq!(tracker.state("m3"), Some(DeliveryState::Failed));
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        assert!(is_acked(5, 5));
+        assert!(!is_acked(6, 5));
+        assert!(is_acked(u32::MAX, 2));
+        assert!(!is_acked(2, u32::MAX));
+    }
+}
@@ -0,0 +1,314 @@
+//! Recent conversations in the Windows taskbar jump list and the macOS dock
+//! menu.
+//!
+//! The list comes from [`crate::unread`], which orders conversations by
+//! their latest message either way. Every entry stands for an
+//! `xmpp:JID?message` URI (`?join` for rooms, XEP-0147), so picking one takes
+//! the deep-link route any other `xmpp:` link takes:
+//!
+//! - Windows: the jump list entry relaunches Fluux with the URI as its
+//!   argument; the single-instance plugin hands it to the running copy as a
+//!   deep link.
+//! - macOS: the dock menu is built from the store whenever it opens, and a
+//!   pick is emitted as a deep-link event from here.
+
+use crate::unread::RecentConversation;
+
+/// Entries shown; the stores may hold more.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const MAX_ENTRIES: usize = 10;
+
+/// What `xmpp:` URIs may carry unescaped in the path (RFC 5122 §2.2 keeps
+/// RFC 3986 unreserved and sub-delims, plus `@` between the parts).
+#[cfg(any(target_os = "macos", target_os = "windows", test))]
+fn keep_unescaped(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~!$&'()*+,;=@".contains(c)
+}
+
+/// The URI that opens `conversation`.
+#[cfg(any(target_os = "macos", target_os = "windows", test))]
+fn uri(conversation: &RecentConversation) -> String {
+    let mut uri = String::from("xmpp:");
+    for c in conversation.conversation_id.chars() {
+        if keep_unescaped(c) {
+            uri.push(c);
+        } else {
+            let mut buf = [0u8; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                uri.push_str(&format!("%{byte:02X}"));
+            }
+        }
+    }
+    uri.push_str(if conversation.groupchat {
+        "?join"
+    } else {
+        "?message"
+    });
+    uri
+}
+
+/// Roster name, or the address.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn label(conversation: &RecentConversation) -> &str {
+    conversation
+        .name
+        .as_deref()
+        .unwrap_or(&conversation.conversation_id)
+}
+
+/// The recent list changed. Only the Windows jump list needs pushing; the
+/// dock menu is built when it opens.
+pub fn changed(app: &tauri::AppHandle, recent: &[RecentConversation]) {
+    #[cfg(target_os = "windows")]
+    jump_list::update(app, recent);
+    #[cfg(not(target_os = "windows"))]
+    let _ = (app, recent);
+}
+
+#[cfg(target_os = "windows")]
+mod jump_list {
+    use super::{label, uri, MAX_ENTRIES};
+    use crate::shell_i18n;
+    use crate::unread::RecentConversation;
+    use std::sync::Mutex;
+    use tauri::Manager;
+    use tracing::warn;
+    use windows::core::{Interface, Result, HSTRING, PROPVARIANT};
+    use windows::Win32::Storage::EnhancedStorage::PKEY_Title;
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
+        COINIT_APARTMENTTHREADED,
+    };
+    use windows::Win32::UI::Shell::Common::{IObjectArray, IObjectCollection};
+    use windows::Win32::UI::Shell::PropertiesSystem::IPropertyStore;
+    use windows::Win32::UI::Shell::{
+        DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW, ShellLink,
+    };
+
+    /// Updates run on their own thread; one at a time, so the last one wins.
+    static UPDATING: Mutex<()> = Mutex::new(());
+
+    pub fn update(app: &tauri::AppHandle, recent: &[RecentConversation]) {
+        let category =
+            shell_i18n::strings(app.state::<shell_i18n::UiLanguage>().get()).conversations;
+        let entries: Vec<(String, String)> = recent
+            .iter()
+            .take(MAX_ENTRIES)
+            .map(|c| (label(c).to_string(), uri(c)))
+            .collect();
+        std::thread::spawn(move || {
+            let _guard = UPDATING.lock().unwrap_or_else(|e| e.into_inner());
+            // SAFETY: COM is initialized on this thread for the duration of
+            // the update and every interface is released before uninit.
+            let result = unsafe {
+                let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+                let result = build(category, &entries);
+                CoUninitialize();
+                result
+            };
+            if let Err(e) = result {
+                warn!(error = %e, "jump list: update failed");
+            }
+        });
+    }
+
+    /// Arguments of a jump list link.
+    unsafe fn arguments(link: &IShellLinkW) -> Option<String> {
+        let mut buf = [0u16; 2048];
+        link.GetArguments(&mut buf).ok()?;
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        Some(String::from_utf16_lossy(&buf[..len]))
+    }
+
+    unsafe fn build(category: &str, entries: &[(String, String)]) -> Result<()> {
+        let exe = std::env::current_exe().map_err(|_| windows::core::Error::empty())?;
+        let exe = HSTRING::from(exe.as_os_str());
+        let list: ICustomDestinationList =
+            CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)?;
+        let mut slots = 0u32;
+        // Entries the user removed must not come back (AppendCategory fails
+        // on them).
+        let removed: IObjectArray = list.BeginList(&mut slots)?;
+        let mut removed_uris = Vec::new();
+        for i in 0..removed.GetCount()? {
+            if let Some(uri) = removed
+                .GetAt::<IShellLinkW>(i)
+                .ok()
+                .and_then(|l| arguments(&l))
+            {
+                removed_uris.push(uri);
+            }
+        }
+
+        let links: IObjectCollection =
+            CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+        let kept = entries
+            .iter()
+            .filter(|(_, uri)| !removed_uris.contains(uri))
+            .take(slots as usize);
+        for (label, uri) in kept {
+            let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+            link.SetPath(&exe)?;
+            link.SetArguments(&HSTRING::from(uri.as_str()))?;
+            link.SetIconLocation(&exe, 0)?;
+            let store: IPropertyStore = link.cast()?;
+            store.SetValue(&PKEY_Title, &PROPVARIANT::from(label.as_str()))?;
+            store.Commit()?;
+            links.AddObject(&link)?;
+        }
+        let links: IObjectArray = links.cast()?;
+        list.AppendCategory(&HSTRING::from(category), &links)?;
+        list.CommitList()
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub use dock_menu::install as install_dock_menu;
+
+#[cfg(target_os = "macos")]
+mod dock_menu {
+    use super::{label, uri, MAX_ENTRIES};
+    use crate::unread::UnreadCounters;
+    use objc2::rc::Retained;
+    use objc2::runtime::{AnyClass, AnyObject, Imp, NSObject, Sel};
+    use objc2::{class, define_class, msg_send, sel, AnyThread};
+    use objc2_foundation::NSString;
+    use std::sync::{Arc, OnceLock};
+    use tauri::{Emitter, Manager};
+    use tracing::warn;
+
+    /// Event the deep-link plugin delivers opened URLs on; both the
+    /// `on_open_url` handler in `main.rs` and the frontend's `onOpenUrl`
+    /// listen to it.
+    const DEEP_LINK_EVENT: &str = "deep-link://new-url";
+
+    static APP: OnceLock<tauri::AppHandle> = OnceLock::new();
+    /// Menu items don't retain their target.
+    static TARGET: OnceLock<Retained<DockMenuTarget>> = OnceLock::new();
+
+    define_class!(
+        #[unsafe(super(NSObject))]
+        #[name = "FluuxDockMenuTarget"]
+        struct DockMenuTarget;
+
+        impl DockMenuTarget {
+            #[unsafe(method(openConversation:))]
+            fn open_conversation(&self, sender: &AnyObject) {
+                // SAFETY: the sender is one of our NSMenuItems, whose
+                // represented object is the URI string.
+                let uri: Option<Retained<NSString>> =
+                    unsafe { msg_send![sender, representedObject] };
+                if let (Some(app), Some(uri)) = (APP.get(), uri) {
+                    open(app, uri.to_string());
+                }
+            }
+        }
+    );
+
+    impl DockMenuTarget {
+        fn new() -> Retained<Self> {
+            // SAFETY: standard NSObject allocation/init for a defined subclass
+            // with no instance variables.
+            unsafe { msg_send![super(Self::alloc().set_ivars(())), init] }
+        }
+    }
+
+    fn open(app: &tauri::AppHandle, uri: String) {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.show();
+            crate::ensure_window_visible(&window);
+            let _ = window.set_focus();
+        }
+        let _ = app.emit(DEEP_LINK_EVENT, [uri]);
+    }
+
+    /// `-[NSApplicationDelegate applicationDockMenu:]`, added to the
+    /// delegate's class by [`install`].
+    extern "C-unwind" fn application_dock_menu(
+        _this: &AnyObject,
+        _cmd: Sel,
+        _sender: &AnyObject,
+    ) -> *mut AnyObject {
+        let (Some(app), Some(target)) = (APP.get(), TARGET.get()) else {
+            return std::ptr::null_mut();
+        };
+        let recent = app.state::<Arc<UnreadCounters>>().recent();
+        if recent.is_empty() {
+            return std::ptr::null_mut();
+        }
+        // SAFETY: called by AppKit on the main thread; every object is
+        // created here and the menu is handed back autoreleased.
+        unsafe {
+            let menu: Retained<AnyObject> = msg_send![class!(NSMenu), new];
+            for conversation in recent.iter().take(MAX_ENTRIES) {
+                let title = match conversation.unread {
+                    0 => label(conversation).to_string(),
+                    n => format!("{} ({n})", label(conversation)),
+                };
+                let item: Retained<AnyObject> = msg_send![class!(NSMenuItem), new];
+                let _: () = msg_send![&*item, setTitle: &*NSString::from_str(&title)];
+                let _: () = msg_send![&*item, setTarget: &**target];
+                let _: () = msg_send![&*item, setAction: sel!(openConversation:)];
+                let represented = NSString::from_str(&uri(conversation));
+                let _: () = msg_send![&*item, setRepresentedObject: &*represented];
+                let _: () = msg_send![&*menu, addItem: &*item];
+            }
+            Retained::autorelease_return(menu)
+        }
+    }
+
+    /// Serve the dock menu. Tauri's application delegate doesn't implement
+    /// `applicationDockMenu:`, so the method is added to its class. Called
+    /// from the Tauri `setup` hook, on the main thread.
+    pub fn install(app: tauri::AppHandle) {
+        let _ = APP.set(app);
+        let _ = TARGET.get_or_init(DockMenuTarget::new);
+        // SAFETY: the function matches the `@@:@` signature registered for
+        // the selector, and the delegate outlives the app.
+        unsafe {
+            let ns_app: Retained<AnyObject> = msg_send![class!(NSApplication), sharedApplication];
+            let delegate: Option<Retained<AnyObject>> = msg_send![&*ns_app, delegate];
+            let Some(delegate) = delegate else {
+                warn!("dock menu: no application delegate");
+                return;
+            };
+            let class = delegate.class() as *const AnyClass as *mut AnyClass;
+            let imp: Imp = std::mem::transmute(
+                application_dock_menu
+                    as extern "C-unwind" fn(&AnyObject, Sel, &AnyObject) -> *mut AnyObject,
+            );
+            let added = objc2::ffi::class_addMethod(
+                class,
+                sel!(applicationDockMenu:),
+                imp,
+                c"@@:@".as_ptr(),
+            );
+            if !added.as_bool() {
+                warn!("dock menu: the delegate already has applicationDockMenu:");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uris_escape_the_address_and_pick_the_action() {
+        let mut conversation = RecentConversation {
+            conversation_id: "d'artagnan@example.org".to_string(),
+            name: None,
+            groupchat: false,
+            unread: 0,
+        };
+        assert_eq!(uri(&conversation), "xmpp:d'artagnan@example.org?message");
+        conversation.conversation_id = "café #1@muc.example.org".to_string();
+        conversation.groupchat = true;
+        assert_eq!(
+            uri(&conversation),
+            "xmpp:caf%C3%A9%20%231@muc.example.org?join"
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! "Remind me about this" for individual messages.
+//!
+//! Reminders are persisted to `reminders.json` in the app data directory and
+//! fired by a native timer, so they survive restarts and do not depend on the
+//! WebView's (throttled) timers. A due reminder shows a system notification
+//! whose click target carries the message id, so activating it jumps to the
+//! message through the usual `notification-activated` routing. A
+//! `reminder-due` event is emitted as well, for the in-app reminder list.
+//!
+//! A reminder that came due while the app was closed fires at the next
+//! launch. Reminding again about the same message replaces the existing
+//! reminder, which is how snoozing works.
+
+use crate::json_file::JsonFile;
+use crate::notifications::{
+    self,
+    backend::{NativeNotification, NavTarget},
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::Emitter;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+const DUE_EVENT: &str = "reminder-due";
+
+/// Longest the reminder loop sleeps between checks. Wall-clock jumps (sleep,
+/// clock changes) are caught within this delay.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Notification preview length, in characters.
+const PREVIEW_CHARS: usize = 140;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reminder {
+    pub id: String,
+    pub message_id: String,
+    /// Where the message lives, in the notification click format.
+    pub target: NavTarget,
+    /// Conversation or room name shown as the notification title.
+    pub title: String,
+    /// Excerpt of the message shown as the notification body.
+    pub preview: String,
+    /// Unix milliseconds.
+    pub remind_at: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PersistedReminders {
+    reminders: Vec<Reminder>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Pending reminders, held in managed state as `Arc<Reminders>`.
+pub struct Reminders {
+    /// Sorted by `remind_at`.
+    reminders: Mutex<Vec<Reminder>>,
+    /// Wakes the reminder loop when a reminder is added.
+    changed: Notify,
+    /// Where reminders are persisted; `None` keeps them in memory.
+    file: Option<JsonFile>,
+}
+
+impl Reminders {
+    /// Load the persisted reminders. A missing or unreadable file starts
+    /// empty.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut reminders = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(
+                |bytes| match serde_json::from_slice::<PersistedReminders>(&bytes) {
+                    Ok(persisted) => Some(persisted.reminders),
+                    Err(e) => {
+                        warn!(error = %e, "reminders: discarding unreadable file");
+                        None
+                    }
+                },
+            )
+            .unwrap_or_default();
+        reminders.sort_by_key(|r| r.remind_at);
+        Self {
+            reminders: Mutex::new(reminders),
+            changed: Notify::new(),
+            file: path.map(JsonFile::new),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Reminder>> {
+        self.reminders.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Called with the reminders lock held; see [`crate::json_file`].
+    fn persist(&self, reminders: &[Reminder]) {
+        if let Some(file) = &self.file {
+            file.save(PersistedReminders {
+                reminders: reminders.to_vec(),
+            });
+        }
+    }
+
+    /// Apply `change` to the reminders and persist them.
+    fn update<T>(&self, change: impl FnOnce(&mut Vec<Reminder>) -> T) -> T {
+        let mut reminders = self.lock();
+        let result = change(&mut reminders);
+        reminders.sort_by_key(|r| r.remind_at);
+        self.persist(&reminders);
+        result
+    }
+
+    pub fn list(&self) -> Vec<Reminder> {
+        self.lock().clone()
+    }
+
+    /// Add `reminder`, replacing any pending reminder for the same message.
+    fn set(&self, reminder: Reminder) {
+        self.update(|reminders| {
+            reminders.retain(|r| {
+                r.message_id != reminder.message_id
+                    || r.target.account_id != reminder.target.account_id
+            });
+            reminders.push(reminder);
+        });
+        // The new reminder may be due before the loop's next wake-up.
+        self.changed.notify_one();
+    }
+
+    fn cancel(&self, id: &str) -> bool {
+        self.update(|reminders| {
+            let before = reminders.len();
+            reminders.retain(|r| r.id != id);
+            reminders.len() != before
+        })
+    }
+
+    /// Remove and return the reminders due at `now`, oldest first.
+    fn take_due(&self, now: u64) -> Vec<Reminder> {
+        if !matches!(self.lock().first(), Some(r) if r.remind_at <= now) {
+            return Vec::new();
+        }
+        self.update(|reminders| {
+            let due = reminders.iter().take_while(|r| r.remind_at <= now).count();
+            reminders.drain(..due).collect()
+        })
+    }
+
+    /// How long until the next reminder is due, capped at [`POLL_INTERVAL`].
+    fn next_wait(&self, now: u64) -> Duration {
+        self.lock()
+            .first()
+            .map(|r| Duration::from_millis(r.remind_at.saturating_sub(now)))
+            .unwrap_or(POLL_INTERVAL)
+            .min(POLL_INTERVAL)
+    }
+}
+
+fn preview(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((cut, _)) => format!("{}…", text[..cut].trim_end()),
+        None => text,
+    }
+}
+
+fn fire(app: &tauri::AppHandle, reminder: Reminder) {
+    info!(id = %reminder.id, "Reminder due");
+    let _ = app.emit(DUE_EVENT, &reminder);
+    let notification = NativeNotification {
+        title: reminder.title,
+        body: reminder.preview,
+        target: NavTarget {
+            message_id: Some(reminder.message_id),
+            ..reminder.target
+        },
+        avatar_path: None,
+    };
+    // Backends may block on the OS notification service.
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = notifications::show(notification) {
+            warn!(error = %e, "reminders: failed to show notification");
+        }
+    });
+}
+
+/// Run the reminder loop for the lifetime of the app. Called from `setup`.
+pub fn start(app: tauri::AppHandle, reminders: Arc<Reminders>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            for reminder in reminders.take_due(now_millis()) {
+                fire(&app, reminder);
+            }
+            let wait = reminders.next_wait(now_millis());
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = reminders.changed.notified() => {}
+            }
+        }
+    });
+}
+
+/// Remind the user about `message_id` at `at` (Unix milliseconds). An
+/// existing reminder for the same message is replaced, so this also snoozes.
+/// `nav_type`/`nav_target` locate the message as in `post_notification`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn remind_me(
+    reminders: tauri::State<'_, Arc<Reminders>>,
+    message_id: String,
+    at: u64,
+    nav_type: String,
+    nav_target: String,
+    account_id: Option<String>,
+    title: String,
+    body: String,
+) -> Result<Reminder, String> {
+    if message_id.trim().is_empty() {
+        return Err("Missing message id".to_string());
+    }
+    if nav_type != "conversation" && nav_type != "room" {
+        return Err(format!("Unsupported navigation type: {nav_type}"));
+    }
+    let reminder = Reminder {
+        id: uuid::Uuid::new_v4().to_string(),
+        message_id,
+        target: NavTarget {
+            nav_type,
+            nav_target,
+            message_id: None,
+            account_id,
+        },
+        title,
+        preview: preview(&body),
+        remind_at: at,
+    };
+    reminders.set(reminder.clone());
+    Ok(reminder)
+}
+
+#[tauri::command]
+pub fn list_reminders(reminders: tauri::State<'_, Arc<Reminders>>) -> Vec<Reminder> {
+    reminders.list()
+}
+
+/// Cancel a reminder. Returns `false` when it already fired or was
+/// cancelled.
+#[tauri::command]
+pub fn cancel_reminder(reminders: tauri::State<'_, Arc<Reminders>>, id: String) -> bool {
+    reminders.cancel(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reminder(id: &str, message_id: &str, remind_at: u64) -> Reminder {
+        Reminder {
+            id: id.to_string(),
+            message_id: message_id.to_string(),
+            target: NavTarget {
+                nav_type: "conversation".to_string(),
+                nav_target: "juliet@example.com".to_string(),
+                message_id: None,
+                account_id: None,
+            },
+            title: "Juliet".to_string(),
+            preview: "Wherefore art thou?".to_string(),
+            remind_at,
+        }
+    }
+
+    #[test]
+    fn due_reminders_are_taken_once_and_snoozing_replaces() {
+        let reminders = Reminders::load(None);
+        reminders.set(reminder("a", "m1", 3_000));
+        reminders.set(reminder("b", "m2", 1_000));
+        // Snoozing m2 replaces its pending reminder.
+        reminders.set(reminder("c", "m2", 2_000));
+        assert_eq!(reminders.list().len(), 2);
+
+        let due: Vec<String> = reminders
+            .take_due(2_500)
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(due, ["c"]);
+        assert!(reminders.take_due(2_500).is_empty());
+        assert_eq!(reminders.next_wait(2_999), Duration::from_millis(1));
+
+        assert!(reminders.cancel("a"));
+        assert!(!reminders.cancel("a"));
+        assert_eq!(reminders.next_wait(0), POLL_INTERVAL);
+    }
+
+    #[test]
+    fn preview_collapses_whitespace_and_truncates() {
+        assert_eq!(preview("  see\n\nyou   there "), "see you there");
+        let long = "é".repeat(PREVIEW_CHARS + 10);
+        let cut = preview(&long);
+        assert_eq!(cut.chars().count(), PREVIEW_CHARS + 1);
+        assert!(cut.ends_with('…'));
+    }
+}
@@ -0,0 +1,296 @@
+//! Meeting times from an iCalendar feed (RFC 5545).
+//!
+//! Only what decides "in a meeting right now" is read: `VEVENT`s with a
+//! start time and an end time or duration. All-day events don't count, nor
+//! do events marked free (`TRANSP:TRANSPARENT`) or cancelled. Daily and
+//! weekly rules (`INTERVAL`, `UNTIL`, `COUNT`, weekly `BYDAY`) are expanded;
+//! other rules count for their first occurrence only, and `EXDATE` is not
+//! applied.
+//!
+//! Times with a `TZID`, and floating ones, are read as local time, which is
+//! right for a calendar kept in the machine's own zone.
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, Utc, Weekday};
+use std::collections::HashMap;
+
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    /// Times are UTC rather than local.
+    utc: bool,
+    repeat: Option<Repeat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Repeat {
+    period: Duration,
+    until: Option<NaiveDateTime>,
+    count: Option<u32>,
+}
+
+impl Event {
+    fn covers(&self, now: NaiveDateTime) -> bool {
+        if now < self.start {
+            return false;
+        }
+        let Some(repeat) = &self.repeat else {
+            return now < self.end;
+        };
+        let period = repeat.period.num_seconds();
+        let index = (now - self.start).num_seconds() / period;
+        let start = self.start + Duration::seconds(index * period);
+        if repeat.count.is_some_and(|count| index >= i64::from(count))
+            || repeat.until.is_some_and(|until| start > until)
+        {
+            return false;
+        }
+        now < start + (self.end - self.start)
+    }
+}
+
+/// Join folded lines (a leading space or tab continues the previous one).
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in ics.lines() {
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+/// A date-time and whether it is UTC; `None` for dates (all-day events).
+fn time(value: &str) -> Option<(NaiveDateTime, bool)> {
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .map(|time| (time, utc))
+}
+
+/// `P1D`, `PT1H30M`, `P1W`…
+fn duration(value: &str) -> Option<Duration> {
+    let mut seconds = 0i64;
+    let mut number = String::new();
+    for c in value.strip_prefix('P')?.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            }
+            'T' => continue,
+            'W' => 7 * 86_400,
+            'D' => 86_400,
+            'H' => 3_600,
+            'M' => 60,
+            'S' => 1,
+            _ => return None,
+        };
+        seconds += number.parse::<i64>().ok()? * unit;
+        number.clear();
+    }
+    Some(Duration::seconds(seconds))
+}
+
+fn weekday(code: &str) -> Option<Weekday> {
+    // Monthly forms carry an ordinal ("1MO"); only the day matters here.
+    let day = code.get(code.len().checked_sub(2)?..)?;
+    Some(match day {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn expand(event: Event, rule: &str) -> Vec<Event> {
+    let parts: HashMap<&str, &str> = rule.split(';').filter_map(|p| p.split_once('=')).collect();
+    let days = match parts.get("FREQ") {
+        Some(&"DAILY") => 1,
+        Some(&"WEEKLY") => 7,
+        _ => return vec![event],
+    };
+    let interval: i64 = parts
+        .get("INTERVAL")
+        .and_then(|i| i.parse().ok())
+        .filter(|&i| i > 0)
+        .unwrap_or(1);
+    let until = parts.get("UNTIL").and_then(|until| {
+        time(until).map(|(t, _)| t).or_else(|| {
+            NaiveDate::parse_from_str(until, "%Y%m%d")
+                .ok()?
+                .and_hms_opt(23, 59, 59)
+        })
+    });
+    let count = parts.get("COUNT").and_then(|c| c.parse::<u32>().ok());
+    let weekdays: Vec<Weekday> = match parts.get("BYDAY") {
+        Some(days_list) if days == 7 => days_list.split(',').filter_map(weekday).collect(),
+        _ => Vec::new(),
+    };
+    let period = Duration::days(days * interval);
+    if weekdays.is_empty() {
+        return vec![Event {
+            repeat: Some(Repeat {
+                period,
+                until,
+                count,
+            }),
+            ..event
+        }];
+    }
+    // One weekly series per listed day; COUNT is shared out between them.
+    let count = count.map(|c| c.div_ceil(weekdays.len() as u32));
+    let first_day = event.start.weekday().num_days_from_monday();
+    weekdays
+        .into_iter()
+        .map(|day| {
+            let shift = Duration::days(i64::from((day.num_days_from_monday() + 7 - first_day) % 7));
+            Event {
+                start: event.start + shift,
+                end: event.end + shift,
+                utc: event.utc,
+                repeat: Some(Repeat {
+                    period,
+                    until,
+                    count,
+                }),
+            }
+        })
+        .collect()
+}
+
+fn event(properties: &[(String, String)]) -> Vec<Event> {
+    let get = |name: &str| {
+        properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    };
+    if get("TRANSP") == Some("TRANSPARENT") || get("STATUS") == Some("CANCELLED") {
+        return Vec::new();
+    }
+    let Some((start, utc)) = get("DTSTART").and_then(time) else {
+        return Vec::new();
+    };
+    let end = match get("DTEND").and_then(time) {
+        Some((end, _)) => end,
+        None => match get("DURATION").and_then(duration) {
+            Some(length) => start + length,
+            None => return Vec::new(),
+        },
+    };
+    if end <= start {
+        return Vec::new();
+    }
+    let event = Event {
+        start,
+        end,
+        utc,
+        repeat: None,
+    };
+    match get("RRULE") {
+        Some(rule) => expand(event, rule),
+        None => vec![event],
+    }
+}
+
+pub fn parse(ics: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    // Properties of the open VEVENT, and how deep we are in components
+    // nested inside it (alarms have properties of their own).
+    let mut current: Option<(Vec<(String, String)>, usize)> = None;
+    for line in unfold(ics) {
+        let Some((head, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = head.split(';').next().unwrap_or(head).to_ascii_uppercase();
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value == "VEVENT" => current = Some((Vec::new(), 0)),
+            ("BEGIN", Some((_, depth))) => *depth += 1,
+            ("END", Some((_, depth))) if *depth > 0 => *depth -= 1,
+            ("END", Some(_)) => {
+                if let Some((properties, _)) = current.take() {
+                    events.extend(event(&properties));
+                }
+            }
+            (_, Some((properties, 0))) => properties.push((name, value.trim().to_string())),
+            _ => {}
+        }
+    }
+    events
+}
+
+/// Whether any event is under way.
+pub fn busy_now(events: &[Event]) -> bool {
+    let utc = Utc::now().naive_utc();
+    let local = Local::now().naive_local();
+    events
+        .iter()
+        .any(|event| event.covers(if event.utc { utc } else { local }))
+}
+
+pub fn fetch(url: &str) -> Result<Vec<Event>, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))?;
+    let response = client
+        .get(url)
+        .send()
+        .map_err(|e| format!("Calendar request failed: {e}"))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Calendar request failed: {}",
+            response.status().as_u16()
+        ));
+    }
+    let body = response
+        .text()
+        .map_err(|e| format!("Calendar read failed: {e}"))?;
+    Ok(parse(&body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> NaiveDateTime {
+        time(value).unwrap().0
+    }
+
+    #[test]
+    fn reads_meetings_and_weekly_recurrences() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+            BEGIN:VEVENT\r\nDTSTART:20261015T090000Z\r\nDURATION:PT30M\r\n\
+            SUMMARY:Stand\r\n -up\r\nBEGIN:VALARM\r\nDURATION:PT5M\r\nEND:VALARM\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20261015\r\n\
+            DTEND;VALUE=DATE:20261016\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nDTSTART:20261016T120000Z\r\nDTEND:20261016T130000Z\r\n\
+            TRANSP:TRANSPARENT\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nDTSTART;TZID=Europe/Paris:20261012T140000\r\n\
+            DTEND;TZID=Europe/Paris:20261012T150000\r\nRRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4\r\n\
+            END:VEVENT\r\nEND:VCALENDAR\r\n";
+        let events = parse(ics);
+        assert_eq!(events.len(), 3);
+        assert!(events[0].utc && events[0].covers(at("20261015T092959")));
+        assert!(!events[0].covers(at("20261015T093000")));
+
+        let weekly = |now: &str| events[1..].iter().any(|e| e.covers(at(now)));
+        assert!(weekly("20261012T143000"));
+        assert!(weekly("20261014T140000"));
+        assert!(weekly("20261021T145959"));
+        assert!(!weekly("20261013T143000"));
+        // COUNT=4: Mon/Wed of two weeks.
+        assert!(!weekly("20261026T143000"));
+    }
+}
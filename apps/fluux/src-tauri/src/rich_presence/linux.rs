@@ -0,0 +1,62 @@
+//! Now playing from MPRIS media players on the session bus.
+
+use super::Tune;
+use std::collections::HashMap;
+use zbus::blocking::fdo::DBusProxy;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{OwnedValue, Value};
+
+const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// The track of the first player that reports `Playing`.
+pub fn now_playing() -> Option<Tune> {
+    let connection = Connection::session().ok()?;
+    let names = DBusProxy::new(&connection).ok()?.list_names().ok()?;
+    names
+        .iter()
+        .filter(|name| name.as_str().starts_with(PLAYER_PREFIX))
+        .find_map(|name| {
+            let player =
+                Proxy::new(&connection, name.as_str(), PLAYER_PATH, PLAYER_INTERFACE).ok()?;
+            let status: String = player.get_property("PlaybackStatus").ok()?;
+            if status != "Playing" {
+                return None;
+            }
+            let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").ok()?;
+            Some(tune(&metadata))
+        })
+}
+
+/// A string, or a list of them (`xesam:artist`) joined.
+fn text(value: Option<&OwnedValue>) -> Option<String> {
+    let text = match &**value? {
+        Value::Str(s) => s.to_string(),
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                Value::Str(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+fn tune(metadata: &HashMap<String, OwnedValue>) -> Tune {
+    // Microseconds, signed in the spec but unsigned in some players.
+    let length = match metadata.get("mpris:length").map(|v| &**v) {
+        Some(Value::I64(us)) => u32::try_from(us / 1_000_000).ok(),
+        Some(Value::U64(us)) => u32::try_from(us / 1_000_000).ok(),
+        _ => None,
+    };
+    Tune {
+        artist: text(metadata.get("xesam:artist")),
+        title: text(metadata.get("xesam:title")),
+        source: text(metadata.get("xesam:album")),
+        length,
+    }
+}
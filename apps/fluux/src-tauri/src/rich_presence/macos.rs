@@ -0,0 +1,79 @@
+//! Now playing from Music and Spotify, read through AppleScript.
+//!
+//! A player is only asked when it is already running, so polling never
+//! launches one. The first query shows the Automation permission prompt.
+
+use super::Tune;
+use objc2::rc::Retained;
+use objc2::runtime::AnyObject;
+use objc2::{class, msg_send};
+use objc2_foundation::NSString;
+use std::process::Command;
+
+/// Bundle id, and the script printing `artist⇥title⇥album⇥duration` while
+/// it plays. Music reports the duration in seconds, Spotify in
+/// milliseconds.
+const PLAYERS: [(&str, &str, u32); 2] = [
+    (
+        "com.apple.Music",
+        r#"tell application id "com.apple.Music"
+    if player state is playing then
+        set t to current track
+        set d to (duration of t) as integer
+        return (artist of t) & tab & (name of t) & tab & (album of t) & tab & d
+    end if
+end tell"#,
+        1,
+    ),
+    (
+        "com.spotify.client",
+        r#"tell application id "com.spotify.client"
+    if player state is playing then
+        set t to current track
+        return (artist of t) & tab & (name of t) & tab & (album of t) & tab & (duration of t)
+    end if
+end tell"#,
+        1000,
+    ),
+];
+
+fn running(bundle_id: &str) -> bool {
+    // SAFETY: a class method taking an NSString and returning an NSArray.
+    unsafe {
+        let bundle_id = NSString::from_str(bundle_id);
+        let apps: Retained<AnyObject> = msg_send![
+            class!(NSRunningApplication),
+            runningApplicationsWithBundleIdentifier: &*bundle_id
+        ];
+        let count: usize = msg_send![&*apps, count];
+        count > 0
+    }
+}
+
+fn parse(output: &str, divisor: u32) -> Option<Tune> {
+    let mut fields = output.trim_end_matches('\n').split('\t');
+    let mut next = || fields.next().map(str::to_string).filter(|f| !f.is_empty());
+    let tune = Tune {
+        artist: next(),
+        title: next(),
+        source: next(),
+        length: next()
+            .and_then(|d| d.parse::<u32>().ok())
+            .map(|d| d / divisor),
+    };
+    (tune.title.is_some() || tune.artist.is_some()).then_some(tune)
+}
+
+pub fn now_playing() -> Option<Tune> {
+    PLAYERS
+        .iter()
+        .filter(|(bundle_id, _, _)| running(bundle_id))
+        .find_map(|(_, script, divisor)| {
+            let output = Command::new("/usr/bin/osascript")
+                .args(["-e", script])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())?;
+            parse(&String::from_utf8_lossy(&output.stdout), *divisor)
+        })
+}
@@ -0,0 +1,315 @@
+//! Presence enrichment from the desktop: what the media player is playing
+//! and whether the calendar says we are in a meeting.
+//!
+//! Each source has its own consent toggle, off until the user turns it on:
+//!
+//! - Now playing (MPRIS players on Linux, Music and Spotify on macOS) is
+//!   published as User Tune (XEP-0118) on our PEP node while a track plays,
+//!   and cleared with an empty tune when playback stops or sharing is
+//!   turned off.
+//! - Calendar busy time, read from an iCalendar feed the user gives (the
+//!   private address most calendar services offer), switches presence to
+//!   Do Not Disturb for the length of a meeting. Entering and leaving emit
+//!   `fluux://auto-dnd` with `{active}`; on leaving, plain availability is
+//!   sent and the frontend can put back the status it had.
+//!
+//! Sources are polled while the session is up. Settings persist in
+//! `rich-presence.json`.
+
+mod calendar;
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+
+use crate::automation;
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::Element;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+const AUTO_DND_EVENT: &str = "fluux://auto-dnd";
+const PUBSUB_NS: &str = "http://jabber.org/protocol/pubsub";
+const TUNE_NS: &str = "http://jabber.org/protocol/tune";
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a fetched calendar is trusted before it is fetched again.
+const CALENDAR_REFRESH: Duration = Duration::from_secs(5 * 60);
+const MEETING_STATUS: &str = "In a meeting";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RichPresenceSettings {
+    /// Publish the playing track as User Tune.
+    pub share_now_playing: bool,
+    /// Go Do Not Disturb during meetings in `calendar_url`.
+    pub calendar_dnd: bool,
+    /// iCalendar feed (`https://` or `webcal://`).
+    pub calendar_url: Option<String>,
+}
+
+/// XEP-0118 fields; `length` in seconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tune {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    /// Album or other collection.
+    pub source: Option<String>,
+    pub length: Option<u32>,
+}
+
+#[derive(Clone, Serialize)]
+struct AutoDnd {
+    active: bool,
+}
+
+static SETTINGS: Mutex<Option<RichPresenceSettings>> = Mutex::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+/// Wakes the poller when a toggle changes.
+static CHANGED: Notify = Notify::const_new();
+
+pub fn settings() -> RichPresenceSettings {
+    SETTINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Load the persisted settings from `dir`. Called from the Tauri `setup`
+/// hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("rich-presence.json")) else {
+        return;
+    };
+    let loaded: RichPresenceSettings = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *SETTINGS.lock().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `SETTINGS` lock held; see [`crate::json_file`].
+fn persist(snapshot: RichPresenceSettings) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+/// `webcal://` is how calendar services hand out feeds; it is fetched over
+/// HTTPS.
+fn normalize_calendar_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    let url = match url.strip_prefix("webcal://") {
+        Some(rest) => format!("https://{rest}"),
+        None => url.to_string(),
+    };
+    let parsed = tauri::Url::parse(&url).map_err(|e| format!("Invalid calendar URL: {e}"))?;
+    if !matches!(parsed.scheme(), "https" | "http") {
+        return Err("The calendar URL must be https:// or webcal://".to_string());
+    }
+    Ok(url)
+}
+
+/// The playing track, from whichever player the platform has.
+fn now_playing() -> Option<Tune> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::now_playing()
+    }
+    #[cfg(target_os = "macos")]
+    {
+        macos::now_playing()
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        None
+    }
+}
+
+/// Publish `tune` on our User Tune node; `None` publishes the empty tune
+/// that means "stopped".
+fn tune_request(tune: Option<&Tune>) -> Element {
+    let mut payload = Element::new("tune").with_attr("xmlns", TUNE_NS);
+    if let Some(tune) = tune {
+        let fields = [
+            ("artist", tune.artist.clone()),
+            ("length", tune.length.map(|l| l.to_string())),
+            ("source", tune.source.clone()),
+            ("title", tune.title.clone()),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value.filter(|v| !v.is_empty()) {
+                payload = payload.with_child(Element::new(name).with_text(&value));
+            }
+        }
+    }
+    Element::new("iq").with_attr("type", "set").with_child(
+        Element::new("pubsub")
+            .with_attr("xmlns", PUBSUB_NS)
+            .with_child(
+                Element::new("publish")
+                    .with_attr("node", TUNE_NS)
+                    .with_child(
+                        Element::new("item")
+                            .with_attr("id", "current")
+                            .with_child(payload),
+                    ),
+            ),
+    )
+}
+
+/// What the poller last did, so it only acts on changes.
+#[derive(Default)]
+struct Poller {
+    /// Last tune published; `None` when nothing is (or an empty tune was).
+    published: Option<Tune>,
+    /// Whether Do Not Disturb is on because of the calendar.
+    dnd: bool,
+    /// Fetched feed and when, keyed by its URL.
+    calendar: Option<(String, Instant, Vec<calendar::Event>)>,
+}
+
+impl Poller {
+    async fn poll_tune(&mut self, settings: &RichPresenceSettings) {
+        let tune = if settings.share_now_playing {
+            tauri::async_runtime::spawn_blocking(now_playing)
+                .await
+                .ok()
+                .flatten()
+        } else {
+            None
+        };
+        if tune == self.published {
+            return;
+        }
+        match session::request(tune_request(tune.as_ref())).await {
+            Ok(_) => self.published = tune,
+            Err(e) => warn!(error = %e, "rich presence: tune not published"),
+        }
+    }
+
+    async fn in_meeting(&mut self, settings: &RichPresenceSettings) -> bool {
+        let Some(url) = settings
+            .calendar_url
+            .clone()
+            .filter(|_| settings.calendar_dnd)
+        else {
+            return false;
+        };
+        let stale = match &self.calendar {
+            Some((fetched_url, at, _)) => *fetched_url != url || at.elapsed() > CALENDAR_REFRESH,
+            None => true,
+        };
+        if stale {
+            let fetch_url = url.clone();
+            match tauri::async_runtime::spawn_blocking(move || calendar::fetch(&fetch_url)).await {
+                Ok(Ok(events)) => self.calendar = Some((url, Instant::now(), events)),
+                Ok(Err(e)) => warn!(error = %e, "rich presence: calendar not read"),
+                Err(e) => warn!(error = %e, "rich presence: calendar task failed"),
+            }
+        }
+        self.calendar
+            .as_ref()
+            .is_some_and(|(_, _, events)| calendar::busy_now(events))
+    }
+
+    async fn poll_calendar(&mut self, app: &tauri::AppHandle, settings: &RichPresenceSettings) {
+        let busy = self.in_meeting(settings).await;
+        if busy == self.dnd {
+            return;
+        }
+        let presence = if busy {
+            automation::presence(Some("dnd"), Some(MEETING_STATUS))
+        } else {
+            automation::presence(None, None)
+        };
+        match presence.and_then(automation::send) {
+            Ok(()) => {
+                info!(active = busy, "rich presence: meeting do-not-disturb");
+                self.dnd = busy;
+                let _ = app.emit(AUTO_DND_EVENT, AutoDnd { active: busy });
+            }
+            Err(e) => warn!(error = %e, "rich presence: presence not sent"),
+        }
+    }
+}
+
+/// Poll the enabled sources for the life of the app.
+pub fn start(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut poller = Poller::default();
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = CHANGED.notified() => {}
+            }
+            if !session::is_ready() {
+                // The client sends its own presence when it reconnects.
+                poller.dnd = false;
+                continue;
+            }
+            let settings = settings();
+            poller.poll_tune(&settings).await;
+            poller.poll_calendar(&app, &settings).await;
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_rich_presence_settings() -> RichPresenceSettings {
+    settings()
+}
+
+/// Replace the settings; turning a source on is the user's consent to read
+/// it.
+#[tauri::command]
+pub fn set_rich_presence_settings(
+    mut settings: RichPresenceSettings,
+) -> Result<RichPresenceSettings, String> {
+    settings.calendar_url = match settings.calendar_url.as_deref().map(str::trim) {
+        Some("") | None => None,
+        Some(url) => Some(normalize_calendar_url(url)?),
+    };
+    if settings.calendar_dnd && settings.calendar_url.is_none() {
+        return Err("A calendar URL is needed for meeting do-not-disturb".to_string());
+    }
+    let mut stored = SETTINGS.lock().unwrap_or_else(|e| e.into_inner());
+    *stored = Some(settings.clone());
+    persist(settings.clone());
+    drop(stored);
+    CHANGED.notify_one();
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tune_publishes_known_fields_and_stops_empty() {
+        let tune = Tune {
+            artist: Some("Yes".to_string()),
+            title: Some("Heart of the Sunrise".to_string()),
+            source: None,
+            length: Some(686),
+        };
+        let xml = tune_request(Some(&tune)).to_xml();
+        assert!(xml.contains("<artist>Yes</artist><length>686</length><title>"));
+        assert!(!xml.contains("<source"));
+        assert!(tune_request(None)
+            .to_xml()
+            .contains("<tune xmlns='http://jabber.org/protocol/tune'/>"));
+        assert_eq!(
+            normalize_calendar_url("webcal://cal.example.org/a.ics").unwrap(),
+            "https://cal.example.org/a.ics"
+        );
+        assert!(normalize_calendar_url("file:///etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,459 @@
+//! Group chat metadata cache: room names, descriptions, subjects, disco
+//! features and avatars, kept on disk so the room list renders at once and
+//! an account with a hundred bookmarked rooms doesn't query each of them on
+//! every start.
+//!
+//! Entries are refreshed from disco#info (`muc#roominfo`) only once older
+//! than the caller's maximum age (see [`refresh_room_metadata`]), or when
+//! the room announces a configuration change (status 104). Subjects are
+//! taken from the relayed subject messages. Avatars (vcard-temp, XEP-0153
+//! hash in the room's presence or in `muc#roominfo_avatarhash`) are fetched
+//! only when the hash changes, and stored in the `avatars` cache category.
+//! Every change is published as `room-metadata-changed`.
+
+use crate::dataforms::DataForm;
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, resource, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
+use tracing::{debug, warn};
+
+const DISCO_INFO_NS: &str = "http://jabber.org/protocol/disco#info";
+const MUC_USER_NS: &str = "http://jabber.org/protocol/muc#user";
+const VCARD_NS: &str = "vcard-temp";
+const VCARD_UPDATE_NS: &str = "vcard-temp:x:update";
+const ROOMINFO_FORM: &str = "http://jabber.org/protocol/muc#roominfo";
+const CHANGED_EVENT: &str = "room-metadata-changed";
+/// Entries older than this are refreshed when the caller gives no age.
+const DEFAULT_MAX_AGE_SECS: u64 = 24 * 3600;
+/// disco#info queries in flight at once during a refresh.
+const REFRESH_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RoomMetadata {
+    pub jid: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub subject: Option<String>,
+    pub occupants: Option<u32>,
+    /// disco#info features (`muc_membersonly`, `muc_passwordprotected`…).
+    pub features: Vec<String>,
+    /// Hex SHA-1 of the room avatar.
+    pub avatar_hash: Option<String>,
+    /// Cached avatar file.
+    pub avatar_path: Option<String>,
+    /// Unix seconds of the last disco#info refresh; 0 if never.
+    pub refreshed_at: u64,
+}
+
+impl RoomMetadata {
+    /// Apply a disco#info answer. Returns the avatar hash it advertises.
+    fn apply_disco(&mut self, query: &Element, now: u64) -> Option<String> {
+        self.name = query
+            .elements()
+            .filter(|e| e.local_name() == "identity")
+            .find(|e| e.attr("category") == Some("conference"))
+            .and_then(|e| e.attr("name"))
+            .filter(|name| !name.is_empty())
+            .map(str::to_string);
+        self.features = query
+            .elements()
+            .filter(|e| e.local_name() == "feature")
+            .filter_map(|e| e.attr("var"))
+            .filter(|var| var.starts_with("muc_"))
+            .map(str::to_string)
+            .collect();
+        self.refreshed_at = now;
+        let form = query
+            .elements()
+            .filter_map(DataForm::from_element)
+            .find(|form| {
+                form.fields.iter().any(|f| {
+                    f.var.as_deref() == Some("FORM_TYPE")
+                        && f.values.iter().any(|v| v == ROOMINFO_FORM)
+                })
+            });
+        let value = |var: &str| {
+            form.as_ref()?
+                .fields
+                .iter()
+                .find(|f| f.var.as_deref() == Some(var))
+                .and_then(|f| f.values.first())
+                .filter(|v| !v.is_empty())
+                .cloned()
+        };
+        self.description = value("muc#roominfo_description");
+        if let Some(subject) = value("muc#roominfo_subject") {
+            self.subject = Some(subject);
+        }
+        self.occupants = value("muc#roominfo_occupants").and_then(|n| n.parse().ok());
+        value("muc#roominfo_avatarhash")
+    }
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Process-wide room metadata. Registered with the bridge tap and held in
+/// Tauri managed state as `Arc<RoomCache>`.
+pub struct RoomCache {
+    /// By lowercased room JID.
+    rooms: Mutex<HashMap<String, RoomMetadata>>,
+    /// Where the cache is persisted; `None` keeps it in memory.
+    file: Option<JsonFile>,
+}
+
+impl RoomCache {
+    /// Load the persisted cache. A missing or unreadable file starts empty.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let rooms: Vec<RoomMetadata> = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        RoomCache {
+            rooms: Mutex::new(
+                rooms
+                    .into_iter()
+                    .map(|room| (room.jid.to_lowercase(), room))
+                    .collect(),
+            ),
+            file: path.map(JsonFile::new),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, RoomMetadata>> {
+        self.rooms.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, room: &str) -> Option<RoomMetadata> {
+        self.lock().get(&room.to_lowercase()).cloned()
+    }
+
+    /// Write the cache in the background; observers must not block on disk.
+    /// Called with the rooms lock held; see [`crate::json_file`].
+    fn persist(&self, rooms: &HashMap<String, RoomMetadata>) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut snapshot: Vec<RoomMetadata> = rooms.values().cloned().collect();
+        snapshot.sort_by(|a, b| a.jid.cmp(&b.jid));
+        file.save(snapshot);
+    }
+
+    /// Apply `change` to the entry of `room`; returns the entry when it
+    /// changed.
+    fn update(&self, room: &str, change: impl FnOnce(&mut RoomMetadata)) -> Option<RoomMetadata> {
+        let room = bare_jid(room);
+        let mut rooms = self.lock();
+        let entry = rooms
+            .entry(room.to_lowercase())
+            .or_insert_with(|| RoomMetadata {
+                jid: room.to_string(),
+                ..Default::default()
+            });
+        let before = entry.clone();
+        change(entry);
+        if *entry == before {
+            return None;
+        }
+        let changed = entry.clone();
+        self.persist(&rooms);
+        Some(changed)
+    }
+
+    /// What a relayed stanza asks for, after applying what it carries.
+    fn ingest(&self, stanza: &Element) -> (Option<RoomMetadata>, Option<Follow>) {
+        let Some(from) = stanza.attr("from") else {
+            return (None, None);
+        };
+        let room = bare_jid(from).to_string();
+        match stanza.local_name() {
+            "message" if stanza.attr("type") == Some("groupchat") => {
+                let config_changed = stanza.child("x", Some(MUC_USER_NS)).is_some_and(|x| {
+                    x.elements()
+                        .any(|s| s.local_name() == "status" && s.attr("code") == Some("104"))
+                });
+                if config_changed {
+                    return (None, Some(Follow::Refresh(room)));
+                }
+                // A subject change carries a <subject/> and no body.
+                let Some(subject) = stanza
+                    .child("subject", None)
+                    .filter(|_| stanza.child("body", None).is_none())
+                else {
+                    return (None, None);
+                };
+                let subject = Some(subject.text()).filter(|s| !s.trim().is_empty());
+                (self.update(&room, |entry| entry.subject = subject), None)
+            }
+            // The room's own presence advertises its avatar hash.
+            "presence" if resource(from).is_none() && stanza.attr("type").is_none() => {
+                let Some(photo) = stanza
+                    .child("x", Some(VCARD_UPDATE_NS))
+                    .and_then(|x| x.child("photo", None))
+                else {
+                    return (None, None);
+                };
+                let hash = photo.text().trim().to_ascii_lowercase();
+                self.avatar_follow(&room, &hash)
+            }
+            _ => (None, None),
+        }
+    }
+
+    /// React to an advertised avatar hash: clear the avatar when it is
+    /// empty, fetch it when it is new.
+    fn avatar_follow(&self, room: &str, hash: &str) -> (Option<RoomMetadata>, Option<Follow>) {
+        if hash.is_empty() {
+            let changed = self.update(room, |entry| {
+                entry.avatar_hash = None;
+                entry.avatar_path = None;
+            });
+            return (changed, None);
+        }
+        let current = self.get(room);
+        let known = current.as_ref().is_some_and(|entry| {
+            entry.avatar_hash.as_deref() == Some(hash)
+                && entry
+                    .avatar_path
+                    .as_deref()
+                    .is_some_and(|p| std::path::Path::new(p).exists())
+        });
+        if known {
+            (None, None)
+        } else {
+            (
+                None,
+                Some(Follow::Avatar(room.to_string(), hash.to_string())),
+            )
+        }
+    }
+}
+
+/// Work a stanza calls for that needs the network.
+#[derive(Debug, PartialEq, Eq)]
+enum Follow {
+    Refresh(String),
+    Avatar(String, String),
+}
+
+fn publish(app: &tauri::AppHandle, changed: Option<RoomMetadata>) {
+    if let Some(changed) = changed {
+        let _ = app.emit(CHANGED_EVENT, &changed);
+    }
+}
+
+fn run(app: &tauri::AppHandle, follow: Follow) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let result = match &follow {
+            Follow::Refresh(room) => refresh(&app, room).await,
+            Follow::Avatar(room, hash) => fetch_avatar(&app, room, hash).await,
+        };
+        if let Err(e) = result {
+            debug!(error = %e, follow = ?follow, "room cache: update failed");
+        }
+    });
+}
+
+/// Re-read `room`'s disco#info, then its avatar if the hash changed.
+async fn refresh(app: &tauri::AppHandle, room: &str) -> Result<(), String> {
+    let iq = Element::new("iq")
+        .with_attr("type", "get")
+        .with_attr("to", room)
+        .with_child(Element::new("query").with_attr("xmlns", DISCO_INFO_NS));
+    let response = session::request(iq).await?;
+    let query = response
+        .child("query", Some(DISCO_INFO_NS))
+        .ok_or("Malformed disco#info response")?;
+    let cache = app.state::<Arc<RoomCache>>();
+    let mut avatar_hash = None;
+    let changed = cache.update(room, |entry| {
+        avatar_hash = entry.apply_disco(query, now_secs());
+    });
+    publish(app, changed);
+    if let Some(hash) = avatar_hash {
+        let (changed, follow) = cache.avatar_follow(room, &hash.to_ascii_lowercase());
+        publish(app, changed);
+        if let Some(Follow::Avatar(room, hash)) = follow {
+            fetch_avatar(app, &room, &hash).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetch the room's vCard photo and cache it under its hash.
+async fn fetch_avatar(app: &tauri::AppHandle, room: &str, hash: &str) -> Result<(), String> {
+    let iq = Element::new("iq")
+        .with_attr("type", "get")
+        .with_attr("to", room)
+        .with_child(Element::new("vCard").with_attr("xmlns", VCARD_NS));
+    let response = session::request(iq).await?;
+    let photo = response
+        .child("vCard", Some(VCARD_NS))
+        .and_then(|vcard| vcard.child("PHOTO", None))
+        .ok_or("Room vCard has no photo")?;
+    let bytes = photo
+        .child("BINVAL", None)
+        .map(|b| b.text().split_whitespace().collect::<String>())
+        .ok_or("Room photo has no data")
+        .and_then(|data| BASE64.decode(data).map_err(|_| "Malformed room photo"))?;
+    if crate::profile::sha1_hex(&bytes) != hash {
+        return Err("Room photo does not match its hash".to_string());
+    }
+    let extension = match photo.child("TYPE", None).map(|t| t.text()).as_deref() {
+        Some("image/jpeg") => "jpg",
+        Some("image/gif") => "gif",
+        Some("image/webp") => "webp",
+        _ => "png",
+    };
+    let dir = crate::cache::dir(app, "avatars")?;
+    let path = dir.join(format!("room-{hash}.{extension}"));
+    let (write_path, blocking_app) = (path.clone(), app.clone());
+    tauri::async_runtime::spawn_blocking(move || {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(write_path, bytes)?;
+        crate::cache::enforce(&blocking_app, "avatars");
+        Ok::<_, std::io::Error>(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("Failed to cache room avatar: {e}"))?;
+    let cache = app.state::<Arc<RoomCache>>();
+    let changed = cache.update(room, |entry| {
+        entry.avatar_hash = Some(hash.to_string());
+        entry.avatar_path = Some(path.to_string_lossy().into_owned());
+    });
+    publish(app, changed);
+    Ok(())
+}
+
+impl StanzaObserver for RoomCache {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction != Direction::Inbound {
+            return Verdict::Forward;
+        }
+        let (changed, follow) = self.ingest(stanza);
+        if let Some(app) = ctx.app {
+            publish(app, changed);
+            if let Some(follow) = follow {
+                run(app, follow);
+            }
+        }
+        Verdict::Forward
+    }
+}
+
+/// Cached metadata of every known room, for an immediate render.
+#[tauri::command]
+pub fn get_room_metadata(cache: tauri::State<'_, Arc<RoomCache>>) -> Vec<RoomMetadata> {
+    let mut rooms: Vec<RoomMetadata> = cache.lock().values().cloned().collect();
+    rooms.sort_by(|a, b| a.jid.cmp(&b.jid));
+    rooms
+}
+
+/// Refresh the rooms whose metadata is older than `max_age_secs` (a day by
+/// default; 0 refreshes all). Changes arrive as `room-metadata-changed`.
+/// Returns how many rooms were queried.
+#[tauri::command]
+pub async fn refresh_room_metadata(
+    app: tauri::AppHandle,
+    cache: tauri::State<'_, Arc<RoomCache>>,
+    rooms: Vec<String>,
+    max_age_secs: Option<u64>,
+) -> Result<usize, String> {
+    let max_age = max_age_secs.unwrap_or(DEFAULT_MAX_AGE_SECS);
+    let now = now_secs();
+    let stale: Vec<String> = rooms
+        .iter()
+        .map(|room| bare_jid(room).to_string())
+        .filter(|room| {
+            cache
+                .get(room)
+                .is_none_or(|entry| now.saturating_sub(entry.refreshed_at) >= max_age)
+        })
+        .collect();
+    let count = stale.len();
+    futures_util::stream::iter(stale)
+        .for_each_concurrent(REFRESH_CONCURRENCY, |room| {
+            let app = app.clone();
+            async move {
+                if let Err(e) = refresh(&app, &room).await {
+                    debug!(room = %room, error = %e, "room cache: refresh failed");
+                }
+            }
+        })
+        .await;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disco_and_relayed_stanzas_update_the_entry() {
+        let query = Element::parse(
+            "<query xmlns='http://jabber.org/protocol/disco#info'>\
+             <identity category='conference' type='text' name='Ops'/>\
+             <feature var='http://jabber.org/protocol/muc'/>\
+             <feature var='muc_membersonly'/><feature var='muc_persistent'/>\
+             <x xmlns='jabber:x:data' type='result'>\
+             <field var='FORM_TYPE' type='hidden'>\
+             <value>http://jabber.org/protocol/muc#roominfo</value></field>\
+             <field var='muc#roominfo_description'><value>On call</value></field>\
+             <field var='muc#roominfo_occupants'><value>12</value></field>\
+             <field var='muc#roominfo_avatarhash'><value>ABC123</value></field>\
+             </x></query>",
+        )
+        .unwrap();
+        let mut entry = RoomMetadata::default();
+        assert_eq!(entry.apply_disco(&query, 42), Some("ABC123".to_string()));
+        assert_eq!(entry.name.as_deref(), Some("Ops"));
+        assert_eq!(entry.description.as_deref(), Some("On call"));
+        assert_eq!(entry.occupants, Some(12));
+        assert_eq!(entry.features, ["muc_membersonly", "muc_persistent"]);
+        assert_eq!(entry.refreshed_at, 42);
+
+        let cache = RoomCache::load(None);
+        let subject = Element::parse(
+            "<message from='ops@conf.example.com/bob' type='groupchat'>\
+             <subject>Release day</subject></message>",
+        )
+        .unwrap();
+        let (changed, follow) = cache.ingest(&subject);
+        assert_eq!(changed.unwrap().subject.as_deref(), Some("Release day"));
+        assert_eq!(follow, None);
+        assert_eq!(cache.ingest(&subject), (None, None));
+
+        let configured = Element::parse(
+            "<message from='ops@conf.example.com' type='groupchat'>\
+             <x xmlns='http://jabber.org/protocol/muc#user'><status code='104'/></x></message>",
+        )
+        .unwrap();
+        let refresh = Follow::Refresh("ops@conf.example.com".to_string());
+        assert_eq!(cache.ingest(&configured), (None, Some(refresh)));
+
+        let avatar = Element::parse(
+            "<presence from='ops@conf.example.com'>\
+             <x xmlns='vcard-temp:x:update'><photo>ABC123</photo></x></presence>",
+        )
+        .unwrap();
+        let fetch = Follow::Avatar("ops@conf.example.com".to_string(), "abc123".to_string());
+        assert_eq!(cache.ingest(&avatar), (None, Some(fetch)));
+    }
+}
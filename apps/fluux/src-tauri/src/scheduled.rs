@@ -0,0 +1,362 @@
+//! Messages scheduled to be sent later.
+//!
+//! Scheduled messages are persisted to `scheduled-messages.json` in the app
+//! data directory and sent natively through the bridge session when due, so
+//! they go out even while the WebView is throttled in the background. A
+//! message whose time passed while offline (or while the app was closed) is
+//! sent as soon as the session is bound again.
+//!
+//! The client never sees stanzas injected by the app, so each send is
+//! reported with a `scheduled-message-sent` event carrying what the chat
+//! view needs to show it.
+
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::stanza::Element;
+use crate::xmpp_proxy::{session, tap};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::Emitter;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+const SENT_EVENT: &str = "scheduled-message-sent";
+const FAILED_EVENT: &str = "scheduled-message-failed";
+
+/// Longest the scheduler sleeps between checks. Bounds how long an overdue
+/// message waits after the session comes back.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Refuse to schedule absurdly far ahead (a year), which is almost always a
+/// unit mistake (seconds vs milliseconds) on the caller's side.
+const MAX_AHEAD_MS: u64 = 366 * 24 * 3600 * 1000;
+
+/// Send attempts before a message is dropped and reported as failed.
+const MAX_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessage {
+    /// Also the stanza `id` and XEP-0359 origin id of the sent message.
+    pub id: String,
+    pub jid: String,
+    pub body: String,
+    /// `chat` or `groupchat`.
+    pub message_type: String,
+    /// Unix milliseconds.
+    pub send_at: u64,
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SentEvent<'a> {
+    #[serde(flatten)]
+    message: &'a ScheduledMessage,
+    /// Unix milliseconds; later than `send_at` when it waited for a session.
+    sent_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FailedEvent<'a> {
+    #[serde(flatten)]
+    message: &'a ScheduledMessage,
+    error: &'a str,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PersistedSchedule {
+    messages: Vec<ScheduledMessage>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Pending scheduled messages, held in managed state as `Arc<Scheduler>`.
+pub struct Scheduler {
+    /// Sorted by `send_at`.
+    messages: Mutex<Vec<ScheduledMessage>>,
+    /// Wakes the scheduler loop when a message is added.
+    changed: Notify,
+    /// Where the schedule is persisted; `None` keeps it in memory.
+    file: Option<JsonFile>,
+}
+
+impl Scheduler {
+    /// Load the persisted schedule. A missing or unreadable file starts
+    /// empty.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut messages = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(
+                |bytes| match serde_json::from_slice::<PersistedSchedule>(&bytes) {
+                    Ok(schedule) => Some(schedule.messages),
+                    Err(e) => {
+                        warn!(error = %e, "scheduled messages: discarding unreadable file");
+                        None
+                    }
+                },
+            )
+            .unwrap_or_default();
+        messages.sort_by_key(|m| m.send_at);
+        Self {
+            messages: Mutex::new(messages),
+            changed: Notify::new(),
+            file: path.map(JsonFile::new),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<ScheduledMessage>> {
+        self.messages.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Called with the schedule lock held; see [`crate::json_file`].
+    fn persist(&self, messages: &[ScheduledMessage]) {
+        if let Some(file) = &self.file {
+            file.save(PersistedSchedule {
+                messages: messages.to_vec(),
+            });
+        }
+    }
+
+    /// Apply `change` to the schedule and persist it.
+    fn update<T>(&self, change: impl FnOnce(&mut Vec<ScheduledMessage>) -> T) -> T {
+        let mut messages = self.lock();
+        let result = change(&mut messages);
+        messages.sort_by_key(|m| m.send_at);
+        self.persist(&messages);
+        result
+    }
+
+    pub fn list(&self) -> Vec<ScheduledMessage> {
+        self.lock().clone()
+    }
+
+    fn add(&self, message: ScheduledMessage) {
+        self.update(|messages| messages.push(message));
+        // The new message may be due before the loop's next wake-up.
+        self.changed.notify_one();
+    }
+
+    fn cancel(&self, id: &str) -> bool {
+        self.update(|messages| {
+            let before = messages.len();
+            messages.retain(|m| m.id != id);
+            messages.len() != before
+        })
+    }
+
+    /// Messages due at `now`, oldest first.
+    fn due(&self, now: u64) -> Vec<ScheduledMessage> {
+        self.lock()
+            .iter()
+            .take_while(|m| m.send_at <= now)
+            .cloned()
+            .collect()
+    }
+
+    /// How long until the next message is due, capped at [`POLL_INTERVAL`].
+    fn next_wait(&self, now: u64) -> Duration {
+        self.lock()
+            .first()
+            .map(|m| Duration::from_millis(m.send_at.saturating_sub(now)))
+            .unwrap_or(POLL_INTERVAL)
+            .min(POLL_INTERVAL)
+    }
+}
+
+fn build_stanza(message: &ScheduledMessage) -> Element {
+    Element::new("message")
+        .with_attr("to", &message.jid)
+        .with_attr("type", &message.message_type)
+        .with_attr("id", &message.id)
+        .with_child(Element::new("body").with_text(&message.body))
+        .with_child(
+            Element::new("origin-id")
+                .with_attr("xmlns", "urn:xmpp:sid:0")
+                .with_attr("id", &message.id),
+        )
+}
+
+/// Send what is due. Without a bound session nothing is attempted; the
+/// messages stay queued for the next pass.
+fn send_due(app: &tauri::AppHandle, scheduler: &Scheduler) {
+    if !session::is_ready() {
+        return;
+    }
+    for message in scheduler.due(now_millis()) {
+        let stanza = build_stanza(&message);
+        match session::send(stanza.clone()) {
+            Ok(()) => {
+                info!(id = %message.id, "Scheduled message sent");
+                scheduler.update(|messages| messages.retain(|m| m.id != message.id));
+                tap::observe_native(Some(app), &stanza);
+                let _ = app.emit(
+                    SENT_EVENT,
+                    SentEvent {
+                        message: &message,
+                        sent_at: now_millis(),
+                    },
+                );
+            }
+            Err(e) => {
+                let attempts = message.attempts + 1;
+                if attempts >= MAX_ATTEMPTS {
+                    warn!(id = %message.id, error = %e, "Scheduled message dropped");
+                    scheduler.update(|messages| messages.retain(|m| m.id != message.id));
+                    let _ = app.emit(
+                        FAILED_EVENT,
+                        FailedEvent {
+                            message: &message,
+                            error: &e,
+                        },
+                    );
+                } else {
+                    warn!(
+                        id = %message.id,
+                        attempts,
+                        error = %e,
+                        "Scheduled message not sent, will retry"
+                    );
+                    scheduler.update(|messages| {
+                        if let Some(m) = messages.iter_mut().find(|m| m.id == message.id) {
+                            m.attempts = attempts;
+                        }
+                    });
+                }
+                // The session went away mid-pass; the rest waits for it.
+                break;
+            }
+        }
+    }
+}
+
+/// Run the scheduler for the lifetime of the app. Called from `setup`.
+pub fn start(app: tauri::AppHandle, scheduler: Arc<Scheduler>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            send_due(&app, &scheduler);
+            let wait = scheduler.next_wait(now_millis());
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = scheduler.changed.notified() => {}
+            }
+        }
+    });
+}
+
+fn validate(jid: &str, body: &str, message_type: &str, send_at: u64) -> Result<(), String> {
+    if jid.trim().is_empty() || jid.trim().contains(char::is_whitespace) {
+        return Err(format!("Invalid recipient: {jid}"));
+    }
+    if body.trim().is_empty() {
+        return Err("Message is empty".to_string());
+    }
+    if message_type != "chat" && message_type != "groupchat" {
+        return Err(format!("Unsupported message type: {message_type}"));
+    }
+    if send_at > now_millis().saturating_add(MAX_AHEAD_MS) {
+        return Err("Cannot schedule more than a year ahead".to_string());
+    }
+    Ok(())
+}
+
+/// Schedule `body` for `jid` at `send_at` (Unix milliseconds). A time in
+/// the past sends as soon as possible.
+#[tauri::command]
+pub fn schedule_message(
+    scheduler: tauri::State<'_, Arc<Scheduler>>,
+    jid: String,
+    body: String,
+    send_at: u64,
+    message_type: Option<String>,
+) -> Result<ScheduledMessage, String> {
+    let message_type = message_type.unwrap_or_else(|| "chat".to_string());
+    validate(&jid, &body, &message_type, send_at)?;
+    let message = ScheduledMessage {
+        id: uuid::Uuid::new_v4().to_string(),
+        jid: jid.trim().to_string(),
+        body,
+        message_type,
+        send_at,
+        attempts: 0,
+    };
+    scheduler.add(message.clone());
+    Ok(message)
+}
+
+#[tauri::command]
+pub fn list_scheduled_messages(
+    scheduler: tauri::State<'_, Arc<Scheduler>>,
+) -> Vec<ScheduledMessage> {
+    scheduler.list()
+}
+
+/// Cancel a scheduled message. Returns `false` when it was already sent or
+/// cancelled.
+#[tauri::command]
+pub fn cancel_scheduled_message(scheduler: tauri::State<'_, Arc<Scheduler>>, id: String) -> bool {
+    scheduler.cancel(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, send_at: u64) -> ScheduledMessage {
+        ScheduledMessage {
+            id: id.to_string(),
+            jid: "juliet@example.com".to_string(),
+            body: "Wherefore art thou?".to_string(),
+            message_type: "chat".to_string(),
+            send_at,
+            attempts: 0,
+        }
+    }
+
+    #[test]
+    fn due_messages_come_out_in_order() {
+        let scheduler = Scheduler::load(None);
+        scheduler.add(message("late", 3_000));
+        scheduler.add(message("early", 1_000));
+        scheduler.add(message("mid", 2_000));
+
+        let due: Vec<String> = scheduler.due(2_500).into_iter().map(|m| m.id).collect();
+        assert_eq!(due, ["early", "mid"]);
+        assert_eq!(scheduler.next_wait(2_999), Duration::from_millis(1));
+        assert_eq!(scheduler.next_wait(0), POLL_INTERVAL);
+
+        assert!(scheduler.cancel("mid"));
+        assert!(!scheduler.cancel("mid"));
+        assert_eq!(scheduler.list().len(), 2);
+    }
+
+    #[test]
+    fn stanza_carries_origin_id_and_escaped_body() {
+        let mut scheduled = message("abc", 0);
+        scheduled.body = "1 < 2 & more".to_string();
+        let xml = build_stanza(&scheduled).to_xml();
+        let parsed = Element::parse(&xml).unwrap();
+        assert_eq!(parsed.attr("id"), Some("abc"));
+        assert_eq!(parsed.child("body", None).unwrap().text(), "1 < 2 & more");
+        assert_eq!(
+            parsed
+                .child("origin-id", Some("urn:xmpp:sid:0"))
+                .and_then(|o| o.attr("id")),
+            Some("abc")
+        );
+
+        assert!(validate("juliet@example.com", "hi", "chat", 0).is_ok());
+        assert!(validate("juliet@example.com", " ", "chat", 0).is_err());
+        assert!(validate("juliet@example.com", "hi", "headline", 0).is_err());
+        assert!(validate("juliet@example.com", "hi", "chat", u64::MAX).is_err());
+    }
+}
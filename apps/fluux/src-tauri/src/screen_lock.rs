@@ -0,0 +1,244 @@
+//! Session lock detection.
+//!
+//! Locking the machine means the user has stepped away, whatever the idle
+//! timer says. Each platform reports it natively:
+//!
+//! - macOS: the `com.apple.screenIsLocked` / `com.apple.screenIsUnlocked`
+//!   distributed notifications.
+//! - Windows: `WM_WTSSESSION_CHANGE` (`WTS_SESSION_LOCK` / `_UNLOCK`),
+//!   delivered to a message-only window registered for session
+//!   notifications.
+//! - Linux: the logind session's `Lock` / `Unlock` signals.
+//!
+//! Changes are emitted as `system-screen-locked` and
+//! `system-screen-unlocked`; the frontend's presence handling sets Away on
+//! lock without waiting for the idle threshold, and back on unlock.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::Emitter;
+use tracing::info;
+
+const LOCKED_EVENT: &str = "system-screen-locked";
+const UNLOCKED_EVENT: &str = "system-screen-unlocked";
+
+static LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Record the new state; `false` when it was already known (a platform
+/// may report the same lock twice).
+fn record(locked: bool) -> bool {
+    LOCKED.swap(locked, Ordering::SeqCst) != locked
+}
+
+#[cfg_attr(
+    not(any(target_os = "macos", target_os = "windows", target_os = "linux")),
+    allow(dead_code)
+)]
+fn changed(app: &tauri::AppHandle, locked: bool) {
+    if !record(locked) {
+        return;
+    }
+    info!(locked, "screen lock changed");
+    let _ = app.emit(if locked { LOCKED_EVENT } else { UNLOCKED_EVENT }, ());
+}
+
+/// Start watching. Called from the Tauri `setup` hook (the macOS observers
+/// are added on the main thread).
+pub fn watch(app: tauri::AppHandle) {
+    #[cfg(any(target_os = "macos", target_os = "windows", target_os = "linux"))]
+    platform::watch(app);
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    let _ = app;
+}
+
+/// Whether the session is locked, for a frontend that starts after the
+/// lock.
+#[tauri::command]
+pub fn is_screen_locked() -> bool {
+    LOCKED.load(Ordering::SeqCst)
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use block2::RcBlock;
+    use objc2_foundation::{NSDistributedNotificationCenter, NSNotification, NSNotificationName};
+    use std::ptr::NonNull;
+
+    pub fn watch(app: tauri::AppHandle) {
+        let center = NSDistributedNotificationCenter::defaultCenter();
+        for (name, locked) in [
+            ("com.apple.screenIsLocked", true),
+            ("com.apple.screenIsUnlocked", false),
+        ] {
+            let app = app.clone();
+            let name = NSNotificationName::from_str(name);
+            // SAFETY: the block only captures an AppHandle, which is Send and
+            // Sync; the center retains the observer for the app's lifetime.
+            let _ = unsafe {
+                center.addObserverForName_object_queue_usingBlock(
+                    Some(&name),
+                    None,
+                    None,
+                    &RcBlock::new(move |_notification: NonNull<NSNotification>| {
+                        super::changed(&app, locked)
+                    }),
+                )
+            };
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::sync::OnceLock;
+    use tracing::warn;
+    use windows::core::w;
+    use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::System::RemoteDesktop::{
+        WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW,
+        HWND_MESSAGE, MSG, WINDOW_EX_STYLE, WINDOW_STYLE, WM_WTSSESSION_CHANGE, WNDCLASSW,
+        WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+    };
+
+    static APP: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if message == WM_WTSSESSION_CHANGE {
+            if let Some(app) = APP.get() {
+                match wparam.0 as u32 {
+                    WTS_SESSION_LOCK => super::changed(app, true),
+                    WTS_SESSION_UNLOCK => super::changed(app, false),
+                    _ => {}
+                }
+            }
+            return LRESULT(0);
+        }
+        DefWindowProcW(hwnd, message, wparam, lparam)
+    }
+
+    pub fn watch(app: tauri::AppHandle) {
+        let _ = APP.set(app);
+        // The window, and the loop pumping its messages, live on their own
+        // thread for the life of the app.
+        std::thread::spawn(|| {
+            // SAFETY: plain Win32 window setup; the class, window and loop
+            // all stay on this thread.
+            unsafe {
+                let instance = GetModuleHandleW(None).unwrap_or_default();
+                let class_name = w!("FluuxSessionWatcher");
+                let class = WNDCLASSW {
+                    lpfnWndProc: Some(window_proc),
+                    hInstance: instance.into(),
+                    lpszClassName: class_name,
+                    ..Default::default()
+                };
+                if RegisterClassW(&class) == 0 {
+                    warn!("screen lock: window class not registered");
+                    return;
+                }
+                let hwnd = match CreateWindowExW(
+                    WINDOW_EX_STYLE(0),
+                    class_name,
+                    w!(""),
+                    WINDOW_STYLE(0),
+                    0,
+                    0,
+                    0,
+                    0,
+                    Some(HWND_MESSAGE),
+                    None,
+                    Some(instance.into()),
+                    None,
+                ) {
+                    Ok(hwnd) => hwnd,
+                    Err(e) => {
+                        warn!(error = %e, "screen lock: no message window");
+                        return;
+                    }
+                };
+                if let Err(e) = WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) {
+                    warn!(error = %e, "screen lock: session notifications unavailable");
+                    return;
+                }
+                let mut message = MSG::default();
+                while GetMessageW(&mut message, None, 0, 0).as_bool() {
+                    DispatchMessageW(&message);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use tracing::warn;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::OwnedObjectPath;
+
+    const LOGIN1: &str = "org.freedesktop.login1";
+    const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+    /// Object path of our own logind session. Signals are sent from the
+    /// real path, so the `auto` alias is only used to learn the id.
+    fn session_path(connection: &Connection) -> zbus::Result<OwnedObjectPath> {
+        let auto = Proxy::new(
+            connection,
+            LOGIN1,
+            "/org/freedesktop/login1/session/auto",
+            SESSION_INTERFACE,
+        )?;
+        let id: String = auto.get_property("Id")?;
+        let manager = Proxy::new(
+            connection,
+            LOGIN1,
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+        )?;
+        manager.call("GetSession", &(id,))
+    }
+
+    fn listen(app: &tauri::AppHandle) -> zbus::Result<()> {
+        let connection = Connection::system()?;
+        let path = session_path(&connection)?;
+        let session = Proxy::new(&connection, LOGIN1, path, SESSION_INTERFACE)?;
+        for signal in session.receive_all_signals()? {
+            let header = signal.header();
+            match header.member().map(|m| m.as_str()) {
+                Some("Lock") => super::changed(app, true),
+                Some("Unlock") => super::changed(app, false),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn watch(app: tauri::AppHandle) {
+        std::thread::spawn(move || {
+            if let Err(e) = listen(&app) {
+                warn!(error = %e, "screen lock: logind session not watched");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_each_change_once() {
+        assert!(!is_screen_locked());
+        assert!(record(true));
+        assert!(!record(true));
+        assert!(is_screen_locked());
+        assert!(record(false));
+    }
+}
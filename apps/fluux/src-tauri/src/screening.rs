@@ -0,0 +1,386 @@
+//! First-contact screening.
+//!
+//! With screening on, a chat message from a stranger (a JID that is not in
+//! the roster, that we never wrote to and that was never approved) is held
+//! in `screening.json` instead of being relayed, so it produces no unread
+//! count, notification or conversation until the user looks at the
+//! quarantine. Approving a sender releases their held messages to the UI
+//! and lets the rest through; rejecting discards them, optionally blocking
+//! and reporting the sender (see [`crate::blocking`]).
+//!
+//! The observer runs right after the blocklist, ahead of every observer
+//! that reacts to new messages.
+
+use crate::blocking::{self, Blocklist, Report, ReportReason};
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use tauri::Emitter;
+use tracing::info;
+
+const ROSTER_NS: &str = "jabber:iq:roster";
+const MUC_USER_NS: &str = "http://jabber.org/protocol/muc#user";
+const CHANGED_EVENT: &str = "quarantine-changed";
+
+/// Messages held per sender; older ones are discarded first.
+const MAX_HELD_PER_SENDER: usize = 20;
+/// Messages held in total.
+const MAX_HELD: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeldMessage {
+    /// Bare JID of the sender.
+    pub from: String,
+    /// The message's `id`, when it has one.
+    pub id: Option<String>,
+    /// Unix seconds.
+    pub received_at: u64,
+    pub body: String,
+    /// The stanza as received, for the UI to import on approval.
+    pub stanza: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Stored {
+    enabled: bool,
+    /// Senders let through despite not being in the roster.
+    approved: BTreeSet<String>,
+    held: Vec<HeldMessage>,
+}
+
+static STORED: Mutex<Option<Stored>> = Mutex::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+fn stored() -> Stored {
+    STORED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Load the settings and the quarantine from `dir`. Called from the Tauri
+/// `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("screening.json")) else {
+        return;
+    };
+    let loaded: Stored = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *STORED.lock().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `STORED` lock held; see [`crate::json_file`].
+fn persist(snapshot: Stored) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+/// Apply `change`; persists and returns the held messages when it reports
+/// a change.
+fn update(change: impl FnOnce(&mut Stored) -> bool) -> Option<Vec<HeldMessage>> {
+    let mut guard = STORED.lock().unwrap_or_else(|e| e.into_inner());
+    let stored = guard.get_or_insert_with(Stored::default);
+    if !change(stored) {
+        return None;
+    }
+    persist(stored.clone());
+    Some(stored.held.clone())
+}
+
+fn publish(app: Option<&tauri::AppHandle>, held: Option<Vec<HeldMessage>>) {
+    if let (Some(app), Some(held)) = (app, held) {
+        let _ = app.emit(CHANGED_EVENT, &held);
+    }
+}
+
+fn hold(stored: &mut Stored, message: HeldMessage) {
+    let from_sender = stored
+        .held
+        .iter()
+        .filter(|h| h.from == message.from)
+        .count();
+    if from_sender >= MAX_HELD_PER_SENDER {
+        if let Some(oldest) = stored.held.iter().position(|h| h.from == message.from) {
+            stored.held.remove(oldest);
+        }
+    }
+    stored.held.push(message);
+    if stored.held.len() > MAX_HELD {
+        let excess = stored.held.len() - MAX_HELD;
+        stored.held.drain(..excess);
+    }
+}
+
+/// Take the held messages of `jid` out of the quarantine.
+fn release(stored: &mut Stored, jid: &str) -> Vec<HeldMessage> {
+    let (released, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut stored.held)
+        .into_iter()
+        .partition(|h| h.from == jid);
+    stored.held = kept;
+    released
+}
+
+/// The sender to screen an inbound message from, or `None` when it is not
+/// first-contact chat traffic.
+fn screened_sender(stanza: &Element, own_bare: Option<&str>) -> Option<String> {
+    if stanza.local_name() != "message"
+        || !matches!(stanza.attr("type"), None | Some("chat" | "normal"))
+        || stanza.child("body", None).is_none()
+        // Private messages from a room occupant.
+        || stanza.child("x", Some(MUC_USER_NS)).is_some()
+    {
+        return None;
+    }
+    let from = bare_jid(stanza.attr("from")?).to_lowercase();
+    // Servers and services have no localpart; carbons come from ourselves.
+    if !from.contains('@') || own_bare.is_some_and(|own| own.eq_ignore_ascii_case(&from)) {
+        return None;
+    }
+    Some(from)
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Bridge observer holding back first-contact messages and mirroring the
+/// roster that decides who is a stranger. Registered with the bridge tap.
+#[derive(Default)]
+pub struct Screening {
+    /// Bare JIDs in the roster; `None` until the first roster result, and
+    /// nothing is held before then.
+    roster: Mutex<Option<BTreeSet<String>>>,
+}
+
+impl Screening {
+    fn roster(&self) -> std::sync::MutexGuard<'_, Option<BTreeSet<String>>> {
+        self.roster.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn learn_roster(&self, stanza: &Element) {
+        let Some(query) = stanza.child("query", Some(ROSTER_NS)) else {
+            return;
+        };
+        let mut guard = self.roster();
+        if stanza.attr("type") == Some("result") {
+            *guard = Some(BTreeSet::new());
+        }
+        let Some(roster) = guard.as_mut() else {
+            return;
+        };
+        for item in query.elements().filter(|e| e.local_name() == "item") {
+            let Some(jid) = item.attr("jid").map(|j| bare_jid(j).to_lowercase()) else {
+                continue;
+            };
+            if item.attr("subscription") == Some("remove") {
+                roster.remove(&jid);
+            } else {
+                roster.insert(jid);
+            }
+        }
+    }
+
+    /// Hold `stanza` if it comes from a stranger. Returns whether it was
+    /// held, and the changed quarantine.
+    fn screen(&self, stanza: &Element, own_bare: Option<&str>) -> (bool, Option<Vec<HeldMessage>>) {
+        let Some(from) = screened_sender(stanza, own_bare) else {
+            return (false, None);
+        };
+        if self
+            .roster()
+            .as_ref()
+            .is_none_or(|roster| roster.contains(&from))
+        {
+            return (false, None);
+        }
+        let mut held = false;
+        let changed = update(|stored| {
+            if !stored.enabled || stored.approved.contains(&from) {
+                return false;
+            }
+            let body = stanza.child("body", None).map(Element::text);
+            hold(
+                stored,
+                HeldMessage {
+                    from: from.clone(),
+                    id: stanza.attr("id").map(str::to_string),
+                    received_at: now_secs(),
+                    body: body.unwrap_or_default(),
+                    stanza: stanza.to_xml(),
+                },
+            );
+            held = true;
+            true
+        });
+        (held, changed)
+    }
+}
+
+impl StanzaObserver for Screening {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        let own = session::own_bare_jid();
+        match (ctx.direction, stanza.local_name()) {
+            (Direction::Inbound, "iq") => {
+                let from_own = stanza
+                    .attr("from")
+                    .is_none_or(|from| own.as_deref().is_some_and(|o| o == bare_jid(from)));
+                if from_own && matches!(stanza.attr("type"), Some("result" | "set")) {
+                    self.learn_roster(stanza);
+                }
+            }
+            (Direction::Inbound, "message") => {
+                let (held, changed) = self.screen(stanza, own.as_deref());
+                publish(ctx.app, changed);
+                if held {
+                    return Verdict::Drop;
+                }
+            }
+            // Writing to someone is as good as approving them. Anything
+            // already held stays for the user to approve or reject.
+            (Direction::Outbound, "message")
+                if stanza.child("body", None).is_some()
+                    && stanza.attr("type") != Some("groupchat") =>
+            {
+                let to = stanza.attr("to").map(|to| bare_jid(to).to_lowercase());
+                let in_roster =
+                    |to: &String| self.roster().as_ref().is_some_and(|r| r.contains(to));
+                if let Some(to) = to.filter(|to| !in_roster(to)) {
+                    update(|stored| stored.approved.insert(to));
+                }
+            }
+            _ => {}
+        }
+        Verdict::Forward
+    }
+}
+
+#[tauri::command]
+pub fn get_screening_enabled() -> bool {
+    stored().enabled
+}
+
+/// Turn screening on or off. Turning it off leaves the held messages in
+/// the quarantine until they are approved or rejected.
+#[tauri::command]
+pub fn set_screening_enabled(enabled: bool) {
+    update(|stored| {
+        let changed = stored.enabled != enabled;
+        stored.enabled = enabled;
+        changed
+    });
+}
+
+#[tauri::command]
+pub fn list_quarantined_messages() -> Vec<HeldMessage> {
+    stored().held
+}
+
+/// Let `jid` through from now on. Returns their held messages, oldest
+/// first, for the UI to import.
+#[tauri::command]
+pub fn approve_sender(app: tauri::AppHandle, jid: String) -> Vec<HeldMessage> {
+    let jid = bare_jid(&jid).to_lowercase();
+    let mut released = Vec::new();
+    publish(
+        Some(&app),
+        update(|stored| {
+            released = release(stored, &jid);
+            stored.approved.insert(jid.clone()) || !released.is_empty()
+        }),
+    );
+    info!(jid = %jid, released = released.len(), "screening: sender approved");
+    released
+}
+
+/// Discard the held messages of `jid`. With `report`, the sender is also
+/// reported and blocked; with `block` alone, only blocked.
+#[tauri::command]
+pub async fn reject_sender(
+    app: tauri::AppHandle,
+    blocklist: tauri::State<'_, Arc<Blocklist>>,
+    jid: String,
+    block: bool,
+    report: Option<ReportReason>,
+) -> Result<(), String> {
+    let jid = bare_jid(&jid).to_lowercase();
+    if block || report.is_some() {
+        let report = report.map(|reason| Report { reason, text: None });
+        blocking::block(&app, &blocklist, &[jid.clone()], report).await?;
+    }
+    publish(
+        Some(&app),
+        update(|stored| !release(stored, &jid).is_empty()),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(xml: &str) -> Element {
+        Element::parse(xml).unwrap()
+    }
+
+    #[test]
+    fn only_first_contact_chat_is_screened() {
+        let own = Some("me@example.com");
+        let chat = parse("<message from='Eve@Example.org/x' type='chat'><body>hi</body></message>");
+        assert_eq!(
+            screened_sender(&chat, own).as_deref(),
+            Some("eve@example.org")
+        );
+        let normal = parse("<message from='eve@example.org'><body>hi</body></message>");
+        assert!(screened_sender(&normal, own).is_some());
+
+        for xml in [
+            "<message from='room@muc.example.org/eve' type='groupchat'><body>hi</body></message>",
+            "<message from='room@muc.example.org/eve' type='chat'><body>hi</body>\
+             <x xmlns='http://jabber.org/protocol/muc#user'/></message>",
+            "<message from='eve@example.org' type='chat'>\
+             <composing xmlns='http://jabber.org/protocol/chatstates'/></message>",
+            "<message from='example.org'><body>Maintenance tonight</body></message>",
+            "<message from='me@example.com/phone' type='chat'><body>hi</body></message>",
+            "<message from='eve@example.org' type='error'><body>hi</body></message>",
+        ] {
+            assert_eq!(screened_sender(&parse(xml), own), None, "{xml}");
+        }
+    }
+
+    #[test]
+    fn quarantine_is_bounded_per_sender_and_released_by_sender() {
+        let mut stored = Stored::default();
+        let message = |from: &str, n: usize| HeldMessage {
+            from: from.to_string(),
+            id: Some(format!("m{n}")),
+            received_at: n as u64,
+            body: String::new(),
+            stanza: String::new(),
+        };
+        for n in 0..MAX_HELD_PER_SENDER + 5 {
+            hold(&mut stored, message("eve@example.org", n));
+        }
+        hold(&mut stored, message("mallory@example.org", 0));
+        assert_eq!(stored.held.len(), MAX_HELD_PER_SENDER + 1);
+
+        let released = release(&mut stored, "eve@example.org");
+        assert_eq!(released.len(), MAX_HELD_PER_SENDER);
+        assert_eq!(released[0].id.as_deref(), Some("m5"));
+        assert_eq!(stored.held.len(), 1);
+    }
+}
@@ -0,0 +1,242 @@
+//! The account's sessions on other devices, and ending one remotely.
+//!
+//! Resources are listed with a disco#items query to the account's own bare
+//! JID, which the server answers with the account's available resources
+//! (XEP-0030 §4.3), and each is asked for its client identity
+//! (disco#info). When the ejabberd admin API is configured (see
+//! [`crate::admin`]), `user_sessions_info` adds the IP address, uptime and
+//! status, and `kick_session` ends a session: plain XMPP gives a user no way
+//! to end another of their sessions.
+
+use crate::caps::{self, CapsCache};
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, resource, Element};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::Manager;
+use tracing::{info, warn};
+
+const DISCO_ITEMS_NS: &str = "http://jabber.org/protocol/disco#items";
+/// A device that doesn't answer disco#info in time is listed without a name.
+const IDENTITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountSession {
+    pub resource: String,
+    /// The session this app is using.
+    pub current: bool,
+    /// Client name from its disco#info identity (`Fluux`, `Conversations`…).
+    pub client: Option<String>,
+    /// Identity type: `pc`, `phone`, `web`…
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub uptime_secs: Option<u64>,
+    pub status: Option<String>,
+    pub status_text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountSessions {
+    pub sessions: Vec<AccountSession>,
+    /// Sessions can be ended (the admin API is configured).
+    pub can_end: bool,
+}
+
+/// One entry of ejabberd's `user_sessions_info`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ApiSession {
+    resource: String,
+    ip: Option<String>,
+    uptime: Option<u64>,
+    status: Option<String>,
+    statustext: Option<String>,
+}
+
+/// `user@host` → (`user`, `host`).
+fn split_account(jid: &str) -> Result<(&str, &str), String> {
+    bare_jid(jid)
+        .split_once('@')
+        .filter(|(user, host)| !user.is_empty() && !host.is_empty())
+        .ok_or_else(|| format!("Not an account JID: {jid}"))
+}
+
+/// Resources of `own` in a disco#items result.
+fn resources_from_items(own: &str, response: &Element) -> Vec<String> {
+    let Some(query) = response.child("query", Some(DISCO_ITEMS_NS)) else {
+        return Vec::new();
+    };
+    query
+        .elements()
+        .filter(|item| item.local_name() == "item")
+        .filter_map(|item| item.attr("jid"))
+        .filter(|jid| bare_jid(jid) == bare_jid(own))
+        .filter_map(resource)
+        .map(str::to_string)
+        .collect()
+}
+
+/// One entry per resource seen by either source, this app's first.
+fn merge(own: &str, resources: Vec<String>, api: Vec<ApiSession>) -> Vec<AccountSession> {
+    let current = resource(own).unwrap_or_default();
+    let mut sessions: Vec<AccountSession> = Vec::new();
+    let names = std::iter::once(current.to_string())
+        .chain(resources)
+        .chain(api.iter().map(|s| s.resource.clone()));
+    for name in names {
+        if !name.is_empty() && !sessions.iter().any(|s| s.resource == name) {
+            sessions.push(AccountSession {
+                current: name == current,
+                resource: name,
+                ..Default::default()
+            });
+        }
+    }
+    for info in api {
+        if let Some(session) = sessions.iter_mut().find(|s| s.resource == info.resource) {
+            session.ip = info.ip.filter(|ip| !ip.is_empty());
+            session.uptime_secs = info.uptime;
+            session.status = info.status.filter(|s| !s.is_empty());
+            session.status_text = info.statustext.filter(|s| !s.is_empty());
+        }
+    }
+    sessions.sort_by(|a, b| b.current.cmp(&a.current).then(a.resource.cmp(&b.resource)));
+    sessions
+}
+
+async fn disco_resources(own: &str) -> Result<Vec<String>, String> {
+    let iq = Element::new("iq")
+        .with_attr("type", "get")
+        .with_attr("to", bare_jid(own))
+        .with_child(Element::new("query").with_attr("xmlns", DISCO_ITEMS_NS));
+    Ok(resources_from_items(own, &session::request(iq).await?))
+}
+
+async fn api_sessions(own: &str) -> Result<Vec<ApiSession>, String> {
+    let (user, host) = split_account(own)?;
+    let result = crate::admin::admin_request(
+        "user_sessions_info".to_string(),
+        Some(serde_json::json!({ "user": user, "host": host })),
+    )
+    .await?;
+    serde_json::from_value(result).map_err(|e| format!("Unexpected user_sessions_info result: {e}"))
+}
+
+/// Fill in the client identity of the other sessions.
+async fn describe(app: &tauri::AppHandle, own: &str, sessions: &mut [AccountSession]) {
+    let caps = app.state::<Arc<CapsCache>>();
+    let lookups = sessions.iter().filter(|s| !s.current).map(|s| {
+        let jid = format!("{}/{}", bare_jid(own), s.resource);
+        let caps = caps.clone();
+        async move {
+            tokio::time::timeout(IDENTITY_TIMEOUT, caps::get_peer_features(caps, jid))
+                .await
+                .ok()
+                .and_then(Result::ok)
+        }
+    });
+    let infos = futures_util::future::join_all(lookups).await;
+    for (session, info) in sessions.iter_mut().filter(|s| !s.current).zip(infos) {
+        let identity = info.and_then(|info| {
+            info.identities
+                .into_iter()
+                .find(|identity| identity.category == "client")
+        });
+        if let Some(identity) = identity {
+            session.client = identity.name;
+            session.device = Some(identity.kind).filter(|kind| !kind.is_empty());
+        }
+    }
+    if let Some(current) = sessions.iter_mut().find(|s| s.current) {
+        current.client = Some(app.package_info().name.clone());
+        current.device = Some("pc".to_string());
+    }
+}
+
+/// The account's sessions, this app's first.
+#[tauri::command]
+pub async fn list_account_sessions(app: tauri::AppHandle) -> Result<AccountSessions, String> {
+    let own = session::own_jid().ok_or("Not connected")?;
+    let resources = disco_resources(&own).await.unwrap_or_else(|e| {
+        warn!(error = %e, "sessions: disco#items on the own JID failed");
+        Vec::new()
+    });
+    let can_end = crate::admin::is_configured().await;
+    let api = if can_end {
+        api_sessions(&own).await.unwrap_or_else(|e| {
+            warn!(error = %e, "sessions: user_sessions_info failed");
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    let mut sessions = merge(&own, resources, api);
+    describe(&app, &own, &mut sessions).await;
+    Ok(AccountSessions { sessions, can_end })
+}
+
+/// End the session bound to `resource`, through the admin API.
+#[tauri::command]
+pub async fn end_account_session(resource: String, reason: Option<String>) -> Result<(), String> {
+    let own = session::own_jid().ok_or("Not connected")?;
+    if crate::xmpp_proxy::stanza::resource(&own) == Some(resource.as_str()) {
+        return Err("This is the current session; log out instead".to_string());
+    }
+    if !crate::admin::is_configured().await {
+        return Err("Ending a session needs the server admin API".to_string());
+    }
+    let (user, host) = split_account(&own)?;
+    crate::admin::admin_request(
+        "kick_session".to_string(),
+        Some(serde_json::json!({
+            "user": user,
+            "host": host,
+            "resource": resource,
+            "reason": reason.unwrap_or_else(|| "Session ended from another device".to_string()),
+        })),
+    )
+    .await?;
+    info!(resource = %resource, "sessions: ended a session");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_disco_and_api_sessions() {
+        let own = "alice@example.com/fluux-desk";
+        let items = Element::parse(
+            "<iq type='result'><query xmlns='http://jabber.org/protocol/disco#items'>\
+             <item jid='alice@example.com/fluux-desk' name='alice'/>\
+             <item jid='alice@example.com/office' name='alice'/>\
+             <item jid='alice@example.com' node='urn:xmpp:microblog:0'/>\
+             <item jid='bob@example.com/phone'/>\
+             </query></iq>",
+        )
+        .unwrap();
+        let resources = resources_from_items(own, &items);
+        assert_eq!(resources, ["fluux-desk", "office"]);
+
+        let api = vec![ApiSession {
+            resource: "phone".to_string(),
+            ip: Some("203.0.113.7".to_string()),
+            uptime: Some(3600),
+            status: Some("away".to_string()),
+            statustext: Some(String::new()),
+        }];
+        let sessions = merge(own, resources, api);
+        let names: Vec<_> = sessions.iter().map(|s| s.resource.as_str()).collect();
+        assert_eq!(names, ["fluux-desk", "office", "phone"]);
+        assert!(sessions[0].current && !sessions[1].current);
+        assert_eq!(sessions[2].ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(sessions[2].status_text, None);
+
+        assert_eq!(split_account(own), Ok(("alice", "example.com")));
+        assert!(split_account("example.com").is_err());
+    }
+}
@@ -0,0 +1,581 @@
+//! End-to-end encrypted settings sync between our own devices.
+//!
+//! The synced settings (notification rules, watch terms, and whatever the
+//! frontend hands over for its own settings and pins) are serialized to
+//! JSON, encrypted with AES-256-GCM under a key derived from a passphrase
+//! with Argon2id, and published as the single item of a private PEP node
+//! (`access_model=whitelist`, so only our own account can read it). The
+//! server only ever sees ciphertext.
+//!
+//! The passphrase is kept in the keychain, per account. Every published
+//! snapshot carries its revision (Unix milliseconds), bound to the
+//! ciphertext as associated data; a device applies a snapshot only when it
+//! is newer than the revision it last published or applied, so the last
+//! writer wins and a device never re-applies its own update. Snapshots are
+//! pulled when the session comes up and applied as they are pushed by the
+//! server; the frontend gets the decrypted settings in `settings-synced`.
+//! Storage, pushes and revision ordering go through [`crate::pep`].
+
+use crate::json_file::JsonFile;
+use crate::notifications::rules::{self, NotificationRules};
+use crate::openpgp_storage::Argon2Params;
+use crate::pep::{self, Item, PushHandler, Schema};
+use crate::word_watch::{self, WatchTerm};
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::Element;
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use keyring::Entry;
+use sequoia_openpgp::crypto::Password;
+use sequoia_openpgp::types::S2K;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tauri::Emitter;
+use tracing::{info, warn};
+
+const SYNC_NS: &str = "urn:fluux:settings-sync:0";
+const ITEM_ID: &str = "current";
+const SYNCED_EVENT: &str = "settings-synced";
+const MIN_PASSPHRASE_LEN: usize = 8;
+/// The Argon2 cost comes with the snapshot, so whoever can publish it (the
+/// server included) picks the work every device does on each pull. Anything
+/// past 10 passes, 8 lanes or 2 GiB (`m` is log2 of the KiB) is refused.
+const MAX_ARGON2_T: u8 = 10;
+const MAX_ARGON2_P: u8 = 8;
+const ARGON2_M: std::ops::RangeInclusive<u8> = 10..=21;
+
+/// The node holding the encrypted snapshot, as its single item.
+pub(crate) static SCHEMA: Schema = Schema {
+    node: SYNC_NS,
+    root: "settings",
+    ns: SYNC_NS,
+    required: &["revision", "salt", "nonce", "t", "p", "m"],
+    max_bytes: 512 * 1024,
+};
+
+/// What travels between devices.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SyncedSettings {
+    pub notification_rules: NotificationRules,
+    pub watch_terms: Vec<WatchTerm>,
+    /// Frontend-owned settings and pins, carried as-is.
+    pub app: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    pub enabled: bool,
+    /// Revision last published or applied, Unix milliseconds.
+    pub revision: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct AccountSync {
+    enabled: bool,
+    revision: Option<u64>,
+}
+
+/// Sync state per account.
+type Stored = BTreeMap<String, AccountSync>;
+
+static STORED: Mutex<Option<Stored>> = Mutex::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+fn account_sync(account: &str) -> AccountSync {
+    STORED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(|stored| stored.get(account).copied())
+        .unwrap_or_default()
+}
+
+/// Load the sync state from `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("settings-sync.json")) else {
+        return;
+    };
+    let loaded: Stored = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *STORED.lock().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `STORED` lock held, so an older state never lands last.
+fn persist(snapshot: Stored) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+/// Apply `change` to the account's state; persists when it reports a change.
+fn update(account: &str, change: impl FnOnce(&mut AccountSync) -> bool) -> SyncStatus {
+    let mut guard = STORED.lock().unwrap_or_else(|e| e.into_inner());
+    let stored = guard.get_or_insert_with(Stored::default);
+    let state = stored.entry(account.to_string()).or_default();
+    let changed = change(state);
+    let state = *state;
+    if changed {
+        persist(stored.clone());
+    }
+    SyncStatus {
+        enabled: state.enabled,
+        revision: state.revision,
+    }
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn current_account() -> Result<String, String> {
+    session::own_bare_jid().ok_or_else(|| "Not connected".to_string())
+}
+
+fn keyring_entry(account: &str) -> Result<Entry, String> {
+    Entry::new(crate::KEYRING_SERVICE, &format!("settings-sync:{account}"))
+        .map_err(|e| format!("Failed to create keyring entry for settings sync: {e}"))
+}
+
+fn load_passphrase(account: &str) -> Result<Option<String>, String> {
+    let (entry, user) = (keyring_entry(account)?, format!("settings-sync:{account}"));
+    match crate::credential_log::read(&entry, &user, "settings sync passphrase") {
+        Ok(passphrase) => Ok(Some(passphrase)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(crate::classify_keyring_error(
+            &e,
+            "read the settings sync passphrase",
+        )),
+    }
+}
+
+/// Run a keychain operation off the async runtime.
+async fn with_keychain<T: Send + 'static>(
+    op: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tokio::task::spawn_blocking(op)
+        .await
+        .map_err(|e| format!("Keychain task panicked: {e}"))?
+}
+
+/// Encrypted snapshot, as published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sealed {
+    revision: u64,
+    salt: [u8; 16],
+    nonce: [u8; 12],
+    params: Argon2Params,
+    ciphertext: Vec<u8>,
+}
+
+fn acceptable(params: Argon2Params) -> bool {
+    (1..=MAX_ARGON2_T).contains(&params.t)
+        && (1..=MAX_ARGON2_P).contains(&params.p)
+        && ARGON2_M.contains(&params.m)
+}
+
+fn derive_key(passphrase: &str, salt: [u8; 16], params: Argon2Params) -> Result<[u8; 32], String> {
+    let s2k = S2K::Argon2 {
+        salt,
+        t: params.t,
+        p: params.p,
+        m: params.m,
+    };
+    let key = s2k
+        .derive_key(&Password::from(passphrase), 32)
+        .map_err(|e| format!("Failed to derive the sync key: {e}"))?;
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&key[..32]);
+    Ok(bytes)
+}
+
+fn random<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0u8; N];
+    sequoia_openpgp::crypto::random(&mut bytes)
+        .map_err(|e| format!("Failed to gather randomness: {e}"))?;
+    Ok(bytes)
+}
+
+/// Encrypt `plaintext` for `revision`, with a fresh salt and nonce.
+fn seal(
+    plaintext: &[u8],
+    passphrase: &str,
+    revision: u64,
+    params: Argon2Params,
+) -> Result<Sealed, String> {
+    let salt = random::<16>()?;
+    let nonce = random::<12>()?;
+    let key = derive_key(passphrase, salt, params)?;
+    let aad = revision.to_string();
+    let ciphertext = Aes256Gcm::new(&Key::<Aes256Gcm>::from(key))
+        .encrypt(
+            &Nonce::from(nonce),
+            Payload {
+                msg: plaintext,
+                aad: aad.as_bytes(),
+            },
+        )
+        .map_err(|_| "Failed to encrypt the settings".to_string())?;
+    Ok(Sealed {
+        revision,
+        salt,
+        nonce,
+        params,
+        ciphertext,
+    })
+}
+
+fn open(sealed: &Sealed, passphrase: &str) -> Result<Vec<u8>, String> {
+    let key = derive_key(passphrase, sealed.salt, sealed.params)?;
+    let aad = sealed.revision.to_string();
+    Aes256Gcm::new(&Key::<Aes256Gcm>::from(key))
+        .decrypt(
+            &Nonce::from(sealed.nonce),
+            Payload {
+                msg: &sealed.ciphertext,
+                aad: aad.as_bytes(),
+            },
+        )
+        .map_err(|_| "Wrong sync passphrase or corrupt settings".to_string())
+}
+
+impl Sealed {
+    fn to_element(&self) -> Element {
+        Element::new("settings")
+            .with_attr("xmlns", SYNC_NS)
+            .with_attr("revision", &self.revision.to_string())
+            .with_attr("salt", &BASE64.encode(self.salt))
+            .with_attr("nonce", &BASE64.encode(self.nonce))
+            .with_attr("t", &self.params.t.to_string())
+            .with_attr("p", &self.params.p.to_string())
+            .with_attr("m", &self.params.m.to_string())
+            .with_text(&BASE64.encode(&self.ciphertext))
+    }
+
+    fn from_element(element: &Element) -> Option<Self> {
+        if element.local_name() != "settings" || element.ns() != Some(SYNC_NS) {
+            return None;
+        }
+        let bytes = |name: &str| BASE64.decode(element.attr(name)?).ok();
+        let number = |name: &str| element.attr(name)?.parse::<u8>().ok();
+        let params = Argon2Params {
+            t: number("t")?,
+            p: number("p")?,
+            m: number("m")?,
+        };
+        if !acceptable(params) {
+            warn!(
+                ?params,
+                "Ignoring a snapshot with an out-of-range Argon2 cost"
+            );
+            return None;
+        }
+        Some(Sealed {
+            revision: element.attr("revision")?.parse().ok()?,
+            salt: bytes("salt")?.try_into().ok()?,
+            nonce: bytes("nonce")?.try_into().ok()?,
+            params,
+            ciphertext: BASE64
+                .decode(element.text().split_whitespace().collect::<String>())
+                .ok()?,
+        })
+    }
+}
+
+/// Our snapshot among a node's items.
+fn snapshot(items: &[Item]) -> Option<Sealed> {
+    items
+        .iter()
+        .find(|item| item.id.as_deref() == Some(ITEM_ID))
+        .and_then(|item| Sealed::from_element(&item.payload))
+}
+
+/// The published snapshot, if any.
+async fn fetch() -> Result<Option<Sealed>, String> {
+    Ok(snapshot(&pep::get_private(&SCHEMA).await?))
+}
+
+/// The settings as they are on this device, with the frontend's `app` part.
+fn local_settings(app: serde_json::Value) -> SyncedSettings {
+    SyncedSettings {
+        notification_rules: rules::rules(),
+        watch_terms: word_watch::get_watch_terms(),
+        app,
+    }
+}
+
+/// Decrypt `sealed` and, when it is newer than what this device has, apply
+/// it and hand it to the frontend.
+async fn apply(
+    app: Option<tauri::AppHandle>,
+    account: String,
+    sealed: Sealed,
+) -> Result<Option<SyncedSettings>, String> {
+    let state = account_sync(&account);
+    if !state.enabled || !pep::is_newer(state.revision, sealed.revision) {
+        return Ok(None);
+    }
+    let passphrase = {
+        let account = account.clone();
+        with_keychain(move || load_passphrase(&account)).await?
+    }
+    .ok_or_else(|| "Settings sync passphrase is missing".to_string())?;
+    let plaintext =
+        tokio::task::spawn_blocking(move || open(&sealed, &passphrase).map(|p| (sealed, p)))
+            .await
+            .map_err(|e| format!("Sync task panicked: {e}"))?;
+    let (sealed, plaintext) = plaintext?;
+    let settings: SyncedSettings = serde_json::from_slice(&plaintext)
+        .map_err(|e| format!("Synced settings are unreadable: {e}"))?;
+    rules::replace(settings.notification_rules.clone());
+    word_watch::set_watch_terms(settings.watch_terms.clone())?;
+    update(&account, |state| {
+        state.revision = Some(sealed.revision);
+        true
+    });
+    info!(
+        revision = sealed.revision,
+        "settings sync: applied remote settings"
+    );
+    if let Some(app) = app {
+        let _ = app.emit(SYNCED_EVENT, &settings);
+    }
+    Ok(Some(settings))
+}
+
+async fn pull(app: Option<tauri::AppHandle>) -> Result<Option<SyncedSettings>, String> {
+    let account = current_account()?;
+    match fetch().await? {
+        Some(sealed) => apply(app, account, sealed).await,
+        None => Ok(None),
+    }
+}
+
+/// Pulls on session start and applies pushed updates.
+#[derive(Default)]
+pub struct SettingsSync {
+    /// Connection we last pulled for.
+    pulled: Mutex<Option<u64>>,
+}
+
+impl PushHandler for SettingsSync {
+    fn pushed(&self, app: Option<&tauri::AppHandle>, items: Vec<Item>) {
+        let (Some(account), Some(sealed)) = (session::own_bare_jid(), snapshot(&items)) else {
+            return;
+        };
+        let app = app.cloned();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = apply(app, account, sealed).await {
+                warn!(error = %e, "settings sync: cannot apply pushed settings");
+            }
+        });
+    }
+}
+
+impl StanzaObserver for SettingsSync {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        // Initial presence: the session is up.
+        let initial_presence = ctx.direction == Direction::Outbound
+            && stanza.local_name() == "presence"
+            && stanza.attr("to").is_none()
+            && stanza.attr("type").is_none();
+        if !initial_presence {
+            return Verdict::Forward;
+        }
+        let mut pulled = self.pulled.lock().unwrap_or_else(|e| e.into_inner());
+        if *pulled == Some(ctx.conn_id) {
+            return Verdict::Forward;
+        }
+        *pulled = Some(ctx.conn_id);
+        let enabled = session::own_bare_jid().is_some_and(|a| account_sync(&a).enabled);
+        if enabled {
+            let app = ctx.app.cloned();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = pull(app).await {
+                    warn!(error = %e, "settings sync: cannot pull settings");
+                }
+            });
+        }
+        Verdict::Forward
+    }
+}
+
+#[tauri::command]
+pub fn get_settings_sync() -> Result<SyncStatus, String> {
+    let state = account_sync(&current_account()?);
+    Ok(SyncStatus {
+        enabled: state.enabled,
+        revision: state.revision,
+    })
+}
+
+/// Turn sync on with `passphrase`. When other devices already published
+/// settings, the passphrase must open them, and they are applied.
+#[tauri::command]
+pub async fn enable_settings_sync(
+    app: tauri::AppHandle,
+    passphrase: String,
+) -> Result<SyncStatus, String> {
+    let account = current_account()?;
+    let passphrase = passphrase.trim().to_string();
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!(
+            "The sync passphrase needs at least {MIN_PASSPHRASE_LEN} characters"
+        ));
+    }
+    if let Some(sealed) = fetch().await? {
+        let check = passphrase.clone();
+        tokio::task::spawn_blocking(move || open(&sealed, &check))
+            .await
+            .map_err(|e| format!("Sync task panicked: {e}"))??;
+    }
+    {
+        let account = account.clone();
+        with_keychain(move || {
+            keyring_entry(&account)?
+                .set_password(&passphrase)
+                .map_err(|e| crate::classify_keyring_error(&e, "save the settings sync passphrase"))
+        })
+        .await?;
+    }
+    pep::subscribe_private(&SCHEMA).await?;
+    update(&account, |state| {
+        state.enabled = true;
+        state.revision = None;
+        true
+    });
+    pull(Some(app)).await?;
+    get_settings_sync()
+}
+
+/// Turn sync off and forget the passphrase. The published snapshot stays
+/// for the other devices.
+#[tauri::command]
+pub async fn disable_settings_sync() -> Result<SyncStatus, String> {
+    let account = current_account()?;
+    {
+        let account = account.clone();
+        with_keychain(move || match keyring_entry(&account)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(crate::classify_keyring_error(
+                &e,
+                "delete the settings sync passphrase",
+            )),
+        })
+        .await?;
+    }
+    Ok(update(&account, |state| {
+        let changed = state.enabled;
+        state.enabled = false;
+        changed
+    }))
+}
+
+/// Encrypt and publish this device's settings, with the frontend's own
+/// settings and pins in `app_settings`.
+#[tauri::command]
+pub async fn publish_synced_settings(
+    app_settings: serde_json::Value,
+) -> Result<SyncStatus, String> {
+    let account = current_account()?;
+    if !account_sync(&account).enabled {
+        return Err("Settings sync is off".to_string());
+    }
+    let passphrase = {
+        let account = account.clone();
+        with_keychain(move || load_passphrase(&account)).await?
+    }
+    .ok_or_else(|| "Settings sync passphrase is missing".to_string())?;
+    let plaintext = serde_json::to_vec(&local_settings(app_settings))
+        .map_err(|e| format!("Failed to serialize settings: {e}"))?;
+    let revision = pep::next_revision(account_sync(&account).revision, now_ms());
+    let sealed = tokio::task::spawn_blocking(move || {
+        seal(
+            &plaintext,
+            &passphrase,
+            revision,
+            Argon2Params::RFC9106_SECOND_OPTION,
+        )
+    })
+    .await
+    .map_err(|e| format!("Sync task panicked: {e}"))??;
+    pep::publish_private(&SCHEMA, ITEM_ID, sealed.to_element()).await?;
+    Ok(update(&account, |state| {
+        state.revision = Some(revision);
+        true
+    }))
+}
+
+/// Fetch and apply the published settings now. `None` when there is
+/// nothing newer than what this device has.
+#[tauri::command]
+pub async fn pull_synced_settings(app: tauri::AppHandle) -> Result<Option<SyncedSettings>, String> {
+    pull(Some(app)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_settings_round_trip_through_the_published_element() {
+        let params = Argon2Params::CHEAP_FOR_TESTS;
+        let sealed = seal(b"{\"app\":1}", "correct horse", 1_700_000_000_000, params).unwrap();
+        let parsed = Element::parse(&sealed.to_element().to_xml()).unwrap();
+        let restored = Sealed::from_element(&parsed).unwrap();
+        assert_eq!(restored, sealed);
+        assert_eq!(open(&restored, "correct horse").unwrap(), b"{\"app\":1}");
+        assert!(open(&restored, "wrong horse").is_err());
+    }
+
+    #[test]
+    fn refuses_snapshots_with_an_out_of_range_argon2_cost() {
+        assert!(acceptable(Argon2Params::RFC9106_SECOND_OPTION));
+        let sealed = seal(b"{}", "correct horse", 1, Argon2Params::CHEAP_FOR_TESTS).unwrap();
+        let published = sealed.to_element().to_xml();
+        for (attr, value) in [
+            ("m", "30"),
+            ("m", "9"),
+            ("t", "255"),
+            ("t", "0"),
+            ("p", "9"),
+        ] {
+            let mut element = Element::parse(&published).unwrap();
+            element.set_attr(attr, value);
+            assert_eq!(Sealed::from_element(&element), None, "{attr}={value}");
+        }
+    }
+
+    #[test]
+    fn the_revision_is_bound_to_the_ciphertext() {
+        let params = Argon2Params::CHEAP_FOR_TESTS;
+        let mut sealed = seal(b"{}", "correct horse", 5, params).unwrap();
+        sealed.revision = 6;
+        assert!(open(&sealed, "correct horse").is_err());
+    }
+
+    #[test]
+    fn finds_our_snapshot_among_the_node_items() {
+        let sealed = seal(b"{}", "passphrase", 1, Argon2Params::CHEAP_FOR_TESTS).unwrap();
+        let payload = Element::parse(&sealed.to_element().to_xml()).unwrap();
+        assert_eq!(SCHEMA.validate(&payload), Ok(()));
+        let item = |id: &str| Item {
+            id: Some(id.to_string()),
+            payload: payload.clone(),
+        };
+        assert_eq!(snapshot(&[item("old"), item(ITEM_ID)]), Some(sealed));
+        assert_eq!(snapshot(&[item("old")]), None);
+    }
+}
@@ -0,0 +1,889 @@
+//! Translated labels for the native shell: the macOS menu bar, the
+//! Linux/Windows tray menu and native dialogs.
+//!
+//! The WebView UI is translated by the frontend; these strings are the few
+//! the OS draws itself. Catalogs are compiled in, one per language, and any
+//! language without one falls back to English. The frontend calls
+//! [`set_ui_language`] when its language changes so the menus are rebuilt
+//! with matching labels; until then the system locale is used.
+
+use std::sync::Mutex;
+
+/// Every label shown by the native menus and dialogs.
+pub struct ShellStrings {
+    pub about: &'static str,
+    pub services: &'static str,
+    pub hide: &'static str,
+    pub hide_others: &'static str,
+    pub show_all: &'static str,
+    pub quit_app: &'static str,
+    pub edit: &'static str,
+    pub undo: &'static str,
+    pub redo: &'static str,
+    pub cut: &'static str,
+    pub copy: &'static str,
+    pub paste: &'static str,
+    pub select_all: &'static str,
+    pub view: &'static str,
+    pub toggle_full_screen: &'static str,
+    pub window: &'static str,
+    pub minimize: &'static str,
+    pub zoom: &'static str,
+    pub close_window: &'static str,
+    pub help: &'static str,
+    pub github: &'static str,
+    pub report_issue: &'static str,
+    pub reveal_logs: &'static str,
+    pub tray_show: &'static str,
+    pub tray_open_logs: &'static str,
+    pub tray_quit: &'static str,
+    pub status: &'static str,
+    pub available: &'static str,
+    pub away: &'static str,
+    pub dnd: &'static str,
+    pub conversations: &'static str,
+    pub new_conversation: &'static str,
+    pub no_conversations: &'static str,
+    pub quit_confirm: &'static str,
+    pub pending_messages: &'static str,
+    pub active_call: &'static str,
+    pub cancel: &'static str,
+}
+
+const EN: ShellStrings = ShellStrings {
+    about: "About Fluux Messenger",
+    services: "Services",
+    hide: "Hide Fluux Messenger",
+    hide_others: "Hide Others",
+    show_all: "Show All",
+    quit_app: "Quit Fluux Messenger",
+    edit: "Edit",
+    undo: "Undo",
+    redo: "Redo",
+    cut: "Cut",
+    copy: "Copy",
+    paste: "Paste",
+    select_all: "Select All",
+    view: "View",
+    toggle_full_screen: "Toggle Full Screen",
+    window: "Window",
+    minimize: "Minimize",
+    zoom: "Zoom",
+    close_window: "Close Window",
+    help: "Help",
+    github: "Fluux Messenger on GitHub",
+    report_issue: "Report an Issue...",
+    reveal_logs: "Reveal Logs in Finder",
+    tray_show: "Show Fluux",
+    tray_open_logs: "Open Logs Folder",
+    tray_quit: "Quit",
+    status: "Status",
+    available: "Available",
+    away: "Away",
+    dnd: "Do Not Disturb",
+    conversations: "Conversations",
+    new_conversation: "New Conversation…",
+    no_conversations: "No Recent Conversations",
+    quit_confirm: "Quit Fluux Messenger?",
+    pending_messages: "Some messages are still waiting to be sent.",
+    active_call: "A call is in progress and will be ended.",
+    cancel: "Cancel",
+};
+
+const CS: ShellStrings = ShellStrings {
+    about: "O aplikaci Fluux Messenger",
+    services: "Služby",
+    hide: "Skrýt Fluux Messenger",
+    hide_others: "Skrýt ostatní",
+    show_all: "Zobrazit vše",
+    quit_app: "Ukončit Fluux Messenger",
+    edit: "Úpravy",
+    undo: "Odvolat",
+    redo: "Znovu",
+    cut: "Vyjmout",
+    copy: "Kopírovat",
+    paste: "Vložit",
+    select_all: "Vybrat vše",
+    view: "Zobrazení",
+    toggle_full_screen: "Zapnout/vypnout celou obrazovku",
+    window: "Okno",
+    minimize: "Minimalizovat",
+    zoom: "Zvětšit",
+    close_window: "Zavřít okno",
+    help: "Nápověda",
+    github: "Fluux Messenger na GitHubu",
+    report_issue: "Nahlásit problém…",
+    reveal_logs: "Zobrazit protokoly ve Finderu",
+    tray_show: "Zobrazit Fluux",
+    tray_open_logs: "Otevřít složku protokolů",
+    tray_quit: "Ukončit",
+    status: "Stav",
+    available: "Dostupný",
+    away: "Pryč",
+    dnd: "Nerušit",
+    conversations: "Konverzace",
+    new_conversation: "Nová konverzace…",
+    no_conversations: "Žádné nedávné konverzace",
+    quit_confirm: "Ukončit Fluux Messenger?",
+    pending_messages: "Některé zprávy ještě čekají na odeslání.",
+    active_call: "Probíhá hovor, který bude ukončen.",
+    cancel: "Zrušit",
+};
+
+const DA: ShellStrings = ShellStrings {
+    about: "Om Fluux Messenger",
+    services: "Tjenester",
+    hide: "Skjul Fluux Messenger",
+    hide_others: "Skjul andre",
+    show_all: "Vis alle",
+    quit_app: "Slut Fluux Messenger",
+    edit: "Rediger",
+    undo: "Fortryd",
+    redo: "Annuller fortryd",
+    cut: "Klip",
+    copy: "Kopier",
+    paste: "Sæt ind",
+    select_all: "Vælg alle",
+    view: "Vis",
+    toggle_full_screen: "Fuld skærm til/fra",
+    window: "Vindue",
+    minimize: "Minimer",
+    zoom: "Zoom",
+    close_window: "Luk vindue",
+    help: "Hjælp",
+    github: "Fluux Messenger på GitHub",
+    report_issue: "Rapporter et problem…",
+    reveal_logs: "Vis logfiler i Finder",
+    tray_show: "Vis Fluux",
+    tray_open_logs: "Åbn logfilmappe",
+    tray_quit: "Afslut",
+    status: "Status",
+    available: "Tilgængelig",
+    away: "Væk",
+    dnd: "Forstyr ikke",
+    conversations: "Samtaler",
+    new_conversation: "Ny samtale…",
+    no_conversations: "Ingen seneste samtaler",
+    quit_confirm: "Slut Fluux Messenger?",
+    pending_messages: "Nogle beskeder venter stadig på at blive sendt.",
+    active_call: "Et opkald er i gang og vil blive afsluttet.",
+    cancel: "Annuller",
+};
+
+const DE: ShellStrings = ShellStrings {
+    about: "Über Fluux Messenger",
+    services: "Dienste",
+    hide: "Fluux Messenger ausblenden",
+    hide_others: "Andere ausblenden",
+    show_all: "Alle einblenden",
+    quit_app: "Fluux Messenger beenden",
+    edit: "Bearbeiten",
+    undo: "Widerrufen",
+    redo: "Wiederholen",
+    cut: "Ausschneiden",
+    copy: "Kopieren",
+    paste: "Einsetzen",
+    select_all: "Alles auswählen",
+    view: "Darstellung",
+    toggle_full_screen: "Vollbild ein/aus",
+    window: "Fenster",
+    minimize: "Im Dock ablegen",
+    zoom: "Zoomen",
+    close_window: "Fenster schließen",
+    help: "Hilfe",
+    github: "Fluux Messenger auf GitHub",
+    report_issue: "Problem melden …",
+    reveal_logs: "Protokolle im Finder zeigen",
+    tray_show: "Fluux anzeigen",
+    tray_open_logs: "Protokollordner öffnen",
+    tray_quit: "Beenden",
+    status: "Status",
+    available: "Verfügbar",
+    away: "Abwesend",
+    dnd: "Nicht stören",
+    conversations: "Unterhaltungen",
+    new_conversation: "Neue Unterhaltung …",
+    no_conversations: "Keine letzten Unterhaltungen",
+    quit_confirm: "Fluux Messenger beenden?",
+    pending_messages: "Einige Nachrichten wurden noch nicht gesendet.",
+    active_call: "Ein Anruf ist aktiv und wird beendet.",
+    cancel: "Abbrechen",
+};
+
+const ES: ShellStrings = ShellStrings {
+    about: "Acerca de Fluux Messenger",
+    services: "Servicios",
+    hide: "Ocultar Fluux Messenger",
+    hide_others: "Ocultar otros",
+    show_all: "Mostrar todo",
+    quit_app: "Salir de Fluux Messenger",
+    edit: "Edición",
+    undo: "Deshacer",
+    redo: "Rehacer",
+    cut: "Cortar",
+    copy: "Copiar",
+    paste: "Pegar",
+    select_all: "Seleccionar todo",
+    view: "Visualización",
+    toggle_full_screen: "Activar/desactivar pantalla completa",
+    window: "Ventana",
+    minimize: "Minimizar",
+    zoom: "Zoom",
+    close_window: "Cerrar ventana",
+    help: "Ayuda",
+    github: "Fluux Messenger en GitHub",
+    report_issue: "Reportar un problema…",
+    reveal_logs: "Mostrar registros en el Finder",
+    tray_show: "Mostrar Fluux",
+    tray_open_logs: "Abrir carpeta de registros",
+    tray_quit: "Salir",
+    status: "Estado",
+    available: "Disponible",
+    away: "Ausente",
+    dnd: "No molestar",
+    conversations: "Conversaciones",
+    new_conversation: "Nueva conversación…",
+    no_conversations: "No hay conversaciones recientes",
+    quit_confirm: "¿Salir de Fluux Messenger?",
+    pending_messages: "Algunos mensajes aún están pendientes de envío.",
+    active_call: "Hay una llamada en curso que se finalizará.",
+    cancel: "Cancelar",
+};
+
+const FI: ShellStrings = ShellStrings {
+    about: "Tietoja: Fluux Messenger",
+    services: "Palvelut",
+    hide: "Kätke Fluux Messenger",
+    hide_others: "Kätke muut",
+    show_all: "Näytä kaikki",
+    quit_app: "Lopeta Fluux Messenger",
+    edit: "Muokkaa",
+    undo: "Kumoa",
+    redo: "Tee uudelleen",
+    cut: "Leikkaa",
+    copy: "Kopioi",
+    paste: "Sijoita",
+    select_all: "Valitse kaikki",
+    view: "Näytä",
+    toggle_full_screen: "Koko näyttö päälle/pois",
+    window: "Ikkuna",
+    minimize: "Pienennä",
+    zoom: "Zoomaa",
+    close_window: "Sulje ikkuna",
+    help: "Ohje",
+    github: "Fluux Messenger GitHubissa",
+    report_issue: "Ilmoita ongelmasta…",
+    reveal_logs: "Näytä lokit Finderissa",
+    tray_show: "Näytä Fluux",
+    tray_open_logs: "Avaa lokikansio",
+    tray_quit: "Lopeta",
+    status: "Tila",
+    available: "Tavoitettavissa",
+    away: "Poissa",
+    dnd: "Älä häiritse",
+    conversations: "Keskustelut",
+    new_conversation: "Uusi keskustelu…",
+    no_conversations: "Ei viimeaikaisia keskusteluja",
+    quit_confirm: "Lopetetaanko Fluux Messenger?",
+    pending_messages: "Joitakin viestejä ei ole vielä lähetetty.",
+    active_call: "Puhelu on käynnissä ja se päätetään.",
+    cancel: "Kumoa",
+};
+
+const FR: ShellStrings = ShellStrings {
+    about: "À propos de Fluux Messenger",
+    services: "Services",
+    hide: "Masquer Fluux Messenger",
+    hide_others: "Masquer les autres",
+    show_all: "Tout afficher",
+    quit_app: "Quitter Fluux Messenger",
+    edit: "Édition",
+    undo: "Annuler",
+    redo: "Rétablir",
+    cut: "Couper",
+    copy: "Copier",
+    paste: "Coller",
+    select_all: "Tout sélectionner",
+    view: "Présentation",
+    toggle_full_screen: "Activer/désactiver le plein écran",
+    window: "Fenêtre",
+    minimize: "Placer dans le Dock",
+    zoom: "Réduire/agrandir",
+    close_window: "Fermer la fenêtre",
+    help: "Aide",
+    github: "Fluux Messenger sur GitHub",
+    report_issue: "Signaler un problème…",
+    reveal_logs: "Afficher les journaux dans le Finder",
+    tray_show: "Afficher Fluux",
+    tray_open_logs: "Ouvrir le dossier des journaux",
+    tray_quit: "Quitter",
+    status: "Statut",
+    available: "Disponible",
+    away: "Absent",
+    dnd: "Ne pas déranger",
+    conversations: "Conversations",
+    new_conversation: "Nouvelle conversation…",
+    no_conversations: "Aucune conversation récente",
+    quit_confirm: "Quitter Fluux Messenger ?",
+    pending_messages: "Certains messages n’ont pas encore été envoyés.",
+    active_call: "Un appel est en cours et sera interrompu.",
+    cancel: "Annuler",
+};
+
+const IT: ShellStrings = ShellStrings {
+    about: "Informazioni su Fluux Messenger",
+    services: "Servizi",
+    hide: "Nascondi Fluux Messenger",
+    hide_others: "Nascondi altre",
+    show_all: "Mostra tutte",
+    quit_app: "Esci da Fluux Messenger",
+    edit: "Composizione",
+    undo: "Annulla",
+    redo: "Ripeti",
+    cut: "Taglia",
+    copy: "Copia",
+    paste: "Incolla",
+    select_all: "Seleziona tutto",
+    view: "Vista",
+    toggle_full_screen: "Attiva/disattiva schermo intero",
+    window: "Finestra",
+    minimize: "Contrai",
+    zoom: "Ridimensiona",
+    close_window: "Chiudi finestra",
+    help: "Aiuto",
+    github: "Fluux Messenger su GitHub",
+    report_issue: "Segnala un problema…",
+    reveal_logs: "Mostra registri nel Finder",
+    tray_show: "Mostra Fluux",
+    tray_open_logs: "Apri cartella dei registri",
+    tray_quit: "Esci",
+    status: "Stato",
+    available: "Disponibile",
+    away: "Assente",
+    dnd: "Non disturbare",
+    conversations: "Conversazioni",
+    new_conversation: "Nuova conversazione…",
+    no_conversations: "Nessuna conversazione recente",
+    quit_confirm: "Uscire da Fluux Messenger?",
+    pending_messages: "Alcuni messaggi non sono ancora stati inviati.",
+    active_call: "È in corso una chiamata che verrà terminata.",
+    cancel: "Annulla",
+};
+
+const NB: ShellStrings = ShellStrings {
+    about: "Om Fluux Messenger",
+    services: "Tjenester",
+    hide: "Skjul Fluux Messenger",
+    hide_others: "Skjul andre",
+    show_all: "Vis alle",
+    quit_app: "Avslutt Fluux Messenger",
+    edit: "Rediger",
+    undo: "Angre",
+    redo: "Gjør om",
+    cut: "Klipp ut",
+    copy: "Kopier",
+    paste: "Lim inn",
+    select_all: "Merk alt",
+    view: "Vis",
+    toggle_full_screen: "Fullskjerm av/på",
+    window: "Vindu",
+    minimize: "Minimer",
+    zoom: "Zoom",
+    close_window: "Lukk vindu",
+    help: "Hjelp",
+    github: "Fluux Messenger på GitHub",
+    report_issue: "Rapporter et problem…",
+    reveal_logs: "Vis logger i Finder",
+    tray_show: "Vis Fluux",
+    tray_open_logs: "Åpne loggmappen",
+    tray_quit: "Avslutt",
+    status: "Status",
+    available: "Tilgjengelig",
+    away: "Borte",
+    dnd: "Ikke forstyrr",
+    conversations: "Samtaler",
+    new_conversation: "Ny samtale…",
+    no_conversations: "Ingen nylige samtaler",
+    quit_confirm: "Avslutte Fluux Messenger?",
+    pending_messages: "Noen meldinger venter fortsatt på å bli sendt.",
+    active_call: "En samtale pågår og vil bli avsluttet.",
+    cancel: "Avbryt",
+};
+
+const NL: ShellStrings = ShellStrings {
+    about: "Over Fluux Messenger",
+    services: "Voorzieningen",
+    hide: "Verberg Fluux Messenger",
+    hide_others: "Verberg andere",
+    show_all: "Toon alles",
+    quit_app: "Stop Fluux Messenger",
+    edit: "Wijzig",
+    undo: "Herstel",
+    redo: "Opnieuw",
+    cut: "Knip",
+    copy: "Kopieer",
+    paste: "Plak",
+    select_all: "Selecteer alles",
+    view: "Weergave",
+    toggle_full_screen: "Schermvullende weergave aan/uit",
+    window: "Venster",
+    minimize: "Minimaliseer",
+    zoom: "Zoom",
+    close_window: "Sluit venster",
+    help: "Help",
+    github: "Fluux Messenger op GitHub",
+    report_issue: "Probleem melden…",
+    reveal_logs: "Toon logbestanden in Finder",
+    tray_show: "Toon Fluux",
+    tray_open_logs: "Logboekmap openen",
+    tray_quit: "Stop",
+    status: "Status",
+    available: "Beschikbaar",
+    away: "Afwezig",
+    dnd: "Niet storen",
+    conversations: "Gesprekken",
+    new_conversation: "Nieuw gesprek…",
+    no_conversations: "Geen recente gesprekken",
+    quit_confirm: "Fluux Messenger stoppen?",
+    pending_messages: "Sommige berichten zijn nog niet verzonden.",
+    active_call: "Er is een gesprek bezig dat wordt beëindigd.",
+    cancel: "Annuleer",
+};
+
+const PL: ShellStrings = ShellStrings {
+    about: "Fluux Messenger – informacje",
+    services: "Usługi",
+    hide: "Ukryj Fluux Messenger",
+    hide_others: "Ukryj pozostałe",
+    show_all: "Pokaż wszystko",
+    quit_app: "Zakończ Fluux Messenger",
+    edit: "Edycja",
+    undo: "Cofnij",
+    redo: "Przywróć",
+    cut: "Wytnij",
+    copy: "Kopiuj",
+    paste: "Wklej",
+    select_all: "Zaznacz wszystko",
+    view: "Widok",
+    toggle_full_screen: "Włącz/wyłącz pełny ekran",
+    window: "Okno",
+    minimize: "Minimalizuj",
+    zoom: "Powiększ",
+    close_window: "Zamknij okno",
+    help: "Pomoc",
+    github: "Fluux Messenger na GitHubie",
+    report_issue: "Zgłoś problem…",
+    reveal_logs: "Pokaż dzienniki w Finderze",
+    tray_show: "Pokaż Fluux",
+    tray_open_logs: "Otwórz folder dzienników",
+    tray_quit: "Zakończ",
+    status: "Status",
+    available: "Dostępny",
+    away: "Zaraz wracam",
+    dnd: "Nie przeszkadzać",
+    conversations: "Rozmowy",
+    new_conversation: "Nowa rozmowa…",
+    no_conversations: "Brak ostatnich rozmów",
+    quit_confirm: "Zakończyć Fluux Messenger?",
+    pending_messages: "Niektóre wiadomości nie zostały jeszcze wysłane.",
+    active_call: "Trwa połączenie, które zostanie zakończone.",
+    cancel: "Anuluj",
+};
+
+const PT: ShellStrings = ShellStrings {
+    about: "Acerca do Fluux Messenger",
+    services: "Serviços",
+    hide: "Ocultar Fluux Messenger",
+    hide_others: "Ocultar outros",
+    show_all: "Mostrar tudo",
+    quit_app: "Sair do Fluux Messenger",
+    edit: "Edição",
+    undo: "Desfazer",
+    redo: "Refazer",
+    cut: "Cortar",
+    copy: "Copiar",
+    paste: "Colar",
+    select_all: "Selecionar tudo",
+    view: "Visualização",
+    toggle_full_screen: "Ativar/desativar ecrã completo",
+    window: "Janela",
+    minimize: "Minimizar",
+    zoom: "Zoom",
+    close_window: "Fechar janela",
+    help: "Ajuda",
+    github: "Fluux Messenger no GitHub",
+    report_issue: "Reportar um problema…",
+    reveal_logs: "Mostrar registos no Finder",
+    tray_show: "Mostrar Fluux",
+    tray_open_logs: "Abrir pasta de registos",
+    tray_quit: "Sair",
+    status: "Estado",
+    available: "Disponível",
+    away: "Ausente",
+    dnd: "Não incomodar",
+    conversations: "Conversas",
+    new_conversation: "Nova conversa…",
+    no_conversations: "Sem conversas recentes",
+    quit_confirm: "Sair do Fluux Messenger?",
+    pending_messages: "Algumas mensagens ainda não foram enviadas.",
+    active_call: "Está a decorrer uma chamada que será terminada.",
+    cancel: "Cancelar",
+};
+
+const RU: ShellStrings = ShellStrings {
+    about: "О программе Fluux Messenger",
+    services: "Службы",
+    hide: "Скрыть Fluux Messenger",
+    hide_others: "Скрыть остальные",
+    show_all: "Показать все",
+    quit_app: "Завершить Fluux Messenger",
+    edit: "Правка",
+    undo: "Отменить",
+    redo: "Повторить",
+    cut: "Вырезать",
+    copy: "Скопировать",
+    paste: "Вставить",
+    select_all: "Выбрать все",
+    view: "Вид",
+    toggle_full_screen: "Включить/выключить полноэкранный режим",
+    window: "Окно",
+    minimize: "Свернуть",
+    zoom: "Изменить масштаб",
+    close_window: "Закрыть окно",
+    help: "Справка",
+    github: "Fluux Messenger на GitHub",
+    report_issue: "Сообщить об ошибке…",
+    reveal_logs: "Показать журналы в Finder",
+    tray_show: "Показать Fluux",
+    tray_open_logs: "Открыть папку журналов",
+    tray_quit: "Выход",
+    status: "Статус",
+    available: "В сети",
+    away: "Отошёл",
+    dnd: "Не беспокоить",
+    conversations: "Беседы",
+    new_conversation: "Новая беседа…",
+    no_conversations: "Нет недавних бесед",
+    quit_confirm: "Завершить Fluux Messenger?",
+    pending_messages: "Некоторые сообщения ещё не отправлены.",
+    active_call: "Идёт звонок, он будет завершён.",
+    cancel: "Отменить",
+};
+
+const SV: ShellStrings = ShellStrings {
+    about: "Om Fluux Messenger",
+    services: "Tjänster",
+    hide: "Göm Fluux Messenger",
+    hide_others: "Göm övriga",
+    show_all: "Visa alla",
+    quit_app: "Avsluta Fluux Messenger",
+    edit: "Redigera",
+    undo: "Ångra",
+    redo: "Gör om",
+    cut: "Klipp ut",
+    copy: "Kopiera",
+    paste: "Klistra in",
+    select_all: "Markera allt",
+    view: "Visa",
+    toggle_full_screen: "Helskärm på/av",
+    window: "Fönster",
+    minimize: "Minimera",
+    zoom: "Zooma",
+    close_window: "Stäng fönster",
+    help: "Hjälp",
+    github: "Fluux Messenger på GitHub",
+    report_issue: "Rapportera ett problem…",
+    reveal_logs: "Visa loggar i Finder",
+    tray_show: "Visa Fluux",
+    tray_open_logs: "Öppna loggmappen",
+    tray_quit: "Avsluta",
+    status: "Status",
+    available: "Tillgänglig",
+    away: "Borta",
+    dnd: "Stör ej",
+    conversations: "Konversationer",
+    new_conversation: "Ny konversation…",
+    no_conversations: "Inga senaste konversationer",
+    quit_confirm: "Avsluta Fluux Messenger?",
+    pending_messages: "Vissa meddelanden har inte skickats ännu.",
+    active_call: "Ett samtal pågår och kommer att avslutas.",
+    cancel: "Avbryt",
+};
+
+const UK: ShellStrings = ShellStrings {
+    about: "Про Fluux Messenger",
+    services: "Служби",
+    hide: "Сховати Fluux Messenger",
+    hide_others: "Сховати інші",
+    show_all: "Показати всі",
+    quit_app: "Закрити Fluux Messenger",
+    edit: "Редагування",
+    undo: "Відмінити",
+    redo: "Повторити",
+    cut: "Вирізати",
+    copy: "Копіювати",
+    paste: "Вставити",
+    select_all: "Вибрати все",
+    view: "Перегляд",
+    toggle_full_screen: "Увімкнути/вимкнути повноекранний режим",
+    window: "Вікно",
+    minimize: "Згорнути",
+    zoom: "Масштаб",
+    close_window: "Закрити вікно",
+    help: "Довідка",
+    github: "Fluux Messenger на GitHub",
+    report_issue: "Повідомити про проблему…",
+    reveal_logs: "Показати журнали у Finder",
+    tray_show: "Показати Fluux",
+    tray_open_logs: "Відкрити теку журналів",
+    tray_quit: "Вийти",
+    status: "Статус",
+    available: "У мережі",
+    away: "Відійшов",
+    dnd: "Не турбувати",
+    conversations: "Розмови",
+    new_conversation: "Нова розмова…",
+    no_conversations: "Немає недавніх розмов",
+    quit_confirm: "Закрити Fluux Messenger?",
+    pending_messages: "Деякі повідомлення ще не надіслано.",
+    active_call: "Триває дзвінок, його буде завершено.",
+    cancel: "Скасувати",
+};
+
+const ZH_CN: ShellStrings = ShellStrings {
+    about: "关于 Fluux Messenger",
+    services: "服务",
+    hide: "隐藏 Fluux Messenger",
+    hide_others: "隐藏其他",
+    show_all: "全部显示",
+    quit_app: "退出 Fluux Messenger",
+    edit: "编辑",
+    undo: "撤销",
+    redo: "重做",
+    cut: "剪切",
+    copy: "拷贝",
+    paste: "粘贴",
+    select_all: "全选",
+    view: "显示",
+    toggle_full_screen: "切换全屏",
+    window: "窗口",
+    minimize: "最小化",
+    zoom: "缩放",
+    close_window: "关闭窗口",
+    help: "帮助",
+    github: "GitHub 上的 Fluux Messenger",
+    report_issue: "报告问题…",
+    reveal_logs: "在访达中显示日志",
+    tray_show: "显示 Fluux",
+    tray_open_logs: "打开日志文件夹",
+    tray_quit: "退出",
+    status: "状态",
+    available: "在线",
+    away: "离开",
+    dnd: "请勿打扰",
+    conversations: "会话",
+    new_conversation: "新建会话…",
+    no_conversations: "没有最近的会话",
+    quit_confirm: "退出 Fluux Messenger？",
+    pending_messages: "部分消息尚未发送。",
+    active_call: "通话正在进行，退出将结束通话。",
+    cancel: "取消",
+};
+
+/// Languages with a catalog, as returned by [`normalize`].
+const CATALOGS: [(&str, &ShellStrings); 16] = [
+    ("en", &EN),
+    ("cs", &CS),
+    ("da", &DA),
+    ("de", &DE),
+    ("es", &ES),
+    ("fi", &FI),
+    ("fr", &FR),
+    ("it", &IT),
+    ("nb", &NB),
+    ("nl", &NL),
+    ("pl", &PL),
+    ("pt", &PT),
+    ("ru", &RU),
+    ("sv", &SV),
+    ("uk", &UK),
+    ("zh-CN", &ZH_CN),
+];
+
+/// Map a BCP 47 tag (`fr-FR`, `pt_BR`, `zh-Hans`, `no`) to a catalog key,
+/// or `"en"` when no catalog matches.
+pub fn normalize(tag: &str) -> &'static str {
+    let tag = tag.trim().replace('_', "-").to_ascii_lowercase();
+    let primary = tag.split('-').next().unwrap_or_default();
+    let key = match primary {
+        "zh" => {
+            // Only Simplified Chinese is translated; Traditional gets English
+            // rather than the wrong script.
+            let traditional = tag
+                .split('-')
+                .skip(1)
+                .any(|part| matches!(part, "hant" | "tw" | "hk" | "mo"));
+            if traditional {
+                "en"
+            } else {
+                "zh-CN"
+            }
+        }
+        "no" | "nn" => "nb",
+        other => other,
+    };
+    CATALOGS
+        .iter()
+        .find(|(lang, _)| *lang == key)
+        .map_or("en", |(lang, _)| lang)
+}
+
+/// Labels for `lang`, falling back to English.
+pub fn strings(lang: &str) -> &'static ShellStrings {
+    let key = normalize(lang);
+    CATALOGS
+        .iter()
+        .find(|(lang, _)| *lang == key)
+        .map_or(&EN, |(_, strings)| strings)
+}
+
+/// Language the native menus are currently built in.
+pub struct UiLanguage(Mutex<&'static str>);
+
+impl UiLanguage {
+    /// Start from the system locale; the frontend overrides it once loaded.
+    pub fn from_system() -> Self {
+        Self(Mutex::new(normalize(
+            &sys_locale::get_locale().unwrap_or_default(),
+        )))
+    }
+
+    pub fn get(&self) -> &'static str {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set(&self, lang: &'static str) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = lang;
+    }
+}
+
+/// The tray menu: "show" and "quit" everywhere, plus "show_logs" on
+/// Windows, where the tray is the only shell entry point to the logs.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+pub fn build_tray_menu<R: tauri::Runtime>(
+    app: &impl tauri::Manager<R>,
+    s: &ShellStrings,
+) -> tauri::Result<tauri::menu::Menu<R>> {
+    use tauri::menu::{Menu, MenuItem};
+
+    let show_item = MenuItem::with_id(app, "show", s.tray_show, true, None::<&str>)?;
+    let quit_item = MenuItem::with_id(app, "quit", s.tray_quit, true, None::<&str>)?;
+    #[cfg(target_os = "windows")]
+    {
+        let show_logs_item =
+            MenuItem::with_id(app, "show_logs", s.tray_open_logs, true, None::<&str>)?;
+        Menu::with_items(app, &[&show_item, &show_logs_item, &quit_item])
+    }
+    #[cfg(target_os = "linux")]
+    {
+        Menu::with_items(app, &[&show_item, &quit_item])
+    }
+}
+
+/// Rebuild the native menus in `lang` (a BCP 47 tag from the frontend).
+/// Returns the catalog actually used, `"en"` when `lang` has none.
+#[tauri::command]
+pub fn set_ui_language(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, UiLanguage>,
+    lang: String,
+) -> Result<String, String> {
+    let resolved = normalize(&lang);
+    state.set(resolved);
+
+    #[cfg(target_os = "macos")]
+    crate::app_menu::rebuild(&app)?;
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    {
+        // No tray (e.g. it failed to build): nothing to relabel.
+        if let Some(tray) = app.tray_by_id(crate::MAIN_TRAY_ID) {
+            let menu = build_tray_menu(&app, strings(resolved)).map_err(|e| e.to_string())?;
+            tray.set_menu(Some(menu)).map_err(|e| e.to_string())?;
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    let _ = app;
+
+    Ok(resolved.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_locale_tags() {
+        assert_eq!(normalize("fr-FR"), "fr");
+        assert_eq!(normalize("pt_BR"), "pt");
+        assert_eq!(normalize("DE"), "de");
+        assert_eq!(normalize("zh-Hans-CN"), "zh-CN");
+        assert_eq!(normalize("zh-CN"), "zh-CN");
+        assert_eq!(normalize("zh-TW"), "en");
+        assert_eq!(normalize("no"), "nb");
+        assert_eq!(normalize("nb-NO"), "nb");
+        assert_eq!(normalize("ja"), "en");
+        assert_eq!(normalize(""), "en");
+    }
+
+    #[test]
+    fn unknown_languages_fall_back_to_english() {
+        assert_eq!(strings("tlh").quit_app, EN.quit_app);
+        assert_eq!(strings("de-AT").edit, "Bearbeiten");
+    }
+
+    #[test]
+    fn every_catalog_labels_every_item() {
+        for (lang, s) in CATALOGS {
+            let labels = [
+                s.about,
+                s.services,
+                s.hide,
+                s.hide_others,
+                s.show_all,
+                s.quit_app,
+                s.edit,
+                s.undo,
+                s.redo,
+                s.cut,
+                s.copy,
+                s.paste,
+                s.select_all,
+                s.view,
+                s.toggle_full_screen,
+                s.window,
+                s.minimize,
+                s.zoom,
+                s.close_window,
+                s.help,
+                s.github,
+                s.report_issue,
+                s.reveal_logs,
+                s.tray_show,
+                s.tray_open_logs,
+                s.tray_quit,
+                s.status,
+                s.available,
+                s.away,
+                s.dnd,
+                s.conversations,
+                s.new_conversation,
+                s.no_conversations,
+                s.quit_confirm,
+                s.pending_messages,
+                s.active_call,
+                s.cancel,
+            ];
+            assert!(labels.iter().all(|l| !l.trim().is_empty()), "{lang}");
+        }
+    }
+}
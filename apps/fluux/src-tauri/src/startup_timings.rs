@@ -0,0 +1,178 @@
+//! Startup profile: when each phase of launching began and how long it took,
+//! measured from the start of `main`.
+//!
+//! Phases are recorded once, the first time they complete: environment
+//! setup, tracing init, the Tauri build (with the `setup` hook inside it),
+//! the first page load, the first proxy start and the first bound session.
+//! Each is logged as it ends. [`get_startup_timings`] returns them and
+//! [`export_startup_trace`] writes them in the Chrome trace event format,
+//! which `chrome://tracing` and Perfetto open.
+
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::info;
+
+pub const ENV_SETUP: &str = "env_setup";
+pub const TRACING_INIT: &str = "tracing_init";
+pub const TAURI_BUILD: &str = "tauri_build";
+pub const SETUP_HOOK: &str = "setup_hook";
+pub const PAGE_LOAD: &str = "page_load";
+pub const PROXY_START: &str = "proxy_start";
+pub const FIRST_CONNECT: &str = "first_connect";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Phase {
+    pub name: &'static str,
+    /// Milliseconds from the start of `main`.
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+#[derive(Default)]
+struct Timings {
+    /// Begun, not yet ended.
+    open: HashMap<&'static str, Instant>,
+    done: Vec<Phase>,
+}
+
+static ORIGIN: OnceLock<Instant> = OnceLock::new();
+static TIMINGS: Mutex<Option<Timings>> = Mutex::new(None);
+
+fn origin() -> Instant {
+    *ORIGIN.get_or_init(Instant::now)
+}
+
+fn millis(from: Instant, to: Instant) -> f64 {
+    to.saturating_duration_since(from).as_secs_f64() * 1000.0
+}
+
+fn with_timings<R>(f: impl FnOnce(&mut Timings) -> R) -> R {
+    let mut guard = TIMINGS.lock().unwrap_or_else(|e| e.into_inner());
+    f(guard.get_or_insert_with(Timings::default))
+}
+
+/// Start the clock. First thing in `main`.
+pub fn init() {
+    origin();
+}
+
+/// A phase begins. Ignored once the phase has been recorded, or while it is
+/// already under way.
+pub fn begin(name: &'static str) {
+    let now = Instant::now();
+    with_timings(|t| {
+        if !t.done.iter().any(|p| p.name == name) {
+            t.open.entry(name).or_insert(now);
+        }
+    });
+}
+
+/// A phase ends; recorded the first time only.
+pub fn end(name: &'static str) {
+    let now = Instant::now();
+    let phase = with_timings(|t| {
+        let start = t.open.remove(name)?;
+        let phase = Phase {
+            name,
+            start_ms: millis(origin(), start),
+            duration_ms: millis(start, now),
+        };
+        t.done.push(phase.clone());
+        Some(phase)
+    });
+    if let Some(phase) = phase {
+        info!(
+            phase = phase.name,
+            start_ms = phase.start_ms.round(),
+            duration_ms = phase.duration_ms.round(),
+            "startup phase"
+        );
+    }
+}
+
+/// Ends its phase when dropped.
+#[must_use]
+pub struct Span(&'static str);
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        end(self.0);
+    }
+}
+
+/// Time the rest of the enclosing scope.
+pub fn span(name: &'static str) -> Span {
+    begin(name);
+    Span(name)
+}
+
+fn recorded() -> Vec<Phase> {
+    let mut phases = with_timings(|t| t.done.clone());
+    phases.sort_by(|a, b| a.start_ms.total_cmp(&b.start_ms));
+    phases
+}
+
+/// Chrome trace event format: one complete (`X`) event per phase, in
+/// microseconds.
+fn chrome_trace(phases: &[Phase]) -> serde_json::Value {
+    let events: Vec<_> = phases
+        .iter()
+        .map(|phase| {
+            json!({
+                "name": phase.name,
+                "cat": "startup",
+                "ph": "X",
+                "ts": (phase.start_ms * 1000.0).round(),
+                "dur": (phase.duration_ms * 1000.0).round(),
+                "pid": std::process::id(),
+                "tid": 1,
+            })
+        })
+        .collect();
+    json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+}
+
+/// Phases recorded so far, in start order.
+#[tauri::command]
+pub fn get_startup_timings() -> Vec<Phase> {
+    recorded()
+}
+
+/// Write the phases as a Chrome trace to `path`.
+#[tauri::command]
+pub fn export_startup_trace(path: String) -> Result<(), String> {
+    let bytes = serde_json::to_vec_pretty(&chrome_trace(&recorded()))
+        .map_err(|e| format!("Failed to encode the trace: {e}"))?;
+    std::fs::write(&path, bytes).map_err(|e| format!("Failed to write {path}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_are_recorded_once_and_exported() {
+        {
+            let _span = span("test_phase");
+        }
+        // A later run of the same phase doesn't replace the first.
+        begin("test_phase");
+        end("test_phase");
+        end("never_begun");
+        let phases: Vec<_> = recorded()
+            .into_iter()
+            .filter(|p| p.name.starts_with("test_") || p.name == "never_begun")
+            .collect();
+        assert_eq!(phases.len(), 1);
+
+        let trace = chrome_trace(&phases);
+        let event = &trace["traceEvents"][0];
+        assert_eq!(event["name"], "test_phase");
+        assert_eq!(event["ph"], "X");
+        assert!(event["dur"].as_f64().unwrap() >= 0.0);
+    }
+}
@@ -0,0 +1,385 @@
+//! Presence subscription requests (RFC 6121 §3), kept in the bridge.
+//!
+//! Every inbound `subscribe` is queued in `subscriptions.json` until it is
+//! answered, from [`respond_subscription`] or by the WebView sending
+//! `subscribed`/`unsubscribed` itself, or withdrawn by the requester. A
+//! request that arrives while the UI is closed or still loading is
+//! therefore never lost.
+//!
+//! The [`SubscriptionPolicy`] answers some requests without asking:
+//! requests from a domain already in the roster (or the account's own) can
+//! be accepted, and requests from JIDs not in the roster denied. Those are
+//! answered here and not relayed to the WebView, which gets a
+//! `subscription-auto-responded` event instead.
+
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tauri::Emitter;
+use tracing::{info, warn};
+
+const ROSTER_NS: &str = "jabber:iq:roster";
+const NICK_NS: &str = "http://jabber.org/protocol/nick";
+const CHANGED_EVENT: &str = "subscription-requests-changed";
+const AUTO_RESPONDED_EVENT: &str = "subscription-auto-responded";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingSubscription {
+    /// Bare JID of the requester.
+    pub jid: String,
+    /// Unix seconds of the latest request.
+    pub received_at: u64,
+    /// Nickname the requester offered (XEP-0172).
+    pub nick: Option<String>,
+    /// The request's `<status/>` text.
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SubscriptionPolicy {
+    /// Accept requests from a domain some roster contact (or the account
+    /// itself) is on.
+    pub accept_roster_domains: bool,
+    /// Deny requests from JIDs not in the roster, unless accepted above.
+    pub deny_unknown: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Stored {
+    policy: SubscriptionPolicy,
+    pending: Vec<PendingSubscription>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AutoResponse<'a> {
+    jid: &'a str,
+    accepted: bool,
+}
+
+static STORED: Mutex<Option<Stored>> = Mutex::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+fn stored() -> Stored {
+    STORED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Load the queue and policy from `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("subscriptions.json")) else {
+        return;
+    };
+    let loaded: Stored = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *STORED.lock().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `STORED` lock held, so saves follow the order of changes.
+fn persist(snapshot: Stored) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+/// Apply `change`; persists and returns the queue when it reports a change.
+fn update(change: impl FnOnce(&mut Stored) -> bool) -> Option<Vec<PendingSubscription>> {
+    let mut guard = STORED.lock().unwrap_or_else(|e| e.into_inner());
+    let stored = guard.get_or_insert_with(Stored::default);
+    if !change(stored) {
+        return None;
+    }
+    persist(stored.clone());
+    Some(stored.pending.clone())
+}
+
+fn publish(app: Option<&tauri::AppHandle>, pending: Option<Vec<PendingSubscription>>) {
+    if let (Some(app), Some(pending)) = (app, pending) {
+        let _ = app.emit(CHANGED_EVENT, &pending);
+    }
+}
+
+fn enqueue(request: PendingSubscription) -> Option<Vec<PendingSubscription>> {
+    update(|stored| {
+        stored.pending.retain(|p| p.jid != request.jid);
+        stored.pending.push(request);
+        true
+    })
+}
+
+fn dequeue(jid: &str) -> Option<Vec<PendingSubscription>> {
+    update(|stored| {
+        let before = stored.pending.len();
+        stored.pending.retain(|p| p.jid != jid);
+        stored.pending.len() != before
+    })
+}
+
+fn domain(jid: &str) -> &str {
+    let bare = bare_jid(jid);
+    bare.split_once('@').map_or(bare, |(_, domain)| domain)
+}
+
+/// The policy's answer to a request from `jid`: accept, deny, or ask the
+/// user (`None`).
+fn decide(
+    policy: SubscriptionPolicy,
+    jid: &str,
+    roster: &BTreeSet<String>,
+    own: Option<&str>,
+) -> Option<bool> {
+    let from_domain = domain(jid);
+    if policy.accept_roster_domains
+        && own
+            .into_iter()
+            .chain(roster.iter().map(String::as_str))
+            .any(|known| domain(known).eq_ignore_ascii_case(from_domain))
+    {
+        return Some(true);
+    }
+    if policy.deny_unknown && !roster.contains(jid) {
+        return Some(false);
+    }
+    None
+}
+
+fn presence(to: &str, kind: &str) -> Element {
+    Element::new("presence")
+        .with_attr("to", to)
+        .with_attr("type", kind)
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Bridge observer: queues requests, answers per the policy and mirrors
+/// the roster the policy needs. Registered with the bridge tap.
+#[derive(Default)]
+pub struct SubscriptionRequests {
+    /// Bare JIDs in the roster.
+    roster: Mutex<BTreeSet<String>>,
+}
+
+impl SubscriptionRequests {
+    fn roster(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.roster.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Roster result or push. Returns contacts we now share presence with.
+    fn learn_roster(&self, stanza: &Element) -> Vec<String> {
+        let Some(query) = stanza.child("query", Some(ROSTER_NS)) else {
+            return Vec::new();
+        };
+        let mut roster = self.roster();
+        if stanza.attr("type") == Some("result") {
+            roster.clear();
+        }
+        let mut approved = Vec::new();
+        for item in query.elements().filter(|e| e.local_name() == "item") {
+            let Some(jid) = item.attr("jid").map(|j| bare_jid(j).to_lowercase()) else {
+                continue;
+            };
+            match item.attr("subscription") {
+                Some("remove") => {
+                    roster.remove(&jid);
+                }
+                subscription => {
+                    if matches!(subscription, Some("from" | "both")) {
+                        approved.push(jid.clone());
+                    }
+                    roster.insert(jid);
+                }
+            }
+        }
+        approved
+    }
+
+    /// An inbound `subscribe`. Returns whether it was answered here.
+    fn on_request(&self, app: Option<&tauri::AppHandle>, stanza: &Element, from: &str) -> bool {
+        let own = session::own_bare_jid();
+        let decision = decide(stored().policy, from, &self.roster(), own.as_deref());
+        if let Some(accept) = decision {
+            let kind = if accept { "subscribed" } else { "unsubscribed" };
+            match session::send(presence(from, kind)) {
+                Ok(()) => {
+                    info!(jid = %from, accepted = accept, "subscriptions: answered per policy");
+                    if let Some(app) = app {
+                        let event = AutoResponse {
+                            jid: from,
+                            accepted: accept,
+                        };
+                        let _ = app.emit(AUTO_RESPONDED_EVENT, event);
+                    }
+                    publish(app, dequeue(from));
+                    return true;
+                }
+                Err(e) => warn!(error = %e, "subscriptions: auto-response failed, queuing"),
+            }
+        }
+        publish(
+            app,
+            enqueue(PendingSubscription {
+                jid: from.to_string(),
+                received_at: now_secs(),
+                nick: stanza.child("nick", Some(NICK_NS)).map(Element::text),
+                message: stanza
+                    .child("status", None)
+                    .map(Element::text)
+                    .filter(|s| !s.is_empty()),
+            }),
+        );
+        false
+    }
+}
+
+impl StanzaObserver for SubscriptionRequests {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        let peer = match ctx.direction {
+            Direction::Inbound => stanza.attr("from"),
+            Direction::Outbound => stanza.attr("to"),
+        }
+        .map(|jid| bare_jid(jid).to_lowercase());
+        match (ctx.direction, stanza.local_name(), stanza.attr("type")) {
+            (Direction::Inbound, "iq", Some("result" | "set")) => {
+                let own = session::own_bare_jid();
+                let from_own = peer.is_none() || peer == own.map(|j| j.to_lowercase());
+                if from_own {
+                    for jid in self.learn_roster(stanza) {
+                        publish(ctx.app, dequeue(&jid));
+                    }
+                }
+            }
+            (Direction::Inbound, "presence", Some("subscribe")) => {
+                if let Some(from) = peer {
+                    if self.on_request(ctx.app, stanza, &from) {
+                        return Verdict::Drop;
+                    }
+                }
+            }
+            // Withdrawn by the requester, or answered by the WebView.
+            (Direction::Inbound, "presence", Some("unsubscribe"))
+            | (Direction::Outbound, "presence", Some("subscribed" | "unsubscribed")) => {
+                if let Some(jid) = peer {
+                    publish(ctx.app, dequeue(&jid));
+                }
+            }
+            _ => {}
+        }
+        Verdict::Forward
+    }
+}
+
+#[tauri::command]
+pub fn list_pending_subscriptions() -> Vec<PendingSubscription> {
+    stored().pending
+}
+
+/// Answer the request from `jid`; with `add_back`, an accepted requester
+/// is asked for their presence in return.
+#[tauri::command]
+pub fn respond_subscription(
+    app: tauri::AppHandle,
+    jid: String,
+    accept: bool,
+    add_back: bool,
+) -> Result<(), String> {
+    let jid = bare_jid(&jid).to_lowercase();
+    if accept {
+        session::send(presence(&jid, "subscribed"))?;
+        if add_back {
+            session::send(presence(&jid, "subscribe"))?;
+        }
+    } else {
+        session::send(presence(&jid, "unsubscribed"))?;
+    }
+    publish(Some(&app), dequeue(&jid));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_subscription_policy() -> SubscriptionPolicy {
+    stored().policy
+}
+
+#[tauri::command]
+pub fn set_subscription_policy(policy: SubscriptionPolicy) -> SubscriptionPolicy {
+    update(|stored| {
+        stored.policy = policy;
+        true
+    });
+    policy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_accepts_known_domains_and_denies_strangers() {
+        let observer = SubscriptionRequests::default();
+        let roster = Element::parse(
+            "<iq type='result'><query xmlns='jabber:iq:roster'>\
+             <item jid='Bob@Partner.example' subscription='to'/>\
+             <item jid='carol@example.com' subscription='both'/>\
+             </query></iq>",
+        )
+        .unwrap();
+        assert_eq!(observer.learn_roster(&roster), ["carol@example.com"]);
+        let roster = observer.roster().clone();
+        let own = Some("alice@example.com");
+
+        let both = SubscriptionPolicy {
+            accept_roster_domains: true,
+            deny_unknown: true,
+        };
+        assert_eq!(
+            decide(both, "dave@partner.example", &roster, own),
+            Some(true)
+        );
+        assert_eq!(decide(both, "erin@example.com", &roster, own), Some(true));
+        assert_eq!(
+            decide(both, "spam@elsewhere.example", &roster, own),
+            Some(false)
+        );
+
+        let deny_only = SubscriptionPolicy {
+            deny_unknown: true,
+            ..Default::default()
+        };
+        assert_eq!(decide(deny_only, "bob@partner.example", &roster, own), None);
+        assert_eq!(
+            decide(deny_only, "dave@partner.example", &roster, own),
+            Some(false)
+        );
+        let manual = SubscriptionPolicy::default();
+        assert_eq!(decide(manual, "spam@elsewhere.example", &roster, own), None);
+
+        let push = Element::parse(
+            "<iq type='set'><query xmlns='jabber:iq:roster'>\
+             <item jid='bob@partner.example' subscription='remove'/>\
+             </query></iq>",
+        )
+        .unwrap();
+        observer.learn_roster(&push);
+        assert!(!observer.roster().contains("bob@partner.example"));
+        assert!(observer.roster().contains("carol@example.com"));
+    }
+}
@@ -0,0 +1,403 @@
+//! Opt-in anonymous telemetry: connection outcome counters, so the failure
+//! classes that are actually common in the field get fixed first.
+//!
+//! Off until the user turns it on ([`set_telemetry_enabled`]); nothing is
+//! counted or sent before that. While on, the connection supervisor's
+//! outcomes are counted:
+//!
+//! - sessions that came online, and how many of them were reconnects (any
+//!   but the first of the run);
+//! - failures by stage and error class (`tls-handshake/certificate-expired`,
+//!   `authenticating/not-authorized`…).
+//!
+//! Every [`BATCH_PERIOD`] the counters are closed into a [`Batch`] carrying
+//! only the app version, OS, architecture, the day and the features in use:
+//! no identifier, server, JID or error text. Batches wait in
+//! `telemetry-queue.json` until [`ENDPOINT`] accepts them; failed uploads are
+//! retried with exponential backoff, and at most [`MAX_QUEUED`] batches are
+//! kept. Turning telemetry off drops the counters and the queue.
+//!
+//! `FLUUX_TELEMETRY_URL` overrides the endpoint, for testing a collector.
+
+use crate::json_file::JsonFile;
+use crate::xmpp_proxy::supervisor::Stage;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const ENDPOINT: &str = "https://telemetry.fluux.io/v1/batches";
+const SCHEMA: u32 = 1;
+const BATCH_PERIOD: Duration = Duration::from_secs(6 * 3600);
+const TICK: Duration = Duration::from_secs(60);
+const MAX_QUEUED: usize = 28;
+const FIRST_BACKOFF: Duration = Duration::from_secs(15 * 60);
+const MAX_BACKOFF: Duration = Duration::from_secs(24 * 3600);
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest error class kept; anything longer or not a plain condition name
+/// is counted as `other`.
+const MAX_CLASS_LEN: usize = 48;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Counters {
+    /// Sessions that came online.
+    pub connects: u64,
+    /// Of those, sessions after the first of the run.
+    pub reconnects: u64,
+    /// `<stage>/<class>` → failures.
+    pub failures: BTreeMap<String, u64>,
+}
+
+impl Counters {
+    fn is_empty(&self) -> bool {
+        self.connects == 0 && self.failures.is_empty()
+    }
+}
+
+/// What is uploaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Batch {
+    pub schema: u32,
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    /// UTC day the batch was closed (`2026-10-15`).
+    pub day: String,
+    /// Optional features and whether they are on.
+    pub features: BTreeMap<String, bool>,
+    #[serde(flatten)]
+    pub counters: Counters,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TelemetrySettings {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Queue {
+    /// Counted, not yet closed into a batch.
+    pending: Counters,
+    batches: VecDeque<Batch>,
+}
+
+#[derive(Default)]
+struct State {
+    queue: Queue,
+    /// A session came online earlier in this run.
+    seen_online: bool,
+    /// The queue changed since it was last written.
+    dirty: bool,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+static SETTINGS_FILE: OnceLock<JsonFile> = OnceLock::new();
+static QUEUE_FILE: OnceLock<JsonFile> = OnceLock::new();
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(guard.get_or_insert_with(State::default))
+}
+
+/// Load the settings and queue from `dir`. Called from the Tauri `setup`
+/// hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(dir) = dir else {
+        return;
+    };
+    let settings_file = SETTINGS_FILE.get_or_init(|| JsonFile::pretty(dir.join("telemetry.json")));
+    let queue_file = QUEUE_FILE.get_or_init(|| JsonFile::new(dir.join("telemetry-queue.json")));
+    let read = |file: &JsonFile| std::fs::read(file.path()).ok();
+    let settings: TelemetrySettings = read(settings_file)
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    ENABLED.store(settings.enabled, Ordering::Relaxed);
+    if settings.enabled {
+        let queue: Queue = read(queue_file)
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        with_state(|state| state.queue = queue);
+    }
+}
+
+/// Called with the `STATE` lock held; see [`crate::json_file`].
+fn persist_settings(settings: TelemetrySettings) {
+    if let Some(file) = SETTINGS_FILE.get() {
+        file.save(settings);
+    }
+}
+
+/// Write the queue if it changed. Blocking.
+fn persist_queue() {
+    let Some(file) = QUEUE_FILE.get() else {
+        return;
+    };
+    let result = with_state(|state| {
+        if !state.dirty {
+            return Ok(());
+        }
+        state.dirty = false;
+        file.save_now(&state.queue)
+    });
+    if let Err(e) = result {
+        warn!(error = %e, "telemetry: failed to persist the queue");
+    }
+}
+
+fn count(f: impl FnOnce(&mut State)) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    with_state(|state| {
+        f(state);
+        state.dirty = true;
+    });
+}
+
+/// A session came online. Called by the connection supervisor.
+pub(crate) fn connected() {
+    count(|state| {
+        state.queue.pending.connects += 1;
+        if state.seen_online {
+            state.queue.pending.reconnects += 1;
+        }
+        state.seen_online = true;
+    });
+}
+
+/// A connection failed at `stage` with error class `class`. Called by the
+/// connection supervisor.
+pub(crate) fn connect_failed(stage: Stage, class: &str) {
+    count(|state| {
+        *state
+            .queue
+            .pending
+            .failures
+            .entry(failure_key(stage, class))
+            .or_default() += 1;
+    });
+}
+
+/// `<stage>/<class>`, with anything that isn't a plain condition name (which
+/// could carry server-provided text) counted as `other`.
+pub(crate) fn failure_key(stage: Stage, class: &str) -> String {
+    let plain = !class.is_empty()
+        && class.len() <= MAX_CLASS_LEN
+        && class
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    let stage = serde_json::to_value(stage)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default();
+    format!("{stage}/{}", if plain { class } else { "other" })
+}
+
+/// Optional features and whether they are on, with the remote feature
+/// flags as `flag:<name>`.
+fn features() -> BTreeMap<String, bool> {
+    let rich_presence = crate::rich_presence::settings();
+    [
+        ("managed", crate::managed_policy::current().managed),
+        ("privacyMode", crate::xmpp_proxy::privacy::enabled()),
+        ("nowPlaying", rich_presence.share_now_playing),
+        ("calendarDnd", rich_presence.calendar_dnd),
+    ]
+    .into_iter()
+    .map(|(name, on)| (name.to_string(), on))
+    .chain(
+        crate::feature_flags::flags()
+            .into_iter()
+            .map(|(name, on)| (format!("flag:{name}"), on)),
+    )
+    .collect()
+}
+
+fn close_batch(counters: Counters, features: BTreeMap<String, bool>) -> Batch {
+    Batch {
+        schema: SCHEMA,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        day: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+        features,
+        counters,
+    }
+}
+
+/// Close the pending counters into a batch, dropping the oldest batches
+/// beyond [`MAX_QUEUED`].
+fn roll(queue: &mut Queue, features: BTreeMap<String, bool>) {
+    if queue.pending.is_empty() {
+        return;
+    }
+    let counters = std::mem::take(&mut queue.pending);
+    queue.batches.push_back(close_batch(counters, features));
+    while queue.batches.len() > MAX_QUEUED {
+        queue.batches.pop_front();
+    }
+}
+
+fn next_backoff(current: Option<Duration>) -> Duration {
+    current.map_or(FIRST_BACKOFF, |d| (d * 2).min(MAX_BACKOFF))
+}
+
+/// POST the batches. Blocking.
+fn upload(batches: &[Batch]) -> Result<(), String> {
+    let url = std::env::var("FLUUX_TELEMETRY_URL").unwrap_or_else(|_| ENDPOINT.to_string());
+    let body = serde_json::to_vec(&serde_json::json!({ "batches": batches }))
+        .map_err(|e| format!("Failed to encode telemetry: {e}"))?;
+    let client = reqwest::blocking::Client::builder()
+        .timeout(UPLOAD_TIMEOUT)
+        .use_preconfigured_tls(crate::xmpp_proxy::tls_client_config()?)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))?;
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()
+        .map_err(|e| format!("Telemetry upload failed: {e}"))?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Telemetry upload failed: HTTP {}",
+            response.status()
+        ))
+    }
+}
+
+/// Close batches and upload them for the life of the app. Called from the
+/// Tauri `setup` hook.
+pub fn start() {
+    tauri::async_runtime::spawn(async move {
+        let mut period_start = Instant::now();
+        let mut backoff: Option<Duration> = None;
+        let mut retry_at = Instant::now();
+        loop {
+            tokio::time::sleep(TICK).await;
+            if !ENABLED.load(Ordering::Relaxed) {
+                period_start = Instant::now();
+                continue;
+            }
+            if period_start.elapsed() >= BATCH_PERIOD {
+                period_start = Instant::now();
+                let features = features();
+                with_state(|state| {
+                    roll(&mut state.queue, features);
+                    state.dirty = true;
+                });
+            }
+            let _ = tauri::async_runtime::spawn_blocking(persist_queue).await;
+            let batches: Vec<Batch> =
+                with_state(|state| state.queue.batches.iter().cloned().collect());
+            if batches.is_empty() || Instant::now() < retry_at {
+                continue;
+            }
+            let sent = batches.len();
+            let result = tauri::async_runtime::spawn_blocking(move || upload(&batches))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+            match result {
+                Ok(()) => {
+                    info!(batches = sent, "telemetry: uploaded");
+                    backoff = None;
+                    with_state(|state| {
+                        // Telemetry may have been turned off meanwhile.
+                        let sent = sent.min(state.queue.batches.len());
+                        state.queue.batches.drain(..sent);
+                        state.dirty = true;
+                    });
+                }
+                Err(e) => {
+                    let delay = next_backoff(backoff);
+                    backoff = Some(delay);
+                    retry_at = Instant::now() + delay;
+                    warn!(error = %e, retry_in_secs = delay.as_secs(), "telemetry: upload failed");
+                }
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_telemetry_settings() -> TelemetrySettings {
+    TelemetrySettings {
+        enabled: ENABLED.load(Ordering::Relaxed),
+    }
+}
+
+/// Opt in or out. Opting out drops everything counted and queued.
+#[tauri::command]
+pub fn set_telemetry_enabled(enabled: bool) {
+    with_state(|state| {
+        ENABLED.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            state.queue = Queue::default();
+            state.dirty = false;
+            if let Some(file) = QUEUE_FILE.get() {
+                let _ = std::fs::remove_file(file.path());
+            }
+        }
+        persist_settings(TelemetrySettings { enabled });
+    });
+}
+
+/// What would be sent next: the queued batches and the pending counters as
+/// a batch, so the user can see exactly what telemetry contains.
+#[tauri::command]
+pub fn preview_telemetry() -> Vec<Batch> {
+    let features = features();
+    with_state(|state| {
+        let mut batches: Vec<Batch> = state.queue.batches.iter().cloned().collect();
+        if !state.queue.pending.is_empty() {
+            batches.push(close_batch(state.queue.pending.clone(), features));
+        }
+        batches
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_are_keyed_without_free_text() {
+        assert_eq!(
+            failure_key(Stage::TlsHandshake, "certificate-expired"),
+            "tls-handshake/certificate-expired"
+        );
+        assert_eq!(
+            failure_key(Stage::Authenticating, "not-authorized"),
+            "authenticating/not-authorized"
+        );
+        assert_eq!(
+            failure_key(Stage::Connecting, "Connection to xmpp.example.org refused"),
+            "connecting/other"
+        );
+
+        let mut queue = Queue::default();
+        roll(&mut queue, BTreeMap::new());
+        assert!(queue.batches.is_empty());
+        for i in 0..MAX_QUEUED + 2 {
+            queue.pending.connects = i as u64 + 1;
+            roll(&mut queue, BTreeMap::new());
+        }
+        assert_eq!(queue.batches.len(), MAX_QUEUED);
+        assert_eq!(queue.batches[0].counters.connects, 3);
+        assert!(queue.pending.is_empty());
+
+        assert_eq!(next_backoff(None), FIRST_BACKOFF);
+        assert_eq!(next_backoff(Some(FIRST_BACKOFF)), FIRST_BACKOFF * 2);
+        assert_eq!(next_backoff(Some(MAX_BACKOFF)), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,230 @@
+//! Registry of the temp files the backend creates, and their deletion.
+//!
+//! Pasted images, voice recordings and other scratch files go under
+//! `<temp>/fluux-<kind>/` through [`path`], which records them. Decrypted
+//! media cached from encrypted attachments is recorded as sensitive. Every
+//! recorded file is deleted when the session ends (logout through
+//! `clear_temp_files`, or app exit); sensitive ones are overwritten first.
+//!
+//! The registry is persisted in `temp-files.json` as files are added, so
+//! what a crashed run left behind is deleted at the next startup. The app
+//! data directory is per profile, so one profile never sweeps another's
+//! files. Files in the temp directories older than `STALE_AFTER` are swept
+//! too, for leftovers no registry knows about.
+
+use crate::json_file::JsonFile;
+use std::collections::BTreeMap;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tauri::Manager;
+use tracing::{info, warn};
+
+/// Subdirectories of the system temp directory the backend writes to.
+const KINDS: [&str; 3] = ["clipboard", "voice", "uploads"];
+const STALE_AFTER: Duration = Duration::from_secs(7 * 24 * 3600);
+const OVERWRITE_CHUNK: usize = 64 * 1024;
+
+/// Recorded files, and whether each is sensitive.
+static TRACKED: Mutex<BTreeMap<PathBuf, bool>> = Mutex::new(BTreeMap::new());
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+fn tracked() -> std::sync::MutexGuard<'static, BTreeMap<PathBuf, bool>> {
+    TRACKED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn kind_dir(kind: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("fluux-{kind}"))
+}
+
+/// A path for a new temp file `name` of `kind` (one of `KINDS`), with its
+/// directory created. The file is recorded for deletion.
+pub fn path(kind: &str, name: &str) -> Result<PathBuf, String> {
+    if !KINDS.contains(&kind) {
+        return Err(format!("Unknown temp file kind: {kind}"));
+    }
+    let dir = kind_dir(kind);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create temp directory {}: {e}", dir.display()))?;
+    let path = dir.join(name);
+    track(&path, false);
+    Ok(path)
+}
+
+/// Record a file for deletion at session end. A `sensitive` file is
+/// overwritten before it is unlinked.
+pub fn track(path: &Path, sensitive: bool) {
+    let mut all = tracked();
+    let entry = all.entry(path.to_path_buf()).or_insert(sensitive);
+    *entry |= sensitive;
+    persist(&all);
+}
+
+/// Delete a recorded file now.
+pub fn remove(path: &Path) {
+    let sensitive = tracked().remove(path);
+    delete(path, sensitive.unwrap_or(false));
+    persist(&tracked());
+}
+
+fn delete(path: &Path, sensitive: bool) {
+    let result = if sensitive {
+        overwrite_and_remove(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    match result {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!(path = %path.display(), error = %e, "temp files: delete failed"),
+    }
+}
+
+/// Overwrite `path` with zeros, flush it to disk, then unlink it.
+///
+/// Best effort: on SSDs and copy-on-write filesystems (APFS, Btrfs) the
+/// old blocks may survive elsewhere; this removes the plaintext from the
+/// file itself and from anything that reads it in place.
+fn overwrite_and_remove(path: &Path) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(0))?;
+    let zeros = vec![0u8; OVERWRITE_CHUNK];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(OVERWRITE_CHUNK as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        left -= n as u64;
+    }
+    file.sync_all()?;
+    file.set_len(0)?;
+    drop(file);
+    std::fs::remove_file(path)
+}
+
+/// Delete every recorded file. Called at session end.
+pub fn clear() {
+    let mut all = tracked();
+    if all.is_empty() {
+        return;
+    }
+    for (path, sensitive) in all.iter() {
+        delete(path, *sensitive);
+    }
+    info!(files = all.len(), "temp files: cleared");
+    all.clear();
+    persist(&all);
+}
+
+/// Files in the temp directories untouched for `STALE_AFTER`.
+fn stale_files(now: SystemTime) -> Vec<PathBuf> {
+    KINDS
+        .iter()
+        .filter_map(|kind| std::fs::read_dir(kind_dir(kind)).ok())
+        .flat_map(|entries| entries.flatten())
+        .filter(|entry| {
+            entry.metadata().is_ok_and(|m| {
+                m.is_file()
+                    && m.modified().is_ok_and(|modified| {
+                        now.duration_since(modified).unwrap_or_default() > STALE_AFTER
+                    })
+            })
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Delete what the previous run recorded, and stale leftovers. Called from
+/// the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("temp-files.json")) else {
+        return;
+    };
+    let previous: BTreeMap<PathBuf, bool> = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    let _ = FILE.set(JsonFile::pretty(path));
+    let stale = stale_files(SystemTime::now());
+    if previous.is_empty() && stale.is_empty() {
+        return;
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        for (path, sensitive) in &previous {
+            delete(path, *sensitive);
+        }
+        for path in &stale {
+            delete(path, false);
+        }
+        info!(
+            recorded = previous.len(),
+            stale = stale.len(),
+            "temp files: swept leftovers"
+        );
+        persist(&tracked());
+    });
+}
+
+/// Called with the `TRACKED` lock held; see [`crate::json_file`]. Written
+/// synchronously: the exit path calls `clear` with no runtime left to run a
+/// task, and the registry is small.
+fn persist(all: &BTreeMap<PathBuf, bool>) {
+    let Some(file) = FILE.get() else {
+        return;
+    };
+    if let Err(e) = file.save_now(all) {
+        warn!(error = %e, "temp files: failed to persist registry");
+    }
+}
+
+/// Delete the session's temp files. The frontend calls this on logout.
+#[tauri::command]
+pub async fn clear_temp_files() -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(clear)
+        .await
+        .map_err(|e| format!("Temp file task failed: {e}"))
+}
+
+/// Overwrite and delete a file the app wrote: a recorded temp file or a
+/// file in the app cache. Other paths are refused.
+#[tauri::command]
+pub async fn secure_delete(app: tauri::AppHandle, path: PathBuf) -> Result<(), String> {
+    let cache = app
+        .path()
+        .app_cache_dir()
+        .map_err(|e| format!("No cache directory: {e}"))?;
+    let canonical = std::fs::canonicalize(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let ours = tracked().contains_key(&path)
+        || std::fs::canonicalize(&cache).is_ok_and(|cache| canonical.starts_with(cache));
+    if !ours {
+        return Err(format!("{} is not a file the app created", path.display()));
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        overwrite_and_remove(&canonical).map_err(|e| format!("Secure delete failed: {e}"))?;
+        let mut all = tracked();
+        all.remove(&path);
+        persist(&all);
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Temp file task failed: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sensitive_flag_sticks_and_removal_untracks() {
+        let path = std::env::temp_dir().join(format!("fluux-temp-test-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, vec![0xAB; OVERWRITE_CHUNK + 17]).unwrap();
+        track(&path, false);
+        track(&path, true);
+        assert_eq!(tracked().get(&path), Some(&true));
+        overwrite_and_remove(&path).unwrap();
+        assert!(!path.exists());
+        remove(&path);
+        assert!(!tracked().contains_key(&path));
+    }
+}
@@ -0,0 +1,600 @@
+//! Local automation triggers: run a webhook or a program when something
+//! happens in the chat.
+//!
+//! A [`Trigger`] pairs a [`Condition`] (a message from a given JID, a
+//! keyword in a message, the connection being lost) with an [`Action`]. The
+//! action gets the event through `{{placeholder}}` templates: `event`,
+//! `from`, `nick`, `conversation`, `body`, `reason` and `time` (RFC 3339).
+//!
+//! Nothing runs without the user's say-so: saving a trigger whose action
+//! has not been approved before shows a native consent dialog naming the
+//! exact URL or program, and only approved actions are ever executed, so a
+//! tampered `triggers.json` cannot start anything new. Programs are run
+//! directly, never through a shell, with each argument templated on its
+//! own; webhook templates insert values JSON-escaped.
+
+use crate::json_file::JsonFile;
+use crate::unread::UnreadCounters;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, resource, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use tracing::{info, warn};
+
+const RAN_EVENT: &str = "trigger-ran";
+
+/// A trigger fires at most once per this interval.
+const MIN_INTERVAL: Duration = Duration::from_secs(5);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// A program still running after this long is killed.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_TRIGGERS: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Condition {
+    /// A message from this bare JID, or in this room.
+    #[serde(rename_all = "camelCase")]
+    MessageFrom {
+        jid: String,
+    },
+    /// A message containing this word.
+    #[serde(rename_all = "camelCase")]
+    Keyword {
+        word: String,
+    },
+    ConnectionLost,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Action {
+    /// POST to `url`; the body is `template` rendered, or all the event's
+    /// values as a JSON object when there is none.
+    #[serde(rename_all = "camelCase")]
+    Webhook {
+        url: String,
+        template: Option<String>,
+    },
+    /// Run `program` (an absolute path) with `args` rendered.
+    #[serde(rename_all = "camelCase")]
+    Command { program: String, args: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trigger {
+    /// Assigned on first save when empty.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    pub condition: Condition,
+    pub action: Action,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Stored {
+    triggers: Vec<Trigger>,
+    /// Actions the user agreed to in the consent dialog.
+    approved: Vec<Action>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RanEvent<'a> {
+    id: &'a str,
+    name: &'a str,
+    error: Option<String>,
+}
+
+static STORED: Mutex<Option<Stored>> = Mutex::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+/// Last time each trigger fired, by id.
+static LAST_FIRED: Mutex<Option<HashMap<String, Instant>>> = Mutex::new(None);
+
+fn stored() -> Stored {
+    STORED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Load the triggers from `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("triggers.json")) else {
+        return;
+    };
+    let loaded: Stored = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *STORED.lock().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `STORED` lock held; see [`crate::json_file`].
+fn persist(snapshot: Stored) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+fn update(change: impl FnOnce(&mut Stored)) -> Vec<Trigger> {
+    let mut guard = STORED.lock().unwrap_or_else(|e| e.into_inner());
+    let stored = guard.get_or_insert_with(Stored::default);
+    change(stored);
+    persist(stored.clone());
+    stored.triggers.clone()
+}
+
+/// What happened, as the template placeholders see it.
+struct Event {
+    vars: Vec<(&'static str, String)>,
+}
+
+impl Event {
+    fn new(kind: &str) -> Self {
+        Event {
+            vars: vec![
+                ("event", kind.to_string()),
+                ("time", chrono::Utc::now().to_rfc3339()),
+            ],
+        }
+    }
+
+    fn with(mut self, name: &'static str, value: &str) -> Self {
+        self.vars.push((name, value.to_string()));
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Replace each `{{name}}` with `escape(value)`; unknown names are left as
+/// they are.
+fn render(template: &str, event: &Event, escape: impl Fn(&str) -> String) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match event.get(name) {
+                    Some(value) => out.push_str(&escape(value)),
+                    None => out.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `value` as the inside of a JSON string.
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+fn meets(condition: &Condition, event: &Event) -> bool {
+    match condition {
+        Condition::MessageFrom { jid } => {
+            event.get("event") == Some("message")
+                && event
+                    .get("conversation")
+                    .is_some_and(|c| c.eq_ignore_ascii_case(jid.trim()))
+        }
+        Condition::Keyword { word } => {
+            event.get("event") == Some("message")
+                && event
+                    .get("body")
+                    .is_some_and(|body| crate::notifications::rules::contains_word(body, word))
+        }
+        Condition::ConnectionLost => event.get("event") == Some("connectionLost"),
+    }
+}
+
+fn validate(trigger: &Trigger) -> Result<(), String> {
+    if trigger.name.trim().is_empty() {
+        return Err("A trigger needs a name".to_string());
+    }
+    match &trigger.condition {
+        Condition::MessageFrom { jid } if !jid.contains('@') && !jid.contains('.') => {
+            return Err(format!("Not a JID: {jid}"));
+        }
+        Condition::Keyword { word } if word.trim().is_empty() => {
+            return Err("The keyword is empty".to_string());
+        }
+        _ => {}
+    }
+    match &trigger.action {
+        Action::Webhook { url, .. } => {
+            let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL: {e}"))?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err("Webhooks must be http or https".to_string());
+            }
+        }
+        Action::Command { program, .. } => {
+            if !std::path::Path::new(program).is_absolute() {
+                return Err("The program must be an absolute path".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Claim a firing of `id`, unless it fired less than [`MIN_INTERVAL`] ago.
+fn claim(id: &str, now: Instant) -> bool {
+    let mut guard = LAST_FIRED.lock().unwrap_or_else(|e| e.into_inner());
+    let last = guard.get_or_insert_with(HashMap::new);
+    if last
+        .get(id)
+        .is_some_and(|at| now.duration_since(*at) < MIN_INTERVAL)
+    {
+        return false;
+    }
+    last.insert(id.to_string(), now);
+    true
+}
+
+async fn run(action: Action, event: Arc<Event>) -> Result<(), String> {
+    match action {
+        Action::Webhook { url, template } => {
+            let body = match template {
+                Some(template) => render(&template, &event, json_escape),
+                None => {
+                    let object: serde_json::Map<String, serde_json::Value> = event
+                        .vars
+                        .iter()
+                        .map(|(n, v)| (n.to_string(), serde_json::Value::String(v.clone())))
+                        .collect();
+                    serde_json::Value::Object(object).to_string()
+                }
+            };
+            tauri::async_runtime::spawn_blocking(move || {
+                let client = reqwest::blocking::Client::builder()
+                    .timeout(WEBHOOK_TIMEOUT)
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                let response = client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .body(body)
+                    .send()
+                    .map_err(|e| e.to_string())?;
+                if !response.status().is_success() {
+                    return Err(format!("Webhook answered {}", response.status()));
+                }
+                Ok(())
+            })
+            .await
+            .map_err(|e| e.to_string())?
+        }
+        Action::Command { program, args } => {
+            let args: Vec<String> = args
+                .iter()
+                .map(|a| render(a, &event, str::to_string))
+                .collect();
+            let mut child = tokio::process::Command::new(&program)
+                .args(&args)
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|e| format!("Could not start {program}: {e}"))?;
+            match tokio::time::timeout(COMMAND_TIMEOUT, child.wait()).await {
+                Ok(Ok(status)) if status.success() => Ok(()),
+                Ok(Ok(status)) => Err(format!("{program} exited with {status}")),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => {
+                    let _ = child.kill().await;
+                    Err(format!("{program} timed out"))
+                }
+            }
+        }
+    }
+}
+
+/// Run every enabled, approved trigger whose condition `event` meets.
+fn fire(app: Option<&tauri::AppHandle>, event: Event) {
+    let stored = stored();
+    let now = Instant::now();
+    let due: Vec<Trigger> = stored
+        .triggers
+        .into_iter()
+        .filter(|t| t.enabled && meets(&t.condition, &event))
+        .filter(|t| stored.approved.contains(&t.action))
+        .filter(|t| claim(&t.id, now))
+        .collect();
+    if due.is_empty() {
+        return;
+    }
+    let event = Arc::new(event);
+    let app = app.cloned();
+    for trigger in due {
+        let event = event.clone();
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let result = run(trigger.action, event).await;
+            match &result {
+                Ok(()) => info!(trigger = %trigger.name, "triggers: ran"),
+                Err(e) => warn!(trigger = %trigger.name, error = %e, "triggers: failed"),
+            }
+            if let Some(app) = app {
+                let ran = RanEvent {
+                    id: &trigger.id,
+                    name: &trigger.name,
+                    error: result.err(),
+                };
+                let _ = app.emit(RAN_EVENT, ran);
+            }
+        });
+    }
+}
+
+/// The connection to the server dropped other than by the user's or the
+/// app's own doing. Called from the bridge.
+pub fn connection_lost(app: Option<&tauri::AppHandle>, reason: &str) {
+    fire(app, Event::new("connectionLost").with("reason", reason));
+}
+
+/// The message event of a live message from someone else, if `stanza` is
+/// one.
+fn message_event(
+    stanza: &Element,
+    own_bare: Option<&str>,
+    own_nick: impl Fn(&str) -> Option<String>,
+) -> Option<Event> {
+    if stanza.local_name() != "message" || crate::word_watch::is_encrypted(stanza) {
+        return None;
+    }
+    let body = stanza.child("body", None)?.text();
+    let from = stanza.attr("from")?;
+    let conversation = bare_jid(from);
+    let nick = match stanza.attr("type") {
+        Some("error") | Some("headline") => return None,
+        Some("groupchat") => {
+            let nick = resource(from)?;
+            if stanza.child("delay", Some("urn:xmpp:delay")).is_some()
+                || own_nick(conversation).as_deref() == Some(nick)
+            {
+                return None;
+            }
+            nick
+        }
+        _ if own_bare.is_some_and(|own| own.eq_ignore_ascii_case(conversation)) => return None,
+        _ => "",
+    };
+    let sender = if nick.is_empty() {
+        conversation.to_string()
+    } else {
+        format!("{conversation}/{nick}")
+    };
+    Some(
+        Event::new("message")
+            .with("from", &sender)
+            .with("nick", nick)
+            .with("conversation", conversation)
+            .with("body", &body),
+    )
+}
+
+/// Bridge observer feeding message events to the triggers.
+pub struct Triggers {
+    /// Our nick per room.
+    unread: Arc<UnreadCounters>,
+}
+
+impl Triggers {
+    pub fn new(unread: Arc<UnreadCounters>) -> Self {
+        Triggers { unread }
+    }
+}
+
+impl StanzaObserver for Triggers {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction != Direction::Inbound || stanza.local_name() != "message" {
+            return Verdict::Forward;
+        }
+        let has_triggers = STORED
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_some_and(|s| s.triggers.iter().any(|t| t.enabled));
+        if !has_triggers {
+            return Verdict::Forward;
+        }
+        let own = session::own_bare_jid();
+        let own_nick = |room: &str| self.unread.own_room_nick(room);
+        if let Some(event) = message_event(stanza, own.as_deref(), own_nick) {
+            fire(ctx.app, event);
+        }
+        Verdict::Forward
+    }
+}
+
+fn describe(action: &Action) -> String {
+    match action {
+        Action::Webhook { url, .. } => format!("send event details to {url}"),
+        Action::Command { program, args } if args.is_empty() => format!("run {program}"),
+        Action::Command { program, args } => format!("run {program} {}", args.join(" ")),
+    }
+}
+
+fn ask_consent(app: &tauri::AppHandle, trigger: &Trigger) -> bool {
+    app.dialog()
+        .message(format!(
+            "The trigger \u{201c}{}\u{201d} will {} whenever it fires, with the \
+             message text and sender when it is about a message.",
+            trigger.name,
+            describe(&trigger.action)
+        ))
+        .title("Allow this automation?")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Allow".to_string(),
+            "Don't Allow".to_string(),
+        ))
+        .blocking_show()
+}
+
+#[tauri::command]
+pub fn list_triggers() -> Vec<Trigger> {
+    stored().triggers
+}
+
+/// Add or replace a trigger. An action not approved before asks for
+/// consent first; declining saves nothing.
+#[tauri::command]
+pub async fn save_trigger(app: tauri::AppHandle, trigger: Trigger) -> Result<Trigger, String> {
+    validate(&trigger)?;
+    let mut trigger = trigger;
+    if trigger.id.is_empty() {
+        trigger.id = uuid::Uuid::new_v4().to_string();
+    }
+    let current = stored();
+    if !current.triggers.iter().any(|t| t.id == trigger.id)
+        && current.triggers.len() >= MAX_TRIGGERS
+    {
+        return Err(format!("At most {MAX_TRIGGERS} triggers"));
+    }
+    if !current.approved.contains(&trigger.action) {
+        let (app, asked) = (app.clone(), trigger.clone());
+        let allowed = tauri::async_runtime::spawn_blocking(move || ask_consent(&app, &asked))
+            .await
+            .map_err(|e| e.to_string())?;
+        if !allowed {
+            return Err("The automation was not allowed".to_string());
+        }
+    }
+    let saved = trigger.clone();
+    update(move |stored| {
+        if !stored.approved.contains(&trigger.action) {
+            stored.approved.push(trigger.action.clone());
+        }
+        match stored.triggers.iter_mut().find(|t| t.id == trigger.id) {
+            Some(existing) => *existing = trigger,
+            None => stored.triggers.push(trigger),
+        }
+        // Approvals no trigger uses any more are forgotten.
+        let in_use: Vec<Action> = stored.triggers.iter().map(|t| t.action.clone()).collect();
+        stored.approved.retain(|a| in_use.contains(a));
+    });
+    Ok(saved)
+}
+
+#[tauri::command]
+pub fn delete_trigger(id: String) -> Vec<Trigger> {
+    update(|stored| {
+        stored.triggers.retain(|t| t.id != id);
+        let in_use: Vec<Action> = stored.triggers.iter().map(|t| t.action.clone()).collect();
+        stored.approved.retain(|a| in_use.contains(a));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_fill_known_placeholders_only() {
+        let event = Event::new("message")
+            .with("from", "bob@example.com")
+            .with("body", "door \"open\"");
+        assert_eq!(
+            render(
+                "{\"text\": \"{{ body }} from {{from}}\", \"x\": \"{{nope}}\"}",
+                &event,
+                json_escape
+            ),
+            "{\"text\": \"door \\\"open\\\" from bob@example.com\", \"x\": \"{{nope}}\"}"
+        );
+        assert_eq!(
+            render("--msg={{body}}", &event, str::to_string),
+            "--msg=door \"open\""
+        );
+        assert_eq!(
+            render("dangling {{body", &event, str::to_string),
+            "dangling {{body"
+        );
+    }
+
+    #[test]
+    fn conditions_match_message_events() {
+        let parse = |xml: &str| Element::parse(xml).unwrap();
+        let own_nick = |_: &str| Some("me".to_string());
+        let own = Some("me@example.com");
+        let room = parse(
+            "<message from='ops@muc.example.com/ann' type='groupchat'>\
+             <body>Deploy FAILED on db1</body></message>",
+        );
+        let event = message_event(&room, own, own_nick).unwrap();
+        assert_eq!(event.get("from"), Some("ops@muc.example.com/ann"));
+        let keyword = Condition::Keyword {
+            word: "failed".to_string(),
+        };
+        assert!(meets(&keyword, &event));
+        let from = Condition::MessageFrom {
+            jid: "ops@muc.example.com".to_string(),
+        };
+        assert!(meets(&from, &event));
+        assert!(!meets(&Condition::ConnectionLost, &event));
+
+        let own_echo = parse(
+            "<message from='ops@muc.example.com/me' type='groupchat'><body>failed</body></message>",
+        );
+        assert!(message_event(&own_echo, own, own_nick).is_none());
+        let carbon =
+            parse("<message from='me@example.com/phone' type='chat'><body>x</body></message>");
+        assert!(message_event(&carbon, own, own_nick).is_none());
+    }
+
+    #[test]
+    fn actions_are_validated() {
+        let trigger = |action: Action| Trigger {
+            id: String::new(),
+            name: "t".to_string(),
+            enabled: true,
+            condition: Condition::ConnectionLost,
+            action,
+        };
+        let command = |program: &str| Action::Command {
+            program: program.to_string(),
+            args: Vec::new(),
+        };
+        let webhook = |url: &str| Action::Webhook {
+            url: url.to_string(),
+            template: None,
+        };
+        assert!(validate(&trigger(command("notify-send"))).is_err());
+        assert!(validate(&trigger(webhook("file:///etc/passwd"))).is_err());
+        assert!(validate(&trigger(webhook("http://127.0.0.1:8123/api/webhook/x"))).is_ok());
+    }
+}
@@ -0,0 +1,459 @@
+//! Chat state (XEP-0085) throttling and typing aggregation.
+//!
+//! Outbound, standalone chat states are cut down to what peers need: a
+//! repeated state is dropped, except that `composing` is refreshed every
+//! [`REFRESH`] so the receiving side's stale-indicator timeout never fires
+//! while we are still typing. `paused` is held back for [`PAUSE_DEBOUNCE`]
+//! and only sent if typing does not resume, so a composer that pauses
+//! between words does not flap composing / paused at the peer.
+//!
+//! Inbound, the same rule drops repeats before they wake the WebView, and
+//! the typing senders of each conversation are aggregated into one
+//! `typing-changed` event carrying the whole set. A sender whose `composing`
+//! is not refreshed within [`TYPING_TIMEOUT`] stops counting, matching the
+//! SDK's own timeout.
+
+use crate::unread::UnreadCounters;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, resource, Element};
+use crate::xmpp_proxy::tap::{self, Direction, StanzaObserver, TapContext, Verdict};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+use tokio::sync::Notify;
+use tracing::warn;
+
+const TYPING_CHANGED_EVENT: &str = "typing-changed";
+
+const CHATSTATES_NS: &str = "http://jabber.org/protocol/chatstates";
+const HINTS_NS: &str = "urn:xmpp:hints";
+
+/// A repeated `composing` goes through again after this long.
+const REFRESH: Duration = Duration::from_secs(20);
+/// How long an outbound `paused` waits for typing to resume.
+const PAUSE_DEBOUNCE: Duration = Duration::from_secs(2);
+/// An unrefreshed `composing` is considered stale after this long.
+const TYPING_TIMEOUT: Duration = Duration::from_secs(30);
+/// Typing senders listed per event; `count` stays exact.
+const MAX_LISTED: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChatState {
+    Active,
+    Composing,
+    Paused,
+    Inactive,
+    Gone,
+}
+
+/// Payload of the `typing-changed` event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypingChanged {
+    pub conversation_id: String,
+    /// The first few typing senders: bare JIDs in chats, nicks in rooms.
+    pub typing: Vec<String>,
+    pub count: u32,
+}
+
+fn chat_state(stanza: &Element) -> Option<ChatState> {
+    stanza
+        .elements()
+        .filter(|e| e.ns() == Some(CHATSTATES_NS))
+        .find_map(|e| match e.local_name() {
+            "active" => Some(ChatState::Active),
+            "composing" => Some(ChatState::Composing),
+            "paused" => Some(ChatState::Paused),
+            "inactive" => Some(ChatState::Inactive),
+            "gone" => Some(ChatState::Gone),
+            _ => None,
+        })
+}
+
+/// A message that carries nothing but a chat state (and processing hints).
+fn is_standalone(stanza: &Element) -> bool {
+    stanza.elements().all(|e| {
+        matches!(e.ns(), Some(CHATSTATES_NS) | Some(HINTS_NS)) || e.local_name() == "thread"
+    })
+}
+
+/// What we last sent to one conversation.
+struct Sent {
+    state: ChatState,
+    at: Instant,
+}
+
+/// What one sender last told us.
+struct Seen {
+    state: ChatState,
+    /// When a stanza from this sender was last let through.
+    forwarded_at: Instant,
+    /// When this state was last received, refreshes included.
+    seen_at: Instant,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Outbound state per conversation bare JID.
+    sent: HashMap<String, Sent>,
+    /// `paused` stanzas held back, with the time they go out.
+    held: HashMap<String, (Instant, Element)>,
+    /// Inbound state per conversation, then per sender.
+    seen: HashMap<String, BTreeMap<String, Seen>>,
+}
+
+impl Inner {
+    fn typing(&self, conversation_id: &str) -> TypingChanged {
+        let typing: Vec<&String> = self
+            .seen
+            .get(conversation_id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, seen)| seen.state == ChatState::Composing)
+            .map(|(sender, _)| sender)
+            .collect();
+        TypingChanged {
+            conversation_id: conversation_id.to_string(),
+            count: typing.len() as u32,
+            typing: typing.into_iter().take(MAX_LISTED).cloned().collect(),
+        }
+    }
+}
+
+/// Chat state filter, registered with the bridge tap.
+pub struct Typing {
+    inner: Mutex<Inner>,
+    /// Our nick per room.
+    unread: Arc<UnreadCounters>,
+    /// Wakes the timer task when a deadline may have moved.
+    changed: Notify,
+}
+
+impl Typing {
+    pub fn new(unread: Arc<UnreadCounters>) -> Self {
+        Typing {
+            inner: Mutex::new(Inner::default()),
+            unread,
+            changed: Notify::new(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn outbound(&self, stanza: &Element, now: Instant) -> Verdict {
+        let Some(to) = stanza.attr("to").map(bare_jid) else {
+            return Verdict::Forward;
+        };
+        let mut inner = self.lock();
+        // Anything else we send cancels a pending `paused`.
+        inner.held.remove(to);
+        let standalone = is_standalone(stanza);
+        let state = match chat_state(stanza) {
+            Some(state) if standalone => state,
+            // A message of ours means we are done typing.
+            _ if stanza.child("body", None).is_some() => ChatState::Active,
+            _ => return Verdict::Forward,
+        };
+        let last = inner.sent.get(to);
+        if standalone {
+            if let Some(last) = last.filter(|last| last.state == state) {
+                if state != ChatState::Composing || now.duration_since(last.at) < REFRESH {
+                    return Verdict::Drop;
+                }
+            }
+            if state == ChatState::Paused
+                && last.is_some_and(|last| last.state == ChatState::Composing)
+            {
+                inner
+                    .held
+                    .insert(to.to_string(), (now + PAUSE_DEBOUNCE, stanza.clone()));
+                return Verdict::Drop;
+            }
+        }
+        inner.sent.insert(to.to_string(), Sent { state, at: now });
+        Verdict::Forward
+    }
+
+    /// Returns whether to relay the stanza, and the new typing set of its
+    /// conversation when that changed.
+    fn inbound(
+        &self,
+        stanza: &Element,
+        own_bare: Option<&str>,
+        own_nick: impl Fn(&str) -> Option<String>,
+        now: Instant,
+    ) -> (Verdict, Option<TypingChanged>) {
+        let unchanged = (Verdict::Forward, None);
+        let Some(from) = stanza.attr("from") else {
+            return unchanged;
+        };
+        // Replayed room history says nothing about now.
+        if stanza.child("delay", Some("urn:xmpp:delay")).is_some() {
+            return unchanged;
+        }
+        let conversation_id = bare_jid(from);
+        let sender = match stanza.attr("type") {
+            Some("error") => return unchanged,
+            Some("groupchat") => {
+                let Some(nick) = resource(from) else {
+                    return unchanged;
+                };
+                let own = own_nick(conversation_id);
+                if own.is_some_and(|own| own.eq_ignore_ascii_case(nick)) {
+                    return unchanged;
+                }
+                nick
+            }
+            _ if own_bare == Some(conversation_id) => return unchanged,
+            _ => conversation_id,
+        };
+        let standalone = is_standalone(stanza);
+        let state = match chat_state(stanza) {
+            Some(state) if standalone => state,
+            _ if stanza.child("body", None).is_some() => ChatState::Active,
+            _ => return unchanged,
+        };
+
+        let mut inner = self.lock();
+        let senders = inner.seen.entry(conversation_id.to_string()).or_default();
+        let was_typing = senders
+            .get(sender)
+            .is_some_and(|seen| seen.state == ChatState::Composing);
+        let repeat = senders.get(sender).is_some_and(|seen| {
+            seen.state == state
+                && (state != ChatState::Composing
+                    || now.duration_since(seen.forwarded_at) < REFRESH)
+        });
+        let verdict = if standalone && repeat {
+            Verdict::Drop
+        } else {
+            Verdict::Forward
+        };
+        let forwarded_at = match (verdict, senders.get(sender)) {
+            (Verdict::Drop, Some(seen)) => seen.forwarded_at,
+            _ => now,
+        };
+        senders.insert(
+            sender.to_string(),
+            Seen {
+                state,
+                forwarded_at,
+                seen_at: now,
+            },
+        );
+        // Quiet senders need not be remembered once a repeat would go
+        // through anyway.
+        senders.retain(|_, seen| {
+            seen.state == ChatState::Composing || now.duration_since(seen.forwarded_at) < REFRESH
+        });
+        if senders.is_empty() {
+            inner.seen.remove(conversation_id);
+        }
+        let change =
+            (was_typing != (state == ChatState::Composing)).then(|| inner.typing(conversation_id));
+        (verdict, change)
+    }
+
+    /// Release the held `paused` stanzas and expire stale typing senders
+    /// due by `now`.
+    fn tick(&self, now: Instant) -> (Vec<Element>, Vec<TypingChanged>) {
+        let mut inner = self.lock();
+        let due: Vec<String> = inner
+            .held
+            .iter()
+            .filter(|(_, (at, _))| *at <= now)
+            .map(|(to, _)| to.clone())
+            .collect();
+        let mut release = Vec::new();
+        for to in due {
+            if let Some((_, stanza)) = inner.held.remove(&to) {
+                inner.sent.insert(
+                    to,
+                    Sent {
+                        state: ChatState::Paused,
+                        at: now,
+                    },
+                );
+                release.push(stanza);
+            }
+        }
+
+        let mut expired = Vec::new();
+        for (conversation_id, senders) in inner.seen.iter_mut() {
+            let mut changed = false;
+            for seen in senders.values_mut() {
+                if seen.state == ChatState::Composing
+                    && now.duration_since(seen.seen_at) >= TYPING_TIMEOUT
+                {
+                    seen.state = ChatState::Paused;
+                    changed = true;
+                }
+            }
+            if changed {
+                expired.push(conversation_id.clone());
+            }
+        }
+        let changes = expired.iter().map(|id| inner.typing(id)).collect();
+        (release, changes)
+    }
+
+    /// The next time [`Typing::tick`] has something to do.
+    fn next_deadline(&self) -> Option<Instant> {
+        let inner = self.lock();
+        let held = inner.held.values().map(|(at, _)| *at);
+        let stale = inner
+            .seen
+            .values()
+            .flat_map(|senders| senders.values())
+            .filter(|seen| seen.state == ChatState::Composing)
+            .map(|seen| seen.seen_at + TYPING_TIMEOUT);
+        held.chain(stale).min()
+    }
+}
+
+impl StanzaObserver for Typing {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if stanza.local_name() != "message" {
+            return Verdict::Forward;
+        }
+        let now = Instant::now();
+        let verdict = match ctx.direction {
+            Direction::Outbound => self.outbound(stanza, now),
+            Direction::Inbound => {
+                let own = session::own_jid();
+                let own_bare = own.as_deref().map(bare_jid);
+                let own_nick = |room: &str| self.unread.own_room_nick(room);
+                let (verdict, change) = self.inbound(stanza, own_bare, own_nick, now);
+                if let (Some(app), Some(change)) = (ctx.app, change) {
+                    let _ = app.emit(TYPING_CHANGED_EVENT, change);
+                }
+                verdict
+            }
+        };
+        // Only a chat state can move a deadline.
+        if chat_state(stanza).is_some() {
+            self.changed.notify_one();
+        }
+        verdict
+    }
+}
+
+/// Run the held-`paused` and stale-typing timers for the lifetime of the
+/// app. Sleeps until the next deadline, or indefinitely when there is none.
+/// Called from `setup`.
+pub fn start(app: tauri::AppHandle, typing: Arc<Typing>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match typing.next_deadline() {
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = typing.changed.notified() => continue,
+                        _ = tokio::time::sleep_until(deadline.into()) => {}
+                    }
+                }
+                None => {
+                    typing.changed.notified().await;
+                    continue;
+                }
+            }
+            let (release, changes) = typing.tick(Instant::now());
+            for stanza in release {
+                match session::send(stanza.clone()) {
+                    Ok(()) => tap::observe_native(Some(&app), &stanza),
+                    Err(e) => warn!(error = %e, "typing: could not send paused state"),
+                }
+            }
+            for change in changes {
+                let _ = app.emit(TYPING_CHANGED_EVENT, change);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(attrs: &str, state: &str) -> Element {
+        Element::parse(&format!(
+            "<message {attrs}><{state} xmlns='http://jabber.org/protocol/chatstates'/></message>"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn outbound_repeats_are_dropped_and_pauses_debounced() {
+        let typing = Typing::new(Arc::new(UnreadCounters::default()));
+        let t0 = Instant::now();
+        let to = "to='juliet@example.com' type='chat'";
+        assert_eq!(
+            typing.outbound(&state(to, "composing"), t0),
+            Verdict::Forward
+        );
+        let t1 = t0 + Duration::from_secs(1);
+        assert_eq!(typing.outbound(&state(to, "composing"), t1), Verdict::Drop);
+
+        // A pause followed by more typing never reaches the peer.
+        assert_eq!(typing.outbound(&state(to, "paused"), t1), Verdict::Drop);
+        assert_eq!(typing.next_deadline(), Some(t1 + PAUSE_DEBOUNCE));
+        assert_eq!(typing.outbound(&state(to, "composing"), t1), Verdict::Drop);
+        assert_eq!(typing.next_deadline(), None);
+
+        // One that lasts goes out when the timer fires.
+        assert_eq!(typing.outbound(&state(to, "paused"), t1), Verdict::Drop);
+        let (release, _) = typing.tick(t1 + PAUSE_DEBOUNCE);
+        assert_eq!(release.len(), 1);
+        assert_eq!(typing.outbound(&state(to, "paused"), t1), Verdict::Drop);
+
+        // Composing is refreshed so the peer's indicator does not time out.
+        let t2 = t1 + PAUSE_DEBOUNCE;
+        assert_eq!(
+            typing.outbound(&state(to, "composing"), t2),
+            Verdict::Forward
+        );
+        assert_eq!(
+            typing.outbound(&state(to, "composing"), t2 + REFRESH),
+            Verdict::Forward
+        );
+    }
+
+    #[test]
+    fn inbound_typing_is_aggregated_per_room() {
+        let typing = Typing::new(Arc::new(UnreadCounters::default()));
+        let own_nick = |_: &str| Some("Me".to_string());
+        let t0 = Instant::now();
+        let from = |nick: &str| format!("from='room@muc.example.com/{nick}' type='groupchat'");
+
+        let (verdict, change) =
+            typing.inbound(&state(&from("ann"), "composing"), None, own_nick, t0);
+        assert_eq!(verdict, Verdict::Forward);
+        assert_eq!(change.unwrap().typing, vec!["ann"]);
+        let (_, change) = typing.inbound(&state(&from("bob"), "composing"), None, own_nick, t0);
+        assert_eq!(change.unwrap().count, 2);
+
+        // A repeat is dropped and changes nothing; our own state is ignored.
+        let (verdict, change) =
+            typing.inbound(&state(&from("ann"), "composing"), None, own_nick, t0);
+        assert_eq!((verdict, change), (Verdict::Drop, None));
+        let (_, change) = typing.inbound(&state(&from("me"), "composing"), None, own_nick, t0);
+        assert!(change.is_none());
+
+        // Ann's message ends her typing.
+        let message = Element::parse(
+            "<message from='room@muc.example.com/ann' type='groupchat'><body>hi</body></message>",
+        )
+        .unwrap();
+        let (verdict, change) = typing.inbound(&message, None, own_nick, t0);
+        assert_eq!(verdict, Verdict::Forward);
+        assert_eq!(change.unwrap().typing, vec!["bob"]);
+
+        // Bob goes quiet without a pause.
+        let (_, changes) = typing.tick(t0 + TYPING_TIMEOUT);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].count, 0);
+    }
+}
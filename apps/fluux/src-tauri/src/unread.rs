@@ -0,0 +1,611 @@
+//! Native unread / mention counters.
+//!
+//! Counts are derived from the inbound stanzas the bridge relays (see
+//! [`crate::xmpp_proxy::tap`]), so they keep ticking while the WebView is
+//! throttled in the background. They feed [`get_unread_summary`], the
+//! `unread-changed` deltas and the Linux D-Bus properties. They count every
+//! message, so they are not the dock badge: that counts conversations, rooms
+//! only on a mention or with notify-all on, and pending inbox events, which
+//! the frontend stores know and the bridge does not (`useNotificationBadge`).
+//! A new resource binding or going offline starts the counts over.
+//!
+//! The same traffic orders the recent conversations (messages either way)
+//! for the Windows jump list and the macOS dock menu; see
+//! [`crate::recent_conversations`].
+
+use crate::xmpp_proxy::stanza::{bare_jid, resource, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tauri::Emitter;
+
+const UNREAD_CHANGED_EVENT: &str = "unread-changed";
+/// Conversations kept in the recent list.
+const RECENT_LIMIT: usize = 10;
+const ROSTER_NS: &str = "jabber:iq:roster";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    pub unread: u32,
+    pub mentions: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationUnread {
+    pub conversation_id: String,
+    pub unread: u32,
+    pub mentions: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadSummary {
+    pub total_unread: u32,
+    pub total_mentions: u32,
+    pub conversations: Vec<ConversationUnread>,
+}
+
+/// Payload of the `unread-changed` event: the new counts for one
+/// conversation plus the new totals, so listeners never need a re-fetch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadDelta {
+    pub conversation_id: String,
+    pub unread: u32,
+    pub mentions: u32,
+    pub total_unread: u32,
+    pub total_mentions: u32,
+}
+
+/// An entry of [`UnreadCounters::recent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentConversation {
+    pub conversation_id: String,
+    /// Roster name, when the contact has one.
+    pub name: Option<String>,
+    pub groupchat: bool,
+    pub unread: u32,
+}
+
+#[derive(Default)]
+struct State {
+    conversations: HashMap<String, Counts>,
+    /// Conversation currently on screen in a focused window; messages to it
+    /// are read as they arrive.
+    active: Option<String>,
+    /// Our own full JID, learned from the resource-binding result.
+    own_jid: Option<String>,
+    /// Our nickname per joined room, learned from outbound MUC presence.
+    room_nicks: HashMap<String, String>,
+    /// Conversation ids with a message either way, most recent first, and
+    /// whether each is a room.
+    recent: VecDeque<(String, bool)>,
+    /// Set when `recent` reorders or one of its names changes.
+    recent_changed: bool,
+    /// Roster names, learned from roster results and pushes.
+    names: HashMap<String, String>,
+}
+
+impl State {
+    /// Forget the session's counts and room nicknames. Returns a zeroed delta
+    /// for every conversation that had unread messages.
+    fn reset(&mut self) -> Vec<UnreadDelta> {
+        let unread: Vec<String> = self
+            .conversations
+            .drain()
+            .filter(|(_, counts)| counts.unread > 0)
+            .map(|(id, _)| id)
+            .collect();
+        self.room_nicks.clear();
+        unread.iter().map(|id| self.delta(id)).collect()
+    }
+
+    fn touch(&mut self, conversation_id: &str, groupchat: bool) {
+        if self.recent.front().map(|(id, _)| id.as_str()) == Some(conversation_id) {
+            return;
+        }
+        self.recent.retain(|(id, _)| id != conversation_id);
+        self.recent
+            .push_front((conversation_id.to_string(), groupchat));
+        self.recent.truncate(RECENT_LIMIT);
+        self.recent_changed = true;
+    }
+
+    fn learn_roster(&mut self, query: &Element) {
+        for item in query.elements().filter(|e| e.local_name() == "item") {
+            let Some(jid) = item.attr("jid").map(bare_jid) else {
+                continue;
+            };
+            let name = item
+                .attr("name")
+                .filter(|n| !n.is_empty() && item.attr("subscription") != Some("remove"));
+            let previous = match name {
+                Some(name) => self.names.insert(jid.to_string(), name.to_string()),
+                None => self.names.remove(jid),
+            };
+            if previous.as_deref() != name && self.recent.iter().any(|(id, _)| id == jid) {
+                self.recent_changed = true;
+            }
+        }
+    }
+
+    fn totals(&self) -> (u32, u32) {
+        self.conversations.values().fold((0, 0), |(u, m), c| {
+            (u.saturating_add(c.unread), m.saturating_add(c.mentions))
+        })
+    }
+
+    fn delta(&self, conversation_id: &str) -> UnreadDelta {
+        let counts = self
+            .conversations
+            .get(conversation_id)
+            .copied()
+            .unwrap_or_default();
+        let (total_unread, total_mentions) = self.totals();
+        UnreadDelta {
+            conversation_id: conversation_id.to_string(),
+            unread: counts.unread,
+            mentions: counts.mentions,
+            total_unread,
+            total_mentions,
+        }
+    }
+}
+
+/// Process-wide unread store. Registered with the bridge tap and held in
+/// Tauri managed state as `Arc<UnreadCounters>`.
+#[derive(Default)]
+pub struct UnreadCounters {
+    state: Mutex<State>,
+}
+
+impl UnreadCounters {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Recent conversations, most recent first.
+    pub fn recent(&self) -> Vec<RecentConversation> {
+        let state = self.lock();
+        state
+            .recent
+            .iter()
+            .map(|(id, groupchat)| RecentConversation {
+                conversation_id: id.clone(),
+                name: state.names.get(id).cloned(),
+                groupchat: *groupchat,
+                unread: state.conversations.get(id).map_or(0, |c| c.unread),
+            })
+            .collect()
+    }
+
+    /// The recent list, when it changed since the last call.
+    pub fn take_recent_change(&self) -> Option<Vec<RecentConversation>> {
+        if !std::mem::take(&mut self.lock().recent_changed) {
+            return None;
+        }
+        Some(self.recent())
+    }
+
+    pub fn summary(&self) -> UnreadSummary {
+        let state = self.lock();
+        let (total_unread, total_mentions) = state.totals();
+        let mut conversations: Vec<ConversationUnread> = state
+            .conversations
+            .iter()
+            .filter(|(_, c)| c.unread > 0)
+            .map(|(id, c)| ConversationUnread {
+                conversation_id: id.clone(),
+                unread: c.unread,
+                mentions: c.mentions,
+            })
+            .collect();
+        conversations.sort_by(|a, b| a.conversation_id.cmp(&b.conversation_id));
+        UnreadSummary {
+            total_unread,
+            total_mentions,
+            conversations,
+        }
+    }
+
+    /// Reset one conversation. Returns the delta to broadcast, or `None` when
+    /// it was already read.
+    pub fn mark_read(&self, conversation_id: &str) -> Option<UnreadDelta> {
+        let mut state = self.lock();
+        let removed = state.conversations.remove(conversation_id)?;
+        if removed.unread == 0 {
+            return None;
+        }
+        Some(state.delta(conversation_id))
+    }
+
+    /// Set (or clear) the on-screen conversation. Selecting a conversation
+    /// also marks it read.
+    pub fn set_active(&self, conversation_id: Option<String>) -> Option<UnreadDelta> {
+        let target = conversation_id.clone();
+        self.lock().active = conversation_id;
+        target.and_then(|id| self.mark_read(&id))
+    }
+
+    /// Start over when a session begins (resource binding) or ends (our
+    /// broadcast unavailable presence), so counts never outlive a logout or
+    /// carry over to another account.
+    fn reset_on_session_change(&self, direction: Direction, stanza: &Element) -> Vec<UnreadDelta> {
+        let starts = direction == Direction::Inbound
+            && stanza.local_name() == "iq"
+            && stanza
+                .child("bind", Some("urn:ietf:params:xml:ns:xmpp-bind"))
+                .is_some();
+        let ends = direction == Direction::Outbound
+            && stanza.local_name() == "presence"
+            && stanza.attr("type") == Some("unavailable")
+            && stanza.attr("to").is_none();
+        if !starts && !ends {
+            return Vec::new();
+        }
+        self.lock().reset()
+    }
+
+    /// Our nickname in the joined room `room`.
+    pub fn own_room_nick(&self, room: &str) -> Option<String> {
+        self.lock().room_nicks.get(room).cloned()
+    }
+
+    /// Apply one relayed stanza. Returns a delta when a counter moved.
+    fn ingest(&self, direction: Direction, stanza: &Element) -> Option<UnreadDelta> {
+        let mut state = self.lock();
+        match (direction, stanza.local_name()) {
+            (Direction::Inbound, "iq") => {
+                if let Some(jid) = stanza
+                    .child("bind", Some("urn:ietf:params:xml:ns:xmpp-bind"))
+                    .and_then(|bind| bind.child("jid", None))
+                {
+                    state.own_jid = Some(jid.text());
+                }
+                if let Some(query) = stanza.child("query", Some(ROSTER_NS)) {
+                    state.learn_roster(query);
+                }
+                None
+            }
+            (Direction::Outbound, "presence") => {
+                // A MUC join is presence to room@service/nick carrying <x xmlns=muc>.
+                let to = stanza.attr("to")?;
+                if stanza.attr("type") == Some("unavailable") {
+                    state.room_nicks.remove(bare_jid(to));
+                } else if stanza
+                    .child("x", Some("http://jabber.org/protocol/muc"))
+                    .is_some()
+                {
+                    state
+                        .room_nicks
+                        .insert(bare_jid(to).to_string(), resource(to)?.to_string());
+                }
+                None
+            }
+            (Direction::Outbound, "message") => {
+                let to = stanza.attr("to")?;
+                if stanza.child("body", None).is_some() {
+                    state.touch(bare_jid(to), stanza.attr("type") == Some("groupchat"));
+                }
+                None
+            }
+            (Direction::Inbound, "message") => {
+                let (conversation_id, mention) = classify_message(&state, stanza)?;
+                state.touch(&conversation_id, stanza.attr("type") == Some("groupchat"));
+                if state.active.as_deref() == Some(conversation_id.as_str()) {
+                    return None;
+                }
+                let counts = state
+                    .conversations
+                    .entry(conversation_id.clone())
+                    .or_default();
+                counts.unread = counts.unread.saturating_add(1);
+                if mention {
+                    counts.mentions = counts.mentions.saturating_add(1);
+                }
+                Some(state.delta(&conversation_id))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Decide whether an inbound message counts as unread, and whether it
+/// mentions us. Returns the conversation id (the sender's bare JID, or the
+/// room JID) on a countable message. Only room messages are checked for
+/// mentions: our nickname as a word, or a XEP-0372 reference to us.
+fn classify_message(state: &State, stanza: &Element) -> Option<(String, bool)> {
+    let body = stanza.child("body", None)?.text();
+    if body.trim().is_empty() {
+        return None;
+    }
+    let from = stanza.attr("from")?;
+    let conversation_id = bare_jid(from).to_string();
+    let own_bare = state.own_jid.as_deref().map(bare_jid);
+    if own_bare == Some(conversation_id.as_str()) {
+        return None;
+    }
+
+    match stanza.attr("type") {
+        Some("groupchat") => {
+            // Room history replayed on join is not new traffic.
+            if stanza.child("delay", Some("urn:xmpp:delay")).is_some() {
+                return None;
+            }
+            let nick = state.room_nicks.get(&conversation_id);
+            // Our own reflected message.
+            if nick.is_some() && nick.map(String::as_str) == resource(from) {
+                return None;
+            }
+            let mention =
+                nick.is_some_and(|nick| mentions(&body, nick)) || references_us(stanza, own_bare);
+            Some((conversation_id, mention))
+        }
+        Some("error") => None,
+        _ => Some((conversation_id, false)),
+    }
+}
+
+/// Whether `nick` appears in `body` as a word of its own, ignoring case:
+/// "zed" in "hey Zed!" but not in "zedonk".
+fn mentions(body: &str, nick: &str) -> bool {
+    let nick = nick.to_lowercase();
+    if nick.is_empty() {
+        return false;
+    }
+    let body = body.to_lowercase();
+    let word_char = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    body.match_indices(&nick).any(|(start, _)| {
+        let before = body[..start].chars().next_back();
+        let after = body[start + nick.len()..].chars().next();
+        !word_char(before) && !word_char(after)
+    })
+}
+
+/// XEP-0372 mention reference pointing at our bare JID.
+fn references_us(stanza: &Element, own_bare: Option<&str>) -> bool {
+    let Some(own) = own_bare else {
+        return false;
+    };
+    stanza.elements().any(|e| {
+        e.local_name() == "reference"
+            && e.ns() == Some("urn:xmpp:reference:0")
+            && e.attr("type") == Some("mention")
+            && e.attr("uri")
+                .and_then(|u| u.strip_prefix("xmpp:"))
+                .map(bare_jid)
+                == Some(own)
+    })
+}
+
+impl StanzaObserver for UnreadCounters {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        let reset = self.reset_on_session_change(ctx.direction, stanza);
+        let changed = self.ingest(ctx.direction, stanza);
+        if let Some(app) = ctx.app {
+            for delta in reset.iter().chain(&changed) {
+                publish(app, delta);
+            }
+        }
+        if let (Some(app), Some(recent)) = (ctx.app, self.take_recent_change()) {
+            crate::recent_conversations::changed(app, &recent);
+        }
+        Verdict::Forward
+    }
+}
+
+/// Broadcast a delta and refresh the D-Bus properties from the new totals.
+fn publish(app: &tauri::AppHandle, delta: &UnreadDelta) {
+    let _ = app.emit(UNREAD_CHANGED_EVENT, delta);
+    #[cfg(target_os = "linux")]
+    crate::dbus_service::unread_changed(delta.total_unread, delta.total_mentions);
+}
+
+#[tauri::command]
+pub fn get_unread_summary(counters: tauri::State<'_, Arc<UnreadCounters>>) -> UnreadSummary {
+    counters.summary()
+}
+
+#[tauri::command]
+pub fn mark_conversation_read(
+    app: tauri::AppHandle,
+    counters: tauri::State<'_, Arc<UnreadCounters>>,
+    conversation_id: String,
+) {
+    if let Some(delta) = counters.mark_read(&conversation_id) {
+        publish(&app, &delta);
+    }
+}
+
+/// Called by the frontend when the visible conversation changes or the
+/// window loses focus (`None`).
+#[tauri::command]
+pub fn set_active_conversation(
+    app: tauri::AppHandle,
+    counters: tauri::State<'_, Arc<UnreadCounters>>,
+    conversation_id: Option<String>,
+) {
+    if let Some(delta) = counters.set_active(conversation_id) {
+        publish(&app, &delta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inbound(counters: &UnreadCounters, xml: &str) -> Option<UnreadDelta> {
+        counters.ingest(Direction::Inbound, &Element::parse(xml).unwrap())
+    }
+
+    fn bind(counters: &UnreadCounters) {
+        inbound(
+            counters,
+            "<iq type='result' id='b'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><jid>me@example.com/desk</jid></bind></iq>",
+        );
+    }
+
+    #[test]
+    fn counts_chat_messages_per_conversation() {
+        let counters = UnreadCounters::default();
+        bind(&counters);
+        inbound(
+            &counters,
+            "<message from='alice@example.com/a' type='chat'><body>one</body></message>",
+        );
+        let delta = inbound(
+            &counters,
+            "<message from='alice@example.com/b' type='chat'><body>two</body></message>",
+        )
+        .expect("counted");
+        assert_eq!(delta.unread, 2);
+        assert_eq!(delta.total_unread, 2);
+        assert_eq!(delta.mentions, 0);
+    }
+
+    #[test]
+    fn ignores_bodyless_own_and_active_messages() {
+        let counters = UnreadCounters::default();
+        bind(&counters);
+        assert!(inbound(&counters, "<message from='alice@example.com' type='chat'><composing xmlns='http://jabber.org/protocol/chatstates'/></message>").is_none());
+        assert!(inbound(
+            &counters,
+            "<message from='me@example.com/phone' type='chat'><body>carbon</body></message>"
+        )
+        .is_none());
+        counters.set_active(Some("bob@example.com".into()));
+        assert!(inbound(
+            &counters,
+            "<message from='bob@example.com' type='chat'><body>seen</body></message>"
+        )
+        .is_none());
+        assert_eq!(counters.summary().total_unread, 0);
+    }
+
+    #[test]
+    fn groupchat_mentions_and_reflections() {
+        let counters = UnreadCounters::default();
+        bind(&counters);
+        counters.ingest(
+            Direction::Outbound,
+            &Element::parse("<presence to='room@muc.example.com/Zed'><x xmlns='http://jabber.org/protocol/muc'/></presence>").unwrap(),
+        );
+        assert!(inbound(
+            &counters,
+            "<message from='room@muc.example.com/Zed' type='groupchat'><body>mine</body></message>"
+        )
+        .is_none());
+        assert!(inbound(&counters, "<message from='room@muc.example.com/ann' type='groupchat'><delay xmlns='urn:xmpp:delay' stamp='x'/><body>old</body></message>").is_none());
+        let delta = inbound(&counters, "<message from='room@muc.example.com/ann' type='groupchat'><body>hey zed</body></message>").unwrap();
+        assert_eq!((delta.unread, delta.mentions), (1, 1));
+        let delta = inbound(&counters, "<message from='room@muc.example.com/ann' type='groupchat'><body>a zedonk, Zed_2 or @zedd</body></message>").unwrap();
+        assert_eq!((delta.unread, delta.mentions), (2, 1));
+        let delta = inbound(&counters, "<message from='room@muc.example.com/ann' type='groupchat'><body>@Zed: ok?</body></message>").unwrap();
+        assert_eq!(delta.mentions, 2);
+    }
+
+    #[test]
+    fn own_localpart_is_not_a_mention() {
+        let counters = UnreadCounters::default();
+        bind(&counters);
+        let delta = inbound(&counters, "<message from='alice@example.com' type='chat'><body>me: send me a message some time</body></message>").unwrap();
+        assert_eq!((delta.unread, delta.mentions), (1, 0));
+        let delta = inbound(&counters, "<message from='alice@example.com' type='chat'><body>look</body><reference xmlns='urn:xmpp:reference:0' type='mention' uri='xmpp:me@example.com'/></message>").unwrap();
+        assert_eq!(delta.mentions, 0);
+        counters.ingest(
+            Direction::Outbound,
+            &Element::parse("<presence to='room@muc.example.com/Zed'><x xmlns='http://jabber.org/protocol/muc'/></presence>").unwrap(),
+        );
+        let delta = inbound(&counters, "<message from='room@muc.example.com/ann' type='groupchat'><body>me too, any message?</body></message>").unwrap();
+        assert_eq!(delta.mentions, 0);
+    }
+
+    #[test]
+    fn reference_mentions_count() {
+        let counters = UnreadCounters::default();
+        bind(&counters);
+        let delta = inbound(&counters, "<message from='room@muc.example.com/ann' type='groupchat'><body>look</body><reference xmlns='urn:xmpp:reference:0' type='mention' uri='xmpp:me@example.com'/></message>").unwrap();
+        assert_eq!(delta.mentions, 1);
+    }
+
+    #[test]
+    fn mark_read_resets_and_reports_totals() {
+        let counters = UnreadCounters::default();
+        bind(&counters);
+        inbound(
+            &counters,
+            "<message from='a@example.com' type='chat'><body>x</body></message>",
+        );
+        inbound(
+            &counters,
+            "<message from='b@example.com' type='chat'><body>y</body></message>",
+        );
+        let delta = counters.mark_read("a@example.com").expect("was unread");
+        assert_eq!((delta.unread, delta.total_unread), (0, 1));
+        assert!(counters.mark_read("a@example.com").is_none());
+        assert_eq!(counters.summary().conversations.len(), 1);
+    }
+
+    #[test]
+    fn a_new_session_or_going_offline_starts_over() {
+        let counters = UnreadCounters::default();
+        bind(&counters);
+        inbound(
+            &counters,
+            "<message from='a@example.com' type='chat'><body>x</body></message>",
+        );
+        let rebind = Element::parse("<iq type='result' id='b'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><jid>other@example.com/desk</jid></bind></iq>").unwrap();
+        let reset = counters.reset_on_session_change(Direction::Inbound, &rebind);
+        assert_eq!(reset.len(), 1);
+        assert_eq!((reset[0].unread, reset[0].total_unread), (0, 0));
+        assert_eq!(counters.summary(), UnreadSummary::default());
+
+        inbound(
+            &counters,
+            "<message from='a@example.com' type='chat'><body>y</body></message>",
+        );
+        let offline = Element::parse("<presence type='unavailable'/>").unwrap();
+        assert_eq!(
+            counters
+                .reset_on_session_change(Direction::Outbound, &offline)
+                .len(),
+            1
+        );
+        let leave = Element::parse("<presence to='room@muc.example.com/Zed' type='unavailable'/>");
+        assert!(counters
+            .reset_on_session_change(Direction::Outbound, &leave.unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn orders_recent_conversations_both_ways() {
+        let counters = UnreadCounters::default();
+        bind(&counters);
+        inbound(&counters, "<iq type='result' id='r'><query xmlns='jabber:iq:roster'><item jid='a@example.com' name='Ann'/></query></iq>");
+        inbound(
+            &counters,
+            "<message from='a@example.com/x' type='chat'><body>x</body></message>",
+        );
+        counters.ingest(
+            Direction::Outbound,
+            &Element::parse(
+                "<message to='room@muc.example.com' type='groupchat'><body>y</body></message>",
+            )
+            .unwrap(),
+        );
+        let recent = counters.take_recent_change().expect("changed");
+        assert_eq!(recent[0].conversation_id, "room@muc.example.com");
+        assert!(recent[0].groupchat);
+        assert_eq!(
+            (recent[1].name.as_deref(), recent[1].unread),
+            (Some("Ann"), 1)
+        );
+        inbound(
+            &counters,
+            "<message from='room@muc.example.com/ann' type='groupchat'><body>z</body></message>",
+        );
+        assert!(counters.take_recent_change().is_none());
+    }
+}
@@ -0,0 +1,365 @@
+//! Update channels and scheduled update checks.
+//!
+//! The updater plugin checks the stable manifest set in `tauri.conf.json`.
+//! Here the user picks a channel (`stable`, `beta`, `nightly`), each with its
+//! own signed manifest, and how often to check in the background (0 turns
+//! scheduled checks off). A check that finds a newer build emits
+//! `fluux://update-available` with the version and release notes, so the
+//! user sees what a beta changes before taking it. Both settings persist in
+//! `updates.json`.
+//!
+//! Updates are whole, signed bundles: the Tauri updater has no binary
+//! deltas. Moving back to a quieter channel never downgrades; the next
+//! stable release newer than the installed build is offered.
+//!
+//! Installing is staged rather than done mid-conversation:
+//! `download_update` fetches the bundle in the background and checks its
+//! signature against the key in `tauri.conf.json`, then `apply_update`
+//! either restarts now, through the normal quit path so the XMPP session is
+//! drained and closed first, or installs when the app next quits so the new
+//! version runs from the following launch. A staged bundle lives in memory
+//! only; one never applied is dropped at exit.
+
+use crate::json_file::JsonFile;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+use tauri_plugin_updater::{Update, UpdaterExt};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+const AVAILABLE_EVENT: &str = "fluux://update-available";
+const DOWNLOAD_EVENT: &str = "fluux://update-download-progress";
+const STAGED_EVENT: &str = "fluux://update-staged";
+const DEFAULT_INTERVAL_HOURS: u32 = 24;
+/// First scheduled check after startup, once the session has settled.
+const FIRST_CHECK_DELAY: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Beta,
+    Nightly,
+}
+
+impl Channel {
+    /// Signed manifest for the channel. Beta and nightly builds are
+    /// published as assets of rolling pre-releases with those tags.
+    fn endpoint(self) -> &'static str {
+        match self {
+            Channel::Stable => {
+                "https://github.com/processone/fluux-messenger/releases/latest/download/latest.json"
+            }
+            Channel::Beta => {
+                "https://github.com/processone/fluux-messenger/releases/download/beta/latest.json"
+            }
+            Channel::Nightly => {
+                "https://github.com/processone/fluux-messenger/releases/download/nightly/latest.json"
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UpdateSettings {
+    pub channel: Channel,
+    /// Hours between background checks; 0 disables them.
+    pub check_interval_hours: u32,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self {
+            channel: Channel::Stable,
+            check_interval_hours: DEFAULT_INTERVAL_HOURS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateInfo {
+    pub version: String,
+    pub current_version: String,
+    pub channel: Channel,
+    pub notes: Option<String>,
+    pub date: Option<String>,
+}
+
+/// When a staged update is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ApplyAt {
+    /// Quit (gracefully), install and relaunch.
+    Now,
+    /// Install when the app quits; the next launch runs the new version.
+    NextLaunch,
+}
+
+/// A downloaded, verified bundle waiting to be installed.
+struct Staged {
+    update: Update,
+    bytes: Vec<u8>,
+    apply: Option<ApplyAt>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct DownloadProgress {
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+static SETTINGS: Mutex<Option<UpdateSettings>> = Mutex::new(None);
+static STAGED: Mutex<Option<Staged>> = Mutex::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+/// Wakes the scheduler when the cadence changes.
+static RESCHEDULE: Notify = Notify::const_new();
+
+pub fn settings() -> UpdateSettings {
+    SETTINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .unwrap_or_default()
+}
+
+/// Load the persisted settings from `dir`. Called from the Tauri `setup`
+/// hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("updates.json")) else {
+        return;
+    };
+    let loaded: UpdateSettings = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *SETTINGS.lock().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `SETTINGS` lock held; see [`crate::json_file`].
+fn persist(snapshot: UpdateSettings) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+fn update_settings(change: impl FnOnce(&mut UpdateSettings)) -> UpdateSettings {
+    let mut guard = SETTINGS.lock().unwrap_or_else(|e| e.into_inner());
+    let settings = guard.get_or_insert_with(UpdateSettings::default);
+    change(settings);
+    persist(*settings);
+    *settings
+}
+
+/// Check the current channel's manifest. `None` when the installed build is
+/// the newest.
+pub(crate) async fn find_update(app: &tauri::AppHandle) -> Result<Option<Update>, String> {
+    let channel = settings().channel;
+    let endpoint = tauri::Url::parse(channel.endpoint()).map_err(|e| e.to_string())?;
+    app.updater_builder()
+        .endpoints(vec![endpoint])
+        .and_then(|builder| builder.build())
+        .map_err(|e| format!("Updater unavailable: {e}"))?
+        .check()
+        .await
+        .map_err(|e| format!("Update check failed: {e}"))
+}
+
+fn describe(update: &Update) -> UpdateInfo {
+    UpdateInfo {
+        version: update.version.clone(),
+        current_version: update.current_version.clone(),
+        channel: settings().channel,
+        notes: update.body.clone(),
+        date: update.date.map(|d| d.to_string()),
+    }
+}
+
+/// Check now, emitting `fluux://update-available` when there is an update.
+async fn check_and_announce(app: &tauri::AppHandle) -> Result<Option<UpdateInfo>, String> {
+    let Some(update) = find_update(app).await? else {
+        return Ok(None);
+    };
+    let info = describe(&update);
+    info!(version = %info.version, channel = ?info.channel, "updates: update available");
+    let _ = app.emit(AVAILABLE_EVENT, &info);
+    Ok(Some(info))
+}
+
+/// Run scheduled checks for the life of the app.
+pub fn start(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut delay = FIRST_CHECK_DELAY;
+        loop {
+            let hours = settings().check_interval_hours;
+            if hours == 0 {
+                RESCHEDULE.notified().await;
+                delay = FIRST_CHECK_DELAY;
+                continue;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                // New cadence: start over with it.
+                _ = RESCHEDULE.notified() => {
+                    delay = Duration::from_secs(u64::from(settings().check_interval_hours) * 3600);
+                    continue;
+                }
+            }
+            if let Err(e) = check_and_announce(&app).await {
+                warn!(error = %e, "updates: scheduled check failed");
+            }
+            delay = Duration::from_secs(u64::from(hours) * 3600);
+        }
+    });
+}
+
+fn staged() -> std::sync::MutexGuard<'static, Option<Staged>> {
+    STAGED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Install the staged update if the user chose when. Called from the
+/// `RunEvent::Exit` handler, after the session has been closed; relaunches
+/// for `ApplyAt::Now`.
+pub fn install_staged(app: &tauri::AppHandle) {
+    let Some(staged) = staged().take() else {
+        return;
+    };
+    let Some(apply) = staged.apply else {
+        info!(version = %staged.update.version, "updates: staged update left unapplied");
+        return;
+    };
+    info!(version = %staged.update.version, ?apply, "updates: installing staged update");
+    if let Err(e) = staged.update.install(&staged.bytes) {
+        warn!(error = %e, "updates: install failed");
+        return;
+    }
+    // The Windows installer exits this process and relaunches by itself.
+    if apply == ApplyAt::Now && !cfg!(target_os = "windows") {
+        tauri::process::restart(&app.env());
+    }
+}
+
+/// A restart-now quit was cancelled: keep the update for the next quit
+/// instead of relaunching then.
+pub fn quit_cancelled() {
+    if let Some(staged) = staged().as_mut() {
+        if staged.apply == Some(ApplyAt::Now) {
+            staged.apply = Some(ApplyAt::NextLaunch);
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_update_settings() -> UpdateSettings {
+    settings()
+}
+
+/// Switch channel and check it right away.
+#[tauri::command]
+pub async fn set_update_channel(
+    app: tauri::AppHandle,
+    channel: Channel,
+) -> Result<Option<UpdateInfo>, String> {
+    update_settings(|s| s.channel = channel);
+    info!(?channel, "updates: channel changed");
+    check_and_announce(&app).await
+}
+
+#[tauri::command]
+pub fn set_update_check_interval(hours: u32) -> UpdateSettings {
+    let settings = update_settings(|s| s.check_interval_hours = hours.min(24 * 30));
+    RESCHEDULE.notify_one();
+    settings
+}
+
+/// Check the current channel now.
+#[tauri::command]
+pub async fn check_for_updates(app: tauri::AppHandle) -> Result<Option<UpdateInfo>, String> {
+    check_and_announce(&app).await
+}
+
+/// Download the current channel's update and stage it. The bundle's
+/// signature is verified before it is accepted.
+#[tauri::command]
+pub async fn download_update(app: tauri::AppHandle) -> Result<UpdateInfo, String> {
+    let update = find_update(&app).await?.ok_or("No update available")?;
+    if let Some(staged) = staged().as_ref() {
+        if staged.update.version == update.version {
+            return Ok(describe(&staged.update));
+        }
+    }
+    let (mut downloaded, mut last_percent) = (0u64, 0u64);
+    let progress_app = app.clone();
+    let bytes = update
+        .download(
+            move |chunk, total| {
+                downloaded += chunk as u64;
+                // Throttled to whole percents; every chunk when the size
+                // is unknown.
+                let percent = total.map_or(0, |t| (downloaded * 100).checked_div(t).unwrap_or(100));
+                if total.is_none() || percent > last_percent {
+                    last_percent = percent;
+                    let progress = DownloadProgress { downloaded, total };
+                    let _ = progress_app.emit(DOWNLOAD_EVENT, progress);
+                }
+            },
+            || {},
+        )
+        .await
+        .map_err(|e| format!("Update download failed: {e}"))?;
+    let info = describe(&update);
+    info!(version = %info.version, bytes = bytes.len(), "updates: update staged");
+    *staged() = Some(Staged {
+        update,
+        bytes,
+        apply: None,
+    });
+    let _ = app.emit(STAGED_EVENT, &info);
+    Ok(info)
+}
+
+/// The staged update, if one has been downloaded.
+#[tauri::command]
+pub fn get_staged_update() -> Option<UpdateInfo> {
+    staged().as_ref().map(|s| describe(&s.update))
+}
+
+/// Choose when the staged update is installed. `now` quits through the
+/// usual path (confirmation if messages are still queued, ack drain,
+/// graceful disconnect) and relaunches into the new version.
+#[tauri::command]
+pub fn apply_update(app: tauri::AppHandle, at: ApplyAt) -> Result<(), String> {
+    staged()
+        .as_mut()
+        .ok_or("No update has been downloaded")?
+        .apply = Some(at);
+    info!(?at, "updates: staged update scheduled");
+    if at == ApplyAt::Now {
+        app.exit(0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_default_missing_fields_and_name_channels_in_lowercase() {
+        let parsed: UpdateSettings = serde_json::from_str(r#"{"channel":"beta"}"#).unwrap();
+        assert_eq!(parsed.channel, Channel::Beta);
+        assert_eq!(parsed.check_interval_hours, DEFAULT_INTERVAL_HOURS);
+        assert!(serde_json::from_str::<Channel>(r#""canary""#).is_err());
+        assert!(Channel::Nightly
+            .endpoint()
+            .ends_with("/nightly/latest.json"));
+        let at: ApplyAt = serde_json::from_str(r#""nextLaunch""#).unwrap();
+        assert_eq!(at, ApplyAt::NextLaunch);
+    }
+}
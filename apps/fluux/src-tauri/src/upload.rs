@@ -59,6 +59,18 @@ struct ProgressPayload {
     total: u64,
 }
 
+/// Emit one `fluux://upload-progress` event.
+pub(crate) fn emit_progress(app: &tauri::AppHandle, upload_id: &str, sent: u64, total: u64) {
+    let _ = app.emit(
+        PROGRESS_EVENT,
+        ProgressPayload {
+            id: upload_id.to_string(),
+            sent,
+            total,
+        },
+    );
+}
+
 fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str, String> {
     headers
         .get(name)
@@ -139,14 +151,7 @@ impl<R: Read> Read for ProgressReader<R> {
         let percent = (self.sent * 100).checked_div(self.total).unwrap_or(100);
         if percent > self.last_percent {
             self.last_percent = percent;
-            let _ = self.app.emit(
-                PROGRESS_EVENT,
-                ProgressPayload {
-                    id: self.upload_id.clone(),
-                    sent: self.sent,
-                    total: self.total,
-                },
-            );
+            emit_progress(&self.app, &self.upload_id, self.sent, self.total);
         }
         Ok(n)
     }
@@ -0,0 +1,455 @@
+//! Resumable HTTP upload of large files (XEP-0363 slots), read from disk.
+//!
+//! `upload_file` takes the bytes over IPC, which doesn't scale to multi-GB
+//! files. `upload_large_file` reads the file itself and PUTs it in
+//! `CHUNK_BYTES` ranges (`Content-Range: bytes a-b/size`). A failed range is
+//! retried with backoff; the offset the server confirmed is persisted in
+//! `pending-uploads.json`, so an upload interrupted by a flaky connection or
+//! a restart continues from there (`resume_large_upload`) instead of from
+//! zero.
+//!
+//! Ranged PUT is an HTTP extension not every upload service implements. If
+//! the server rejects the first range, the upload falls back to one
+//! streaming PUT of the whole file, retried from the start.
+//!
+//! The file's SHA-256 is computed while it is read (the already-sent prefix
+//! is re-read on resume) and returned base64-encoded, for the file's
+//! XEP-0300 hash in the message. Progress is reported as the
+//! `fluux://upload-progress` events of `upload_file`. Encryption is not
+//! offered here: AES-GCM (XEP-0454) needs the whole file at once.
+
+use crate::json_file::JsonFile;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const CHUNK_BYTES: u64 = 8 * 1024 * 1024;
+const MAX_ATTEMPTS: u32 = 5;
+const RETRY_BASE: Duration = Duration::from_secs(2);
+const CHUNK_TIMEOUT_SECS: u64 = 300;
+const WHOLE_TIMEOUT_SECS: u64 = 6 * 3600;
+
+/// Persisted state of an upload that has not finished.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingUpload {
+    pub id: String,
+    pub path: PathBuf,
+    pub put_url: String,
+    pub content_type: String,
+    pub extra_headers: Vec<(String, String)>,
+    pub size: u64,
+    /// Modification time (Unix seconds) when the upload started; a file
+    /// changed since then can't be resumed.
+    pub modified: u64,
+    /// Bytes the server has confirmed.
+    pub confirmed: u64,
+    /// Whether the server takes ranged PUTs; `None` until it has answered
+    /// one.
+    pub ranged: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LargeUploadResult {
+    pub size: u64,
+    /// Base64 SHA-256 of the file.
+    pub sha256: String,
+}
+
+static PENDING: Mutex<BTreeMap<String, PendingUpload>> = Mutex::new(BTreeMap::new());
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+/// Uploads with a task running, so one can't be resumed twice.
+static RUNNING: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+fn pending() -> std::sync::MutexGuard<'static, BTreeMap<String, PendingUpload>> {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Load the unfinished uploads from `dir`. Called from the Tauri `setup`
+/// hook; nothing resumes until the WebView asks.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("pending-uploads.json")) else {
+        return;
+    };
+    let loaded: BTreeMap<String, PendingUpload> = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    if !loaded.is_empty() {
+        info!(count = loaded.len(), "upload: unfinished uploads found");
+    }
+    *pending() = loaded;
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Write `all` out, with the `PENDING` lock held. Called from the blocking
+/// upload task, between ranges, so it writes synchronously.
+fn persist(all: &BTreeMap<String, PendingUpload>) {
+    let Some(file) = FILE.get() else {
+        return;
+    };
+    if let Err(e) = file.save_now(all) {
+        warn!(error = %e, "upload: failed to persist pending uploads");
+    }
+}
+
+fn save(upload: &PendingUpload) {
+    let mut all = pending();
+    all.insert(upload.id.clone(), upload.clone());
+    persist(&all);
+}
+
+fn forget(id: &str) {
+    let mut all = pending();
+    if all.remove(id).is_some() {
+        persist(&all);
+    }
+}
+
+/// Marks an upload as running for as long as it is held.
+struct RunGuard(String);
+
+impl RunGuard {
+    fn claim(id: &str) -> Result<Self, String> {
+        let mut running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        if !running.insert(id.to_string()) {
+            return Err(format!("Upload {id} is already running"));
+        }
+        Ok(Self(id.to_string()))
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        RUNNING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.0);
+    }
+}
+
+fn modified_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+fn content_range(start: u64, end: u64, size: u64) -> String {
+    format!("bytes {start}-{}/{size}", end - 1)
+}
+
+/// Whether a failed PUT is worth repeating: network trouble, timeouts, rate
+/// limiting and server errors, not a refusal.
+fn retryable(status: Option<u16>) -> bool {
+    match status {
+        None => true,
+        Some(status) => status == 408 || status == 429 || status >= 500,
+    }
+}
+
+/// Why one PUT failed.
+struct PutError {
+    status: Option<u16>,
+    message: String,
+}
+
+fn client(timeout_secs: u64) -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {e}"))
+}
+
+fn put(
+    client: &reqwest::blocking::Client,
+    upload: &PendingUpload,
+    body: reqwest::blocking::Body,
+    range: Option<String>,
+) -> Result<(), PutError> {
+    let mut request = client
+        .put(&upload.put_url)
+        .header("Content-Type", &upload.content_type)
+        .body(body);
+    if let Some(range) = range {
+        request = request.header("Content-Range", range);
+    }
+    for (name, value) in &upload.extra_headers {
+        request = request.header(name, value);
+    }
+    let response = request.send().map_err(|e| PutError {
+        status: None,
+        message: e.to_string(),
+    })?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(PutError {
+            status: Some(response.status().as_u16()),
+            message: format!("Upload failed: {}", response.status().as_u16()),
+        })
+    }
+}
+
+/// Run `attempt` until it succeeds, fails for good or runs out of tries.
+fn with_retries(
+    id: &str,
+    what: &str,
+    mut attempt: impl FnMut() -> Result<(), PutError>,
+) -> Result<(), PutError> {
+    let mut tries = 0;
+    loop {
+        tries += 1;
+        match attempt() {
+            Ok(()) => return Ok(()),
+            Err(e) if tries < MAX_ATTEMPTS && retryable(e.status) => {
+                let delay = RETRY_BASE * 2u32.pow(tries - 1);
+                warn!(id, what, error = %e.message, ?delay, "upload: retrying");
+                std::thread::sleep(delay);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Hash the first `len` bytes of `file` into `hasher`.
+fn hash_prefix(file: &mut std::fs::File, len: u64, hasher: &mut Sha256) -> Result<(), String> {
+    file.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+    let mut prefix = file.take(len);
+    std::io::copy(&mut prefix, hasher).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Feeds what reqwest reads into the hash and progress events.
+struct HashingReader {
+    inner: std::fs::File,
+    hasher: Arc<Mutex<Sha256>>,
+    app: tauri::AppHandle,
+    id: String,
+    sent: u64,
+    total: u64,
+    last_percent: u64,
+}
+
+impl Read for HashingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .update(&buf[..n]);
+        self.sent += n as u64;
+        let percent = (self.sent * 100).checked_div(self.total).unwrap_or(100);
+        if percent > self.last_percent {
+            self.last_percent = percent;
+            crate::upload::emit_progress(&self.app, &self.id, self.sent, self.total);
+        }
+        Ok(n)
+    }
+}
+
+/// Upload the rest of `upload`, ranges first, persisting progress. On
+/// success the state is dropped; on a failure that may pass it is kept.
+fn run(app: &tauri::AppHandle, mut upload: PendingUpload) -> Result<LargeUploadResult, String> {
+    let metadata = std::fs::metadata(&upload.path).map_err(|e| e.to_string())?;
+    if metadata.len() != upload.size || modified_secs(&metadata) != upload.modified {
+        forget(&upload.id);
+        return Err(format!(
+            "{} changed since the upload started",
+            upload.path.display()
+        ));
+    }
+    let mut file = std::fs::File::open(&upload.path).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+
+    if upload.ranged != Some(false) && upload.size > CHUNK_BYTES {
+        let client = client(CHUNK_TIMEOUT_SECS)?;
+        hash_prefix(&mut file, upload.confirmed, &mut hasher)?;
+        if upload.confirmed > 0 {
+            info!(id = %upload.id, offset = upload.confirmed, "upload: resuming");
+        }
+        while upload.confirmed < upload.size {
+            let start = upload.confirmed;
+            let end = (start + CHUNK_BYTES).min(upload.size);
+            let mut chunk = vec![0u8; (end - start) as usize];
+            file.read_exact(&mut chunk).map_err(|e| e.to_string())?;
+            let range = content_range(start, end, upload.size);
+            let result = with_retries(&upload.id, &range, || {
+                put(&client, &upload, chunk.clone().into(), Some(range.clone()))
+            });
+            match result {
+                Ok(()) => {
+                    hasher.update(&chunk);
+                    upload.confirmed = end;
+                    upload.ranged = Some(true);
+                    save(&upload);
+                    crate::upload::emit_progress(app, &upload.id, end, upload.size);
+                }
+                // Refused before anything was stored: no ranged PUT here.
+                Err(e) if start == 0 && upload.ranged.is_none() && !retryable(e.status) => {
+                    info!(id = %upload.id, status = ?e.status,
+                        "upload: ranges refused, sending the file whole");
+                    upload.ranged = Some(false);
+                    save(&upload);
+                    break;
+                }
+                Err(e) if retryable(e.status) => return Err(format!("{} (resumable)", e.message)),
+                Err(e) => {
+                    forget(&upload.id);
+                    return Err(e.message);
+                }
+            }
+        }
+    }
+
+    if upload.confirmed < upload.size || upload.size == 0 {
+        let client = client(WHOLE_TIMEOUT_SECS)?;
+        let shared = Arc::new(Mutex::new(Sha256::new()));
+        let result = with_retries(&upload.id, "whole file", || {
+            // Each attempt reads the file again from the start.
+            *shared.lock().unwrap_or_else(|e| e.into_inner()) = Sha256::new();
+            let inner = std::fs::File::open(&upload.path).map_err(|e| PutError {
+                status: Some(0),
+                message: e.to_string(),
+            })?;
+            let reader = HashingReader {
+                inner,
+                hasher: shared.clone(),
+                app: app.clone(),
+                id: upload.id.clone(),
+                sent: 0,
+                total: upload.size,
+                last_percent: 0,
+            };
+            let body = reqwest::blocking::Body::sized(reader, upload.size);
+            put(&client, &upload, body, None)
+        });
+        if let Err(e) = result {
+            if !retryable(e.status) {
+                forget(&upload.id);
+            }
+            return Err(e.message);
+        }
+        hasher = std::mem::take(&mut *shared.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    forget(&upload.id);
+    info!(id = %upload.id, size = upload.size, "upload: large file uploaded");
+    Ok(LargeUploadResult {
+        size: upload.size,
+        sha256: BASE64.encode(hasher.finalize()),
+    })
+}
+
+async fn run_blocking(
+    app: tauri::AppHandle,
+    upload: PendingUpload,
+) -> Result<LargeUploadResult, String> {
+    let guard = RunGuard::claim(&upload.id)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let _guard = guard;
+        run(&app, upload)
+    })
+    .await
+    .map_err(|e| format!("upload: task join error: {e}"))?
+}
+
+/// Upload the file at `path` to an XEP-0363 slot, resumably.
+#[tauri::command]
+pub async fn upload_large_file(
+    app: tauri::AppHandle,
+    upload_id: String,
+    path: PathBuf,
+    put_url: String,
+    content_type: String,
+    extra_headers: Option<BTreeMap<String, String>>,
+) -> Result<LargeUploadResult, String> {
+    let metadata = std::fs::metadata(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    let upload = PendingUpload {
+        id: upload_id,
+        path,
+        put_url,
+        content_type,
+        extra_headers: extra_headers.unwrap_or_default().into_iter().collect(),
+        size: metadata.len(),
+        modified: modified_secs(&metadata),
+        confirmed: 0,
+        ranged: None,
+    };
+    if metadata.len() > CHUNK_BYTES {
+        save(&upload);
+    }
+    run_blocking(app, upload).await
+}
+
+/// Continue an upload interrupted earlier, in this run or a previous one.
+#[tauri::command]
+pub async fn resume_large_upload(
+    app: tauri::AppHandle,
+    upload_id: String,
+) -> Result<LargeUploadResult, String> {
+    let upload = pending()
+        .get(&upload_id)
+        .cloned()
+        .ok_or_else(|| format!("No unfinished upload {upload_id}"))?;
+    run_blocking(app, upload).await
+}
+
+#[tauri::command]
+pub fn list_pending_uploads() -> Vec<PendingUpload> {
+    pending().values().cloned().collect()
+}
+
+/// Give up on an unfinished upload. The slot is left to expire.
+#[tauri::command]
+pub fn discard_pending_upload(upload_id: String) {
+    forget(&upload_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_inclusive_and_only_transient_failures_retry() {
+        assert_eq!(
+            content_range(0, CHUNK_BYTES, 20_000_000),
+            "bytes 0-8388607/20000000"
+        );
+        assert_eq!(
+            content_range(16_777_216, 20_000_000, 20_000_000),
+            "bytes 16777216-19999999/20000000"
+        );
+        assert!(retryable(None));
+        assert!(retryable(Some(503)));
+        assert!(retryable(Some(429)));
+        assert!(!retryable(Some(413)));
+        assert!(!retryable(Some(403)));
+    }
+
+    #[test]
+    fn prefix_hash_matches_a_straight_hash() {
+        let path = std::env::temp_dir().join(format!("upload-resume-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"hello world").unwrap();
+        let mut file = std::fs::File::open(&path).unwrap();
+        let mut resumed = Sha256::new();
+        hash_prefix(&mut file, 5, &mut resumed).unwrap();
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        resumed.update(&rest);
+        assert_eq!(resumed.finalize(), Sha256::digest(b"hello world"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
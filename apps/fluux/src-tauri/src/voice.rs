@@ -0,0 +1,464 @@
+//! Voice message recording: microphone capture, Opus-in-Ogg encoding and a
+//! waveform for the message bubble.
+//!
+//! MediaRecorder support differs across WebViews (WKWebView records AAC in
+//! MP4, WebKitGTK depends on the installed GStreamer plugins, permission
+//! prompts reappear after reloads), so recording happens here instead: cpal
+//! captures the default input device on a dedicated thread, and the audio
+//! is downmixed, resampled to 48 kHz and encoded as it arrives, so memory
+//! stays flat however long the message is. The result is an
+//! `audio/ogg; codecs=opus` file ready for upload.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use serde::Serialize;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tauri::Emitter;
+
+const OPUS_RATE: u32 = 48_000;
+/// 20 ms frames, the usual choice for speech.
+const FRAME_SAMPLES: usize = 960;
+const OPUS_BITRATE: i32 = 32_000;
+const MAX_PACKET_BYTES: usize = 4000;
+/// Recording stops by itself after this long.
+const MAX_DURATION: Duration = Duration::from_secs(15 * 60);
+/// Number of bars in the returned waveform.
+const WAVEFORM_BARS: usize = 64;
+const LIMIT_EVENT: &str = "voice-recording-limit-reached";
+const OGG_SERIAL: u32 = 0x464c_5558;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceRecording {
+    pub path: String,
+    pub mime_type: String,
+    pub duration_ms: u64,
+    pub size: u64,
+    /// Peak level per bar, 0–100, oldest first.
+    pub waveform: Vec<u8>,
+}
+
+/// Downmix interleaved frames to mono.
+fn downmix(data: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return data.to_vec();
+    }
+    data.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// Streaming linear-interpolation resampler. Plenty for speech, and free of
+/// the latency and dependencies of a windowed-sinc resampler.
+struct Resampler {
+    /// Input samples per output sample.
+    step: f64,
+    /// Position of the next output sample, relative to `last`.
+    pos: f64,
+    last: f32,
+}
+
+impl Resampler {
+    fn new(input_rate: u32) -> Self {
+        Resampler {
+            step: input_rate as f64 / OPUS_RATE as f64,
+            pos: 0.0,
+            last: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        for &sample in input {
+            while self.pos < 1.0 {
+                out.push(self.last + (sample - self.last) * self.pos as f32);
+                self.pos += self.step;
+            }
+            self.pos -= 1.0;
+            self.last = sample;
+        }
+    }
+}
+
+/// Reduce per-frame peaks to `bars` values scaled to 0–100.
+fn waveform(peaks: &[f32], bars: usize) -> Vec<u8> {
+    if peaks.is_empty() {
+        return Vec::new();
+    }
+    let bars = bars.min(peaks.len());
+    let buckets: Vec<f32> = (0..bars)
+        .map(|i| {
+            let start = i * peaks.len() / bars;
+            let end = ((i + 1) * peaks.len() / bars).max(start + 1);
+            peaks[start..end].iter().copied().fold(0.0, f32::max)
+        })
+        .collect();
+    // Normalise to the loudest bar so quiet microphones still draw a shape.
+    let loudest = buckets.iter().copied().fold(0.0, f32::max);
+    if loudest <= f32::EPSILON {
+        return vec![0; bars];
+    }
+    buckets
+        .iter()
+        .map(|b| ((b / loudest).clamp(0.0, 1.0) * 100.0).round() as u8)
+        .collect()
+}
+
+/// RFC 7845 identification header.
+fn opus_head(pre_skip: u16, input_rate: u32) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1); // version
+    head.push(1); // channels
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&input_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // channel mapping family
+    head
+}
+
+/// RFC 7845 comment header, with no comments.
+fn opus_tags() -> Vec<u8> {
+    let vendor = concat!("Fluux ", env!("CARGO_PKG_VERSION"));
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor.as_bytes());
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    tags
+}
+
+/// Encodes 48 kHz mono audio into an Ogg Opus stream as it is fed.
+struct OggOpusWriter {
+    encoder: opus::Encoder,
+    writer: ogg::PacketWriter<'static, BufWriter<File>>,
+    pre_skip: u64,
+    pending: Vec<f32>,
+    /// Real (unpadded) samples encoded so far.
+    samples: u64,
+    peaks: Vec<f32>,
+}
+
+impl OggOpusWriter {
+    fn create(path: &std::path::Path, input_rate: u32) -> Result<Self, String> {
+        let mut encoder =
+            opus::Encoder::new(OPUS_RATE, opus::Channels::Mono, opus::Application::Voip)
+                .map_err(|e| format!("Failed to create Opus encoder: {e}"))?;
+        encoder
+            .set_bitrate(opus::Bitrate::Bits(OPUS_BITRATE))
+            .map_err(|e| format!("Failed to configure Opus encoder: {e}"))?;
+        let pre_skip = encoder
+            .get_lookahead()
+            .map_err(|e| format!("Failed to configure Opus encoder: {e}"))?
+            .max(0) as u64;
+        let file = File::create(path).map_err(|e| format!("Failed to create recording: {e}"))?;
+        let mut writer = ogg::PacketWriter::new(BufWriter::new(file));
+        // Each header must sit alone on its page.
+        for header in [opus_head(pre_skip as u16, input_rate), opus_tags()] {
+            writer
+                .write_packet(header, OGG_SERIAL, ogg::PacketWriteEndInfo::EndPage, 0)
+                .map_err(|e| format!("Failed to write recording: {e}"))?;
+        }
+        Ok(OggOpusWriter {
+            encoder,
+            writer,
+            pre_skip,
+            pending: Vec::with_capacity(FRAME_SAMPLES * 2),
+            samples: 0,
+            peaks: Vec::new(),
+        })
+    }
+
+    fn write_frame(&mut self, frame: &[f32], real: usize, last: bool) -> Result<(), String> {
+        let packet = self
+            .encoder
+            .encode_vec_float(frame, MAX_PACKET_BYTES)
+            .map_err(|e| format!("Opus encoding failed: {e}"))?;
+        self.samples += real as u64;
+        self.peaks
+            .push(frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs())));
+        let end = if last {
+            ogg::PacketWriteEndInfo::EndStream
+        } else {
+            ogg::PacketWriteEndInfo::NormalPacket
+        };
+        self.writer
+            .write_packet(packet, OGG_SERIAL, end, self.pre_skip + self.samples)
+            .map_err(|e| format!("Failed to write recording: {e}"))
+    }
+
+    fn push(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.pending.extend_from_slice(samples);
+        let full = self.pending.len() / FRAME_SAMPLES * FRAME_SAMPLES;
+        let frames: Vec<f32> = self.pending.drain(..full).collect();
+        for frame in frames.chunks(FRAME_SAMPLES) {
+            self.write_frame(frame, FRAME_SAMPLES, false)?;
+        }
+        Ok(())
+    }
+
+    /// Encode what is left (zero-padded to a frame) and close the stream.
+    /// The final granule position counts only real samples, so players trim
+    /// the padding.
+    fn finish(mut self) -> Result<(u64, Vec<f32>), String> {
+        let real = self.pending.len();
+        let mut frame = std::mem::take(&mut self.pending);
+        frame.resize(FRAME_SAMPLES, 0.0);
+        self.write_frame(&frame, real, true)?;
+        let mut inner = self.writer.into_inner();
+        std::io::Write::flush(&mut inner).map_err(|e| format!("Failed to write recording: {e}"))?;
+        Ok((self.samples, self.peaks))
+    }
+}
+
+struct ActiveRecording {
+    stop: Arc<AtomicBool>,
+    path: PathBuf,
+    worker: JoinHandle<Result<(u64, Vec<f32>), String>>,
+}
+
+/// The recording in progress, if any. One microphone, one recording.
+static ACTIVE: Mutex<Option<ActiveRecording>> = Mutex::new(None);
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    tx: mpsc::Sender<Vec<f32>>,
+) -> Result<cpal::Stream, String>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let channels = config.channels as usize;
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let samples: Vec<f32> = data
+                    .iter()
+                    .map(|s| <f32 as cpal::FromSample<T>>::from_sample_(*s))
+                    .collect();
+                let _ = tx.send(downmix(&samples, channels));
+            },
+            |e| tracing::warn!("Voice recording stream error: {}", e),
+            None,
+        )
+        .map_err(|e| format!("Failed to open microphone: {e}"))
+}
+
+/// Capture thread: owns the (not `Send`) cpal stream and the encoder until
+/// `stop` is raised or the length limit is hit. `ready` reports whether the
+/// microphone could be opened, so the start command can fail synchronously.
+fn record(
+    app: tauri::AppHandle,
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    ready: mpsc::Sender<Result<(), String>>,
+) -> Result<(u64, Vec<f32>), String> {
+    let opened = (|| {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or("No microphone available")?;
+        let supported = device
+            .default_input_config()
+            .map_err(|e| format!("Microphone unavailable: {e}"))?;
+        let input_rate = supported.sample_rate().0;
+        let config: cpal::StreamConfig = supported.config();
+        let (tx, rx) = mpsc::channel();
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, tx),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, tx),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, tx),
+            cpal::SampleFormat::I32 => build_stream::<i32>(&device, &config, tx),
+            other => Err(format!("Unsupported microphone sample format: {other}")),
+        }?;
+        let writer = OggOpusWriter::create(&path, input_rate)?;
+        stream
+            .play()
+            .map_err(|e| format!("Failed to start microphone: {e}"))?;
+        Ok::<_, String>((stream, rx, writer, input_rate))
+    })();
+    let (stream, rx, mut writer, input_rate) = match opened {
+        Ok(opened) => {
+            let _ = ready.send(Ok(()));
+            opened
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e.clone()));
+            return Err(e);
+        }
+    };
+
+    let mut resampler = Resampler::new(input_rate);
+    let mut resampled = Vec::new();
+    let max_samples = MAX_DURATION.as_secs() * OPUS_RATE as u64;
+    while !stop.load(Ordering::Relaxed) {
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(chunk) => {
+                resampled.clear();
+                resampler.process(&chunk, &mut resampled);
+                writer.push(&resampled)?;
+                if writer.samples >= max_samples {
+                    tracing::info!("Voice recording reached the length limit");
+                    let _ = app.emit(LIMIT_EVENT, ());
+                    break;
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    drop(stream);
+    writer.finish()
+}
+
+fn start(app: tauri::AppHandle) -> Result<(), String> {
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    if active.is_some() {
+        return Err("A voice recording is already in progress".to_string());
+    }
+    let path = crate::temp_files::path("voice", &format!("voice-{}.ogg", uuid::Uuid::new_v4()))?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let worker = {
+        let (path, stop) = (path.clone(), stop.clone());
+        std::thread::Builder::new()
+            .name("voice-recorder".into())
+            .spawn(move || record(app, path, stop, ready_tx))
+            .map_err(|e| format!("Failed to start recorder: {e}"))?
+    };
+    match ready_rx.recv() {
+        Ok(Ok(())) => {
+            *active = Some(ActiveRecording { stop, path, worker });
+            Ok(())
+        }
+        Ok(Err(e)) => {
+            crate::temp_files::remove(&path);
+            Err(e)
+        }
+        Err(_) => Err("Recorder exited unexpectedly".to_string()),
+    }
+}
+
+/// Start recording from the default microphone. Fails right away when no
+/// microphone can be opened (missing device or permission denied).
+#[tauri::command]
+pub async fn start_voice_recording(app: tauri::AppHandle) -> Result<(), String> {
+    // Opening the device can take a moment; keep it off the async runtime.
+    tauri::async_runtime::spawn_blocking(move || start(app))
+        .await
+        .map_err(|e| format!("Recorder task panicked: {e}"))?
+}
+
+fn take_active() -> Result<ActiveRecording, String> {
+    ACTIVE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .ok_or_else(|| "No voice recording in progress".to_string())
+}
+
+/// Stop recording and return the encoded file.
+#[tauri::command]
+pub async fn stop_voice_recording() -> Result<VoiceRecording, String> {
+    let active = take_active()?;
+    active.stop.store(true, Ordering::Relaxed);
+    let path = active.path;
+    let (samples, peaks) = tauri::async_runtime::spawn_blocking(move || active.worker.join())
+        .await
+        .map_err(|e| format!("Recorder task panicked: {e}"))?
+        .map_err(|_| "Recorder thread panicked".to_string())??;
+    let size = std::fs::metadata(&path)
+        .map_err(|e| format!("Recording is missing: {e}"))?
+        .len();
+    Ok(VoiceRecording {
+        path: path.to_string_lossy().into_owned(),
+        mime_type: "audio/ogg; codecs=opus".to_string(),
+        duration_ms: samples * 1000 / OPUS_RATE as u64,
+        size,
+        waveform: waveform(&peaks, WAVEFORM_BARS),
+    })
+}
+
+/// Stop recording and throw the audio away.
+#[tauri::command]
+pub async fn cancel_voice_recording() -> Result<(), String> {
+    let active = take_active()?;
+    active.stop.store(true, Ordering::Relaxed);
+    let path = active.path;
+    let _ = tauri::async_runtime::spawn_blocking(move || active.worker.join()).await;
+    crate::temp_files::remove(&path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downmixes_interleaved_stereo() {
+        assert_eq!(downmix(&[1.0, 0.0, 0.5, 0.5], 2), vec![0.5, 0.5]);
+        assert_eq!(downmix(&[0.25], 1), vec![0.25]);
+    }
+
+    #[test]
+    fn resampler_produces_expected_sample_count() {
+        let mut out = Vec::new();
+        let mut resampler = Resampler::new(44_100);
+        for _ in 0..10 {
+            resampler.process(&[0.0; 4410], &mut out);
+        }
+        // One second in, one second out, give or take the last sample.
+        assert!((out.len() as i64 - 48_000).abs() <= 1, "{}", out.len());
+
+        let mut same = Vec::new();
+        Resampler::new(48_000).process(&[0.1, 0.2, 0.3], &mut same);
+        assert_eq!(same.len(), 3);
+    }
+
+    #[test]
+    fn waveform_is_normalised_to_loudest_bar() {
+        assert_eq!(waveform(&[0.1, 0.2, 0.4, 0.2], 2), vec![50, 100]);
+        assert_eq!(waveform(&[0.0, 0.0], 4), vec![0, 0]);
+        assert!(waveform(&[], 64).is_empty());
+    }
+
+    #[test]
+    fn writes_a_playable_ogg_opus_stream() {
+        let path =
+            std::env::temp_dir().join(format!("fluux-voice-test-{}.ogg", uuid::Uuid::new_v4()));
+        let mut writer = OggOpusWriter::create(&path, 44_100).unwrap();
+        let tone: Vec<f32> = (0..48_000 + 100)
+            .map(|i| (i as f32 * 0.05).sin() * 0.5)
+            .collect();
+        writer.push(&tone).unwrap();
+        let (samples, peaks) = writer.finish().unwrap();
+        assert_eq!(samples, 48_100);
+        assert_eq!(peaks.len(), 51);
+
+        let mut reader = ogg::PacketReader::new(File::open(&path).unwrap());
+        let head = reader.read_packet_expected().unwrap();
+        assert!(head.data.starts_with(b"OpusHead"));
+        assert_eq!(
+            u32::from_le_bytes(head.data[12..16].try_into().unwrap()),
+            44_100
+        );
+        let tags = reader.read_packet_expected().unwrap();
+        assert!(tags.data.starts_with(b"OpusTags"));
+        let mut last = None;
+        while let Some(packet) = reader.read_packet().unwrap() {
+            last = Some(packet);
+        }
+        let last = last.unwrap();
+        assert!(last.last_in_stream());
+        assert_eq!(last.absgp_page(), pre_skip_of(&head.data) + 48_100);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn pre_skip_of(head: &[u8]) -> u64 {
+        u16::from_le_bytes([head[10], head[11]]) as u64
+    }
+}
@@ -0,0 +1,250 @@
+//! Recovery from WebView renderer crashes.
+//!
+//! When the process rendering the page dies, the window is left blank (on
+//! Linux, a permanent white window) while the rest of the app, including
+//! the XMPP proxy, keeps running. Each platform's own signal is watched and
+//! the page reloaded; the proxy is untouched, so the fresh page reconnects
+//! through it like after a wake-from-sleep reload.
+//!
+//! - Linux: WebKitGTK's `web-process-terminated`, plus
+//!   `is-web-process-responsive`: a web process unresponsive for
+//!   [`UNRESPONSIVE_GRACE`] is terminated, which then reloads the same way.
+//! - Windows: WebView2's `ProcessFailed`. A failed render process is
+//!   reloaded; a failed browser process takes the whole WebView with it, so
+//!   the app restarts.
+//! - macOS: `webViewWebContentProcessDidTerminate:` on the WKWebView's
+//!   navigation delegate. WebKit offers no unresponsiveness signal there.
+//!
+//! Crash reasons are logged. A page that keeps crashing is reloaded at most
+//! [`MAX_RELOADS`] times per [`RELOAD_WINDOW`], so a crash on load can't
+//! turn into a reload loop.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+const MAX_RELOADS: usize = 3;
+const RELOAD_WINDOW: Duration = Duration::from_secs(5 * 60);
+/// How long the web process may stay unresponsive before it is killed.
+#[cfg(target_os = "linux")]
+const UNRESPONSIVE_GRACE: Duration = Duration::from_secs(15);
+
+/// Recent reloads, oldest first.
+static RELOADS: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+
+/// Whether another reload fits in the budget; records it if so.
+fn allow_reload(history: &mut VecDeque<Instant>, now: Instant) -> bool {
+    while history
+        .front()
+        .is_some_and(|&at| now.duration_since(at) >= RELOAD_WINDOW)
+    {
+        history.pop_front();
+    }
+    if history.len() >= MAX_RELOADS {
+        return false;
+    }
+    history.push_back(now);
+    true
+}
+
+/// Log a renderer failure and decide whether to recover from it.
+#[cfg_attr(
+    not(any(target_os = "linux", target_os = "macos", target_os = "windows")),
+    allow(dead_code)
+)]
+fn crashed(reason: &str) -> bool {
+    let mut history = RELOADS.lock().unwrap_or_else(|e| e.into_inner());
+    if allow_reload(&mut history, Instant::now()) {
+        warn!(reason, "webview: renderer process lost, reloading");
+        true
+    } else {
+        error!(
+            reason,
+            "webview: renderer process lost again, giving up on reloading"
+        );
+        false
+    }
+}
+
+/// Watch the main window's WebView. Called from the Tauri `setup` hook.
+pub fn watch(window: &tauri::WebviewWindow) {
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    platform::watch(window);
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    let _ = window;
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::UNRESPONSIVE_GRACE;
+    use gtk::glib;
+    use tracing::warn;
+    use webkit2gtk::{WebProcessTerminationReason, WebViewExt};
+
+    pub fn watch(window: &tauri::WebviewWindow) {
+        let result = window.with_webview(|webview| {
+            let view = webview.inner();
+            view.connect_web_process_terminated(|view, reason| {
+                let reason = match reason {
+                    WebProcessTerminationReason::Crashed => "crashed",
+                    WebProcessTerminationReason::ExceededMemoryLimit => "exceeded memory limit",
+                    WebProcessTerminationReason::TerminatedByApi => "unresponsive, terminated",
+                    _ => "unknown",
+                };
+                if reason == "crashed" {
+                    // Next launch renders with every GPU workaround.
+                    crate::gpu_workarounds::renderer_crashed();
+                }
+                if super::crashed(reason) {
+                    view.reload();
+                }
+            });
+            view.connect_is_web_process_responsive_notify(|view| {
+                if view.is_web_process_responsive() {
+                    return;
+                }
+                warn!("webview: web process unresponsive");
+                let view = view.clone();
+                glib::timeout_add_local_once(UNRESPONSIVE_GRACE, move || {
+                    if !view.is_web_process_responsive() {
+                        view.terminate_web_process();
+                    }
+                });
+            });
+        });
+        if let Err(e) = result {
+            warn!(error = %e, "webview: crash watchdog not installed");
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use tauri::Manager;
+    use tracing::{error, warn};
+    use webview2_com::Microsoft::Web::WebView2::Win32::{
+        ICoreWebView2, ICoreWebView2ProcessFailedEventArgs, COREWEBVIEW2_PROCESS_FAILED_KIND,
+        COREWEBVIEW2_PROCESS_FAILED_KIND_BROWSER_PROCESS_EXITED,
+        COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_EXITED,
+        COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_UNRESPONSIVE,
+    };
+    use webview2_com::ProcessFailedEventHandler;
+
+    pub fn watch(window: &tauri::WebviewWindow) {
+        let app = window.app_handle().clone();
+        let result = window.with_webview(move |webview| {
+            let handler = ProcessFailedEventHandler::create(Box::new(
+                move |sender: Option<ICoreWebView2>,
+                      args: Option<ICoreWebView2ProcessFailedEventArgs>| {
+                    let mut kind = COREWEBVIEW2_PROCESS_FAILED_KIND::default();
+                    if let Some(args) = args {
+                        // SAFETY: reading a field of the event's own args.
+                        unsafe { args.ProcessFailedKind(&mut kind)? };
+                    }
+                    let reason = match kind {
+                        COREWEBVIEW2_PROCESS_FAILED_KIND_BROWSER_PROCESS_EXITED => {
+                            error!("webview: browser process exited, restarting");
+                            app.restart();
+                        }
+                        COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_EXITED => "exited",
+                        COREWEBVIEW2_PROCESS_FAILED_KIND_RENDER_PROCESS_UNRESPONSIVE => {
+                            "unresponsive"
+                        }
+                        // GPU and utility processes: WebView2 restarts them.
+                        other => {
+                            warn!(kind = other.0, "webview: helper process failed");
+                            return Ok(());
+                        }
+                    };
+                    if let Some(core) = sender.filter(|_| super::crashed(reason)) {
+                        // SAFETY: the sender is the live CoreWebView2.
+                        unsafe { core.Reload()? };
+                    }
+                    Ok(())
+                },
+            ));
+            // SAFETY: called on the WebView's thread with its live controller.
+            let added = unsafe {
+                webview.controller().CoreWebView2().and_then(|core| {
+                    let mut token = 0;
+                    core.add_ProcessFailed(&handler, &mut token)
+                })
+            };
+            if let Err(e) = added {
+                warn!(error = %e, "webview: crash watchdog not installed");
+            }
+        });
+        if let Err(e) = result {
+            warn!(error = %e, "webview: crash watchdog not installed");
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use objc2::runtime::{AnyClass, AnyObject, Imp, Sel};
+    use objc2::{msg_send, sel};
+    use tracing::{info, warn};
+
+    /// `-[WKNavigationDelegate webViewWebContentProcessDidTerminate:]`,
+    /// added to the delegate's class by [`watch`].
+    extern "C-unwind" fn did_terminate(_this: &AnyObject, _cmd: Sel, web_view: &AnyObject) {
+        if super::crashed("web content process terminated") {
+            // SAFETY: WebKit passes the WKWebView whose process ended.
+            let _: *mut AnyObject = unsafe { msg_send![web_view, reload] };
+        }
+    }
+
+    pub fn watch(window: &tauri::WebviewWindow) {
+        let result = window.with_webview(|webview| {
+            // SAFETY: `inner()` is the live WKWebView; the function matches
+            // the `v@:@` signature registered for the selector, and the
+            // delegate outlives the view.
+            unsafe {
+                let Some(view) = (webview.inner() as *mut AnyObject).as_ref() else {
+                    return;
+                };
+                let delegate: *mut AnyObject = msg_send![view, navigationDelegate];
+                let Some(delegate) = delegate.as_ref() else {
+                    warn!("webview: no navigation delegate, crash watchdog not installed");
+                    return;
+                };
+                let class = delegate.class() as *const AnyClass as *mut AnyClass;
+                let imp: Imp = std::mem::transmute(
+                    did_terminate as extern "C-unwind" fn(&AnyObject, Sel, &AnyObject),
+                );
+                let added = objc2::ffi::class_addMethod(
+                    class,
+                    sel!(webViewWebContentProcessDidTerminate:),
+                    imp,
+                    c"v@:@".as_ptr(),
+                );
+                if !added.as_bool() {
+                    info!("webview: the navigation delegate already handles process termination");
+                }
+            }
+        });
+        if let Err(e) = result {
+            warn!(error = %e, "webview: crash watchdog not installed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reloads_are_capped_per_window() {
+        let mut history = VecDeque::new();
+        let start = Instant::now();
+        for i in 0..MAX_RELOADS as u64 {
+            assert!(allow_reload(&mut history, start + Duration::from_secs(i)));
+        }
+        assert!(!allow_reload(&mut history, start + Duration::from_secs(60)));
+        // The first reload ages out of the window.
+        assert!(allow_reload(&mut history, start + RELOAD_WINDOW));
+        assert!(!allow_reload(&mut history, start + RELOAD_WINDOW));
+    }
+}
@@ -0,0 +1,303 @@
+//! Always-on-top, opacity and compact mode for the main window.
+//!
+//! These keep a small chat window pinned over other apps. The window-state
+//! plugin already restores size, position and decorations; the settings it
+//! does not know about are persisted next to its file in
+//! `window-prefs.json` and re-applied at startup by [`apply`].
+//!
+//! Tauri has no window opacity API, so it is set natively: `alphaValue` on
+//! macOS, a layered window on Windows, the GTK widget opacity on Linux
+//! (visible only under a compositing window manager).
+
+use crate::json_file::JsonFile;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{LogicalSize, WebviewWindow};
+use tracing::warn;
+
+/// Lowest opacity accepted; below it the window is too faint to find again.
+const MIN_OPACITY: f64 = 0.3;
+
+/// Compact preset: a narrow, undecorated window.
+const COMPACT_SIZE: (f64, f64) = (380.0, 600.0);
+const COMPACT_MIN_SIZE: (f64, f64) = (320.0, 420.0);
+/// Regular limits, as configured in tauri.conf.json.
+const NORMAL_SIZE: (f64, f64) = (1000.0, 700.0);
+const NORMAL_MIN_SIZE: (f64, f64) = (800.0, 600.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WindowPrefs {
+    pub always_on_top: bool,
+    /// 1.0 is opaque.
+    pub opacity: f64,
+    pub compact: bool,
+    /// Logical size to return to when leaving compact mode.
+    pub normal_size: Option<(f64, f64)>,
+}
+
+impl Default for WindowPrefs {
+    fn default() -> Self {
+        Self {
+            always_on_top: false,
+            opacity: 1.0,
+            compact: false,
+            normal_size: None,
+        }
+    }
+}
+
+fn clamp_opacity(opacity: f64) -> f64 {
+    if opacity.is_finite() {
+        opacity.clamp(MIN_OPACITY, 1.0)
+    } else {
+        1.0
+    }
+}
+
+/// Window preferences, held as managed state.
+pub struct WindowPrefsStore {
+    prefs: Mutex<WindowPrefs>,
+    /// Where prefs are persisted; `None` keeps them in memory.
+    file: Option<JsonFile>,
+}
+
+impl WindowPrefsStore {
+    /// Load persisted prefs. A missing or unreadable file starts from the
+    /// defaults.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut prefs: WindowPrefs = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(prefs) => Some(prefs),
+                Err(e) => {
+                    warn!(error = %e, "window prefs: discarding unreadable file");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        prefs.opacity = clamp_opacity(prefs.opacity);
+        Self {
+            prefs: Mutex::new(prefs),
+            file: path.map(JsonFile::new),
+        }
+    }
+
+    fn get(&self) -> WindowPrefs {
+        *self.prefs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Apply `change` and persist the result.
+    fn update(&self, change: impl FnOnce(&mut WindowPrefs)) -> WindowPrefs {
+        let mut prefs = self.prefs.lock().unwrap_or_else(|e| e.into_inner());
+        change(&mut prefs);
+        self.persist(*prefs);
+        *prefs
+    }
+
+    /// Called with the prefs lock held; see [`crate::json_file`].
+    fn persist(&self, prefs: WindowPrefs) {
+        if let Some(file) = &self.file {
+            file.save(prefs);
+        }
+    }
+}
+
+fn set_native_opacity(window: &WebviewWindow, opacity: f64) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    {
+        let ns_window = window.ns_window().map_err(|e| e.to_string())? as usize;
+        window
+            .run_on_main_thread(move || {
+                // SAFETY: `ns_window` is the live NSWindow behind this Tauri
+                // window, and AppKit is only touched on the main thread.
+                unsafe {
+                    let ns_window = &*(ns_window as *const objc2_app_kit::NSWindow);
+                    ns_window.setAlphaValue(opacity);
+                }
+            })
+            .map_err(|e| e.to_string())
+    }
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::Foundation::{COLORREF, HWND};
+        use windows::Win32::UI::WindowsAndMessaging::{
+            GetWindowLongPtrW, SetLayeredWindowAttributes, SetWindowLongPtrW, GWL_EXSTYLE,
+            LWA_ALPHA, WS_EX_LAYERED,
+        };
+        // Tauri's `HWND` comes from another `windows` major; only the raw
+        // handle is shared.
+        let hwnd = HWND(window.hwnd().map_err(|e| e.to_string())?.0);
+        // SAFETY: `hwnd` is the live top-level window; adding WS_EX_LAYERED
+        // is required before SetLayeredWindowAttributes takes effect.
+        unsafe {
+            let style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE);
+            let _ = SetWindowLongPtrW(hwnd, GWL_EXSTYLE, style | WS_EX_LAYERED.0 as isize);
+            SetLayeredWindowAttributes(
+                hwnd,
+                COLORREF(0),
+                (opacity * 255.0).round() as u8,
+                LWA_ALPHA,
+            )
+            .map_err(|e| e.to_string())
+        }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use gtk::prelude::WidgetExt;
+        // GTK objects are not Send: fetch the widget on the main thread.
+        let target = window.clone();
+        window
+            .run_on_main_thread(move || match target.gtk_window() {
+                Ok(gtk_window) => gtk_window.set_opacity(opacity),
+                Err(e) => warn!(error = %e, "window prefs: no GTK window for opacity"),
+            })
+            .map_err(|e| e.to_string())
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        let _ = (window, opacity);
+        Err("Window opacity is not supported on this platform".to_string())
+    }
+}
+
+fn set_compact(
+    window: &WebviewWindow,
+    compact: bool,
+    normal_size: (f64, f64),
+) -> Result<(), String> {
+    let (size, min_size) = if compact {
+        (COMPACT_SIZE, COMPACT_MIN_SIZE)
+    } else {
+        (normal_size, NORMAL_MIN_SIZE)
+    };
+    if compact {
+        window.unmaximize().map_err(|e| e.to_string())?;
+    }
+    window
+        .set_decorations(!compact)
+        .map_err(|e| e.to_string())?;
+    window
+        .set_min_size(Some(LogicalSize::new(min_size.0, min_size.1)))
+        .map_err(|e| e.to_string())?;
+    window
+        .set_size(LogicalSize::new(size.0, size.1))
+        .map_err(|e| e.to_string())
+}
+
+/// Re-apply persisted prefs to the main window at startup.
+pub fn apply(window: &WebviewWindow, store: &WindowPrefsStore) {
+    let prefs = store.get();
+    if prefs.always_on_top {
+        if let Err(e) = window.set_always_on_top(true) {
+            warn!(error = %e, "window prefs: failed to restore always-on-top");
+        }
+    }
+    if prefs.opacity < 1.0 {
+        if let Err(e) = set_native_opacity(window, prefs.opacity) {
+            warn!(error = %e, "window prefs: failed to restore opacity");
+        }
+    }
+    if prefs.compact {
+        // The window-state plugin already restored the compact size, but the
+        // configured minimum size clamps it back up.
+        let normal = prefs.normal_size.unwrap_or(NORMAL_SIZE);
+        if let Err(e) = set_compact(window, true, normal) {
+            warn!(error = %e, "window prefs: failed to restore compact mode");
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_window_prefs(store: tauri::State<'_, WindowPrefsStore>) -> WindowPrefs {
+    store.get()
+}
+
+#[tauri::command]
+pub fn set_always_on_top(
+    window: WebviewWindow,
+    store: tauri::State<'_, WindowPrefsStore>,
+    enabled: bool,
+) -> Result<WindowPrefs, String> {
+    window
+        .set_always_on_top(enabled)
+        .map_err(|e| e.to_string())?;
+    Ok(store.update(|prefs| prefs.always_on_top = enabled))
+}
+
+/// Set the window opacity, clamped to 0.3–1.0.
+#[tauri::command]
+pub fn set_window_opacity(
+    window: WebviewWindow,
+    store: tauri::State<'_, WindowPrefsStore>,
+    opacity: f64,
+) -> Result<WindowPrefs, String> {
+    let opacity = clamp_opacity(opacity);
+    set_native_opacity(&window, opacity)?;
+    Ok(store.update(|prefs| prefs.opacity = opacity))
+}
+
+/// Switch the compact preset on or off. Leaving it restores the size the
+/// window had before.
+#[tauri::command]
+pub fn set_compact_mode(
+    window: WebviewWindow,
+    store: tauri::State<'_, WindowPrefsStore>,
+    enabled: bool,
+) -> Result<WindowPrefs, String> {
+    let prefs = store.get();
+    if prefs.compact == enabled {
+        return Ok(prefs);
+    }
+    let normal_size = if enabled {
+        let scale = window.scale_factor().map_err(|e| e.to_string())?;
+        let size = window
+            .inner_size()
+            .map_err(|e| e.to_string())?
+            .to_logical::<f64>(scale);
+        (size.width, size.height)
+    } else {
+        prefs.normal_size.unwrap_or(NORMAL_SIZE)
+    };
+    set_compact(&window, enabled, normal_size)?;
+    Ok(store.update(|prefs| {
+        prefs.compact = enabled;
+        prefs.normal_size = Some(normal_size);
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opacity_is_clamped() {
+        assert_eq!(clamp_opacity(0.75), 0.75);
+        assert_eq!(clamp_opacity(0.0), MIN_OPACITY);
+        assert_eq!(clamp_opacity(4.0), 1.0);
+        assert_eq!(clamp_opacity(f64::NAN), 1.0);
+    }
+
+    #[test]
+    fn missing_fields_and_files_use_defaults() {
+        let prefs: WindowPrefs = serde_json::from_str(r#"{"alwaysOnTop":true}"#).unwrap();
+        assert!(prefs.always_on_top);
+        assert_eq!(prefs.opacity, 1.0);
+        assert!(!prefs.compact);
+
+        let path =
+            std::env::temp_dir().join(format!("fluux-window-prefs-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"opacity":0.01}"#).unwrap();
+        assert_eq!(
+            WindowPrefsStore::load(Some(path.clone())).get().opacity,
+            MIN_OPACITY
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            WindowPrefsStore::load(Some(path)).get(),
+            WindowPrefs::default()
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Main-window geometry remembered per monitor layout.
+//!
+//! The window-state plugin keeps a single geometry, so undocking a laptop
+//! (or plugging in a different screen) leaves it off-screen and
+//! `ensure_window_visible` recenters it, and the placement made for the
+//! docked setup is gone by the time the dock is back. Here geometry is kept
+//! per monitor topology: the set of monitor rectangles and scale factors,
+//! hashed into a key. When the window moves or resizes it is recorded
+//! under the current key; when the topology changes (at startup or while
+//! running) the profile saved for the new one, if any, is restored.
+
+use crate::json_file::JsonFile;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{PhysicalPosition, PhysicalSize, WebviewWindow, WindowEvent};
+use tracing::{debug, warn};
+
+/// Topologies remembered; the least recently used is dropped beyond this.
+const MAX_PROFILES: usize = 16;
+
+/// Moves and resizes arrive continuously while dragging; they are written
+/// out at most this often.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(2);
+
+/// One monitor as it contributes to the topology key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct MonitorRect {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    /// Scale factor in thousandths, so the key does not depend on float
+    /// formatting.
+    scale_milli: u32,
+}
+
+/// Stable key for a set of monitors, independent of enumeration order.
+fn topology_key(monitors: &[MonitorRect]) -> String {
+    let mut monitors = monitors.to_vec();
+    monitors.sort();
+    let mut hasher = Sha1::new();
+    for m in &monitors {
+        hasher.update(format!(
+            "{},{},{},{},{};",
+            m.x, m.y, m.width, m.height, m.scale_milli
+        ));
+    }
+    hasher.finalize()[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn current_topology(window: &WebviewWindow) -> Option<String> {
+    let monitors = window.available_monitors().ok()?;
+    if monitors.is_empty() {
+        return None;
+    }
+    let rects: Vec<MonitorRect> = monitors
+        .iter()
+        .map(|m| MonitorRect {
+            x: m.position().x,
+            y: m.position().y,
+            width: m.size().width,
+            height: m.size().height,
+            scale_milli: (m.scale_factor() * 1000.0).round() as u32,
+        })
+        .collect();
+    Some(topology_key(&rects))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Geometry {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    maximized: bool,
+    /// Unix seconds; drives eviction.
+    last_used: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PersistedProfiles {
+    profiles: HashMap<String, Geometry>,
+}
+
+/// Per-topology window geometry, shared with the window event handler.
+pub struct WindowProfiles {
+    profiles: Mutex<HashMap<String, Geometry>>,
+    /// Topology the window was last placed for.
+    current: Mutex<Option<String>>,
+    dirty: AtomicBool,
+    /// Where profiles are persisted; `None` keeps them in memory.
+    file: Option<JsonFile>,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl WindowProfiles {
+    /// Load persisted profiles. A missing or unreadable file starts empty.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let persisted: PersistedProfiles = path
+            .as_ref()
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| match serde_json::from_slice(&bytes) {
+                Ok(profiles) => Some(profiles),
+                Err(e) => {
+                    warn!(error = %e, "window profiles: discarding unreadable file");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            profiles: Mutex::new(persisted.profiles),
+            current: Mutex::new(None),
+            dirty: AtomicBool::new(false),
+            file: path.map(JsonFile::new),
+        }
+    }
+
+    fn record(&self, key: &str, geometry: Geometry) {
+        let mut profiles = self.profiles.lock().unwrap_or_else(|e| e.into_inner());
+        profiles.insert(key.to_string(), geometry);
+        while profiles.len() > MAX_PROFILES {
+            let Some(oldest) = profiles
+                .iter()
+                .filter(|(k, _)| k.as_str() != key)
+                .min_by_key(|(_, g)| g.last_used)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            profiles.remove(&oldest);
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    fn get(&self, key: &str) -> Option<Geometry> {
+        self.profiles
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            .copied()
+    }
+
+    /// Write the profiles if anything changed since the last write.
+    fn persist_if_dirty(&self) {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let Some(file) = &self.file else {
+            return;
+        };
+        let profiles = self.profiles.lock().unwrap_or_else(|e| e.into_inner());
+        let snapshot = PersistedProfiles {
+            profiles: profiles.clone(),
+        };
+        if let Err(e) = file.save_now(&snapshot) {
+            warn!(error = %e, "window profiles: failed to persist");
+        }
+    }
+
+    /// Record where the window is now, under the current topology.
+    fn capture(&self, window: &WebviewWindow, key: &str) {
+        let maximized = window.is_maximized().unwrap_or(false);
+        let geometry = match (maximized, self.get(key)) {
+            // Keep the restored geometry underneath a maximized window.
+            (true, Some(previous)) => Geometry {
+                maximized: true,
+                last_used: now_secs(),
+                ..previous
+            },
+            _ => {
+                let (Ok(position), Ok(size)) = (window.outer_position(), window.inner_size())
+                else {
+                    return;
+                };
+                Geometry {
+                    x: position.x,
+                    y: position.y,
+                    width: size.width,
+                    height: size.height,
+                    maximized,
+                    last_used: now_secs(),
+                }
+            }
+        };
+        self.record(key, geometry);
+    }
+
+    /// Place the window as saved for `key`. Returns `false` without a
+    /// profile for it.
+    fn restore(&self, window: &WebviewWindow, key: &str) -> bool {
+        let Some(geometry) = self.get(key) else {
+            return false;
+        };
+        debug!(topology = key, "Restoring window profile");
+        let _ = window.unmaximize();
+        let _ = window.set_size(PhysicalSize::new(geometry.width, geometry.height));
+        let _ = window.set_position(PhysicalPosition::new(geometry.x, geometry.y));
+        if geometry.maximized {
+            let _ = window.maximize();
+        }
+        self.record(
+            key,
+            Geometry {
+                last_used: now_secs(),
+                ..geometry
+            },
+        );
+        true
+    }
+
+    /// React to the window having moved or resized: restore the profile of
+    /// a newly detected topology, or record the new geometry.
+    fn on_geometry_changed(&self, window: &WebviewWindow) {
+        let Some(key) = current_topology(window) else {
+            return;
+        };
+        let changed = {
+            let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+            let changed = current.as_deref() != Some(key.as_str());
+            *current = Some(key.clone());
+            changed
+        };
+        if changed && self.restore(window, &key) {
+            return;
+        }
+        self.capture(window, &key);
+    }
+}
+
+/// Restore the profile for the monitors present at startup and track the
+/// window from then on. Call before `ensure_window_visible`, which remains
+/// the fallback for a topology seen for the first time.
+pub fn attach(window: &WebviewWindow, profiles: Arc<WindowProfiles>) {
+    if let Some(key) = current_topology(window) {
+        if !profiles.restore(window, &key) {
+            debug!(topology = %key, "No window profile for this monitor layout yet");
+        }
+        *profiles.current.lock().unwrap_or_else(|e| e.into_inner()) = Some(key);
+    }
+
+    let handler_window = window.clone();
+    let handler_profiles = profiles.clone();
+    window.on_window_event(move |event| {
+        if matches!(
+            event,
+            WindowEvent::Moved(_)
+                | WindowEvent::Resized(_)
+                | WindowEvent::ScaleFactorChanged { .. }
+        ) {
+            handler_profiles.on_geometry_changed(&handler_window);
+        }
+    });
+
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(PERSIST_INTERVAL);
+        loop {
+            interval.tick().await;
+            let profiles = profiles.clone();
+            let _ = tokio::task::spawn_blocking(move || profiles.persist_if_dirty()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, width: u32, scale_milli: u32) -> MonitorRect {
+        MonitorRect {
+            x,
+            y: 0,
+            width,
+            height: 1080,
+            scale_milli,
+        }
+    }
+
+    #[test]
+    fn topology_key_ignores_monitor_order() {
+        let laptop = rect(0, 1920, 2000);
+        let external = rect(1920, 2560, 1000);
+        assert_eq!(
+            topology_key(&[laptop, external]),
+            topology_key(&[external, laptop])
+        );
+        assert_ne!(topology_key(&[laptop]), topology_key(&[laptop, external]));
+        assert_ne!(
+            topology_key(&[laptop]),
+            topology_key(&[rect(0, 1920, 1000)])
+        );
+        assert_eq!(topology_key(&[laptop]).len(), 16);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_profiles() {
+        let profiles = WindowProfiles::load(None);
+        let geometry = |last_used| Geometry {
+            x: 0,
+            y: 0,
+            width: 800,
+            height: 600,
+            maximized: false,
+            last_used,
+        };
+        for i in 0..MAX_PROFILES as u64 {
+            profiles.record(&format!("k{i}"), geometry(100 + i));
+        }
+        // The newest key survives even with the oldest timestamp.
+        profiles.record("new", geometry(1));
+        assert!(profiles.get("new").is_some());
+        assert!(profiles.get("k0").is_none());
+        assert!(profiles.get("k1").is_some());
+    }
+}
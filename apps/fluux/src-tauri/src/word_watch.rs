@@ -0,0 +1,375 @@
+//! Watch words: terms (plain words or regular expressions) that raise an
+//! alert whenever an incoming message contains them, e.g. "pager" or a
+//! host name in an ops room.
+//!
+//! Plaintext messages are scanned as the bridge relays them (see
+//! [`crate::xmpp_proxy::tap`]); an encrypted message's body is only a
+//! fallback, so the frontend hands the decrypted payload to
+//! [`scan_decrypted_message`] instead. Room history replayed on join and
+//! our own messages are skipped. A match emits `word-watch-match` and shows
+//! a native notification naming the term; unlike message notifications,
+//! these are not subject to the notification rules.
+//!
+//! Terms are persisted in `word-watch.json`. Matching ignores case; plain
+//! words match whole words only.
+
+use crate::json_file::JsonFile;
+use crate::notifications::backend::{NativeNotification, NavTarget};
+use crate::unread::UnreadCounters;
+use crate::xmpp_proxy::session;
+use crate::xmpp_proxy::stanza::{bare_jid, resource, Element};
+use crate::xmpp_proxy::tap::{Direction, StanzaObserver, TapContext, Verdict};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, RwLock, RwLockWriteGuard};
+use tauri::Emitter;
+use tracing::warn;
+
+const MATCH_EVENT: &str = "word-watch-match";
+/// Longest message text carried in the event and notification.
+const EXCERPT_CHARS: usize = 140;
+/// Compiled size cap for user-supplied expressions.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchTerm {
+    pub term: String,
+    /// `term` is a regular expression rather than a word.
+    #[serde(default)]
+    pub regex: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchMatch {
+    /// Conversation or room JID.
+    pub conversation_id: String,
+    pub groupchat: bool,
+    /// Sender: a bare JID, or the nickname in a room.
+    pub from: String,
+    pub message_id: Option<String>,
+    /// The term as registered.
+    pub term: String,
+    /// The text it matched.
+    pub matched: String,
+    pub excerpt: String,
+}
+
+static TERMS: RwLock<Vec<(WatchTerm, Regex)>> = RwLock::new(Vec::new());
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+fn compile(term: &WatchTerm) -> Result<Regex, String> {
+    let text = term.term.trim();
+    if text.is_empty() {
+        return Err("Empty watch term".to_string());
+    }
+    // Plain words are bounded by the start, the end or a non-word
+    // character, which (unlike `\b`) also works for terms such as `#ops`.
+    let pattern = if term.regex {
+        text.to_string()
+    } else {
+        format!(r"(?:^|\W)({})(?:$|\W)", regex::escape(text))
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid watch term {text}: {e}"))
+}
+
+/// Compile and install `terms`. Returns the still held lock.
+fn install(
+    terms: Vec<WatchTerm>,
+) -> Result<RwLockWriteGuard<'static, Vec<(WatchTerm, Regex)>>, String> {
+    let compiled = terms
+        .into_iter()
+        .map(|term| compile(&term).map(|regex| (term, regex)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut installed = TERMS.write().unwrap_or_else(|e| e.into_inner());
+    *installed = compiled;
+    Ok(installed)
+}
+
+fn terms() -> Vec<WatchTerm> {
+    TERMS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(term, _)| term.clone())
+        .collect()
+}
+
+/// Load the persisted terms from `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("word-watch.json")) else {
+        return;
+    };
+    let loaded: Vec<WatchTerm> = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    // Skip terms a newer regex engine accepted and this one doesn't.
+    let valid = loaded.into_iter().filter(|t| compile(t).is_ok()).collect();
+    let _ = install(valid);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `TERMS` lock held; see [`crate::json_file`].
+fn persist(snapshot: Vec<WatchTerm>) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+/// The first term `body` matches: (term, matched text).
+fn first_match(body: &str) -> Option<(String, String)> {
+    let terms = TERMS.read().unwrap_or_else(|e| e.into_inner());
+    terms.iter().find_map(|(term, regex)| {
+        let captures = regex.captures(body)?;
+        let found = captures.get(1).or_else(|| captures.get(0))?;
+        Some((term.term.clone(), found.as_str().to_string()))
+    })
+}
+
+fn excerpt(body: &str) -> String {
+    let mut excerpt: String = body.chars().take(EXCERPT_CHARS).collect();
+    if excerpt.len() < body.len() {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
+/// Scan one message body; on a match, emit the event and notify.
+fn scan(
+    app: &tauri::AppHandle,
+    conversation_id: &str,
+    groupchat: bool,
+    from: &str,
+    message_id: Option<&str>,
+    body: &str,
+) {
+    let Some((term, matched)) = first_match(body) else {
+        return;
+    };
+    let found = WatchMatch {
+        conversation_id: conversation_id.to_string(),
+        groupchat,
+        from: from.to_string(),
+        message_id: message_id.map(str::to_string),
+        term,
+        matched,
+        excerpt: excerpt(body),
+    };
+    let _ = app.emit(MATCH_EVENT, &found);
+    let notification = NativeNotification {
+        title: format!("“{}” — {}", found.term, found.from),
+        body: found.excerpt.clone(),
+        target: NavTarget {
+            nav_type: if groupchat { "room" } else { "conversation" }.to_string(),
+            nav_target: found.conversation_id.clone(),
+            message_id: found.message_id.clone(),
+            account_id: session::own_jid().map(|jid| bare_jid(&jid).to_string()),
+        },
+        avatar_path: None,
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = crate::notifications::show(notification) {
+            warn!(error = %e, "word watch: notification failed");
+        }
+    });
+}
+
+/// The encrypted payload elements whose `<body>` is only a fallback.
+pub(crate) fn is_encrypted(stanza: &Element) -> bool {
+    stanza.elements().any(|e| {
+        matches!(
+            (e.local_name(), e.ns()),
+            ("openpgp", Some("urn:xmpp:openpgp:0"))
+                | ("encrypted", Some("eu.siacs.conversations.axolotl"))
+                | ("encrypted", Some("urn:xmpp:omemo:2"))
+        )
+    })
+}
+
+/// (conversation, groupchat, sender, body) of a live incoming message worth
+/// scanning.
+fn scannable(
+    stanza: &Element,
+    own_bare: Option<&str>,
+    own_nick: impl Fn(&str) -> Option<String>,
+) -> Option<(String, bool, String, String)> {
+    if stanza.local_name() != "message" || is_encrypted(stanza) {
+        return None;
+    }
+    let body = stanza.child("body", None)?.text();
+    let from = stanza.attr("from")?;
+    let conversation_id = bare_jid(from).to_string();
+    match stanza.attr("type") {
+        Some("error") => None,
+        Some("groupchat") => {
+            let nick = resource(from)?;
+            if stanza.child("delay", Some("urn:xmpp:delay")).is_some()
+                || own_nick(&conversation_id).as_deref() == Some(nick)
+            {
+                return None;
+            }
+            Some((conversation_id, true, nick.to_string(), body))
+        }
+        _ if own_bare == Some(conversation_id.as_str()) => None,
+        _ => Some((conversation_id.clone(), false, conversation_id, body)),
+    }
+}
+
+/// Bridge observer scanning plaintext messages.
+pub struct WordWatch {
+    unread: Arc<UnreadCounters>,
+}
+
+impl WordWatch {
+    pub fn new(unread: Arc<UnreadCounters>) -> Self {
+        WordWatch { unread }
+    }
+}
+
+impl StanzaObserver for WordWatch {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        let Some(app) = ctx.app.filter(|_| ctx.direction == Direction::Inbound) else {
+            return Verdict::Forward;
+        };
+        if TERMS.read().unwrap_or_else(|e| e.into_inner()).is_empty() {
+            return Verdict::Forward;
+        }
+        let own = session::own_jid();
+        let own_bare = own.as_deref().map(bare_jid);
+        if let Some((conversation_id, groupchat, from, body)) =
+            scannable(stanza, own_bare, |room| self.unread.own_room_nick(room))
+        {
+            scan(
+                app,
+                &conversation_id,
+                groupchat,
+                &from,
+                stanza.attr("id"),
+                &body,
+            );
+        }
+        Verdict::Forward
+    }
+}
+
+#[tauri::command]
+pub fn get_watch_terms() -> Vec<WatchTerm> {
+    terms()
+}
+
+/// Replace the watch terms. Refused, leaving the old ones, when a regular
+/// expression doesn't compile.
+#[tauri::command]
+pub fn set_watch_terms(terms: Vec<WatchTerm>) -> Result<Vec<WatchTerm>, String> {
+    let terms: Vec<WatchTerm> = terms
+        .into_iter()
+        .map(|t| WatchTerm {
+            term: t.term.trim().to_string(),
+            regex: t.regex,
+        })
+        .filter(|t| !t.term.is_empty())
+        .collect();
+    let _installed = install(terms.clone())?;
+    persist(terms.clone());
+    Ok(terms)
+}
+
+/// Scan a decrypted message. `payload_xml` is the decrypted `<payload>`
+/// (XEP-0373); live messages only, not archive replays.
+#[tauri::command]
+pub fn scan_decrypted_message(
+    app: tauri::AppHandle,
+    from: String,
+    message_id: Option<String>,
+    payload_xml: String,
+) {
+    let Some(body) = Element::parse(&payload_xml)
+        .and_then(|payload| payload.child("body", None).map(Element::text))
+    else {
+        return;
+    };
+    let conversation_id = bare_jid(&from).to_string();
+    if session::own_jid().as_deref().map(bare_jid) == Some(conversation_id.as_str()) {
+        return;
+    }
+    scan(
+        &app,
+        &conversation_id,
+        false,
+        &conversation_id,
+        message_id.as_deref(),
+        &body,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(text: &str, regex: bool) -> WatchTerm {
+        WatchTerm {
+            term: text.to_string(),
+            regex,
+        }
+    }
+
+    #[test]
+    fn words_match_whole_and_regexes_as_written() {
+        let pager = compile(&term("pager", false)).unwrap();
+        assert!(pager.is_match("PAGER: db01 is down"));
+        assert!(pager.is_match("who has the pager?"));
+        assert!(!pager.is_match("pagerduty is quiet"));
+        let channel = compile(&term("#ops", false)).unwrap();
+        assert!(channel.is_match("see #ops"));
+        let hosts = compile(&term(r"db\d+\.prod", true)).unwrap();
+        assert_eq!(
+            hosts.find("db12.prod timeout").unwrap().as_str(),
+            "db12.prod"
+        );
+        assert!(compile(&term("db(", true)).is_err());
+        assert!(compile(&term("  ", false)).is_err());
+    }
+
+    #[test]
+    fn only_live_messages_from_others_are_scanned() {
+        let own_nick = |_: &str| Some("me".to_string());
+        let own = Some("me@example.com");
+        let parse = |xml: &str| Element::parse(xml).unwrap();
+
+        let chat =
+            parse("<message from='bob@example.com/pc' type='chat'><body>pager</body></message>");
+        let (conversation, groupchat, from, body) = scannable(&chat, own, own_nick).unwrap();
+        assert_eq!(
+            (conversation.as_str(), groupchat, from.as_str()),
+            ("bob@example.com", false, "bob@example.com")
+        );
+        assert_eq!(body, "pager");
+
+        let room = parse(
+            "<message from='ops@conf.example.com/alice' type='groupchat'><body>pager</body>\
+             </message>",
+        );
+        assert_eq!(scannable(&room, own, own_nick).unwrap().2, "alice");
+        let reflected = parse(
+            "<message from='ops@conf.example.com/me' type='groupchat'><body>pager</body></message>",
+        );
+        assert!(scannable(&reflected, own, own_nick).is_none());
+        let history = parse(
+            "<message from='ops@conf.example.com/alice' type='groupchat'><body>pager</body>\
+             <delay xmlns='urn:xmpp:delay' stamp='2026-01-01T00:00:00Z'/></message>",
+        );
+        assert!(scannable(&history, own, own_nick).is_none());
+        let encrypted = parse(
+            "<message from='bob@example.com/pc' type='chat'><body>[encrypted]</body>\
+             <openpgp xmlns='urn:xmpp:openpgp:0'>AAAA</openpgp></message>",
+        );
+        assert!(scannable(&encrypted, own, own_nick).is_none());
+    }
+}
@@ -0,0 +1,251 @@
+//! Bridge throughput and latency measurement (hidden `--bench-bridge` flag).
+//!
+//! Runs the real bridge between a loopback WebSocket client and a loopback
+//! TCP server, then pumps synthetic workloads through it: a flood of small
+//! stream-management acks, MAM pages of ordinary messages, a few stanzas near
+//! the size limit, and ping round-trips. The upstream is plain TCP, so TLS
+//! record overhead is not included; everything else on the path (framing,
+//! stanza extraction, the tap) is. Results go to stdout.
+//!
+//! The framing functions alone have criterion benchmarks in `bench/`.
+
+use super::supervisor::ConnectionSupervisor;
+use super::{bridge_websocket_tls, NEXT_PROXY_CONNECTION_ID};
+use futures_util::{SinkExt, StreamExt};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Time allowed for one workload before it counts as stalled.
+const WORKLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+struct Throughput {
+    name: &'static str,
+    stanzas: usize,
+    bytes: usize,
+    elapsed: Duration,
+}
+
+/// A bridged client and the server end of its upstream connection.
+async fn connect() -> Result<(Client, TcpStream), String> {
+    let upstream_listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| format!("bind upstream: {e}"))?;
+    let upstream_addr = upstream_listener.local_addr().map_err(|e| e.to_string())?;
+    let ws_listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| format!("bind WebSocket: {e}"))?;
+    let ws_url = format!(
+        "ws://{}",
+        ws_listener.local_addr().map_err(|e| e.to_string())?
+    );
+
+    tokio::spawn(async move {
+        let Ok((stream, _)) = ws_listener.accept().await else {
+            return;
+        };
+        let Ok(ws) = tokio_tungstenite::accept_async(stream).await else {
+            return;
+        };
+        let Ok(upstream) = TcpStream::connect(upstream_addr).await else {
+            return;
+        };
+        let _ = upstream.set_nodelay(true);
+        let conn_id = NEXT_PROXY_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        let (_shutdown, shutdown_rx) = tokio::sync::broadcast::channel(1);
+        let supervisor = Arc::new(ConnectionSupervisor::new(conn_id, None));
+        let _ = bridge_websocket_tls(
+            ws,
+            upstream,
+            shutdown_rx,
+            None,
+            Vec::new(),
+            conn_id,
+            supervisor,
+        )
+        .await;
+    });
+
+    let (client, _) = tokio_tungstenite::connect_async(ws_url)
+        .await
+        .map_err(|e| format!("connect WebSocket: {e}"))?;
+    let (server, _) = upstream_listener
+        .accept()
+        .await
+        .map_err(|e| format!("accept upstream: {e}"))?;
+    let _ = server.set_nodelay(true);
+    Ok((client, server))
+}
+
+/// Send `stanzas` from the server and time until the client has them all.
+async fn pump(name: &'static str, stanzas: Vec<String>) -> Result<Throughput, String> {
+    let (mut client, mut server) = connect().await?;
+    let count = stanzas.len();
+    let bytes = stanzas.iter().map(String::len).sum();
+    let started = Instant::now();
+    let writer = tokio::spawn(async move {
+        // Batch writes the way a server flushes its socket buffer.
+        let mut batch = String::with_capacity(64 * 1024);
+        for stanza in stanzas {
+            batch.push_str(&stanza);
+            if batch.len() >= 64 * 1024 {
+                server.write_all(batch.as_bytes()).await?;
+                batch.clear();
+            }
+        }
+        server.write_all(batch.as_bytes()).await?;
+        // Keep the upstream open until the client is done reading.
+        let mut sink = [0u8; 1024];
+        while server.read(&mut sink).await? > 0 {}
+        Ok::<(), std::io::Error>(())
+    });
+    let mut received = 0;
+    let read_all = async {
+        while received < count {
+            match client.next().await {
+                Some(Ok(Message::Text(_))) => received += 1,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(format!("{name}: WebSocket read: {e}")),
+                None => return Err(format!("{name}: bridge closed after {received} stanzas")),
+            }
+        }
+        Ok(())
+    };
+    tokio::time::timeout(WORKLOAD_TIMEOUT, read_all)
+        .await
+        .map_err(|_| format!("{name}: stalled after {received} of {count} stanzas"))??;
+    let elapsed = started.elapsed();
+    let _ = client.close(None).await;
+    writer.abort();
+    Ok(Throughput {
+        name,
+        stanzas: count,
+        bytes,
+        elapsed,
+    })
+}
+
+/// Round-trip times for `count` pings echoed by the server.
+async fn round_trips(count: usize) -> Result<Vec<Duration>, String> {
+    let (mut client, server) = connect().await?;
+    let echo = tokio::spawn(async move {
+        let (mut read, mut write) = server.into_split();
+        let _ = tokio::io::copy(&mut read, &mut write).await;
+    });
+    let mut samples = Vec::with_capacity(count);
+    for i in 0..count {
+        let ping = format!("<iq type='get' id='bench-{i}'><ping xmlns='urn:xmpp:ping'/></iq>");
+        let started = Instant::now();
+        client
+            .send(Message::Text(ping.into()))
+            .await
+            .map_err(|e| format!("latency: WebSocket write: {e}"))?;
+        loop {
+            match tokio::time::timeout(WORKLOAD_TIMEOUT, client.next()).await {
+                Ok(Some(Ok(Message::Text(_)))) => break,
+                Ok(Some(Ok(_))) => {}
+                Ok(Some(Err(e))) => return Err(format!("latency: WebSocket read: {e}")),
+                Ok(None) => return Err("latency: bridge closed".to_string()),
+                Err(_) => return Err(format!("latency: ping {i} unanswered")),
+            }
+        }
+        samples.push(started.elapsed());
+    }
+    let _ = client.close(None).await;
+    echo.abort();
+    Ok(samples)
+}
+
+fn sm_acks(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|h| format!("<a xmlns='urn:xmpp:sm:3' h='{h}'/>"))
+        .collect()
+}
+
+/// MAM results with bodies of `body_len` bytes.
+fn mam_page(count: usize, body_len: usize) -> Vec<String> {
+    let body = "x".repeat(body_len);
+    (0..count)
+        .map(|i| {
+            format!(
+                "<message to='juliet@example.com/desk'>\
+                 <result xmlns='urn:xmpp:mam:2' queryid='q' id='mam-{i}'>\
+                 <forwarded xmlns='urn:xmpp:forward:0'>\
+                 <delay xmlns='urn:xmpp:delay' stamp='2026-01-01T00:00:00Z'/>\
+                 <message xmlns='jabber:client' from='romeo@example.net/a' \
+                 to='juliet@example.com' type='chat' id='m{i}'><body>{body}</body>\
+                 </message></forwarded></result></message>"
+            )
+        })
+        .collect()
+}
+
+fn percentile(sorted: &[Duration], pct: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    sorted[(sorted.len() - 1) * pct / 100]
+}
+
+fn report(results: &[Throughput], mut latencies: Vec<Duration>) -> String {
+    let mut out = format!(
+        "{:<14} {:>9} {:>10} {:>10} {:>12} {:>8}\n",
+        "workload", "stanzas", "MB", "ms", "stanzas/s", "MB/s"
+    );
+    for r in results {
+        let secs = r.elapsed.as_secs_f64().max(f64::EPSILON);
+        let mb = r.bytes as f64 / (1024.0 * 1024.0);
+        out.push_str(&format!(
+            "{:<14} {:>9} {:>10.1} {:>10.1} {:>12.0} {:>8.1}\n",
+            r.name,
+            r.stanzas,
+            mb,
+            secs * 1000.0,
+            r.stanzas as f64 / secs,
+            mb / secs
+        ));
+    }
+    latencies.sort();
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    out.push_str(&format!(
+        "round trip ({} pings): p50 {:.3} ms, p99 {:.3} ms, max {:.3} ms\n",
+        latencies.len(),
+        ms(percentile(&latencies, 50)),
+        ms(percentile(&latencies, 99)),
+        ms(latencies.last().copied().unwrap_or_default())
+    ));
+    out
+}
+
+/// Run every workload and return the report.
+pub async fn run() -> Result<String, String> {
+    let results = vec![
+        pump("sm-acks", sm_acks(100_000)).await?,
+        pump("mam-pages", mam_page(5_000, 512)).await?,
+        pump("huge-stanzas", mam_page(8, 512 * 1024)).await?,
+    ];
+    let latencies = round_trips(2_000).await?;
+    Ok(report(&results, latencies))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn workloads_pass_through_the_bridge() {
+        let acks = pump("sm-acks", sm_acks(500)).await.unwrap();
+        assert_eq!(acks.stanzas, 500);
+        let pages = pump("mam-pages", mam_page(20, 64)).await.unwrap();
+        assert_eq!(pages.stanzas, 20);
+        let latencies = round_trips(10).await.unwrap();
+        let text = report(&[acks, pages], latencies);
+        assert!(text.contains("round trip (10 pings)"));
+    }
+}
@@ -0,0 +1,135 @@
+//! Read buffer of the bridge's upstream reader.
+//!
+//! Bytes are read straight into a [`BytesMut`] that also holds the partial
+//! stanza waiting for its end, so there is no intermediate stack buffer to
+//! copy from, and consumed stanzas are dropped from the front without
+//! moving the rest. The read size adapts to the traffic: it doubles while
+//! reads fill it (archive catch-up, roster pushes) and halves back after a
+//! run of small ones, so an idle connection doesn't pin a large buffer.
+//! Buffers are pooled, so reconnects reuse the memory of closed bridges.
+
+use bytes::{Buf, BufMut, BytesMut};
+use std::sync::Mutex;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const MIN_READ: usize = 4 * 1024;
+const MAX_READ: usize = 64 * 1024;
+/// Small reads in a row before the read size is halved.
+const SHRINK_AFTER: u8 = 8;
+/// Buffers kept for reuse.
+const POOL_SIZE: usize = 4;
+/// Buffers that grew past this (a huge stanza) are freed, not pooled.
+const MAX_POOLED_CAPACITY: usize = 256 * 1024;
+
+static POOL: Mutex<Vec<BytesMut>> = Mutex::new(Vec::new());
+
+pub(super) struct ReadBuffer {
+    data: BytesMut,
+    read_size: usize,
+    small_reads: u8,
+}
+
+impl ReadBuffer {
+    pub(super) fn new() -> Self {
+        let data = POOL
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop()
+            .unwrap_or_else(|| BytesMut::with_capacity(MIN_READ));
+        ReadBuffer {
+            data,
+            read_size: MIN_READ,
+            small_reads: 0,
+        }
+    }
+
+    /// Read once from `reader`, appending to the buffered bytes. `Ok(0)` at
+    /// the end of the stream.
+    pub(super) async fn read_from<R>(&mut self, reader: &mut R) -> std::io::Result<usize>
+    where
+        R: AsyncRead + Unpin,
+    {
+        self.data.reserve(self.read_size);
+        let n = reader
+            .read_buf(&mut (&mut self.data).limit(self.read_size))
+            .await?;
+        self.adapt(n);
+        Ok(n)
+    }
+
+    fn adapt(&mut self, read: usize) {
+        if read == self.read_size {
+            self.read_size = (self.read_size * 2).min(MAX_READ);
+            self.small_reads = 0;
+        } else if read < self.read_size / 4 {
+            self.small_reads += 1;
+            if self.small_reads >= SHRINK_AFTER {
+                self.read_size = (self.read_size / 2).max(MIN_READ);
+                self.small_reads = 0;
+            }
+        } else {
+            self.small_reads = 0;
+        }
+    }
+
+    /// Bytes read and not consumed yet.
+    pub(super) fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Drop the first `n` bytes.
+    pub(super) fn consume(&mut self, n: usize) {
+        self.data.advance(n);
+    }
+}
+
+impl Drop for ReadBuffer {
+    fn drop(&mut self) {
+        let mut data = std::mem::take(&mut self.data);
+        if data.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        data.clear();
+        let mut pool = POOL.lock().unwrap_or_else(|e| e.into_inner());
+        if pool.len() < POOL_SIZE {
+            pool.push(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_size_grows_with_full_reads_and_shrinks_when_idle() {
+        let mut buffer = ReadBuffer::new();
+        for _ in 0..10 {
+            let size = buffer.read_size;
+            buffer.adapt(size);
+        }
+        assert_eq!(buffer.read_size, MAX_READ);
+        for _ in 0..SHRINK_AFTER {
+            buffer.adapt(100);
+        }
+        assert_eq!(buffer.read_size, MAX_READ / 2);
+        for _ in 0..200 {
+            buffer.adapt(100);
+        }
+        assert_eq!(buffer.read_size, MIN_READ);
+    }
+
+    #[tokio::test]
+    async fn reads_and_consumes_without_losing_the_tail() {
+        let mut upstream: &[u8] = b"<presence/><message><bo";
+        let mut buffer = ReadBuffer::new();
+        let n = buffer.read_from(&mut upstream).await.unwrap();
+        assert_eq!(n, 23);
+        buffer.consume("<presence/>".len());
+        assert_eq!(buffer.bytes(), b"<message><bo");
+        let mut rest: &[u8] = b"dy/></message>";
+        buffer.read_from(&mut rest).await.unwrap();
+        assert_eq!(buffer.bytes(), b"<message><body/></message>");
+        assert_eq!(buffer.read_from(&mut rest).await.unwrap(), 0);
+    }
+}
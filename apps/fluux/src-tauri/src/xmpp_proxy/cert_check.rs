@@ -0,0 +1,351 @@
+//! Early warnings about the server certificate.
+//!
+//! A self-hosted server whose certificate renewal silently broke keeps
+//! working until the day it expires, then locks every user out at once. On
+//! each connection the bridge looks at the leaf certificate the server
+//! presented and reports, as a `certificate-warning` event, when it expires
+//! within [`EXPIRY_WARNING_DAYS`] or carries no embedded Signed Certificate
+//! Timestamps (RFC 6962), which browsers require of publicly trusted
+//! certificates and whose absence usually means a private or misissued one.
+//!
+//! Only SCTs embedded in the certificate are seen: rustls does not request
+//! them over the TLS extension or OCSP. The certificate has already been
+//! verified by then; this parses just enough DER to read the validity and
+//! the extension identifiers.
+//!
+//! A certificate that fails verification because it is for other names is
+//! described by a [`NameMismatch`] in the `failed` connection phase: the
+//! names it is for, the one expected, and the `?domain=` server setting
+//! (see `parse_server_input`) that would check it against its own name.
+
+use super::dns::{ConnectionMode, XmppEndpoint};
+use serde::Serialize;
+
+/// Warn when the leaf expires within this many days.
+pub(super) const EXPIRY_WARNING_DAYS: u64 = 14;
+
+/// DER encoding of the SCT list extension OID, 1.3.6.1.4.1.11129.2.4.2.
+const SCT_LIST_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xd6, 0x79, 0x02, 0x04, 0x02];
+
+const SEQUENCE: u8 = 0x30;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const OID: u8 = 0x06;
+const VERSION: u8 = 0xa0;
+const EXTENSIONS: u8 = 0xa3;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum CertificateWarning {
+    /// The leaf expires in `days_left` days (0 once it is the last day).
+    #[serde(rename_all = "camelCase")]
+    ExpiresSoon { days_left: u64, not_after: u64 },
+    /// No embedded Signed Certificate Timestamps.
+    NoSct,
+}
+
+/// Marker `upgrade_to_tls` puts before the names of a certificate that is
+/// not valid for the expected one (`(cert-names: a b)`), so they survive the
+/// endpoint aggregation like the `tls-error:` class.
+pub(super) const NAMES_MARKER: &str = "cert-names: ";
+
+/// A server certificate that is not valid for the name checked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NameMismatch {
+    pub expected: String,
+    /// Its subjectAltName entries.
+    pub presented: Vec<String>,
+    /// Server setting that verifies the certificate against one of its own
+    /// names (`tls://host:port?domain=name`), when it has a usable one.
+    pub suggested_server: Option<String>,
+}
+
+impl NameMismatch {
+    pub(super) fn new(endpoint: &XmppEndpoint, presented: Vec<String>) -> Self {
+        let name = presented
+            .iter()
+            .find(|name| **name == endpoint.host)
+            .or_else(|| presented.iter().find(|name| !name.starts_with("*.")));
+        let host = if endpoint.host.contains(':') {
+            format!("[{}]", endpoint.host)
+        } else {
+            endpoint.host.clone()
+        };
+        let scheme = match endpoint.mode {
+            ConnectionMode::DirectTls => "tls",
+            ConnectionMode::Tcp => "tcp",
+        };
+        NameMismatch {
+            expected: endpoint.tls_name().to_string(),
+            suggested_server: name
+                .map(|name| format!("{scheme}://{host}:{}?domain={name}", endpoint.port)),
+            presented,
+        }
+    }
+}
+
+/// webpki lists names as `DnsName("chat.example.net")`; keep the name.
+fn presented_name(entry: &str) -> String {
+    entry
+        .split_once('(')
+        .and_then(|(_, rest)| rest.strip_suffix(')'))
+        .unwrap_or(entry)
+        .trim_matches('"')
+        .to_string()
+}
+
+/// The names of a certificate that failed name verification, from a TLS
+/// handshake error; `None` for any other failure.
+pub(super) fn presented_names(error: &std::io::Error) -> Option<Vec<String>> {
+    let error = error.get_ref()?.downcast_ref::<rustls::Error>()?;
+    match error {
+        rustls::Error::InvalidCertificate(rustls::CertificateError::NotValidForNameContext {
+            presented,
+            ..
+        }) => Some(
+            presented
+                .iter()
+                .map(|entry| presented_name(entry))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// The names [`NAMES_MARKER`] introduces in a connect error.
+pub(super) fn names_from_error(message: &str) -> Option<Vec<String>> {
+    let start = message.find(NAMES_MARKER)? + NAMES_MARKER.len();
+    let rest = &message[start..];
+    let end = rest.find(')').unwrap_or(rest.len());
+    Some(rest[..end].split_whitespace().map(str::to_string).collect())
+}
+
+/// Split one DER TLV off `input`: its tag, contents, and what follows.
+fn tlv(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | b as usize);
+        (len, &rest[count..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// `tlv`, requiring `tag`.
+fn expect(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match tlv(input)? {
+        (found, contents, rest) if found == tag => Some((contents, rest)),
+        _ => None,
+    }
+}
+
+fn digits(text: &[u8]) -> Option<u64> {
+    if text.is_empty() || !text.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(text.iter().fold(0, |n, d| n * 10 + u64::from(d - b'0')))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u64, day: u64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Seconds since the epoch for a UTCTime or GeneralizedTime in the
+/// `…HHMMSSZ` form certificates use (RFC 5280 §4.1.2.5).
+fn parse_time(tag: u8, text: &[u8]) -> Option<u64> {
+    let (year, rest) = match tag {
+        UTC_TIME => {
+            let yy = digits(text.get(..2)?)? as i64;
+            (if yy < 50 { 2000 + yy } else { 1900 + yy }, &text[2..])
+        }
+        GENERALIZED_TIME => (digits(text.get(..4)?)? as i64, &text[4..]),
+        _ => return None,
+    };
+    if rest.len() != 11 || rest[10] != b'Z' {
+        return None;
+    }
+    let field = |at: usize| digits(&rest[at..at + 2]);
+    let (month, day) = (field(0)?, field(2)?);
+    let (hour, minute, second) = (field(4)?, field(6)?, field(8)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + (hour * 3_600 + minute * 60 + second) as i64;
+    u64::try_from(secs).ok()
+}
+
+/// The leaf's notAfter (epoch seconds) and whether it embeds SCTs.
+fn parse_leaf(der: &[u8]) -> Option<(u64, bool)> {
+    let (certificate, _) = expect(der, SEQUENCE)?;
+    let (tbs, _) = expect(certificate, SEQUENCE)?;
+    let mut rest = tbs;
+    if rest.first() == Some(&VERSION) {
+        rest = tlv(rest)?.2;
+    }
+    // serialNumber, signature, issuer.
+    for _ in 0..3 {
+        rest = tlv(rest)?.2;
+    }
+    let (validity, after_validity) = expect(rest, SEQUENCE)?;
+    let (_, _, not_after) = tlv(validity)?;
+    let (tag, not_after, _) = tlv(not_after)?;
+    let not_after = parse_time(tag, not_after)?;
+    // subject, subjectPublicKeyInfo.
+    rest = tlv(tlv(after_validity)?.2)?.2;
+
+    let mut has_sct = false;
+    while let Some((tag, contents, next)) = tlv(rest) {
+        rest = next;
+        if tag != EXTENSIONS {
+            continue;
+        }
+        let (mut extensions, _) = expect(contents, SEQUENCE)?;
+        while let Some((extension, next)) = expect(extensions, SEQUENCE) {
+            extensions = next;
+            if expect(extension, OID).is_some_and(|(oid, _)| oid == SCT_LIST_OID) {
+                has_sct = true;
+            }
+        }
+    }
+    Some((not_after, has_sct))
+}
+
+/// Warnings for the leaf certificate `der`, as of `now` (epoch seconds).
+/// A certificate that can't be parsed yields none.
+pub(super) fn inspect(der: &[u8], now: u64) -> Vec<CertificateWarning> {
+    let Some((not_after, has_sct)) = parse_leaf(der) else {
+        return Vec::new();
+    };
+    let mut warnings = Vec::new();
+    let days_left = not_after.saturating_sub(now) / 86_400;
+    if days_left < EXPIRY_WARNING_DAYS {
+        warnings.push(CertificateWarning::ExpiresSoon {
+            days_left,
+            not_after,
+        });
+    }
+    if !has_sct {
+        warnings.push(CertificateWarning::NoSct);
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if contents.len() < 0x80 {
+            out.push(contents.len() as u8);
+        } else {
+            out.push(0x82);
+            out.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+        }
+        out.extend_from_slice(contents);
+        out
+    }
+
+    /// A skeletal certificate: only the fields `parse_leaf` walks are real.
+    fn certificate(not_after: &str, extension_oid: &[u8]) -> Vec<u8> {
+        let name = encode(SEQUENCE, &[]);
+        let validity = [
+            encode(UTC_TIME, b"260101000000Z"),
+            encode(GENERALIZED_TIME, not_after.as_bytes()),
+        ]
+        .concat();
+        let extension = encode(SEQUENCE, &encode(OID, extension_oid));
+        let tbs = [
+            encode(VERSION, &encode(0x02, &[2])),
+            encode(0x02, &[1]),
+            encode(SEQUENCE, &[]),
+            name.clone(),
+            encode(SEQUENCE, &validity),
+            name,
+            encode(SEQUENCE, &[]),
+            encode(EXTENSIONS, &encode(SEQUENCE, &extension)),
+        ]
+        .concat();
+        encode(
+            SEQUENCE,
+            &[encode(SEQUENCE, &tbs), encode(SEQUENCE, &[])].concat(),
+        )
+    }
+
+    #[test]
+    fn warns_about_expiry_and_missing_scts() {
+        // 2026-10-15T00:00:00Z.
+        let now = 1_792_022_400;
+        assert_eq!(parse_time(GENERALIZED_TIME, b"20261015000000Z"), Some(now));
+        assert_eq!(parse_time(UTC_TIME, b"261015000000Z"), Some(now));
+
+        let healthy = certificate("20270101000000Z", SCT_LIST_OID);
+        assert!(inspect(&healthy, now).is_empty());
+
+        let expiring = certificate("20261020000000Z", &[0x55, 0x1d, 0x11]);
+        assert_eq!(
+            inspect(&expiring, now),
+            vec![
+                CertificateWarning::ExpiresSoon {
+                    days_left: 5,
+                    not_after: now + 5 * 86_400,
+                },
+                CertificateWarning::NoSct,
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_certificates_are_ignored() {
+        assert!(inspect(&[], 0).is_empty());
+        assert!(inspect(&[0x30, 0x84, 0xff, 0xff, 0xff, 0xff], 0).is_empty());
+        let cert = certificate("20270101000000Z", SCT_LIST_OID);
+        assert!(inspect(&cert[..cert.len() / 2], 0).is_empty());
+    }
+
+    #[test]
+    fn describes_a_name_mismatch_with_a_workaround() {
+        let error = "TLS handshake failed with example.com (tls-error: certificate-name-mismatch) \
+                     (cert-names: chat.example.net *.example.net): invalid peer certificate";
+        let presented = names_from_error(error).unwrap();
+        assert_eq!(presented, ["chat.example.net", "*.example.net"]);
+        assert_eq!(
+            presented_name("DnsName(\"chat.example.net\")"),
+            "chat.example.net"
+        );
+
+        let endpoint = XmppEndpoint {
+            host: "xmpp.example.com".to_string(),
+            port: 5223,
+            mode: ConnectionMode::DirectTls,
+            domain: Some("example.com".to_string()),
+        };
+        let mismatch = NameMismatch::new(&endpoint, presented);
+        assert_eq!(mismatch.expected, "example.com");
+        assert_eq!(
+            mismatch.suggested_server.as_deref(),
+            Some("tls://xmpp.example.com:5223?domain=chat.example.net")
+        );
+        assert!(names_from_error("TLS handshake failed (tls-error: timeout): x").is_none());
+    }
+}
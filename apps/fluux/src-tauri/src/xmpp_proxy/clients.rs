@@ -0,0 +1,148 @@
+//! Registry of the WebSocket clients currently attached to the proxy.
+//!
+//! A WebView reload leaves the Rust process, and therefore the proxy, running
+//! while the page that owned the old sockets is gone. A socket whose peer
+//! vanished without a close frame stays "connected" until the bridge's
+//! inactivity watchdog notices, minutes later, and keeps a bridged XMPP
+//! session alive meanwhile. When the new page asks for the proxy again,
+//! [`probe_all`] pings every bridged client and the bridge drops those that
+//! do not answer within [`CLIENT_PROBE_TIMEOUT`]. The registry also backs
+//! `get_proxy_status`.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// How long a probed client has to answer the ping before its socket is
+/// considered orphaned. Pongs are sent by the WebView's network stack, so a
+/// live client answers within milliseconds.
+pub(super) const CLIENT_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Where a client connection is in its lifetime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientPhase {
+    /// WebSocket accepted, waiting for the initial `<open/>`.
+    Handshake,
+    /// Resolving and connecting upstream.
+    Connecting,
+    /// Relaying between the WebSocket and the server.
+    Bridged,
+}
+
+struct Client {
+    phase: ClientPhase,
+    opened: Instant,
+    probe: Arc<Notify>,
+}
+
+static CLIENTS: Mutex<BTreeMap<u64, Client>> = Mutex::new(BTreeMap::new());
+
+fn clients() -> std::sync::MutexGuard<'static, BTreeMap<u64, Client>> {
+    CLIENTS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A client connection as reported by `get_proxy_status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientStatus {
+    pub conn_id: u64,
+    pub phase: ClientPhase,
+    pub age_ms: u64,
+}
+
+/// Keeps `conn_id` in the registry until dropped.
+pub(super) struct Registration {
+    conn_id: u64,
+}
+
+/// Add a freshly accepted client.
+pub(super) fn register(conn_id: u64) -> Registration {
+    clients().insert(
+        conn_id,
+        Client {
+            phase: ClientPhase::Handshake,
+            opened: Instant::now(),
+            probe: Arc::new(Notify::new()),
+        },
+    );
+    Registration { conn_id }
+}
+
+impl Registration {
+    pub(super) fn set_phase(&self, phase: ClientPhase) {
+        if let Some(client) = clients().get_mut(&self.conn_id) {
+            client.phase = phase;
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        clients().remove(&self.conn_id);
+    }
+}
+
+/// Signal the bridge of `conn_id` waits on for probe requests.
+pub(super) fn probe_signal(conn_id: u64) -> Arc<Notify> {
+    clients()
+        .get(&conn_id)
+        .map(|client| client.probe.clone())
+        // Not registered (tests drive the bridge directly): never probed.
+        .unwrap_or_default()
+}
+
+/// Ask every bridged client to prove it is still there. Returns how many
+/// were probed.
+pub(super) fn probe_all() -> usize {
+    clients()
+        .values()
+        .filter(|client| client.phase == ClientPhase::Bridged)
+        // `notify_one` stores a permit, so a bridge busy elsewhere still sees
+        // the request.
+        .map(|client| client.probe.notify_one())
+        .count()
+}
+
+/// Current clients, oldest first.
+pub fn snapshot() -> Vec<ClientStatus> {
+    clients()
+        .iter()
+        .map(|(&conn_id, client)| ClientStatus {
+            conn_id,
+            phase: client.phase,
+            age_ms: client.opened.elapsed().as_millis() as u64,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn registry_tracks_phases_and_probes_bridged_clients() {
+        // Ids far above what the proxy tests allocate.
+        let handshake = register(u64::MAX - 1);
+        let bridged = register(u64::MAX);
+        bridged.set_phase(ClientPhase::Bridged);
+
+        let status = snapshot();
+        let find = |id| status.iter().find(|c| c.conn_id == id).map(|c| c.phase);
+        assert_eq!(find(u64::MAX - 1), Some(ClientPhase::Handshake));
+        assert_eq!(find(u64::MAX), Some(ClientPhase::Bridged));
+
+        let signal = probe_signal(u64::MAX);
+        assert!(probe_all() >= 1);
+        tokio::time::timeout(Duration::from_secs(1), signal.notified())
+            .await
+            .expect("bridged client should be probed");
+
+        drop(handshake);
+        drop(bridged);
+        assert!(!snapshot()
+            .iter()
+            .any(|c| c.conn_id == u64::MAX || c.conn_id == u64::MAX - 1));
+    }
+}
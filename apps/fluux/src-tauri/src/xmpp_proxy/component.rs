@@ -0,0 +1,384 @@
+//! External component mode (XEP-0114).
+//!
+//! Runs a second loopback WebSocket listener whose connections are bridged to
+//! a server's component port instead of a client-to-server stream. The proxy
+//! performs the `jabber:component:accept` handshake with the shared secret,
+//! so a local bot or bridge only has to speak RFC 7395 framing: it opens the
+//! stream, then sends and receives stanzas addressed from the component
+//! domain.
+//!
+//! Each WebSocket connection gets its own upstream component stream. The
+//! component protocol has no TLS; the server should be on the same host or
+//! reached over a trusted network.
+
+use super::dns::to_ascii_host;
+use crate::error::FluuxError;
+use super::framing::{extract_stanza, extract_stream_error_condition};
+use super::{ProxyStartResult, LOOPBACK_BIND_ORDER, TCP_CONNECT_TIMEOUT};
+use futures_util::{SinkExt, StreamExt};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use sha1::{Digest, Sha1};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::{accept_hdr_async, tungstenite::Message};
+use tracing::{debug, error, info, warn};
+
+const COMPONENT_NS: &str = "jabber:component:accept";
+const FRAMING_NS: &str = "urn:ietf:params:xml:ns:xmpp-framing";
+const DEFAULT_COMPONENT_PORT: u16 = 5347;
+/// Time allowed for the server to accept the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct ComponentProxy {
+    domain: String,
+    server: String,
+    ws_url: String,
+    task: JoinHandle<()>,
+}
+
+/// The running component proxy, if any.
+static COMPONENT: Mutex<Option<ComponentProxy>> = Mutex::const_new(None);
+
+/// Split `host[:port]` (IPv6 literals in brackets), defaulting to the
+/// component port.
+fn parse_component_server(server: &str) -> Result<(String, u16), String> {
+    let server = server.trim();
+    if server.is_empty() {
+        return Err("Component server is empty".to_string());
+    }
+    if let Some(rest) = server.strip_prefix('[') {
+        let (host, after) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("Invalid component server: {server}"))?;
+        return match after.strip_prefix(':') {
+            Some(port) => Ok((host.to_string(), parse_port(port)?)),
+            None if after.is_empty() => Ok((host.to_string(), DEFAULT_COMPONENT_PORT)),
+            None => Err(format!("Invalid component server: {server}")),
+        };
+    }
+    match server.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => Ok((host.to_string(), parse_port(port)?)),
+        Some(_) => Err(format!("Put IPv6 addresses in brackets: [{server}]")),
+        None => Ok((server.to_string(), DEFAULT_COMPONENT_PORT)),
+    }
+}
+
+fn parse_port(port: &str) -> Result<u16, String> {
+    port.parse::<u16>()
+        .ok()
+        .filter(|port| *port != 0)
+        .ok_or_else(|| format!("Invalid component port: {port}"))
+}
+
+/// The handshake digest: hex SHA-1 of the stream id followed by the secret.
+fn handshake_digest(stream_id: &str, secret: &str) -> String {
+    Sha1::digest(format!("{stream_id}{secret}").as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// The `id` attribute of the server's stream header.
+fn stream_header_id(header: &str) -> Option<String> {
+    let start = header.find("<stream:stream")?;
+    let mut reader = Reader::from_str(&header[start..]);
+    reader.config_mut().check_end_names = false;
+    let event = reader.read_event();
+    let attrs = match &event {
+        Ok(Event::Start(e)) | Ok(Event::Empty(e)) => e.attributes(),
+        _ => return None,
+    };
+    attrs
+        .flatten()
+        .find(|attr| attr.key.as_ref() == b"id")
+        .map(|attr| String::from_utf8_lossy(&attr.value).to_string())
+        .filter(|id| !id.is_empty())
+}
+
+/// Read from `stream` until `buffer` holds a complete top-level element.
+async fn next_element(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> Result<String, String> {
+    let mut read_buf = [0u8; 4096];
+    loop {
+        if let Some((element, used)) = extract_stanza(buffer) {
+            buffer.drain(..used);
+            return Ok(element);
+        }
+        match stream.read(&mut read_buf).await {
+            Ok(0) => return Err("Component server closed the connection".to_string()),
+            Ok(n) => buffer.extend_from_slice(&read_buf[..n]),
+            Err(e) => return Err(format!("Component server read failed: {e}")),
+        }
+    }
+}
+
+/// Connect to the server's component port and authenticate as `domain`.
+/// Returns the stream along with any bytes read past the handshake.
+async fn connect_component(
+    host: &str,
+    port: u16,
+    domain: &str,
+    secret: &str,
+) -> Result<(TcpStream, Vec<u8>), String> {
+    let mut stream = tokio::time::timeout(TCP_CONNECT_TIMEOUT, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| format!("Timed out connecting to {host}:{port}"))?
+        .map_err(|e| format!("Failed to connect to {host}:{port}: {e}"))?;
+    let _ = stream.set_nodelay(true);
+
+    let mut buffer = Vec::new();
+    let handshake = async {
+        let header = format!(
+            "<?xml version='1.0'?><stream:stream xmlns='{COMPONENT_NS}' \
+             xmlns:stream='http://etherx.jabber.org/streams' to='{domain}'>"
+        );
+        stream
+            .write_all(header.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to open component stream: {e}"))?;
+        let reply = next_element(&mut stream, &mut buffer).await?;
+        if let Some(condition) = extract_stream_error_condition(&reply) {
+            return Err(format!("Component stream refused: {condition}"));
+        }
+        let id = stream_header_id(&reply)
+            .ok_or_else(|| "Component server sent no stream id".to_string())?;
+        let digest = handshake_digest(&id, secret);
+        stream
+            .write_all(format!("<handshake>{digest}</handshake>").as_bytes())
+            .await
+            .map_err(|e| format!("Failed to send component handshake: {e}"))?;
+        let answer = next_element(&mut stream, &mut buffer).await?;
+        if let Some(condition) = extract_stream_error_condition(&answer) {
+            return Err(format!("Component handshake rejected: {condition}"));
+        }
+        if !answer.trim_start().starts_with("<handshake") {
+            return Err("Unexpected answer to component handshake".to_string());
+        }
+        Ok(())
+    };
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .map_err(|_| "Component handshake timed out".to_string())??;
+    Ok((stream, buffer))
+}
+
+/// Bridge one local WebSocket client to its own component stream.
+async fn handle_component_client(
+    ws_stream: TcpStream,
+    host: &str,
+    port: u16,
+    domain: &str,
+    secret: &str,
+) -> Result<(), String> {
+    #[allow(clippy::result_large_err)]
+    let ws = accept_hdr_async(ws_stream, |req: &Request, mut resp: Response| {
+        if let Some(protocol) = req.headers().get("Sec-WebSocket-Protocol") {
+            resp.headers_mut()
+                .insert("Sec-WebSocket-Protocol", protocol.clone());
+        }
+        Ok(resp)
+    })
+    .await
+    .map_err(|e| format!("WebSocket handshake failed: {e}"))?;
+    let (mut ws_write, mut ws_read) = ws.split();
+
+    let (upstream, mut buffer) = match connect_component(host, port, domain, secret).await {
+        Ok(connected) => connected,
+        Err(e) => {
+            let _ = ws_write
+                .send(Message::Text(
+                    format!("<close xmlns='{FRAMING_NS}'/>").into(),
+                ))
+                .await;
+            let _ = ws_write.close().await;
+            return Err(e);
+        }
+    };
+    info!(domain = %domain, "Component stream authenticated");
+    let (mut tcp_read, mut tcp_write) = upstream.into_split();
+    let open = format!("<open xmlns='{FRAMING_NS}' from='{domain}' version='1.0'/>");
+
+    let mut read_buf = [0u8; 8192];
+    loop {
+        // Stanzas that arrived with the handshake answer go out first.
+        while let Some((stanza, used)) = extract_stanza(&buffer) {
+            buffer.drain(..used);
+            if stanza == "</stream:stream>" {
+                let _ = ws_write
+                    .send(Message::Text(
+                        format!("<close xmlns='{FRAMING_NS}'/>").into(),
+                    ))
+                    .await;
+                return Ok(());
+            }
+            debug!(data = %stanza, "Component->WS");
+            ws_write
+                .send(Message::Text(stanza.into()))
+                .await
+                .map_err(|e| format!("WebSocket write failed: {e}"))?;
+        }
+        tokio::select! {
+            read = tcp_read.read(&mut read_buf) => match read {
+                Ok(0) => return Ok(()),
+                Ok(n) => buffer.extend_from_slice(&read_buf[..n]),
+                Err(e) => return Err(format!("Component server read failed: {e}")),
+            },
+            msg = ws_read.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    let trimmed = text.trim();
+                    // The stream is already open upstream: answer framing locally.
+                    if trimmed.starts_with("<open") {
+                        ws_write
+                            .send(Message::Text(open.clone().into()))
+                            .await
+                            .map_err(|e| format!("WebSocket write failed: {e}"))?;
+                    } else if trimmed.starts_with("<close") {
+                        let _ = tcp_write.write_all(b"</stream:stream>").await;
+                        return Ok(());
+                    } else {
+                        debug!(data = %trimmed, "WS->Component");
+                        tcp_write
+                            .write_all(trimmed.as_bytes())
+                            .await
+                            .map_err(|e| format!("Component server write failed: {e}"))?;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => {
+                    let _ = tcp_write.write_all(b"</stream:stream>").await;
+                    return Ok(());
+                }
+                Some(Err(e)) => return Err(format!("WebSocket read failed: {e}")),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Start the component proxy for `domain` on `server` (`host[:port]`, port
+/// 5347 by default), replacing any running one. Returns the loopback
+/// WebSocket URL local bots connect to.
+#[tauri::command]
+pub async fn start_component_proxy(
+    domain: String,
+    secret: String,
+    server: String,
+) -> Result<ProxyStartResult, FluuxError> {
+    crate::managed_policy::check_server(&server).map_err(FluuxError::Policy)?;
+    let domain = to_ascii_host(domain.trim()).map_err(FluuxError::InvalidInput)?;
+    if domain.is_empty() || domain.contains(['\'', '"', '<', '>', '&', '/', '@']) {
+        return Err(FluuxError::InvalidInput(format!(
+            "Invalid component domain: {domain}"
+        )));
+    }
+    if secret.is_empty() {
+        return Err(FluuxError::InvalidInput(
+            "Component secret is empty".to_string(),
+        ));
+    }
+    let (host, port) = parse_component_server(&server).map_err(FluuxError::InvalidInput)?;
+    if !matches!(host.as_str(), "localhost" | "127.0.0.1" | "::1") {
+        warn!(host = %host, "Component stream to a remote host is not encrypted");
+    }
+
+    // Fail early on a wrong secret or unreachable server, before handing out
+    // a URL.
+    connect_component(&host, port, &domain, &secret)
+        .await
+        .map_err(FluuxError::upstream)?;
+
+    let mut guard = COMPONENT.lock().await;
+    if let Some(old) = guard.take() {
+        old.task.abort();
+    }
+
+    let mut bound = None;
+    let mut bind_errors = Vec::new();
+    for (bind_addr, loopback_host) in LOOPBACK_BIND_ORDER {
+        match TcpListener::bind(bind_addr).await {
+            Ok(listener) => {
+                bound = Some((listener, loopback_host));
+                break;
+            }
+            Err(e) => bind_errors.push(format!("{bind_addr}: {e}")),
+        }
+    }
+    let (listener, loopback_host) = bound.ok_or_else(|| {
+        FluuxError::LocalServer(format!(
+            "Failed to bind component listener on loopback ({})",
+            bind_errors.join(", ")
+        ))
+    })?;
+    let local_addr = listener
+        .local_addr()
+        .map_err(|e| FluuxError::LocalServer(format!("Failed to get local address: {e}")))?;
+    let ws_url = format!("ws://{}:{}", loopback_host, local_addr.port());
+
+    let task_domain = domain.clone();
+    let task = tokio::spawn(async move {
+        while let Ok((stream, addr)) = listener.accept().await {
+            info!(addr = %addr, "New component client");
+            let (host, domain, secret) = (host.clone(), task_domain.clone(), secret.clone());
+            tokio::spawn(async move {
+                if let Err(e) = handle_component_client(stream, &host, port, &domain, &secret).await
+                {
+                    error!(error = %e, "Component connection error");
+                }
+            });
+        }
+    });
+
+    info!(domain = %domain, server = %server, url = %ws_url, "Component proxy started");
+    *guard = Some(ComponentProxy {
+        domain,
+        server,
+        ws_url: ws_url.clone(),
+        task,
+    });
+    Ok(ProxyStartResult { url: ws_url })
+}
+
+/// Stop the component proxy. Connected bots are not disconnected; they keep
+/// their streams until they close them.
+#[tauri::command]
+pub async fn stop_component_proxy() {
+    if let Some(proxy) = COMPONENT.lock().await.take() {
+        proxy.task.abort();
+        info!(domain = %proxy.domain, server = %proxy.server, url = %proxy.ws_url,
+            "Component proxy stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_uses_stream_id_and_secret() {
+        // Example from XEP-0114 section 3.
+        let header = "<?xml version='1.0'?><stream:stream \
+                      xmlns:stream='http://etherx.jabber.org/streams' \
+                      xmlns='jabber:component:accept' from='plays.shakespeare.lit' \
+                      id='3BF96D32'>";
+        let id = stream_header_id(header).unwrap();
+        assert_eq!(id, "3BF96D32");
+        assert_eq!(
+            handshake_digest(&id, "sharedsecret"),
+            "3b6c216d9b55a7b4f3e82ab3e10cb81569f115d4"
+        );
+
+        assert_eq!(
+            parse_component_server("localhost").unwrap(),
+            ("localhost".to_string(), 5347)
+        );
+        assert_eq!(
+            parse_component_server("[::1]:5275").unwrap(),
+            ("::1".to_string(), 5275)
+        );
+        assert!(parse_component_server("::1").is_err());
+        assert!(parse_component_server("example.com:0").is_err());
+    }
+}
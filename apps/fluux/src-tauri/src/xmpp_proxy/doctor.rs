@@ -0,0 +1,253 @@
+//! Connection probe for the `doctor` subcommand.
+//!
+//! Walks the same path the proxy takes to reach a server, one endpoint at a
+//! time and without stopping at the first success: SRV resolution (plus any
+//! configured fallback hosts), the Happy Eyeballs TCP race, then direct TLS
+//! or STARTTLS, with the timing of each step, the negotiated TLS version and
+//! ALPN, and warnings about the server certificate. No login is attempted,
+//! so the report needs no credentials and carries none.
+
+use super::dns::{
+    parse_server_input, resolve_xmpp_server, ConnectionMode, ParsedServer, XmppEndpoint,
+};
+use super::{
+    cert_check, happy_eyeballs, host_overrides, negotiated_tls, net_prefs, now_millis,
+    perform_starttls, upgrade_to_tls, TCP_CONNECT_TIMEOUT, XMPP_CLIENT_ALPN,
+};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// What one endpoint did.
+struct Probe {
+    tcp: Result<Duration, String>,
+    tls: Option<Result<Secured, String>>,
+}
+
+struct Secured {
+    elapsed: Duration,
+    version: Option<String>,
+    alpn: Option<String>,
+    warnings: Vec<cert_check::CertificateWarning>,
+}
+
+fn ms(d: Duration) -> u128 {
+    d.as_millis()
+}
+
+async fn probe(endpoint: &XmppEndpoint) -> Probe {
+    let network = net_prefs::current();
+    let started = Instant::now();
+    let tcp = happy_eyeballs::connect_tcp(
+        &endpoint.host,
+        endpoint.port,
+        &network,
+        happy_eyeballs::CONNECTION_ATTEMPT_DELAY,
+        TCP_CONNECT_TIMEOUT,
+    )
+    .await;
+    let tcp_stream = match tcp {
+        Ok(stream) => stream,
+        Err(e) => {
+            return Probe {
+                tcp: Err(e),
+                tls: None,
+            }
+        }
+    };
+    let tcp_elapsed = started.elapsed();
+    let started = Instant::now();
+    let handshake = async {
+        match endpoint.mode {
+            ConnectionMode::Tcp => {
+                perform_starttls(tcp_stream, endpoint.tls_name(), &endpoint.host).await
+            }
+            ConnectionMode::DirectTls => {
+                upgrade_to_tls(tcp_stream, endpoint.tls_name(), Some(XMPP_CLIENT_ALPN)).await
+            }
+        }
+    };
+    let tls = match tokio::time::timeout(TCP_CONNECT_TIMEOUT, handshake).await {
+        Ok(Ok(stream)) => {
+            let (version, alpn) = negotiated_tls(&stream);
+            let warnings = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|chain| chain.first())
+                .map(|leaf| cert_check::inspect(leaf, now_millis() / 1000))
+                .unwrap_or_default();
+            Ok(Secured {
+                elapsed: started.elapsed(),
+                version,
+                alpn,
+                warnings,
+            })
+        }
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(format!(
+            "TLS handshake timed out after {}s",
+            TCP_CONNECT_TIMEOUT.as_secs()
+        )),
+    };
+    Probe {
+        tcp: Ok(tcp_elapsed),
+        tls: Some(tls),
+    }
+}
+
+fn describe_warning(warning: &cert_check::CertificateWarning) -> String {
+    match warning {
+        cert_check::CertificateWarning::ExpiresSoon { days_left, .. } => {
+            format!("certificate expires in {days_left} day(s)")
+        }
+        cert_check::CertificateWarning::NoSct => {
+            "certificate has no Certificate Transparency timestamps".to_string()
+        }
+    }
+}
+
+/// Append one endpoint's section to `out`; true when it connected securely.
+fn report_endpoint(out: &mut String, endpoint: &XmppEndpoint, probe: &Probe) -> bool {
+    let mode = match endpoint.mode {
+        ConnectionMode::Tcp => "STARTTLS",
+        ConnectionMode::DirectTls => "direct TLS",
+    };
+    let _ = writeln!(out, "{}:{} ({mode})", endpoint.host, endpoint.port);
+    match &probe.tcp {
+        Ok(elapsed) => {
+            let _ = writeln!(out, "  TCP   ok in {} ms", ms(*elapsed));
+        }
+        Err(e) => {
+            let _ = writeln!(out, "  TCP   FAILED: {e}");
+            return false;
+        }
+    }
+    match &probe.tls {
+        Some(Ok(secured)) => {
+            let _ = writeln!(
+                out,
+                "  TLS   ok in {} ms ({}, ALPN {}, name {})",
+                ms(secured.elapsed),
+                secured.version.as_deref().unwrap_or("unknown version"),
+                secured.alpn.as_deref().unwrap_or("none"),
+                endpoint.tls_name()
+            );
+            for warning in &secured.warnings {
+                let _ = writeln!(out, "  WARN  {}", describe_warning(warning));
+            }
+            true
+        }
+        Some(Err(e)) => {
+            let _ = writeln!(out, "  TLS   FAILED: {e}");
+            false
+        }
+        None => false,
+    }
+}
+
+/// Probe `server` (a domain, or an explicit `tls://host:port` and the like)
+/// and return the report. `Err` carries the report too, when no endpoint
+/// could be reached securely.
+pub async fn run(server: &str) -> Result<String, String> {
+    super::init_crypto_provider();
+    let mut out = String::new();
+    let started = Instant::now();
+    let (mut endpoints, domain) = match parse_server_input(server) {
+        ParsedServer::Direct(host, port, mode, domain) => {
+            let _ = writeln!(out, "Explicit endpoint, DNS SRV lookup skipped");
+            let endpoint = XmppEndpoint {
+                host,
+                port,
+                mode,
+                domain: domain.clone(),
+            };
+            (vec![endpoint], domain)
+        }
+        ParsedServer::Domain(domain) => match resolve_xmpp_server(&domain).await {
+            Ok(endpoints) => {
+                let _ = writeln!(
+                    out,
+                    "DNS   {} endpoint(s) for {domain} in {} ms",
+                    endpoints.len(),
+                    ms(started.elapsed())
+                );
+                (endpoints, Some(domain))
+            }
+            Err(e) => {
+                let _ = writeln!(out, "DNS   FAILED for {domain}: {e}");
+                (Vec::new(), Some(domain))
+            }
+        },
+    };
+    if let Some(domain) = &domain {
+        let before = endpoints.len();
+        host_overrides::merge(&mut endpoints, domain);
+        if endpoints.len() > before {
+            let _ = writeln!(
+                out,
+                "      {} configured fallback endpoint(s)",
+                endpoints.len() - before
+            );
+        }
+    }
+    if endpoints.is_empty() {
+        let _ = writeln!(out, "\nNo endpoint to try.");
+        return Err(out);
+    }
+
+    let mut reachable = 0;
+    for endpoint in &endpoints {
+        out.push('\n');
+        let probe = probe(endpoint).await;
+        if report_endpoint(&mut out, endpoint, &probe) {
+            reachable += 1;
+        }
+    }
+    let _ = writeln!(
+        out,
+        "\n{reachable} of {} endpoint(s) reachable",
+        endpoints.len()
+    );
+    if reachable == 0 {
+        Err(out)
+    } else {
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_stops_at_the_first_failed_step() {
+        let endpoint = XmppEndpoint {
+            host: "xmpp.example.org".to_string(),
+            port: 5223,
+            mode: ConnectionMode::DirectTls,
+            domain: Some("example.org".to_string()),
+        };
+        let mut out = String::new();
+        let refused = Probe {
+            tcp: Err("connection refused".to_string()),
+            tls: None,
+        };
+        assert!(!report_endpoint(&mut out, &endpoint, &refused));
+        assert!(out.contains("TCP   FAILED: connection refused"));
+        assert!(!out.contains("TLS"));
+
+        let mut out = String::new();
+        let secured = Probe {
+            tcp: Ok(Duration::from_millis(12)),
+            tls: Some(Ok(Secured {
+                elapsed: Duration::from_millis(40),
+                version: Some("TLSv1_3".to_string()),
+                alpn: Some("xmpp-client".to_string()),
+                warnings: vec![cert_check::CertificateWarning::NoSct],
+            })),
+        };
+        assert!(report_endpoint(&mut out, &endpoint, &secured));
+        assert!(out.contains("name example.org"));
+        assert!(out.contains("WARN  certificate has no Certificate Transparency"));
+    }
+}
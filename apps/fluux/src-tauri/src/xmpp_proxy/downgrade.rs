@@ -0,0 +1,232 @@
+//! SASL mechanism downgrade detection (XEP-0474).
+//!
+//! SASL is negotiated by the WebView's client over the bridge, but every
+//! nonza passes through the proxy, so it can check what the client cannot:
+//! that the mechanism list the client was offered is the one the server
+//! sent. A server implementing XEP-0474 puts a `d` attribute in the SCRAM
+//! server-first-message carrying a hash of its mechanism list and channel
+//! binding types. If an attacker on the upstream path stripped mechanisms
+//! (typically the `SCRAM-…-PLUS` ones) from the stream features, that hash
+//! no longer matches the list the bridge relayed, and the connection is
+//! refused before the client completes authentication.
+//!
+//! Servers without XEP-0474 send no `d` attribute; nothing is checked then.
+
+use super::stanza::Element;
+use super::tap::Direction;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::sync::Mutex;
+
+const SASL_NS: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const SASL2_NS: &str = "urn:xmpp:sasl:2";
+const SASL_CB_NS: &str = "urn:xmpp:sasl-cb:0";
+
+#[derive(Default)]
+struct State {
+    /// Mechanisms from the last stream features, as advertised.
+    mechanisms: Vec<String>,
+    /// Channel binding types from the last stream features.
+    channel_bindings: Vec<String>,
+    /// Mechanism the client chose.
+    chosen: Option<String>,
+    /// Set once a server-first-message has been checked.
+    verified: bool,
+}
+
+/// Watches one bridged connection's SASL exchange for a stripped mechanism
+/// list.
+#[derive(Default)]
+pub struct DowngradeGuard {
+    state: Mutex<State>,
+}
+
+impl DowngradeGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Follow a relayed nonza. Returns an error describing the mismatch
+    /// when the server's downgrade-protection hash disagrees with the
+    /// mechanisms that were relayed; the connection must then be dropped.
+    pub(super) fn observe(&self, direction: Direction, raw: &str) -> Result<(), String> {
+        let trimmed = raw.trim_start();
+        if self.state().verified {
+            return Ok(());
+        }
+        match direction {
+            Direction::Outbound if trimmed.starts_with("<auth") => {
+                if let Some(auth) = Element::parse(raw) {
+                    if matches!(auth.ns(), Some(SASL_NS) | Some(SASL2_NS)) {
+                        self.state().chosen = auth.attr("mechanism").map(str::to_string);
+                    }
+                }
+                Ok(())
+            }
+            Direction::Inbound
+                if trimmed.starts_with("<stream:features") || trimmed.starts_with("<features") =>
+            {
+                if let Some(features) = Element::parse(raw) {
+                    self.record_features(&features);
+                }
+                Ok(())
+            }
+            Direction::Inbound if trimmed.starts_with("<challenge") => {
+                let Some(challenge) = Element::parse(raw) else {
+                    return Ok(());
+                };
+                if !matches!(challenge.ns(), Some(SASL_NS) | Some(SASL2_NS)) {
+                    return Ok(());
+                }
+                self.check_challenge(challenge.text().trim())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record_features(&self, features: &Element) {
+        // SASL2 advertises its mechanisms under <authentication>.
+        let list = features
+            .child("mechanisms", Some(SASL_NS))
+            .or_else(|| features.child("authentication", Some(SASL2_NS)));
+        let Some(list) = list else {
+            return;
+        };
+        let mut state = self.state();
+        state.mechanisms = list
+            .elements()
+            .filter(|e| e.local_name() == "mechanism")
+            .map(|e| e.text().trim().to_string())
+            .collect();
+        state.channel_bindings = features
+            .child("sasl-channel-binding", Some(SASL_CB_NS))
+            .map(|cb| {
+                cb.elements()
+                    .filter_map(|e| e.attr("type"))
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+
+    fn check_challenge(&self, payload: &str) -> Result<(), String> {
+        let mut state = self.state();
+        let Some(mechanism) = state.chosen.clone() else {
+            return Ok(());
+        };
+        // Only the first challenge is the server-first-message.
+        state.verified = true;
+        let Ok(decoded) = BASE64.decode(payload) else {
+            return Ok(());
+        };
+        let message = String::from_utf8_lossy(&decoded);
+        let Some(received) = message.split(',').find_map(|attr| attr.strip_prefix("d=")) else {
+            return Ok(());
+        };
+        let Some(expected) = downgrade_hash(&mechanism, &state.mechanisms, &state.channel_bindings)
+        else {
+            return Ok(());
+        };
+        if received == expected {
+            Ok(())
+        } else {
+            Err(format!(
+                "SASL downgrade detected: server's mechanism hash does not match the \
+                 advertised mechanisms ({})",
+                state.mechanisms.join(", ")
+            ))
+        }
+    }
+}
+
+/// The XEP-0474 `d` value for `mechanisms` and `channel_bindings`, hashed
+/// with the hash function of the SCRAM `mechanism` in use: both lists sorted
+/// and comma-joined, separated by `|` when channel binding types were
+/// advertised, then base64. `None` for non-SCRAM mechanisms.
+fn downgrade_hash(
+    mechanism: &str,
+    mechanisms: &[String],
+    channel_bindings: &[String],
+) -> Option<String> {
+    let mut mechanisms = mechanisms.to_vec();
+    mechanisms.sort();
+    let mut input = mechanisms.join(",");
+    if !channel_bindings.is_empty() {
+        let mut channel_bindings = channel_bindings.to_vec();
+        channel_bindings.sort();
+        input.push('|');
+        input.push_str(&channel_bindings.join(","));
+    }
+    let family = mechanism.strip_suffix("-PLUS").unwrap_or(mechanism);
+    let digest = match family {
+        "SCRAM-SHA-1" => Sha1::digest(input.as_bytes()).to_vec(),
+        "SCRAM-SHA-256" => Sha256::digest(input.as_bytes()).to_vec(),
+        "SCRAM-SHA-512" => Sha512::digest(input.as_bytes()).to_vec(),
+        _ => return None,
+    };
+    Some(BASE64.encode(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEATURES: &str = "<stream:features>\
+        <mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+        <mechanism>SCRAM-SHA-256</mechanism><mechanism>SCRAM-SHA-256-PLUS</mechanism>\
+        </mechanisms>\
+        <sasl-channel-binding xmlns='urn:xmpp:sasl-cb:0'>\
+        <channel-binding type='tls-exporter'/></sasl-channel-binding></stream:features>";
+    const AUTH: &str =
+        "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='SCRAM-SHA-256'>x</auth>";
+
+    fn challenge(d: &str) -> String {
+        let first = format!("r=abc,s=c2FsdA==,i=4096,d={d}");
+        format!(
+            "<challenge xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>{}</challenge>",
+            BASE64.encode(first)
+        )
+    }
+
+    fn hash(mechanisms: &[&str]) -> String {
+        let mechanisms: Vec<String> = mechanisms.iter().map(|m| m.to_string()).collect();
+        downgrade_hash("SCRAM-SHA-256", &mechanisms, &["tls-exporter".to_string()]).unwrap()
+    }
+
+    #[test]
+    fn matching_hash_passes_and_stripped_list_is_refused() {
+        let intact = DowngradeGuard::new();
+        intact.observe(Direction::Inbound, FEATURES).unwrap();
+        intact.observe(Direction::Outbound, AUTH).unwrap();
+        let d = hash(&["SCRAM-SHA-256-PLUS", "SCRAM-SHA-256"]);
+        assert!(intact.observe(Direction::Inbound, &challenge(&d)).is_ok());
+
+        // The server hashed a list with the -PLUS mechanism in it, but the
+        // features the client saw had it stripped.
+        let stripped = DowngradeGuard::new();
+        let features = FEATURES.replace("<mechanism>SCRAM-SHA-256-PLUS</mechanism>", "");
+        stripped.observe(Direction::Inbound, &features).unwrap();
+        stripped.observe(Direction::Outbound, AUTH).unwrap();
+        let err = stripped
+            .observe(Direction::Inbound, &challenge(&d))
+            .unwrap_err();
+        assert!(err.contains("downgrade"), "{err}");
+    }
+
+    #[test]
+    fn servers_without_the_extension_are_not_checked() {
+        let guard = DowngradeGuard::new();
+        guard.observe(Direction::Inbound, FEATURES).unwrap();
+        guard.observe(Direction::Outbound, AUTH).unwrap();
+        let first = BASE64.encode("r=abc,s=c2FsdA==,i=4096");
+        let plain =
+            format!("<challenge xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>{first}</challenge>");
+        assert!(guard.observe(Direction::Inbound, &plain).is_ok());
+    }
+}
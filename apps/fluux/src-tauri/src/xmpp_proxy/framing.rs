@@ -210,8 +210,11 @@ pub fn translate_tcp_to_ws<'a>(text: &'a str) -> Cow<'a, str> {
                 result.push('<');
                 remaining = &remaining[8..]; // skip "<stream:"
             } else {
-                result.push(remaining.as_bytes()[0] as char);
-                remaining = &remaining[1..];
+                // Copy one whole character: slicing by byte would split a
+                // multi-byte one and panic.
+                let ch = remaining.chars().next().unwrap_or_default();
+                result.push(ch);
+                remaining = &remaining[ch.len_utf8()..];
             }
         }
         // Inject xmlns on the root element (after the first tag name, before '>', ' ', or '/')
@@ -257,8 +260,7 @@ pub fn extract_stream_error_condition(stanza: &str) -> Option<String> {
     // Only a stream-level <error> root qualifies: the prefixed TCP form, or the
     // translated form which carries the stream namespace explicitly.
     let is_stream_error = trimmed.starts_with("<stream:error")
-        || (trimmed.starts_with("<error")
-            && trimmed.contains("http://etherx.jabber.org/streams"));
+        || (trimmed.starts_with("<error") && trimmed.contains("http://etherx.jabber.org/streams"));
     if !is_stream_error {
         return None;
     }
@@ -299,12 +301,9 @@ enum ParserState {
     InStanza,
 }
 
-/// Convert a byte slice to a String, trying zero-copy UTF-8 first.
-fn bytes_to_string(bytes: &[u8]) -> String {
-    match std::str::from_utf8(bytes) {
-        Ok(s) => s.to_string(),
-        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
-    }
+/// Borrow a byte slice as text; only invalid UTF-8 is copied (lossily).
+fn bytes_to_str(bytes: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(bytes)
 }
 
 /// Extract a single complete XMPP stanza from the given buffer slice.
@@ -313,6 +312,12 @@ fn bytes_to_string(bytes: &[u8]) -> String {
 /// or `None` if the buffer doesn't contain a complete stanza yet.
 /// The caller is responsible for advancing past the consumed bytes.
 pub fn extract_stanza(buffer: &[u8]) -> Option<(String, usize)> {
+    next_stanza(buffer).map(|(stanza, used)| (stanza.into_owned(), used))
+}
+
+/// [`extract_stanza`] borrowing the stanza from `buffer`, for the bridge's
+/// reader, which does not keep it past translation.
+pub fn next_stanza(buffer: &[u8]) -> Option<(Cow<'_, str>, usize)> {
     // Special case: check for stream closing tag first
     // This appears alone without a matching opening tag in the buffer
     let trimmed = buffer
@@ -321,7 +326,7 @@ pub fn extract_stanza(buffer: &[u8]) -> Option<(String, usize)> {
     if let Some(start) = trimmed {
         if buffer[start..].starts_with(b"</stream:stream>") {
             let tag_end = start + b"</stream:stream>".len();
-            return Some(("</stream:stream>".to_string(), tag_end));
+            return Some((Cow::Borrowed("</stream:stream>"), tag_end));
         }
     }
 
@@ -353,7 +358,7 @@ pub fn extract_stanza(buffer: &[u8]) -> Option<(String, usize)> {
                 {
                     // Return the stream opening immediately
                     let tag_end = reader.buffer_position() as usize;
-                    return Some((bytes_to_string(&buffer[0..tag_end]), tag_end));
+                    return Some((bytes_to_str(&buffer[0..tag_end]), tag_end));
                 }
 
                 depth += 1;
@@ -372,13 +377,13 @@ pub fn extract_stanza(buffer: &[u8]) -> Option<(String, usize)> {
                     && (local_name.as_ref() == b"stream" || e.name().as_ref() == b"stream:stream")
                 {
                     let tag_end = reader.buffer_position() as usize;
-                    return Some((bytes_to_string(&buffer[0..tag_end]), tag_end));
+                    return Some((bytes_to_str(&buffer[0..tag_end]), tag_end));
                 }
 
                 // Self-closing top-level stanza (e.g., <presence/>, <r xmlns='urn:xmpp:sm:3'/>)
                 if state == ParserState::Idle && depth == 0 {
                     let tag_end = reader.buffer_position() as usize;
-                    return Some((bytes_to_string(&buffer[pos..tag_end]), tag_end));
+                    return Some((bytes_to_str(&buffer[pos..tag_end]), tag_end));
                 }
 
                 // Otherwise it's a self-closing child element, continue
@@ -394,7 +399,7 @@ pub fn extract_stanza(buffer: &[u8]) -> Option<(String, usize)> {
                     && depth == 0
                 {
                     let tag_end = reader.buffer_position() as usize;
-                    return Some(("</stream:stream>".to_string(), tag_end));
+                    return Some((Cow::Borrowed("</stream:stream>"), tag_end));
                 }
 
                 depth = depth.saturating_sub(1);
@@ -402,7 +407,7 @@ pub fn extract_stanza(buffer: &[u8]) -> Option<(String, usize)> {
                 // Stanza complete when depth returns to 0 while InStanza
                 if state == ParserState::InStanza && depth == 0 {
                     let tag_end = reader.buffer_position() as usize;
-                    return Some((bytes_to_string(&buffer[stanza_start..tag_end]), tag_end));
+                    return Some((bytes_to_str(&buffer[stanza_start..tag_end]), tag_end));
                 }
             }
             Ok(Event::Eof) => {
@@ -537,7 +542,8 @@ mod tests {
     fn test_translate_open_without_from_attribute() {
         // When <open/> has no `from`, the resulting <stream:stream> must not
         // contain a stray `from=` attribute.
-        let open_tag = r#"<open xmlns="urn:ietf:params:xml:ns:xmpp-framing" to="example.com" version="1.0"/>"#;
+        let open_tag =
+            r#"<open xmlns="urn:ietf:params:xml:ns:xmpp-framing" to="example.com" version="1.0"/>"#;
         let translated = translate_ws_to_tcp(open_tag);
 
         assert!(translated.contains("<stream:stream"));
@@ -799,6 +805,30 @@ mod tests {
         assert_eq!(&*translated, iq);
     }
 
+    #[test]
+    fn test_tcp_to_ws_stream_prefix_keeps_multibyte_text() {
+        // Byte-wise prefix stripping used to split multi-byte characters and panic.
+        let error = "<stream:error><policy-violation \
+                     xmlns='urn:ietf:params:xml:ns:xmpp-streams'/>\
+                     <text>Trop de connexions — réessayez</text></stream:error>";
+        let translated = translate_tcp_to_ws(error);
+        assert!(translated.starts_with("<error xmlns="));
+        assert!(translated.contains("Trop de connexions — réessayez"));
+    }
+
+    #[test]
+    fn test_extract_stanza_survives_invalid_utf8_and_deep_nesting() {
+        let mut buf = b"<message><body>\xff\xfe</body></message>".to_vec();
+        let (stanza, consumed) = extract_stanza(&buf).unwrap();
+        assert_eq!(consumed, buf.len());
+        assert!(stanza.contains('\u{fffd}'));
+
+        buf = "<a>".repeat(50_000).into_bytes();
+        assert!(extract_stanza(&buf).is_none());
+        buf.extend_from_slice("</a>".repeat(50_000).as_bytes());
+        assert_eq!(extract_stanza(&buf).unwrap().1, buf.len());
+    }
+
     // --- STARTTLS protocol parsing tests ---
     // These test the stanza extraction and parsing patterns used by perform_starttls()
 
@@ -1028,34 +1058,49 @@ mod tests {
     #[test]
     fn test_stream_error_condition_host_unknown() {
         let s = r#"<stream:error><host-unknown xmlns='urn:ietf:params:xml:ns:xmpp-streams'/></stream:error>"#;
-        assert_eq!(extract_stream_error_condition(s).as_deref(), Some("host-unknown"));
+        assert_eq!(
+            extract_stream_error_condition(s).as_deref(),
+            Some("host-unknown")
+        );
     }
 
     #[test]
     fn test_stream_error_condition_see_other_host_with_text_value() {
         // <see-other-host> carries a text value (the redirect target)
         let s = r#"<stream:error><see-other-host xmlns='urn:ietf:params:xml:ns:xmpp-streams'>other.example.com:5222</see-other-host></stream:error>"#;
-        assert_eq!(extract_stream_error_condition(s).as_deref(), Some("see-other-host"));
+        assert_eq!(
+            extract_stream_error_condition(s).as_deref(),
+            Some("see-other-host")
+        );
     }
 
     #[test]
     fn test_stream_error_condition_skips_leading_text_element() {
         // Condition should win even if a <text> element is present.
         let s = r#"<stream:error><text xmlns='urn:ietf:params:xml:ns:xmpp-streams'>bye</text><conflict xmlns='urn:ietf:params:xml:ns:xmpp-streams'/></stream:error>"#;
-        assert_eq!(extract_stream_error_condition(s).as_deref(), Some("conflict"));
+        assert_eq!(
+            extract_stream_error_condition(s).as_deref(),
+            Some("conflict")
+        );
     }
 
     #[test]
     fn test_stream_error_condition_translated_form() {
         // Already RFC-7395-translated (prefix stripped, explicit xmlns).
         let s = r#"<error xmlns="http://etherx.jabber.org/streams"><policy-violation xmlns="urn:ietf:params:xml:ns:xmpp-streams"/></error>"#;
-        assert_eq!(extract_stream_error_condition(s).as_deref(), Some("policy-violation"));
+        assert_eq!(
+            extract_stream_error_condition(s).as_deref(),
+            Some("policy-violation")
+        );
     }
 
     #[test]
     fn test_stream_error_condition_not_authorized_with_text_after() {
         let s = r#"<stream:error><not-authorized xmlns='urn:ietf:params:xml:ns:xmpp-streams'/><text xmlns='urn:ietf:params:xml:ns:xmpp-streams'>denied</text></stream:error>"#;
-        assert_eq!(extract_stream_error_condition(s).as_deref(), Some("not-authorized"));
+        assert_eq!(
+            extract_stream_error_condition(s).as_deref(),
+            Some("not-authorized")
+        );
     }
 
     #[test]
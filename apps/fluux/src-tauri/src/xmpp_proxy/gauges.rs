@@ -0,0 +1,72 @@
+//! Bytes the bridges hold in memory, summed over all connections, for
+//! `get_memory_stats`.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Partial stanzas waiting for their end in the TLS→WebSocket reader.
+pub(super) static STANZA_BUFFER: AtomicUsize = AtomicUsize::new(0);
+/// Translated stanzas queued for a WebSocket that hasn't taken them yet.
+pub(super) static WS_QUEUE: AtomicUsize = AtomicUsize::new(0);
+
+/// One holder's share of a total; given back when dropped.
+pub(super) struct Gauge {
+    total: &'static AtomicUsize,
+    held: usize,
+}
+
+impl Gauge {
+    pub(super) fn new(total: &'static AtomicUsize) -> Self {
+        Gauge { total, held: 0 }
+    }
+
+    /// The holder now holds `bytes`.
+    pub(super) fn set(&mut self, bytes: usize) {
+        if bytes > self.held {
+            self.total.fetch_add(bytes - self.held, Ordering::Relaxed);
+        } else {
+            self.total.fetch_sub(self.held - bytes, Ordering::Relaxed);
+        }
+        self.held = bytes;
+    }
+}
+
+impl Drop for Gauge {
+    fn drop(&mut self) {
+        self.set(0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferUsage {
+    pub stanza_buffer_bytes: usize,
+    pub ws_queue_bytes: usize,
+}
+
+pub fn usage() -> BufferUsage {
+    BufferUsage {
+        stanza_buffer_bytes: STANZA_BUFFER.load(Ordering::Relaxed),
+        ws_queue_bytes: WS_QUEUE.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gauges_add_up_and_give_back_on_drop() {
+        static TOTAL: AtomicUsize = AtomicUsize::new(0);
+        let mut a = Gauge::new(&TOTAL);
+        let mut b = Gauge::new(&TOTAL);
+        a.set(100);
+        b.set(40);
+        a.set(30);
+        assert_eq!(TOTAL.load(Ordering::Relaxed), 70);
+        drop(a);
+        assert_eq!(TOTAL.load(Ordering::Relaxed), 40);
+        drop(b);
+        assert_eq!(TOTAL.load(Ordering::Relaxed), 0);
+    }
+}
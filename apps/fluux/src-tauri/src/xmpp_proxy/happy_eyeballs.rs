@@ -23,6 +23,7 @@ use futures_util::stream::{FuturesUnordered, StreamExt};
 use tokio::net::{lookup_host, TcpStream};
 
 use super::dns::to_ascii_host;
+use super::net_prefs::NetworkPrefs;
 use tracing::info;
 
 /// RFC 8305 "Connection Attempt Delay": how long to wait before starting the
@@ -160,9 +161,12 @@ where
 /// address for the host and races them via [`happy_eyeballs_connect`], so a
 /// black-holed IPv6 address falls through to a reachable IPv4 one within
 /// `attempt_delay` instead of consuming the entire `overall_timeout`.
+/// `prefs` filters and orders the addresses by family and sets the source
+/// address each attempt binds to.
 pub async fn connect_tcp(
     host: &str,
     port: u16,
+    prefs: &NetworkPrefs,
     attempt_delay: Duration,
     overall_timeout: Duration,
 ) -> Result<TcpStream, String> {
@@ -178,7 +182,16 @@ pub async fn connect_tcp(
         return Err(format!("no addresses resolved for {}:{}", host, port));
     }
 
-    let ordered = interleave_by_family(&addrs);
+    let ordered = interleave_by_family(&prefs.order(&addrs));
+    if ordered.is_empty() {
+        return Err(format!(
+            "no {:?} addresses for {}:{} (resolved {})",
+            prefs.address_family,
+            host,
+            port,
+            addrs.len()
+        ));
+    }
     info!(
         host,
         port,
@@ -190,7 +203,7 @@ pub async fn connect_tcp(
         &ordered,
         attempt_delay,
         overall_timeout,
-        |addr| async move { TcpStream::connect(addr).await },
+        |addr| prefs.connect(addr),
     )
     .await
     .map_err(|e| format!("TCP connect failed to {}:{}: {}", host, port, e))?;
@@ -410,6 +423,7 @@ mod tests {
         let stream = connect_tcp(
             "127.0.0.1",
             port,
+            &NetworkPrefs::default(),
             Duration::from_millis(250),
             Duration::from_secs(5),
         )
@@ -431,6 +445,7 @@ mod tests {
         let err = connect_tcp(
             "127.0.0.1",
             port,
+            &NetworkPrefs::default(),
             Duration::from_millis(250),
             Duration::from_secs(5),
         )
@@ -0,0 +1,168 @@
+//! Manually configured fallback endpoints per XMPP domain.
+//!
+//! Some servers publish known fallback hosts (a backup on port 443, a second
+//! data centre) but have DNS that is unreliable or missing their SRV records.
+//! The user can list such endpoints for an account's domain, in the same
+//! `tls://host:port` / `tcp://host:port` syntax as the server field. They are
+//! tried after the SRV results, in the order given, and alone when SRV
+//! resolution fails outright. Persisted in `host-overrides.json`.
+//!
+//! Keyed by domain rather than by account: the proxy learns the JID's domain
+//! from the client's `<open to=…>`, but never the full JID before SASL.
+
+use super::dns::{parse_server_input, ParsedServer, XmppEndpoint};
+use crate::error::FluuxError;
+use crate::json_file::JsonFile;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tracing::info;
+
+type Overrides = BTreeMap<String, Vec<String>>;
+
+static OVERRIDES: Mutex<Overrides> = Mutex::new(BTreeMap::new());
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+fn overrides() -> std::sync::MutexGuard<'static, Overrides> {
+    OVERRIDES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn key(domain: &str) -> String {
+    domain.trim().trim_end_matches('.').to_lowercase()
+}
+
+/// Parse one override, which must name its scheme so the connection mode
+/// is explicit.
+fn parse_endpoint(input: &str, domain: &str) -> Result<XmppEndpoint, String> {
+    let trimmed = input.trim();
+    if !trimmed.starts_with("tls://") && !trimmed.starts_with("tcp://") {
+        return Err(format!(
+            "Endpoint '{trimmed}' must start with tls:// or tcp://"
+        ));
+    }
+    match parse_server_input(trimmed) {
+        ParsedServer::Direct(host, port, mode, explicit_domain) if !host.is_empty() => {
+            Ok(XmppEndpoint {
+                host,
+                port,
+                mode,
+                // The certificate is checked against the XMPP domain, as for
+                // SRV targets.
+                domain: explicit_domain.or_else(|| Some(domain.to_string())),
+            })
+        }
+        _ => Err(format!("Endpoint '{trimmed}' has no host")),
+    }
+}
+
+/// Fallback endpoints configured for `domain`, in order.
+pub(super) fn endpoints_for(domain: &str) -> Vec<XmppEndpoint> {
+    let Some(list) = overrides().get(&key(domain)).cloned() else {
+        return Vec::new();
+    };
+    list.iter()
+        .filter_map(|entry| parse_endpoint(entry, domain).ok())
+        .collect()
+}
+
+/// Append `domain`'s overrides to `endpoints`, skipping any already there.
+pub(super) fn merge(endpoints: &mut Vec<XmppEndpoint>, domain: &str) {
+    for endpoint in endpoints_for(domain) {
+        let known = endpoints.iter().any(|e| {
+            e.host.eq_ignore_ascii_case(&endpoint.host)
+                && e.port == endpoint.port
+                && e.mode == endpoint.mode
+        });
+        if !known {
+            info!(host = %endpoint.host, port = endpoint.port, mode = ?endpoint.mode,
+                "Adding configured fallback endpoint");
+            endpoints.push(endpoint);
+        }
+    }
+}
+
+/// Load the persisted overrides from `dir`. Called from the Tauri `setup`
+/// hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("host-overrides.json")) else {
+        return;
+    };
+    let loaded: Overrides = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    *overrides() = loaded;
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `OVERRIDES` lock held; see [`crate::json_file`].
+fn persist(snapshot: Overrides) {
+    if let Some(file) = FILE.get() {
+        file.save(snapshot);
+    }
+}
+
+#[tauri::command]
+pub fn get_host_overrides(domain: String) -> Vec<String> {
+    overrides().get(&key(&domain)).cloned().unwrap_or_default()
+}
+
+/// Replace the fallback endpoints for `domain`. An empty list removes them.
+#[tauri::command]
+pub fn set_host_overrides(domain: String, endpoints: Vec<String>) -> Result<(), FluuxError> {
+    let domain = key(&domain);
+    if domain.is_empty() {
+        return Err(FluuxError::InvalidInput("Domain is empty".to_string()));
+    }
+    let endpoints: Vec<String> = endpoints
+        .iter()
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty())
+        .collect();
+    for endpoint in &endpoints {
+        parse_endpoint(endpoint, &domain).map_err(FluuxError::InvalidInput)?;
+    }
+    let mut all = overrides();
+    if endpoints.is_empty() {
+        all.remove(&domain);
+    } else {
+        all.insert(domain.clone(), endpoints);
+    }
+    persist(all.clone());
+    info!(domain, "Fallback endpoints updated");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::dns::ConnectionMode;
+    use super::*;
+
+    #[test]
+    fn overrides_are_validated_and_appended_after_srv_results() {
+        assert!(set_host_overrides("Example.ORG.".into(), vec!["backup:443".into()]).is_err());
+        set_host_overrides(
+            "Example.ORG.".into(),
+            vec![
+                "tls://backup1.example.org:443".into(),
+                "tcp://xmpp.example.org".into(),
+            ],
+        )
+        .unwrap();
+        let mut endpoints = vec![XmppEndpoint {
+            host: "xmpp.example.org".to_string(),
+            port: 5222,
+            mode: ConnectionMode::Tcp,
+            domain: Some("example.org".to_string()),
+        }];
+        merge(&mut endpoints, "example.org");
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[1].host, "backup1.example.org");
+        assert_eq!(endpoints[1].port, 443);
+        assert_eq!(endpoints[1].mode, ConnectionMode::DirectTls);
+        assert_eq!(endpoints[1].tls_name(), "example.org");
+
+        set_host_overrides("example.org".into(), Vec::new()).unwrap();
+        assert!(endpoints_for("example.org").is_empty());
+    }
+}
@@ -0,0 +1,587 @@
+//! Serverless messaging on the local network (XEP-0174).
+//!
+//! When no server is reachable (an office LAN cut off from the internet, a
+//! conference room), peers can still chat directly. While enabled, this
+//! module:
+//!
+//! - advertises the user as a `_presence._tcp` DNS-SD service over mDNS,
+//!   with the XEP-0174 TXT record (nick, status, …), and answers queries for
+//!   it;
+//! - browses for other peers and reports the list as a `link-local-peers`
+//!   event;
+//! - accepts streams from peers on the advertised port, parking each one and
+//!   announcing it as a `link-local-incoming` event until the WebView claims
+//!   it.
+//!
+//! Link-local streams are then bridged like server streams, over the same
+//! local WebSocket protocol: a proxy started for `link-local://` routes each
+//! WebSocket to the peer named in the client's `<open to=…>` (or
+//! `link-local://<peer>` names it outright), and
+//! `link-local://incoming/<id>` picks up a parked incoming stream. Streams
+//! are plain TCP, as XEP-0174 specifies; there is no SASL step.
+
+use super::mdns::{self, Message, RData, Record};
+use crate::error::FluuxError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+const SCHEME: &str = "link-local://";
+const SERVICE: &str = "_presence._tcp.local";
+/// TTLs recommended by RFC 6762 §10 for host and other records.
+const HOST_TTL: u32 = 120;
+const SERVICE_TTL: u32 = 4500;
+const REQUERY_INTERVAL: Duration = Duration::from_secs(60);
+/// How long an incoming stream waits for the WebView to claim it.
+const INCOMING_CLAIM_TIMEOUT: Duration = Duration::from_secs(60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the user publishes about themselves (the XEP-0174 TXT record).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LinkLocalProfile {
+    /// User part of the service instance name, `user@machine`.
+    pub user: String,
+    pub nick: Option<String>,
+    pub first: Option<String>,
+    pub last: Option<String>,
+    /// `avail`, `away` or `dnd`.
+    pub status: Option<String>,
+    pub msg: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkLocalPeer {
+    /// Service instance name, `user@machine`; what `<open to=…>` names.
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub addresses: Vec<IpAddr>,
+    /// TXT record entries (`nick`, `status`, `msg`, …).
+    pub txt: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkLocalStatus {
+    pub enabled: bool,
+    /// Our own instance name, while enabled.
+    pub name: Option<String>,
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IncomingEvent {
+    id: u64,
+    /// The peer the connection came from, when its address is known.
+    from: Option<String>,
+    address: String,
+}
+
+struct Service {
+    name: String,
+    port: u16,
+    shutdown: watch::Sender<bool>,
+}
+
+#[derive(Default)]
+struct PeerEntry {
+    host: Option<String>,
+    port: u16,
+    txt: BTreeMap<String, String>,
+    expires: Option<Instant>,
+}
+
+/// What the browser has learnt, keyed by full service instance name.
+#[derive(Default)]
+struct Directory {
+    peers: BTreeMap<String, PeerEntry>,
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+static SERVICE_STATE: Mutex<Option<Service>> = Mutex::new(None);
+static DIRECTORY: Mutex<Option<Directory>> = Mutex::new(None);
+static INCOMING: Mutex<Option<HashMap<u64, TcpStream>>> = Mutex::new(None);
+static NEXT_INCOMING_ID: AtomicU64 = AtomicU64::new(1);
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn instance_fqdn(name: &str) -> String {
+    format!("{name}.{SERVICE}")
+}
+
+/// `host.local` for this machine, with characters DNS labels can't hold
+/// replaced.
+fn local_host() -> String {
+    let host = tauri_plugin_os::hostname();
+    let host = host.trim_end_matches(".local");
+    let label: String = host
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("{}.local", label.trim_matches('-'))
+}
+
+fn txt_entries(profile: &LinkLocalProfile, port: u16) -> Vec<String> {
+    let mut entries = vec!["txtvers=1".to_string(), format!("port.p2pj={port}")];
+    for (key, value) in [
+        ("nick", &profile.nick),
+        ("1st", &profile.first),
+        ("last", &profile.last),
+        ("status", &profile.status),
+        ("msg", &profile.msg),
+    ] {
+        if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+            entries.push(format!("{key}={value}"));
+        }
+    }
+    entries
+}
+
+/// Our records, announced and sent in answer to matching queries.
+fn own_records(name: &str, host: &str, port: u16, txt: &[String], ttl_scale: u32) -> Vec<Record> {
+    let fqdn = instance_fqdn(name);
+    let mut records = vec![
+        Record::new(SERVICE, SERVICE_TTL * ttl_scale, RData::Ptr(fqdn.clone())),
+        Record::new(
+            &fqdn,
+            HOST_TTL * ttl_scale,
+            RData::Srv {
+                priority: 0,
+                weight: 0,
+                port,
+                target: host.to_string(),
+            },
+        ),
+        Record::new(&fqdn, SERVICE_TTL * ttl_scale, RData::Txt(txt.to_vec())),
+    ];
+    if let Some(ip) = mdns::local_ipv4() {
+        records.push(Record::new(host, HOST_TTL * ttl_scale, RData::A(ip)));
+    }
+    records
+}
+
+impl Directory {
+    /// Fold a response's records in, ignoring our own. Returns whether the
+    /// peer list may have changed.
+    fn apply(&mut self, records: &[Record], own_fqdn: &str, now: Instant) -> bool {
+        let mut changed = false;
+        let suffix = format!(".{SERVICE}");
+        for record in records {
+            let expiry = now + Duration::from_secs(u64::from(record.ttl));
+            // A goodbye for an instance's SRV or TXT also withdraws it.
+            if record.ttl == 0 && record.name.ends_with(&suffix) {
+                changed |= self.peers.remove(&record.name).is_some();
+                continue;
+            }
+            match &record.data {
+                RData::Ptr(fqdn) if record.name.eq_ignore_ascii_case(SERVICE) => {
+                    if fqdn == own_fqdn {
+                        continue;
+                    }
+                    if record.ttl == 0 {
+                        changed |= self.peers.remove(fqdn).is_some();
+                    } else {
+                        self.peers.entry(fqdn.clone()).or_default().expires = Some(expiry);
+                        changed = true;
+                    }
+                }
+                RData::Srv { port, target, .. }
+                    if record.name.ends_with(&suffix) && record.name != own_fqdn =>
+                {
+                    let entry = self.peers.entry(record.name.clone()).or_default();
+                    entry.host = Some(target.clone());
+                    entry.port = *port;
+                    entry.expires.get_or_insert(expiry);
+                    changed = true;
+                }
+                RData::Txt(entries)
+                    if record.name.ends_with(&suffix) && record.name != own_fqdn =>
+                {
+                    let entry = self.peers.entry(record.name.clone()).or_default();
+                    entry.txt = entries
+                        .iter()
+                        .filter_map(|e| e.split_once('='))
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect();
+                    entry.expires.get_or_insert(expiry);
+                    changed = true;
+                }
+                RData::A(ip) => changed |= self.add_address(&record.name, (*ip).into()),
+                RData::Aaaa(ip) => changed |= self.add_address(&record.name, (*ip).into()),
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    fn add_address(&mut self, host: &str, ip: IpAddr) -> bool {
+        let addrs = self.hosts.entry(host.to_lowercase()).or_default();
+        if addrs.contains(&ip) {
+            return false;
+        }
+        addrs.push(ip);
+        true
+    }
+
+    /// Drop peers whose records have expired. Returns whether any did.
+    fn expire(&mut self, now: Instant) -> bool {
+        let before = self.peers.len();
+        self.peers
+            .retain(|_, peer| peer.expires.is_none_or(|expires| expires > now));
+        self.peers.len() != before
+    }
+
+    /// Peers with enough records to connect to.
+    fn list(&self) -> Vec<LinkLocalPeer> {
+        let suffix = format!(".{SERVICE}");
+        self.peers
+            .iter()
+            .filter_map(|(fqdn, entry)| {
+                let host = entry.host.clone()?;
+                let addresses = self.hosts.get(&host.to_lowercase())?.clone();
+                Some(LinkLocalPeer {
+                    name: fqdn.strip_suffix(&suffix).unwrap_or(fqdn).to_string(),
+                    host,
+                    port: entry.port,
+                    addresses,
+                    txt: entry.txt.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn peers() -> Vec<LinkLocalPeer> {
+    lock(&DIRECTORY)
+        .as_ref()
+        .map(Directory::list)
+        .unwrap_or_default()
+}
+
+fn emit_peers(app: &tauri::AppHandle) {
+    let _ = app.emit("link-local-peers", peers());
+}
+
+async fn send(socket: &tokio::net::UdpSocket, message: &Message) {
+    if let Err(e) = socket.send_to(&message.encode(), mdns::group()).await {
+        debug!(error = %e, "link-local: mDNS send failed");
+    }
+}
+
+/// The mDNS responder and browser, until `shutdown` flips.
+async fn run_mdns(
+    socket: tokio::net::UdpSocket,
+    own: Vec<Record>,
+    goodbye: Vec<Record>,
+    mut shutdown: watch::Receiver<bool>,
+    app: tauri::AppHandle,
+) {
+    let own_fqdn = own
+        .iter()
+        .find_map(|r| match &r.data {
+            RData::Ptr(fqdn) => Some(fqdn.clone()),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let host = own
+        .iter()
+        .find_map(|r| match &r.data {
+            RData::Srv { target, .. } => Some(target.clone()),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    // Announce twice, a second apart (RFC 6762 §8.3), then browse.
+    send(&socket, &Message::response(own.clone())).await;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    send(&socket, &Message::response(own.clone())).await;
+
+    let mut requery = tokio::time::interval(REQUERY_INTERVAL);
+    let mut buf = vec![0u8; 9000];
+    loop {
+        tokio::select! {
+            _ = requery.tick() => {
+                send(&socket, &Message::query(vec![(SERVICE.to_string(), mdns::TYPE_PTR)])).await;
+                let expired = lock(&DIRECTORY)
+                    .as_mut()
+                    .is_some_and(|dir| dir.expire(Instant::now()));
+                if expired {
+                    emit_peers(&app);
+                }
+            }
+            received = socket.recv_from(&mut buf) => {
+                let Ok((len, from)) = received else { continue };
+                let Some(message) = Message::decode(&buf[..len]) else {
+                    debug!(%from, "link-local: ignoring malformed mDNS packet");
+                    continue;
+                };
+                if message.response {
+                    let changed = lock(&DIRECTORY)
+                        .as_mut()
+                        .is_some_and(|dir| dir.apply(&message.records, &own_fqdn, Instant::now()));
+                    if changed {
+                        emit_peers(&app);
+                    }
+                } else {
+                    let asks_for_us = message.questions.iter().any(|(name, qtype)| {
+                        (name.eq_ignore_ascii_case(SERVICE)
+                            && matches!(*qtype, mdns::TYPE_PTR | mdns::TYPE_ANY))
+                            || name.eq_ignore_ascii_case(&own_fqdn)
+                            || name.eq_ignore_ascii_case(&host)
+                    });
+                    if asks_for_us {
+                        send(&socket, &Message::response(own.clone())).await;
+                    }
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+    }
+    send(&socket, &Message::response(goodbye)).await;
+}
+
+/// Accept peer streams and park them until claimed.
+async fn run_listener(
+    listener: TcpListener,
+    mut shutdown: watch::Receiver<bool>,
+    app: tauri::AppHandle,
+) {
+    loop {
+        let (stream, address) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(error = %e, "link-local: accept failed");
+                    continue;
+                }
+            },
+            _ = shutdown.changed() => break,
+        };
+        let id = NEXT_INCOMING_ID.fetch_add(1, Ordering::Relaxed);
+        let from = peers()
+            .into_iter()
+            .find(|peer| peer.addresses.contains(&address.ip()))
+            .map(|peer| peer.name);
+        info!(id, %address, from = ?from, "link-local: incoming stream");
+        lock(&INCOMING)
+            .get_or_insert_with(HashMap::new)
+            .insert(id, stream);
+        let _ = app.emit(
+            "link-local-incoming",
+            IncomingEvent {
+                id,
+                from,
+                address: address.to_string(),
+            },
+        );
+        tokio::spawn(async move {
+            tokio::time::sleep(INCOMING_CLAIM_TIMEOUT).await;
+            if let Some(incoming) = lock(&INCOMING).as_mut() {
+                if incoming.remove(&id).is_some() {
+                    debug!(id, "link-local: unclaimed incoming stream dropped");
+                }
+            }
+        });
+    }
+}
+
+/// The link-local stream named by `server_input`, for the proxy to bridge
+/// in place of a server connection. `None` when `server_input` isn't a
+/// `link-local://` address.
+pub(super) async fn open(
+    server_input: &str,
+    client_domain: Option<&str>,
+) -> Option<Result<TcpStream, String>> {
+    let target = server_input.trim().strip_prefix(SCHEME)?;
+    Some(open_target(target, client_domain).await)
+}
+
+async fn open_target(target: &str, client_domain: Option<&str>) -> Result<TcpStream, String> {
+    if let Some(id) = target.strip_prefix("incoming/") {
+        let id: u64 = id
+            .parse()
+            .map_err(|_| format!("Invalid incoming id '{id}'"))?;
+        return lock(&INCOMING)
+            .as_mut()
+            .and_then(|incoming| incoming.remove(&id))
+            .ok_or_else(|| format!("No pending link-local stream {id}"));
+    }
+    let name = Some(target.trim_end_matches('/'))
+        .filter(|t| !t.is_empty())
+        .or(client_domain)
+        .ok_or("No link-local peer named")?;
+    let peer = peers()
+        .into_iter()
+        .find(|peer| peer.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Link-local peer '{name}' not found"))?;
+    let mut last_error = String::from("no addresses");
+    for ip in &peer.addresses {
+        let addr = std::net::SocketAddr::new(*ip, peer.port);
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => {
+                info!(peer = %peer.name, %addr, "link-local: connected to peer");
+                return Ok(stream);
+            }
+            Ok(Err(e)) => last_error = e.to_string(),
+            Err(_) => last_error = "timed out".to_string(),
+        }
+    }
+    Err(format!(
+        "Failed to connect to link-local peer '{name}': {last_error}"
+    ))
+}
+
+/// Start advertising and browsing, or restart with a new profile.
+#[tauri::command]
+pub async fn start_link_local(
+    app: tauri::AppHandle,
+    profile: LinkLocalProfile,
+) -> Result<LinkLocalStatus, FluuxError> {
+    let user = profile.user.trim();
+    if user.is_empty() || user.contains(['@', '.']) {
+        return Err(FluuxError::InvalidInput(
+            "The user name must be non-empty and contain no '@' or '.'".to_string(),
+        ));
+    }
+    stop_link_local(app.clone());
+
+    let host = local_host();
+    let name = format!("{user}@{}", host.trim_end_matches(".local"));
+    let listener = TcpListener::bind(("0.0.0.0", 0)).await.map_err(|e| {
+        FluuxError::LocalServer(format!("Failed to listen for link-local peers: {e}"))
+    })?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| FluuxError::LocalServer(e.to_string()))?
+        .port();
+    let socket = mdns::socket()
+        .map_err(|e| FluuxError::LocalServer(format!("Failed to open the mDNS socket: {e}")))?;
+
+    let txt = txt_entries(&profile, port);
+    let own = own_records(&name, &host, port, &txt, 1);
+    let goodbye = own_records(&name, &host, port, &txt, 0);
+    let (shutdown, shutdown_rx) = watch::channel(false);
+    *lock(&DIRECTORY) = Some(Directory::default());
+    tokio::spawn(run_mdns(
+        socket,
+        own,
+        goodbye,
+        shutdown_rx.clone(),
+        app.clone(),
+    ));
+    tokio::spawn(run_listener(listener, shutdown_rx, app));
+
+    info!(name = %name, port, "link-local: advertising");
+    *lock(&SERVICE_STATE) = Some(Service {
+        name: name.clone(),
+        port,
+        shutdown,
+    });
+    Ok(LinkLocalStatus {
+        enabled: true,
+        name: Some(name),
+        port: Some(port),
+    })
+}
+
+/// Withdraw the advertisement and forget discovered peers.
+#[tauri::command]
+pub fn stop_link_local(app: tauri::AppHandle) {
+    let Some(service) = lock(&SERVICE_STATE).take() else {
+        return;
+    };
+    let _ = service.shutdown.send(true);
+    *lock(&DIRECTORY) = None;
+    *lock(&INCOMING) = None;
+    info!(name = %service.name, "link-local: stopped");
+    emit_peers(&app);
+}
+
+#[tauri::command]
+pub fn link_local_status() -> LinkLocalStatus {
+    let service = lock(&SERVICE_STATE);
+    LinkLocalStatus {
+        enabled: service.is_some(),
+        name: service.as_ref().map(|s| s.name.clone()),
+        port: service.as_ref().map(|s| s.port),
+    }
+}
+
+#[tauri::command]
+pub fn list_link_local_peers() -> Vec<LinkLocalPeer> {
+    peers()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directory_tracks_peers_from_responses() {
+        let now = Instant::now();
+        let profile = LinkLocalProfile {
+            user: "juliet".to_string(),
+            nick: Some("Jules".to_string()),
+            status: Some("avail".to_string()),
+            ..Default::default()
+        };
+        let txt = txt_entries(&profile, 5562);
+        let mut records = own_records("juliet@pronto", "pronto.local", 5562, &txt, 1);
+        records.retain(|r| r.rtype != mdns::TYPE_A);
+        records.push(Record::new(
+            "pronto.local",
+            120,
+            RData::A("10.2.1.188".parse().unwrap()),
+        ));
+
+        let mut directory = Directory::default();
+        assert!(directory.apply(&records, &instance_fqdn("romeo@montague"), now));
+        let peers = directory.list();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].name, "juliet@pronto");
+        assert_eq!(peers[0].port, 5562);
+        assert_eq!(
+            peers[0].addresses,
+            vec!["10.2.1.188".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(peers[0].txt.get("nick").map(String::as_str), Some("Jules"));
+
+        // Our own advertisement echoed back is not a peer.
+        let mut own = Directory::default();
+        own.apply(&records, &instance_fqdn("juliet@pronto"), now);
+        assert!(own.list().is_empty());
+
+        // A goodbye (TTL 0) removes the peer at once.
+        let goodbye = own_records("juliet@pronto", "pronto.local", 5562, &txt, 0);
+        assert!(directory.apply(&goodbye, "", now));
+        assert!(directory.list().is_empty());
+    }
+
+    #[test]
+    fn peers_expire_with_their_ptr_record() {
+        let now = Instant::now();
+        let mut directory = Directory::default();
+        let mut records = own_records("juliet@pronto", "pronto.local", 5562, &[], 1);
+        records.push(Record::new(
+            "pronto.local",
+            120,
+            RData::A("10.2.1.188".parse().unwrap()),
+        ));
+        directory.apply(&records, "", now);
+        assert!(!directory.expire(now + Duration::from_secs(60)));
+        assert!(directory.expire(now + Duration::from_secs(u64::from(SERVICE_TTL) + 1)));
+        assert!(directory.list().is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+//! Diagnostics for the loopback listener the WebView connects to.
+//!
+//! Two things break the local hop in practice: the port the proxy asks for
+//! is taken by another program, or security software filters loopback
+//! traffic (common on Windows), for every process or only the WebView's.
+//! The first shows as a bind error ([`bind_issue`]). The second shows as
+//! our own connection to a fresh listener not getting through
+//! ([`self_check`]), or, when only the WebView is filtered, as the WebView
+//! reporting that it could not open the URL (`report_proxy_unreachable`).
+//! The proxy then tries the next address or another port, and reports what
+//! it found as a `proxy-loopback-diagnostic` event with a remediation hint
+//! and the URL it serves from now on, rather than a generic failure.
+
+use serde::Serialize;
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
+
+/// How long a connection to a fresh listener may take. Loopback connects
+/// within a millisecond unless something filters it.
+const SELF_CHECK_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum LoopbackIssue {
+    /// Another program listens on the address.
+    PortInUse { addr: String },
+    /// Binding was refused (security software, sandbox, no such address).
+    BindDenied { addr: String, error: String },
+    /// Our own connection to the listener did not get through.
+    SelfConnectFailed { url: String, error: String },
+    /// The WebView could not connect although the listener works.
+    ClientBlocked { url: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LoopbackDiagnostic<'a> {
+    #[serde(flatten)]
+    issue: &'a LoopbackIssue,
+    remediation: &'static str,
+    /// URL the proxy serves after working around the problem, if it could.
+    fallback_url: Option<&'a str>,
+}
+
+/// The issue a failed bind of `addr` points to.
+pub(super) fn bind_issue(addr: &str, error: &std::io::Error) -> LoopbackIssue {
+    match error.kind() {
+        std::io::ErrorKind::AddrInUse => LoopbackIssue::PortInUse {
+            addr: addr.to_string(),
+        },
+        _ => LoopbackIssue::BindDenied {
+            addr: addr.to_string(),
+            error: error.to_string(),
+        },
+    }
+}
+
+fn remediation(issue: &LoopbackIssue) -> &'static str {
+    match issue {
+        LoopbackIssue::PortInUse { .. } => {
+            "Another program uses this local port, so Fluux moved to another one. Nothing to do \
+             unless it keeps happening."
+        }
+        LoopbackIssue::BindDenied { .. } => {
+            "The system refused to let Fluux listen on a local port. Check that your security \
+             software allows Fluux to accept connections on 127.0.0.1."
+        }
+        LoopbackIssue::SelfConnectFailed { .. } | LoopbackIssue::ClientBlocked { .. } => {
+            if cfg!(target_os = "windows") {
+                "Security software is blocking local connections. Allow Fluux and Microsoft Edge \
+                 WebView2 (msedgewebview2.exe) to connect to 127.0.0.1 in your firewall or \
+                 antivirus settings."
+            } else if cfg!(target_os = "macos") {
+                "A network filter is blocking local connections. Allow Fluux to connect to \
+                 127.0.0.1 in your firewall (e.g. Little Snitch or LuLu)."
+            } else {
+                "A firewall is filtering loopback traffic. Allow connections on the lo interface \
+                 (e.g. `iptables -I INPUT -i lo -j ACCEPT`)."
+            }
+        }
+    }
+}
+
+/// Log `issue` and emit it as `proxy-loopback-diagnostic`.
+pub(super) fn report(
+    app: Option<&tauri::AppHandle>,
+    issue: &LoopbackIssue,
+    fallback_url: Option<&str>,
+) {
+    warn!(?issue, fallback_url, "Loopback listener problem");
+    if let Some(app) = app {
+        let _ = app.emit(
+            "proxy-loopback-diagnostic",
+            LoopbackDiagnostic {
+                issue,
+                remediation: remediation(issue),
+                fallback_url,
+            },
+        );
+    }
+}
+
+/// Connect to `listener` over loopback and accept the connection, as the
+/// WebView will. Run before the accept loop takes the listener over.
+pub(super) async fn self_check(listener: &TcpListener) -> Result<(), String> {
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    let check = async {
+        tokio::try_join!(
+            async { TcpStream::connect(addr).await.map_err(|e| e.to_string()) },
+            async { listener.accept().await.map_err(|e| e.to_string()) },
+        )
+    };
+    match tokio::time::timeout(SELF_CHECK_TIMEOUT, check).await {
+        Ok(result) => result.map(drop),
+        Err(_) => Err(format!(
+            "no connection within {}ms",
+            SELF_CHECK_TIMEOUT.as_millis()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn checks_the_listener_and_names_bind_failures() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        self_check(&listener).await.unwrap();
+
+        let addr = listener.local_addr().unwrap().to_string();
+        let error = TcpListener::bind(&addr).await.unwrap_err();
+        assert_eq!(
+            bind_issue(&addr, &error),
+            LoopbackIssue::PortInUse { addr: addr.clone() }
+        );
+        let json = serde_json::to_value(LoopbackDiagnostic {
+            issue: &LoopbackIssue::ClientBlocked { url: addr },
+            remediation: "",
+            fallback_url: Some("ws://127.0.0.1:1"),
+        })
+        .unwrap();
+        assert_eq!(json["kind"], "client-blocked");
+        assert_eq!(json["fallbackUrl"], "ws://127.0.0.1:1");
+    }
+}
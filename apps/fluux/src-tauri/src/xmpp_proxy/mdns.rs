@@ -0,0 +1,342 @@
+//! Minimal multicast DNS (RFC 6762) wire format and socket, for link-local
+//! messaging (see `link_local`).
+//!
+//! Only what DNS-SD service discovery needs is modelled: PTR, SRV, TXT, A
+//! and AAAA records. Names are written uncompressed and read with
+//! compression pointers followed. IPv4 multicast only.
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+use tokio::net::UdpSocket;
+
+pub(super) const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub(super) const MDNS_PORT: u16 = 5353;
+
+pub(super) const TYPE_A: u16 = 1;
+pub(super) const TYPE_PTR: u16 = 12;
+pub(super) const TYPE_TXT: u16 = 16;
+pub(super) const TYPE_AAAA: u16 = 28;
+pub(super) const TYPE_SRV: u16 = 33;
+pub(super) const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+/// Top bit of the class: "cache flush" in answers, "unicast response" in
+/// questions.
+const CLASS_FLAG: u16 = 0x8000;
+const FLAG_RESPONSE: u16 = 0x8400;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum RData {
+    Ptr(String),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    Txt(Vec<String>),
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+impl Record {
+    pub fn new(name: &str, ttl: u32, data: RData) -> Self {
+        let rtype = match data {
+            RData::Ptr(_) => TYPE_PTR,
+            RData::Srv { .. } => TYPE_SRV,
+            RData::Txt(_) => TYPE_TXT,
+            RData::A(_) => TYPE_A,
+            RData::Aaaa(_) => TYPE_AAAA,
+            RData::Other => 0,
+        };
+        Self {
+            name: name.to_string(),
+            rtype,
+            ttl,
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct Message {
+    pub response: bool,
+    /// Question names and types.
+    pub questions: Vec<(String, u16)>,
+    /// Answer, authority and additional records together.
+    pub records: Vec<Record>,
+}
+
+impl Message {
+    pub fn query(questions: Vec<(String, u16)>) -> Self {
+        Self {
+            response: false,
+            questions,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn response(records: Vec<Record>) -> Self {
+        Self {
+            response: true,
+            questions: Vec::new(),
+            records,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(512);
+        let flags = if self.response { FLAG_RESPONSE } else { 0 };
+        for field in [
+            0,
+            flags,
+            self.questions.len() as u16,
+            self.records.len() as u16,
+            0,
+            0,
+        ] {
+            out.extend_from_slice(&field.to_be_bytes());
+        }
+        for (name, qtype) in &self.questions {
+            write_name(&mut out, name);
+            out.extend_from_slice(&qtype.to_be_bytes());
+            out.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        for record in &self.records {
+            write_name(&mut out, &record.name);
+            out.extend_from_slice(&record.rtype.to_be_bytes());
+            // Shared PTR records must not carry the cache-flush bit.
+            let class = if record.rtype == TYPE_PTR {
+                CLASS_IN
+            } else {
+                CLASS_IN | CLASS_FLAG
+            };
+            out.extend_from_slice(&class.to_be_bytes());
+            out.extend_from_slice(&record.ttl.to_be_bytes());
+            let mut rdata = Vec::new();
+            match &record.data {
+                RData::Ptr(name) => write_name(&mut rdata, name),
+                RData::Srv {
+                    priority,
+                    weight,
+                    port,
+                    target,
+                } => {
+                    for field in [priority, weight, port] {
+                        rdata.extend_from_slice(&field.to_be_bytes());
+                    }
+                    write_name(&mut rdata, target);
+                }
+                RData::Txt(entries) => {
+                    for entry in entries {
+                        let bytes = &entry.as_bytes()[..entry.len().min(255)];
+                        rdata.push(bytes.len() as u8);
+                        rdata.extend_from_slice(bytes);
+                    }
+                    if entries.is_empty() {
+                        rdata.push(0);
+                    }
+                }
+                RData::A(ip) => rdata.extend_from_slice(&ip.octets()),
+                RData::Aaaa(ip) => rdata.extend_from_slice(&ip.octets()),
+                RData::Other => {}
+            }
+            out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            out.extend_from_slice(&rdata);
+        }
+        out
+    }
+
+    /// Parse a packet. `None` if it is truncated or malformed.
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        let u16_at = |at: usize| -> Option<u16> {
+            Some(u16::from_be_bytes(packet.get(at..at + 2)?.try_into().ok()?))
+        };
+        let flags = u16_at(2)?;
+        let questions = u16_at(4)? as usize;
+        let records = [u16_at(6)?, u16_at(8)?, u16_at(10)?]
+            .iter()
+            .map(|n| *n as usize)
+            .sum::<usize>();
+
+        let mut message = Message {
+            response: flags & 0x8000 != 0,
+            ..Default::default()
+        };
+        let mut at = 12;
+        for _ in 0..questions {
+            let (name, next) = read_name(packet, at)?;
+            message.questions.push((name, u16_at(next)?));
+            at = next + 4;
+        }
+        for _ in 0..records {
+            let (name, next) = read_name(packet, at)?;
+            let rtype = u16_at(next)?;
+            let ttl = u32::from_be_bytes(packet.get(next + 4..next + 8)?.try_into().ok()?);
+            let len = u16_at(next + 8)? as usize;
+            let start = next + 10;
+            let rdata = packet.get(start..start + len)?;
+            let data = match rtype {
+                TYPE_PTR => RData::Ptr(read_name(packet, start)?.0),
+                TYPE_SRV if len >= 6 => RData::Srv {
+                    priority: u16_at(start)?,
+                    weight: u16_at(start + 2)?,
+                    port: u16_at(start + 4)?,
+                    target: read_name(packet, start + 6)?.0,
+                },
+                TYPE_TXT => {
+                    let mut entries = Vec::new();
+                    let mut rest = rdata;
+                    while let Some((&len, tail)) = rest.split_first() {
+                        let entry = tail.get(..len as usize)?;
+                        if !entry.is_empty() {
+                            entries.push(String::from_utf8_lossy(entry).into_owned());
+                        }
+                        rest = &tail[len as usize..];
+                    }
+                    RData::Txt(entries)
+                }
+                TYPE_A if len == 4 => RData::A(Ipv4Addr::from(<[u8; 4]>::try_from(rdata).ok()?)),
+                TYPE_AAAA if len == 16 => {
+                    RData::Aaaa(Ipv6Addr::from(<[u8; 16]>::try_from(rdata).ok()?))
+                }
+                _ => RData::Other,
+            };
+            message.records.push(Record {
+                name,
+                rtype,
+                ttl,
+                data,
+            });
+            at = start + len;
+        }
+        Some(message)
+    }
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() {
+            continue;
+        }
+        let bytes = &label.as_bytes()[..label.len().min(63)];
+        out.push(bytes.len() as u8);
+        out.extend_from_slice(bytes);
+    }
+    out.push(0);
+}
+
+/// Read the name at `at`, returning it and the offset just past it.
+fn read_name(packet: &[u8], mut at: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bounds the number of pointers followed, so a loop can't hang us.
+    for _ in 0..64 {
+        let len = *packet.get(at)?;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(at + 1)));
+            }
+            l if l & 0xc0 == 0xc0 => {
+                let low = *packet.get(at + 1)?;
+                end.get_or_insert(at + 2);
+                at = (((l & 0x3f) as usize) << 8) | low as usize;
+            }
+            l if l < 64 => {
+                let label = packet.get(at + 1..at + 1 + l as usize)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                at += 1 + l as usize;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Destination for multicast queries and announcements.
+pub(super) fn group() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(MDNS_GROUP, MDNS_PORT))
+}
+
+/// A socket bound to the mDNS port and joined to the group. The port is
+/// shared with the system responder (Avahi, mDNSResponder), if any.
+pub(super) fn socket() -> std::io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_multicast_ttl_v4(255)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// The address this machine reaches the multicast group from, to publish in
+/// A records. No packet is sent.
+pub(super) fn local_ipv4() -> Option<Ipv4Addr> {
+    let probe = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    probe.connect(group()).ok()?;
+    match probe.local_addr().ok()?.ip() {
+        std::net::IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_survive_a_round_trip() {
+        let message = Message::response(vec![
+            Record::new(
+                "_presence._tcp.local",
+                4500,
+                RData::Ptr("juliet@pronto._presence._tcp.local".to_string()),
+            ),
+            Record::new(
+                "juliet@pronto._presence._tcp.local",
+                120,
+                RData::Srv {
+                    priority: 0,
+                    weight: 0,
+                    port: 5562,
+                    target: "pronto.local".to_string(),
+                },
+            ),
+            Record::new(
+                "juliet@pronto._presence._tcp.local",
+                4500,
+                RData::Txt(vec!["txtvers=1".to_string(), "status=avail".to_string()]),
+            ),
+            Record::new("pronto.local", 120, RData::A(Ipv4Addr::new(10, 2, 1, 188))),
+        ]);
+        assert_eq!(Message::decode(&message.encode()), Some(message));
+    }
+
+    #[test]
+    fn follows_compression_pointers_and_rejects_loops() {
+        // Header, then a PTR answer whose name is "local" and whose target
+        // points back at it with a "x" label in front.
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        packet.extend_from_slice(&[5, b'l', b'o', b'c', b'a', b'l', 0]);
+        packet.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0, 60, 0, 4]);
+        packet.extend_from_slice(&[1, b'x', 0xc0, 12]);
+        let message = Message::decode(&packet).unwrap();
+        assert_eq!(message.records[0].data, RData::Ptr("x.local".to_string()));
+
+        let looping = [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0xc0, 12, 0, 12, 0, 1];
+        assert_eq!(Message::decode(&looping), None);
+    }
+}
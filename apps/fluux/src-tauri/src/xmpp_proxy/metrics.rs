@@ -0,0 +1,268 @@
+//! Bridge counters in the Prometheus text format, for long-running desktop
+//! and headless sessions.
+//!
+//! The counters are always kept (they are a handful of atomics); the
+//! endpoint only runs with `--metrics-port=PORT`, on loopback, and serves
+//! `GET /metrics`. Failure classes reuse the telemetry keys, so nothing
+//! server-provided ends up in a label.
+
+use super::supervisor::Stage;
+use super::tap::Direction;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+static CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+static ONLINE: AtomicU64 = AtomicU64::new(0);
+static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+static BYTES_IN: AtomicU64 = AtomicU64::new(0);
+static BYTES_OUT: AtomicU64 = AtomicU64::new(0);
+static FORWARDED_IN: AtomicU64 = AtomicU64::new(0);
+static FORWARDED_OUT: AtomicU64 = AtomicU64::new(0);
+static DROPPED_IN: AtomicU64 = AtomicU64::new(0);
+static DROPPED_OUT: AtomicU64 = AtomicU64::new(0);
+/// Failures by `<stage>/<class>`.
+static FAILURES: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+fn bump(counter: &AtomicU64, by: u64) {
+    counter.fetch_add(by, Ordering::Relaxed);
+}
+
+/// A proxied connection was started.
+pub(super) fn connection_started() {
+    bump(&CONNECTIONS, 1);
+}
+
+/// A connection came online; every one after the first is a reconnect.
+pub(super) fn connected() {
+    if ONLINE.fetch_add(1, Ordering::Relaxed) > 0 {
+        bump(&RECONNECTS, 1);
+    }
+}
+
+/// A connection failed at `stage` with error class `class`.
+pub(super) fn connect_failed(stage: Stage, class: &str) {
+    let key = crate::telemetry::failure_key(stage, class);
+    let mut failures = FAILURES.lock().unwrap_or_else(|e| e.into_inner());
+    *failures.entry(key).or_default() += 1;
+}
+
+/// `bytes` of stream data passed through the bridge.
+pub(super) fn transferred(direction: Direction, bytes: usize) {
+    match direction {
+        Direction::Inbound => bump(&BYTES_IN, bytes as u64),
+        Direction::Outbound => bump(&BYTES_OUT, bytes as u64),
+    }
+}
+
+/// A routed stanza was relayed (`forwarded`) or dropped by the bridge.
+pub(super) fn stanza(direction: Direction, forwarded: bool) {
+    let counter = match (direction, forwarded) {
+        (Direction::Inbound, true) => &FORWARDED_IN,
+        (Direction::Outbound, true) => &FORWARDED_OUT,
+        (Direction::Inbound, false) => &DROPPED_IN,
+        (Direction::Outbound, false) => &DROPPED_OUT,
+    };
+    bump(counter, 1);
+}
+
+fn load(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP fluux_{name} {help}");
+    let _ = writeln!(out, "# TYPE fluux_{name} {kind}");
+}
+
+/// All metrics, in the Prometheus text exposition format (0.0.4).
+pub fn render() -> String {
+    let mut out = String::new();
+    family(
+        &mut out,
+        "connections_total",
+        "counter",
+        "Proxied connections started.",
+    );
+    let _ = writeln!(out, "fluux_connections_total {}", load(&CONNECTIONS));
+    family(
+        &mut out,
+        "connections_online_total",
+        "counter",
+        "Connections that came online.",
+    );
+    let _ = writeln!(out, "fluux_connections_online_total {}", load(&ONLINE));
+    family(
+        &mut out,
+        "reconnects_total",
+        "counter",
+        "Connections online after the first.",
+    );
+    let _ = writeln!(out, "fluux_reconnects_total {}", load(&RECONNECTS));
+
+    let clients = super::clients::snapshot();
+    family(
+        &mut out,
+        "clients",
+        "gauge",
+        "Bridged WebSocket clients by phase.",
+    );
+    for (phase, name) in [
+        (super::clients::ClientPhase::Handshake, "handshake"),
+        (super::clients::ClientPhase::Connecting, "connecting"),
+        (super::clients::ClientPhase::Bridged, "bridged"),
+    ] {
+        let count = clients.iter().filter(|c| c.phase == phase).count();
+        let _ = writeln!(out, "fluux_clients{{phase=\"{name}\"}} {count}");
+    }
+
+    family(
+        &mut out,
+        "connect_failures_total",
+        "counter",
+        "Failures by stage and class.",
+    );
+    let failures = FAILURES.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    for (key, count) in &failures {
+        let (stage, class) = key.split_once('/').unwrap_or((key, "other"));
+        let _ = writeln!(
+            out,
+            "fluux_connect_failures_total{{stage=\"{stage}\",class=\"{class}\"}} {count}"
+        );
+    }
+
+    family(
+        &mut out,
+        "bytes_total",
+        "counter",
+        "Stream bytes through the bridge.",
+    );
+    let _ = writeln!(
+        out,
+        "fluux_bytes_total{{direction=\"inbound\"}} {}",
+        load(&BYTES_IN)
+    );
+    let _ = writeln!(
+        out,
+        "fluux_bytes_total{{direction=\"outbound\"}} {}",
+        load(&BYTES_OUT)
+    );
+
+    family(
+        &mut out,
+        "stanzas_total",
+        "counter",
+        "Routed stanzas relayed or dropped.",
+    );
+    for (direction, outcome, counter) in [
+        ("inbound", "forwarded", &FORWARDED_IN),
+        ("outbound", "forwarded", &FORWARDED_OUT),
+        ("inbound", "dropped", &DROPPED_IN),
+        ("outbound", "dropped", &DROPPED_OUT),
+    ] {
+        let _ = writeln!(
+            out,
+            "fluux_stanzas_total{{direction=\"{direction}\",outcome=\"{outcome}\"}} {}",
+            load(counter)
+        );
+    }
+
+    let usage = super::gauges::usage();
+    family(
+        &mut out,
+        "buffer_bytes",
+        "gauge",
+        "Bytes held in bridge buffers.",
+    );
+    let _ = writeln!(
+        out,
+        "fluux_buffer_bytes{{buffer=\"stanza\"}} {}",
+        usage.stanza_buffer_bytes
+    );
+    let _ = writeln!(
+        out,
+        "fluux_buffer_bytes{{buffer=\"ws_queue\"}} {}",
+        usage.ws_queue_bytes
+    );
+
+    family(
+        &mut out,
+        "outbound_queue",
+        "gauge",
+        "Stanzas waiting to go upstream by priority.",
+    );
+    let depth = super::scheduler::depth();
+    for (priority, queued) in super::scheduler::Priority::ALL.iter().zip(depth) {
+        let _ = writeln!(
+            out,
+            "fluux_outbound_queue{{priority=\"{}\"}} {queued}",
+            priority.label()
+        );
+    }
+    out
+}
+
+/// `Some(port)` when `--metrics-port=PORT` was passed.
+pub fn port(args: &[String]) -> Option<u16> {
+    args.iter()
+        .find_map(|arg| arg.strip_prefix("--metrics-port="))
+        .and_then(|port| port.parse().ok())
+}
+
+async fn metrics() -> ([(axum::http::HeaderName, &'static str); 1], String) {
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        render(),
+    )
+}
+
+/// Serve `GET /metrics` on `127.0.0.1:port`.
+pub fn serve(port: u16) {
+    tauri::async_runtime::spawn(async move {
+        let address = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+        let listener = match tokio::net::TcpListener::bind(address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Metrics: cannot listen on {address}: {e}");
+                warn!(error = %e, "metrics: endpoint not started");
+                return;
+            }
+        };
+        let port = listener.local_addr().map_or(port, |a| a.port());
+        info!(port, "metrics: endpoint listening");
+        let router = axum::Router::new().route("/metrics", axum::routing::get(metrics));
+        let _ = axum::serve(listener, router).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_in_the_text_format() {
+        stanza(Direction::Outbound, false);
+        transferred(Direction::Inbound, 42);
+        connect_failed(Stage::TlsHandshake, "certificate-expired");
+        let text = render();
+        assert!(text.contains("# TYPE fluux_stanzas_total counter\n"));
+        let failure = "fluux_connect_failures_total{stage=\"tls-handshake\",\
+                       class=\"certificate-expired\"} ";
+        assert!(text.contains(failure));
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let (_, value) = line.rsplit_once(' ').unwrap();
+            assert!(value.parse::<u64>().is_ok(), "{line}");
+        }
+    }
+
+    #[test]
+    fn port_comes_from_the_flag() {
+        assert_eq!(port(&["--metrics-port=9464".to_string()]), Some(9464));
+        assert_eq!(port(&["--headless".to_string()]), None);
+    }
+}
@@ -0,0 +1,417 @@
+//! In-process fake XMPP server for offline UI work (`--mock-server`).
+//!
+//! With the flag set, the proxy never resolves or dials anything: each
+//! WebSocket connection is answered here, in RFC 7395 framing. Any
+//! credentials are accepted, messages come back from whoever they were sent
+//! to, and the roster, presences and message archive are canned. Everything
+//! is deterministic (fixed ids and timestamps), so end-to-end tests can
+//! assert on exact output.
+
+use super::stanza::{bare_jid, Element};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures_util::{SinkExt, StreamExt};
+use std::sync::OnceLock;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, info};
+
+const FRAMING_NS: &str = "urn:ietf:params:xml:ns:xmpp-framing";
+const STREAMS_NS: &str = "http://etherx.jabber.org/streams";
+const SASL_NS: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const BIND_NS: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+const ROSTER_NS: &str = "jabber:iq:roster";
+const MAM_NS: &str = "urn:xmpp:mam:2";
+const DISCO_INFO_NS: &str = "http://jabber.org/protocol/disco#info";
+const STANZAS_NS: &str = "urn:ietf:params:xml:ns:xmpp-stanzas";
+const DEFAULT_DOMAIN: &str = "mock.fluux.local";
+
+/// Canned contacts: localpart, name, presence `show` (`None` = available).
+const CONTACTS: [(&str, &str, Option<&str>); 3] = [
+    ("echo", "Echo Bot", None),
+    ("romeo", "Romeo", Some("away")),
+    ("nurse", "Nurse", Some("xa")),
+];
+
+/// Canned archive with `romeo`: (id, from romeo?, timestamp, body).
+const HISTORY: [(&str, bool, &str, &str); 3] = [
+    (
+        "mock-mam-1",
+        true,
+        "2026-01-01T10:00:00Z",
+        "Hello from the mock server!",
+    ),
+    ("mock-mam-2", false, "2026-01-01T10:01:00Z", "Hi Romeo."),
+    (
+        "mock-mam-3",
+        true,
+        "2026-01-01T10:02:00Z",
+        "Everything here is canned.",
+    ),
+];
+
+static MOCK_SERVER: OnceLock<bool> = OnceLock::new();
+
+/// Set the mock-server flag (called once from main.rs at startup).
+pub fn set_mock_server(enabled: bool) {
+    let _ = MOCK_SERVER.set(enabled);
+}
+
+pub(crate) fn enabled() -> bool {
+    MOCK_SERVER.get().copied().unwrap_or(false)
+}
+
+/// One client's view of the fake server.
+struct MockSession {
+    domain: String,
+    /// Localpart accepted at authentication.
+    user: Option<String>,
+    /// Full JID once bound.
+    jid: Option<String>,
+    next_id: u64,
+}
+
+impl MockSession {
+    fn new() -> Self {
+        Self {
+            domain: DEFAULT_DOMAIN.to_string(),
+            user: None,
+            jid: None,
+            next_id: 0,
+        }
+    }
+
+    fn id(&mut self) -> String {
+        self.next_id += 1;
+        format!("mock-{}", self.next_id)
+    }
+
+    fn bare(&self) -> String {
+        let user = self.user.as_deref().unwrap_or("tester");
+        format!("{user}@{}", self.domain)
+    }
+
+    fn contact(&self, localpart: &str) -> String {
+        format!("{localpart}@{}", self.domain)
+    }
+
+    /// Frames to send back for one client frame, and whether the stream ends.
+    fn handle(&mut self, frame: &str) -> (Vec<String>, bool) {
+        let Some(element) = Element::parse(frame.trim()) else {
+            return (Vec::new(), false);
+        };
+        match element.local_name() {
+            "open" => {
+                if let Some(to) = element.attr("to").filter(|to| !to.is_empty()) {
+                    self.domain = to.to_string();
+                }
+                (self.open_stream(), false)
+            }
+            "close" => (vec![format!("<close xmlns='{FRAMING_NS}'/>")], true),
+            "auth" => {
+                self.user =
+                    Some(plain_username(&element.text()).unwrap_or_else(|| "tester".to_string()));
+                (
+                    vec![Element::new("success").with_attr("xmlns", SASL_NS).to_xml()],
+                    false,
+                )
+            }
+            "iq" => (self.handle_iq(&element), false),
+            "presence" => (self.handle_presence(&element), false),
+            "message" => (self.handle_message(&element), false),
+            _ => (Vec::new(), false),
+        }
+    }
+
+    fn open_stream(&mut self) -> Vec<String> {
+        let id = self.id();
+        let open = Element::new("open")
+            .with_attr("xmlns", FRAMING_NS)
+            .with_attr("from", &self.domain)
+            .with_attr("id", &id)
+            .with_attr("version", "1.0")
+            .with_attr("xml:lang", "en");
+        let feature = if self.user.is_none() {
+            Element::new("mechanisms")
+                .with_attr("xmlns", SASL_NS)
+                .with_child(Element::new("mechanism").with_text("PLAIN"))
+        } else {
+            Element::new("bind").with_attr("xmlns", BIND_NS)
+        };
+        let features = Element::new("features")
+            .with_attr("xmlns", STREAMS_NS)
+            .with_child(feature);
+        vec![open.to_xml(), features.to_xml()]
+    }
+
+    fn handle_iq(&mut self, iq: &Element) -> Vec<String> {
+        let kind = iq.attr("type").unwrap_or_default();
+        if kind != "get" && kind != "set" {
+            return Vec::new();
+        }
+        let mut reply = Element::new("iq")
+            .with_attr("type", "result")
+            .with_attr("id", iq.attr("id").unwrap_or_default());
+        if let Some(to) = iq.attr("to") {
+            reply.set_attr("from", to);
+        }
+        // No `to`, or our own bare JID: a request to the account itself.
+        let own = self.bare();
+        let addressed_to_account = !matches!(iq.attr("to"), Some(to) if bare_jid(to) != own);
+
+        if let Some(bind) = iq.child("bind", Some(BIND_NS)) {
+            let resource = bind
+                .child("resource", None)
+                .map(Element::text)
+                .filter(|r| !r.is_empty())
+                .unwrap_or_else(|| "mock".to_string());
+            let jid = format!("{}/{resource}", self.bare());
+            self.jid = Some(jid.clone());
+            return vec![reply
+                .with_child(
+                    Element::new("bind")
+                        .with_attr("xmlns", BIND_NS)
+                        .with_child(Element::new("jid").with_text(&jid)),
+                )
+                .to_xml()];
+        }
+        if let Some(jid) = &self.jid {
+            reply.set_attr("to", jid);
+        }
+        if kind == "get" && addressed_to_account && iq.child("query", Some(ROSTER_NS)).is_some() {
+            let mut query = Element::new("query").with_attr("xmlns", ROSTER_NS);
+            for (localpart, name, _) in CONTACTS {
+                query = query.with_child(
+                    Element::new("item")
+                        .with_attr("jid", &self.contact(localpart))
+                        .with_attr("name", name)
+                        .with_attr("subscription", "both"),
+                );
+            }
+            return vec![reply.with_child(query).to_xml()];
+        }
+        if kind == "set" {
+            if let Some(query) = iq.child("query", Some(MAM_NS)) {
+                return self.archive(reply, query, addressed_to_account);
+            }
+        }
+        if kind == "get" && iq.child("query", Some(DISCO_INFO_NS)).is_some() {
+            let query = Element::new("query")
+                .with_attr("xmlns", DISCO_INFO_NS)
+                .with_child(
+                    Element::new("identity")
+                        .with_attr("category", "server")
+                        .with_attr("type", "im")
+                        .with_attr("name", "Fluux mock server"),
+                )
+                .with_child(Element::new("feature").with_attr("var", MAM_NS));
+            return vec![reply.with_child(query).to_xml()];
+        }
+        if kind == "set" {
+            // Accept any other request (carbons, blocking, …) as done.
+            return vec![reply.to_xml()];
+        }
+        reply.set_attr("type", "error");
+        vec![reply
+            .with_child(
+                Element::new("error")
+                    .with_attr("type", "cancel")
+                    .with_child(Element::new("service-unavailable").with_attr("xmlns", STANZAS_NS)),
+            )
+            .to_xml()]
+    }
+
+    /// The canned history with `romeo` for the account archive; rooms and
+    /// other archives are empty.
+    fn archive(&mut self, reply: Element, query: &Element, own_archive: bool) -> Vec<String> {
+        let queryid = query.attr("queryid").unwrap_or_default();
+        let own = self.bare();
+        let romeo = self.contact("romeo");
+        let mut frames = Vec::new();
+        if own_archive {
+            for (id, from_romeo, stamp, body) in HISTORY {
+                let (from, to) = if from_romeo {
+                    (&romeo, &own)
+                } else {
+                    (&own, &romeo)
+                };
+                let archived = Element::new("message")
+                    .with_attr("from", from)
+                    .with_attr("to", to)
+                    .with_attr("type", "chat")
+                    .with_attr("id", id)
+                    .with_child(Element::new("body").with_text(body));
+                let forwarded = Element::new("forwarded")
+                    .with_attr("xmlns", "urn:xmpp:forward:0")
+                    .with_child(
+                        Element::new("delay")
+                            .with_attr("xmlns", "urn:xmpp:delay")
+                            .with_attr("stamp", stamp),
+                    )
+                    .with_child(archived);
+                let mut result = Element::new("message")
+                    .with_attr("id", &self.id())
+                    .with_child(
+                        Element::new("result")
+                            .with_attr("xmlns", MAM_NS)
+                            .with_attr("queryid", queryid)
+                            .with_attr("id", id)
+                            .with_child(forwarded),
+                    );
+                if let Some(jid) = &self.jid {
+                    result.set_attr("to", jid);
+                }
+                frames.push(result.to_xml());
+            }
+        }
+        let mut set = Element::new("set").with_attr("xmlns", "http://jabber.org/protocol/rsm");
+        if own_archive {
+            set = set
+                .with_child(Element::new("first").with_text(HISTORY[0].0))
+                .with_child(Element::new("last").with_text(HISTORY[HISTORY.len() - 1].0));
+        }
+        let fin = Element::new("fin")
+            .with_attr("xmlns", MAM_NS)
+            .with_attr("complete", "true")
+            .with_child(set);
+        frames.push(reply.with_child(fin).to_xml());
+        frames
+    }
+
+    fn handle_presence(&mut self, presence: &Element) -> Vec<String> {
+        // Only the initial broadcast gets an answer: our own presence back,
+        // then the contacts'.
+        if presence.attr("to").is_some() || presence.attr("type").is_some() {
+            return Vec::new();
+        }
+        let Some(jid) = self.jid.clone() else {
+            return Vec::new();
+        };
+        let mut own = presence.clone();
+        own.set_attr("from", &jid);
+        own.set_attr("to", &jid);
+        let mut frames = vec![own.to_xml()];
+        for (localpart, _, show) in CONTACTS {
+            let mut contact = Element::new("presence")
+                .with_attr("from", &format!("{}/mock", self.contact(localpart)))
+                .with_attr("to", &jid);
+            if let Some(show) = show {
+                contact = contact.with_child(Element::new("show").with_text(show));
+            }
+            frames.push(contact.to_xml());
+        }
+        frames
+    }
+
+    fn handle_message(&mut self, message: &Element) -> Vec<String> {
+        let (Some(to), Some(body)) = (message.attr("to"), message.child("body", None)) else {
+            return Vec::new();
+        };
+        let mut echo = Element::new("message")
+            .with_attr("from", to)
+            .with_attr("type", message.attr("type").unwrap_or("chat"))
+            .with_attr("id", &self.id())
+            .with_child(Element::new("body").with_text(&body.text()));
+        if let Some(jid) = &self.jid {
+            echo.set_attr("to", jid);
+        }
+        vec![echo.to_xml()]
+    }
+}
+
+/// The authentication identity of a SASL PLAIN response.
+fn plain_username(response: &str) -> Option<String> {
+    let decoded = BASE64.decode(response.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let user = decoded.split('\0').nth(1)?;
+    let user = user.split('@').next().unwrap_or(user);
+    (!user.is_empty()).then(|| user.to_string())
+}
+
+/// Serve one WebSocket connection from the fake server, starting with the
+/// client's first frame.
+pub(crate) async fn serve(
+    mut ws: WebSocketStream<TcpStream>,
+    initial_frame: String,
+    conn_id: u64,
+) -> Result<(), String> {
+    info!(conn_id, "Serving connection from the mock server");
+    let mut session = MockSession::new();
+    let mut frame = Some(initial_frame);
+    loop {
+        let text = match frame.take() {
+            Some(text) => text,
+            None => match ws.next().await {
+                Some(Ok(Message::Text(text))) => text.to_string(),
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Err(e)) => return Err(format!("WebSocket read failed: {e}")),
+                Some(Ok(_)) => continue,
+            },
+        };
+        debug!(conn_id, data = %text, "WS->mock");
+        let (replies, closed) = session.handle(&text);
+        for reply in replies {
+            debug!(conn_id, data = %reply, "mock->WS");
+            ws.send(Message::Text(reply.into()))
+                .await
+                .map_err(|e| format!("WebSocket write failed: {e}"))?;
+        }
+        if closed {
+            let _ = ws.close(None).await;
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_in_echoes_and_serves_canned_data() {
+        let mut session = MockSession::new();
+        let (frames, _) = session.handle("<open xmlns='urn:ietf:params:xml:ns:xmpp-framing'/>");
+        assert!(frames[1].contains("PLAIN"));
+
+        // "\0juliet\0secret"
+        session.handle(
+            "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='PLAIN'>\
+             AGp1bGlldABzZWNyZXQ=</auth>",
+        );
+        let (frames, _) = session.handle("<open xmlns='urn:ietf:params:xml:ns:xmpp-framing'/>");
+        assert!(frames[1].contains(BIND_NS));
+        let (frames, _) = session.handle(
+            "<iq type='set' id='b1'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'>\
+             <resource>desk</resource></bind></iq>",
+        );
+        assert!(frames[0].contains("juliet@mock.fluux.local/desk"));
+
+        let (frames, _) =
+            session.handle("<iq type='get' id='r1'><query xmlns='jabber:iq:roster'/></iq>");
+        let roster = Element::parse(&frames[0]).unwrap();
+        assert_eq!(
+            roster
+                .child("query", Some(ROSTER_NS))
+                .unwrap()
+                .elements()
+                .count(),
+            3
+        );
+
+        let (frames, _) = session
+            .handle("<iq type='set' id='m1'><query xmlns='urn:xmpp:mam:2' queryid='q'/></iq>");
+        assert_eq!(frames.len(), HISTORY.len() + 1);
+        assert!(frames[HISTORY.len()].contains("complete='true'"));
+
+        let (frames, _) = session.handle(
+            "<message to='echo@mock.fluux.local' type='chat' id='c1'><body>ping</body></message>",
+        );
+        let echo = Element::parse(&frames[0]).unwrap();
+        assert_eq!(echo.attr("from"), Some("echo@mock.fluux.local"));
+        assert_eq!(echo.child("body", None).unwrap().text(), "ping");
+
+        let (_, closed) = session.handle("<close xmlns='urn:ietf:params:xml:ns:xmpp-framing'/>");
+        assert!(closed);
+    }
+}
@@ -1,15 +1,45 @@
-mod dns;
+pub mod bench;
+mod buffers;
+mod cert_check;
+pub mod clients;
+pub mod component;
+pub(crate) mod dns;
+pub mod doctor;
+mod downgrade;
 mod framing;
+pub mod gauges;
+pub mod metrics;
 mod happy_eyeballs;
+pub mod host_overrides;
+pub mod link_local;
+mod loopback;
+mod mdns;
+pub mod mock;
+pub mod muc_join;
+pub mod net_prefs;
+pub(crate) mod privacy;
+mod reachability;
+mod scheduler;
+pub mod session;
+pub mod stanza;
+pub mod supervisor;
+pub mod tap;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+pub mod trust;
+mod wake;
 
 use dns::{
     parse_server_input, resolve_xmpp_server, to_ascii_host, ConnectionMode, ParsedServer,
     XmppEndpoint,
 };
 use framing::{
-    extract_open_to, extract_stanza, extract_stream_error_condition, translate_tcp_to_ws,
-    translate_ws_to_tcp,
+    extract_open_to, extract_stanza, extract_stream_error_condition, next_stanza,
+    translate_tcp_to_ws, translate_ws_to_tcp,
 };
+use supervisor::{ConnectionSupervisor, Phase};
+
+use crate::error::FluuxError;
 
 use futures_util::{SinkExt, StreamExt};
 use serde::Serialize;
@@ -23,7 +53,7 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{Mutex, RwLock};
 use tokio::task::JoinHandle;
 use tokio_rustls::rustls::pki_types::ServerName;
-use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::rustls::ClientConfig;
 use tokio_rustls::TlsConnector;
 use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
 use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
@@ -54,6 +84,19 @@ fn is_insecure_tls() -> bool {
     DANGEROUS_INSECURE_TLS.get().copied().unwrap_or(false)
 }
 
+/// Global flag restricting upstream connections to direct TLS endpoints.
+/// Set once at startup from the managed policy (see managed_policy.rs).
+static REQUIRE_DIRECT_TLS: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+
+/// Set the direct-TLS-only flag (called once from main.rs at startup).
+pub fn set_require_direct_tls(enabled: bool) {
+    let _ = REQUIRE_DIRECT_TLS.set(enabled);
+}
+
+fn is_direct_tls_required() -> bool {
+    REQUIRE_DIRECT_TLS.get().copied().unwrap_or(false)
+}
+
 /// TCP connection timeout for outbound XMPP server connections.
 ///
 /// Applied to both STARTTLS (port 5222) and direct TLS (port 5223) TCP connect calls.
@@ -90,10 +133,6 @@ const INITIAL_CLIENT_STANZA_TIMEOUT: std::time::Duration = std::time::Duration::
 /// connection is definitively dead, not just quiet.
 const BRIDGE_INACTIVITY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
 
-/// How often the watchdog checks for inactivity.
-/// Checking every 30 seconds is lightweight and provides reasonable granularity.
-const WATCHDOG_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
-
 /// Maximum allowed size for the TLS-to-WebSocket stanza extraction buffer.
 ///
 /// If the buffer exceeds this limit after stanza extraction, the connection is
@@ -105,6 +144,26 @@ const WATCHDOG_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_s
 /// legitimate stanzas (vCard avatars, MAM result pages) rarely exceed 100 KB.
 const MAX_STANZA_BUFFER_SIZE: usize = 1_024 * 1_024;
 
+/// How long a small outbound stanza waits for company before it is written.
+///
+/// Bursts of tiny stanzas (SM acks, chat states while typing in busy MUCs)
+/// then share one TLS record and one syscall. Short enough to be invisible
+/// next to network latency.
+const WRITE_COALESCE_WINDOW: std::time::Duration = std::time::Duration::from_millis(2);
+
+/// Outbound stanzas at least this large are written at once, along with
+/// anything already batched.
+const WRITE_COALESCE_MAX: usize = 1_024;
+
+/// Bytes of translated stanzas allowed to wait for the WebSocket.
+///
+/// When the WebView stops reading (JS blocked, page frozen), sends to the
+/// WebSocket stall. Past this budget the bridge stops reading upstream, so
+/// a MAM flood waits in the server's socket instead of in our memory, and
+/// the frontend gets a `client-slow` event. The budget must exceed
+/// `MAX_STANZA_BUFFER_SIZE` so a single maximal stanza always fits.
+const WS_SEND_BUDGET: usize = 4 * 1_024 * 1_024;
+
 /// Monotonic connection id for correlating proxy logs across tasks and frontend events.
 static NEXT_PROXY_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
 
@@ -116,6 +175,21 @@ fn now_millis() -> u64 {
         .as_millis() as u64
 }
 
+/// Last traffic over any bridge, in milliseconds since UNIX epoch (0 before
+/// the first). The native keepalive tick is pushed back while it is recent.
+static LAST_TRAFFIC_MS: AtomicU64 = AtomicU64::new(0);
+
+pub fn last_traffic_ms() -> u64 {
+    LAST_TRAFFIC_MS.load(Ordering::Relaxed)
+}
+
+/// Record traffic on a bridge whose last activity is `activity`.
+fn mark_activity(activity: &AtomicU64) {
+    let now = now_millis();
+    activity.store(now, Ordering::Relaxed);
+    LAST_TRAFFIC_MS.store(now, Ordering::Relaxed);
+}
+
 /// Maximum payload for a WebSocket close-frame reason: control frames cap at
 /// 125 bytes and the status code consumes the first 2.
 const MAX_CLOSE_REASON_BYTES: usize = 123;
@@ -151,6 +225,56 @@ fn format_bridge_close_reason(end_reason_label: &str, stream_error: Option<&str>
     clamp_close_reason(reason)
 }
 
+/// Private-use close codes (RFC 6455 §7.4.2 reserves 4000–4999 for
+/// applications) for bridge ends that have no fitting registered code.
+const CLOSE_CODE_UPSTREAM_CLOSED: u16 = 4000;
+const CLOSE_CODE_WATCHDOG: u16 = 4001;
+const CLOSE_CODE_STREAM_ERROR: u16 = 4002;
+const CLOSE_CODE_CLIENT_ORPHANED: u16 = 4003;
+const CLOSE_CODE_UPSTREAM_CONNECT_FAILED: u16 = 4004;
+const CLOSE_CODE_UPSTREAM_TLS_FAILED: u16 = 4005;
+const CLOSE_CODE_SASL_DOWNGRADE: u16 = 4006;
+const CLOSE_CODE_UPSTREAM_LOST_IN_SLEEP: u16 = 4007;
+
+/// Pick the WebSocket close code for a bridge end, keyed like
+/// [`format_bridge_close_reason`]. Each failure class gets its own code so
+/// xmpp.js reconnect logic and telemetry can tell them apart without parsing
+/// the reason string: 1000 for a client-initiated close, 1001 for app
+/// shutdown, 1011 for read errors, and the private 4000-range codes above for
+/// the rest. A relayed stream error wins over the transport end reason, as it
+/// does in the reason text.
+fn bridge_close_code(end_reason_label: &str, stream_error: Option<&str>) -> CloseCode {
+    if stream_error.is_some() {
+        return CloseCode::Library(CLOSE_CODE_STREAM_ERROR);
+    }
+    if end_reason_label.starts_with("tls-error ") {
+        return CloseCode::Library(CLOSE_CODE_UPSTREAM_TLS_FAILED);
+    }
+    match end_reason_label {
+        "WebSocketClosedByClient" => CloseCode::Normal,
+        "Shutdown" => CloseCode::Away,
+        "TlsReadError" | "WebSocketReadError" => CloseCode::Error,
+        "TlsClosed" => CloseCode::Library(CLOSE_CODE_UPSTREAM_CLOSED),
+        "WatchdogTimeout" => CloseCode::Library(CLOSE_CODE_WATCHDOG),
+        "ClientOrphaned" => CloseCode::Library(CLOSE_CODE_CLIENT_ORPHANED),
+        "UpstreamConnectFailed" => CloseCode::Library(CLOSE_CODE_UPSTREAM_CONNECT_FAILED),
+        "SaslDowngrade" => CloseCode::Library(CLOSE_CODE_SASL_DOWNGRADE),
+        "UpstreamLostInSleep" => CloseCode::Library(CLOSE_CODE_UPSTREAM_LOST_IN_SLEEP),
+        _ => CloseCode::Normal,
+    }
+}
+
+/// The human-readable `<text>` of an upstream `<stream:error>`, if the server
+/// sent one (RFC 6120 §4.9.2). Empty text counts as none.
+fn stream_error_text(xml: &str) -> Option<String> {
+    let error = stanza::Element::parse(xml)?;
+    let text = error
+        .child("text", Some("urn:ietf:params:xml:ns:xmpp-streams"))?
+        .text();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
 /// Extract the stream-error condition that an upstream-connect failure encodes.
 ///
 /// `perform_starttls` formats a relayed upstream `<stream:error>` as
@@ -227,7 +351,7 @@ fn transport_error_class_from_error(message: &str) -> Option<String> {
     }
 }
 
-/// Send the RFC 7395 `<close/>` plus a WebSocket close frame carrying `reason`,
+/// Send the RFC 7395 `<close/>` plus a WebSocket close frame carrying `code` and `reason`,
 /// so the client (xmpp.js) gets a deterministic disconnect with the real cause
 /// instead of an abrupt socket drop. Best-effort, bounded by a short timeout.
 ///
@@ -235,6 +359,7 @@ fn transport_error_class_from_error(message: &str) -> Option<String> {
 /// equivalent close handshake on the split sink.
 async fn send_close_with_reason(
     ws: &mut tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
+    code: CloseCode,
     reason: String,
 ) {
     let _ = tokio::time::timeout(std::time::Duration::from_secs(2), async {
@@ -245,7 +370,7 @@ async fn send_close_with_reason(
             .await;
         let _ = ws
             .send(Message::Close(Some(CloseFrame {
-                code: CloseCode::Normal,
+                code,
                 reason: reason.into(),
             })))
             .await;
@@ -315,51 +440,66 @@ impl rustls::client::danger::ServerCertVerifier for InsecureCertVerifier {
     }
 }
 
-/// Create a TLS connector using the system's native root certificates.
+/// Build the rustls client configuration used for every outbound TLS
+/// connection: the system's native root certificates plus any the user
+/// added (cached, see [`trust::roots`]), or no verification at all when
+/// `--dangerous-insecure-tls` is set.
 ///
-/// Used by both `DirectTls` connections and `STARTTLS` upgrades to avoid
-/// duplicating the TLS setup logic.
-///
-/// When `--dangerous-insecure-tls` is set, certificate verification is skipped entirely.
-fn create_tls_connector() -> Result<TlsConnector, String> {
+/// Shared with native HTTPS clients (e.g. the ejabberd admin API) so they
+/// follow exactly the same trust policy as the XMPP connection.
+pub(crate) fn tls_client_config() -> Result<ClientConfig, String> {
     if is_insecure_tls() {
         warn!("TLS certificate verification DISABLED (--dangerous-insecure-tls)");
         let provider = rustls::crypto::ring::default_provider();
-        let config = ClientConfig::builder()
+        return Ok(ClientConfig::builder()
             .dangerous()
             .with_custom_certificate_verifier(Arc::new(InsecureCertVerifier(Arc::new(provider))))
-            .with_no_client_auth();
-        return Ok(TlsConnector::from(Arc::new(config)));
+            .with_no_client_auth());
     }
 
-    let mut root_store = RootCertStore::empty();
-    let native_certs = rustls_native_certs::load_native_certs();
-    if native_certs.certs.is_empty() {
-        return Err(
-            "No system root certificates found. TLS connections will fail. \
-            Ensure CA certificates are installed (e.g., ca-certificates package on Linux)."
-                .to_string(),
-        );
-    }
-    for cert in native_certs.certs {
-        root_store
-            .add(cert)
-            .map_err(|e| format!("Failed to add cert: {}", e))?;
-    }
-
-    let config = ClientConfig::builder()
+    let root_store = trust::roots()?;
+    Ok(ClientConfig::builder()
         .with_root_certificates(root_store)
-        .with_no_client_auth();
+        .with_no_client_auth())
+}
 
+/// ALPN protocol id for client-to-server XMPP over direct TLS (XEP-0368).
+/// Multiplexing front-ends (sslh, traefik) route port 443 on it.
+const XMPP_CLIENT_ALPN: &[u8] = b"xmpp-client";
+
+/// Create a TLS connector using the system's native root certificates,
+/// offering `alpn` when given.
+///
+/// Used by both `DirectTls` connections and `STARTTLS` upgrades to avoid
+/// duplicating the TLS setup logic. Only direct TLS offers ALPN: after
+/// STARTTLS the stream is already known to be XMPP.
+fn create_tls_connector(alpn: Option<&[u8]>) -> Result<TlsConnector, String> {
+    let mut config = tls_client_config()?;
+    if let Some(protocol) = alpn {
+        config.alpn_protocols = vec![protocol.to_vec()];
+    }
     Ok(TlsConnector::from(Arc::new(config)))
 }
 
+/// The TLS version and ALPN protocol negotiated on `tls_stream`.
+fn negotiated_tls(
+    tls_stream: &tokio_rustls::client::TlsStream<TcpStream>,
+) -> (Option<String>, Option<String>) {
+    let (_, connection) = tls_stream.get_ref();
+    let version = connection.protocol_version().map(|v| format!("{v:?}"));
+    let alpn = connection
+        .alpn_protocol()
+        .map(|p| String::from_utf8_lossy(p).into_owned());
+    (version, alpn)
+}
+
 /// Upgrade a TCP stream to TLS using the given host for SNI.
 async fn upgrade_to_tls(
     tcp_stream: TcpStream,
     host: &str,
+    alpn: Option<&[u8]>,
 ) -> Result<tokio_rustls::client::TlsStream<TcpStream>, String> {
-    let connector = create_tls_connector()?;
+    let connector = create_tls_connector(alpn)?;
     // Internationalized domains must be punycoded here: rustls accepts ASCII
     // names only, and certificates for IDNs carry the A-label in their SAN.
     // The XMPP domainpart stays a U-label on the wire (see `perform_starttls`).
@@ -376,7 +516,11 @@ async fn upgrade_to_tls(
             // Embed a stable `tls-error: <class>` marker so the connection
             // handler can recover the class for the WebSocket close reason.
             // The marker survives the `connect_first_endpoint` aggregation.
-            format!("TLS handshake failed with {} (tls-error: {}): {}", host, class, e)
+            // A name mismatch also lists the certificate's names.
+            let names = cert_check::presented_names(&e)
+                .map(|names| format!(" ({}{})", cert_check::NAMES_MARKER, names.join(" ")))
+                .unwrap_or_default();
+            format!("TLS handshake failed with {} (tls-error: {}){}: {}", host, class, names, e)
         })
 }
 
@@ -409,6 +553,17 @@ pub struct ProxyStartResult {
     pub url: String,
 }
 
+/// Proxy liveness and attached clients, returned by `get_proxy_status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyStatus {
+    /// The listener is bound and its accept loop is running.
+    pub alive: bool,
+    /// Local WebSocket URL, while a proxy exists.
+    pub url: Option<String>,
+    pub server: Option<String>,
+    pub connections: Vec<clients::ClientStatus>,
+}
+
 /// XMPP WebSocket-to-TCP proxy state.
 ///
 /// The proxy is always-on: it binds a local WebSocket listener once and keeps it
@@ -449,6 +604,12 @@ impl XmppProxy {
         self.app_handle = Some(handle);
     }
 
+    /// Whether the listener is bound and still accepting. A started proxy
+    /// whose accept loop has ended hands out a URL nothing listens on.
+    fn is_alive(&self) -> bool {
+        self.local_addr.is_some() && self.task.as_ref().is_some_and(|task| !task.is_finished())
+    }
+
     /// Start the proxy server.
     ///
     /// Binds a local WebSocket listener. DNS/SRV resolution is deferred to
@@ -458,44 +619,83 @@ impl XmppProxy {
     /// - `tls://host:port` or `tcp://host:port` — explicit endpoint, skip SRV
     /// - `host:port` — explicit endpoint, mode inferred from port (5223=TLS, else STARTTLS)
     /// - `domain` — SRV resolution with fallback to domain:5222 STARTTLS
-    pub async fn start(&mut self, server: String) -> Result<ProxyStartResult, String> {
+    pub async fn start(&mut self, server: String) -> Result<ProxyStartResult, FluuxError> {
+        self.start_on(server, None).await
+    }
+
+    /// Like [`start`](Self::start), binding `preferred_port` when it is free
+    /// so a restarted proxy keeps the URL the frontend already holds. Falls
+    /// back to a random port.
+    async fn start_on(
+        &mut self,
+        server: String,
+        preferred_port: Option<u16>,
+    ) -> Result<ProxyStartResult, FluuxError> {
         if self.local_addr.is_some() {
-            return Err("Proxy already running".to_string());
+            return Err(FluuxError::Internal("Proxy already running".to_string()));
         }
 
         info!(server = %server, "Starting proxy (DNS resolution deferred to per-connection)");
 
-        // Bind to loopback on a random port (IPv4 first; see LOOPBACK_BIND_ORDER).
+        // Bind to loopback (IPv4 first; see LOOPBACK_BIND_ORDER), on the
+        // preferred port if possible, else on a random one. A listener our
+        // own connection cannot reach is skipped too (see `loopback`).
         let mut bind_errors = Vec::new();
+        let mut issues = Vec::new();
         let mut bound = None;
-        for (bind_addr, host) in LOOPBACK_BIND_ORDER {
+        let preferred = preferred_port.into_iter().flat_map(|port| {
+            LOOPBACK_BIND_ORDER.map(|(bind_addr, host)| {
+                let ip = bind_addr.rsplit_once(':').map_or(bind_addr, |(ip, _)| ip);
+                (format!("{ip}:{port}"), host)
+            })
+        });
+        let random = LOOPBACK_BIND_ORDER.map(|(bind_addr, host)| (bind_addr.to_string(), host));
+        for (bind_addr, host) in preferred.chain(random) {
+            let bind_addr = bind_addr.as_str();
             match TcpListener::bind(bind_addr).await {
                 Ok(listener) => {
+                    if let Err(err) = loopback::self_check(&listener).await {
+                        warn!(bind_addr, error = %err, "Loopback listener unreachable");
+                        let port = listener.local_addr().map_or(0, |addr| addr.port());
+                        issues.push(loopback::LoopbackIssue::SelfConnectFailed {
+                            url: format!("ws://{host}:{port}"),
+                            error: err.clone(),
+                        });
+                        bind_errors.push(format!("{}: {}", bind_addr, err));
+                        continue;
+                    }
                     info!(bind_addr, host, "WebSocket server bound to loopback");
                     bound = Some((listener, host));
                     break;
                 }
                 Err(err) => {
                     debug!(bind_addr, error = %err, "Loopback bind attempt failed");
+                    issues.push(loopback::bind_issue(bind_addr, &err));
                     bind_errors.push(format!("{}: {}", bind_addr, err));
                 }
             }
         }
-        let (listener, loopback_host) = bound.ok_or_else(|| {
-            format!(
+        let Some((listener, loopback_host)) = bound else {
+            for issue in &issues {
+                loopback::report(self.app_handle.as_ref(), issue, None);
+            }
+            return Err(FluuxError::LocalServer(format!(
                 "Failed to bind WebSocket server on loopback ({})",
                 bind_errors.join(", ")
-            )
-        })?;
+            )));
+        };
 
         let local_addr = listener
             .local_addr()
-            .map_err(|e| format!("Failed to get local address: {}", e))?;
+            .map_err(|e| FluuxError::LocalServer(format!("Failed to get local address: {}", e)))?;
 
         self.local_addr = Some(local_addr);
         let ws_url = format!("ws://{}:{}", loopback_host, local_addr.port());
         self.ws_url = ws_url.clone();
         self.server_input = server.clone();
+        for issue in &issues {
+            loopback::report(self.app_handle.as_ref(), issue, Some(&ws_url));
+        }
 
         // Create shutdown channel
         let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
@@ -540,13 +740,16 @@ impl XmppProxy {
     }
 
     /// Stop the proxy server
-    pub async fn stop(&mut self) -> Result<(), String> {
+    pub async fn stop(&mut self) -> Result<(), FluuxError> {
         if let Some(shutdown_tx) = self.shutdown_tx.take() {
             let _ = shutdown_tx.send(());
         }
 
         if let Some(task) = self.task.take() {
             task.abort();
+            // Wait for the task to drop the listener so its port can be
+            // bound again right away.
+            let _ = task.await;
         }
 
         self.local_addr = None;
@@ -572,6 +775,8 @@ async fn handle_connection(
     // Track active connections for diagnostics (no connection limit)
     active_connections.fetch_add(1, Ordering::SeqCst);
     let _guard = ConnectionGuard::new(active_connections.clone());
+    let registration = clients::register(conn_id);
+    let supervisor = Arc::new(ConnectionSupervisor::new(conn_id, app_handle.clone()));
 
     // Upgrade to WebSocket, echoing any requested subprotocol (e.g. "xmpp" per RFC 7395).
     // Browsers reject the connection if the server does not echo the Sec-WebSocket-Protocol
@@ -609,18 +814,53 @@ async fn handle_connection(
         "Received initial client stanza"
     );
 
+    // --mock-server: answer in-process, never touching the network.
+    if mock::enabled() {
+        registration.set_phase(clients::ClientPhase::Bridged);
+        return mock::serve(ws, initial_ws_text, conn_id).await;
+    }
+    registration.set_phase(clients::ClientPhase::Connecting);
+
     // The client's initial <open to='…'/> carries the JID's service domain.
     // Use it as the STARTTLS `to=` / TLS SNI for explicit endpoints, where the
     // connection host may legitimately differ from the XMPP domain
     // (e.g. tcp://chat.process-one.net for JID me@process-one.net).
     let client_domain = extract_open_to(&initial_ws_text);
 
+    // link-local://: a LAN peer found over mDNS, no server involved.
+    if let Some(peer) = link_local::open(server_input, client_domain.as_deref()).await {
+        let stream = match peer {
+            Ok(stream) => stream,
+            Err(err) => {
+                let label = "UpstreamConnectFailed";
+                supervisor.fail(&err, None);
+                warn!(conn_id, error = %err, "Link-local peer connection failed");
+                let code = bridge_close_code(label, None);
+                send_close_with_reason(&mut ws, code, format_bridge_close_reason(label, None))
+                    .await;
+                return Ok(());
+            }
+        };
+        registration.set_phase(clients::ClientPhase::Bridged);
+        return bridge_websocket_tls(
+            ws,
+            stream,
+            shutdown,
+            app_handle,
+            vec![initial_ws_text],
+            conn_id,
+            supervisor,
+        )
+        .await;
+    }
+
     // Buffer client text frames received while upstream connect/STARTTLS is in progress.
     // They are flushed to TLS once the bridge starts.
     let mut pending_ws_texts = vec![initial_ws_text];
 
     let upstream_connect_started = Instant::now();
-    let connect_future = connect_upstream_tls(server_input, client_domain.as_deref());
+    let connect_future =
+        connect_upstream_tls(server_input, client_domain.as_deref(), &supervisor);
     tokio::pin!(connect_future);
 
     let tls_stream = loop {
@@ -653,7 +893,9 @@ async fn handle_connection(
                         } else {
                             "UpstreamConnectFailed".to_string()
                         };
+                        let code = bridge_close_code(&label, condition.as_deref());
                         let reason = format_bridge_close_reason(&label, condition.as_deref());
+                        supervisor.fail(&err, condition.clone());
                         warn!(
                             conn_id,
                             error = %err,
@@ -661,7 +903,7 @@ async fn handle_connection(
                             connect_ms = upstream_connect_started.elapsed().as_millis() as u64,
                             "Upstream connection failed; closing WebSocket with reason"
                         );
-                        send_close_with_reason(&mut ws, reason).await;
+                        send_close_with_reason(&mut ws, code, reason).await;
                         return Ok(());
                     }
                 }
@@ -710,6 +952,7 @@ async fn handle_connection(
         }
     };
 
+    registration.set_phase(clients::ClientPhase::Bridged);
     let bridge_result = bridge_websocket_tls(
         ws,
         tls_stream,
@@ -717,6 +960,8 @@ async fn handle_connection(
         app_handle,
         pending_ws_texts,
         conn_id,
+        // `connect_future` still borrows the original until it is dropped.
+        supervisor.clone(),
     )
     .await;
     info!(
@@ -774,29 +1019,72 @@ async fn wait_for_initial_client_stanza(
 /// reachable IPv4 address is attempted. See [`happy_eyeballs`].
 async fn try_connect_endpoint(
     endpoint: &XmppEndpoint,
+    network: &net_prefs::NetworkPrefs,
+    supervisor: &ConnectionSupervisor,
 ) -> Result<tokio_rustls::client::TlsStream<TcpStream>, String> {
+    supervisor.enter(Phase::Connecting {
+        host: endpoint.host.clone(),
+        port: endpoint.port,
+        direct_tls: endpoint.mode == ConnectionMode::DirectTls,
+    });
     let tcp_stream = happy_eyeballs::connect_tcp(
         &endpoint.host,
         endpoint.port,
+        network,
         happy_eyeballs::CONNECTION_ATTEMPT_DELAY,
         TCP_CONNECT_TIMEOUT,
     )
     .await?;
+    // Stanzas are written whole (and small ones batched, see
+    // WRITE_COALESCE_WINDOW); Nagle would only hold them back waiting for acks.
+    if let Err(e) = tcp_stream.set_nodelay(true) {
+        debug!(error = %e, "Failed to set TCP_NODELAY");
+    }
 
-    match endpoint.mode {
+    supervisor.enter(Phase::TlsHandshake {
+        host: endpoint.host.clone(),
+        tls_name: endpoint.tls_name().to_string(),
+        local_addr: tcp_stream.local_addr().ok().map(|a| a.to_string()),
+        peer_addr: tcp_stream.peer_addr().ok().map(|a| a.to_string()),
+    });
+    let tls_stream = match endpoint.mode {
         ConnectionMode::Tcp => {
             info!(host = %endpoint.host, port = endpoint.port, "Connected (TCP), performing STARTTLS");
-            let tls_stream =
-                perform_starttls(tcp_stream, endpoint.tls_name(), &endpoint.host).await?;
+            let tls_stream = perform_starttls(tcp_stream, endpoint.tls_name(), &endpoint.host)
+                .await
+                .inspect_err(|e| note_name_mismatch(supervisor, endpoint, e))?;
             info!(host = %endpoint.host, port = endpoint.port, "STARTTLS upgrade complete");
-            Ok(tls_stream)
+            tls_stream
         }
         ConnectionMode::DirectTls => {
-            let tls_stream = upgrade_to_tls(tcp_stream, endpoint.tls_name()).await?;
+            let tls_stream =
+                upgrade_to_tls(tcp_stream, endpoint.tls_name(), Some(XMPP_CLIENT_ALPN))
+                    .await
+                    .inspect_err(|e| note_name_mismatch(supervisor, endpoint, e))?;
             info!(host = %endpoint.host, port = endpoint.port,
                 tls_name = endpoint.tls_name(), "Connected (direct TLS)");
-            Ok(tls_stream)
+            tls_stream
         }
+    };
+    let (tls_version, alpn) = negotiated_tls(&tls_stream);
+    supervisor.enter(Phase::Secured { tls_version, alpn });
+    if let Some(leaf) = tls_stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+    {
+        let warnings = cert_check::inspect(leaf, now_millis() / 1000);
+        supervisor.certificate_warnings(endpoint.tls_name(), warnings);
+    }
+    Ok(tls_stream)
+}
+
+/// Keep, for the failure report, the names of a certificate `endpoint`
+/// presented that were not valid for its TLS name.
+fn note_name_mismatch(supervisor: &ConnectionSupervisor, endpoint: &XmppEndpoint, error: &str) {
+    if let Some(presented) = cert_check::names_from_error(error) {
+        supervisor.name_mismatch(cert_check::NameMismatch::new(endpoint, presented));
     }
 }
 
@@ -915,38 +1203,68 @@ where
 async fn connect_upstream_tls(
     server_input: &str,
     client_domain: Option<&str>,
+    supervisor: &ConnectionSupervisor,
 ) -> Result<tokio_rustls::client::TlsStream<TcpStream>, String> {
+    supervisor.enter(Phase::Resolving {
+        server: server_input.to_string(),
+    });
     // Resolve DNS/SRV per connection (fresh resolution handles DNS changes after sleep)
     let resolve_started = Instant::now();
-    let endpoints = match parse_server_input(server_input) {
+    let (mut endpoints, xmpp_domain) = match parse_server_input(server_input) {
         ParsedServer::Direct(host, port, mode, domain) => {
             // Domain precedence: explicit `?domain=` override → client `<open to=>`
             // (the JID's domain) → None (falls back to the connection host).
             let domain = domain.or_else(|| client_domain.map(|d| d.to_string()));
             info!(host = %host, port, mode = ?mode, domain = ?domain, "Using explicit endpoint");
-            vec![XmppEndpoint {
+            let endpoint = XmppEndpoint {
                 host,
                 port,
                 mode,
-                domain,
-            }]
+                domain: domain.clone(),
+            };
+            (vec![endpoint], domain)
         }
-        ParsedServer::Domain(domain) => resolve_xmpp_server(&domain)
-            .await
-            .map_err(|e| format!("Failed to resolve XMPP server: {}", e))?,
+        ParsedServer::Domain(domain) => match resolve_xmpp_server(&domain).await {
+            Ok(endpoints) => (endpoints, Some(domain)),
+            // Configured fallback hosts exist precisely for unreliable DNS.
+            Err(e) if !host_overrides::endpoints_for(&domain).is_empty() => {
+                warn!(domain = %domain, error = %e,
+                    "Resolution failed, using configured fallback endpoints");
+                (Vec::new(), Some(domain))
+            }
+            Err(e) => return Err(format!("Failed to resolve XMPP server: {}", e)),
+        },
     };
+    if let Some(domain) = &xmpp_domain {
+        host_overrides::merge(&mut endpoints, domain);
+    }
+
+    if is_direct_tls_required() {
+        endpoints.retain(|endpoint| endpoint.mode == ConnectionMode::DirectTls);
+        if endpoints.is_empty() {
+            return Err(format!(
+                "No direct TLS endpoint for {server_input}, and policy forbids STARTTLS"
+            ));
+        }
+    }
 
     let dns_resolve_ms = resolve_started.elapsed().as_millis() as u64;
     let endpoint_count = endpoints.len();
     info!(endpoint_count, dns_resolve_ms, "Resolved endpoints, attempting connections");
 
+    let network = net_prefs::current();
+    if network.probe_endpoints {
+        reachability::prefer_reachable(&mut endpoints, &network).await;
+    }
+
     // Try each endpoint in priority order, capped by an overall budget so a
     // multi-record domain that black-holes can't stall ~N × TCP_CONNECT_TIMEOUT.
+    let network = &network;
     connect_first_endpoint(
         &endpoints,
         OVERALL_CONNECT_TIMEOUT,
         TCP_CONNECT_TIMEOUT,
-        |endpoint| async move { try_connect_endpoint(&endpoint).await },
+        |endpoint| async move { try_connect_endpoint(&endpoint, network, supervisor).await },
     )
     .await
 }
@@ -1128,7 +1446,7 @@ async fn perform_starttls(
     );
 
     // Step 5: Upgrade TCP socket to TLS (use XMPP domain for SNI, not connection host)
-    let tls_stream = upgrade_to_tls(tcp_stream, domain)
+    let tls_stream = upgrade_to_tls(tcp_stream, domain, None)
         .await
         .map_err(|e| format!("STARTTLS: {}", e))?;
 
@@ -1136,20 +1454,81 @@ async fn perform_starttls(
     Ok(tls_stream)
 }
 
+/// Write everything still queued in `outbound`.
+async fn write_batch<W>(
+    writer: &mut W,
+    outbound: &mut scheduler::Scheduler,
+) -> std::io::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    if outbound.is_empty() {
+        return Ok(());
+    }
+    writer.write_all(outbound.take(usize::MAX).as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Write the most urgent `WRITE_COALESCE_MAX` bytes of `outbound`. What is
+/// left is due at once, but the caller reads new frames first, so a stanza
+/// more urgent than the backlog gets ahead of it.
+async fn write_next<W>(
+    writer: &mut W,
+    outbound: &mut scheduler::Scheduler,
+    flush_at: &mut Option<tokio::time::Instant>,
+) -> std::io::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let chunk = outbound.take(WRITE_COALESCE_MAX);
+    *flush_at = (!outbound.is_empty()).then(tokio::time::Instant::now);
+    if chunk.is_empty() {
+        return Ok(());
+    }
+    writer.write_all(chunk.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Queue `text` and write now if enough is queued, else leave it to the
+/// coalescing timer in `flush_at` (started by the first stanza of the batch).
+async fn queue_write<W>(
+    writer: &mut W,
+    outbound: &mut scheduler::Scheduler,
+    text: String,
+    flush_at: &mut Option<tokio::time::Instant>,
+) -> std::io::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    outbound.push(text);
+    if outbound.len() >= WRITE_COALESCE_MAX {
+        return write_next(writer, outbound, flush_at).await;
+    }
+    flush_at.get_or_insert_with(|| tokio::time::Instant::now() + WRITE_COALESCE_WINDOW);
+    Ok(())
+}
+
 /// Bridge WebSocket and TLS stream.
 ///
 /// Both connection modes (DirectTls and Tcp/STARTTLS) end up here after TLS is established.
 /// Bidirectionally bridges:
 /// - WebSocket → TLS: translates RFC 7395 framing (`<open/>`) to TCP framing (`<stream:stream>`)
 /// - TLS → WebSocket: translates TCP framing back to RFC 7395 and extracts stanza boundaries
-async fn bridge_websocket_tls(
+///
+/// The upstream is generic so the test harness can bridge to an in-memory
+/// scripted server (see `test_support`).
+async fn bridge_websocket_tls<U>(
     ws: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
-    tls_stream: tokio_rustls::client::TlsStream<TcpStream>,
+    tls_stream: U,
     mut shutdown: tokio::sync::broadcast::Receiver<()>,
     app_handle: Option<tauri::AppHandle>,
     pending_ws_texts: Vec<String>,
     conn_id: u64,
-) -> Result<(), String> {
+    supervisor: Arc<ConnectionSupervisor>,
+) -> Result<(), String>
+where
+    U: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + 'static,
+{
     #[derive(Debug, Clone, Serialize)]
     struct ProxyConnectionClosedEvent {
         conn_id: u64,
@@ -1157,6 +1536,34 @@ async fn bridge_websocket_tls(
         /// Upstream stream-error condition (e.g. "host-unknown"), when the server
         /// reported one before closing. `None` for plain transport-level closes.
         stream_error: Option<String>,
+        /// The XEP-0198 session can be resumed on the next connection.
+        resumable: bool,
+    }
+
+    /// Sent when the client stops draining the WebSocket and the bridge
+    /// pauses upstream reads (see `WS_SEND_BUDGET`).
+    #[derive(Debug, Clone, Serialize)]
+    struct ClientSlowEvent {
+        conn_id: u64,
+        queued_bytes: usize,
+    }
+
+    /// Sent as soon as the server's `<stream:error>` arrives, before the
+    /// bridge closes, so the UI can explain the disconnect (e.g. `conflict`:
+    /// signed in elsewhere) rather than show a generic one.
+    #[derive(Debug, Clone, Serialize)]
+    struct StreamErrorEvent {
+        conn_id: u64,
+        condition: String,
+        text: Option<String>,
+    }
+
+    /// Sent when the server's XEP-0474 hash shows the mechanism list was
+    /// tampered with; the connection is dropped before authentication ends.
+    #[derive(Debug, Clone, Serialize)]
+    struct SaslDowngradeEvent {
+        conn_id: u64,
+        error: String,
     }
 
     let bridge_started = Instant::now();
@@ -1173,6 +1580,9 @@ async fn bridge_websocket_tls(
         TlsClosed,
         TlsReadError,
         WatchdogTimeout,
+        ClientOrphaned,
+        SaslDowngrade,
+        UpstreamLostInSleep,
         Shutdown,
     }
 
@@ -1185,43 +1595,116 @@ async fn bridge_websocket_tls(
 
     // Shared activity timestamp for inactivity watchdog (epoch millis)
     let last_activity = Arc::new(AtomicU64::new(now_millis()));
+    // Last frame of any kind from the client, pongs included (epoch millis).
+    let last_client_frame = Arc::new(AtomicU64::new(now_millis()));
 
     // Most recent upstream stream-error condition (if any), captured by the
     // TLS→WS task and read at teardown so we can report *why* the server closed.
     let last_stream_error = Arc::new(std::sync::Mutex::new(None::<String>));
 
+    // Checks the SASL exchange for a stripped mechanism list (XEP-0474).
+    let downgrade_guard = Arc::new(downgrade::DowngradeGuard::new());
+
     // Flush any buffered client text stanzas collected before bridge startup.
     for text in pending_ws_texts {
+        supervisor.observe(tap::Direction::Outbound, &text);
+        let _ = downgrade_guard.observe(tap::Direction::Outbound, &text);
+        let Some(text) = tap::dispatch(
+            &tap::TapContext {
+                conn_id,
+                direction: tap::Direction::Outbound,
+                app: app_handle.as_ref(),
+            },
+            &text,
+        ) else {
+            continue;
+        };
         let translated = translate_ws_to_tcp(&text);
         debug!(data = %translated, "WS->TLS translated (buffered pre-bridge)");
         tls_write
             .write_all(translated.as_bytes())
             .await
             .map_err(|e| format!("Failed to write buffered client stanza to TLS: {}", e))?;
-        last_activity.store(now_millis(), Ordering::Relaxed);
+        mark_activity(&last_activity);
     }
 
+    // Native requests can be injected upstream from here on (once the client
+    // has bound a resource; see `session`).
+    let mut injected_rx = session::attach(conn_id);
+
     // Task 1: WebSocket -> TLS (translate RFC 7395 WebSocket framing to traditional XMPP)
     let activity_ws = last_activity.clone();
+    let client_frame_ws = last_client_frame.clone();
+    let supervisor_ws = supervisor.clone();
+    let downgrade_ws = downgrade_guard.clone();
+    let app_for_ws = app_handle.clone();
     let mut ws_to_tls = tokio::spawn(async move {
-        while let Some(msg) = ws_read.next().await {
+        // Outbound stanzas not yet written, and when they must be.
+        let mut outbound = scheduler::Scheduler::new();
+        let mut flush_at: Option<tokio::time::Instant> = None;
+        loop {
+            let msg = tokio::select! {
+                msg = ws_read.next() => match msg {
+                    Some(msg) => {
+                        client_frame_ws.store(now_millis(), Ordering::Relaxed);
+                        msg
+                    }
+                    None => break,
+                },
+                Some(native) = injected_rx.recv() => {
+                    // Stanza injected by a native feature; already TCP-framed.
+                    debug!(data = %native, "Native->TLS");
+                    if let Err(e) =
+                        queue_write(&mut tls_write, &mut outbound, native, &mut flush_at).await
+                    {
+                        error!(error = %e, "Native->TLS write error");
+                        return BridgeEndReason::TlsReadError;
+                    }
+                    mark_activity(&activity_ws);
+                    continue;
+                }
+                _ = tokio::time::sleep_until(flush_at.unwrap_or_else(tokio::time::Instant::now)),
+                    if flush_at.is_some() =>
+                {
+                    if let Err(e) = write_next(&mut tls_write, &mut outbound, &mut flush_at).await {
+                        error!(error = %e, "WS->TLS write error");
+                        return BridgeEndReason::TlsReadError;
+                    }
+                    continue;
+                }
+            };
             match msg {
                 Ok(Message::Text(text)) => {
                     debug!(data = %text, "WS->TLS");
+                    supervisor_ws.observe(tap::Direction::Outbound, &text);
+                    let _ = downgrade_ws.observe(tap::Direction::Outbound, &text);
+                    let Some(text) = tap::dispatch(
+                        &tap::TapContext {
+                            conn_id,
+                            direction: tap::Direction::Outbound,
+                            app: app_for_ws.as_ref(),
+                        },
+                        &text,
+                    ) else {
+                        continue;
+                    };
 
                     // Translate WebSocket framing (RFC 7395) to traditional XMPP
-                    let translated = translate_ws_to_tcp(&text);
+                    let translated = translate_ws_to_tcp(&text).into_owned();
 
                     debug!(data = %translated, "WS->TLS translated");
 
-                    if let Err(e) = tls_write.write_all(translated.as_bytes()).await {
+                    if let Err(e) =
+                        queue_write(&mut tls_write, &mut outbound, translated, &mut flush_at).await
+                    {
                         error!(error = %e, "WS->TLS write error");
                         return BridgeEndReason::TlsReadError;
                     }
-                    activity_ws.store(now_millis(), Ordering::Relaxed);
+                    mark_activity(&activity_ws);
                 }
                 Ok(Message::Close(_)) => {
                     info!("WebSocket closed by client");
+                    let _ = write_batch(&mut tls_write, &mut outbound).await;
                     return BridgeEndReason::WebSocketClosedByClient;
                 }
                 Err(e) => {
@@ -1231,85 +1714,204 @@ async fn bridge_websocket_tls(
                 _ => {}
             }
         }
+        let _ = write_batch(&mut tls_write, &mut outbound).await;
         BridgeEndReason::WebSocketClosedByClient
     });
 
-    // Task 2: TLS -> WebSocket (requires stanza boundary detection)
+    // Task 2: TLS -> WebSocket (requires stanza boundary detection).
+    // Stanzas are handed to a sender task through a queue bounded by
+    // WS_SEND_BUDGET bytes: when the client stops reading, the reader waits for
+    // room instead of buffering without limit.
+    let send_budget = Arc::new(tokio::sync::Semaphore::new(WS_SEND_BUDGET));
+    let (send_tx, mut send_rx) = tokio::sync::mpsc::unbounded_channel::<(
+        String,
+        tokio::sync::OwnedSemaphorePermit,
+        gauges::Gauge,
+    )>();
+    let ws_write_for_sender = ws_write.clone();
+    let mut ws_sender = tokio::spawn(async move {
+        while let Some((text, permit, queued)) = send_rx.recv().await {
+            let result = ws_write_for_sender
+                .lock()
+                .await
+                .send(Message::Text(text.into()))
+                .await;
+            drop(permit);
+            drop(queued);
+            if let Err(e) = result {
+                debug!(error = %e, "TLS->WS write error (WebSocket likely closed)");
+                return BridgeEndReason::WebSocketReadError;
+            }
+        }
+        // The reader hung up; its own end reason is the one to report.
+        std::future::pending().await
+    });
+
     let activity_tls = last_activity.clone();
     let stream_error_capture = last_stream_error.clone();
-    let ws_write_for_tls = ws_write.clone();
+    let app_for_tls = app_handle.clone();
+    let supervisor_tls = supervisor.clone();
+    let downgrade_tls = downgrade_guard.clone();
     let mut tls_to_ws = tokio::spawn(async move {
-        let mut buffer = Vec::new();
-        let mut read_buf = [0u8; 8192];
+        let reason = async {
+            let mut buffer = buffers::ReadBuffer::new();
+            let mut buffered = gauges::Gauge::new(&gauges::STANZA_BUFFER);
 
-        loop {
-            // Read from TLS
-            match tls_read.read(&mut read_buf).await {
-                Ok(0) => {
-                    info!("TLS connection closed");
-                    return BridgeEndReason::TlsClosed;
-                }
-                Ok(n) => {
-                    buffer.extend_from_slice(&read_buf[..n]);
-
-                    debug!(bytes = n, "Received from TLS");
-
-                    // Extract complete stanzas from buffer and translate to RFC 7395.
-                    // Track consumed offset to avoid O(n²) memmoves — compact once at the end.
-                    let mut consumed = 0;
-                    while let Some((stanza, bytes_used)) = extract_stanza(&buffer[consumed..]) {
-                        consumed += bytes_used;
-                        // Remember any stream-error condition so teardown can report
-                        // why the server closed (e.g. host-unknown, see-other-host).
-                        if let Some(cond) = extract_stream_error_condition(&stanza) {
-                            if let Ok(mut slot) = stream_error_capture.lock() {
-                                *slot = Some(cond);
+            loop {
+                // Read from TLS
+                match buffer.read_from(&mut tls_read).await {
+                    Ok(0) => {
+                        info!("TLS connection closed");
+                        return BridgeEndReason::TlsClosed;
+                    }
+                    Ok(n) => {
+                        debug!(bytes = n, "Received from TLS");
+
+                        // Extract complete stanzas from buffer and translate to RFC 7395.
+                        // Track consumed offset and drop the consumed bytes once at the end.
+                        let mut consumed = 0;
+                        while let Some((stanza, bytes_used)) =
+                            next_stanza(&buffer.bytes()[consumed..])
+                        {
+                            consumed += bytes_used;
+                            // Remember any stream-error condition so teardown can report
+                            // why the server closed (e.g. host-unknown, see-other-host).
+                            if let Some(cond) = extract_stream_error_condition(&stanza) {
+                                let text = stream_error_text(&stanza);
+                                warn!(
+                                    conn_id,
+                                    condition = %cond,
+                                    text = ?text,
+                                    "Upstream sent stream error"
+                                );
+                                if let Some(ref handle) = app_for_tls {
+                                    let _ = handle.emit(
+                                        "stream-error",
+                                        StreamErrorEvent {
+                                            conn_id,
+                                            condition: cond.clone(),
+                                            text,
+                                        },
+                                    );
+                                }
+                                if let Ok(mut slot) = stream_error_capture.lock() {
+                                    *slot = Some(cond);
+                                }
+                            }
+                            supervisor_tls.observe(tap::Direction::Inbound, &stanza);
+                            if let Err(e) = downgrade_tls.observe(tap::Direction::Inbound, &stanza)
+                            {
+                                // Don't relay the challenge: the client must not
+                                // finish authenticating over a tampered stream.
+                                error!(conn_id, error = %e, "Refusing connection");
+                                supervisor_tls.fail(&e, None);
+                                if let Some(ref handle) = app_for_tls {
+                                    let _ = handle.emit(
+                                        "sasl-downgrade",
+                                        SaslDowngradeEvent { conn_id, error: e },
+                                    );
+                                }
+                                return BridgeEndReason::SaslDowngrade;
+                            }
+                            let Some(stanza) = tap::dispatch(
+                                &tap::TapContext {
+                                    conn_id,
+                                    direction: tap::Direction::Inbound,
+                                    app: app_for_tls.as_ref(),
+                                },
+                                &stanza,
+                            ) else {
+                                continue;
+                            };
+                            let translated = translate_tcp_to_ws(&stanza).into_owned();
+                            debug!(data = %translated, "TLS->WS");
+                            let cost = translated.len().clamp(1, WS_SEND_BUDGET) as u32;
+                            let permit = match send_budget.clone().try_acquire_many_owned(cost) {
+                                Ok(permit) => permit,
+                                Err(_) => {
+                                    let queued_bytes =
+                                        WS_SEND_BUDGET - send_budget.available_permits();
+                                    warn!(
+                                        conn_id,
+                                        queued_bytes,
+                                        "Client is not reading; pausing upstream reads"
+                                    );
+                                    if let Some(ref handle) = app_for_tls {
+                                        let _ = handle.emit(
+                                            "client-slow",
+                                            ClientSlowEvent {
+                                                conn_id,
+                                                queued_bytes,
+                                            },
+                                        );
+                                    }
+                                    let stalled = Instant::now();
+                                    let Ok(permit) =
+                                        send_budget.clone().acquire_many_owned(cost).await
+                                    else {
+                                        return BridgeEndReason::WebSocketReadError;
+                                    };
+                                    info!(
+                                        conn_id,
+                                        stalled_ms = stalled.elapsed().as_millis() as u64,
+                                        "Client caught up; resuming upstream reads"
+                                    );
+                                    permit
+                                }
+                            };
+                            let mut queued = gauges::Gauge::new(&gauges::WS_QUEUE);
+                            queued.set(translated.len());
+                            if send_tx.send((translated, permit, queued)).is_err() {
+                                return BridgeEndReason::WebSocketReadError;
                             }
                         }
-                        let translated = translate_tcp_to_ws(&stanza);
-                        debug!(data = %translated, "TLS->WS");
-                        if let Err(e) = ws_write_for_tls
-                            .lock()
-                            .await
-                            .send(Message::Text(translated.into_owned().into()))
-                            .await
-                        {
-                            debug!(error = %e, "TLS->WS write error (WebSocket likely closed)");
-                            return BridgeEndReason::WebSocketReadError;
+                        buffer.consume(consumed);
+                        let pending = buffer.bytes().len();
+                        buffered.set(pending);
+
+                        // Guard against unbounded buffer growth from incomplete/malformed XML
+                        if pending > MAX_STANZA_BUFFER_SIZE {
+                            error!(
+                                buffer_bytes = pending,
+                                limit = MAX_STANZA_BUFFER_SIZE,
+                                "Stanza buffer exceeded size limit, closing connection"
+                            );
+                            return BridgeEndReason::TlsReadError;
                         }
-                    }
-                    if consumed > 0 {
-                        buffer.drain(..consumed);
-                    }
 
-                    // Guard against unbounded buffer growth from incomplete/malformed XML
-                    if buffer.len() > MAX_STANZA_BUFFER_SIZE {
-                        error!(
-                            buffer_bytes = buffer.len(),
-                            limit = MAX_STANZA_BUFFER_SIZE,
-                            "Stanza buffer exceeded size limit, closing connection"
-                        );
+                        mark_activity(&activity_tls);
+                    }
+                    Err(e) => {
+                        error!(error = %e, "TLS read error");
                         return BridgeEndReason::TlsReadError;
                     }
-
-                    activity_tls.store(now_millis(), Ordering::Relaxed);
-                }
-                Err(e) => {
-                    error!(error = %e, "TLS read error");
-                    return BridgeEndReason::TlsReadError;
                 }
             }
         }
+        .await;
+        // Let stanzas still queued (a final stream error, say) reach the
+        // client before the bridge tears down.
+        if matches!(
+            reason,
+            BridgeEndReason::TlsClosed | BridgeEndReason::TlsReadError
+        ) {
+            let drained = send_budget.acquire_many(WS_SEND_BUDGET as u32);
+            let _ = tokio::time::timeout(std::time::Duration::from_secs(2), drained).await;
+        }
+        reason
     });
 
-    // Watchdog: periodically check for inactivity to detect zombie connections
+    // Watchdog: close zombie connections after a period of inactivity. It
+    // sleeps until the inactivity deadline and re-arms from the latest
+    // activity, so a busy or idle bridge costs one wakeup per timeout rather
+    // than a poll; while the system sleeps it waits for the wake.
     let watchdog_activity = last_activity.clone();
     let watchdog = async move {
+        let timeout_ms = BRIDGE_INACTIVITY_TIMEOUT.as_millis() as u64;
         loop {
-            tokio::time::sleep(WATCHDOG_CHECK_INTERVAL).await;
             let last = watchdog_activity.load(Ordering::Relaxed);
             let elapsed_ms = now_millis().saturating_sub(last);
-            if elapsed_ms > BRIDGE_INACTIVITY_TIMEOUT.as_millis() as u64 {
+            if elapsed_ms > timeout_ms {
                 warn!(
                     elapsed_secs = elapsed_ms / 1000,
                     timeout_secs = BRIDGE_INACTIVITY_TIMEOUT.as_secs(),
@@ -1317,9 +1919,52 @@ async fn bridge_websocket_tls(
                 );
                 break;
             }
+            let remaining = timeout_ms - elapsed_ms + 1;
+            tokio::time::sleep(std::time::Duration::from_millis(remaining)).await;
+            crate::power::until_awake().await;
+        }
+    };
+
+    // Reports `degraded` while the server leaves acks unanswered.
+    let supervisor_health = supervisor.clone();
+    let health = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(supervisor::HEALTH_CHECK_INTERVAL).await;
+            supervisor_health.check_health();
+        }
+    });
+
+    // Orphan probe (see `clients`): on request, ping the client and give up
+    // on it if nothing comes back in time.
+    let probe = clients::probe_signal(conn_id);
+    let ws_write_for_probe = ws_write.clone();
+    let prober = async move {
+        loop {
+            probe.notified().await;
+            let sent_at = now_millis();
+            if let Err(e) = ws_write_for_probe
+                .lock()
+                .await
+                .send(Message::Ping(Default::default()))
+                .await
+            {
+                debug!(error = %e, "Client probe ping failed");
+                break;
+            }
+            tokio::time::sleep(clients::CLIENT_PROBE_TIMEOUT).await;
+            if last_client_frame.load(Ordering::Relaxed) < sent_at {
+                warn!(
+                    timeout_ms = clients::CLIENT_PROBE_TIMEOUT.as_millis() as u64,
+                    "Client did not answer the probe; treating its socket as orphaned"
+                );
+                break;
+            }
         }
     };
 
+    // Right after a wake, make sure the upstream survived the sleep (see `wake`).
+    let wake_check = wake::upstream_lost(conn_id);
+
     // Wait for any task to complete, watchdog to trigger, or shutdown signal
     let end_reason = tokio::select! {
         result = &mut ws_to_tls => {
@@ -1340,10 +1985,27 @@ async fn bridge_websocket_tls(
                 }
             }
         }
+        result = &mut ws_sender => {
+            match result {
+                Ok(reason) => reason,
+                Err(e) => {
+                    error!(error = %e, "WS sender task join error");
+                    BridgeEndReason::WebSocketReadError
+                }
+            }
+        }
         _ = watchdog => {
             info!("Connection closed by inactivity watchdog");
             BridgeEndReason::WatchdogTimeout
         }
+        _ = prober => {
+            info!("Connection closed: client socket orphaned");
+            BridgeEndReason::ClientOrphaned
+        }
+        _ = wake_check => {
+            info!("Connection closed: upstream lost during sleep");
+            BridgeEndReason::UpstreamLostInSleep
+        }
         _ = shutdown.recv() => {
             info!("Connection closed by shutdown");
             BridgeEndReason::Shutdown
@@ -1353,6 +2015,10 @@ async fn bridge_websocket_tls(
     // Abort both bridge tasks so they don't linger holding resources
     ws_to_tls.abort();
     tls_to_ws.abort();
+    ws_sender.abort();
+    health.abort();
+    let resumable = session::carries(conn_id) && session::is_resumable();
+    session::detach(conn_id);
 
     let end_reason_label = format!("{:?}", end_reason);
     // The TLS→WS task has stopped writing by now; read any captured upstream
@@ -1361,6 +2027,7 @@ async fn bridge_websocket_tls(
         .lock()
         .ok()
         .and_then(|slot| slot.clone());
+    let close_code = bridge_close_code(&end_reason_label, captured_stream_error.as_deref());
     let close_reason =
         format_bridge_close_reason(&end_reason_label, captured_stream_error.as_deref());
 
@@ -1380,7 +2047,7 @@ async fn bridge_websocket_tls(
 
         writer
             .send(Message::Close(Some(CloseFrame {
-                code: CloseCode::Normal,
+                code: close_code,
                 reason: close_reason.clone().into(),
             })))
             .await
@@ -1391,7 +2058,12 @@ async fn bridge_websocket_tls(
     .await;
     match close_handshake_result {
         Ok(Ok(())) => {
-            info!(conn_id, reason = %close_reason, "Sent RFC7395/WebSocket close handshake to client");
+            info!(
+                conn_id,
+                code = u16::from(close_code),
+                reason = %close_reason,
+                "Sent RFC7395/WebSocket close handshake to client"
+            );
         }
         Ok(Err(err)) => {
             warn!(conn_id, error = %err, "Failed to send clean close handshake to client");
@@ -1407,6 +2079,7 @@ async fn bridge_websocket_tls(
         end_reason,
         BridgeEndReason::Shutdown | BridgeEndReason::WebSocketClosedByClient
     ) {
+        crate::triggers::connection_lost(app_handle.as_ref(), &end_reason_label);
         if let Some(ref handle) = app_handle {
             let _ = handle.emit(
                 "proxy-connection-closed",
@@ -1414,11 +2087,16 @@ async fn bridge_websocket_tls(
                     conn_id,
                     reason: end_reason_label.clone(),
                     stream_error: captured_stream_error.clone(),
+                    resumable,
                 },
             );
         }
     }
 
+    supervisor.enter(Phase::Disconnected {
+        reason: close_reason.clone(),
+    });
+
     info!(
         conn_id,
         reason = %end_reason_label,
@@ -1436,14 +2114,16 @@ static PROXY: RwLock<Option<XmppProxy>> = RwLock::const_new(None);
 /// Start the XMPP proxy (exposed to Tauri commands).
 ///
 /// Idempotent: if a proxy is already running for the same server, returns
-/// the existing WebSocket URL without restarting. If the server changed
-/// (or on page reload), stops the old proxy and starts a new one.
+/// the existing WebSocket URL without restarting, after probing the attached
+/// clients so sockets left behind by a WebView reload are dropped. If the
+/// server changed or the old listener died, stops the old proxy and starts a
+/// new one on the same port, so a URL the frontend still holds stays valid.
 ///
 /// The `server` parameter supports: `tls://host:port`, `tcp://host:port`, `host:port`, or bare `domain`.
 pub async fn start_proxy(
     server: String,
     app_handle: Option<tauri::AppHandle>,
-) -> Result<ProxyStartResult, String> {
+) -> Result<ProxyStartResult, FluuxError> {
     // Initialize crypto provider before any TLS operations
     init_crypto_provider();
 
@@ -1451,19 +2131,23 @@ pub async fn start_proxy(
 
     // If proxy is already running for the same server, reuse it
     if let Some(ref existing) = *proxy_guard {
-        if existing.server_input == server && existing.local_addr.is_some() {
-            info!(server = %server, url = %existing.ws_url, "Proxy already running for this server, reusing");
+        if existing.server_input == server && existing.is_alive() {
+            // A new start request usually means the page (re)loaded: any
+            // client of the previous page that is still attached is orphaned.
+            let probed = clients::probe_all();
+            info!(server = %server, url = %existing.ws_url, probed, "Proxy already running for this server, reusing");
             return Ok(ProxyStartResult {
                 url: existing.ws_url.clone(),
             });
         }
     }
 
-    // Different server or stale proxy: stop the old one and start fresh.
-    // Also handles WebView reload where the Rust process stays alive but
-    // the old proxy's WebSocket client is gone.
+    // Different server or dead listener: stop the old one and start fresh,
+    // keeping its port when possible.
+    let mut preferred_port = None;
     if let Some(mut old_proxy) = proxy_guard.take() {
-        info!("Stopping existing proxy before starting new one");
+        info!(alive = old_proxy.is_alive(), "Stopping existing proxy before starting new one");
+        preferred_port = old_proxy.local_addr.map(|addr| addr.port());
         old_proxy.stop().await.ok();
     }
 
@@ -1471,14 +2155,61 @@ pub async fn start_proxy(
     if let Some(handle) = app_handle {
         proxy.set_app_handle(handle);
     }
-    let result = proxy.start(server).await?;
+    let result = proxy.start_on(server, preferred_port).await?;
+    if preferred_port.is_some_and(|port| proxy.local_addr.map(|a| a.port()) != Some(port)) {
+        warn!(url = %result.url, "Proxy restarted on a different port");
+    }
     *proxy_guard = Some(proxy);
 
     Ok(result)
 }
 
+/// The WebView could not open `url`, the proxy URL it was given (exposed to
+/// Tauri commands). Unless the proxy moved since, it is restarted on another
+/// random port, checked from here (see `loopback`): when that works, the
+/// WebView's own connections are being filtered, which is reported with a
+/// remediation hint. Returns the URL to use from now on.
+pub async fn report_unreachable(url: String) -> Result<ProxyStartResult, FluuxError> {
+    let mut proxy_guard = PROXY.write().await;
+    let Some(mut old_proxy) = proxy_guard.take() else {
+        return Err(FluuxError::LocalServer("Proxy not running".to_string()));
+    };
+    if old_proxy.ws_url != url && old_proxy.is_alive() {
+        let current = old_proxy.ws_url.clone();
+        *proxy_guard = Some(old_proxy);
+        return Ok(ProxyStartResult { url: current });
+    }
+    let server = old_proxy.server_input.clone();
+    let app_handle = old_proxy.app_handle.clone();
+    old_proxy.stop().await.ok();
+
+    let mut proxy = XmppProxy::new();
+    if let Some(handle) = app_handle.clone() {
+        proxy.set_app_handle(handle);
+    }
+    let result = proxy.start_on(server, None).await?;
+    loopback::report(
+        app_handle.as_ref(),
+        &loopback::LoopbackIssue::ClientBlocked { url },
+        Some(&result.url),
+    );
+    *proxy_guard = Some(proxy);
+    Ok(result)
+}
+
+/// Proxy liveness and its attached clients (exposed to Tauri commands).
+pub async fn proxy_status() -> ProxyStatus {
+    let proxy_guard = PROXY.read().await;
+    ProxyStatus {
+        alive: proxy_guard.as_ref().is_some_and(XmppProxy::is_alive),
+        url: proxy_guard.as_ref().map(|proxy| proxy.ws_url.clone()),
+        server: proxy_guard.as_ref().map(|proxy| proxy.server_input.clone()),
+        connections: clients::snapshot(),
+    }
+}
+
 /// Stop the XMPP proxy (exposed to Tauri commands)
-pub async fn stop_proxy() -> Result<(), String> {
+pub async fn stop_proxy() -> Result<(), FluuxError> {
     let mut proxy_guard = PROXY.write().await;
 
     if let Some(mut proxy) = proxy_guard.take() {
@@ -1521,6 +2252,55 @@ mod tests {
         proxy.stop().await.expect("proxy should stop cleanly");
     }
 
+    /// A restarted proxy binds the port it had, so the URL the frontend
+    /// holds stays valid, and reports its liveness.
+    #[tokio::test]
+    async fn test_restart_keeps_port() {
+        let mut proxy = XmppProxy::new();
+        let first = proxy
+            .start("tcp://example.org:5222".to_string())
+            .await
+            .expect("proxy should bind a loopback listener");
+        assert!(proxy.is_alive());
+        let port = proxy.local_addr.expect("bound address").port();
+        proxy.stop().await.expect("proxy should stop cleanly");
+        assert!(!proxy.is_alive());
+
+        let mut restarted = XmppProxy::new();
+        let second = restarted
+            .start_on("tcp://example.net:5222".to_string(), Some(port))
+            .await
+            .expect("proxy should restart");
+        assert_eq!(first.url, second.url);
+        restarted.stop().await.expect("proxy should stop cleanly");
+    }
+
+    // --- Outbound write batching tests ---
+
+    #[tokio::test]
+    async fn test_small_writes_are_batched_until_a_large_one() {
+        let mut upstream: Vec<u8> = Vec::new();
+        let mut outbound = scheduler::Scheduler::new();
+        let mut flush_at = None;
+
+        for ack in ["<a xmlns='urn:xmpp:sm:3' h='1'/>", "<a xmlns='urn:xmpp:sm:3' h='2'/>"] {
+            queue_write(&mut upstream, &mut outbound, ack.to_string(), &mut flush_at)
+                .await
+                .unwrap();
+        }
+        assert!(upstream.is_empty(), "small stanzas wait for the window");
+        assert!(flush_at.is_some());
+
+        let message = format!("<message><body>{}</body></message>", "x".repeat(2048));
+        queue_write(&mut upstream, &mut outbound, message, &mut flush_at)
+            .await
+            .unwrap();
+        let written = String::from_utf8(upstream).unwrap();
+        assert!(written.starts_with("<a xmlns='urn:xmpp:sm:3' h='1'/><a "));
+        assert!(written.ends_with("</message>"));
+        assert!(outbound.is_empty() && flush_at.is_none());
+    }
+
     // --- ConnectionGuard tests ---
 
     #[test]
@@ -1559,7 +2339,7 @@ mod tests {
     #[test]
     fn test_create_tls_connector() {
         init_crypto_provider();
-        let result = create_tls_connector();
+        let result = create_tls_connector(Some(XMPP_CLIENT_ALPN));
         assert!(
             result.is_ok(),
             "Should create TLS connector with system certs"
@@ -1603,6 +2383,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_stream_error_text() {
+        let conflict = "<stream:error><conflict xmlns='urn:ietf:params:xml:ns:xmpp-streams'/>\
+                        <text xmlns='urn:ietf:params:xml:ns:xmpp-streams'>Replaced by new \
+                        connection</text></stream:error>";
+        assert_eq!(
+            stream_error_text(conflict).as_deref(),
+            Some("Replaced by new connection")
+        );
+        let bare = "<stream:error><system-shutdown \
+                    xmlns='urn:ietf:params:xml:ns:xmpp-streams'/></stream:error>";
+        assert_eq!(stream_error_text(bare), None);
+    }
+
+    #[test]
+    fn test_bridge_close_codes_distinguish_end_reasons() {
+        let code = |label, cond| u16::from(bridge_close_code(label, cond));
+        assert_eq!(code("WebSocketClosedByClient", None), 1000);
+        assert_eq!(code("Shutdown", None), 1001);
+        assert_eq!(code("TlsReadError", None), 1011);
+        assert_eq!(code("TlsClosed", None), CLOSE_CODE_UPSTREAM_CLOSED);
+        assert_eq!(code("WatchdogTimeout", None), CLOSE_CODE_WATCHDOG);
+        assert_eq!(code("ClientOrphaned", None), CLOSE_CODE_CLIENT_ORPHANED);
+        assert_eq!(code("UpstreamConnectFailed", None), CLOSE_CODE_UPSTREAM_CONNECT_FAILED);
+        assert_eq!(code("tls-error certificate-expired", None), CLOSE_CODE_UPSTREAM_TLS_FAILED);
+        assert_eq!(code("SaslDowngrade", None), CLOSE_CODE_SASL_DOWNGRADE);
+        assert_eq!(code("UpstreamLostInSleep", None), CLOSE_CODE_UPSTREAM_LOST_IN_SLEEP);
+        // A relayed stream error is the more specific signal.
+        assert_eq!(code("TlsClosed", Some("conflict")), CLOSE_CODE_STREAM_ERROR);
+        assert_eq!(code("UpstreamConnectFailed", Some("host-unknown")), CLOSE_CODE_STREAM_ERROR);
+    }
+
     #[test]
     fn test_clamp_close_reason_within_limit() {
         let s = "Bridge closed: TlsClosed".to_string();
@@ -1852,7 +2664,7 @@ mod tests {
         });
 
         let stream = TcpStream::connect(addr).await.expect("connect to peer");
-        let err = upgrade_to_tls(stream, "ツ.com")
+        let err = upgrade_to_tls(stream, "ツ.com", None)
             .await
             .expect_err("plain-TCP peer cannot complete a TLS handshake");
 
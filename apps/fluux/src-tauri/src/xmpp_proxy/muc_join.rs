@@ -0,0 +1,421 @@
+//! Group chat joins (XEP-0045 §7.2) run by the bridge.
+//!
+//! [`join_room`] sends the join presence, with the history limit and
+//! password, and waits for the room's answer: our own occupant presence
+//! (status 110) or a presence error. A nickname already in use is retried
+//! with a numeric suffix (`alice2`, `alice3`…); any other error is mapped to
+//! a [`JoinFailure`]. The outcome is a single `room-joined` or
+//! `room-join-failed` event, and the raw error presences are not relayed, so
+//! the frontend never has to interpret them. A `passwordRequired` failure is
+//! the cue to ask for the password and join again with it.
+//!
+//! Everything the room sends once joined (occupants, subject, history)
+//! reaches the WebView as usual.
+
+use super::session;
+use crate::error::FluuxError;
+use super::stanza::{bare_jid, resource, Element};
+use super::tap::{self, Direction, StanzaObserver, TapContext, Verdict};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::Emitter;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+const MUC_NS: &str = "http://jabber.org/protocol/muc";
+const MUC_USER_NS: &str = "http://jabber.org/protocol/muc#user";
+/// How long a room has to answer one join attempt.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(20);
+/// Nicknames tried before giving up on conflicts: the requested one and
+/// four suffixed ones.
+const MAX_NICK_ATTEMPTS: u32 = 5;
+
+/// History the room sends on join (XEP-0045 §7.2.13). Unset fields leave it
+/// to the room's default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryLimit {
+    pub max_stanzas: Option<u32>,
+    pub seconds: Option<u32>,
+    /// XEP-0082 timestamp.
+    pub since: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomJoined {
+    pub room: String,
+    /// The nickname we have in the room: suffixed after a conflict, or the
+    /// one the room assigned (status 210).
+    pub nick: String,
+    pub requested_nick: String,
+    /// The room didn't exist and was created by this join (status 201); it
+    /// stays locked until configured.
+    pub created: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JoinFailure {
+    /// Every nickname tried was in use.
+    NickConflict,
+    /// The room requires a registered (reserved) nickname.
+    NickReserved,
+    PasswordRequired,
+    WrongPassword,
+    Banned,
+    MembersOnly,
+    RoomFull,
+    /// Also returned for a locked room still being configured.
+    RoomNotFound,
+    /// The service doesn't let us create the room.
+    CreationRestricted,
+    Timeout,
+    NotConnected,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomJoinFailed {
+    pub room: String,
+    /// The last nickname tried.
+    pub nick: String,
+    pub reason: JoinFailure,
+    /// Defined condition of the room's error, if it sent one.
+    pub condition: Option<String>,
+    /// The room's human-readable error text.
+    pub text: Option<String>,
+}
+
+/// The room's answer to one join attempt.
+enum Reply {
+    Joined(Element),
+    Error(Element),
+}
+
+struct Attempt {
+    nick: String,
+    tx: oneshot::Sender<Reply>,
+}
+
+/// Joins in flight, by lowercased room JID.
+static ATTEMPTS: Mutex<Option<HashMap<String, Attempt>>> = Mutex::new(None);
+
+fn attempts<T>(f: impl FnOnce(&mut HashMap<String, Attempt>) -> T) -> T {
+    let mut guard = ATTEMPTS.lock().unwrap_or_else(|e| e.into_inner());
+    f(guard.get_or_insert_with(HashMap::new))
+}
+
+fn status_codes(presence: &Element) -> Vec<String> {
+    presence
+        .child("x", Some(MUC_USER_NS))
+        .map(|x| {
+            x.elements()
+                .filter(|e| e.local_name() == "status")
+                .filter_map(|e| e.attr("code"))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `base`, then `base2`, `base3`…
+fn nick_candidate(base: &str, attempt: u32) -> String {
+    if attempt == 0 {
+        base.to_string()
+    } else {
+        format!("{base}{}", attempt + 1)
+    }
+}
+
+fn join_presence(
+    room: &str,
+    nick: &str,
+    password: Option<&str>,
+    history: &HistoryLimit,
+) -> Element {
+    let mut limit = Element::new("history");
+    let mut limited = false;
+    for (name, value) in [
+        ("maxstanzas", history.max_stanzas.map(|n| n.to_string())),
+        ("seconds", history.seconds.map(|n| n.to_string())),
+        ("since", history.since.clone()),
+    ] {
+        if let Some(value) = value {
+            limit.set_attr(name, &value);
+            limited = true;
+        }
+    }
+    let mut x = Element::new("x").with_attr("xmlns", MUC_NS);
+    if limited {
+        x = x.with_child(limit);
+    }
+    if let Some(password) = password {
+        x = x.with_child(Element::new("password").with_text(password));
+    }
+    Element::new("presence")
+        .with_attr("to", &format!("{room}/{nick}"))
+        .with_child(x)
+}
+
+/// The failure a presence error stands for.
+fn classify(condition: &str, with_password: bool) -> JoinFailure {
+    match condition {
+        "conflict" => JoinFailure::NickConflict,
+        "not-acceptable" => JoinFailure::NickReserved,
+        "not-authorized" if with_password => JoinFailure::WrongPassword,
+        "not-authorized" => JoinFailure::PasswordRequired,
+        "forbidden" => JoinFailure::Banned,
+        "registration-required" => JoinFailure::MembersOnly,
+        "service-unavailable" => JoinFailure::RoomFull,
+        "item-not-found" => JoinFailure::RoomNotFound,
+        "not-allowed" => JoinFailure::CreationRestricted,
+        _ => JoinFailure::Other,
+    }
+}
+
+/// Send one join presence and wait for the room's answer.
+async fn attempt(
+    app: Option<&tauri::AppHandle>,
+    room: &str,
+    nick: &str,
+    password: Option<&str>,
+    history: &HistoryLimit,
+) -> Result<Reply, JoinFailure> {
+    let key = room.to_lowercase();
+    let (tx, rx) = oneshot::channel();
+    let busy = attempts(|pending| {
+        if pending.contains_key(&key) {
+            return true;
+        }
+        let nick = nick.to_string();
+        pending.insert(key.clone(), Attempt { nick, tx });
+        false
+    });
+    if busy {
+        return Err(JoinFailure::Other);
+    }
+    let presence = join_presence(room, nick, password, history);
+    if session::send(presence.clone()).is_err() {
+        attempts(|pending| pending.remove(&key));
+        return Err(JoinFailure::NotConnected);
+    }
+    // Lets the other observers (unread counters) learn our nickname.
+    tap::observe_native(app, &presence);
+    match tokio::time::timeout(JOIN_TIMEOUT, rx).await {
+        Ok(Ok(reply)) => Ok(reply),
+        Ok(Err(_)) => Err(JoinFailure::NotConnected),
+        Err(_) => {
+            attempts(|pending| pending.remove(&key));
+            Err(JoinFailure::Timeout)
+        }
+    }
+}
+
+/// Join `room` as `nick`, retrying suffixed nicknames on conflict.
+pub async fn join(
+    app: Option<&tauri::AppHandle>,
+    room: &str,
+    nick: &str,
+    password: Option<&str>,
+    history: &HistoryLimit,
+) -> Result<RoomJoined, RoomJoinFailed> {
+    let room = bare_jid(room);
+    let failed = |nick: &str, reason, error: Option<&Element>| RoomJoinFailed {
+        room: room.to_string(),
+        nick: nick.to_string(),
+        reason,
+        condition: error.map(session::error_condition),
+        text: error
+            .and_then(|e| e.child("error", None))
+            .and_then(|e| e.child("text", None))
+            .map(Element::text),
+    };
+    let mut candidate = nick.to_string();
+    for n in 0..MAX_NICK_ATTEMPTS {
+        candidate = nick_candidate(nick, n);
+        match attempt(app, room, &candidate, password, history).await {
+            Ok(Reply::Joined(presence)) => {
+                let codes = status_codes(&presence);
+                return Ok(RoomJoined {
+                    room: room.to_string(),
+                    nick: presence
+                        .attr("from")
+                        .and_then(resource)
+                        .unwrap_or(&candidate)
+                        .to_string(),
+                    requested_nick: nick.to_string(),
+                    created: codes.iter().any(|c| c == "201"),
+                });
+            }
+            Ok(Reply::Error(error)) => {
+                let condition = session::error_condition(&error);
+                let reason = classify(&condition, password.is_some());
+                if reason != JoinFailure::NickConflict {
+                    return Err(failed(&candidate, reason, Some(&error)));
+                }
+            }
+            Err(reason) => return Err(failed(&candidate, reason, None)),
+        }
+    }
+    Err(failed(&candidate, JoinFailure::NickConflict, None))
+}
+
+/// Bridge observer routing the rooms' answers to the joins in flight.
+pub struct MucJoins;
+
+impl StanzaObserver for MucJoins {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict {
+        if ctx.direction != Direction::Inbound || stanza.local_name() != "presence" {
+            return Verdict::Forward;
+        }
+        let Some(from) = stanza.attr("from") else {
+            return Verdict::Forward;
+        };
+        let key = bare_jid(from).to_lowercase();
+        let is_error = stanza.attr("type") == Some("error");
+        let attempt = attempts(|pending| {
+            let attempt = pending.get(&key)?;
+            let answers = if is_error {
+                // Errors come from room/nick, or from the bare room.
+                resource(from).is_none_or(|nick| nick == attempt.nick)
+            } else {
+                stanza.attr("type").is_none()
+                    && (status_codes(stanza).iter().any(|c| c == "110")
+                        || resource(from) == Some(attempt.nick.as_str()))
+            };
+            answers.then(|| pending.remove(&key)).flatten()
+        });
+        let Some(attempt) = attempt else {
+            return Verdict::Forward;
+        };
+        if is_error {
+            let _ = attempt.tx.send(Reply::Error(stanza.clone()));
+            Verdict::Drop
+        } else {
+            let _ = attempt.tx.send(Reply::Joined(stanza.clone()));
+            Verdict::Forward
+        }
+    }
+}
+
+/// Join `room` and report the outcome as `room-joined` or
+/// `room-join-failed`, which is also the command's result.
+#[tauri::command]
+pub async fn join_room(
+    app: tauri::AppHandle,
+    room: String,
+    nick: String,
+    password: Option<String>,
+    history: Option<HistoryLimit>,
+) -> Result<RoomJoined, FluuxError> {
+    let nick = nick.trim();
+    if nick.is_empty() {
+        return Err(FluuxError::InvalidInput("A nickname is required".to_string()));
+    }
+    let history = history.unwrap_or_default();
+    match join(Some(&app), &room, nick, password.as_deref(), &history).await {
+        Ok(joined) => {
+            info!(room = %joined.room, nick = %joined.nick, "Joined room");
+            let _ = app.emit("room-joined", &joined);
+            Ok(joined)
+        }
+        Err(failed) => {
+            warn!(room = %failed.room, reason = ?failed.reason, "Room join failed");
+            let _ = app.emit("room-join-failed", &failed);
+            Err(FluuxError::Server(match &failed.text {
+                Some(text) => format!("Could not join {}: {text}", failed.room),
+                None => format!("Could not join {}: {:?}", failed.room, failed.reason),
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_presence_and_error_mapping() {
+        let history = HistoryLimit {
+            max_stanzas: Some(20),
+            ..Default::default()
+        };
+        let presence = join_presence("ops@conf.example.com", "alice2", Some("s3cret"), &history);
+        assert_eq!(presence.attr("to"), Some("ops@conf.example.com/alice2"));
+        let x = presence.child("x", Some(MUC_NS)).unwrap();
+        assert_eq!(
+            x.child("history", None).unwrap().attr("maxstanzas"),
+            Some("20")
+        );
+        assert_eq!(x.child("password", None).unwrap().text(), "s3cret");
+        let bare = join_presence(
+            "ops@conf.example.com",
+            "alice",
+            None,
+            &HistoryLimit::default(),
+        );
+        let x = bare.child("x", Some(MUC_NS)).unwrap();
+        assert!(x.child("history", None).is_none() && x.child("password", None).is_none());
+
+        assert_eq!(nick_candidate("alice", 0), "alice");
+        assert_eq!(nick_candidate("alice", 1), "alice2");
+        assert_eq!(
+            classify("not-authorized", false),
+            JoinFailure::PasswordRequired
+        );
+        assert_eq!(classify("not-authorized", true), JoinFailure::WrongPassword);
+        assert_eq!(
+            classify("registration-required", false),
+            JoinFailure::MembersOnly
+        );
+        assert_eq!(classify("undefined-condition", false), JoinFailure::Other);
+
+        let joined = Element::parse(
+            "<presence from='ops@conf.example.com/alice'>\
+             <x xmlns='http://jabber.org/protocol/muc#user'>\
+             <status code='110'/><status code='201'/></x></presence>",
+        )
+        .unwrap();
+        assert_eq!(status_codes(&joined), ["110", "201"]);
+    }
+
+    #[test]
+    fn observer_answers_only_the_pending_join() {
+        let observer = MucJoins;
+        let ctx = TapContext {
+            conn_id: 1,
+            direction: Direction::Inbound,
+            app: None,
+        };
+        let (tx, mut rx) = oneshot::channel();
+        attempts(|pending| {
+            pending.insert(
+                "lobby@conf.example.com".to_string(),
+                Attempt {
+                    nick: "alice".to_string(),
+                    tx,
+                },
+            )
+        });
+        let other = Element::parse("<presence from='lobby@conf.example.com/bob'/>").unwrap();
+        assert_eq!(observer.observe(&ctx, &other), Verdict::Forward);
+        assert!(rx.try_recv().is_err());
+
+        let conflict = Element::parse(
+            "<presence from='Lobby@conf.example.com/alice' type='error'>\
+             <error type='cancel'><conflict xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/>\
+             </error></presence>",
+        )
+        .unwrap();
+        assert_eq!(observer.observe(&ctx, &conflict), Verdict::Drop);
+        match rx.try_recv() {
+            Ok(Reply::Error(error)) => assert_eq!(session::error_condition(&error), "conflict"),
+            _ => panic!("expected the conflict to answer the join"),
+        }
+        assert_eq!(observer.observe(&ctx, &conflict), Verdict::Forward);
+    }
+}
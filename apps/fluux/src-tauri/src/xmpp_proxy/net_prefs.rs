@@ -0,0 +1,224 @@
+//! Address family preference and source binding for upstream connections.
+//!
+//! By default the upstream socket races every resolved address (see
+//! `happy_eyeballs`) from whatever source address the OS routes through,
+//! and tries SRV candidates in order. These settings change that, persisted
+//! in `network.json`:
+//!
+//! - the address family: prefer or restrict to IPv4 or IPv6, for networks
+//!   where one family is broken in ways Happy Eyeballs cannot detect (e.g. it
+//!   connects but stalls later);
+//! - a local source address, or on Linux an interface name, to bind the
+//!   socket to, so VPN split-tunnel users can send XMPP over (or around)
+//!   the tunnel;
+//! - probing the first SRV candidates at once and starting with the first
+//!   to answer (see `reachability`), for domains whose primary host is often
+//!   down.
+//!
+//! Changes apply from the next connection.
+
+use crate::error::FluuxError;
+use crate::json_file::JsonFile;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+use tokio::net::{TcpSocket, TcpStream};
+use tracing::info;
+
+static SETTINGS: RwLock<Option<NetworkPrefs>> = RwLock::new(None);
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AddressFamily {
+    /// Race both families, in the resolver's order.
+    #[default]
+    Auto,
+    PreferIpv4,
+    PreferIpv6,
+    Ipv4Only,
+    Ipv6Only,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NetworkPrefs {
+    pub address_family: AddressFamily,
+    /// Local address to connect from.
+    pub bind_address: Option<IpAddr>,
+    /// Network interface to connect through (Linux only).
+    pub bind_interface: Option<String>,
+    /// Race TCP probes across the first SRV candidates before connecting.
+    pub probe_endpoints: bool,
+}
+
+impl NetworkPrefs {
+    /// `addrs` filtered and ordered by the family preference. A preferred
+    /// family only goes first: `connect_tcp` still interleaves the result,
+    /// so a broken preferred family falls through to the other one.
+    pub(super) fn order(&self, addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+        let family = match self.bind_address {
+            // A bound socket can only reach its own family.
+            Some(IpAddr::V4(_)) => AddressFamily::Ipv4Only,
+            Some(IpAddr::V6(_)) => AddressFamily::Ipv6Only,
+            None => self.address_family,
+        };
+        let (v4, v6): (Vec<SocketAddr>, Vec<SocketAddr>) =
+            addrs.iter().partition(|addr| addr.is_ipv4());
+        match family {
+            AddressFamily::Auto => addrs.to_vec(),
+            AddressFamily::PreferIpv4 => [v4, v6].concat(),
+            AddressFamily::PreferIpv6 => [v6, v4].concat(),
+            AddressFamily::Ipv4Only => v4,
+            AddressFamily::Ipv6Only => v6,
+        }
+    }
+
+    /// Connect to `addr` from the configured source.
+    pub(super) async fn connect(&self, addr: SocketAddr) -> std::io::Result<TcpStream> {
+        if self.bind_address.is_none() && self.bind_interface.is_none() {
+            return TcpStream::connect(addr).await;
+        }
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        if let Some(ip) = self.bind_address {
+            socket.bind(SocketAddr::new(ip, 0))?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(interface) = &self.bind_interface {
+            socket.bind_device(Some(interface.as_bytes()))?;
+        }
+        socket.connect(addr).await
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match (self.bind_address, self.address_family) {
+            (Some(IpAddr::V4(_)), AddressFamily::Ipv6Only)
+            | (Some(IpAddr::V6(_)), AddressFamily::Ipv4Only) => {
+                return Err("The bind address is not in the allowed address family".to_string())
+            }
+            _ => {}
+        }
+        if let Some(interface) = &self.bind_interface {
+            if interface.trim().is_empty() {
+                return Err("Interface name is empty".to_string());
+            }
+            if !cfg!(target_os = "linux") {
+                return Err(
+                    "Binding to an interface is only supported on Linux; bind to its address \
+                     instead"
+                        .to_string(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Current settings.
+pub(crate) fn current() -> NetworkPrefs {
+    SETTINGS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Load the persisted settings from `dir`. Called from the Tauri `setup`
+/// hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("network.json")) else {
+        return;
+    };
+    let prefs: NetworkPrefs = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    if prefs != NetworkPrefs::default() {
+        info!(?prefs, "Custom upstream network settings");
+    }
+    *SETTINGS.write().unwrap_or_else(|e| e.into_inner()) = Some(prefs);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `SETTINGS` lock held; see [`crate::json_file`].
+fn persist(prefs: NetworkPrefs) {
+    if let Some(file) = FILE.get() {
+        file.save(prefs);
+    }
+}
+
+#[tauri::command]
+pub fn get_network_settings() -> NetworkPrefs {
+    current()
+}
+
+/// Replace the upstream network settings. They apply from the next
+/// connection.
+#[tauri::command]
+pub fn set_network_settings(settings: NetworkPrefs) -> Result<(), FluuxError> {
+    settings.validate().map_err(FluuxError::InvalidInput)?;
+    info!(?settings, "Upstream network settings changed");
+    let mut current = SETTINGS.write().unwrap_or_else(|e| e.into_inner());
+    *current = Some(settings.clone());
+    persist(settings);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs() -> Vec<SocketAddr> {
+        ["[2001:db8::1]:5222", "192.0.2.1:5222", "[2001:db8::2]:5222"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn orders_and_filters_by_family() {
+        let prefs = |address_family| NetworkPrefs {
+            address_family,
+            ..Default::default()
+        };
+        let all = addrs();
+        assert_eq!(prefs(AddressFamily::Auto).order(&all), all);
+        assert_eq!(
+            prefs(AddressFamily::PreferIpv4).order(&all),
+            vec![all[1], all[0], all[2]]
+        );
+        assert_eq!(
+            prefs(AddressFamily::Ipv6Only).order(&all),
+            vec![all[0], all[2]]
+        );
+        let bound = NetworkPrefs {
+            bind_address: Some("192.0.2.50".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(bound.order(&all), vec![all[1]]);
+        let conflicting = NetworkPrefs {
+            address_family: AddressFamily::Ipv6Only,
+            ..bound
+        };
+        assert!(conflicting.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn connects_from_the_bound_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let prefs = NetworkPrefs {
+            bind_address: Some("127.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        let stream = prefs.connect(listener.local_addr().unwrap()).await.unwrap();
+        assert_eq!(
+            stream.local_addr().unwrap().ip(),
+            "127.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+}
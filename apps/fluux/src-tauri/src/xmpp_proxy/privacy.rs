@@ -0,0 +1,292 @@
+//! Do-not-track mode: stop the client advertising what it is.
+//!
+//! By default every correspondent can learn the client name, version and OS
+//! (XEP-0092), fingerprint the exact build from its caps hash (XEP-0115) and
+//! disco identity, read the resource (which names the client), and see how
+//! long the user has been idle (XEP-0012, XEP-0319). With the mode on, the
+//! bridge rewrites the client's traffic on the way out:
+//!
+//! - the requested resource is replaced by a random one, and a SASL2 bind
+//!   `<tag/>` is dropped so the server picks a random resource itself;
+//!   the `<user-agent/>` keeps only its id, which FAST tokens are bound to;
+//! - version and last-activity answers become `service-unavailable`;
+//! - presence loses its caps and idle elements;
+//! - disco#info answers lose identity names and the software-info form.
+//!
+//! Peers that relied on caps fall back to querying disco#info, which gets
+//! the redacted answer. The resource is only rewritten at bind time, so
+//! turning the mode on takes full effect from the next connection.
+
+use super::stanza::{Element, Node};
+use super::tap::Direction;
+use crate::json_file::JsonFile;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tracing::info;
+
+const BIND_NS: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+const BIND2_NS: &str = "urn:xmpp:bind:0";
+const SASL2_NS: &str = "urn:xmpp:sasl:2";
+const VERSION_NS: &str = "jabber:iq:version";
+const LAST_NS: &str = "jabber:iq:last";
+const CAPS_NS: &str = "http://jabber.org/protocol/caps";
+const IDLE_NS: &str = "urn:xmpp:idle:1";
+const DISCO_INFO_NS: &str = "http://jabber.org/protocol/disco#info";
+const SOFTWARE_INFO_FORM: &str = "urn:xmpp:dataforms:softwareinfo";
+const STANZAS_NS: &str = "urn:ietf:params:xml:ns:xmpp-stanzas";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Held while the mode changes, so the last change is also the last saved.
+static TOGGLE: Mutex<()> = Mutex::new(());
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+
+#[derive(Default, Serialize, Deserialize)]
+struct Settings {
+    enabled: bool,
+}
+
+/// Load the persisted setting from `dir`. Called from the Tauri `setup` hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("privacy.json")) else {
+        return;
+    };
+    let settings: Settings = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    if settings.enabled {
+        info!("Do-not-track mode active");
+    }
+    ENABLED.store(settings.enabled, Ordering::Relaxed);
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with `TOGGLE` held; see [`crate::json_file`].
+fn persist(settings: Settings) {
+    if let Some(file) = FILE.get() {
+        file.save(settings);
+    }
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn random_resource() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..12].to_string()
+}
+
+/// Drop the child elements of `element` that match `remove`.
+fn strip(element: &mut Element, remove: impl Fn(&Element) -> bool) -> bool {
+    let before = element.children.len();
+    element
+        .children
+        .retain(|node| !matches!(node, Node::Element(e) if remove(e)));
+    element.children.len() != before
+}
+
+/// Mutable counterpart of [`Element::child`].
+fn child_mut<'e>(
+    element: &'e mut Element,
+    local_name: &str,
+    ns: Option<&str>,
+) -> Option<&'e mut Element> {
+    element.children.iter_mut().find_map(|node| match node {
+        Node::Element(e) if e.local_name() == local_name && (ns.is_none() || e.ns() == ns) => {
+            Some(e)
+        }
+        _ => None,
+    })
+}
+
+fn is_software_info_form(form: &Element) -> bool {
+    form.local_name() == "x"
+        && form
+            .elements()
+            .filter(|field| field.attr("var") == Some("FORM_TYPE"))
+            .filter_map(|field| field.child("value", None))
+            .any(|value| value.text() == SOFTWARE_INFO_FORM)
+}
+
+/// Rewrite an outbound SASL2 `<authenticate/>`. Other nonzas carry nothing
+/// identifying.
+pub(crate) fn rewrite_nonza(direction: Direction, raw: &str) -> Option<String> {
+    if direction != Direction::Outbound
+        || !enabled()
+        || !raw.trim_start().starts_with("<authenticate")
+    {
+        return None;
+    }
+    let mut authenticate = Element::parse(raw)?;
+    if authenticate.ns() != Some(SASL2_NS) {
+        return None;
+    }
+    let mut changed = false;
+    if let Some(bind) = child_mut(&mut authenticate, "bind", Some(BIND2_NS)) {
+        changed |= strip(bind, |e| e.local_name() == "tag");
+    }
+    if let Some(user_agent) = child_mut(&mut authenticate, "user-agent", None) {
+        changed |= strip(user_agent, |e| {
+            e.local_name() == "software" || e.local_name() == "device"
+        });
+    }
+    changed.then(|| authenticate.to_xml())
+}
+
+/// Rewrite an outbound stanza. Returns `None` when it is relayed unchanged.
+pub(crate) fn rewrite_stanza(direction: Direction, stanza: &Element) -> Option<Element> {
+    if direction != Direction::Outbound || !enabled() {
+        return None;
+    }
+    match stanza.local_name() {
+        "iq" => rewrite_iq(stanza),
+        "presence" => {
+            let mut presence = stanza.clone();
+            let changed = strip(&mut presence, |e| {
+                (e.local_name() == "c" && e.ns() == Some(CAPS_NS))
+                    || (e.local_name() == "idle" && e.ns() == Some(IDLE_NS))
+            });
+            changed.then_some(presence)
+        }
+        _ => None,
+    }
+}
+
+fn rewrite_iq(iq: &Element) -> Option<Element> {
+    match iq.attr("type") {
+        Some("set") => {
+            // Without a requested resource the server already picks a random one.
+            iq.child("bind", Some(BIND_NS))?.child("resource", None)?;
+            let mut iq = iq.clone();
+            let bind = child_mut(&mut iq, "bind", Some(BIND_NS))?;
+            let resource = child_mut(bind, "resource", None)?;
+            resource.children = vec![Node::Text(random_resource())];
+            Some(iq)
+        }
+        Some("result") => {
+            if iq.child("query", Some(VERSION_NS)).is_some()
+                || iq.child("query", Some(LAST_NS)).is_some()
+            {
+                return Some(service_unavailable(iq));
+            }
+            iq.child("query", Some(DISCO_INFO_NS))?;
+            let mut iq = iq.clone();
+            let query = child_mut(&mut iq, "query", Some(DISCO_INFO_NS))?;
+            let mut changed = strip(query, is_software_info_form);
+            for node in query.children.iter_mut() {
+                if let Node::Element(identity) = node {
+                    if identity.local_name() == "identity" && identity.attr("name").is_some() {
+                        identity.attrs.retain(|(key, _)| key != "name");
+                        changed = true;
+                    }
+                }
+            }
+            changed.then_some(iq)
+        }
+        _ => None,
+    }
+}
+
+/// The error the client would have sent had it not implemented the query.
+fn service_unavailable(result: &Element) -> Element {
+    let mut error = Element::new("iq").with_attr("type", "error");
+    for attr in ["to", "id"] {
+        if let Some(value) = result.attr(attr) {
+            error.set_attr(attr, value);
+        }
+    }
+    error.with_child(
+        Element::new("error")
+            .with_attr("type", "cancel")
+            .with_child(Element::new("service-unavailable").with_attr("xmlns", STANZAS_NS)),
+    )
+}
+
+#[tauri::command]
+pub fn get_privacy_mode() -> bool {
+    enabled()
+}
+
+/// Turn do-not-track mode on or off. The resource changes from the next
+/// connection; everything else applies immediately.
+#[tauri::command]
+pub fn set_privacy_mode(enabled: bool) {
+    let _toggle = TOGGLE.lock().unwrap_or_else(|e| e.into_inner());
+    ENABLED.store(enabled, Ordering::Relaxed);
+    persist(Settings { enabled });
+    info!(enabled, "Do-not-track mode toggled");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(xml: &str) -> Option<Element> {
+        rewrite_stanza(Direction::Outbound, &Element::parse(xml).unwrap())
+    }
+
+    // A single test: the mode is process-wide.
+    #[test]
+    fn redacts_identifying_traffic_only_when_enabled() {
+        let bind = "<iq type='set' id='b1'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'>\
+                    <resource>fluux-macos</resource></bind></iq>";
+        let presence = "<presence><show>away</show>\
+                        <c xmlns='http://jabber.org/protocol/caps' hash='sha-1' \
+                        node='https://fluux.io' ver='abc='/>\
+                        <idle xmlns='urn:xmpp:idle:1' since='2026-10-15T10:00:00Z'/></presence>";
+        let authenticate = "<authenticate xmlns='urn:xmpp:sasl:2' mechanism='PLAIN'>\
+                            <user-agent id='d4565fa7'><software>Fluux</software>\
+                            <device>Juliet's MacBook</device></user-agent>\
+                            <bind xmlns='urn:xmpp:bind:0'><tag>Fluux</tag></bind></authenticate>";
+
+        ENABLED.store(false, Ordering::Relaxed);
+        assert!(rewrite(bind).is_none());
+        assert!(rewrite(presence).is_none());
+        assert!(rewrite_nonza(Direction::Outbound, authenticate).is_none());
+
+        ENABLED.store(true, Ordering::Relaxed);
+        let bound = rewrite(bind).unwrap();
+        let resource = bound
+            .child("bind", Some(BIND_NS))
+            .unwrap()
+            .child("resource", None);
+        assert_ne!(resource.unwrap().text(), "fluux-macos");
+
+        let presence = rewrite(presence).unwrap();
+        assert!(presence.child("c", Some(CAPS_NS)).is_none());
+        assert!(presence.child("idle", Some(IDLE_NS)).is_none());
+        assert_eq!(presence.child("show", None).unwrap().text(), "away");
+
+        let version = rewrite(
+            "<iq type='result' to='romeo@example.net/a' id='v1'>\
+             <query xmlns='jabber:iq:version'><name>Fluux</name><os>macOS</os></query></iq>",
+        )
+        .unwrap();
+        assert_eq!(version.attr("type"), Some("error"));
+        assert_eq!(version.attr("id"), Some("v1"));
+        assert!(version.child("query", None).is_none());
+
+        let disco = rewrite(
+            "<iq type='result' to='romeo@example.net/a' id='d1'>\
+             <query xmlns='http://jabber.org/protocol/disco#info'>\
+             <identity category='client' type='pc' name='Fluux'/>\
+             <feature var='urn:xmpp:receipts'/>\
+             <x xmlns='jabber:x:data' type='result'><field var='FORM_TYPE' type='hidden'>\
+             <value>urn:xmpp:dataforms:softwareinfo</value></field>\
+             <field var='os'><value>macOS</value></field></x></query></iq>",
+        )
+        .unwrap();
+        let query = disco.child("query", Some(DISCO_INFO_NS)).unwrap();
+        assert_eq!(query.child("identity", None).unwrap().attr("name"), None);
+        assert!(query.child("feature", None).is_some());
+        assert!(query.child("x", None).is_none());
+
+        let authenticate = rewrite_nonza(Direction::Outbound, authenticate).unwrap();
+        assert!(!authenticate.contains("<tag>"));
+        assert!(!authenticate.contains("MacBook"));
+        assert!(authenticate.contains("id='d4565fa7'"));
+        ENABLED.store(false, Ordering::Relaxed);
+    }
+}
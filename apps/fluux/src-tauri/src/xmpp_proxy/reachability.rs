@@ -0,0 +1,140 @@
+//! Reachability probes across SRV candidates, before the first connect.
+//!
+//! `connect_first_endpoint` tries endpoints strictly in SRV order, each
+//! bounded by the per-attempt timeout. When the primary host is down but its
+//! name still resolves (its A records point at a machine that drops SYNs),
+//! every connection first waits that timeout out before the backup host is
+//! even tried. With `probeEndpoints` set (see `net_prefs`), the first
+//! [`PROBED_ENDPOINTS`] candidates get a short TCP connect raced at once,
+//! and the first to accept goes first; the probe connections are closed
+//! right away. When none answers within [`PROBE_TIMEOUT`] the SRV order is
+//! kept, and the regular attempts, with their longer timeouts, decide.
+
+use super::dns::XmppEndpoint;
+use super::happy_eyeballs::{connect_tcp, CONNECTION_ATTEMPT_DELAY};
+use super::net_prefs::NetworkPrefs;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Candidates probed, from the top of the SRV order.
+const PROBED_ENDPOINTS: usize = 3;
+/// How long the probes may take altogether.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Index of the first of `candidates` whose `probe` succeeds within
+/// `timeout`, all probed at once.
+async fn first_responder<T, F, Fut>(candidates: &[T], timeout: Duration, probe: F) -> Option<usize>
+where
+    T: Clone,
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let mut probes: FuturesUnordered<_> = candidates
+        .iter()
+        .enumerate()
+        .map(|(index, candidate)| {
+            let probe = probe(candidate.clone());
+            async move { (index, probe.await) }
+        })
+        .collect();
+    let first = async {
+        while let Some((index, result)) = probes.next().await {
+            match result {
+                Ok(()) => return Some(index),
+                Err(e) => debug!(index, error = %e, "Endpoint probe failed"),
+            }
+        }
+        None
+    };
+    tokio::time::timeout(timeout, first).await.ok().flatten()
+}
+
+/// Move the first of the top endpoints to accept a TCP connection to the
+/// front, keeping the others in order.
+pub(super) async fn prefer_reachable(endpoints: &mut [XmppEndpoint], prefs: &NetworkPrefs) {
+    if endpoints.len() < 2 {
+        return;
+    }
+    let started = Instant::now();
+    let top = &endpoints[..endpoints.len().min(PROBED_ENDPOINTS)];
+    let winner = first_responder(top, PROBE_TIMEOUT, |endpoint: XmppEndpoint| async move {
+        connect_tcp(
+            &endpoint.host,
+            endpoint.port,
+            prefs,
+            CONNECTION_ATTEMPT_DELAY,
+            PROBE_TIMEOUT,
+        )
+        .await
+        .map(drop)
+    })
+    .await;
+    let probe_ms = started.elapsed().as_millis() as u64;
+    match winner {
+        Some(0) => debug!(probe_ms, "Endpoint probe: first candidate answered first"),
+        Some(index) => {
+            info!(
+                host = %endpoints[index].host,
+                port = endpoints[index].port,
+                probe_ms,
+                "Endpoint probe: starting with the first candidate to answer"
+            );
+            endpoints[..=index].rotate_right(1);
+        }
+        None => info!(
+            probe_ms,
+            "Endpoint probe: no candidate answered, keeping SRV order"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xmpp_proxy::dns::ConnectionMode;
+
+    #[tokio::test(start_paused = true)]
+    async fn picks_the_first_candidate_to_answer() {
+        let probe = |delay_ms: u64| async move {
+            match delay_ms {
+                0 => Err("refused".to_string()),
+                delay_ms => {
+                    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                    Ok(())
+                }
+            }
+        };
+        // A black hole first, then a refusal, then a reachable host.
+        let candidates = [3_600_000, 0, 40, 20];
+        assert_eq!(
+            first_responder(&candidates, PROBE_TIMEOUT, probe).await,
+            Some(3)
+        );
+        assert_eq!(
+            first_responder(&[3_600_000, 0], PROBE_TIMEOUT, probe).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn moves_the_reachable_endpoint_first() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = {
+            let unused = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            unused.local_addr().unwrap().port()
+        };
+        let endpoint = |port| XmppEndpoint {
+            host: "127.0.0.1".to_string(),
+            port,
+            mode: ConnectionMode::DirectTls,
+            domain: None,
+        };
+        let mut endpoints = [endpoint(closed), endpoint(closed), endpoint(open)];
+        prefer_reachable(&mut endpoints, &NetworkPrefs::default()).await;
+        let ports: Vec<u16> = endpoints.iter().map(|e| e.port).collect();
+        assert_eq!(ports, [open, closed, closed]);
+    }
+}
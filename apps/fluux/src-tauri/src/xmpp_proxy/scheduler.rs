@@ -0,0 +1,283 @@
+//! Outbound stanza scheduling: what the bridge writes upstream next.
+//!
+//! Outbound stanzas wait here for the coalescing window, and when the
+//! upstream is slow to take them, in one queue per [`Priority`]. Each write
+//! takes the most urgent stanzas first, so a message the user just sent
+//! overtakes a backlog of file chunks, archive queries or read markers
+//! instead of queuing behind it. Order within a priority is kept.
+//!
+//! Stream-level elements other than stream-management acks (`<open/>`,
+//! SASL, `<enable/>`, `<close/>`…) are barriers: everything queued before
+//! one is written before it, and nothing queued after it overtakes it.
+//! Presence stays in the same queue as messages and requests: leaving a
+//! room cannot overtake the last message to it, and the initial broadcast
+//! presence cannot overtake the roster get sent before it (RFC 6121 §2.2).
+//! Going offline (unavailable presence) is a barrier.
+//!
+//! Once the client asks for stream management (XEP-0198 `<enable/>` or
+//! `<resume/>`), stanzas are no longer reordered: the server counts them in
+//! the order it receives them and the client keeps its unacknowledged ones
+//! in the order it sent them, so an `<a h='…'/>` would otherwise acknowledge
+//! the wrong stanzas and a resumption resend or drop the wrong ones. Only
+//! the `<r/>` and `<a/>` nonzas, which are not counted, still go first.
+
+use super::stanza::Element;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const CHATSTATES_NS: &str = "http://jabber.org/protocol/chatstates";
+const RECEIPTS_NS: &str = "urn:xmpp:receipts";
+const MARKERS_NS: &str = "urn:xmpp:chat-markers:0";
+const IBB_NS: &str = "http://jabber.org/protocol/ibb";
+const MAM_NS: &str = "urn:xmpp:mam:2";
+const SM_NS: &str = "urn:xmpp:sm:3";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Acks and IQ answers.
+    High,
+    /// Messages, requests and presence.
+    Normal,
+    /// Standalone chat states, receipts and markers.
+    Low,
+    /// File chunks and archive queries.
+    Background,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 4] = [
+        Priority::High,
+        Priority::Normal,
+        Priority::Low,
+        Priority::Background,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+            Priority::Background => "background",
+        }
+    }
+}
+
+/// Stanzas queued upstream over all connections, by priority.
+static DEPTH: [AtomicUsize; 4] = [const { AtomicUsize::new(0) }; 4];
+
+/// Queued stanzas per priority, in [`Priority::ALL`] order.
+pub fn depth() -> [usize; 4] {
+    Priority::ALL.map(|p| DEPTH[p as usize].load(Ordering::Relaxed))
+}
+
+/// How a TCP-framed outbound element is scheduled; `None` for a barrier.
+fn classify(raw: &str) -> Option<Priority> {
+    let trimmed = raw.trim_start();
+    let routed = ["<message", "<presence", "<iq"]
+        .iter()
+        .any(|name| trimmed.starts_with(name));
+    if !routed {
+        return is_ack(trimmed).then_some(Priority::High);
+    }
+    let Some(stanza) = Element::parse(raw) else {
+        return Some(Priority::Normal);
+    };
+    let payload_ns = |ns: &str| stanza.elements().any(|e| e.ns() == Some(ns));
+    Some(match stanza.local_name() {
+        "presence" if stanza.attr("type") == Some("unavailable") => return None,
+        "iq" if matches!(stanza.attr("type"), Some("result") | Some("error")) => Priority::High,
+        "iq" | "message" if payload_ns(IBB_NS) => Priority::Background,
+        "iq" if payload_ns(MAM_NS) => Priority::Background,
+        "message" if stanza.child("body", None).is_none() => {
+            if payload_ns(CHATSTATES_NS) || payload_ns(RECEIPTS_NS) || payload_ns(MARKERS_NS) {
+                Priority::Low
+            } else {
+                Priority::Normal
+            }
+        }
+        _ => Priority::Normal,
+    })
+}
+
+/// A stream-management `<a/>` or `<r/>`, which the server does not count.
+fn is_ack(trimmed: &str) -> bool {
+    (trimmed.starts_with("<a ") || trimmed.starts_with("<r ")) && trimmed.contains(SM_NS)
+}
+
+/// A request to enable or resume stream management, after which every
+/// stanza is counted.
+fn starts_counting(trimmed: &str) -> bool {
+    (trimmed.starts_with("<enable ") || trimmed.starts_with("<resume ")) && trimmed.contains(SM_NS)
+}
+
+struct Entry {
+    seq: u64,
+    text: String,
+}
+
+/// One connection's outbound queues.
+#[derive(Default)]
+pub(super) struct Scheduler {
+    queues: [VecDeque<Entry>; 4],
+    barriers: VecDeque<Entry>,
+    next_seq: u64,
+    bytes: usize,
+    /// Stream management was requested: stanzas keep their order.
+    counted: bool,
+}
+
+impl Scheduler {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue TCP-framed outbound text (one stanza or stream element).
+    pub(super) fn push(&mut self, text: String) {
+        self.bytes += text.len();
+        let entry = Entry {
+            seq: self.next_seq,
+            text,
+        };
+        self.next_seq += 1;
+        let trimmed = entry.text.trim_start();
+        let priority = match classify(trimmed) {
+            Some(Priority::High) if is_ack(trimmed) => Some(Priority::High),
+            // One queue, so counted stanzas are written in the order sent.
+            Some(_) if self.counted => Some(Priority::Normal),
+            priority => priority,
+        };
+        self.counted |= starts_counting(trimmed);
+        match priority {
+            Some(priority) => {
+                DEPTH[priority as usize].fetch_add(1, Ordering::Relaxed);
+                self.queues[priority as usize].push_back(entry);
+            }
+            None => self.barriers.push_back(entry),
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.bytes == 0
+    }
+
+    /// Bytes waiting to be written.
+    pub(super) fn len(&self) -> usize {
+        self.bytes
+    }
+
+    fn pop(&mut self) -> Option<Entry> {
+        let fence = self.barriers.front().map_or(u64::MAX, |b| b.seq);
+        let ready = Priority::ALL.into_iter().find(|&p| {
+            self.queues[p as usize]
+                .front()
+                .is_some_and(|entry| entry.seq < fence)
+        });
+        let entry = match ready {
+            Some(priority) => {
+                DEPTH[priority as usize].fetch_sub(1, Ordering::Relaxed);
+                self.queues[priority as usize].pop_front()
+            }
+            None => self.barriers.pop_front(),
+        }?;
+        self.bytes -= entry.text.len();
+        Some(entry)
+    }
+
+    /// The next write: the most urgent stanzas, until at least `max` bytes
+    /// (and at least one stanza) are taken.
+    pub(super) fn take(&mut self, max: usize) -> String {
+        let mut out = String::new();
+        while out.len() < max {
+            match self.pop() {
+                Some(entry) => out.push_str(&entry.text),
+                None => break,
+            }
+        }
+        out
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        for priority in Priority::ALL {
+            let queued = self.queues[priority as usize].len();
+            DEPTH[priority as usize].fetch_sub(queued, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_by_urgency() {
+        let cases = [
+            ("<a xmlns='urn:xmpp:sm:3' h='4'/>", Some(Priority::High)),
+            ("<presence><show>away</show></presence>", Some(Priority::Normal)),
+            ("<iq type='result' id='p1' to='example.org'/>", Some(Priority::High)),
+            ("<presence to='room@muc/me' type='unavailable'/>", None),
+            ("<message to='a@b' type='chat'><body>hi</body></message>", Some(Priority::Normal)),
+            (
+                "<message to='a@b'><composing xmlns='http://jabber.org/protocol/chatstates'/></message>",
+                Some(Priority::Low),
+            ),
+            (
+                "<iq type='set' id='1'><data xmlns='http://jabber.org/protocol/ibb' seq='0'/></iq>",
+                Some(Priority::Background),
+            ),
+            ("<close xmlns='urn:ietf:params:xml:ns:xmpp-framing'/>", None),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(classify(raw), expected, "{raw}");
+        }
+    }
+
+    #[test]
+    fn urgent_stanzas_overtake_but_not_across_barriers() {
+        let mut scheduler = Scheduler::new();
+        let chunk = "<iq type='set' id='c'><data xmlns='http://jabber.org/protocol/ibb'/></iq>";
+        scheduler.push(chunk.to_string());
+        scheduler.push("<message to='a@b'><body>now</body></message>".to_string());
+        scheduler.push("</stream:stream>".to_string());
+        scheduler.push("<presence/>".to_string());
+        let first = scheduler.take(1);
+        assert!(first.contains("now"));
+        assert_eq!(scheduler.take(1), chunk);
+        assert_eq!(scheduler.take(1), "</stream:stream>");
+        assert_eq!(scheduler.take(usize::MAX), "<presence/>");
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn initial_presence_follows_the_roster_get() {
+        let mut scheduler = Scheduler::new();
+        let roster = "<iq type='get' id='r1'><query xmlns='jabber:iq:roster'/></iq>";
+        scheduler.push(roster.to_string());
+        scheduler.push("<presence/>".to_string());
+        assert_eq!(scheduler.take(usize::MAX), format!("{roster}<presence/>"));
+    }
+
+    #[test]
+    fn keeps_send_order_once_stream_management_is_on() {
+        let mut scheduler = Scheduler::new();
+        scheduler.push("<enable xmlns='urn:xmpp:sm:3' resume='true'/>".to_string());
+        let sent = [
+            "<message to='a@b'><body>one</body></message>",
+            "<iq type='result' id='v1' to='example.org'/>",
+            "<message to='a@b'><received xmlns='urn:xmpp:receipts' id='m0'/></message>",
+            "<presence><show>away</show></presence>",
+            "<iq type='set' id='c'><data xmlns='http://jabber.org/protocol/ibb'/></iq>",
+            "<message to='a@b'><body>two</body></message>",
+            "<presence type='unavailable'/>",
+        ];
+        for stanza in sent {
+            scheduler.push(stanza.to_string());
+        }
+        scheduler.push("<r xmlns='urn:xmpp:sm:3'/>".to_string());
+        // The uncounted ack request goes first, after the barrier before it.
+        assert!(scheduler.take(1).starts_with("<enable"));
+        assert_eq!(scheduler.take(1), "<r xmlns='urn:xmpp:sm:3'/>");
+        assert_eq!(scheduler.take(usize::MAX), sent.concat());
+    }
+}
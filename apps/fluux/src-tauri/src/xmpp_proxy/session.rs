@@ -0,0 +1,414 @@
+//! Native participation in the XMPP session relayed by the bridge.
+//!
+//! The WebView's client owns the stream, but some native features need to
+//! talk to the server themselves (fetch the blocklist, query disco, …). They
+//! do so through [`request`] / [`send`], which inject stanzas into the live
+//! bridge's upstream writer and swallow the matching responses so the client
+//! never sees traffic it did not originate.
+//!
+//! Injecting or swallowing stanzas skews XEP-0198 counters: the server counts
+//! stanzas the client never sent, and the client never counts stanzas it never
+//! received. Both deltas are tracked here and the `h` values of `<a/>`,
+//! `<resume/>` and `<resumed/>` are rewritten in flight so each side keeps a
+//! consistent view of its own peer.
+
+use super::stanza::{self, Element};
+use super::tap::Direction;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::{debug, warn};
+
+/// How long a native IQ waits for its response before giving up.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+const SM_NS: &str = "urn:xmpp:sm:3";
+const BIND_NS: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+
+#[derive(Default)]
+struct State {
+    /// Bridge currently carrying the session and its injection channel.
+    attached: Option<(u64, mpsc::UnboundedSender<String>)>,
+    /// Full JID from the last resource bind (kept across resumptions).
+    jid: Option<String>,
+    /// Bound (or resumed) on the attached bridge; injection is refused before.
+    ready: bool,
+    /// Stanzas the server counted from us that the client never sent
+    /// (injected minus outbound stanzas dropped natively).
+    outbound_delta: u32,
+    /// Stanzas the server sent that the client never received.
+    inbound_delta: u32,
+    /// Stream management is active on the attached bridge.
+    sm_enabled: bool,
+    /// The server agreed to let the stream-management session be resumed.
+    resumable: bool,
+    /// Stanzas sent upstream on the SM session, native ones included.
+    sent: u32,
+    /// Server's last acknowledged count (`h` of its latest `<a/>`).
+    acked: u32,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+static PENDING: Mutex<Option<HashMap<String, oneshot::Sender<Element>>>> = Mutex::new(None);
+
+/// Server's acknowledged count, published on every `<a/>` and `<resumed/>`.
+fn acks() -> &'static watch::Sender<u32> {
+    static ACKS: OnceLock<watch::Sender<u32>> = OnceLock::new();
+    ACKS.get_or_init(|| watch::Sender::new(0))
+}
+
+fn with_state<T>(f: impl FnOnce(&mut State) -> T) -> T {
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(guard.get_or_insert_with(State::default))
+}
+
+/// Register a bridge as the live session. Returns the receiver the bridge's
+/// upstream writer drains for injected stanzas.
+pub(crate) fn attach(conn_id: u64) -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    with_state(|state| {
+        state.attached = Some((conn_id, tx));
+        state.ready = false;
+        state.sm_enabled = false;
+        state.resumable = false;
+    });
+    rx
+}
+
+/// Forget a bridge when it ends. A newer bridge that already attached is left
+/// untouched.
+pub(crate) fn detach(conn_id: u64) {
+    with_state(|state| {
+        if state.attached.as_ref().map(|(id, _)| *id) == Some(conn_id) {
+            state.attached = None;
+            state.ready = false;
+            state.sm_enabled = false;
+            state.resumable = false;
+            // Dropping the waiters fails their requests immediately instead
+            // of letting them run into the timeout.
+            PENDING.lock().unwrap_or_else(|e| e.into_inner()).take();
+        }
+    });
+}
+
+/// A bridge is attached and its session is bound (or resumed), so
+/// [`send`] and [`request`] can go through.
+pub fn is_ready() -> bool {
+    with_state(|state| state.attached.is_some() && state.ready)
+}
+
+/// The session is bound (or resumed) on the bridge `conn_id`.
+pub(crate) fn carries(conn_id: u64) -> bool {
+    with_state(|state| state.ready && state.attached.as_ref().map(|(id, _)| *id) == Some(conn_id))
+}
+
+/// The server would let the current stream-management session be resumed
+/// (XEP-0198 `resume`), so a lost connection need not start over.
+pub fn is_resumable() -> bool {
+    with_state(|state| state.sm_enabled && state.resumable)
+}
+
+/// Full JID of the session, once bound.
+pub fn own_jid() -> Option<String> {
+    with_state(|state| state.jid.clone())
+}
+
+/// Inspect an inbound routed stanza before observers see it. Returns `true`
+/// when it answers a native request and must not reach the client.
+pub(crate) fn intercept_inbound(conn_id: u64, stanza: &Element) -> bool {
+    if stanza.local_name() != "iq" {
+        return false;
+    }
+    let kind = stanza.attr("type").unwrap_or("");
+    if kind != "result" && kind != "error" {
+        return false;
+    }
+    if let Some(jid) = stanza
+        .child("bind", Some(BIND_NS))
+        .and_then(|bind| bind.child("jid", None))
+        .map(|jid| jid.text())
+    {
+        with_state(|state| {
+            if state.attached.as_ref().map(|(id, _)| *id) == Some(conn_id) {
+                state.jid = Some(jid.trim().to_string());
+                state.ready = true;
+                crate::startup_timings::end(crate::startup_timings::FIRST_CONNECT);
+            }
+        });
+        return false;
+    }
+    let Some(id) = stanza.attr("id") else {
+        return false;
+    };
+    let waiter = PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_mut()
+        .and_then(|pending| pending.remove(id));
+    match waiter {
+        Some(tx) => {
+            let _ = tx.send(stanza.clone());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Account for a routed stanza the bridge dropped instead of relaying.
+pub(crate) fn note_dropped(direction: Direction) {
+    with_state(|state| match direction {
+        Direction::Inbound => state.inbound_delta = state.inbound_delta.wrapping_add(1),
+        Direction::Outbound => state.outbound_delta = state.outbound_delta.wrapping_sub(1),
+    });
+}
+
+/// Account for a routed stanza the bridge relayed.
+pub(crate) fn note_forwarded(direction: Direction) {
+    if direction == Direction::Outbound {
+        with_state(|state| state.sent = state.sent.wrapping_add(1));
+    }
+}
+
+/// Handle a stream-management nonza. Returns a rewritten copy when its `h`
+/// counter has to be adjusted for native traffic.
+pub(crate) fn rewrite_nonza(conn_id: u64, direction: Direction, raw: &str) -> Option<String> {
+    let trimmed = raw.trim_start();
+    if !(trimmed.starts_with("<a ")
+        || trimmed.starts_with("<enable")
+        || trimmed.starts_with("<resume"))
+    {
+        return None;
+    }
+    let mut nonza = Element::parse(raw)?;
+    if nonza.ns() != Some(SM_NS) {
+        return None;
+    }
+    with_state(|state| {
+        let delta = match (nonza.local_name(), direction) {
+            ("enable", Direction::Outbound) => {
+                // Fresh SM session: counting restarts from zero on both sides.
+                state.outbound_delta = 0;
+                state.inbound_delta = 0;
+                state.sent = 0;
+                state.acked = 0;
+                return None;
+            }
+            ("enabled", Direction::Inbound) => {
+                state.sm_enabled = true;
+                state.resumable = matches!(nonza.attr("resume"), Some("true") | Some("1"));
+                return None;
+            }
+            ("resumed", Direction::Inbound) => {
+                if state.attached.as_ref().map(|(id, _)| *id) == Some(conn_id) {
+                    state.ready = state.jid.is_some();
+                    state.sm_enabled = true;
+                    state.resumable = true;
+                }
+                // The client retransmits whatever the server had not yet
+                // received, so counting restarts from the server's `h`.
+                if let Some(h) = nonza.attr("h").and_then(|h| h.parse().ok()) {
+                    state.sent = h;
+                    state.acked = h;
+                    acks().send_replace(h);
+                }
+                // Server's count of our stanzas → what the client actually sent.
+                state.outbound_delta.wrapping_neg()
+            }
+            ("a", Direction::Inbound) => {
+                if let Some(h) = nonza.attr("h").and_then(|h| h.parse().ok()) {
+                    state.acked = h;
+                    acks().send_replace(h);
+                }
+                state.outbound_delta.wrapping_neg()
+            }
+            // Client's count of received stanzas → what the server actually sent.
+            ("a", Direction::Outbound) | ("resume", Direction::Outbound) => state.inbound_delta,
+            _ => return None,
+        };
+        if delta == 0 {
+            return None;
+        }
+        let h: u32 = nonza.attr("h")?.parse().ok()?;
+        nonza.set_attr("h", &h.wrapping_add(delta).to_string());
+        Some(nonza.to_xml())
+    })
+}
+
+/// Send a stanza upstream without waiting for anything back.
+pub fn send(stanza: Element) -> Result<(), String> {
+    with_state(|state| {
+        let (_, tx) = state
+            .attached
+            .as_ref()
+            .filter(|_| state.ready)
+            .ok_or_else(|| "Not connected".to_string())?;
+        tx.send(stanza.to_xml())
+            .map_err(|_| "Connection closed".to_string())?;
+        state.outbound_delta = state.outbound_delta.wrapping_add(1);
+        state.sent = state.sent.wrapping_add(1);
+        Ok(())
+    })
+}
+
+/// Ask the server to acknowledge what it has received (`<r/>`). The `<a/>`
+/// reaches the client too, which XEP-0198 allows at any time.
+pub fn request_ack() -> Result<(), String> {
+    with_state(|state| {
+        let (_, tx) = state
+            .attached
+            .as_ref()
+            .filter(|_| state.ready && state.sm_enabled)
+            .ok_or_else(|| "Stream management not active".to_string())?;
+        tx.send(format!("<r xmlns='{SM_NS}'/>"))
+            .map_err(|_| "Connection closed".to_string())
+    })
+}
+
+/// Stanzas sent upstream that the server has not acknowledged yet, or
+/// `None` without a stream-management session to ask.
+pub fn unacked_outbound() -> Option<u32> {
+    with_state(|state| {
+        (state.attached.is_some() && state.sm_enabled)
+            .then(|| state.sent.wrapping_sub(state.acked))
+    })
+}
+
+/// Stream-management sequence number the stanza being dispatched will get
+/// once relayed upstream, or `None` without stream management. Meant for
+/// observers of outbound stanzas, which run before the stanza is counted.
+pub fn next_outbound_seq() -> Option<u32> {
+    with_state(|state| {
+        (state.attached.is_some() && state.sm_enabled).then(|| state.sent.wrapping_add(1))
+    })
+}
+
+/// Follow the server's acknowledged count (`h`), in the same numbering as
+/// [`next_outbound_seq`].
+pub fn subscribe_acks() -> watch::Receiver<u32> {
+    acks().subscribe()
+}
+
+/// Send an IQ get/set and wait for its result. An `error` response is turned
+/// into `Err` carrying the defined condition.
+pub async fn request(iq: Element) -> Result<Element, String> {
+    let response = exchange(iq).await?;
+    if response.attr("type") == Some("error") {
+        return Err(format!("Server error: {}", error_condition(&response)));
+    }
+    Ok(response)
+}
+
+/// Like [`request`], but hands back `error` responses too, for callers that
+/// treat some conditions (e.g. `item-not-found`) as an answer.
+pub async fn exchange(mut iq: Element) -> Result<Element, String> {
+    let id = format!("fluux-{}", uuid::Uuid::new_v4());
+    iq.set_attr("id", &id);
+    let (tx, rx) = oneshot::channel();
+    PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(id.clone(), tx);
+
+    let forget = || {
+        if let Some(pending) = PENDING.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            pending.remove(&id);
+        }
+    };
+    if let Err(e) = send(iq) {
+        forget();
+        return Err(e);
+    }
+
+    let response = match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(_)) => {
+            forget();
+            return Err("Connection closed".to_string());
+        }
+        Err(_) => {
+            forget();
+            warn!(id = %id, "Native IQ timed out");
+            return Err("Request timed out".to_string());
+        }
+    };
+    debug!(id = %id, kind = ?response.attr("type"), "Native IQ answered");
+    Ok(response)
+}
+
+/// Defined condition of an IQ error (e.g. `item-not-found`).
+pub fn error_condition(iq: &Element) -> String {
+    iq.child("error", None)
+        .and_then(|error| {
+            error.elements().find(|e| {
+                e.ns() == Some("urn:ietf:params:xml:ns:xmpp-stanzas") && e.local_name() != "text"
+            })
+        })
+        .map(|condition| condition.local_name().to_string())
+        .unwrap_or_else(|| "undefined-condition".to_string())
+}
+
+/// Bare JID of the session, once bound.
+pub fn own_bare_jid() -> Option<String> {
+    own_jid().map(|jid| stanza::bare_jid(&jid).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The session is a process-wide singleton; keep every assertion that
+    // touches it in one test so parallel test threads cannot interleave.
+    #[test]
+    fn counters_and_bind_tracking() {
+        let mut rx = attach(7);
+        assert!(send(Element::new("presence")).is_err());
+        let bind = Element::parse(
+            "<iq type='result' id='b'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'><jid>me@example.com/desk</jid></bind></iq>",
+        )
+        .unwrap();
+        assert!(!intercept_inbound(7, &bind));
+        assert_eq!(own_bare_jid().as_deref(), Some("me@example.com"));
+
+        assert_eq!(rewrite_nonza(7, Direction::Outbound, "<enable xmlns='urn:xmpp:sm:3'/>"), None);
+        assert_eq!(unacked_outbound(), None);
+        assert_eq!(rewrite_nonza(7, Direction::Inbound, "<enabled xmlns='urn:xmpp:sm:3'/>"), None);
+        send(Element::new("presence")).unwrap();
+        assert!(rx.try_recv().unwrap().starts_with("<presence"));
+        note_dropped(Direction::Inbound);
+        note_forwarded(Direction::Outbound);
+        note_forwarded(Direction::Inbound);
+        assert_eq!(unacked_outbound(), Some(2));
+
+        let a_in = rewrite_nonza(7, Direction::Inbound, "<a xmlns='urn:xmpp:sm:3' h='10'/>").unwrap();
+        assert!(a_in.contains("h='9'"));
+        let a_out = rewrite_nonza(7, Direction::Outbound, "<a xmlns='urn:xmpp:sm:3' h='4'/>").unwrap();
+        assert!(a_out.contains("h='5'"));
+        assert_eq!(rewrite_nonza(7, Direction::Outbound, "<r xmlns='urn:xmpp:sm:3'/>"), None);
+
+        request_ack().unwrap();
+        assert_eq!(rx.try_recv().unwrap(), "<r xmlns='urn:xmpp:sm:3'/>");
+        assert!(rewrite_nonza(7, Direction::Inbound, "<a xmlns='urn:xmpp:sm:3' h='2'/>").is_some());
+        assert_eq!(unacked_outbound(), Some(0));
+
+        assert!(carries(7) && !is_resumable());
+        let enabled = "<enabled xmlns='urn:xmpp:sm:3' id='s1' resume='true'/>";
+        assert_eq!(rewrite_nonza(7, Direction::Inbound, enabled), None);
+        assert!(is_resumable());
+
+        detach(7);
+        assert!(send(Element::new("presence")).is_err());
+        assert_eq!(unacked_outbound(), None);
+        assert!(!carries(7) && !is_resumable());
+    }
+
+    #[test]
+    fn error_condition_skips_text() {
+        let iq = Element::parse(
+            "<iq type='error'><error type='cancel'><text xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'>x</text><item-not-found xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></error></iq>",
+        )
+        .unwrap();
+        assert_eq!(error_condition(&iq), "item-not-found");
+    }
+}
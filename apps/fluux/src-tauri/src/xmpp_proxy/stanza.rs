@@ -0,0 +1,350 @@
+//! Minimal owned element tree for stanzas the bridge inspects natively.
+//!
+//! The bridge forwards stanzas as opaque strings; native features that need
+//! to look *inside* one (unread counters, blocking, receipts, …) parse it
+//! into an [`Element`] here. This is deliberately not a general-purpose XML
+//! DOM: namespaces are read from each element's own `xmlns` attribute (which
+//! is how XMPP extension payloads are written on the wire) rather than fully
+//! resolved, and comments/processing instructions are dropped.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+/// Deepest nesting [`Element::parse`] accepts. Serializing and dropping a
+/// tree recurse once per level, so a hostile stanza nested thousands deep
+/// would overflow the stack; real stanzas stay well under a dozen levels.
+pub const MAX_DEPTH: usize = 128;
+
+/// One node inside an [`Element`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+/// An XML element with its attributes (unescaped) and children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    /// Qualified name exactly as on the wire (e.g. `message`, `stream:error`).
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    pub fn new(name: &str) -> Self {
+        Element {
+            name: name.to_string(),
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Builder: set (or replace) an attribute.
+    pub fn with_attr(mut self, name: &str, value: &str) -> Self {
+        self.set_attr(name, value);
+        self
+    }
+
+    /// Builder: append a child element.
+    pub fn with_child(mut self, child: Element) -> Self {
+        self.children.push(Node::Element(child));
+        self
+    }
+
+    /// Builder: append a text node.
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.children.push(Node::Text(text.to_string()));
+        self
+    }
+
+    pub fn set_attr(&mut self, name: &str, value: &str) {
+        match self.attrs.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value.to_string(),
+            None => self.attrs.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Serialize back to XML with attribute and text escaping.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        self.write_xml(&mut out);
+        out
+    }
+
+    fn write_xml(&self, out: &mut String) {
+        out.push('<');
+        out.push_str(&self.name);
+        for (key, value) in &self.attrs {
+            out.push(' ');
+            out.push_str(key);
+            out.push_str("='");
+            out.push_str(&escape(value));
+            out.push('\'');
+        }
+        if self.children.is_empty() {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+        for child in &self.children {
+            match child {
+                Node::Element(element) => element.write_xml(out),
+                Node::Text(text) => out.push_str(&escape(text)),
+            }
+        }
+        out.push_str("</");
+        out.push_str(&self.name);
+        out.push('>');
+    }
+
+    /// Parse a single serialized stanza. Returns `None` for anything that is
+    /// not one well-formed element (stream headers, partial input, garbage)
+    /// or that nests deeper than [`MAX_DEPTH`].
+    pub fn parse(xml: &str) -> Option<Element> {
+        let mut reader = Reader::from_str(xml.trim());
+        reader.config_mut().trim_text(false);
+
+        let mut stack: Vec<Element> = Vec::new();
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(e)) => {
+                    if stack.len() >= MAX_DEPTH {
+                        return None;
+                    }
+                    stack.push(element_from_start(&e));
+                }
+                Ok(Event::Empty(e)) => {
+                    let element = element_from_start(&e);
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(element)),
+                        None => return Some(element),
+                    }
+                }
+                Ok(Event::End(_)) => {
+                    let element = stack.pop()?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(element)),
+                        None => return Some(element),
+                    }
+                }
+                Ok(Event::Text(t)) => push_text(&mut stack, &String::from_utf8_lossy(&t)),
+                Ok(Event::CData(t)) => push_text(&mut stack, &String::from_utf8_lossy(&t)),
+                Ok(Event::GeneralRef(r)) => {
+                    let entity = format!("&{};", String::from_utf8_lossy(&r));
+                    push_text(&mut stack, &unescape(&entity));
+                }
+                Ok(Event::Eof) | Err(_) => return None,
+                Ok(_) => {}
+            }
+        }
+    }
+
+    /// Element name without any namespace prefix.
+    pub fn local_name(&self) -> &str {
+        self.name.rsplit(':').next().unwrap_or(&self.name)
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The element's own default namespace declaration, if any.
+    pub fn ns(&self) -> Option<&str> {
+        self.attr("xmlns")
+    }
+
+    /// Child elements, in document order.
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// First child element with the given local name and, when `ns` is
+    /// given, a matching `xmlns` declaration.
+    pub fn child(&self, local_name: &str, ns: Option<&str>) -> Option<&Element> {
+        self.elements()
+            .find(|e| e.local_name() == local_name && (ns.is_none() || e.ns() == ns))
+    }
+
+    /// Concatenated direct text content.
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|node| match node {
+                Node::Text(text) => Some(text.as_str()),
+                Node::Element(_) => None,
+            })
+            .collect()
+    }
+}
+
+fn element_from_start(start: &BytesStart<'_>) -> Element {
+    let attrs = start
+        .attributes()
+        .flatten()
+        .map(|attr| {
+            (
+                String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                unescape(&String::from_utf8_lossy(&attr.value)),
+            )
+        })
+        .collect();
+    Element {
+        name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+        attrs,
+        children: Vec::new(),
+    }
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+    let Some(parent) = stack.last_mut() else {
+        return;
+    };
+    if let Some(Node::Text(existing)) = parent.children.last_mut() {
+        existing.push_str(text);
+    } else {
+        parent.children.push(Node::Text(text.to_string()));
+    }
+}
+
+/// Escape text or an attribute value for serialization.
+pub fn escape(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\'' => out.push_str("&apos;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Resolve the five predefined XML entities and numeric character references.
+/// Unknown entities are kept verbatim rather than failing the whole stanza.
+fn unescape(raw: &str) -> String {
+    if !raw.contains('&') {
+        return raw.to_string();
+    }
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let tail = &rest[amp..];
+        let Some(semi) = tail.find(';') else {
+            out.push_str(tail);
+            return out;
+        };
+        let entity = &tail[1..semi];
+        let resolved = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match resolved {
+            Some(c) => out.push(c),
+            None => out.push_str(&tail[..=semi]),
+        }
+        rest = &tail[semi + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Strip the resource from a JID (`user@host/res` → `user@host`).
+pub fn bare_jid(jid: &str) -> &str {
+    jid.split('/').next().unwrap_or(jid)
+}
+
+/// The resource part of a full JID, if any.
+pub fn resource(jid: &str) -> Option<&str> {
+    jid.split_once('/').map(|(_, res)| res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_message_with_body_and_attributes() {
+        let el = Element::parse(
+            "<message from='alice@example.com/phone' type='chat' id='m1'><body>Hi &amp; bye</body></message>",
+        )
+        .expect("parses");
+        assert_eq!(el.local_name(), "message");
+        assert_eq!(el.attr("from"), Some("alice@example.com/phone"));
+        assert_eq!(el.child("body", None).map(|b| b.text()), Some("Hi & bye".to_string()));
+    }
+
+    #[test]
+    fn child_lookup_honours_namespace() {
+        let el = Element::parse(
+            "<message><x xmlns='jabber:x:conference'/><x xmlns='jabber:x:oob'><url>u</url></x></message>",
+        )
+        .unwrap();
+        let oob = el.child("x", Some("jabber:x:oob")).expect("oob child");
+        assert_eq!(oob.child("url", None).unwrap().text(), "u");
+        assert!(el.child("x", Some("urn:other")).is_none());
+    }
+
+    #[test]
+    fn unescapes_attribute_values_and_char_refs() {
+        let el = Element::parse("<presence status='a&apos;b &#x263A; &#65;'/>").unwrap();
+        assert_eq!(el.attr("status"), Some("a'b ☺ A"));
+    }
+
+    #[test]
+    fn rejects_stream_header_and_partial_input() {
+        assert!(Element::parse("<stream:stream xmlns='jabber:client'>").is_none());
+        assert!(Element::parse("<message><body>half").is_none());
+        assert!(Element::parse("not xml").is_none());
+    }
+
+    #[test]
+    fn rejects_pathological_nesting() {
+        let depth = MAX_DEPTH + 1;
+        let deep = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        assert!(Element::parse(&deep).is_none());
+        let ok = format!("{}{}", "<a>".repeat(MAX_DEPTH), "</a>".repeat(MAX_DEPTH));
+        assert!(Element::parse(&ok).is_some());
+    }
+
+    #[test]
+    fn serialization_round_trips_through_parse() {
+        let built = Element::new("iq")
+            .with_attr("type", "set")
+            .with_attr("id", "a'b")
+            .with_child(
+                Element::new("block")
+                    .with_attr("xmlns", "urn:xmpp:blocking")
+                    .with_child(Element::new("item").with_attr("jid", "x@y")),
+            )
+            .with_child(Element::new("note").with_text("<1 & 2>"));
+        let xml = built.to_xml();
+        assert_eq!(Element::parse(&xml), Some(built));
+        assert!(xml.contains("id='a&apos;b'"));
+    }
+
+    #[test]
+    fn jid_helpers_split_resource() {
+        assert_eq!(bare_jid("room@muc.example.com/nick/with/slash"), "room@muc.example.com");
+        assert_eq!(resource("room@muc.example.com/nick/with/slash"), Some("nick/with/slash"));
+        assert_eq!(resource("example.com"), None);
+    }
+}
@@ -0,0 +1,522 @@
+//! Per-connection progress, reported to the frontend as typed phases.
+//!
+//! Until a connection is online the UI only knew "connecting" and, on
+//! failure, a close reason. A [`ConnectionSupervisor`] follows one proxied
+//! connection through DNS, TCP, TLS, SASL and resource binding and emits a
+//! `connection-phase` event at every step:
+//!
+//! `resolving` → `connecting` (once per endpoint tried) → `tls-handshake` →
+//! `secured` → `authenticating` → `online`, then `degraded` while the server stops
+//! acknowledging stream-management requests, and `disconnected` at the end.
+//! A failure at any step is `failed`, naming the step and the cause.
+//!
+//! The proxy drives the first four phases itself. SASL and binding are
+//! negotiated by the WebView's client over the bridge, so they are inferred
+//! from the nonzas relayed (see [`ConnectionSupervisor::observe`]).
+
+use super::cert_check::{CertificateWarning, NameMismatch};
+use super::stanza::Element;
+use super::tap::Direction;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+use tracing::{info, warn};
+
+/// How long an `<r/>` may stay unanswered before the connection is reported
+/// degraded. Servers answer within a round trip; 15 s of silence means the
+/// link is stalling even if TCP has not noticed yet.
+const ACK_OVERDUE: Duration = Duration::from_secs(15);
+
+/// How often the bridge checks for an overdue ack.
+pub(super) const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+const SASL_NS: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const SASL2_NS: &str = "urn:xmpp:sasl:2";
+const BIND_NS: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+const SM_NS: &str = "urn:xmpp:sm:3";
+
+/// Step a [`Phase::Failed`] happened in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    Resolving,
+    Connecting,
+    TlsHandshake,
+    Authenticating,
+    Binding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "phase", rename_all = "kebab-case")]
+pub enum Phase {
+    Resolving {
+        server: String,
+    },
+    Connecting {
+        host: String,
+        port: u16,
+        direct_tls: bool,
+    },
+    /// TCP is connected; `local_addr` and `peer_addr` show which source
+    /// address and server address it went through.
+    TlsHandshake {
+        host: String,
+        tls_name: String,
+        local_addr: Option<String>,
+        peer_addr: Option<String>,
+    },
+    /// TLS is up. `alpn` is the protocol the server agreed to (direct TLS
+    /// offers `xmpp-client`, XEP-0368); `None` when it ignored ALPN.
+    Secured {
+        tls_version: Option<String>,
+        alpn: Option<String>,
+    },
+    Authenticating {
+        mechanism: Option<String>,
+    },
+    Online {
+        jid: Option<String>,
+        /// The session was resumed (XEP-0198) rather than newly bound.
+        resumed: bool,
+    },
+    Degraded {
+        reason: String,
+    },
+    Failed {
+        stage: Stage,
+        error: String,
+        /// SASL failure or stream-error condition, when the server sent one.
+        condition: Option<String>,
+        /// The certificate of an endpoint tried is for other names.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name_mismatch: Option<NameMismatch>,
+    },
+    Disconnected {
+        reason: String,
+    },
+}
+
+impl Phase {
+    fn stage(&self) -> Option<Stage> {
+        match self {
+            Phase::Resolving { .. } => Some(Stage::Resolving),
+            Phase::Connecting { .. } => Some(Stage::Connecting),
+            Phase::TlsHandshake { .. } => Some(Stage::TlsHandshake),
+            // Authenticated but not bound yet still reports as authenticating;
+            // `observe` moves to binding on SASL success.
+            Phase::Authenticating { .. } => Some(Stage::Authenticating),
+            _ => None,
+        }
+    }
+}
+
+/// Error class of a failure, for telemetry: the server's condition when it
+/// sent one, else the transport class (`certificate-expired`, `timeout`…).
+fn failure_class(error: &str, condition: Option<&str>) -> String {
+    condition
+        .map(str::to_string)
+        .or_else(|| super::transport_error_class_from_error(error))
+        .unwrap_or_else(|| super::classify_tls_error(error).to_string())
+}
+
+#[derive(Clone, Serialize)]
+struct CertificateWarningEvent<'a> {
+    conn_id: u64,
+    tls_name: &'a str,
+    warnings: &'a [CertificateWarning],
+}
+
+#[derive(Clone, Serialize)]
+struct PhaseEvent<'a> {
+    conn_id: u64,
+    /// Since the WebSocket was accepted.
+    elapsed_ms: u64,
+    #[serde(flatten)]
+    phase: &'a Phase,
+}
+
+struct State {
+    phase: Option<Phase>,
+    /// Step a failure would be attributed to.
+    stage: Stage,
+    online: bool,
+    /// When the oldest unanswered `<r/>` went out.
+    ack_requested: Option<Instant>,
+    /// Last certificate name mismatch, reported with the failure.
+    name_mismatch: Option<NameMismatch>,
+}
+
+/// Tracks and reports the phases of one proxied connection.
+pub struct ConnectionSupervisor {
+    conn_id: u64,
+    started: Instant,
+    app: Option<tauri::AppHandle>,
+    state: Mutex<State>,
+}
+
+impl ConnectionSupervisor {
+    pub fn new(conn_id: u64, app: Option<tauri::AppHandle>) -> Self {
+        super::metrics::connection_started();
+        Self {
+            conn_id,
+            started: Instant::now(),
+            app,
+            state: Mutex::new(State {
+                phase: None,
+                stage: Stage::Resolving,
+                online: false,
+                ack_requested: None,
+                name_mismatch: None,
+            }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Phase last reported.
+    pub fn phase(&self) -> Option<Phase> {
+        self.state().phase.clone()
+    }
+
+    /// Record and emit `phase`, unless it is the one already reported.
+    pub(super) fn enter(&self, phase: Phase) {
+        let came_online = {
+            let mut state = self.state();
+            if state.phase.as_ref() == Some(&phase) {
+                return;
+            }
+            if let Some(stage) = phase.stage() {
+                state.stage = stage;
+            }
+            let was_online = state.online;
+            match &phase {
+                Phase::Online { .. } => state.online = true,
+                Phase::Failed { .. } | Phase::Disconnected { .. } => state.online = false,
+                _ => {}
+            }
+            state.phase = Some(phase.clone());
+            state.online && !was_online
+        };
+        match &phase {
+            Phase::Online { .. } if came_online => {
+                crate::telemetry::connected();
+                super::metrics::connected();
+            }
+            Phase::Failed {
+                stage,
+                error,
+                condition,
+                ..
+            } => {
+                let class = failure_class(error, condition.as_deref());
+                crate::telemetry::connect_failed(*stage, &class);
+                super::metrics::connect_failed(*stage, &class);
+            }
+            _ => {}
+        }
+        let elapsed_ms = self.started.elapsed().as_millis() as u64;
+        match &phase {
+            Phase::Failed { .. } | Phase::Degraded { .. } => {
+                warn!(
+                    conn_id = self.conn_id,
+                    elapsed_ms,
+                    ?phase,
+                    "Connection phase"
+                )
+            }
+            _ => info!(
+                conn_id = self.conn_id,
+                elapsed_ms,
+                ?phase,
+                "Connection phase"
+            ),
+        }
+        if let Some(app) = &self.app {
+            let _ = app.emit(
+                "connection-phase",
+                PhaseEvent {
+                    conn_id: self.conn_id,
+                    elapsed_ms,
+                    phase: &phase,
+                },
+            );
+        }
+    }
+
+    /// Report a failure in the current step.
+    pub(super) fn fail(&self, error: &str, condition: Option<String>) {
+        let (stage, name_mismatch) = {
+            let mut state = self.state();
+            (state.stage, state.name_mismatch.take())
+        };
+        self.enter(Phase::Failed {
+            stage,
+            error: error.to_string(),
+            condition,
+            name_mismatch,
+        });
+    }
+
+    /// Record that an endpoint's certificate is for other names; reported
+    /// with the failure if no endpoint connects.
+    pub(super) fn name_mismatch(&self, mismatch: NameMismatch) {
+        self.state().name_mismatch = Some(mismatch);
+    }
+
+    /// Report problems found in the server certificate (see
+    /// `cert_check`) as a `certificate-warning` event. Does not affect the
+    /// connection.
+    pub(super) fn certificate_warnings(&self, tls_name: &str, warnings: Vec<CertificateWarning>) {
+        if warnings.is_empty() {
+            return;
+        }
+        warn!(
+            conn_id = self.conn_id,
+            tls_name,
+            ?warnings,
+            "Server certificate warning"
+        );
+        if let Some(app) = &self.app {
+            let _ = app.emit(
+                "certificate-warning",
+                CertificateWarningEvent {
+                    conn_id: self.conn_id,
+                    tls_name,
+                    warnings: &warnings,
+                },
+            );
+        }
+    }
+
+    /// Follow SASL, binding and stream-management acks in a relayed nonza
+    /// or stanza. Cheap prefix checks keep this off the parse path for
+    /// ordinary traffic.
+    pub(super) fn observe(&self, direction: Direction, raw: &str) {
+        let trimmed = raw.trim_start();
+        let online = self.state().online;
+        match direction {
+            Direction::Outbound => {
+                // `<auth>` (SASL) and `<authenticate>` (SASL2).
+                if !online && trimmed.starts_with("<auth") {
+                    let Some(auth) = Element::parse(raw) else {
+                        return;
+                    };
+                    if matches!(auth.ns(), Some(SASL_NS) | Some(SASL2_NS)) {
+                        self.enter(Phase::Authenticating {
+                            mechanism: auth.attr("mechanism").map(str::to_string),
+                        });
+                    }
+                } else if trimmed.starts_with("<r ") || trimmed.starts_with("<r/") {
+                    self.state().ack_requested.get_or_insert_with(Instant::now);
+                }
+            }
+            Direction::Inbound => {
+                if trimmed.starts_with("<a ") {
+                    self.ack_received();
+                } else if !online {
+                    self.observe_setup(trimmed);
+                }
+            }
+        }
+    }
+
+    fn observe_setup(&self, trimmed: &str) {
+        let interesting = ["<success", "<failure", "<iq", "<resumed"]
+            .iter()
+            .any(|prefix| trimmed.starts_with(prefix));
+        if !interesting {
+            return;
+        }
+        let Some(element) = Element::parse(trimmed) else {
+            return;
+        };
+        match (element.local_name(), element.ns()) {
+            ("success", Some(SASL_NS)) => self.state().stage = Stage::Binding,
+            ("success", Some(SASL2_NS)) => {
+                // SASL2 with Bind 2 binds in the same round trip.
+                if element.child("bound", Some("urn:xmpp:bind:0")).is_some() {
+                    let jid = element
+                        .child("authorization-identifier", None)
+                        .map(|jid| jid.text().trim().to_string());
+                    self.enter(Phase::Online {
+                        jid,
+                        resumed: false,
+                    });
+                } else {
+                    self.state().stage = Stage::Binding;
+                }
+            }
+            ("failure", Some(SASL_NS)) | ("failure", Some(SASL2_NS)) => {
+                let condition = element
+                    .elements()
+                    .map(|child| child.local_name())
+                    .find(|name| *name != "text")
+                    .map(str::to_string);
+                let text = element
+                    .child("text", None)
+                    .map(|text| text.text())
+                    .unwrap_or_else(|| "Authentication failed".to_string());
+                self.fail(&text, condition);
+            }
+            ("iq", _) if element.child("bind", Some(BIND_NS)).is_some() => {
+                match element.attr("type") {
+                    Some("result") => {
+                        let jid = element
+                            .child("bind", Some(BIND_NS))
+                            .and_then(|bind| bind.child("jid", None))
+                            .map(|jid| jid.text().trim().to_string());
+                        self.enter(Phase::Online {
+                            jid,
+                            resumed: false,
+                        });
+                    }
+                    Some("error") => self.fail(
+                        "Resource binding failed",
+                        Some(super::session::error_condition(&element)),
+                    ),
+                    _ => {}
+                }
+            }
+            ("resumed", Some(SM_NS)) => self.enter(Phase::Online {
+                jid: None,
+                resumed: true,
+            }),
+            _ => {}
+        }
+    }
+
+    fn ack_received(&self) {
+        let recovered = {
+            let mut state = self.state();
+            state.ack_requested = None;
+            matches!(state.phase, Some(Phase::Degraded { .. }))
+        };
+        if recovered {
+            let jid = super::session::own_jid();
+            self.enter(Phase::Online {
+                jid,
+                resumed: false,
+            });
+        }
+    }
+
+    /// Report `degraded` once an ack has been overdue for [`ACK_OVERDUE`].
+    /// Called every [`HEALTH_CHECK_INTERVAL`] by the bridge.
+    pub(super) fn check_health(&self) {
+        let overdue = {
+            let state = self.state();
+            state.online
+                && !matches!(state.phase, Some(Phase::Degraded { .. }))
+                && state
+                    .ack_requested
+                    .is_some_and(|since| since.elapsed() >= ACK_OVERDUE)
+        };
+        if overdue {
+            self.enter(Phase::Degraded {
+                reason: format!(
+                    "Server has not acknowledged stanzas for {}s",
+                    ACK_OVERDUE.as_secs()
+                ),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_sasl_and_bind() {
+        let supervisor = ConnectionSupervisor::new(1, None);
+        supervisor.enter(Phase::TlsHandshake {
+            host: "xmpp.example.com".to_string(),
+            tls_name: "example.com".to_string(),
+            local_addr: None,
+            peer_addr: None,
+        });
+        supervisor.observe(
+            Direction::Outbound,
+            "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='SCRAM-SHA-1'>biws</auth>",
+        );
+        assert_eq!(
+            supervisor.phase(),
+            Some(Phase::Authenticating {
+                mechanism: Some("SCRAM-SHA-1".to_string())
+            })
+        );
+        supervisor.observe(
+            Direction::Inbound,
+            "<success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>dj1=</success>",
+        );
+        supervisor.observe(
+            Direction::Inbound,
+            "<iq type='result' id='b'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'>\
+             <jid>me@example.com/fluux</jid></bind></iq>",
+        );
+        assert_eq!(
+            supervisor.phase(),
+            Some(Phase::Online {
+                jid: Some("me@example.com/fluux".to_string()),
+                resumed: false
+            })
+        );
+    }
+
+    #[test]
+    fn sasl_failure_names_the_condition() {
+        let supervisor = ConnectionSupervisor::new(2, None);
+        supervisor.observe(
+            Direction::Outbound,
+            "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='PLAIN'>AA==</auth>",
+        );
+        supervisor.observe(
+            Direction::Inbound,
+            "<failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><not-authorized/></failure>",
+        );
+        match supervisor.phase() {
+            Some(Phase::Failed {
+                stage, condition, ..
+            }) => {
+                assert_eq!(stage, Stage::Authenticating);
+                assert_eq!(condition.as_deref(), Some("not-authorized"));
+            }
+            other => panic!("expected a failure, got {other:?}"),
+        }
+
+        let json = serde_json::to_value(Phase::TlsHandshake {
+            host: "h".to_string(),
+            tls_name: "d".to_string(),
+            local_addr: Some("192.0.2.10:50000".to_string()),
+            peer_addr: Some("192.0.2.1:5222".to_string()),
+        })
+        .unwrap();
+        assert_eq!(json["phase"], "tls-handshake");
+        let json = serde_json::to_value(Phase::Secured {
+            tls_version: Some("TLSv1_3".to_string()),
+            alpn: Some("xmpp-client".to_string()),
+        })
+        .unwrap();
+        assert_eq!(json["phase"], "secured");
+        assert_eq!(json["alpn"], "xmpp-client");
+    }
+
+    #[test]
+    fn overdue_acks_degrade_until_answered() {
+        let supervisor = ConnectionSupervisor::new(3, None);
+        supervisor.enter(Phase::Online {
+            jid: None,
+            resumed: true,
+        });
+        supervisor.observe(Direction::Outbound, "<r xmlns='urn:xmpp:sm:3'/>");
+        supervisor.state().ack_requested = Some(Instant::now() - ACK_OVERDUE);
+        supervisor.check_health();
+        assert!(matches!(supervisor.phase(), Some(Phase::Degraded { .. })));
+
+        supervisor.observe(Direction::Inbound, "<a xmlns='urn:xmpp:sm:3' h='3'/>");
+        assert!(matches!(supervisor.phase(), Some(Phase::Online { .. })));
+    }
+}
@@ -0,0 +1,140 @@
+//! Native observers of the stanzas flowing through the bridge.
+//!
+//! The WebView's XMPP client owns the session; the bridge only relays bytes.
+//! Features that need to keep native state in step with that session (unread
+//! counters, badges, …) register a [`StanzaObserver`] here instead of each
+//! adding its own hook to `bridge_websocket_tls`. Observers run inline on the
+//! bridge tasks, so they must be cheap and must never block.
+//!
+//! An observer may also veto relaying a stanza (e.g. inbound traffic from a
+//! blocked JID). Observers run in registration order and a dropped stanza is
+//! not shown to the ones after the observer that dropped it.
+
+use super::stanza::Element;
+use super::{metrics, privacy, session};
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock};
+
+/// Which way a stanza is travelling through the bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Server → WebView.
+    Inbound,
+    /// WebView → server.
+    Outbound,
+}
+
+/// Per-stanza context handed to observers.
+pub struct TapContext<'a> {
+    pub conn_id: u64,
+    pub direction: Direction,
+    pub app: Option<&'a tauri::AppHandle>,
+}
+
+/// What the bridge should do with an observed stanza.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Forward,
+    Drop,
+}
+
+pub trait StanzaObserver: Send + Sync {
+    fn observe(&self, ctx: &TapContext<'_>, stanza: &Element) -> Verdict;
+}
+
+static OBSERVERS: RwLock<Vec<Arc<dyn StanzaObserver>>> = RwLock::new(Vec::new());
+
+/// Register an observer for the lifetime of the process. Called from the
+/// Tauri `setup` hook.
+pub fn register(observer: Arc<dyn StanzaObserver>) {
+    OBSERVERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(observer);
+}
+
+/// Only routed stanzas are of interest; stream management acks, SASL and
+/// stream framing are skipped before paying for a parse.
+fn is_routed_stanza(raw: &str) -> bool {
+    let trimmed = raw.trim_start();
+    trimmed.starts_with("<message") || trimmed.starts_with("<presence") || trimmed.starts_with("<iq")
+}
+
+/// Hand one relayed stanza to the native session and every registered
+/// observer. Returns what to relay: the stanza unchanged, a rewritten copy
+/// (stream-management counters, do-not-track mode), or `None` when it must
+/// be dropped.
+pub(crate) fn dispatch<'r>(ctx: &TapContext<'_>, raw: &'r str) -> Option<Cow<'r, str>> {
+    metrics::transferred(ctx.direction, raw.len());
+    if !is_routed_stanza(raw) {
+        let raw = match privacy::rewrite_nonza(ctx.direction, raw) {
+            Some(rewritten) => Cow::Owned(rewritten),
+            None => Cow::Borrowed(raw),
+        };
+        return Some(
+            match session::rewrite_nonza(ctx.conn_id, ctx.direction, &raw) {
+                Some(rewritten) => Cow::Owned(rewritten),
+                None => raw,
+            },
+        );
+    }
+    let Some(stanza) = Element::parse(raw) else {
+        session::note_forwarded(ctx.direction);
+        metrics::stanza(ctx.direction, true);
+        return Some(Cow::Borrowed(raw));
+    };
+    if ctx.direction == Direction::Inbound && session::intercept_inbound(ctx.conn_id, &stanza) {
+        // Answer to a native request: the client never asked, so it never sees
+        // it; the session has already accounted for it.
+        session::note_dropped(Direction::Inbound);
+        metrics::stanza(Direction::Inbound, false);
+        return None;
+    }
+    // Rewritten before observers run, so they see what actually goes out.
+    let rewritten = privacy::rewrite_stanza(ctx.direction, &stanza);
+    let stanza = rewritten.as_ref().unwrap_or(&stanza);
+    let observers = OBSERVERS.read().unwrap_or_else(|e| e.into_inner());
+    for observer in observers.iter() {
+        if observer.observe(ctx, stanza) == Verdict::Drop {
+            session::note_dropped(ctx.direction);
+            metrics::stanza(ctx.direction, false);
+            return None;
+        }
+    }
+    session::note_forwarded(ctx.direction);
+    metrics::stanza(ctx.direction, true);
+    Some(match &rewritten {
+        Some(rewritten) => Cow::Owned(rewritten.to_xml()),
+        None => Cow::Borrowed(raw),
+    })
+}
+
+/// Show observers a stanza the app sent itself through
+/// [`session::send`], which does not pass through the bridge's outbound
+/// path. Observers cannot veto it: it is already on the wire.
+pub fn observe_native(app: Option<&tauri::AppHandle>, stanza: &Element) {
+    let ctx = TapContext {
+        // No bridge client originated it.
+        conn_id: 0,
+        direction: Direction::Outbound,
+        app,
+    };
+    let observers = OBSERVERS.read().unwrap_or_else(|e| e.into_inner());
+    for observer in observers.iter() {
+        observer.observe(&ctx, stanza);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_routed_stanzas_are_dispatched() {
+        assert!(is_routed_stanza("<message to='a@b'/>"));
+        assert!(is_routed_stanza("  <iq type='get'/>"));
+        assert!(is_routed_stanza("<presence/>"));
+        assert!(!is_routed_stanza("<r xmlns='urn:xmpp:sm:3'/>"));
+        assert!(!is_routed_stanza("<open xmlns='urn:ietf:params:xml:ns:xmpp-framing'/>"));
+    }
+}
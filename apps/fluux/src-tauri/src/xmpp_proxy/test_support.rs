@@ -0,0 +1,310 @@
+//! Test harness: the bridge against a scripted in-memory server.
+//!
+//! A [`Script`] is a list of steps the fake server plays in order: expect
+//! some bytes from the client, send bytes back, trickle them a few at a time,
+//! stall, or drop the connection. Faults are just steps, so a test reads as
+//! the conversation it exercises. Scripts run over an in-memory duplex pipe
+//! (for the bridge, which takes any upstream stream) or a loopback TCP
+//! listener (for STARTTLS, which needs a real socket to upgrade).
+//!
+//! Compiled for `cargo test` and with the `test-support` feature.
+
+use super::supervisor::ConnectionSupervisor;
+use super::{bridge_websocket_tls, NEXT_PROXY_CONNECTION_ID};
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// How long an `Expect` step waits before failing the script.
+const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One step of the fake server's side of the conversation.
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Read until the client has sent something containing this text.
+    Expect(String),
+    /// Send these bytes in one write.
+    Send(String),
+    /// Send these bytes `chunk` bytes at a time, pausing `delay` between
+    /// writes (a slow or congested server).
+    Trickle {
+        data: String,
+        chunk: usize,
+        delay: Duration,
+    },
+    /// Do nothing for a while.
+    Stall(Duration),
+    /// Close the connection, possibly mid-stanza after a `Send`.
+    Disconnect,
+}
+
+/// The fake server's script.
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn expect(mut self, text: &str) -> Self {
+        self.steps.push(Step::Expect(text.to_string()));
+        self
+    }
+
+    pub fn send(mut self, data: &str) -> Self {
+        self.steps.push(Step::Send(data.to_string()));
+        self
+    }
+
+    pub fn trickle(mut self, data: &str, chunk: usize, delay: Duration) -> Self {
+        self.steps.push(Step::Trickle {
+            data: data.to_string(),
+            chunk: chunk.max(1),
+            delay,
+        });
+        self
+    }
+
+    pub fn stall(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Stall(duration));
+        self
+    }
+
+    pub fn disconnect(mut self) -> Self {
+        self.steps.push(Step::Disconnect);
+        self
+    }
+
+    /// A server that opens a client stream after the client's header.
+    pub fn stream_opened(self) -> Self {
+        self.expect("<stream:stream").send(
+            "<?xml version='1.0'?><stream:stream xmlns='jabber:client' \
+             xmlns:stream='http://etherx.jabber.org/streams' from='example.org' \
+             id='scripted' version='1.0'>",
+        )
+    }
+
+    /// Play the script on `stream`. Returns everything the client sent, or
+    /// the step that failed.
+    pub async fn play<S>(self, mut stream: S) -> Result<String, String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut received = Vec::new();
+        let mut consumed = 0;
+        let mut read_buf = [0u8; 4096];
+        for step in self.steps {
+            match step {
+                Step::Expect(text) => {
+                    let wait = async {
+                        loop {
+                            let unseen = &received[consumed..];
+                            if let Some(at) = unseen
+                                .windows(text.len())
+                                .position(|window| window == text.as_bytes())
+                            {
+                                consumed += at + text.len();
+                                return Ok(());
+                            }
+                            match stream.read(&mut read_buf).await {
+                                Ok(0) => return Err(format!("client closed before {text}")),
+                                Ok(n) => received.extend_from_slice(&read_buf[..n]),
+                                Err(e) => return Err(format!("read failed before {text}: {e}")),
+                            }
+                        }
+                    };
+                    tokio::time::timeout(EXPECT_TIMEOUT, wait)
+                        .await
+                        .map_err(|_| format!("timed out expecting {text}"))??;
+                }
+                Step::Send(data) => stream
+                    .write_all(data.as_bytes())
+                    .await
+                    .map_err(|e| format!("write failed: {e}"))?,
+                Step::Trickle { data, chunk, delay } => {
+                    for piece in data.as_bytes().chunks(chunk) {
+                        stream
+                            .write_all(piece)
+                            .await
+                            .map_err(|e| format!("write failed: {e}"))?;
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+                Step::Stall(duration) => tokio::time::sleep(duration).await,
+                Step::Disconnect => {
+                    let _ = stream.shutdown().await;
+                    break;
+                }
+            }
+        }
+        Ok(String::from_utf8_lossy(&received).into_owned())
+    }
+
+    /// Play the script for the first connection to a loopback listener.
+    pub async fn listen(self) -> (SocketAddr, JoinHandle<Result<String, String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind scripted server");
+        let addr = listener.local_addr().expect("scripted server address");
+        let task = tokio::spawn(async move {
+            let (stream, _) = listener
+                .accept()
+                .await
+                .map_err(|e| format!("accept failed: {e}"))?;
+            self.play(stream).await
+        });
+        (addr, task)
+    }
+}
+
+/// A client WebSocket bridged to a scripted server.
+pub struct BridgeHarness {
+    /// The client end, as the WebView would hold it.
+    pub ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// The bridge's own result once it ends.
+    pub bridge: JoinHandle<Result<(), String>>,
+    /// The script's result: what the client sent upstream.
+    pub server: JoinHandle<Result<String, String>>,
+    /// Fires the proxy shutdown signal.
+    pub shutdown: tokio::sync::broadcast::Sender<()>,
+}
+
+impl BridgeHarness {
+    /// Start the bridge between a fresh WebSocket client and `script`, with
+    /// `initial` as the client frames buffered before the bridge started.
+    pub async fn start(script: Script, initial: Vec<String>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind harness WebSocket listener");
+        let url = format!("ws://{}", listener.local_addr().expect("listener address"));
+        let (upstream, server_end) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(script.play(server_end));
+        let (shutdown, _) = tokio::sync::broadcast::channel(1);
+        let bridge_shutdown = shutdown.subscribe();
+
+        let bridge = tokio::spawn(async move {
+            let (stream, _) = listener
+                .accept()
+                .await
+                .map_err(|e| format!("accept failed: {e}"))?;
+            let ws = tokio_tungstenite::accept_async(stream)
+                .await
+                .map_err(|e| format!("WebSocket handshake failed: {e}"))?;
+            let conn_id = NEXT_PROXY_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+            let supervisor = Arc::new(ConnectionSupervisor::new(conn_id, None));
+            bridge_websocket_tls(
+                ws,
+                upstream,
+                bridge_shutdown,
+                None,
+                initial,
+                conn_id,
+                supervisor,
+            )
+            .await
+        });
+        let (ws, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .expect("connect harness WebSocket client");
+        Self {
+            ws,
+            bridge,
+            server,
+            shutdown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::perform_starttls;
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    const OPEN: &str = "<open xmlns='urn:ietf:params:xml:ns:xmpp-framing' \
+                        to='example.org' version='1.0'/>";
+
+    /// Text frames until the bridge closes the WebSocket.
+    async fn frames_until_close(harness: &mut BridgeHarness) -> Vec<String> {
+        let mut frames = Vec::new();
+        while let Ok(Some(Ok(msg))) =
+            tokio::time::timeout(Duration::from_secs(5), harness.ws.next()).await
+        {
+            match msg {
+                Message::Text(text) => frames.push(text.to_string()),
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn trickled_stanzas_are_reassembled_and_garbage_is_survived() {
+        let message = "<message from='romeo@example.org/a' id='m1'><body>hi</body></message>";
+        let script = Script::new()
+            .stream_opened()
+            .trickle(message, 7, Duration::from_millis(5))
+            // Garbage the parser can't frame stays buffered rather than
+            // crashing the bridge, and later disconnects end it cleanly.
+            .send("<<>>not xml")
+            .expect("<presence")
+            .disconnect();
+        let mut harness = BridgeHarness::start(script, vec![OPEN.to_string()]).await;
+        harness
+            .ws
+            .send(Message::Text("<presence/>".into()))
+            .await
+            .unwrap();
+
+        let frames = frames_until_close(&mut harness).await;
+        assert!(frames[0].starts_with("<open"));
+        assert_eq!(frames[1], message);
+        assert!(frames.last().unwrap().starts_with("<close"));
+        let sent = harness.server.await.unwrap().unwrap();
+        assert!(sent.contains("<stream:stream"));
+        assert!(harness.bridge.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn mid_stanza_disconnect_closes_the_client() {
+        let script = Script::new()
+            .stream_opened()
+            .send("<message id='cut'><body>half a sta")
+            .disconnect();
+        let mut harness = BridgeHarness::start(script, vec![OPEN.to_string()]).await;
+        let frames = frames_until_close(&mut harness).await;
+        assert!(frames.iter().all(|frame| !frame.contains("cut")));
+        assert!(frames.last().unwrap().starts_with("<close"));
+    }
+
+    #[tokio::test]
+    async fn starttls_failures_are_reported() {
+        let (addr, server) = Script::new()
+            .stream_opened()
+            .send(
+                "<stream:features><starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>\
+                 </stream:features>",
+            )
+            .expect("<starttls")
+            .send("<failure xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>")
+            .listen()
+            .await;
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let err = perform_starttls(tcp, "example.org", "127.0.0.1")
+            .await
+            .err()
+            .unwrap();
+        assert!(err.contains("rejected STARTTLS"), "{err}");
+        assert!(server.await.unwrap().is_ok());
+    }
+}
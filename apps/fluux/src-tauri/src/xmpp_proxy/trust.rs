@@ -0,0 +1,351 @@
+//! Extra root certificates trusted alongside the system store.
+//!
+//! Servers on corporate networks or in labs often use a private CA the OS
+//! does not know about. Rather than turning verification off with
+//! `--dangerous-insecure-tls`, the user adds the CA's certificate here; it is
+//! merged into the root store of every outbound TLS connection (see
+//! `tls_client_config`) and persisted in `trusted-cas.json`. Certificates are
+//! identified by the SHA-256 fingerprint of their DER encoding, written as
+//! lowercase hex.
+//!
+//! The added roots apply to every account: rustls has one root store per
+//! connector, and the proxy does not know which account a connection is for
+//! until SASL.
+//!
+//! The merged store is built once and cached ([`roots`]): loading the system
+//! certificates takes tens of milliseconds and used to happen on every
+//! connection. [`watch`] rebuilds it when the system store changes, so an
+//! enterprise certificate rollout applies without a restart. On Linux and
+//! the BSDs the store is a set of files and their modification times are
+//! polled; macOS and Windows keep it in the keychain and registry, which are
+//! simply reloaded periodically. `reload_trust_store` forces a rebuild.
+
+use crate::error::FluuxError;
+use crate::json_file::JsonFile;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use rustls::RootCertStore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// How often [`watch`] looks for changes to the system store.
+const WATCH_INTERVAL: Duration = Duration::from_secs(60);
+/// Where the store is not a set of files, reload it this often anyway.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const PERIODIC_RELOAD: Duration = Duration::from_secs(60 * 60);
+
+static TRUSTED: Mutex<Vec<TrustedCa>> = Mutex::new(Vec::new());
+static FILE: OnceLock<JsonFile> = OnceLock::new();
+/// The merged root store, built on first use.
+static ROOTS: RwLock<Option<Arc<RootCertStore>>> = RwLock::new(None);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrustedCa {
+    fingerprint: String,
+    /// The certificate alone, PEM-encoded (bundles are split on add).
+    pem: String,
+}
+
+fn trusted() -> std::sync::MutexGuard<'static, Vec<TrustedCa>> {
+    TRUSTED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Load the persisted certificates from `dir`. Called from the Tauri `setup`
+/// hook.
+pub fn load(dir: Option<PathBuf>) {
+    let Some(path) = dir.map(|d| d.join("trusted-cas.json")) else {
+        return;
+    };
+    let cas: Vec<TrustedCa> = std::fs::read(&path)
+        .ok()
+        .and_then(|bytes| match serde_json::from_slice(&bytes) {
+            Ok(cas) => Some(cas),
+            Err(e) => {
+                warn!(error = %e, "trusted CAs: discarding unreadable file");
+                None
+            }
+        })
+        .unwrap_or_default();
+    if !cas.is_empty() {
+        info!(count = cas.len(), "Trusting additional root certificates");
+    }
+    *trusted() = cas;
+    invalidate();
+    let _ = FILE.set(JsonFile::pretty(path));
+}
+
+/// Called with the `TRUSTED` lock held; see [`crate::json_file`].
+fn persist(cas: Vec<TrustedCa>) {
+    if let Some(file) = FILE.get() {
+        file.save(cas);
+    }
+}
+
+/// Parse every certificate in a PEM bundle, checking each is usable as a
+/// trust anchor.
+fn parse_bundle(pem: &str) -> Result<Vec<TrustedCa>, String> {
+    let mut cas = Vec::new();
+    for cert in CertificateDer::pem_slice_iter(pem.as_bytes()) {
+        let cert = cert.map_err(|e| format!("Invalid PEM: {e}"))?;
+        RootCertStore::empty()
+            .add(cert.clone())
+            .map_err(|e| format!("Not a usable CA certificate: {e}"))?;
+        let single = pem_encode(&cert);
+        cas.push(TrustedCa {
+            fingerprint: fingerprint(&cert),
+            pem: single,
+        });
+    }
+    if cas.is_empty() {
+        return Err("No certificate found in the PEM data".to_string());
+    }
+    Ok(cas)
+}
+
+fn pem_encode(der: &[u8]) -> String {
+    let body = BASE64.encode(der);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in body.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Add the user's extra roots to `store`. Returns how many were added.
+fn add_to(store: &mut RootCertStore) -> usize {
+    let mut added = 0;
+    for ca in trusted().iter() {
+        match CertificateDer::from_pem_slice(ca.pem.as_bytes()) {
+            Ok(cert) if store.add(cert).is_ok() => added += 1,
+            _ => warn!(fingerprint = %ca.fingerprint, "Skipping unusable trusted CA"),
+        }
+    }
+    added
+}
+
+/// System roots plus the user's, freshly loaded.
+fn build_roots() -> Result<RootCertStore, String> {
+    let mut store = RootCertStore::empty();
+    let native_certs = rustls_native_certs::load_native_certs();
+    for error in &native_certs.errors {
+        warn!(error = %error, "Error loading system root certificates");
+    }
+    let user_roots = add_to(&mut store);
+    if native_certs.certs.is_empty() && user_roots == 0 {
+        return Err(
+            "No system root certificates found. TLS connections will fail. \
+            Ensure CA certificates are installed (e.g., ca-certificates package on Linux)."
+                .to_string(),
+        );
+    }
+    for cert in native_certs.certs {
+        store
+            .add(cert)
+            .map_err(|e| format!("Failed to add cert: {}", e))?;
+    }
+    Ok(store)
+}
+
+/// The root store for outbound TLS, loading it on first use.
+pub(crate) fn roots() -> Result<Arc<RootCertStore>, String> {
+    if let Some(store) = ROOTS.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return Ok(store.clone());
+    }
+    reload()
+}
+
+/// Rebuild the cached root store. Connections already up keep the store
+/// they were verified with.
+fn reload() -> Result<Arc<RootCertStore>, String> {
+    let store = Arc::new(build_roots()?);
+    *ROOTS.write().unwrap_or_else(|e| e.into_inner()) = Some(store.clone());
+    Ok(store)
+}
+
+/// Drop the cached store so the next connection rebuilds it.
+fn invalidate() {
+    *ROOTS.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Files and directories the system store is read from on this platform,
+/// following the OpenSSL conventions `rustls-native-certs` uses.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn store_sources() -> Vec<PathBuf> {
+    let mut sources: Vec<PathBuf> = ["SSL_CERT_FILE", "SSL_CERT_DIR"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .flat_map(|value| std::env::split_paths(&value).collect::<Vec<_>>())
+        .collect();
+    sources.extend(
+        [
+            "/etc/ssl/certs",
+            "/etc/ssl/certs/ca-certificates.crt",
+            "/etc/ssl/cert.pem",
+            "/etc/ssl/ca-bundle.pem",
+            "/etc/pki/tls/certs/ca-bundle.crt",
+            "/etc/pki/ca-trust/extracted/pem/tls-ca-bundle.pem",
+        ]
+        .into_iter()
+        .map(PathBuf::from),
+    );
+    sources
+}
+
+/// Modification times of the store's sources; a change means a reload.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn store_stamp() -> Vec<Option<SystemTime>> {
+    store_sources()
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Where the store is not files, the stamp is the current reload period, so
+/// it changes every [`PERIODIC_RELOAD`].
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn store_stamp() -> Vec<Option<SystemTime>> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let period = now.as_secs() / PERIODIC_RELOAD.as_secs();
+    vec![Some(
+        SystemTime::UNIX_EPOCH + Duration::from_secs(period * PERIODIC_RELOAD.as_secs()),
+    )]
+}
+
+/// Rebuild the root store whenever the system store changes. Called once
+/// from the Tauri `setup` hook.
+pub fn watch() {
+    tauri::async_runtime::spawn(async {
+        let mut stamp = tauri::async_runtime::spawn_blocking(store_stamp)
+            .await
+            .unwrap_or_default();
+        loop {
+            tokio::time::sleep(WATCH_INTERVAL).await;
+            let current = tauri::async_runtime::spawn_blocking(store_stamp)
+                .await
+                .unwrap_or_default();
+            if current == stamp {
+                continue;
+            }
+            stamp = current;
+            match tauri::async_runtime::spawn_blocking(reload).await {
+                Ok(Ok(store)) => info!(roots = store.len(), "System trust store changed; reloaded"),
+                Ok(Err(e)) => warn!(error = %e, "Failed to reload trust store"),
+                Err(e) => warn!(error = %e, "Trust store reload task failed"),
+            }
+        }
+    });
+}
+
+/// Reload the system and user root certificates now. Returns how many
+/// roots are trusted.
+#[tauri::command]
+pub async fn reload_trust_store() -> Result<usize, FluuxError> {
+    let store = tauri::async_runtime::spawn_blocking(reload)
+        .await
+        .map_err(|e| FluuxError::Internal(e.to_string()))?
+        .map_err(FluuxError::Internal)?;
+    info!(roots = store.len(), "Trust store reloaded");
+    Ok(store.len())
+}
+
+/// Trust the certificates in `pem` (one or a bundle) for outbound TLS from
+/// the next connection on. Returns their fingerprints; certificates already
+/// trusted are not added twice.
+#[tauri::command]
+pub fn add_trusted_ca(pem: String) -> Result<Vec<String>, FluuxError> {
+    let parsed = parse_bundle(&pem).map_err(FluuxError::InvalidInput)?;
+    let fingerprints = parsed.iter().map(|ca| ca.fingerprint.clone()).collect();
+    {
+        let mut cas = trusted();
+        for ca in parsed {
+            if !cas.iter().any(|known| known.fingerprint == ca.fingerprint) {
+                info!(fingerprint = %ca.fingerprint, "Trusting root certificate");
+                cas.push(ca);
+            }
+        }
+        persist(cas.clone());
+    }
+    invalidate();
+    Ok(fingerprints)
+}
+
+/// Stop trusting the certificate with this SHA-256 `fingerprint` (hex,
+/// colons and case ignored).
+#[tauri::command]
+pub fn remove_trusted_ca(fingerprint: String) -> Result<(), FluuxError> {
+    let wanted: String = fingerprint
+        .chars()
+        .filter(|c| *c != ':')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    {
+        let mut cas = trusted();
+        let before = cas.len();
+        cas.retain(|ca| ca.fingerprint != wanted);
+        if cas.len() == before {
+            return Err(FluuxError::InvalidInput(format!(
+                "No trusted certificate with fingerprint {fingerprint}"
+            )));
+        }
+        persist(cas.clone());
+    }
+    info!(fingerprint = %wanted, "Removed trusted root certificate");
+    invalidate();
+    Ok(())
+}
+
+/// Fingerprints of the certificates added with [`add_trusted_ca`].
+#[tauri::command]
+pub fn list_trusted_cas() -> Vec<String> {
+    trusted().iter().map(|ca| ca.fingerprint.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_input_without_certificates() {
+        assert!(parse_bundle("").is_err());
+        assert!(
+            parse_bundle("-----BEGIN CERTIFICATE-----\n!!!\n-----END CERTIFICATE-----\n").is_err()
+        );
+    }
+
+    #[test]
+    fn root_store_is_cached_until_reloaded() {
+        let Ok(first) = roots() else {
+            return; // No system certificates in this environment.
+        };
+        assert!(Arc::ptr_eq(&first, &roots().unwrap()));
+        let reloaded = reload().unwrap();
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+        assert_eq!(first.len(), reloaded.len());
+    }
+
+    #[test]
+    fn fingerprints_are_lowercase_sha256_hex() {
+        // SHA-256 of the empty string.
+        assert_eq!(
+            fingerprint(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
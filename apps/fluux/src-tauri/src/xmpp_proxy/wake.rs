@@ -0,0 +1,63 @@
+//! Checking the upstream connection as soon as the system wakes.
+//!
+//! A TCP connection rarely survives a laptop's sleep: the server gave up on
+//! it, or the network changed meanwhile. Nothing noticed until a write timed
+//! out or the WebView's keepalive gave up, so after a wake the UI showed
+//! "reconnecting" for several seconds before reconnecting at all. Now the
+//! bridge carrying the session pings the server on every wake (see
+//! `power::subscribe_wakes`) and, when no answer comes within
+//! [`WAKE_PROBE_TIMEOUT`], ends at once as `UpstreamLostInSleep`. Its
+//! `proxy-connection-closed` event says whether the XEP-0198 session is
+//! resumable; the client then resumes it from its stored stream-management
+//! state on the next connection instead of logging in again.
+//!
+//! The resumption itself stays with the client: the resumed stream has to
+//! authenticate first, and SASL is the client's.
+
+use super::session;
+use super::stanza::Element;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// How long the server has to answer the ping after a wake. A live
+/// connection answers within a round trip.
+const WAKE_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const PING_NS: &str = "urn:xmpp:ping";
+
+/// Return once the upstream of bridge `conn_id` is found dead after a wake.
+pub(super) async fn upstream_lost(conn_id: u64) {
+    let mut wakes = crate::power::subscribe_wakes();
+    while wakes.changed().await.is_ok() {
+        if !session::carries(conn_id) {
+            continue;
+        }
+        let started = Instant::now();
+        let ping = Element::new("iq")
+            .with_attr("type", "get")
+            .with_child(Element::new("ping").with_attr("xmlns", PING_NS));
+        match tokio::time::timeout(WAKE_PROBE_TIMEOUT, session::exchange(ping)).await {
+            // Any answer, an error included, proves the connection alive.
+            Ok(Ok(_)) => debug!(
+                conn_id,
+                probe_ms = started.elapsed().as_millis() as u64,
+                "Upstream answered after wake"
+            ),
+            // Not ready or already closing: nothing to check.
+            Ok(Err(e)) => debug!(conn_id, error = %e, "Wake probe not sent"),
+            Err(_) => {
+                warn!(
+                    conn_id,
+                    timeout_ms = WAKE_PROBE_TIMEOUT.as_millis() as u64,
+                    resumable = session::is_resumable(),
+                    "Upstream did not answer after wake"
+                );
+                return;
+            }
+        }
+        // Wakes reported while probing (the notification and the keepalive's
+        // sleep gap) are covered by this probe.
+        wakes.borrow_and_update();
+    }
+    std::future::pending().await
+}
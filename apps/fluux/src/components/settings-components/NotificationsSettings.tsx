@@ -14,6 +14,7 @@ import { Toggle } from '@/components/ui/Toggle'
 import { useSettingsStore } from '@/stores/settingsStore'
 import { isLinux, isWindows } from '@/utils/tauri'
 import { getTrayStatus, type TrayStatus } from '@/utils/windowBehavior'
+import { toCommandError } from '@/utils/tauriError'
 
 type NotificationStatus = 'checking' | 'granted' | 'denied' | 'default' | 'unavailable'
 
@@ -54,7 +55,7 @@ async function openNotificationSettings(): Promise<boolean> {
     // Linux has no single control center: the command tries each desktop's
     // settings binary and fails when none is installed. Swallowing that left
     // the button silently dead (#1072), so the caller surfaces it.
-    console.error('[Settings] Failed to open notification settings:', error)
+    console.error('[Settings] Failed to open notification settings:', toCommandError(error))
     return false
   }
 }
@@ -7,6 +7,7 @@ import { rebuildSearchIndex } from '@fluux/sdk'
 import type { RebuildProgress } from '@fluux/sdk'
 import { SettingsSection } from '@/components/ui/SettingsSection'
 import { isTauri } from '@/utils/tauri'
+import { toCommandError } from '@/utils/tauriError'
 
 export function StorageSettings() {
   const { t } = useTranslation()
@@ -59,7 +60,7 @@ export function StorageSettings() {
       const { invoke } = await import('@tauri-apps/api/core')
       await invoke('open_logs_folder')
     } catch (error) {
-      console.error('[StorageSettings] Failed to open logs folder:', error)
+      console.error('[StorageSettings] Failed to open logs folder:', toCommandError(error))
     }
   }
 
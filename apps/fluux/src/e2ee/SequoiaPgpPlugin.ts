@@ -15,6 +15,12 @@
  */
 
 import { E2EEPluginError } from '@fluux/sdk'
+import type {
+  ConversationHandle,
+  DecryptResult,
+  EncryptedPayload,
+  InboundDecryptContext,
+} from '@fluux/sdk'
 import {
   OpenPGPPluginBase,
   type CertValidation,
@@ -48,6 +54,29 @@ export class SequoiaPgpPlugin extends OpenPGPPluginBase {
     return 'SequoiaPgpPlugin'
   }
 
+  /**
+   * Decrypt, then hand live received messages to the Rust word watch: the
+   * bridge only sees the fallback body of an encrypted message. Archive
+   * replays, retries and our own messages are not scanned.
+   */
+  async decrypt(
+    handle: ConversationHandle,
+    payload: EncryptedPayload,
+    context?: InboundDecryptContext,
+  ): Promise<DecryptResult> {
+    const result = await super.decrypt(handle, payload, context)
+    if (result.plaintext && !context?.isSelfOutgoing && !context?.fromArchive && !context?.fromRetry) {
+      this.invoke('scan_decrypted_message', {
+        from: result.senderDevice.jid,
+        messageId: context?.messageId ?? null,
+        payloadXml: new TextDecoder().decode(result.plaintext),
+      }).catch(() => {
+        // Best effort: a failed scan must not fail the decrypt.
+      })
+    }
+    return result
+  }
+
   // ---------------------------------------------------------------------------
   // Abstract crypto method implementations (Rust via Tauri IPC)
   // ---------------------------------------------------------------------------
@@ -119,6 +119,7 @@ describe('useDesktopNotifications posting + guard', () => {
       navTarget: 'team@conf.example.com',
       messageId: 'room-message-1',
       accountId: 'me@example.com',
+      mentioned: false,
       avatarPath: null,
     })
     expect(sendNotification).not.toHaveBeenCalled()
@@ -269,6 +269,8 @@ export function useDesktopNotifications(): void {
           navTarget: room.jid,
           messageId: message.id,
           accountId,
+          // For the native rules engine's mention-only rooms.
+          mentioned: Boolean(message.isMention || message.isMentionAll),
           avatarPath: avatarUrl?.startsWith('file://') ? avatarUrl.replace(/^file:\/\//, '') : null,
         })
       } else {
@@ -3,6 +3,7 @@ import type { XMPPClient } from '@fluux/sdk/core'
 import { invoke } from '@tauri-apps/api/core'
 import { listen } from '@tauri-apps/api/event'
 import { isTauri } from '@/utils/tauri'
+import { toCommandError } from '@/utils/tauriError'
 import { useMcpBridgeStore, type McpActivityEntry } from '@/stores/mcpBridgeStore'
 import { listConversations, getHistory, sendMessageTool, type McpToolName } from '@/utils/mcpTools'
 
@@ -76,6 +77,8 @@ export async function resetMcpToken(): Promise<void> {
   const info = await enqueueServerOp(() =>
     invoke<{ port: number; token: string }>('mcp_reset_token', {
       preferredPort: useMcpBridgeStore.getState().preferredPort,
+    }).catch((error: unknown) => {
+      throw toCommandError(error)
     })
   )
   setServerInfo({ port: info.port, token: info.token })
@@ -115,8 +118,9 @@ export function useMcpBridge(client: XMPPClient): void {
             preferredPort: useMcpBridgeStore.getState().preferredPort,
           })
         )
-      } catch {
+      } catch (error) {
         // Server failed to start (e.g. bind error) — nothing to listen for.
+        console.warn('[MCP] Server failed to start:', toCommandError(error))
         return
       }
       if (cancelled) return
@@ -7,6 +7,9 @@ const mockState = {
   conversations: new Map<string, unknown>(),
   activeConversationId: null as string | null,
   roomsWithUnreadCount: 0,
+  // Rooms run through the real selector, on top of roomsWithUnreadCount
+  roomEntities: new Map<string, unknown>(),
+  roomMeta: new Map<string, unknown>(),
   pendingCount: 0,
 }
 
@@ -27,6 +30,20 @@ vi.mock('@tauri-apps/api/window', () => ({
   }),
 }))
 
+const mockInvoke = vi.fn((..._args: unknown[]) => Promise.resolve())
+vi.mock('@tauri-apps/api/core', () => ({
+  invoke: (...args: unknown[]) => mockInvoke(...args),
+}))
+
+const mockSetWebAppBadge = vi.fn()
+vi.mock('@/utils/appBadge', () => ({
+  setWebAppBadge: (count: number) => mockSetWebAppBadge(count),
+}))
+
+// Listeners registered on the vanilla chat store, to simulate store changes
+type Listener = (selected: unknown, previous: unknown) => void
+const chatListeners: Array<{ selector: (s: unknown) => unknown; listener: Listener }> = []
+
 // Mock the SDK - use inline functions that read from mockState
 vi.mock('@fluux/sdk', async (importOriginal) => {
   const actual = await importOriginal<typeof import('@fluux/sdk')>()
@@ -41,49 +58,63 @@ vi.mock('@fluux/sdk', async (importOriginal) => {
         conversations: mockState.conversations,
         activeConversationId: mockState.activeConversationId,
       }),
-      subscribe: vi.fn(() => () => {}),
+      subscribe: vi.fn((selector: (s: unknown) => unknown, listener: Listener) => {
+        chatListeners.push({ selector, listener })
+        return () => {}
+      }),
     },
     roomStore: {
-      getState: () => ({}),
+      getState: () => ({ activeRoomJid: null }),
+      subscribe: vi.fn(() => () => {}),
+    },
+    connectionStore: {
+      getState: () => ({ windowVisible: true }),
       subscribe: vi.fn(() => () => {}),
     },
   }
 })
 
 // Mock React store hooks (from @fluux/sdk/react)
-vi.mock('@fluux/sdk/react', () => ({
-  useChatStore: Object.assign(
-    (selector: (s: unknown) => unknown) => {
-      const state = {
-        conversations: mockState.conversations,
-        activeConversationId: mockState.activeConversationId,
+vi.mock('@fluux/sdk/react', async () => {
+  const { roomSelectors } = await vi.importActual<typeof import('@fluux/sdk')>('@fluux/sdk')
+  const roomsWithUnreadCount = () =>
+    mockState.roomsWithUnreadCount +
+    roomSelectors.roomsWithUnreadCount({
+      roomEntities: mockState.roomEntities,
+      roomMeta: mockState.roomMeta,
+    } as unknown as Parameters<typeof roomSelectors.roomsWithUnreadCount>[0])
+  return {
+    useChatStore: Object.assign(
+      (selector: (s: unknown) => unknown) => {
+        const state = {
+          conversations: mockState.conversations,
+          activeConversationId: mockState.activeConversationId,
+        }
+        return selector(state)
+      },
+      {
+        getState: () => ({
+          conversations: mockState.conversations,
+          activeConversationId: mockState.activeConversationId,
+        }),
+        subscribe: vi.fn(() => () => {}),
       }
-      return selector(state)
-    },
-    {
-      getState: () => ({
-        conversations: mockState.conversations,
-        activeConversationId: mockState.activeConversationId,
-      }),
-      subscribe: vi.fn(() => () => {}),
-    }
-  ),
-  useRoomStore: Object.assign(
-    (selector: (s: unknown) => unknown) => {
-      const state = {
-        roomsWithUnreadCount: () => mockState.roomsWithUnreadCount,
+    ),
+    useRoomStore: Object.assign(
+      (selector: (s: unknown) => unknown) => {
+        const state = { roomsWithUnreadCount }
+        return selector(state)
+      },
+      {
+        getState: () => ({}),
+        subscribe: vi.fn(() => () => {}),
       }
-      return selector(state)
-    },
-    {
-      getState: () => ({}),
-      subscribe: vi.fn(() => () => {}),
-    }
-  ),
-  useConnectionStore: (selector: (s: { status: string }) => unknown) =>
-    selector({ status: 'online' }),
-  useContactTime: () => null, useLastActivity: vi.fn(),
-}))
+    ),
+    useConnectionStore: (selector: (s: { status: string }) => unknown) =>
+      selector({ status: 'online' }),
+    useContactTime: () => null, useLastActivity: vi.fn(),
+  }
+})
 
 // Helper to create a conversation and add to mock data
 function setMockConversations(conversations: Array<{
@@ -98,21 +129,95 @@ describe('useNotificationBadge', () => {
   beforeEach(() => {
     vi.clearAllMocks()
     mockSetBadgeCount.mockClear()
+    chatListeners.length = 0
 
     // Reset all mock state
     mockState.conversations = new Map()
     mockState.activeConversationId = null
     mockState.roomsWithUnreadCount = 0
+    mockState.roomEntities = new Map()
+    mockState.roomMeta = new Map()
     mockState.pendingCount = 0
 
-    // Mock Tauri detection
-    // @ts-expect-error - mocking Tauri internals
-    window.__TAURI_INTERNALS__ = {}
+    // jsdom has no canvas; the favicon badge then only tracks the count
+    vi.spyOn(HTMLCanvasElement.prototype, 'getContext').mockReturnValue(null)
   })
 
   afterEach(() => {
-    // @ts-expect-error - cleaning up Tauri mock
-    delete window.__TAURI_INTERNALS__
+    vi.restoreAllMocks()
+  })
+
+  describe('in Tauri', () => {
+    beforeEach(() => {
+      // @ts-expect-error - mocking Tauri internals
+      window.__TAURI_INTERNALS__ = {}
+    })
+
+    afterEach(() => {
+      // @ts-expect-error - cleaning up Tauri mock
+      delete window.__TAURI_INTERNALS__
+    })
+
+    it('sets the dock badge from the stores', async () => {
+      setMockConversations([{ id: 'user@example.com', unreadCount: 1 }])
+      mockState.activeConversationId = 'bob@example.com'
+
+      renderHook(() => useNotificationBadge())
+
+      await vi.waitFor(() => {
+        expect(mockSetBadgeCount).toHaveBeenCalledWith(1)
+      })
+      expect(mockInvoke).toHaveBeenCalledWith('set_active_conversation', {
+        conversationId: 'bob@example.com',
+      })
+      expect(mockSetWebAppBadge).not.toHaveBeenCalled()
+    })
+
+    it('does not raise the dock badge for a plain message in a normal room', async () => {
+      const joined = { joined: true }
+      mockState.roomEntities = new Map([
+        ['chat@conference.example.com', joined],
+        ['team@conference.example.com', joined],
+      ])
+      mockState.roomMeta = new Map([
+        // A non-mention message in a room without notify-all
+        ['chat@conference.example.com', { unreadCount: 1, mentionsCount: 0, notifyAll: false }],
+        ['team@conference.example.com', { unreadCount: 1, mentionsCount: 1, notifyAll: false }],
+      ])
+
+      renderHook(() => useNotificationBadge())
+
+      await vi.waitFor(() => {
+        expect(mockSetBadgeCount).toHaveBeenCalledWith(1) // only the mention
+      })
+      expect(mockSetBadgeCount).not.toHaveBeenCalledWith(2)
+    })
+
+    it('reports conversations the store marks read', () => {
+      renderHook(() => useNotificationBadge())
+      const meta = chatListeners.find(({ selector }) =>
+        selector({ conversationMeta: 'meta' }) === 'meta'
+      )!
+      const pointer = { messageId: 'm2' }
+
+      meta.listener(
+        new Map([
+          ['alice@example.com', { unreadCount: 0, readPointer: pointer }],
+          ['bob@example.com', { unreadCount: 2 }],
+          ['carol@example.com', { unreadCount: 0 }],
+        ]),
+        new Map([
+          ['alice@example.com', { unreadCount: 3 }],
+          ['bob@example.com', { unreadCount: 1 }],
+          ['carol@example.com', { unreadCount: 0 }],
+        ])
+      )
+
+      expect(mockInvoke).toHaveBeenCalledWith('mark_conversation_read', {
+        conversationId: 'alice@example.com',
+      })
+      expect(mockInvoke).toHaveBeenCalledTimes(2) // set_active_conversation + alice
+    })
   })
 
   describe('store-driven unread tracking', () => {
@@ -126,7 +231,7 @@ describe('useNotificationBadge', () => {
       renderHook(() => useNotificationBadge())
 
       await vi.waitFor(() => {
-        expect(mockSetBadgeCount).toHaveBeenCalledWith(1)
+        expect(mockSetWebAppBadge).toHaveBeenCalledWith(1)
       })
     })
 
@@ -140,7 +245,7 @@ describe('useNotificationBadge', () => {
       renderHook(() => useNotificationBadge())
 
       await vi.waitFor(() => {
-        expect(mockSetBadgeCount).toHaveBeenCalledWith(undefined)
+        expect(mockSetWebAppBadge).toHaveBeenCalledWith(0)
       })
     })
   })
@@ -155,7 +260,7 @@ describe('useNotificationBadge', () => {
       renderHook(() => useNotificationBadge())
 
       await vi.waitFor(() => {
-        expect(mockSetBadgeCount).toHaveBeenCalledWith(2) // 2 conversations with unread
+        expect(mockSetWebAppBadge).toHaveBeenCalledWith(2) // 2 conversations with unread
       })
     })
 
@@ -165,7 +270,7 @@ describe('useNotificationBadge', () => {
       renderHook(() => useNotificationBadge())
 
       await vi.waitFor(() => {
-        expect(mockSetBadgeCount).toHaveBeenCalledWith(3)
+        expect(mockSetWebAppBadge).toHaveBeenCalledWith(3)
       })
     })
 
@@ -175,7 +280,7 @@ describe('useNotificationBadge', () => {
       renderHook(() => useNotificationBadge())
 
       await vi.waitFor(() => {
-        expect(mockSetBadgeCount).toHaveBeenCalledWith(2)
+        expect(mockSetWebAppBadge).toHaveBeenCalledWith(2)
       })
     })
 
@@ -189,7 +294,7 @@ describe('useNotificationBadge', () => {
       renderHook(() => useNotificationBadge())
 
       await vi.waitFor(() => {
-        expect(mockSetBadgeCount).toHaveBeenCalledWith(6) // 1 + 2 + 3
+        expect(mockSetWebAppBadge).toHaveBeenCalledWith(6) // 1 + 2 + 3
       })
     })
 
@@ -201,7 +306,7 @@ describe('useNotificationBadge', () => {
       renderHook(() => useNotificationBadge())
 
       await vi.waitFor(() => {
-        expect(mockSetBadgeCount).toHaveBeenCalledWith(undefined)
+        expect(mockSetWebAppBadge).toHaveBeenCalledWith(0)
       })
     })
   })
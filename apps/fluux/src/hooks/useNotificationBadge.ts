@@ -1,5 +1,6 @@
 import { useEffect, useRef } from 'react'
-import { useEvents, computeBadgeCount } from '@fluux/sdk'
+import { invoke } from '@tauri-apps/api/core'
+import { useEvents, computeBadgeCount, chatStore, roomStore, connectionStore } from '@fluux/sdk'
 import { useChatStore, useRoomStore } from '@fluux/sdk/react'
 import { notificationDebug } from '@/utils/notificationDebug'
 import { setWebAppBadge } from '@/utils/appBadge'
@@ -23,6 +24,46 @@ async function setTauriBadge(count: number): Promise<void> {
   }
 }
 
+type UnreadMeta = Map<string, { unreadCount: number; readPointer?: unknown }>
+
+/**
+ * Keep the native unread counters (behind `get_unread_summary` and the Linux
+ * D-Bus properties) in step with what the user sees: the conversation or room
+ * on screen while the window is focused, and every conversation the stores
+ * mark read, whichever path did it. Returns the unsubscribe function.
+ */
+function syncNativeUnread(): () => void {
+  let active: string | null | undefined
+  const reportActive = () => {
+    const next = connectionStore.getState().windowVisible
+      ? (chatStore.getState().activeConversationId ?? roomStore.getState().activeRoomJid)
+      : null
+    if (next === active) return
+    active = next
+    invoke('set_active_conversation', { conversationId: next }).catch(() => undefined)
+  }
+  const reportRead = (meta: UnreadMeta, previous: UnreadMeta) => {
+    for (const [conversationId, { unreadCount, readPointer }] of meta) {
+      const before = previous.get(conversationId)
+      // Read here or on another device (the read pointer moved to the end).
+      const read = (before?.unreadCount ?? 0) > 0 || readPointer !== before?.readPointer
+      if (unreadCount === 0 && read) {
+        invoke('mark_conversation_read', { conversationId }).catch(() => undefined)
+      }
+    }
+  }
+
+  const unsubscribes = [
+    chatStore.subscribe((s) => s.activeConversationId, reportActive),
+    roomStore.subscribe((s) => s.activeRoomJid, reportActive),
+    connectionStore.subscribe((s) => s.windowVisible, reportActive),
+    chatStore.subscribe((s) => s.conversationMeta, reportRead),
+    roomStore.subscribe((s) => s.roomMeta, reportRead),
+  ]
+  reportActive()
+  return () => unsubscribes.forEach((unsubscribe) => unsubscribe())
+}
+
 // Browser favicon badge implementation
 class FaviconBadge {
   private originalFavicon: string | null = null
@@ -106,7 +147,8 @@ class FaviconBadge {
 
 /**
  * Hook to manage notification badges for unread messages and inbox events.
- * - In Tauri: Sets the dock/taskbar badge count
+ * - In Tauri: Sets the dock/taskbar badge count, and reports the on-screen
+ *   conversation and reads to the native unread counters
  * - In Browser: Updates the favicon with a notification indicator
  *
  * Badge count is a simple sum of store-maintained unread counts.
@@ -128,6 +170,10 @@ export function useNotificationBadge(): void {
 
   const faviconBadgeRef = useRef<FaviconBadge | null>(null)
 
+  useEffect(() => {
+    if (isTauri()) return syncNativeUnread()
+  }, [])
+
   // Initialize favicon badge handler (browser only)
   useEffect(() => {
     if (!isTauri() && typeof document !== 'undefined') {
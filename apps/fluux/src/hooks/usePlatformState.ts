@@ -3,6 +3,7 @@ import type { UnlistenFn } from '@tauri-apps/api/event'
 import { useXMPP, useSystemState, usePresence, consoleStore } from '@fluux/sdk'
 import { useConnectionStore } from '@fluux/sdk/react'
 import { isTauri } from '../utils/tauri'
+import { toCommandError } from '../utils/tauriError'
 import type { ReconnectIntent } from '../utils/reconnectIntent'
 import { getReconnectIntent } from '@/utils/reconnectIntent'
 import { startWakeGracePeriod, startSyncGracePeriod } from '../utils/renderLoopDetector'
@@ -143,6 +144,12 @@ export function shouldHandleDisplayWake(payload: SystemWakePayload | undefined):
   return true
 }
 
+/** Payload of `get_power_state` and the `power-source-changed` event. */
+interface PowerState {
+  onBattery: boolean
+  batteryPercent: number | null
+}
+
 /**
  * Payload for the Rust 30s `xmpp-keepalive` tick. Mirrors the Rust
  * `KeepalivePayload` (serde camelCase). An older binary emits the legacy
@@ -315,7 +322,7 @@ export function usePlatformState() {
   }, [])
 
   const markOsIdleUnavailable = useCallback((err: unknown): boolean => {
-    const message = err instanceof Error ? err.message : String(err)
+    const { message } = toCommandError(err)
     const unsupported = message.includes('Linux idle detection unavailable')
       || message.includes('MIT-SCREEN-SAVER')
       || message.includes('XScreenSaver')
@@ -465,7 +472,7 @@ export function usePlatformState() {
         const unsupported = markOsIdleUnavailable(err)
         idleSource = unsupported ? 'DOM (Tauri fallback cached)' : 'DOM (Tauri fallback)'
         if (!unsupported) {
-          consoleStore.getState().addEvent(`[checkIdle] Tauri get_idle_time failed: ${err}, falling back to DOM`, 'presence')
+          consoleStore.getState().addEvent(`[checkIdle] Tauri get_idle_time failed: ${toCommandError(err).message}, falling back to DOM`, 'presence')
         }
       }
     } else if (isTauri()) {
@@ -524,7 +531,7 @@ export function usePlatformState() {
     }
   }, [status, handleActivity, checkIdle, autoAwayConfig.checkIntervalMs])
 
-  // ── Effect 2: Tauri OS wake/sleep events ──────────────────────────────────
+  // ── Effect 2: Tauri OS wake/sleep/lock events ─────────────────────────────
   // No status dependency — listeners stay registered to catch wake even during
   // reconnection. client.notifySystemState() checks connection state internally.
 
@@ -535,6 +542,8 @@ export function usePlatformState() {
     let unlistenWake: UnlistenFn | undefined
     let unlistenWakeDeferred: UnlistenFn | undefined
     let unlistenSleep: UnlistenFn | undefined
+    let unlistenLock: UnlistenFn | undefined
+    let unlistenUnlock: UnlistenFn | undefined
 
     void import('@tauri-apps/api/event').then(({ listen }) => {
       // Immediate wake notification — DEMOTED to rendering-only. The native
@@ -583,6 +592,24 @@ export function usePlatformState() {
       }).then(fn => {
         if (cancelled) { fn() } else { unlistenSleep = fn }
       })
+
+      // Session lock — the user has stepped away, so go Away now rather
+      // than after the idle threshold.
+      void listen('system-screen-locked', () => {
+        if (cancelled || !autoAwayConfig.enabled) return
+        logEvent('Screen locked')
+        notifyIdle(new Date())
+      }).then(fn => {
+        if (cancelled) { fn() } else { unlistenLock = fn }
+      })
+
+      void listen('system-screen-unlocked', () => {
+        if (cancelled || !autoAwayConfig.enabled) return
+        logEvent('Screen unlocked')
+        notifyActive()
+      }).then(fn => {
+        if (cancelled) { fn() } else { unlistenUnlock = fn }
+      })
     })
 
     return () => {
@@ -590,8 +617,10 @@ export function usePlatformState() {
       unlistenWake?.()
       unlistenWakeDeferred?.()
       unlistenSleep?.()
+      unlistenLock?.()
+      unlistenUnlock?.()
     }
-  }, [client, shouldHandleWake, logEvent, maybeReloadOnLongWake])
+  }, [client, shouldHandleWake, logEvent, maybeReloadOnLongWake, autoAwayConfig.enabled, notifyIdle, notifyActive])
 
   // ── Effect 3: Time-gap wake detection (JS heartbeat) ──────────────────────
   // Also runs during 'reconnecting' status so we still update the heartbeat
@@ -714,6 +743,9 @@ export function usePlatformState() {
 
     let unlistenKeepalive: UnlistenFn | undefined
     let unlistenProxyClosed: UnlistenFn | undefined
+    let unlistenStreamError: UnlistenFn | undefined
+    let unlistenSaslDowngrade: UnlistenFn | undefined
+    let unlistenCertWarning: UnlistenFn | undefined
     let cleanedUp = false
 
     void import('@tauri-apps/api/event').then(({ listen }) => {
@@ -768,12 +800,56 @@ export function usePlatformState() {
       }).then((fn) => {
         if (cleanedUp) { fn() } else { unlistenProxyClosed = fn }
       })
+
+      // Upstream <stream:error>, reported before the bridge closes so the
+      // console records why (e.g. conflict: signed in elsewhere).
+      void listen('stream-error', (event) => {
+        const record = (event.payload ?? {}) as Record<string, unknown>
+        const condition = typeof record.condition === 'string' ? record.condition : 'unknown'
+        const text = typeof record.text === 'string' ? record.text : ''
+        const detail = text ? `${condition}: ${text}` : condition
+        console.log(`[PlatformState] Server stream error (conn=${String(record.conn_id)}, ${detail})`)
+        consoleStore.getState().addEvent(`Server closed the stream (${detail})`, 'connection')
+      }).then((fn) => {
+        if (cleanedUp) { fn() } else { unlistenStreamError = fn }
+      })
+
+      // The proxy refused the connection: the server's XEP-0474 hash shows
+      // the SASL mechanism list was tampered with on the way.
+      void listen('sasl-downgrade', (event) => {
+        const record = (event.payload ?? {}) as Record<string, unknown>
+        const error = typeof record.error === 'string' ? record.error : 'SASL downgrade detected'
+        console.error(`[PlatformState] ${error} (conn=${String(record.conn_id)})`)
+        consoleStore.getState().addEvent(`Security: ${error}`, 'error')
+      }).then((fn) => {
+        if (cleanedUp) { fn() } else { unlistenSaslDowngrade = fn }
+      })
+
+      // Server certificate close to expiry or without SCTs. The connection
+      // proceeds; this is early warning for whoever runs the server.
+      void listen('certificate-warning', (event) => {
+        const record = (event.payload ?? {}) as Record<string, unknown>
+        const host = typeof record.tls_name === 'string' ? record.tls_name : 'server'
+        const warnings = Array.isArray(record.warnings) ? record.warnings : []
+        for (const warning of warnings as Array<Record<string, unknown>>) {
+          const message = warning.kind === 'expires-soon'
+            ? `Certificate for ${host} expires in ${String(warning.daysLeft)} day(s)`
+            : `Certificate for ${host} has no embedded certificate transparency timestamps`
+          console.warn(`[PlatformState] ${message}`)
+          consoleStore.getState().addEvent(message, 'connection')
+        }
+      }).then((fn) => {
+        if (cleanedUp) { fn() } else { unlistenCertWarning = fn }
+      })
     })
 
     return () => {
       cleanedUp = true
       unlistenKeepalive?.()
       unlistenProxyClosed?.()
+      unlistenStreamError?.()
+      unlistenSaslDowngrade?.()
+      unlistenCertWarning?.()
     }
   }, [client, shouldHandleWake])
 
@@ -822,6 +898,38 @@ export function usePlatformState() {
     }
   }, [status, presenceConnect, presenceDisconnect, notifyActive, logEvent, markOsIdleUnavailable])
 
+  // ── Effect 7: Power source (battery → SDK power saving) ───────────────────
+  // On battery the SDK defers its post-connect MAM sync; back on AC it runs.
+
+  useEffect(() => {
+    if (!isTauri()) return
+
+    let cancelled = false
+    let unlistenPower: UnlistenFn | undefined
+    const apply = (state: PowerState | undefined) => {
+      if (cancelled || !state) return
+      client.setPowerSaving(state.onBattery)
+    }
+
+    void import('@tauri-apps/api/core').then(({ invoke }) => {
+      invoke<PowerState>('get_power_state').then(apply).catch(() => {})
+    })
+    void import('@tauri-apps/api/event').then(({ listen }) => {
+      void listen<PowerState>('power-source-changed', (event) => {
+        if (cancelled) return
+        logEvent(event.payload.onBattery ? 'Running on battery' : 'Running on AC power')
+        apply(event.payload)
+      }).then(fn => {
+        if (cancelled) { fn() } else { unlistenPower = fn }
+      })
+    })
+
+    return () => {
+      cancelled = true
+      unlistenPower?.()
+    }
+  }, [client, logEvent])
+
   // Reactive display state for App's full-screen spinner gate: when a
   // display-paused reconnect holds the machine in reconnecting.paused, App
   // drops the spinner (it would otherwise spin forever) and renders ChatLayout.
@@ -14,6 +14,7 @@ import { tauriProxyAdapter } from './utils/tauriProxyAdapter'
 import { installBeforeInputGuard } from './utils/tauriInputFix'
 import { logStartupCapabilities } from './utils/startupDiagnostics'
 import { startStallSentinel } from './utils/stallSentinel'
+import { syncRemoteFlags } from './utils/featureFlags'
 import { registerServiceWorker } from './utils/serviceWorkerUpdate'
 import { requestPersistentStorage } from './utils/persistStorage'
 import { sweepExpiredPassphrases } from './e2ee/webPassphraseCache'
@@ -95,6 +96,9 @@ installBeforeInputGuard()
 logStartupCapabilities()
 startStallSentinel()
 
+// Remote kill switches (desktop): the backend verifies the signed flag set.
+void syncRemoteFlags()
+
 // Auto-recover from dynamic import failures. Two failure modes share the
 // same recovery path:
 //  - Web: Vite's production build uses content-hashed chunk filenames, so a
@@ -1,5 +1,5 @@
 import { describe, it, expect, beforeEach } from 'vitest'
-import { isFeatureEnabled } from './featureFlags'
+import { isFeatureEnabled, setRemoteFlags } from './featureFlags'
 
 describe('isFeatureEnabled', () => {
   beforeEach(() => localStorage.clear())
@@ -23,3 +23,23 @@ describe('isFeatureEnabled', () => {
     expect(isFeatureEnabled('enableMessageVirtualization')).toBe(true)
   })
 })
+
+describe('remote flags', () => {
+  beforeEach(() => {
+    localStorage.clear()
+    setRemoteFlags({})
+  })
+
+  it('a remote kill switch turns a flag off', () => {
+    expect(isFeatureEnabled('linkPreviews')).toBe(true)
+    setRemoteFlags({ linkPreviews: false, someFutureFlag: false })
+    expect(isFeatureEnabled('linkPreviews')).toBe(false)
+    expect(isFeatureEnabled('enableMessageVirtualization')).toBe(true)
+  })
+
+  it('a localStorage override wins over the remote state', () => {
+    setRemoteFlags({ linkPreviews: false })
+    localStorage.setItem('fluux:flags:linkPreviews', 'true')
+    expect(isFeatureEnabled('linkPreviews')).toBe(true)
+  })
+})
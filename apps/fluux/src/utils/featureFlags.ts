@@ -1,4 +1,6 @@
-export type FeatureFlag = 'enableMessageVirtualization'
+import { isTauri } from './tauri'
+
+export type FeatureFlag = 'enableMessageVirtualization' | 'linkPreviews'
 
 /** Default state per flag (the shipped behavior when localStorage has no override). */
 const FLAG_DEFAULTS: Record<FeatureFlag, boolean> = {
@@ -8,11 +10,30 @@ const FLAG_DEFAULTS: Record<FeatureFlag, boolean> = {
   // invariants (chromium + webkit): prepend anchor within 20px, no oscillation, FAB
   // scroll-to-bottom lands correctly, windowing active. Opt out via localStorage override.
   enableMessageVirtualization: true,
+  // Link previews: ON. Can be switched off remotely (kill switch) when a fetch or parse
+  // problem shows up in the field; the backend refuses the fetch as well.
+  linkPreviews: true,
+}
+
+/** Remote kill-switch state (desktop): flags the signed remote flag set names. */
+let remoteFlags: Partial<Record<FeatureFlag, boolean>> = {}
+
+function isKnownFlag(name: string): name is FeatureFlag {
+  return Object.prototype.hasOwnProperty.call(FLAG_DEFAULTS, name)
+}
+
+/** Replace the remote flag state. Names this build doesn't know are ignored. */
+export function setRemoteFlags(flags: Record<string, boolean>): void {
+  remoteFlags = {}
+  for (const [name, enabled] of Object.entries(flags)) {
+    if (isKnownFlag(name) && typeof enabled === 'boolean') remoteFlags[name] = enabled
+  }
 }
 
 /**
- * Dev/bake feature flags. The default is per-flag (see FLAG_DEFAULTS); a localStorage
- * override (`fluux:flags:<flag>`) forces the flag on (`'true'`) or off (`'false'`):
+ * Dev/bake feature flags. The default is per-flag (see FLAG_DEFAULTS), overridden by the
+ * remote flag set (desktop, see syncRemoteFlags); a localStorage override
+ * (`fluux:flags:<flag>`) wins over both and forces the flag on (`'true'`) or off (`'false'`):
  *   localStorage.setItem('fluux:flags:enableMessageVirtualization', 'false') // opt out
  */
 export function isFeatureEnabled(flag: FeatureFlag): boolean {
@@ -20,8 +41,27 @@ export function isFeatureEnabled(flag: FeatureFlag): boolean {
     const stored = localStorage.getItem(`fluux:flags:${flag}`)
     if (stored === 'true') return true
     if (stored === 'false') return false
-    return FLAG_DEFAULTS[flag]
+    return remoteFlags[flag] ?? FLAG_DEFAULTS[flag]
   } catch {
-    return FLAG_DEFAULTS[flag]
+    return remoteFlags[flag] ?? FLAG_DEFAULTS[flag]
+  }
+}
+
+/**
+ * Desktop: load the remote flag set the backend verified (cached or freshly fetched) and
+ * follow its updates. No-op in web mode.
+ */
+export async function syncRemoteFlags(): Promise<void> {
+  if (!isTauri()) return
+  try {
+    // Imported dynamically to avoid loading Tauri APIs in web mode
+    const { invoke } = await import('@tauri-apps/api/core')
+    const { listen } = await import('@tauri-apps/api/event')
+    await listen<Record<string, boolean>>('feature-flags-changed', (event) => {
+      setRemoteFlags(event.payload)
+    })
+    setRemoteFlags(await invoke<Record<string, boolean>>('get_feature_flags'))
+  } catch (error) {
+    console.warn('[FeatureFlags] Failed to load remote flags:', error)
   }
 }
@@ -6,6 +6,7 @@
 // Note: invoke is imported dynamically inside functions to avoid loading Tauri APIs in web mode
 
 import { isTauri } from './tauri'
+import { toCommandError } from './tauriError'
 
 export interface StoredCredentials {
   jid: string
@@ -48,7 +49,11 @@ export async function saveCredentials(
 
   console.log('[Fluux] Keychain: saving credentials')
   const { invoke } = await import('@tauri-apps/api/core')
-  await invoke('save_credentials', { jid, password, server })
+  try {
+    await invoke('save_credentials', { jid, password, server })
+  } catch (error) {
+    throw toCommandError(error)
+  }
   // Set flag so we know to check keychain on next launch
   localStorage.setItem(STORAGE_KEY_HAS_CREDENTIALS, 'true')
   console.log('[Fluux] Keychain: credentials saved')
@@ -76,7 +81,7 @@ export async function getCredentials(): Promise<StoredCredentials | null> {
     }
     return result
   } catch (error) {
-    console.error('[Fluux] Keychain: failed to get credentials:', error)
+    console.error('[Fluux] Keychain: failed to get credentials:', toCommandError(error))
     // Clear flag on error to prevent repeated failed attempts
     localStorage.removeItem(STORAGE_KEY_HAS_CREDENTIALS)
     return null
@@ -123,7 +128,7 @@ export async function deleteCredentials(options: DeleteCredentialsOptions = {}):
     }
 
     if (!result.ok) {
-      throw result.error
+      throw toCommandError(result.error)
     }
 
     console.log('[Fluux] Keychain: credentials deleted')
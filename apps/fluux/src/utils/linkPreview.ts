@@ -5,6 +5,8 @@
 
 // Note: invoke is imported dynamically inside functions to avoid loading Tauri APIs in web mode
 import { isTauri } from './tauri'
+import { isFeatureEnabled } from './featureFlags'
+import { toCommandError } from './tauriError'
 
 export interface UrlMetadata {
   url: string
@@ -23,13 +25,17 @@ export async function fetchUrlMetadata(url: string): Promise<UrlMetadata | null>
     console.warn('Link preview is only available in the desktop app')
     return null
   }
+  if (!isFeatureEnabled('linkPreviews')) return null
 
   try {
     const { invoke } = await import('@tauri-apps/api/core')
     const result = await invoke<UrlMetadata>('fetch_url_metadata', { url })
     return result
   } catch (error) {
-    console.error('Failed to fetch URL metadata:', error)
+    const commandError = toCommandError(error)
+    // Disabled by policy or a feature flag: expected, not worth an error.
+    if (commandError.kind === 'policy') return null
+    console.error('Failed to fetch URL metadata:', commandError)
     return null
   }
 }
@@ -0,0 +1,23 @@
+import { describe, expect, it } from 'vitest'
+import { isFluuxError, toCommandError } from './tauriError'
+
+describe('toCommandError', () => {
+  it('keeps the detail as message and the kind and retry flag', () => {
+    const rejection = {
+      kind: 'tls',
+      retryable: false,
+      message: "The server's identity could not be verified.",
+      detail: 'TLS handshake failed (tls-error: certificate-expired)',
+    }
+    expect(isFluuxError(rejection)).toBe(true)
+    const error = toCommandError(rejection)
+    expect(error.message).toBe('TLS handshake failed (tls-error: certificate-expired)')
+    expect(error.kind).toBe('tls')
+    expect(error.retryable).toBe(false)
+  })
+
+  it('wraps string rejections of unconverted commands', () => {
+    expect(isFluuxError('Proxy not running')).toBe(false)
+    expect(toCommandError('Proxy not running').message).toBe('Proxy not running')
+  })
+})
@@ -0,0 +1,38 @@
+/**
+ * Errors Tauri commands reject with.
+ *
+ * The Rust side serializes its `FluuxError` as `{ kind, retryable, message,
+ * detail }`: `kind` is a stable key for localized wording and retry policy,
+ * `message` an English fallback, `detail` the technical text. Commands not
+ * converted yet still reject with a plain string.
+ */
+
+export interface FluuxError {
+  kind: string
+  retryable: boolean
+  message: string
+  detail: string
+}
+
+export function isFluuxError(value: unknown): value is FluuxError {
+  return (
+    typeof value === 'object' &&
+    value !== null &&
+    typeof (value as FluuxError).kind === 'string' &&
+    typeof (value as FluuxError).retryable === 'boolean' &&
+    typeof (value as FluuxError).detail === 'string'
+  )
+}
+
+/**
+ * An `Error` for a command rejection. Its message is the technical detail,
+ * which callers log and the SDK scans for `tls-error` / `stream-error`
+ * markers; `kind` and `retryable` are kept on it.
+ */
+export function toCommandError(value: unknown): Error & Partial<Pick<FluuxError, 'kind' | 'retryable'>> {
+  if (value instanceof Error) return value
+  if (isFluuxError(value)) {
+    return Object.assign(new Error(value.detail), { kind: value.kind, retryable: value.retryable })
+  }
+  return new Error(String(value))
+}
@@ -1,4 +1,5 @@
 import type { ProxyAdapter } from '@fluux/sdk'
+import { toCommandError } from './tauriError'
 
 /**
  * Tauri proxy adapter for native TCP/TLS XMPP connections.
@@ -32,7 +33,7 @@ export const tauriProxyAdapter: ProxyAdapter = {
         `[ProxyAdapter] op#${opId} start_xmpp_proxy failed after ${Date.now() - startedAt}ms`,
         err
       )
-      throw err
+      throw toCommandError(err)
     }
   },
 
@@ -49,7 +50,7 @@ export const tauriProxyAdapter: ProxyAdapter = {
         `[ProxyAdapter] op#${opId} stop_xmpp_proxy failed after ${Date.now() - startedAt}ms`,
         err
       )
-      throw err
+      throw toCommandError(err)
     }
   },
 }
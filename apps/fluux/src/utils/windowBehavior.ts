@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/core'
 import { isLinux, isTauri, isWindows } from './tauri'
+import { toCommandError } from './tauriError'
 
 export interface TrayStatus {
   enabled: boolean
@@ -11,7 +12,11 @@ export function supportsTrayPreference(): boolean {
 
 export async function setKeepInSystemTray(enabled: boolean): Promise<TrayStatus | null> {
   if (!supportsTrayPreference()) return null
-  return invoke<TrayStatus>('set_keep_in_tray', { enabled })
+  try {
+    return await invoke<TrayStatus>('set_keep_in_tray', { enabled })
+  } catch (error) {
+    throw toCommandError(error)
+  }
 }
 
 export async function getTrayStatus(): Promise<TrayStatus | null> {
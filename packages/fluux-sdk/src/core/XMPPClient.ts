@@ -383,6 +383,12 @@ export class XMPPClient {
    */
   private modulesInitialized = false
 
+  /**
+   * Whether the host asked to save power (e.g. running on battery).
+   * @internal
+   */
+  private powerSaving = false
+
   /**
    * E2EE deferred-decrypt engine. Repairs messages stored with an
    * `encryptedPayload` once the blocking condition clears (plugin registered,
@@ -1147,6 +1153,28 @@ export class XMPPClient {
     this.connection.handleKeepaliveTick(displayActive, sleptMs)
   }
 
+  /**
+   * Tell the SDK whether to save power, typically while running on battery.
+   *
+   * While enabled, the post-connect background MAM sync is deferred; it runs
+   * once power saving is turned off again. Messages for the open conversation
+   * and live traffic are unaffected.
+   *
+   * @param enabled Whether to save power.
+   */
+  setPowerSaving(enabled: boolean): void {
+    if (this.powerSaving === enabled) return
+    this.powerSaving = enabled
+    this.emit('powerSaving', enabled)
+  }
+
+  /**
+   * Whether power saving is on (see {@link setPowerSaving}).
+   */
+  isPowerSaving(): boolean {
+    return this.powerSaving
+  }
+
   /**
    * Notify the SDK of a system state change.
    *
@@ -112,6 +112,30 @@ describe('setupBackgroundSyncSideEffects', () => {
       })
     })
 
+    it('should defer catch-up while power saving and run it when that ends', async () => {
+      connectionStore.getState().setServerInfo({
+        identities: [],
+        domain: 'example.com',
+        features: [NS_MAM],
+      })
+      mockClient.isPowerSaving.mockReturnValue(true)
+
+      connectionStore.getState().setStatus('disconnected')
+      cleanup = setupBackgroundSyncSideEffects(mockClient)
+
+      simulateFreshSession(mockClient)
+
+      await new Promise(resolve => setTimeout(resolve, 50))
+      expect(mockClient.mam.catchUpAllConversations).not.toHaveBeenCalled()
+
+      mockClient.isPowerSaving.mockReturnValue(false)
+      mockClient._emit('powerSaving', false)
+
+      await vi.waitFor(() => {
+        expect(mockClient.mam.catchUpAllConversations).toHaveBeenCalledTimes(1)
+      })
+    })
+
     it('should not double-trigger catch-up', async () => {
       connectionStore.getState().setServerInfo({
         identities: [],
@@ -41,6 +41,9 @@ import { MAM_ROOM_CATCHUP_DELAY_MS, selectRoomsNeedingResumeSeed } from '../util
  * On SM resumption (`'resumed'` event), no MAM queries are needed because the
  * server replays all undelivered stanzas automatically.
  *
+ * While the client is in power saving mode (`client.setPowerSaving(true)`, e.g.
+ * on battery), the whole process waits until power saving is turned off.
+ *
  * @param client - The XMPPClient instance
  * @param options - Configuration options
  * @returns Unsubscribe function to clean up the subscriptions
@@ -61,6 +64,8 @@ export function setupBackgroundSyncSideEffects(
   // catch-up cursor boundary so live messages arriving during the 10s room
   // catch-up window can't poison the cursor and silently skip the offline gap.
   let sessionStartTime: number | undefined
+  // Background sync was due while power saving; run it when that ends
+  let deferredForPower = false
 
   // --- Late-MAM room retry (issue D) ---
   // A room whose disco resolves supportsMAM AFTER the single 10s catch-up pass
@@ -174,6 +179,12 @@ export function setupBackgroundSyncSideEffects(
    */
   function triggerBackgroundSync(): void {
     if (backgroundSyncDone) return
+    if (client.isPowerSaving()) {
+      if (!deferredForPower) logInfo('Background sync: deferred while power saving')
+      deferredForPower = true
+      return
+    }
+    deferredForPower = false
     backgroundSyncDone = true
 
     logInfo('Background sync: starting')
@@ -330,6 +341,7 @@ export function setupBackgroundSyncSideEffects(
     (status) => {
       if (status !== 'online' && previousStatus === 'online') {
         isFreshSession = false
+        deferredForPower = false
         resetRoomRetryState()
         if (roomCatchUpTimer) {
           clearTimeout(roomCatchUpTimer)
@@ -390,6 +402,13 @@ export function setupBackgroundSyncSideEffects(
     }
   )
 
+  // Power saving ended: run the background sync it held back
+  const unsubscribePowerSaving = client.on('powerSaving', (enabled) => {
+    if (enabled || !deferredForPower || !isFreshSession) return
+    logInfo('Background sync: power saving ended, resuming')
+    triggerBackgroundSync()
+  })
+
   // --- Deferred E2EE decryption triggers ---
   // When an E2EE plugin registers (e.g. OpenPGP plugin loaded after
   // background sync already fetched MAM messages), re-decrypt any messages
@@ -413,6 +432,7 @@ export function setupBackgroundSyncSideEffects(
     unsubscribeConnection()
     unsubscribeServerInfo()
     unsubscribeRoomMAM()
+    unsubscribePowerSaving()
     unsubscribePluginRegistered()
     unsubscribeKeyUnlocked()
     if (roomCatchUpTimer) {
@@ -841,6 +841,33 @@ describe('XMPPClient Connection', () => {
       expect(errorArg).not.toContain('WebSocket closed')
     })
 
+    it('should surface the bridge close reason when it comes with a bridge failure close code', async () => {
+      // The bridge closes with a code per failure class; 4002 is its
+      // stream-error code. The reason is read the same way as with 1000.
+      mockXmppClientInstance.start.mockRejectedValue(new Error('Connection refused'))
+
+      await expect(
+        xmppClient.connect({
+          jid: 'user@example.com',
+          password: 'secret',
+          server: 'example.com',
+          skipDiscovery: true,
+        })
+      ).rejects.toThrow('Connection refused')
+
+      vi.mocked(mockStores.connection.setError).mockClear()
+
+      mockXmppClientInstance._emit('disconnect', {
+        clean: true,
+        reason: { code: 4002, reason: 'Bridge closed: stream-error host-unknown' },
+      })
+
+      const errorArg = vi.mocked(mockStores.connection.setError).mock.calls[0][0]
+      expect(errorArg).not.toBeNull()
+      expect(errorArg).toContain('host-unknown')
+      expect(errorArg).not.toContain('WebSocket closed')
+    })
+
     it('should prefer discovered XEP-0156 WebSocket endpoint before proxy for domain server inputs', async () => {
       mockDiscoverWebSocket.mockResolvedValue('wss://discovered.example.com/ws')
 
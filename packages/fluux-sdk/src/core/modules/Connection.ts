@@ -91,6 +91,11 @@ const AUTH_STREAM_ERROR_MARKERS = [
 const isAuthStreamError = (message: string): boolean =>
   AUTH_STREAM_ERROR_MARKERS.some(marker => message.includes(marker))
 
+// Close codes the desktop proxy bridge uses: normal, app shutdown, read error,
+// and its private 4000-range codes for the remaining failure classes.
+const isBridgeCloseCode = (code: number): boolean =>
+  code === 1000 || code === 1001 || code === 1011 || (code >= 4000 && code < 4100)
+
 /**
  * Thrown by `runTimedConnectionAttempt` when its timeout fires for a client
  * that has already been replaced by a newer attempt. The outer `connect()`
@@ -2175,15 +2180,16 @@ export class Connection extends BaseModule {
       if (this.xmpp !== registeredClient) {
         const machineState = this.getMachineState()
         const machineStateText = JSON.stringify(machineState)
-        // The Rust bridge sends code 1000 with a reason that starts with
-        // "Bridge closed" and may carry the real cause (e.g.
-        // "Bridge closed: stream-error host-unknown"). Match the prefix so the
-        // enriched reason is still recognised as an expected bridge teardown.
+        // The Rust bridge sends a reason that starts with "Bridge closed" and
+        // may carry the real cause (e.g. "Bridge closed: stream-error
+        // host-unknown"), with a close code per failure class (1000, 1001,
+        // 1011 or 4000-4099). Match the prefix so the enriched reason is still
+        // recognised as an expected bridge teardown.
         const isExpectedBridgeClose = wasClean
           && rawReason
           && typeof rawReason === 'object'
           && 'code' in rawReason
-          && (rawReason as { code: number }).code === 1000
+          && isBridgeCloseCode((rawReason as { code: number }).code)
           && 'reason' in rawReason
           && (rawReason as { reason?: string }).reason?.startsWith('Bridge closed') === true
         if (!isExpectedBridgeClose) {
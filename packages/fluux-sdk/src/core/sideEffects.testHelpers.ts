@@ -58,6 +58,7 @@ export function createMockClient() {
       discoverMAMSearchCapability: vi.fn().mockResolvedValue(undefined),
     },
     isConnected: vi.fn().mockReturnValue(true),
+    isPowerSaving: vi.fn().mockReturnValue(false),
     retryPendingDecrypts: vi.fn().mockResolvedValue(0),
     e2ee: null as any,
     on: vi.fn((event: string, handler: (...args: unknown[]) => void) => {
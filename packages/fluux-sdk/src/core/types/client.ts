@@ -195,6 +195,8 @@ export interface XMPPClientEvents {
   occupantAvatarUpdate: (roomJid: string, nick: string, hash: string, realJid?: string, occupantId?: string) => void
   /** Roster (contact list) fully loaded from server */
   rosterLoaded: () => void
+  /** Power saving turned on or off by the host */
+  powerSaving: (enabled: boolean) => void
 }
 
 /**
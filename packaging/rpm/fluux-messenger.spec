@@ -14,8 +14,11 @@ Requires:       webkit2gtk4.1
 Requires:       gtk3
 Requires:       glib2
 Requires:       libayatana-appindicator-gtk3
+Requires:       alsa-lib
 Requires:       xdg-utils
 Requires:       desktop-file-utils
+# Video previews for formats other than MP4/MOV
+Recommends:     ffmpeg
 
 # Disable automatic dependency generation (we use pre-built binary)
 AutoReqProv:    no
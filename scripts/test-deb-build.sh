@@ -23,6 +23,7 @@ sudo apt-get install -y \
     libgtk-3-dev \
     libayatana-appindicator3-dev \
     libxss-dev \
+    libasound2-dev \
     librsvg2-dev \
     libssl-dev \
     pkg-config \